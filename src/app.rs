@@ -0,0 +1,3399 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use ratatui::layout::{Position, Rect};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::calculator::{
+    self, CalculatorModule, DigitGrouping, EntryContext, ModuloBehavior, Outcome,
+};
+use crate::cli::StartOptions;
+use crate::config::{Config, Settings};
+use crate::constants::{self, CONSTANTS};
+use crate::dates::DateCalculator;
+use crate::eval::{ANS, Evaluator};
+use crate::finance::{Tvm, TvmField};
+use crate::glyphs::GlyphSet;
+use crate::graph::{self, Feature, GraphModule, Transform, Viewport};
+use crate::input::RepeatFilter;
+use crate::jobs::Jobs;
+use crate::keymap::Keymap;
+use crate::macros::{Macros, Step};
+use crate::matrix::MatrixEditor;
+use crate::palette::{Chooser, Command, CommandPalette};
+use crate::precision::{self, Reading};
+use crate::random::Rng;
+use crate::share::{self, SharePayload};
+use crate::stats::{self, DataTable};
+use crate::ui::{ExpressionColumns, LayoutMode};
+use crate::units::Converter;
+use crate::{clipboard, ui};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AppState {
+    Normal,  // Button navigation mode
+    Typing,  // Direct typing mode
+    Graph,   // Graph mode for plotting expressions
+    Convert, // Unit converter panel
+    Stats,   // Statistics data table
+    Matrix,  // Matrix editor
+    Dates,   // Date calculator
+}
+
+/// Modal overlays drawn above the current view; they capture all key input while open.
+#[derive(Debug, Clone)]
+pub enum Popup {
+    Palette(CommandPalette),
+    ImportPrompt(String),
+    SharePreview(SharePayload),
+    Convert(Chooser),
+    /// The constants picker; see [`constants::CONSTANTS`].
+    Constants(Chooser),
+    /// The expression evaluated several ways; see [`precision::compare`].
+    Precision {
+        expression: String,
+        readings: Vec<Reading>,
+    },
+    Confirm {
+        message: String,
+        action: ConfirmAction,
+    },
+}
+
+/// What to do when a confirm popup is accepted with `y`.
+#[derive(Debug, Clone)]
+pub enum ConfirmAction {
+    RestoreContext(EntryContext),
+    Quit,
+    ResetSettings,
+    /// Move the config and session files to `config.bak` and `session.bak` after a
+    /// reset.
+    BackUpConfig,
+}
+
+/// Ways the user can ask to leave the app; see [`App::request_quit`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuitTrigger {
+    /// `q` or Esc in Normal mode.
+    Key,
+    /// Ctrl+C.
+    Interrupt,
+    /// Ctrl+Q, which never asks for confirmation.
+    Force,
+    /// The event loop failed and the terminal is being restored.
+    Error,
+}
+
+/// How long a pressed button stays lit.
+const FLASH: Duration = Duration::from_millis(150);
+/// How long the Typing mode cursor stays on, then off, while blinking.
+const BLINK: Duration = Duration::from_millis(500);
+
+/// Least time a typing hint stays up before a newer one replaces it.
+const HINT_HOLD: Duration = Duration::from_millis(300);
+
+/// Samples across the graph for a settled view, and for one still moving under a drag.
+const GRAPH_SAMPLES: u16 = 100;
+/// Where the graph is exported, in the working directory.
+const EXPORT_PATH: &str = "plot.csv";
+/// Rows written when the graph is exported.
+const EXPORT_SAMPLES: usize = 1000;
+const DRAG_SAMPLES: u16 = 50;
+
+/// Share of its speed a released drag keeps from one tick to the next.
+const GLIDE_DECAY: f64 = 0.8;
+
+const CONVERTER_STATUS: &str = "Unit converter - Tab to move, arrows to choose, Enter to use";
+
+const STATS_STATUS: &str = "Statistics - type values, Enter for the next row, g to plot";
+
+const MATRIX_STATUS: &str = "Matrix editor - type values, Tab for the next cell, Enter to insert";
+
+const DATES_STATUS: &str = "Date calculator - type YYYY-MM-DD, Tab to move, Enter to calculate";
+
+pub struct App {
+    pub state: AppState,
+    pub calculator_module: CalculatorModule,
+    pub graph_module: GraphModule,
+    pub button_position: Option<(usize, usize)>, // (row, col)
+    pub show_history: bool,
+    pub history_selected: usize,
+    pub scroll_offset: usize,
+    pub status_message: String,
+    pub mouse_position: Option<(u16, u16)>, // (x, y) for hover tracking
+    pub graph_expression: String,
+    pub graph_x_min: f64,
+    pub graph_x_max: f64,
+    pub graph_y_min: f64,
+    pub graph_y_max: f64,
+    pub graph_cursor_x: f64,
+    pub graph_cursor_y: f64,
+    pub show_cursor_coords: bool,
+    pub second_function_mode: bool, // For 2nd function key
+    pub show_help: bool,
+    pub config: Config,
+    /// What keys do, from the config.
+    pub keymap: Keymap,
+    /// Symbols for the keypad, help and history.
+    pub glyphs: GlyphSet,
+    /// Work running off the event loop, which quitting would cut short.
+    pub jobs: Jobs,
+    pub nav_repeat: RepeatFilter<KeyCode>,
+    pub popup: Option<Popup>,
+    /// The unit converter panel's selection, kept between visits.
+    pub converter: Converter,
+    /// The statistics table's data, kept between visits.
+    pub stats: DataTable,
+    /// The matrix editor's grid, kept between visits.
+    pub matrix_editor: MatrixEditor,
+    /// The date calculator's fields, kept between visits.
+    pub dates: DateCalculator,
+    /// Financial mode's time-value-of-money values.
+    pub tvm: Tvm,
+    pub should_quit: bool,
+    /// Per-statement results of the last multi-statement calculation, shown until the
+    /// next key press.
+    pub statement_results: Vec<(String, Outcome)>,
+    /// Keep one graph unit the same on-screen length on both axes.
+    pub aspect_lock: bool,
+    /// Size in cells of the plot area, as of the last frame.
+    pub graph_plot_size: (u16, u16),
+    /// Char index where the Typing mode selection started; the cursor is the other end.
+    pub selection_anchor: Option<usize>,
+    /// Screen layout of the expression in Typing mode, recorded while drawing.
+    pub expression_columns: RefCell<Option<ExpressionColumns>>,
+    /// Where each keypad button was drawn in the last frame, with its visible row and
+    /// column, so clicks land on the button under them whatever the layout.
+    pub button_rects: RefCell<Vec<(Rect, usize, usize)>>,
+    /// Cells of the graph plot, reused from frame to frame.
+    pub graph_grid: RefCell<Vec<char>>,
+    /// Size of the terminal as of the last frame.
+    pub terminal_size: Rect,
+    /// Layout drawn in the last frame, which decides what input can reach.
+    pub layout_mode: LayoutMode,
+    /// Button (row including scroll, column) lit up by a press, and the ticks it stays lit.
+    pub button_flash: Option<(usize, usize, u64)>,
+    /// Recorded key macros and the recording in progress.
+    pub macros: Macros,
+    /// Cell the graph was grabbed at and the view at that moment, while dragging.
+    pub drag_anchor: Option<(u16, u16, Viewport)>,
+    /// World-space distance the last drag step moved the view.
+    drag_step: (f64, f64),
+    /// Distance per tick a released drag keeps panning, slowing to a stop.
+    pub glide: Option<(f64, f64)>,
+    /// Which integration bound is held, by its index in the graph's bounds, while one
+    /// is being dragged.
+    pub bound_drag: Option<usize>,
+    /// What can come next in the expression, shown in Typing mode.
+    pub typing_hint: Option<String>,
+    /// A newer hint waiting for the shown one to have been up for `HINT_HOLD`.
+    pending_hint: Option<Option<String>>,
+    hint_shown_at: Instant,
+    /// Ticks since the Typing mode cursor last restarted its blink.
+    blink_ticks: u64,
+    /// Commands whose shortcut has been mentioned; each is mentioned once.
+    tipped: Vec<Command>,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        let mut app = Self {
+            state: AppState::Normal,
+            calculator_module: CalculatorModule::new(),
+            graph_module: GraphModule::new(),
+            button_position: None, // No selection by default
+            show_history: false,
+            history_selected: 0,
+            scroll_offset: 0,
+            status_message: "Calculator ready. Press ` for typing mode, ? for help".to_string(),
+            mouse_position: None,
+            graph_expression: String::new(),
+            graph_x_min: -10.0,
+            graph_x_max: 10.0,
+            graph_y_min: -10.0,
+            graph_y_max: 10.0,
+            graph_cursor_x: 0.0,
+            graph_cursor_y: 0.0,
+            show_cursor_coords: true,
+            second_function_mode: false,
+            show_help: false,
+            nav_repeat: RepeatFilter::new(Duration::ZERO, Duration::ZERO),
+            glyphs: GlyphSet::default(),
+            jobs: Jobs::default(),
+            config: Config::default(),
+            keymap: Keymap::new(&Config::default()),
+            should_quit: false,
+            statement_results: Vec::new(),
+            aspect_lock: false,
+            graph_plot_size: (0, 0),
+            selection_anchor: None,
+            expression_columns: RefCell::new(None),
+            button_rects: RefCell::new(Vec::new()),
+            graph_grid: RefCell::new(Vec::new()),
+            terminal_size: Rect::default(),
+            layout_mode: LayoutMode::default(),
+            button_flash: None,
+            macros: Macros::default(),
+            drag_anchor: None,
+            drag_step: (0.0, 0.0),
+            glide: None,
+            bound_drag: None,
+            typing_hint: None,
+            pending_hint: None,
+            hint_shown_at: Instant::now(),
+            blink_ticks: 0,
+            tipped: Vec::new(),
+            popup: None,
+            converter: Converter::default(),
+            stats: DataTable::default(),
+            matrix_editor: MatrixEditor::default(),
+            dates: DateCalculator::default(),
+            tvm: Tvm::default(),
+        };
+        app.graph_module.ticks = true;
+        if let Some(seed) = config.random_seed {
+            app.calculator_module.rng = Rng::seeded(seed);
+        }
+        app.apply_settings(Settings::from_config(config));
+        app
+    }
+
+    /// The settings in effect now.
+    pub fn settings(&self) -> Settings {
+        let calc = &self.calculator_module;
+        Settings {
+            config: self.config.clone(),
+            mode: calc.mode,
+            angle_mode: calc.angle_mode,
+            word_size: calc.word_size,
+            percent_behavior: calc.percent_behavior,
+            modulo_behavior: calc.modulo_behavior,
+            max_digits: calc.max_digits,
+            sig_figs: calc.sig_figs,
+            result_format: calc.result_format,
+            digit_grouping: calc.digit_grouping,
+            big_integers: calc.big_integers,
+            fractions: calc.fractions,
+            cancellation_check: calc.cancellation_check,
+            glyphs: self.glyphs,
+            second_function_mode: self.second_function_mode,
+            aspect_lock: self.aspect_lock,
+            show_cursor_coords: self.show_cursor_coords,
+            gridlines: self.graph_module.gridlines,
+        }
+    }
+
+    /// Put every setting in place at once. History, variables and the expression stay;
+    /// its result is worked out again under the new settings.
+    pub fn apply_settings(&mut self, settings: Settings) {
+        let Settings {
+            config,
+            mode,
+            angle_mode,
+            word_size,
+            percent_behavior,
+            modulo_behavior,
+            max_digits,
+            sig_figs,
+            result_format,
+            digit_grouping,
+            big_integers,
+            fractions,
+            cancellation_check,
+            glyphs,
+            second_function_mode,
+            aspect_lock,
+            show_cursor_coords,
+            gridlines,
+        } = settings;
+        self.nav_repeat = RepeatFilter::new(
+            Duration::from_millis(config.nav_initial_delay_ms),
+            Duration::from_millis(config.nav_repeat_interval_ms),
+        );
+        self.keymap = Keymap::new(&config);
+        self.config = config;
+        let calc = &mut self.calculator_module;
+        calc.mode = mode;
+        calc.angle_mode = angle_mode;
+        calc.word_size = word_size;
+        calc.percent_behavior = percent_behavior;
+        calc.modulo_behavior = modulo_behavior;
+        calc.max_digits = max_digits;
+        calc.sig_figs = sig_figs;
+        calc.result_format = result_format;
+        calc.digit_grouping = digit_grouping;
+        calc.big_integers = big_integers;
+        calc.fractions = fractions;
+        calc.cancellation_check = cancellation_check;
+        calc.update_result();
+        self.glyphs = glyphs;
+        self.second_function_mode = second_function_mode;
+        self.aspect_lock = aspect_lock;
+        self.show_cursor_coords = show_cursor_coords;
+        self.graph_module.gridlines = gridlines;
+        // The keypad may have changed shape under the selection
+        self.button_position = None;
+        self.scroll_offset = 0;
+    }
+
+    /// Go back to the built-in defaults, as if started without a config file, then offer
+    /// to move the config and session files aside so the next start agrees.
+    pub fn reset_settings(&mut self) {
+        self.apply_settings(Settings::default());
+        if self.config.glyphs.is_none() {
+            self.glyphs = GlyphSet::detect();
+        }
+        self.status_message = "Settings reset to defaults".to_string();
+        let files: Vec<String> = Config::files()
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        if !files.is_empty() {
+            self.popup = Some(Popup::Confirm {
+                message: format!(
+                    "Settings reset. Also move {} aside to .bak? y/N",
+                    files.join(" and ")
+                ),
+                action: ConfirmAction::BackUpConfig,
+            });
+        }
+    }
+
+    /// Preset the mode and angle from the command line, and load a startup expression
+    /// for editing with its live result. A bad expression still loads, showing its error.
+    pub fn apply_start_options(&mut self, start: &StartOptions) {
+        if let Some(mode) = start.mode {
+            self.calculator_module.mode = mode;
+        }
+        if let Some(angle_mode) = start.angle_mode {
+            self.calculator_module.angle_mode = angle_mode;
+        }
+        if let Some(expression) = &start.expression {
+            let calc = &mut self.calculator_module;
+            calc.set_expression(expression.clone());
+            calc.update_result();
+            if let Err(error) = calc.evaluate_str(expression) {
+                calc.error_message = Some(format!("Error: {}", error));
+                calc.error = Some(error);
+            }
+            self.state = AppState::Typing;
+            self.status_message = "Typing mode - Enter to calculate, ` to exit".to_string();
+        }
+    }
+
+    pub fn get_calculator_buttons(&self) -> Vec<Vec<(&'static str, &'static str)>> {
+        if self.second_function_mode {
+            // Secondary function mode - show variables and advanced functions
+            match self.calculator_module.mode {
+                // Everyday percentages in place of e and the parens, which Basic's
+                // first keypad has
+                calculator::CalculatorMode::Basic => vec![
+                    vec![("C", "c"), ("CE", "C"), ("⌫", "bksp"), ("÷", "/")],
+                    vec![("x", "x"), ("y", "y"), ("z", "z"), ("×", "*")],
+                    vec![("a", "a"), ("b", "b"), ("c", "c"), ("−", "-")],
+                    vec![
+                        ("%chg", "pctchange"),
+                        ("markup", "markup"),
+                        ("margin", "margin"),
+                        ("π", "pi"),
+                    ],
+                    vec![("^", "^"), ("%", "%"), ("Graph", "g"), ("2nd", "2nd")],
+                    vec![("ans", "ans"), ("0", "0"), (".", "."), ("=", "enter")],
+                ],
+                calculator::CalculatorMode::Programmer | calculator::CalculatorMode::Financial => {
+                    vec![
+                        vec![("C", "c"), ("CE", "C"), ("⌫", "bksp"), ("÷", "/")],
+                        vec![("x", "x"), ("y", "y"), ("z", "z"), ("×", "*")],
+                        vec![("a", "a"), ("b", "b"), ("c", "c"), ("−", "-")],
+                        vec![("π", "pi"), ("e", "e"), ("(", "("), (")", ")")],
+                        vec![("^", "^"), ("%", "%"), ("Graph", "g"), ("2nd", "2nd")],
+                        vec![("ans", "ans"), ("0", "0"), (".", "."), ("=", "enter")],
+                    ]
+                }
+                calculator::CalculatorMode::Scientific => vec![
+                    vec![("C", "c"), ("CE", "C"), ("⌫", "bksp"), ("÷", "/")],
+                    vec![("x", "x"), ("y", "y"), ("z", "z"), ("×", "*")],
+                    vec![("a", "a"), ("b", "b"), ("c", "c"), ("−", "-")],
+                    vec![("sin", "s"), ("cos", "c"), ("tan", "t"), ("+", "+")],
+                    vec![
+                        ("asin", "asin"),
+                        ("acos", "acos"),
+                        ("atan", "atan"),
+                        ("atan2", "atan2"),
+                    ],
+                    vec![("√", "q"), ("log", "l"), ("ln", "n"), ("^", "^")],
+                    vec![("exp", "e"), ("ans", "ans"), (".", "."), ("=", "enter")],
+                    vec![("abs", "a"), ("1/x", "i"), ("x²", "x"), ("%", "%")],
+                    vec![
+                        ("gcd", "gcd"),
+                        ("lcm", "lcm"),
+                        ("prime?", "isprime"),
+                        ("factor", "factor"),
+                    ],
+                    vec![("π", "pi"), ("e", "e"), ("Graph", "g"), ("2nd", "2nd")],
+                ],
+            }
+        } else {
+            // Primary function mode - show numbers and basic operations
+            match self.calculator_module.mode {
+                calculator::CalculatorMode::Basic => vec![
+                    vec![("C", "c"), ("CE", "C"), ("⌫", "bksp"), ("÷", "/")],
+                    vec![("7", "7"), ("8", "8"), ("9", "9"), ("×", "*")],
+                    vec![("4", "4"), ("5", "5"), ("6", "6"), ("−", "-")],
+                    vec![("1", "1"), ("2", "2"), ("3", "3"), ("+", "+")],
+                    vec![("(", "("), ("0", "0"), (")", ")"), (".", ".")],
+                    vec![("^", "^"), ("%", "%"), ("=", "enter"), ("2nd", "2nd")],
+                ],
+                calculator::CalculatorMode::Scientific => vec![
+                    vec![("C", "c"), ("CE", "C"), ("⌫", "bksp"), ("÷", "/")],
+                    vec![("7", "7"), ("8", "8"), ("9", "9"), ("×", "*")],
+                    vec![("4", "4"), ("5", "5"), ("6", "6"), ("−", "-")],
+                    vec![("1", "1"), ("2", "2"), ("3", "3"), ("+", "+")],
+                    vec![("(", "("), ("0", "0"), (")", ")"), (".", ".")],
+                    vec![("^", "^"), ("%", "%"), ("=", "enter"), ("2nd", "2nd")],
+                ],
+                // Clear is "AC" here, leaving "C" for the hex digit
+                calculator::CalculatorMode::Programmer => vec![
+                    vec![("AC", "c"), ("CE", "C"), ("⌫", "bksp"), ("÷", "/")],
+                    vec![("A", "hexA"), ("B", "hexB"), ("C", "hexC"), ("×", "*")],
+                    vec![("D", "hexD"), ("E", "hexE"), ("F", "hexF"), ("−", "-")],
+                    vec![("7", "7"), ("8", "8"), ("9", "9"), ("+", "+")],
+                    vec![("4", "4"), ("5", "5"), ("6", "6"), ("AND", "and")],
+                    vec![("1", "1"), ("2", "2"), ("3", "3"), ("OR", "or")],
+                    vec![("(", "("), ("0", "0"), (")", ")"), ("XOR", "xor")],
+                    vec![("<<", "shl"), (">>", "shr"), ("NOT", "not"), ("=", "enter")],
+                    vec![("^", "^"), ("%", "%"), ("ans", "ans"), ("2nd", "2nd")],
+                ],
+                // The value keys store the result, or work it out after CPT
+                calculator::CalculatorMode::Financial => vec![
+                    vec![
+                        ("N", "tvmN"),
+                        ("I/Y", "tvmI"),
+                        ("PV", "tvmPV"),
+                        ("PMT", "tvmPMT"),
+                    ],
+                    vec![("FV", "tvmFV"), ("CPT", "cpt"), ("C", "c"), ("⌫", "bksp")],
+                    vec![("7", "7"), ("8", "8"), ("9", "9"), ("÷", "/")],
+                    vec![("4", "4"), ("5", "5"), ("6", "6"), ("×", "*")],
+                    vec![("1", "1"), ("2", "2"), ("3", "3"), ("−", "-")],
+                    vec![("0", "0"), (".", "."), ("=", "enter"), ("+", "+")],
+                ],
+            }
+        }
+    }
+
+    /// Evaluate the expression, keeping the per-statement results when there are several.
+    pub fn calculate(&mut self) {
+        let started = Instant::now();
+        let source = self.calculator_module.current_expression.clone();
+        let results = self.calculator_module.calculate();
+        if results.len() > 1 {
+            self.statement_results = results;
+        }
+        self.report_duration(started.elapsed());
+        if self.calculator_module.error.is_none() {
+            self.list_roots_in_view(&source);
+        }
+    }
+
+    /// After a `solve`, which gives one root, list all of them across the graph's x
+    /// range when there are more.
+    fn list_roots_in_view(&mut self, expression: &str) {
+        let (a, b) = (self.graph_x_min, self.graph_x_max);
+        let Some(roots) = self
+            .calculator_module
+            .evaluator()
+            .solve_roots(expression, a, b)
+        else {
+            return;
+        };
+        if roots.len() > 1 {
+            let listed: Vec<String> = roots
+                .iter()
+                .map(|&root| {
+                    let rounded = calculator::round_sig_figs(root, 6.0).unwrap_or(root);
+                    calculator::format_result(rounded)
+                })
+                .collect();
+            self.status_message = format!(
+                "Roots for {} to {}: {}",
+                calculator::format_result(a),
+                calculator::format_result(b),
+                listed.join(", ")
+            );
+        }
+    }
+
+    /// Whether something that took `elapsed` was slow enough to mention.
+    pub fn is_slow(&self, elapsed: Duration) -> bool {
+        elapsed >= Duration::from_millis(self.config.slow_calculation_ms)
+    }
+
+    /// Show how long a calculation took when it was slow enough to notice.
+    pub fn report_duration(&mut self, elapsed: Duration) {
+        if self.is_slow(elapsed) && self.calculator_module.error.is_none() {
+            self.status_message = format!(
+                "= {} in {}",
+                self.calculator_module.current_result,
+                ui::format_elapsed(elapsed)
+            );
+        }
+    }
+
+    pub fn press_button(&mut self) {
+        if let Some((row, col)) = self.button_position {
+            let buttons = self.get_calculator_buttons();
+            let actual_row = self.scroll_offset + row;
+            if actual_row < buttons.len() && buttons[actual_row].get(col).is_some() {
+                self.button_flash = Some((actual_row, col, self.ticks_for(FLASH)));
+                // Also fetch the label to disambiguate collisions (e.g., cos vs clear, variable 'c')
+                let (label, key) = buttons[actual_row][col];
+                match key {
+                    "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => self
+                        .calculator_module
+                        .append_digit(key.chars().next().unwrap()),
+                    "+" => self.calculator_module.append_operator("+"),
+                    "-" => self.calculator_module.append_operator("-"),
+                    "*" => self.calculator_module.append_operator("*"),
+                    "/" => self.calculator_module.append_operator("/"),
+                    "^" => self.calculator_module.append_operator("^"),
+                    "%" => self.calculator_module.append_operator("%"),
+                    "(" => self.calculator_module.insert_str("("),
+                    ")" => self.calculator_module.insert_str(")"),
+                    "." => self.calculator_module.append_decimal(),
+                    "enter" => self.calculate(),
+                    "bksp" => self.calculator_module.backspace(),
+                    // 'c' key conflicts: could be clear current, cos function, or variable 'c'
+                    "c" => {
+                        if label == "cos" {
+                            self.calculator_module.apply_function("cos");
+                        } else if self.second_function_mode && label == "c" {
+                            self.calculator_module.insert_str("c");
+                        } else if !self.calculator_module.acknowledge_error(false) {
+                            // The first press after an error only dismisses it
+                            self.calculator_module.clear();
+                        }
+                    }
+                    "C" => {
+                        self.calculator_module.clear_all();
+                        self.history_selected = 0;
+                    }
+                    // Scientific functions by label disambiguation
+                    "s" => self.calculator_module.apply_function("sin"),
+                    "t" => self.calculator_module.apply_function("tan"),
+                    "q" => self.calculator_module.apply_function("sqrt"),
+                    "l" => self.calculator_module.apply_function("log"),
+                    "n" => self.calculator_module.apply_function("ln"),
+                    "asin" | "acos" | "atan" => self.calculator_module.apply_function(key),
+                    // Two arguments, or a text result, so typed rather than applied to
+                    // the result
+                    "atan2" | "gcd" | "lcm" | "isprime" | "factor" | "pctchange" | "markup"
+                    | "margin" => {
+                        self.calculator_module.insert_str(&format!("{}(", key));
+                    }
+                    // 'a' could be abs function or variable 'a' in 2nd mode
+                    "a" => {
+                        if label == "abs" {
+                            self.calculator_module.apply_function("abs");
+                        } else if self.second_function_mode && label == "a" {
+                            self.calculator_module.insert_str("a");
+                        }
+                    }
+                    // 'e' could be exp() function or Euler's constant
+                    "e" => {
+                        if label == "exp" {
+                            self.calculator_module.apply_function("exp");
+                        } else if label == "e" {
+                            self.calculator_module.insert_str("2.71828");
+                        }
+                    }
+                    "i" => self.calculator_module.apply_function("1/x"),
+                    "x" => {
+                        // Check if this is the x² function or the x variable button
+                        if label == "x²" {
+                            self.calculator_module.apply_function("x^2");
+                        } else if label == "x" {
+                            self.calculator_module.insert_str("x");
+                        }
+                    }
+                    // Variables y, z, b only in 2nd function mode (a and c handled above)
+                    "y" | "z" | "b" if self.second_function_mode => {
+                        self.calculator_module.insert_str(key);
+                    }
+                    "g" => {
+                        if self.second_function_mode {
+                            self.enter_graph_mode();
+                        } else {
+                            self.calculate();
+                        }
+                    }
+                    "2nd" => self.toggle_second_function(),
+                    "pi" => self.calculator_module.insert_str("3.14159"),
+                    "ans" => self.calculator_module.insert_str(ANS),
+                    "hexA" | "hexB" | "hexC" | "hexD" | "hexE" | "hexF" => {
+                        let digit = key.chars().last().unwrap_or('0');
+                        self.calculator_module.append_hex_digit(digit);
+                    }
+                    "and" => self.calculator_module.insert_str("&"),
+                    "or" => self.calculator_module.insert_str("|"),
+                    "xor" => self.calculator_module.insert_str(" xor "),
+                    "not" => self.calculator_module.insert_str("~"),
+                    "shl" => self.calculator_module.insert_str("<<"),
+                    "shr" => self.calculator_module.insert_str(">>"),
+                    "tvmN" => self.tvm_key(TvmField::N),
+                    "tvmI" => self.tvm_key(TvmField::Rate),
+                    "tvmPV" => self.tvm_key(TvmField::Pv),
+                    "tvmPMT" => self.tvm_key(TvmField::Pmt),
+                    "tvmFV" => self.tvm_key(TvmField::Fv),
+                    "cpt" => {
+                        self.tvm.computing = !self.tvm.computing;
+                        self.status_message = match self.tvm.computing {
+                            true => "CPT - press N, I/Y, PV, PMT or FV to work it out",
+                            false => "CPT cancelled",
+                        }
+                        .to_string();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// A time-value-of-money key: work `field` out from the others after CPT, or else
+    /// store the result in it and start the next value afresh.
+    pub fn tvm_key(&mut self, field: TvmField) {
+        let label = field.label();
+        if self.tvm.computing {
+            self.tvm.computing = false;
+            match self.tvm.compute(field) {
+                Some(value) => {
+                    self.calculator_module
+                        .set_expression(calculator::format_result(value));
+                    self.calculator_module.update_result();
+                    self.status_message =
+                        format!("{} = {}", label, calculator::format_result(value));
+                }
+                None => {
+                    self.status_message = format!("No {} fits the other values", label);
+                }
+            }
+            return;
+        }
+        match self.calculator_module.result_value {
+            Some(value) if self.calculator_module.error.is_none() => {
+                self.tvm.set(field, value);
+                self.calculator_module.clear();
+                self.status_message = format!("{} = {}", label, calculator::format_result(value));
+            }
+            _ => self.status_message = format!("Enter a number to store in {}", label),
+        }
+    }
+
+    /// How many keypad rows the last frame drew; programmer mode's readouts leave room
+    /// for fewer.
+    fn visible_button_rows(&self) -> usize {
+        self.button_rects
+            .borrow()
+            .iter()
+            .map(|&(_, row, _)| row + 1)
+            .max()
+            .unwrap_or(ui::VISIBLE_BUTTON_ROWS)
+    }
+
+    pub fn button_up(&mut self) {
+        if let Some((row, col)) = self.button_position {
+            if row > 0 {
+                self.button_position = Some((row - 1, col));
+            } else if self.scroll_offset > 0 {
+                self.scroll_offset -= 1;
+            }
+        } else {
+            // First navigation - set to (0, 0)
+            self.button_position = Some((0, 0));
+        }
+    }
+
+    pub fn button_down(&mut self) {
+        if let Some((row, col)) = self.button_position {
+            let buttons = self.get_calculator_buttons();
+            if (self.scroll_offset + row + 1) < buttons.len() {
+                if row + 1 < self.visible_button_rows() {
+                    self.button_position = Some((row + 1, col));
+                } else {
+                    self.scroll_offset += 1;
+                }
+            }
+        } else {
+            // First navigation - set to (0, 0)
+            self.button_position = Some((0, 0));
+        }
+    }
+
+    /// Whether a navigation key event should act, given the key repeat profile.
+    pub fn can_navigate(&mut self, key: KeyCode) -> bool {
+        // A replayed macro arrives all at once and would look like a held key
+        self.macros.is_replaying() || self.nav_repeat.accept(key, Instant::now())
+    }
+
+    pub fn button_left(&mut self) {
+        if let Some((row, col)) = self.button_position
+            && col > 0
+        {
+            self.button_position = Some((row, col - 1));
+        } else {
+            // First navigation - set to (0, 0)
+            self.button_position = Some((0, 0));
+        }
+    }
+
+    pub fn button_right(&mut self) {
+        if let Some((row, col)) = self.button_position {
+            let buttons = self.get_calculator_buttons();
+            if col < buttons[row].len() - 1 {
+                self.button_position = Some((row, col + 1));
+            }
+        } else {
+            // First navigation - set to (0, 0)
+            self.button_position = Some((0, 0));
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.calculator_module.toggle_mode();
+        self.button_position = None; // Clear selection when switching modes
+        self.scroll_offset = 0;
+    }
+
+    pub fn toggle_second_function(&mut self) {
+        self.second_function_mode = !self.second_function_mode;
+        self.button_position = None; // Clear selection when switching modes
+        self.scroll_offset = 0;
+        if self.second_function_mode {
+            self.status_message =
+                "2nd function mode - Press 2nd again to return to primary functions".to_string();
+        } else {
+            self.status_message =
+                "Primary function mode - Press 2nd for variables and advanced functions"
+                    .to_string();
+        }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+        self.status_message = if self.show_help {
+            "Help - Press ? or Esc to close".to_string()
+        } else {
+            match self.state {
+                AppState::Normal => "Calculator ready. Press ` for typing mode, ? for help",
+                AppState::Typing => "Typing mode - type expressions, ` to exit",
+                AppState::Graph => "Graph mode - Esc to exit, arrows to pan, +/- to zoom",
+                AppState::Convert => CONVERTER_STATUS,
+                AppState::Stats => STATS_STATUS,
+                AppState::Matrix => MATRIX_STATUS,
+                AppState::Dates => DATES_STATUS,
+            }
+            .to_string()
+        };
+    }
+
+    pub fn toggle_history(&mut self) {
+        self.show_history = !self.show_history;
+        if self.show_history {
+            // Select newest entry by default
+            if !self.calculator_module.history.is_empty() {
+                self.history_selected = self.calculator_module.history.len() - 1;
+            } else {
+                self.history_selected = 0;
+            }
+            self.status_message =
+                "History view - h to toggle back, ↑↓ navigate, r to recall".to_string();
+        } else {
+            self.status_message = "Calculator mode".to_string();
+        }
+    }
+
+    pub fn history_next(&mut self) {
+        if !self.calculator_module.history.is_empty() {
+            self.history_selected =
+                (self.history_selected + 1) % self.calculator_module.history.len();
+        }
+    }
+
+    pub fn history_prev(&mut self) {
+        if !self.calculator_module.history.is_empty() {
+            if self.history_selected == 0 {
+                self.history_selected = self.calculator_module.history.len() - 1;
+            } else {
+                self.history_selected -= 1;
+            }
+        }
+    }
+
+    pub fn recall_from_history(&mut self) {
+        self.calculator_module
+            .recall_from_history(self.history_selected);
+        // Exit history back to calculator view after recall
+        self.show_history = false;
+        self.status_message = "Calculator mode".to_string();
+
+        // Offer to restore the bindings the entry was computed with
+        let changes = self
+            .calculator_module
+            .history
+            .get(self.history_selected)
+            .and_then(|entry| entry.context.as_ref())
+            .and_then(|context| self.calculator_module.context_changes(context));
+        if let Some(changes) = changes {
+            self.popup = Some(Popup::Confirm {
+                message: format!("Restore captured context [{}]? y/N", changes),
+                action: ConfirmAction::RestoreContext(changes),
+            });
+        }
+    }
+
+    /// Selected char range of the expression, if anything is selected.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.selection_anchor?;
+        let len = self.calculator_module.current_expression.chars().count();
+        let cursor = self.calculator_module.cursor();
+        let range = anchor.min(cursor).min(len)..anchor.max(cursor).min(len);
+        (!range.is_empty()).then_some(range)
+    }
+
+    /// Put the cursor on the expression character under a click, returning whether the
+    /// click landed on the expression line.
+    pub fn click_expression(&mut self, column: u16, row: u16) -> bool {
+        let index = self
+            .expression_columns
+            .borrow()
+            .as_ref()
+            .and_then(|columns| columns.index_at(column, row));
+        match index {
+            Some(index) => {
+                self.selection_anchor = None;
+                self.calculator_module.set_cursor(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move the cursor one char, extending the selection from where it started.
+    pub fn extend_selection(&mut self, left: bool) {
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.calculator_module.cursor());
+        }
+        if left {
+            self.calculator_module.move_cursor_left();
+        } else {
+            self.calculator_module.move_cursor_right();
+        }
+    }
+
+    /// Show what the selected part of the expression evaluates to, optionally
+    /// replacing the selection with the value.
+    pub fn evaluate_selection(&mut self, replace: bool) {
+        let Some(range) = self.selection() else {
+            self.status_message = "Select part of the expression with Shift+←/→ first".to_string();
+            return;
+        };
+        let selected: String = self
+            .calculator_module
+            .current_expression
+            .chars()
+            .skip(range.start)
+            .take(range.len())
+            .collect();
+
+        match self.calculator_module.evaluate_str(&selected) {
+            Ok(Outcome::Huge(_)) if replace => {
+                self.status_message = format!(
+                    "{} is too large to write out in place of the selection",
+                    selected.trim()
+                );
+            }
+            Ok(Outcome::Text(_)) if replace => {
+                self.status_message = format!(
+                    "{} gives text, so it can't replace the selection",
+                    selected.trim()
+                );
+            }
+            Ok(outcome) if replace => {
+                let value = outcome.to_string();
+                self.calculator_module.replace_range(range, &value);
+                self.selection_anchor = None;
+                self.status_message = format!("Replaced {} with {}", selected.trim(), value);
+            }
+            Ok(outcome) => self.status_message = format!("{} = {}", selected.trim(), outcome),
+            Err(e) => self.status_message = format!("Selection {}: {}", selected.trim(), e),
+        }
+    }
+
+    pub fn insert_last_result(&mut self) {
+        if !self.calculator_module.insert_last_result() {
+            self.status_message =
+                "No previous result yet - press Enter to calculate first".to_string();
+        }
+    }
+
+    pub fn cycle_sig_figs(&mut self) {
+        self.calculator_module.cycle_sig_figs();
+        self.status_message = match self.calculator_module.sig_figs {
+            Some(figures) => format!("Results rounded to {} significant figures", figures),
+            None => "Results shown in full".to_string(),
+        };
+    }
+
+    pub fn cycle_result_format(&mut self) {
+        self.calculator_module.cycle_result_format();
+        self.status_message = format!(
+            "Results shown as {}",
+            self.calculator_module.result_format.name()
+        );
+    }
+
+    /// Step the grouping of shown results through off, commas and periods.
+    pub fn cycle_digit_grouping(&mut self) {
+        let calc = &mut self.calculator_module;
+        calc.digit_grouping = match calc.digit_grouping {
+            DigitGrouping::Off => DigitGrouping::Comma,
+            DigitGrouping::Comma => DigitGrouping::Period,
+            DigitGrouping::Period => DigitGrouping::Off,
+        };
+        calc.update_result();
+        self.status_message = match calc.digit_grouping {
+            DigitGrouping::Off => "Results shown ungrouped: 1234567.89",
+            DigitGrouping::Comma => "Results grouped with commas: 1,234,567.89 (type 1_234)",
+            DigitGrouping::Period => "Results grouped with periods: 1.234.567,89 (type 1_234)",
+        }
+        .to_string();
+    }
+
+    pub fn toggle_big_integers(&mut self) {
+        let calc = &mut self.calculator_module;
+        calc.big_integers = !calc.big_integers;
+        calc.update_result();
+        self.status_message = if calc.big_integers {
+            "Big-integer mode on: integer + - * ^ % ! are exact".to_string()
+        } else {
+            "Big-integer mode off".to_string()
+        };
+    }
+
+    pub fn toggle_fractions(&mut self) {
+        let calc = &mut self.calculator_module;
+        calc.fractions = !calc.fractions;
+        calc.update_result();
+        self.status_message = if calc.fractions {
+            "Fraction mode on: results of + - * / ^ are exact fractions".to_string()
+        } else {
+            "Fraction mode off: results shown as decimals".to_string()
+        };
+    }
+
+    pub fn toggle_cancellation_check(&mut self) {
+        let calc = &mut self.calculator_module;
+        calc.cancellation_check = !calc.cancellation_check;
+        self.status_message = if calc.cancellation_check {
+            "Cancellation check on: results that lose digits to subtraction are flagged".to_string()
+        } else {
+            "Cancellation check off".to_string()
+        };
+    }
+
+    /// Switch `%` between the truncated remainder and the floored modulo.
+    pub fn toggle_modulo_behavior(&mut self) {
+        let calc = &mut self.calculator_module;
+        calc.modulo_behavior = match calc.modulo_behavior {
+            ModuloBehavior::Truncated => ModuloBehavior::Floored,
+            ModuloBehavior::Floored => ModuloBehavior::Truncated,
+        };
+        calc.update_result();
+        self.status_message = match calc.modulo_behavior {
+            ModuloBehavior::Truncated => "% is a remainder with the sign of a: -7 % 3 = -1",
+            ModuloBehavior::Floored => "% is a modulo with the sign of b: -7 % 3 = 2",
+        }
+        .to_string();
+    }
+
+    /// Swap the expression with the one a recall, clear or calculate last replaced.
+    pub fn swap_expressions(&mut self) {
+        let calc = &mut self.calculator_module;
+        self.status_message = if calc.swap_alternate() {
+            format!("Swapped to {}", calc.current_expression)
+        } else {
+            "No previous expression to swap to".to_string()
+        };
+    }
+
+    pub fn cycle_word_size(&mut self) {
+        self.calculator_module.cycle_word_size();
+        self.status_message = format!(
+            "Word size: {} - programmer mode wraps results to it",
+            self.calculator_module.word_size.label()
+        );
+    }
+
+    pub fn toggle_angle_mode(&mut self) {
+        self.calculator_module.toggle_angle_mode();
+        self.status_message = format!("Angle mode: {}", self.calculator_module.angle_mode.label());
+    }
+
+    pub fn enter_graph_mode(&mut self) {
+        if !self.calculator_module.current_expression.is_empty() {
+            // Every sample would fail on a name with no value, leaving an empty plot. A
+            // parametric curve is in t rather than x, and a polar one in theta
+            let calc = &self.calculator_module;
+            for curve in graph::curves(&calc.current_expression) {
+                let (parameters, curve): (&[&str], _) = match graph::polar(curve) {
+                    Some(r) => (&["theta"], r),
+                    None if graph::is_parametric(curve) => (&["t"], curve),
+                    None if graph::is_implicit(curve) => (&["x", "y"], curve),
+                    None => (&["x"], curve),
+                };
+                let undefined: Vec<String> = calculator::free_variables(curve)
+                    .into_iter()
+                    .filter(|name| !parameters.contains(&name.as_str()))
+                    .filter(|name| !calc.variables.contains_key(name))
+                    .filter(|name| constants::find_constant(name).is_none())
+                    .filter(|name| name != ANS || calc.last_value.is_none())
+                    .collect();
+                if let Some(first) = undefined.first() {
+                    self.status_message = format!(
+                        "Define {} first to graph in {} (e.g. {} = 1)",
+                        undefined.join(", "),
+                        parameters.join(" and "),
+                        first
+                    );
+                    return;
+                }
+            }
+            self.graph_module
+                .set_variables(&calc.variables, calc.last_value);
+            self.graph_module.set_modulo(calc.modulo_behavior);
+            self.graph_module.scatter.clear();
+            self.graph_module.tangent = None;
+            self.graph_module.findings = None;
+            self.graph_module.bounds.clear();
+            self.graph_module.integral = None;
+            self.graph_expression = self.calculator_module.current_expression.clone();
+            self.fit_aspect();
+            self.graph_module.x_min = self.graph_x_min;
+            self.graph_module.x_max = self.graph_x_max;
+            self.graph_module.y_min = self.graph_y_min;
+            self.graph_module.y_max = self.graph_y_max;
+
+            // Generate initial graph points. A malformed expression is pointed at as
+            // when calculating, so it can be fixed in place
+            if let Err(e) =
+                self.graph_module
+                    .generate_points(&self.graph_expression, GRAPH_SAMPLES, 50)
+            {
+                self.status_message = format!("Error generating graph: {}", e);
+                if e.is_syntax() {
+                    self.calculator_module.show_error(e);
+                }
+                return;
+            }
+
+            self.state = AppState::Graph;
+            self.show_help = false;
+            self.status_message =
+                "Graph mode - Esc to exit, arrows to pan, +/- to zoom".to_string();
+        } else {
+            self.status_message = "Enter an expression first, then press Graph".to_string();
+        }
+    }
+
+    pub fn exit_graph_mode(&mut self) {
+        self.drag_anchor = None;
+        self.glide = None;
+        self.show_help = false;
+        // Data is only plotted from the statistics table, so that is where it goes back to
+        if !self.graph_module.scatter.is_empty() {
+            self.state = AppState::Stats;
+            self.status_message = STATS_STATUS.to_string();
+            return;
+        }
+        self.state = AppState::Normal;
+        self.status_message = "Calculator ready. Press ` for typing mode, ? for help".to_string();
+    }
+
+    pub fn pan_graph(&mut self, dx: f64, dy: f64) {
+        let x_range = self.graph_x_max - self.graph_x_min;
+        let y_range = self.graph_y_max - self.graph_y_min;
+
+        self.graph_x_min += dx * x_range * 0.1;
+        self.graph_x_max += dx * x_range * 0.1;
+        self.graph_y_min += dy * y_range * 0.1;
+        self.graph_y_max += dy * y_range * 0.1;
+
+        self.refresh_graph();
+    }
+
+    pub fn zoom_graph(&mut self, factor: f64) {
+        let x_center = (self.graph_x_min + self.graph_x_max) / 2.0;
+        let y_center = (self.graph_y_min + self.graph_y_max) / 2.0;
+        let x_range = self.graph_x_max - self.graph_x_min;
+        let y_range = self.graph_y_max - self.graph_y_min;
+
+        let new_x_range = x_range / factor;
+        let new_y_range = y_range / factor;
+
+        self.graph_x_min = x_center - new_x_range / 2.0;
+        self.graph_x_max = x_center + new_x_range / 2.0;
+        self.graph_y_min = y_center - new_y_range / 2.0;
+        self.graph_y_max = y_center + new_y_range / 2.0;
+
+        self.refresh_graph();
+    }
+
+    /// Go back to the default view, or to one fitting the data when data is plotted.
+    pub fn reset_graph_view(&mut self) {
+        let default = self.graph_module.data_view().unwrap_or_default();
+        self.graph_x_min = default.x_min;
+        self.graph_x_max = default.x_max;
+        self.graph_y_min = default.y_min;
+        self.graph_y_max = default.y_max;
+
+        self.refresh_graph();
+    }
+
+    fn graph_view(&self) -> Viewport {
+        Viewport {
+            x_min: self.graph_x_min,
+            x_max: self.graph_x_max,
+            y_min: self.graph_y_min,
+            y_max: self.graph_y_max,
+        }
+    }
+
+    /// Move the view by a world-space offset.
+    fn shift_graph(&mut self, dx: f64, dy: f64) {
+        self.graph_x_min += dx;
+        self.graph_x_max += dx;
+        self.graph_y_min += dy;
+        self.graph_y_max += dy;
+    }
+
+    /// Grab the graph at cell (`x`, `y`) when that is inside the plot, or the
+    /// integration bound there when it's on or beside one.
+    pub fn start_graph_drag(&mut self, x: u16, y: u16) {
+        let area = ui::graph_plot_area(self.terminal_size, self.layout_mode);
+        let view = self.graph_view();
+        let transform = Transform::new(view, area);
+        let held = self.graph_module.bounds.iter().position(|&bound| {
+            transform
+                .to_screen(bound, view.y_min)
+                .is_some_and(|(col, _)| col.abs_diff(x) <= 1)
+        });
+        if area.contains(Position::new(x, y)) && held.is_some() {
+            self.bound_drag = held;
+        } else if area.contains(Position::new(x, y)) {
+            self.glide = None;
+            self.drag_step = (0.0, 0.0);
+            self.drag_anchor = Some((x, y, self.graph_view()));
+        }
+    }
+
+    /// Move a grabbed graph so the point grabbed stays under the mouse at (`x`, `y`).
+    /// The plot is redrawn at a lower sample rate until the drag ends.
+    pub fn drag_graph(&mut self, x: u16, y: u16) {
+        if let Some(index) = self.bound_drag {
+            let area = ui::graph_plot_area(self.terminal_size, self.layout_mode);
+            (self.graph_module.bounds[index], _) =
+                Transform::new(self.graph_view(), area).to_world(x, y);
+            self.update_integral();
+            return;
+        }
+        let Some((anchor_x, anchor_y, start)) = self.drag_anchor else {
+            return;
+        };
+        // Measured in the view as it was when grabbed, the mouse has moved from one world
+        // point to another; shifting the view back by that much puts the first under it
+        let transform = Transform::new(
+            start,
+            ui::graph_plot_area(self.terminal_size, self.layout_mode),
+        );
+        let (from_x, from_y) = transform.to_world(anchor_x, anchor_y);
+        let (to_x, to_y) = transform.to_world(x, y);
+        let (dx, dy) = (from_x - to_x, from_y - to_y);
+
+        let before = self.graph_view();
+        self.graph_x_min = start.x_min + dx;
+        self.graph_x_max = start.x_max + dx;
+        self.graph_y_min = start.y_min + dy;
+        self.graph_y_max = start.y_max + dy;
+        self.drag_step = (
+            self.graph_x_min - before.x_min,
+            self.graph_y_min - before.y_min,
+        );
+        self.replot(DRAG_SAMPLES);
+    }
+
+    /// Let go of a dragged graph: it glides on with the momentum setting, otherwise it
+    /// is replotted at full resolution where it is.
+    pub fn end_graph_drag(&mut self) {
+        if self.bound_drag.take().is_some() || self.drag_anchor.take().is_none() {
+            return;
+        }
+        let step = std::mem::take(&mut self.drag_step);
+        if self.config.graph_momentum && step != (0.0, 0.0) {
+            self.glide = Some(step);
+        } else {
+            self.refresh_graph();
+        }
+    }
+
+    /// Pan one tick further along a released drag, ending the glide once it is slower
+    /// than a thousandth of the view per tick.
+    fn step_glide(&mut self) {
+        let Some((dx, dy)) = self.glide else {
+            return;
+        };
+        self.shift_graph(dx, dy);
+        let (dx, dy) = (dx * GLIDE_DECAY, dy * GLIDE_DECAY);
+        let x_floor = (self.graph_x_max - self.graph_x_min) / 1000.0;
+        let y_floor = (self.graph_y_max - self.graph_y_min) / 1000.0;
+        if dx.abs() < x_floor && dy.abs() < y_floor {
+            self.glide = None;
+            self.refresh_graph();
+        } else {
+            self.glide = Some((dx, dy));
+            self.replot(DRAG_SAMPLES);
+        }
+    }
+
+    /// Draw the tangent to the curve at the cursor's x and show its equation, or take it
+    /// away when it's already drawn there.
+    pub fn toggle_tangent(&mut self) {
+        let x = self.graph_cursor_x;
+        if self
+            .graph_module
+            .tangent
+            .take()
+            .is_some_and(|tangent| tangent.x == x)
+        {
+            self.status_message = "Tangent cleared".to_string();
+            return;
+        }
+        if self.graph_expression.trim().is_empty() {
+            self.status_message = "Only a curve has a tangent; plot one with a fit".to_string();
+            return;
+        }
+        self.graph_module.tangent = self.graph_module.tangent_at(x, &self.graph_expression);
+        self.status_message = match self.graph_module.tangent {
+            Some(tangent) => tangent.describe(),
+            None => format!(
+                "No tangent at x = {:.2}: the curve is undefined or vertical there",
+                x
+            ),
+        };
+    }
+
+    /// Mark the points of `feature` on the curve in view and list them beside it, or
+    /// take them away when they're already shown. They follow the view as it moves.
+    pub fn toggle_findings(&mut self, feature: Feature) {
+        let name = feature.name().to_lowercase();
+        if self
+            .graph_module
+            .findings
+            .take()
+            .is_some_and(|findings| findings.feature == feature)
+        {
+            self.status_message = format!("{} cleared", feature.name());
+            return;
+        }
+        if self.graph_expression.trim().is_empty() {
+            self.status_message = format!("Only a curve has {}; plot one with a fit", name);
+            return;
+        }
+        if feature == Feature::Intersections && graph::curves(&self.graph_expression).count() < 2 {
+            self.status_message =
+                "Plot two curves to intersect, separated by ; (e.g. x^2; 2x + 1)".to_string();
+            return;
+        }
+        match self.graph_module.find(feature, &self.graph_expression) {
+            Ok(findings) => {
+                self.status_message = findings.summary();
+                self.graph_module.findings = Some(findings);
+            }
+            Err(e) => self.status_message = format!("Error finding {}: {}", name, e),
+        }
+    }
+
+    /// Drop an integration bound at the cursor's x. Once both are down the area under
+    /// the curve between them is shaded and shown; after that `b` moves the nearer one
+    /// to the cursor.
+    pub fn drop_bound(&mut self) {
+        if self.graph_expression.trim().is_empty() {
+            self.status_message =
+                "Only a curve has an area under it; plot one with a fit".to_string();
+            return;
+        }
+        let x = self.graph_cursor_x;
+        let bounds = &mut self.graph_module.bounds;
+        if let [first, second] = bounds[..] {
+            let nearer = usize::from((second - x).abs() < (first - x).abs());
+            bounds[nearer] = x;
+        } else {
+            bounds.push(x);
+        }
+        self.update_integral();
+    }
+
+    pub fn clear_bounds(&mut self) {
+        self.graph_module.bounds.clear();
+        self.graph_module.integral = None;
+        self.status_message = "Bounds cleared".to_string();
+    }
+
+    /// Work out the area between the bounds again, and show it.
+    fn update_integral(&mut self) {
+        let graph = &mut self.graph_module;
+        let [first, second] = graph.bounds[..] else {
+            self.status_message = format!(
+                "Bound at x = {:.2}; b again to drop the other",
+                self.graph_cursor_x
+            );
+            return;
+        };
+        match graph.integrate(&self.graph_expression, first.min(second), first.max(second)) {
+            Ok(integral) => {
+                self.status_message = integral.describe();
+                graph.integral = Some(integral);
+            }
+            Err(e) => self.status_message = format!("Error integrating: {}", e),
+        }
+    }
+
+    pub fn toggle_gridlines(&mut self) {
+        self.graph_module.gridlines = !self.graph_module.gridlines;
+        self.status_message = if self.graph_module.gridlines {
+            "Gridlines on".to_string()
+        } else {
+            "Gridlines off".to_string()
+        };
+    }
+
+    pub fn toggle_aspect_lock(&mut self) {
+        self.aspect_lock = !self.aspect_lock;
+        self.status_message = if self.aspect_lock {
+            format!(
+                "Aspect lock on (cell aspect {})",
+                self.config.cell_aspect_ratio
+            )
+        } else {
+            "Aspect lock off".to_string()
+        };
+        self.refresh_graph();
+    }
+
+    /// Record the terminal size drawn this frame, the layout it got and the graph plot
+    /// area within it. The mini layout has no keypad, so it always takes typed input.
+    pub fn set_terminal_size(&mut self, size: Rect) {
+        self.terminal_size = size;
+        self.layout_mode = ui::layout_mode(size, self.config.mini_layout);
+        if self.layout_mode == LayoutMode::Mini && self.state == AppState::Normal {
+            self.state = AppState::Typing;
+            self.status_message = "Mini layout - type expressions, h for history".to_string();
+        }
+        self.set_graph_plot_area(ui::graph_plot_area(size, self.layout_mode));
+    }
+
+    /// Time between Ticks while something is animating, or `None` when the animation
+    /// clock can sleep.
+    pub fn tick_interval(&self) -> Option<Duration> {
+        let blinking = self.config.cursor_blink
+            && self.state == AppState::Typing
+            && self.popup.is_none()
+            && !self.show_help;
+        let animating = self.button_flash.is_some() || self.glide.is_some();
+        (blinking || animating || self.pending_hint.is_some())
+            .then(|| Duration::from_millis(1000 / self.config.tick_rate_hz))
+    }
+
+    /// Advance every animation by one frame.
+    pub fn tick(&mut self) {
+        self.blink_ticks += 1;
+        if let Some((row, col, ticks)) = self.button_flash {
+            self.button_flash = (ticks > 1).then_some((row, col, ticks - 1));
+        }
+        self.step_glide();
+    }
+
+    /// Work out the typing hint for the expression as it is at `now`. A change only
+    /// replaces the shown hint once that has been up for `HINT_HOLD`, so fast typing
+    /// doesn't make it flicker; until then it waits in `pending_hint`.
+    pub fn update_typing_hint(&mut self, now: Instant) {
+        let calc = &self.calculator_module;
+        let hint = (self.config.typing_hints && self.state == AppState::Typing)
+            .then(|| {
+                calculator::hint_for(
+                    &calc.current_expression,
+                    calc.angle_mode,
+                    calc.percent_behavior,
+                )
+            })
+            .flatten();
+        if hint == self.typing_hint {
+            self.pending_hint = None;
+        } else if now.duration_since(self.hint_shown_at) >= HINT_HOLD {
+            self.typing_hint = hint;
+            self.hint_shown_at = now;
+            self.pending_hint = None;
+        } else {
+            self.pending_hint = Some(hint);
+        }
+    }
+
+    /// Show the cursor solidly again, as after a key press.
+    pub fn restart_blink(&mut self) {
+        self.blink_ticks = 0;
+    }
+
+    /// Whether the Typing mode cursor is in the visible half of its blink.
+    pub fn cursor_visible(&self) -> bool {
+        !self.config.cursor_blink || (self.blink_ticks / self.ticks_for(BLINK)).is_multiple_of(2)
+    }
+
+    /// Whole ticks spanning `duration` at the configured rate, at least one.
+    fn ticks_for(&self, duration: Duration) -> u64 {
+        (duration.as_millis() as u64 * self.config.tick_rate_hz / 1000).max(1)
+    }
+
+    /// Record the plot area drawn this frame, re-fitting the y range if it changed
+    /// while the aspect is locked.
+    pub fn set_graph_plot_area(&mut self, area: Rect) {
+        let size = (area.width, area.height);
+        if size != self.graph_plot_size {
+            self.graph_plot_size = size;
+            if self.aspect_lock && self.state == AppState::Graph {
+                self.refresh_graph();
+            }
+        }
+    }
+
+    /// With the aspect lock on, resize the y range around its center so one unit spans
+    /// the same on-screen distance on both axes.
+    fn fit_aspect(&mut self) {
+        let (width, height) = self.graph_plot_size;
+        if !self.aspect_lock || width == 0 || height == 0 {
+            return;
+        }
+        // A cell is `cell_aspect_ratio` times taller than wide, so a row must span that
+        // many times the units of a column.
+        let units_per_column = (self.graph_x_max - self.graph_x_min) / width as f64;
+        let y_range = units_per_column * self.config.cell_aspect_ratio * height as f64;
+        let y_center = (self.graph_y_min + self.graph_y_max) / 2.0;
+        self.graph_y_min = y_center - y_range / 2.0;
+        self.graph_y_max = y_center + y_range / 2.0;
+    }
+
+    /// Apply the aspect lock, push the view to the graph module and replot.
+    fn refresh_graph(&mut self) {
+        self.replot(GRAPH_SAMPLES);
+    }
+
+    /// Like [`Self::refresh_graph`], with `samples` points across the plot.
+    fn replot(&mut self, samples: u16) {
+        self.fit_aspect();
+        self.graph_module.square_ticks = self.aspect_lock;
+        self.graph_module.x_min = self.graph_x_min;
+        self.graph_module.x_max = self.graph_x_max;
+        self.graph_module.y_min = self.graph_y_min;
+        self.graph_module.y_max = self.graph_y_max;
+
+        // Regenerate graph points
+        let started = Instant::now();
+        if let Err(e) = self
+            .graph_module
+            .generate_points(&self.graph_expression, samples, 50)
+        {
+            self.status_message = format!("Error regenerating graph: {}", e);
+        } else if self.is_slow(started.elapsed()) {
+            self.status_message =
+                format!("Graph drawn in {}", ui::format_elapsed(started.elapsed()));
+        }
+        if let Some(feature) = self.graph_module.findings.as_ref().map(|f| f.feature) {
+            self.graph_module.findings =
+                self.graph_module.find(feature, &self.graph_expression).ok();
+        }
+    }
+
+    pub fn update_graph_cursor(&mut self, x: u16, y: u16, graph_area: Rect) {
+        if graph_area.contains(Position::new(x, y)) {
+            (self.graph_cursor_x, self.graph_cursor_y) =
+                Transform::new(self.graph_view(), graph_area).to_world(x, y);
+        }
+    }
+
+    pub fn open_palette(&mut self) {
+        self.popup = Some(Popup::Palette(CommandPalette::default()));
+    }
+
+    pub fn handle_popup_key(&mut self, code: KeyCode) {
+        let Some(popup) = self.popup.as_mut() else {
+            return;
+        };
+        match popup {
+            Popup::Palette(palette) => match code {
+                KeyCode::Esc => self.popup = None,
+                KeyCode::Up => palette.select_prev(),
+                KeyCode::Down => palette.select_next(),
+                KeyCode::Backspace => palette.pop(),
+                KeyCode::Char(c) => palette.push(c),
+                KeyCode::Enter => {
+                    let command = palette.selected_command();
+                    self.popup = None;
+                    if let Some(command) = command {
+                        self.record_step(Step::Command(command));
+                        self.with_shortcut_tip(command, |app| app.run_command(command));
+                    }
+                }
+                _ => {}
+            },
+            Popup::ImportPrompt(input) => match code {
+                KeyCode::Esc => self.popup = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => {
+                    let input = input.clone();
+                    self.import_share(&input);
+                }
+                _ => {}
+            },
+            Popup::SharePreview(payload) => match code {
+                KeyCode::Enter | KeyCode::Char('y') => {
+                    let payload = payload.clone();
+                    self.popup = None;
+                    self.apply_share(payload);
+                }
+                KeyCode::Esc | KeyCode::Char('n') => {
+                    self.popup = None;
+                    self.status_message = "Import cancelled".to_string();
+                }
+                _ => {}
+            },
+            Popup::Convert(chooser) => match code {
+                KeyCode::Esc | KeyCode::Char('u') => self.popup = None,
+                KeyCode::Up => chooser.select_prev(calculator::CONVERSIONS.len()),
+                KeyCode::Down => chooser.select_next(calculator::CONVERSIONS.len()),
+                KeyCode::Enter | KeyCode::Char(' ') => {
+                    let conversion = &calculator::CONVERSIONS[chooser.selected];
+                    // The popup stays open so conversions can be chained
+                    if self.calculator_module.apply_conversion(conversion.name) {
+                        self.status_message = format!(
+                            "{} = {}",
+                            conversion.label, self.calculator_module.current_result
+                        );
+                    } else {
+                        self.status_message = "No numeric result to convert".to_string();
+                    }
+                }
+                _ => {}
+            },
+            Popup::Constants(chooser) => match code {
+                KeyCode::Esc | KeyCode::Char('k') => self.popup = None,
+                KeyCode::Up => chooser.select_prev(CONSTANTS.len()),
+                KeyCode::Down => chooser.select_next(CONSTANTS.len()),
+                KeyCode::Enter | KeyCode::Char(' ') => {
+                    let constant = &CONSTANTS[chooser.selected];
+                    self.popup = None;
+                    self.calculator_module.insert_str(constant.name);
+                    self.status_message = format!(
+                        "Inserted {}: {}, {} {}",
+                        constant.name,
+                        constant.label,
+                        calculator::format_result(constant.value),
+                        constant.unit
+                    )
+                    .trim_end()
+                    .to_string();
+                }
+                _ => {}
+            },
+            Popup::Precision { .. } => {
+                if matches!(code, KeyCode::Esc | KeyCode::Enter) {
+                    self.popup = None;
+                }
+            }
+            Popup::Confirm { action, .. } => match code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    let action = action.clone();
+                    self.popup = None;
+                    self.confirm(action);
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc | KeyCode::Enter => {
+                    self.popup = None;
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn confirm(&mut self, action: ConfirmAction) {
+        match action {
+            ConfirmAction::RestoreContext(context) => {
+                self.calculator_module.restore_context(&context);
+                self.status_message = format!("Restored [{}]", context);
+            }
+            ConfirmAction::Quit => self.should_quit = true,
+            ConfirmAction::ResetSettings => self.reset_settings(),
+            ConfirmAction::BackUpConfig => {
+                self.status_message = match Config::back_up() {
+                    Ok(moved) if moved.is_empty() => {
+                        "No config or session file to move".to_string()
+                    }
+                    Ok(moved) => {
+                        // The macros came from the session file, so go with it
+                        let session = Config::session_path().map(|path| path.with_extension("bak"));
+                        if session.is_some_and(|session| moved.contains(&session)) {
+                            self.macros.clear();
+                        }
+                        let moved: Vec<String> = moved
+                            .iter()
+                            .map(|path| path.display().to_string())
+                            .collect();
+                        format!("Moved to {}", moved.join(" and "))
+                    }
+                    Err(e) => format!("Couldn't move the config files: {}", e),
+                };
+            }
+        }
+    }
+
+    /// The quit trigger a key press maps to, if any. Only the force-quit chord gets
+    /// through while a popup is open.
+    pub fn quit_trigger(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<QuitTrigger> {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Char('q') if ctrl => Some(QuitTrigger::Force),
+            _ if self.popup.is_some() => None,
+            KeyCode::Char('c') if ctrl => Some(QuitTrigger::Interrupt),
+            KeyCode::Char('q') if self.state == AppState::Normal => Some(QuitTrigger::Key),
+            KeyCode::Esc if self.state == AppState::Normal && !self.show_help => {
+                Some(QuitTrigger::Key)
+            }
+            _ => None,
+        }
+    }
+
+    /// Single entry point for leaving the app. Sets `should_quit`, or asks first when
+    /// a job is still running, or when there is work that would be lost and
+    /// `confirm_quit` is enabled.
+    pub fn request_quit(&mut self, trigger: QuitTrigger) {
+        let may_prompt = matches!(trigger, QuitTrigger::Key | QuitTrigger::Interrupt);
+        let prompt = self
+            .running_jobs()
+            .or_else(|| self.unsaved_work().filter(|_| self.config.confirm_quit));
+        match prompt.filter(|_| may_prompt) {
+            Some(message) => {
+                self.popup = Some(Popup::Confirm {
+                    message,
+                    action: ConfirmAction::Quit,
+                })
+            }
+            None => self.should_quit = true,
+        }
+    }
+
+    /// Confirmation prompt naming the jobs quitting now would cancel.
+    fn running_jobs(&self) -> Option<String> {
+        let jobs = self.jobs.descriptions();
+        let first = jobs.first()?;
+        Some(match jobs.len() {
+            1 => format!("{} still running - quit anyway? y/N", first),
+            n => format!(
+                "{} and {} more still running - quit anyway? y/N",
+                first,
+                n - 1
+            ),
+        })
+    }
+
+    /// Confirmation prompt describing what quitting now would discard.
+    fn unsaved_work(&self) -> Option<String> {
+        if self.calculator_module.has_unevaluated_expression() {
+            return Some("Quit and discard current expression? y/N".to_string());
+        }
+        // History is never written out, so any entries are lost on exit.
+        match self.calculator_module.history.len() {
+            0 => None,
+            1 => Some("Quit and discard 1 history entry? y/N".to_string()),
+            n => Some(format!("Quit and discard {} history entries? y/N", n)),
+        }
+    }
+
+    pub fn check_precision(&mut self) {
+        let expression = self.calculator_module.current_expression.trim().to_string();
+        if expression.is_empty() {
+            self.status_message = "Enter an expression to check first".to_string();
+            return;
+        }
+        self.popup = Some(Popup::Precision {
+            readings: precision::compare(&self.calculator_module, &expression),
+            expression,
+        });
+    }
+
+    pub fn open_conversions(&mut self) {
+        self.popup = Some(Popup::Convert(Chooser::default()));
+    }
+
+    pub fn open_constants(&mut self) {
+        self.popup = Some(Popup::Constants(Chooser::default()));
+    }
+
+    /// Show the unit converter, starting from the current result if there is one.
+    pub fn open_converter(&mut self) {
+        if let Ok(value) = self.calculator_module.current_result.parse() {
+            self.converter.set_value(value);
+        }
+        self.state = AppState::Convert;
+        self.show_help = false;
+        self.status_message = CONVERTER_STATUS.to_string();
+    }
+
+    /// Show the statistics table.
+    pub fn open_stats(&mut self) {
+        self.state = AppState::Stats;
+        self.show_help = false;
+        self.status_message = STATS_STATUS.to_string();
+    }
+
+    pub fn exit_stats(&mut self) {
+        self.stats.commit();
+        self.state = AppState::Normal;
+        self.show_help = false;
+        self.status_message = "Calculator ready. Press ` for typing mode, ? for help".to_string();
+    }
+
+    /// Plot the statistics table's (x, y) pairs as points, in a view that fits them, with
+    /// the curve of the table's model through them when it fits.
+    pub fn graph_data(&mut self) {
+        self.stats.commit();
+        let pairs = self.stats.pairs();
+        if pairs.is_empty() {
+            self.status_message = "Enter x and y on the same row to plot them".to_string();
+            return;
+        }
+        let count = pairs.len();
+        let fit = stats::fit(self.stats.model, &pairs);
+        self.graph_module.scatter = pairs;
+        self.graph_module.tangent = None;
+        self.graph_module.findings = None;
+        self.graph_module.bounds.clear();
+        self.graph_module.integral = None;
+        self.graph_expression = fit
+            .as_ref()
+            .map_or_else(String::new, |fit| fit.expression());
+        self.state = AppState::Graph;
+        self.show_help = false;
+        self.reset_graph_view();
+        let fitted = match fit {
+            Some(fit) => format!(" and the {} fit", fit.model.label().to_lowercase()),
+            None => String::new(),
+        };
+        self.status_message = format!(
+            "Plotted {} point{}{} - Esc back to the table, arrows to pan, +/- to zoom",
+            count,
+            if count == 1 { "" } else { "s" },
+            fitted
+        );
+    }
+
+    /// Show the matrix editor.
+    pub fn open_matrix_editor(&mut self) {
+        self.state = AppState::Matrix;
+        self.show_help = false;
+        self.status_message = MATRIX_STATUS.to_string();
+    }
+
+    pub fn exit_matrix_editor(&mut self) {
+        self.matrix_editor.commit();
+        self.state = AppState::Normal;
+        self.show_help = false;
+        self.status_message = "Calculator ready. Press ` for typing mode, ? for help".to_string();
+    }
+
+    /// Insert the edited matrix at the cursor and carry on typing, to work with it.
+    pub fn insert_matrix(&mut self) {
+        self.matrix_editor.commit();
+        let (rows, cols) = self.matrix_editor.matrix.size();
+        self.calculator_module
+            .insert_str(&self.matrix_editor.expression());
+        self.state = AppState::Typing;
+        self.show_help = false;
+        self.status_message = format!(
+            "Inserted a {}×{} matrix - try det(...), inv(...), transpose(...) or *",
+            rows, cols
+        );
+    }
+
+    /// Show the date calculator, with today in any date not yet typed.
+    pub fn open_dates(&mut self) {
+        self.dates.fill_today();
+        self.state = AppState::Dates;
+        self.show_help = false;
+        self.status_message = DATES_STATUS.to_string();
+    }
+
+    pub fn exit_dates(&mut self) {
+        self.state = AppState::Normal;
+        self.show_help = false;
+        self.status_message = "Calculator ready. Press ` for typing mode, ? for help".to_string();
+    }
+
+    /// Calculate the focused date calculation as an expression, so it lands in history,
+    /// and go back to the calculator.
+    pub fn use_date_calculation(&mut self) {
+        let Some(expression) = self.dates.expression() else {
+            self.status_message =
+                "Enter dates as YYYY-MM-DD and a whole number of days".to_string();
+            return;
+        };
+        self.exit_dates();
+        self.calculator_module.set_expression(expression.clone());
+        self.calculate();
+        if self.calculator_module.error_message.is_none() {
+            self.status_message =
+                format!("{} = {}", expression, self.calculator_module.current_result);
+        }
+    }
+
+    /// Fit the next model through the statistics table's pairs.
+    pub fn cycle_fit_model(&mut self) {
+        self.stats.model = self.stats.model.next();
+        let model = self.stats.model;
+        self.status_message = format!("{} fit: {}", model.label(), model.equation());
+    }
+
+    pub fn exit_converter(&mut self) {
+        self.state = AppState::Normal;
+        self.show_help = false;
+        self.status_message = "Calculator ready. Press ` for typing mode, ? for help".to_string();
+    }
+
+    /// Calculate the converter's conversion as a `convert` expression, so it lands in
+    /// history, and go back to the calculator.
+    pub fn use_conversion(&mut self) {
+        if self.converter.result().is_none() {
+            self.status_message = "Enter a value to convert".to_string();
+            return;
+        }
+        let expression = self.converter.expression();
+        self.exit_converter();
+        self.calculator_module.set_expression(expression.clone());
+        self.calculate();
+        if self.calculator_module.error_message.is_none() {
+            self.status_message =
+                format!("{} = {}", expression, self.calculator_module.current_result);
+        }
+    }
+
+    /// Do `action`, which ran `command` the long way, then mention the key that runs it
+    /// directly. Each command's tip is shown once, and only when `shortcut_tips` is on.
+    pub fn with_shortcut_tip(&mut self, command: Command, action: impl FnOnce(&mut Self)) {
+        // Keys for the state the command was run from, which it may leave
+        let keys = self.keymap.shortcuts(self.state, command);
+        let before = self.status_message.clone();
+        action(self);
+        if keys.is_empty() || !self.config.shortcut_tips || self.tipped.contains(&command) {
+            return;
+        }
+        self.tipped.push(command);
+        let tip = format!("tip: {} does this directly", keys.join(" or "));
+        self.status_message = if self.status_message == before {
+            tip
+        } else {
+            format!("{} | {}", self.status_message, tip)
+        };
+    }
+
+    /// The palette command a keypad button does the same as, if any.
+    pub fn button_command(&self, row: usize, col: usize) -> Option<Command> {
+        let buttons = self.get_calculator_buttons();
+        let (label, _) = *buttons.get(self.scroll_offset + row)?.get(col)?;
+        match label {
+            "Graph" if self.second_function_mode => Some(Command::Graph),
+            _ => None,
+        }
+    }
+
+    pub fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Share => self.share(),
+            Command::ImportShare => self.popup = Some(Popup::ImportPrompt(String::new())),
+            Command::ToggleAngleMode => self.toggle_angle_mode(),
+            Command::ConvertResult => self.open_conversions(),
+            Command::UnitConverter => self.open_converter(),
+            Command::Statistics => self.open_stats(),
+            Command::MatrixEditor => self.open_matrix_editor(),
+            Command::DateCalculator => self.open_dates(),
+            Command::InsertConstant => self.open_constants(),
+            Command::PrecisionCheck => self.check_precision(),
+            Command::ToggleBigIntegers => self.toggle_big_integers(),
+            Command::ToggleFractions => self.toggle_fractions(),
+            Command::ToggleCancellationCheck => self.toggle_cancellation_check(),
+            Command::ToggleModulo => self.toggle_modulo_behavior(),
+            Command::CycleSigFigs => self.cycle_sig_figs(),
+            Command::CycleResultFormat => self.cycle_result_format(),
+            Command::CycleDigitGrouping => self.cycle_digit_grouping(),
+            Command::CycleWordSize => self.cycle_word_size(),
+            Command::ClearExpression => self.calculator_module.clear(),
+            Command::ClearAll => {
+                self.calculator_module.clear_all();
+                self.history_selected = 0;
+            }
+            Command::Graph => self.enter_graph_mode(),
+            Command::ExportGraph => self.export_graph(),
+            Command::ResetSettings => {
+                self.popup = Some(Popup::Confirm {
+                    message: "Reset all settings to their defaults? History is kept. y/N"
+                        .to_string(),
+                    action: ConfirmAction::ResetSettings,
+                })
+            }
+            Command::ShowKeyRepeat => {
+                self.status_message = format!(
+                    "Key repeat: first press immediate, then every {} ms after {} ms held (nav_repeat_interval_ms, nav_initial_delay_ms)",
+                    self.nav_repeat.interval().as_millis(),
+                    self.nav_repeat.initial_delay().as_millis()
+                );
+            }
+        }
+    }
+
+    /// Write the graph, or the expression when not graphing, to [`EXPORT_PATH`] as CSV
+    /// across the graph's x range, on a job thread. Rows go to a `.part` file renamed
+    /// into place once all are written, so a cancelled export leaves nothing behind.
+    pub fn export_graph(&mut self) {
+        let calc = &self.calculator_module;
+        let expression = match self.state {
+            AppState::Graph => self.graph_expression.clone(),
+            _ => calc.current_expression.clone(),
+        };
+        let variables = calc.variables.clone();
+        let (ans, modulo) = (calc.last_value, calc.modulo_behavior);
+        let range = (self.graph_x_min, self.graph_x_max);
+        self.status_message = format!("Exporting {} to {}", expression, EXPORT_PATH);
+        self.jobs
+            .spawn(format!("export to {}", EXPORT_PATH), move |token| {
+                let evaluator = Evaluator {
+                    ans,
+                    modulo,
+                    ..Evaluator::new(&variables)
+                };
+                let partial = format!("{}.part", EXPORT_PATH);
+                let written = File::create(&partial)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|file| {
+                        let mut out = BufWriter::new(file);
+                        let done = graph::write_csv(
+                            &mut out,
+                            &evaluator,
+                            &expression,
+                            range,
+                            EXPORT_SAMPLES,
+                            || token.is_cancelled(),
+                        )?;
+                        out.flush()?;
+                        // Closed first, as an open file can't be renamed everywhere
+                        drop(out);
+                        if done {
+                            fs::rename(&partial, EXPORT_PATH)?;
+                        }
+                        Ok(done)
+                    });
+                if !matches!(written, Ok(true)) {
+                    // Already gone when it couldn't be created
+                    let _ = fs::remove_file(&partial);
+                }
+                match written {
+                    Ok(true) => format!("Exported {} rows to {}", EXPORT_SAMPLES, EXPORT_PATH),
+                    Ok(false) => format!("Export to {} cancelled", EXPORT_PATH),
+                    Err(e) => format!("Export to {} failed: {}", EXPORT_PATH, e),
+                }
+            });
+    }
+
+    pub fn share_payload(&self) -> SharePayload {
+        if self.state == AppState::Graph {
+            SharePayload {
+                expression: self.graph_expression.clone(),
+                mode: Some(self.calculator_module.mode),
+                angle_mode: Some(self.calculator_module.angle_mode),
+                variables: self.shared_variables(&self.graph_expression),
+                viewport: Some(Viewport {
+                    x_min: self.graph_x_min,
+                    x_max: self.graph_x_max,
+                    y_min: self.graph_y_min,
+                    y_max: self.graph_y_max,
+                }),
+            }
+        } else {
+            SharePayload {
+                expression: self.calculator_module.current_expression.clone(),
+                mode: Some(self.calculator_module.mode),
+                angle_mode: Some(self.calculator_module.angle_mode),
+                variables: self.shared_variables(&self.calculator_module.current_expression),
+                viewport: None,
+            }
+        }
+    }
+
+    /// Bindings for the variables `expression` references (the graph's `x` is never bound).
+    fn shared_variables(&self, expression: &str) -> Vec<(String, f64)> {
+        calculator::free_variables(expression)
+            .into_iter()
+            .filter_map(|name| {
+                let value = *self.calculator_module.variables.get(&name)?;
+                Some((name, value))
+            })
+            .collect()
+    }
+
+    pub fn share(&mut self) {
+        let encoded = share::encode(&self.share_payload());
+        self.status_message = match clipboard::copy_to_clipboard(&encoded) {
+            Ok(()) => format!("Share string copied to clipboard: {}", encoded),
+            Err(_) => format!("Share string: {}", encoded),
+        };
+    }
+
+    /// Decode a share string and show its preview; nothing is applied until confirmed.
+    pub fn import_share(&mut self, text: &str) {
+        match share::decode(text) {
+            Ok(payload) => self.popup = Some(Popup::SharePreview(payload)),
+            Err(e) => {
+                self.popup = None;
+                self.status_message = format!("Invalid share string: {}", e);
+            }
+        }
+    }
+
+    pub fn apply_share(&mut self, payload: SharePayload) {
+        if let Some(mode) = payload.mode
+            && mode != self.calculator_module.mode
+        {
+            self.toggle_mode();
+        }
+        if let Some(angle_mode) = payload.angle_mode {
+            self.calculator_module.angle_mode = angle_mode;
+        }
+        for (name, value) in payload.variables {
+            self.calculator_module.variables.insert(name, value);
+        }
+        self.calculator_module.clear();
+        self.calculator_module.set_expression(payload.expression);
+        self.calculator_module.update_result();
+        self.status_message = "Share string imported".to_string();
+
+        if let Some(viewport) = payload.viewport {
+            self.graph_x_min = viewport.x_min;
+            self.graph_x_max = viewport.x_max;
+            self.graph_y_min = viewport.y_min;
+            self.graph_y_max = viewport.y_max;
+            self.enter_graph_mode();
+        } else if self.state == AppState::Graph {
+            self.exit_graph_mode();
+        }
+    }
+
+    /// Whether the keypad is on screen to be clicked: in Normal mode, with history
+    /// closed or open beside it, and a layout that has one.
+    pub fn accepts_button_clicks(&self, terminal_size: Rect) -> bool {
+        if self.state != AppState::Normal || self.show_help {
+            return false;
+        }
+        if self.show_history && !ui::is_split(terminal_size) {
+            return false;
+        }
+        if terminal_size.width < ui::MIN_WIDTH || terminal_size.height < ui::MIN_HEIGHT {
+            return false; // Only the resize notice is drawn
+        }
+        // The mini layout has no keypad
+        ui::layout_mode(terminal_size, self.config.mini_layout) != LayoutMode::Mini
+    }
+
+    /// The visible button under a click, found in the keypad drawn last frame; before
+    /// the first frame it is worked out from the full-width layout.
+    pub fn mouse_to_button_coords(
+        &self,
+        x: u16,
+        y: u16,
+        terminal_size: Rect,
+    ) -> Option<(usize, usize)> {
+        if !self.accepts_button_clicks(terminal_size) {
+            return None;
+        }
+        let drawn = self.button_rects.borrow();
+        if !drawn.is_empty() {
+            return drawn
+                .iter()
+                .find(|(rect, ..)| rect.contains(Position::new(x, y)))
+                .map(|&(_, row, col)| (row, col));
+        }
+
+        // Calculate the button area bounds more accurately
+        // Title: 3 lines, Display: 6 lines, so buttons start at y = 9.
+        // The compact layout shrinks the display to 3 lines and each button row to 1.
+        let (button_start_y, button_height) = if ui::is_compact(terminal_size) {
+            (6, 1)
+        } else {
+            (9, 3)
+        };
+
+        if y < button_start_y {
+            return None; // Clicked above button area
+        }
+
+        let button_row = (y - button_start_y) / button_height;
+
+        // Calculate button column based on terminal width and number of buttons per row
+        let buttons = self.get_calculator_buttons();
+        let actual_row = self.scroll_offset + button_row as usize;
+
+        if actual_row >= buttons.len() {
+            return None; // Clicked below button area
+        }
+
+        let buttons_in_row = buttons[actual_row].len();
+        let button_width = (terminal_size.width / buttons_in_row as u16).max(1);
+        let button_col = x / button_width;
+
+        if button_col < buttons_in_row as u16 {
+            Some((button_row as usize, button_col as usize))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_TYPING_ALIASES;
+    use crate::event::AppEvent;
+    use crate::keymap::Chord;
+    use crossterm::event::{Event, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+
+    fn press(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+        app.handle_event(AppEvent::Input(Event::Key(KeyEvent::new(code, modifiers))));
+    }
+
+    fn dirty_app() -> App {
+        let mut app = App::new();
+        app.calculator_module.set_expression("12*(3+4".to_string());
+        app
+    }
+
+    #[test]
+    fn clean_app_quits_on_every_trigger() {
+        for (code, modifiers) in [
+            (KeyCode::Char('q'), KeyModifiers::NONE),
+            (KeyCode::Esc, KeyModifiers::NONE),
+            (KeyCode::Char('c'), KeyModifiers::CONTROL),
+            (KeyCode::Char('q'), KeyModifiers::CONTROL),
+        ] {
+            let mut app = App::new();
+            press(&mut app, code, modifiers);
+            assert!(app.should_quit, "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn dirty_app_asks_before_quitting() {
+        for (code, modifiers) in [
+            (KeyCode::Char('q'), KeyModifiers::NONE),
+            (KeyCode::Esc, KeyModifiers::NONE),
+            (KeyCode::Char('c'), KeyModifiers::CONTROL),
+        ] {
+            let mut app = dirty_app();
+            press(&mut app, code, modifiers);
+            assert!(!app.should_quit, "{:?}", code);
+            assert!(matches!(
+                &app.popup,
+                Some(Popup::Confirm { message, .. }) if message == "Quit and discard current expression? y/N"
+            ));
+
+            press(&mut app, KeyCode::Char('n'), KeyModifiers::NONE);
+            assert!(!app.should_quit && app.popup.is_none());
+
+            press(&mut app, code, modifiers);
+            press(&mut app, KeyCode::Char('y'), KeyModifiers::NONE);
+            assert!(app.should_quit, "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn force_and_error_quits_skip_the_prompt() {
+        let mut app = dirty_app();
+        press(&mut app, KeyCode::Char('q'), KeyModifiers::NONE);
+        press(&mut app, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(app.should_quit);
+
+        let mut app = dirty_app();
+        app.request_quit(QuitTrigger::Error);
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn prompt_can_be_disabled() {
+        let mut app = App::with_config(Config {
+            confirm_quit: false,
+            ..Config::default()
+        });
+        app.calculator_module.set_expression("1+".to_string());
+        press(&mut app, KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn reset_restores_every_setting_and_keeps_history() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("2^10".to_string());
+        app.calculator_module.calculate();
+        // Every field is listed, so a new setting can't be left out of the reset
+        let tweaked = Settings {
+            config: Config {
+                nav_initial_delay_ms: 1,
+                sparkline: true,
+                ..Config::default()
+            },
+            mode: calculator::CalculatorMode::Scientific,
+            angle_mode: calculator::AngleMode::Degrees,
+            word_size: crate::programmer::WordSize::Bits16,
+            percent_behavior: calculator::PercentBehavior::Contextual,
+            modulo_behavior: ModuloBehavior::Floored,
+            max_digits: 5,
+            sig_figs: Some(3),
+            result_format: calculator::ResultFormat::Engineering,
+            digit_grouping: calculator::DigitGrouping::Period,
+            big_integers: true,
+            fractions: true,
+            cancellation_check: false,
+            glyphs: GlyphSet::Ascii,
+            second_function_mode: true,
+            aspect_lock: true,
+            show_cursor_coords: false,
+            gridlines: true,
+        };
+        app.apply_settings(tweaked.clone());
+        assert_eq!(app.settings(), tweaked);
+
+        app.run_command(Command::ResetSettings);
+        assert_eq!(app.settings(), tweaked);
+        press(&mut app, KeyCode::Char('y'), KeyModifiers::NONE);
+        assert_eq!(
+            app.settings(),
+            Settings {
+                glyphs: GlyphSet::detect(),
+                ..Settings::default()
+            }
+        );
+        assert_eq!(app.calculator_module.history.len(), 1);
+        assert_eq!(app.calculator_module.current_result, "1024");
+    }
+
+    #[test]
+    fn shortcut_tips_name_keys_that_do_the_same() {
+        // What a command leaves behind, to compare running it both ways
+        fn outcome(app: &App) -> String {
+            format!(
+                "{:?} {:?} {:?} {}",
+                app.settings(),
+                app.state,
+                app.popup.is_some(),
+                app.calculator_module.current_expression
+            )
+        }
+        let mut tipped = 0;
+        for &command in Command::ALL {
+            for keys in App::new().keymap.shortcuts(AppState::Normal, command) {
+                let chord = Chord::parse(&keys).unwrap();
+                let mut by_key = App::new();
+                by_key.calculator_module.set_expression("x^2".to_string());
+                let mut by_palette = App::new();
+                by_palette
+                    .calculator_module
+                    .set_expression("x^2".to_string());
+                press(&mut by_key, chord.code, chord.modifiers);
+                by_palette.with_shortcut_tip(command, |app| app.run_command(command));
+                assert_eq!(outcome(&by_key), outcome(&by_palette), "{}", keys);
+                assert!(
+                    by_palette
+                        .status_message
+                        .ends_with(&format!("tip: {} does this directly", keys)),
+                    "{}",
+                    by_palette.status_message
+                );
+                tipped += 1;
+            }
+        }
+        assert_eq!(tipped, 12);
+        assert!(
+            App::new()
+                .keymap
+                .shortcuts(AppState::Normal, Command::Share)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn keys_bound_in_the_config_replace_the_defaults() {
+        let config = Config::parse("bind = toggle_angle_mode a Ctrl+a\nbind = share F5").unwrap();
+        let mut app = App::with_config(config);
+        press(&mut app, KeyCode::Char('d'), KeyModifiers::NONE);
+        assert_eq!(
+            app.calculator_module.angle_mode,
+            calculator::AngleMode::Radians
+        );
+        press(&mut app, KeyCode::Char('a'), KeyModifiers::NONE);
+        assert_eq!(
+            app.calculator_module.angle_mode,
+            calculator::AngleMode::Degrees
+        );
+
+        // Typing mode types plain keys, so only the Ctrl binding works there
+        app.state = AppState::Typing;
+        press(&mut app, KeyCode::Char('a'), KeyModifiers::NONE);
+        assert_eq!(app.calculator_module.current_expression, "a");
+        press(&mut app, KeyCode::Char('a'), KeyModifiers::CONTROL);
+        assert_eq!(
+            app.calculator_module.angle_mode,
+            calculator::AngleMode::Radians
+        );
+
+        // Tips name the keys as bound
+        app.state = AppState::Normal;
+        app.with_shortcut_tip(Command::ToggleAngleMode, |app| {
+            app.run_command(Command::ToggleAngleMode)
+        });
+        assert!(
+            app.status_message
+                .ends_with("tip: a or Ctrl+a does this directly"),
+            "{}",
+            app.status_message
+        );
+        assert_eq!(
+            app.keymap.shortcuts(AppState::Normal, Command::Share),
+            ["F5"]
+        );
+        assert_eq!(
+            app.keymap
+                .command(AppState::Normal, KeyCode::F(5), KeyModifiers::NONE),
+            Some(Command::Share)
+        );
+    }
+
+    #[test]
+    fn shortcut_tips_are_shown_once_and_can_be_turned_off() {
+        let mut app = App::new();
+        press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        for c in "angle".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(
+            app.status_message,
+            "Angle mode: DEG | tip: d does this directly"
+        );
+        app.with_shortcut_tip(Command::ToggleAngleMode, |app| {
+            app.run_command(Command::ToggleAngleMode)
+        });
+        assert_eq!(app.status_message, "Angle mode: RAD");
+
+        // Clicking the Graph button of the 2nd keypad
+        app.terminal_size = Rect::new(0, 0, 80, 30);
+        app.toggle_second_function();
+        app.calculator_module.set_expression("x".to_string());
+        app.handle_event(AppEvent::Input(Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 45,
+            row: 22,
+            modifiers: KeyModifiers::NONE,
+        })));
+        assert_eq!(app.state, AppState::Graph);
+        assert!(
+            app.status_message
+                .ends_with("tip: Ctrl+g does this directly")
+        );
+
+        let mut quiet = App::with_config(Config {
+            shortcut_tips: false,
+            ..Config::default()
+        });
+        quiet.with_shortcut_tip(Command::CycleSigFigs, |app| {
+            app.run_command(Command::CycleSigFigs)
+        });
+        assert!(!quiet.status_message.contains("tip"));
+    }
+
+    #[test]
+    fn other_keyboard_layouts_can_type_every_symbol() {
+        let mut app = App::with_config(Config {
+            typing_aliases: [DEFAULT_TYPING_ALIASES, &[('p', '^'), ('x', '*')]].concat(),
+            ..Config::default()
+        });
+        app.state = AppState::Typing;
+        let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        for (key, modifiers) in [
+            ('8', KeyModifiers::NONE),
+            (':', KeyModifiers::NONE),
+            ('2', KeyModifiers::NONE),
+            ('x', KeyModifiers::SHIFT),
+            ('(', altgr),
+            ('3', KeyModifiers::NONE),
+            ('p', KeyModifiers::NONE),
+            ('2', KeyModifiers::NONE),
+            ('ˆ', KeyModifiers::NONE),
+            ('2', KeyModifiers::NONE),
+            (')', altgr),
+        ] {
+            press(&mut app, KeyCode::Char(key), modifiers);
+        }
+        assert_eq!(app.calculator_module.current_expression, "8/2*(3^2^2)");
+        assert_eq!(app.calculator_module.current_result, "324");
+
+        // Ctrl shortcuts aren't aliased, and Normal mode keys are left alone
+        press(&mut app, KeyCode::Char('r'), KeyModifiers::CONTROL);
+        assert!(app.calculator_module.current_expression.ends_with(')'));
+        app.state = AppState::Normal;
+        press(&mut app, KeyCode::Char('p'), KeyModifiers::NONE);
+        assert!(app.calculator_module.current_expression.ends_with(')'));
+    }
+
+    #[test]
+    fn running_jobs_always_ask_before_quitting() {
+        let mut app = App::with_config(Config {
+            confirm_quit: false,
+            ..Config::default()
+        });
+        let export = app.jobs.start("export to plot.csv");
+        press(&mut app, KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(!app.should_quit);
+        assert!(matches!(
+            &app.popup,
+            Some(Popup::Confirm { message, .. })
+                if message == "export to plot.csv still running - quit anyway? y/N"
+        ));
+        press(&mut app, KeyCode::Char('n'), KeyModifiers::NONE);
+
+        // Once the job is done nothing is left to ask about
+        drop(export);
+        press(&mut app, KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn evaluated_expression_and_history_count_as_unsaved() {
+        let mut app = dirty_app();
+        app.calculator_module.set_expression("1+2".to_string());
+        app.calculator_module.calculate();
+        assert!(!app.calculator_module.has_unevaluated_expression());
+        app.request_quit(QuitTrigger::Key);
+        assert!(matches!(
+            &app.popup,
+            Some(Popup::Confirm { message, .. }) if message == "Quit and discard 1 history entry? y/N"
+        ));
+    }
+
+    #[test]
+    fn quit_keys_only_apply_where_they_mean_quit() {
+        let mut app = App::new();
+        app.state = AppState::Typing;
+        assert_eq!(
+            app.quit_trigger(KeyCode::Char('q'), KeyModifiers::NONE),
+            None
+        );
+        app.state = AppState::Normal;
+        app.show_help = true;
+        assert_eq!(app.quit_trigger(KeyCode::Esc, KeyModifiers::NONE), None);
+    }
+
+    #[test]
+    fn aspect_lock_keeps_circles_round() {
+        let mut app = App::new();
+        app.graph_plot_size = (78, 18);
+        app.calculator_module
+            .set_expression("sqrt(1-x^2)".to_string());
+        app.enter_graph_mode();
+        assert!(!app.graph_module.square_ticks);
+        app.toggle_aspect_lock();
+        // With it the ticks are spaced alike, so gridlines make squares too
+        assert!(app.graph_module.square_ticks);
+
+        for step in [
+            |app: &mut App| app.zoom_graph(1.2),
+            |app: &mut App| app.pan_graph(1.0, -1.0),
+        ] {
+            step(&mut app);
+            let (width, height) = app.graph_plot_size;
+            // The unit circle's extent in cells, scaled by the 2.0 cell aspect ratio
+            let across = 2.0 / (app.graph_x_max - app.graph_x_min) * width as f64;
+            let tall = 2.0 / (app.graph_y_max - app.graph_y_min) * height as f64 * 2.0;
+            assert!((across / tall - 1.0).abs() < 1e-9, "{} vs {}", across, tall);
+        }
+
+        app.set_graph_plot_area(Rect::new(0, 0, 40, 30));
+        let x_per_cell = (app.graph_x_max - app.graph_x_min) / 40.0;
+        let y_per_cell = (app.graph_y_max - app.graph_y_min) / 30.0;
+        assert!((y_per_cell / x_per_cell - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn graphing_needs_every_name_but_x_defined() {
+        let mut app = App::new();
+        app.calculator_module
+            .set_expression("a*sin(x) + b".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            app.status_message,
+            "Define a, b first to graph in x (e.g. a = 1)"
+        );
+
+        // Defined variables are plotted with their values
+        app.calculator_module
+            .set_expression("a = 2; b = 1".to_string());
+        app.calculate();
+        app.calculator_module.set_expression("a*x + b".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+        assert_eq!(
+            app.graph_module.get_point_at_x(3.0, &app.graph_expression),
+            Some(7.0)
+        );
+        app.exit_graph_mode();
+
+        app.calculator_module.set_expression("x^2".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+        app.exit_graph_mode();
+
+        // A parametric curve is in t instead, and x is a name like any other there
+        app.calculator_module
+            .set_expression("x; [a*cos(t), x*sin(t)]".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            app.status_message,
+            "Define x first to graph in t (e.g. x = 1)"
+        );
+        app.calculator_module
+            .set_expression("x; [a*cos(t), b*sin(t)]".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+        assert!(!app.graph_module.points.is_empty());
+        app.exit_graph_mode();
+
+        // And a polar one in theta, r only naming what it plots
+        app.calculator_module
+            .set_expression("r = w*theta".to_string());
+        app.enter_graph_mode();
+        assert_eq!(
+            app.status_message,
+            "Define w first to graph in theta (e.g. w = 1)"
+        );
+        app.calculator_module
+            .set_expression("r = a*theta".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+        assert!(app.graph_module.polar);
+        app.exit_graph_mode();
+
+        // An implicit relation is in both x and y
+        app.calculator_module
+            .set_expression("x^2 + y^2 - w".to_string());
+        app.enter_graph_mode();
+        assert_eq!(
+            app.status_message,
+            "Define w first to graph in x and y (e.g. w = 1)"
+        );
+        app.calculator_module
+            .set_expression("x^2 + y^2 - a".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+        assert!(!app.graph_module.points.is_empty());
+    }
+
+    #[test]
+    fn t_draws_and_clears_the_tangent_at_the_cursor() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("x^2".to_string());
+        app.enter_graph_mode();
+        app.graph_cursor_x = 1.0;
+        press(&mut app, KeyCode::Char('t'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Tangent at (1, 1): y = 2x - 1");
+        assert!(app.graph_module.tangent.is_some());
+        press(&mut app, KeyCode::Char('t'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Tangent cleared");
+        assert_eq!(app.graph_module.tangent, None);
+
+        // Moving the cursor moves the tangent, and a new graph starts without one
+        press(&mut app, KeyCode::Char('t'), KeyModifiers::NONE);
+        app.graph_cursor_x = -1.0;
+        press(&mut app, KeyCode::Char('t'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Tangent at (-1, 1): y = -2x - 1");
+        app.exit_graph_mode();
+        app.calculator_module.set_expression("ln(x)".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.graph_module.tangent, None);
+        press(&mut app, KeyCode::Char('t'), KeyModifiers::NONE);
+        assert!(app.status_message.starts_with("No tangent at x = -1.00"));
+    }
+
+    #[test]
+    fn z_marks_the_roots_in_view_and_follows_the_view() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("x^2 - 4".to_string());
+        app.enter_graph_mode();
+        press(&mut app, KeyCode::Char('z'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "2 roots in view");
+        let roots = |app: &App| app.graph_module.findings.as_ref().unwrap().lines();
+        assert_eq!(roots(&app), ["x = -2", "x = 2"]);
+
+        // Panning looks again in the new view
+        for _ in 0..5 {
+            app.pan_graph(1.0, 0.0);
+        }
+        assert_eq!(roots(&app), ["x = 2"]);
+        press(&mut app, KeyCode::Char('z'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Roots cleared");
+        assert_eq!(app.graph_module.findings, None);
+    }
+
+    #[test]
+    fn i_marks_where_two_curves_cross() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("x^2".to_string());
+        app.enter_graph_mode();
+        press(&mut app, KeyCode::Char('i'), KeyModifiers::NONE);
+        assert!(
+            app.status_message
+                .starts_with("Plot two curves to intersect")
+        );
+        assert_eq!(app.graph_module.findings, None);
+
+        app.exit_graph_mode();
+        app.calculator_module
+            .set_expression("x^2; 2x + 3".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+        press(&mut app, KeyCode::Char('i'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "2 intersections in view");
+        press(&mut app, KeyCode::Char('m'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "1 extremum in view");
+        assert_eq!(
+            app.graph_module.findings.as_ref().unwrap().lines(),
+            ["min (0, 0)"]
+        );
+        // Another feature takes the place of the last
+        press(&mut app, KeyCode::Char('z'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "2 roots in view");
+        press(&mut app, KeyCode::Char('i'), KeyModifiers::NONE);
+        assert_eq!(
+            app.graph_module.findings.unwrap().lines(),
+            ["(-1, 1)", "(3, 9)"]
+        );
+    }
+
+    #[test]
+    fn g_toggles_gridlines_from_the_configured_start() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("x^2".to_string());
+        app.enter_graph_mode();
+        assert!(app.graph_module.ticks && !app.graph_module.gridlines);
+        press(&mut app, KeyCode::Char('g'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Gridlines on");
+        assert!(app.graph_module.gridlines);
+        press(&mut app, KeyCode::Char('g'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Gridlines off");
+
+        let app = App::with_config(Config {
+            graph_gridlines: true,
+            ..Config::default()
+        });
+        assert!(app.graph_module.gridlines);
+    }
+
+    #[test]
+    fn graphing_a_malformed_expression_points_at_the_mistake() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("x^2 + *3".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Normal);
+        assert!(matches!(
+            app.calculator_module.error,
+            Some(calculator::EvalError::Syntax { pos: 6, .. })
+        ));
+        assert_eq!(app.calculator_module.error_column, Some(6));
+
+        // Errors that aren't the text's fault leave the expression alone
+        app.calculator_module.acknowledge_error(false);
+        app.calculator_module
+            .set_expression("convert(x, \"km\", \"kg\")".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.calculator_module.error, None);
+        assert!(app.status_message.starts_with("Error generating graph"));
+    }
+
+    #[test]
+    fn dragging_keeps_the_grabbed_point_under_the_mouse() {
+        let mut app = App::new();
+        app.set_terminal_size(Rect::new(0, 0, 80, 30));
+        app.calculator_module.set_expression("x".to_string());
+        app.enter_graph_mode();
+        let mouse = |app: &mut App, kind, column, row| {
+            app.handle_event(AppEvent::Input(Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })));
+            (app.graph_cursor_x, app.graph_cursor_y)
+        };
+
+        mouse(&mut app, MouseEventKind::Moved, 20, 10);
+        let grabbed = (app.graph_cursor_x, app.graph_cursor_y);
+        mouse(&mut app, MouseEventKind::Down(MouseButton::Left), 20, 10);
+        let under = mouse(&mut app, MouseEventKind::Drag(MouseButton::Left), 35, 14);
+        assert!((under.0 - grabbed.0).abs() < 1e-9 && (under.1 - grabbed.1).abs() < 1e-9);
+        // Dragging right and down shows more of the left and top
+        assert!(app.graph_x_min < -10.0 && app.graph_y_min > -10.0);
+
+        // Without momentum the view stays put once released
+        mouse(&mut app, MouseEventKind::Up(MouseButton::Left), 35, 14);
+        assert_eq!(app.drag_anchor, None);
+        assert_eq!(app.tick_interval(), None);
+
+        app.config.graph_momentum = true;
+        mouse(&mut app, MouseEventKind::Down(MouseButton::Left), 35, 14);
+        mouse(&mut app, MouseEventKind::Drag(MouseButton::Left), 30, 14);
+        mouse(&mut app, MouseEventKind::Up(MouseButton::Left), 30, 14);
+        let released = app.graph_x_min;
+        assert!(app.glide.is_some());
+        while app.glide.is_some() {
+            app.handle_event(AppEvent::Tick);
+        }
+        // The last step went left, so the view carried on right
+        assert!(app.graph_x_min > released);
+    }
+
+    #[test]
+    fn bounds_shade_the_integral_and_drag_without_panning() {
+        let mut app = App::new();
+        app.set_terminal_size(Rect::new(0, 0, 80, 30));
+        app.calculator_module.set_expression("2x".to_string());
+        app.enter_graph_mode();
+        let mouse = |app: &mut App, kind, column| {
+            app.handle_event(AppEvent::Input(Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row: 12,
+                modifiers: KeyModifiers::NONE,
+            })));
+        };
+        // The area under 2x is x², so between the bounds it's the difference of squares
+        let area = |app: &App| {
+            let [a, b] = app.graph_module.bounds[..] else {
+                panic!("{:?}", app.graph_module.bounds);
+            };
+            let integral = app.graph_module.integral.as_ref().unwrap();
+            assert!((integral.area.unwrap() - (b * b - a * a).abs()).abs() < 1e-9);
+            assert_eq!(app.status_message, integral.describe());
+        };
+
+        mouse(&mut app, MouseEventKind::Moved, 50);
+        press(&mut app, KeyCode::Char('b'), KeyModifiers::NONE);
+        assert!(app.status_message.starts_with("Bound at x = "));
+        assert_eq!(app.graph_module.integral, None);
+        mouse(&mut app, MouseEventKind::Moved, 60);
+        press(&mut app, KeyCode::Char('b'), KeyModifiers::NONE);
+        area(&app);
+
+        // Dragging a bound moves it and not the view
+        let before = app.graph_module.bounds[1];
+        mouse(&mut app, MouseEventKind::Down(MouseButton::Left), 61);
+        mouse(&mut app, MouseEventKind::Drag(MouseButton::Left), 70);
+        assert!(app.graph_module.bounds[1] > before);
+        assert_eq!((app.graph_x_min, app.graph_x_max), (-10.0, 10.0));
+        area(&app);
+        mouse(&mut app, MouseEventKind::Up(MouseButton::Left), 70);
+        assert_eq!(app.bound_drag, None);
+
+        // With both down, b moves the nearer one
+        let first = app.graph_module.bounds[0];
+        mouse(&mut app, MouseEventKind::Moved, 20);
+        press(&mut app, KeyCode::Char('b'), KeyModifiers::NONE);
+        assert!(app.graph_module.bounds[0] < first);
+        area(&app);
+        press(&mut app, KeyCode::Char('B'), KeyModifiers::NONE);
+        assert_eq!(app.status_message, "Bounds cleared");
+        assert!(app.graph_module.bounds.is_empty() && app.graph_module.integral.is_none());
+    }
+
+    #[test]
+    fn typing_hints_wait_before_changing() {
+        let mut app = App::new();
+        app.state = AppState::Typing;
+        let start = Instant::now() + HINT_HOLD;
+        app.calculator_module.set_expression("2+".to_string());
+        app.update_typing_hint(start);
+        assert_eq!(
+            app.typing_hint.as_deref(),
+            Some("enter a number, ( or a function")
+        );
+
+        // Typing on quickly leaves the hint up and keeps the new one waiting
+        app.calculator_module.set_expression("2+sqrt(".to_string());
+        app.update_typing_hint(start + HINT_HOLD / 2);
+        assert_eq!(
+            app.typing_hint.as_deref(),
+            Some("enter a number, ( or a function")
+        );
+        assert!(app.tick_interval().is_some());
+        app.update_typing_hint(start + HINT_HOLD);
+        assert_eq!(
+            app.typing_hint.as_deref(),
+            Some("argument expected for sqrt")
+        );
+
+        app.config.typing_hints = false;
+        app.update_typing_hint(start + HINT_HOLD * 2);
+        assert_eq!(app.typing_hint, None);
+    }
+
+    #[test]
+    fn selection_evaluates_in_place() {
+        let mut app = App::new();
+        app.state = AppState::Typing;
+        app.calculator_module
+            .set_expression("2*(3+4)-1".to_string());
+        app.calculator_module.set_cursor(2);
+        for _ in 0..5 {
+            app.extend_selection(false);
+        }
+        assert_eq!(app.selection(), Some(2..7));
+
+        app.evaluate_selection(false);
+        assert_eq!(app.status_message, "(3+4) = 7");
+        assert_eq!(app.calculator_module.current_expression, "2*(3+4)-1");
+
+        app.evaluate_selection(true);
+        assert_eq!(app.calculator_module.current_expression, "2*7-1");
+        assert_eq!(app.calculator_module.cursor(), 3);
+        assert_eq!(app.selection(), None);
+
+        app.calculator_module.set_cursor(1);
+        app.extend_selection(false);
+        app.extend_selection(false);
+        app.evaluate_selection(false);
+        assert_eq!(
+            app.status_message,
+            "Selection *7: Expected a number, name or ( at column 1"
+        );
+    }
+
+    #[test]
+    fn ticks_drive_the_flash_and_blink() {
+        let mut app = App::new();
+        assert_eq!(app.tick_interval(), None);
+
+        // A pressed button stays lit for 150 ms: 4 frames at 30 Hz
+        app.button_position = Some((1, 0));
+        app.press_button();
+        assert_eq!(app.button_flash, Some((1, 0, 4)));
+        assert!(app.tick_interval().is_some());
+        for _ in 0..3 {
+            app.handle_event(AppEvent::Tick);
+        }
+        assert!(app.button_flash.is_some());
+        app.handle_event(AppEvent::Tick);
+        assert_eq!(app.button_flash, None);
+        assert_eq!(app.tick_interval(), None);
+
+        // In Typing mode the cursor is on for 15 frames, then off for 15
+        press(&mut app, KeyCode::Char('`'), KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Typing);
+        assert_eq!(app.tick_interval(), Some(Duration::from_millis(33)));
+        for _ in 0..14 {
+            app.handle_event(AppEvent::Tick);
+        }
+        assert!(app.cursor_visible());
+        app.handle_event(AppEvent::Tick);
+        assert!(!app.cursor_visible());
+
+        // Typing shows it again straight away
+        press(&mut app, KeyCode::Char('1'), KeyModifiers::NONE);
+        assert_eq!(app.calculator_module.current_expression, "71");
+        assert!(app.cursor_visible());
+    }
+
+    #[test]
+    fn replayed_macros_act_like_typed_keys() {
+        let mut app = App::new();
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        let type_keys = |app: &mut App, keys: &str| {
+            for c in keys.chars() {
+                let code = match c {
+                    '\n' => KeyCode::Enter,
+                    '\u{1b}' => KeyCode::Esc,
+                    '<' => KeyCode::Left,
+                    c => KeyCode::Char(c),
+                };
+                press(app, code, KeyModifiers::NONE);
+            }
+        };
+        type_keys(&mut app, "`5\n");
+
+        // Record "press C, then type last result plus one", then replay it twice
+        press(&mut app, KeyCode::Char('R'), ctrl_shift);
+        assert!(app.macros.is_recording());
+        type_keys(&mut app, "\u{1b}<\n`$+1\n");
+        press(&mut app, KeyCode::Char('R'), ctrl_shift);
+        type_keys(&mut app, "a");
+        assert_eq!(app.macros.get('a').map(<[_]>::len), Some(8));
+        assert_eq!(app.macros.get('a').unwrap()[5], Step::Typed('+'));
+
+        // What was typed is replayed, not the keys, so a later alias doesn't change it
+        app.keymap = Keymap::new(&Config {
+            typing_aliases: vec![('+', '-')],
+            ..Config::default()
+        });
+        for _ in 0..2 {
+            press(&mut app, KeyCode::Char('p'), ctrl_shift);
+            type_keys(&mut app, "a");
+        }
+
+        let results: Vec<&str> = app
+            .calculator_module
+            .history
+            .iter()
+            .map(|entry| entry.result.as_str())
+            .collect();
+        assert_eq!(results, ["5", "6", "7", "8"]);
+        assert!(!app.macros.is_recording());
+    }
+
+    #[test]
+    fn macros_record_the_palette_command_not_its_search() {
+        let mut app = App::new();
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        press(&mut app, KeyCode::Char('R'), ctrl_shift);
+        press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        for c in "angle".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Char('R'), ctrl_shift);
+        press(&mut app, KeyCode::Char('a'), KeyModifiers::NONE);
+        assert_eq!(
+            app.macros.get('a'),
+            Some(&[Step::Command(Command::ToggleAngleMode)][..])
+        );
+
+        let angle_mode = app.calculator_module.angle_mode;
+        press(&mut app, KeyCode::Char('p'), ctrl_shift);
+        press(&mut app, KeyCode::Char('a'), KeyModifiers::NONE);
+        assert_ne!(app.calculator_module.angle_mode, angle_mode);
+    }
+
+    #[test]
+    fn start_options_load_an_expression_for_editing() {
+        let mut app = App::new();
+        app.apply_start_options(&StartOptions {
+            expression: Some("340*1.21".to_string()),
+            mode: Some(calculator::CalculatorMode::Scientific),
+            angle_mode: Some(calculator::AngleMode::Degrees),
+        });
+        assert_eq!(app.state, AppState::Typing);
+        assert_eq!(app.calculator_module.current_result, "411.4");
+        assert_eq!(app.calculator_module.cursor(), 8);
+        assert!(app.calculator_module.history.is_empty());
+        assert_eq!(
+            app.calculator_module.angle_mode,
+            calculator::AngleMode::Degrees
+        );
+
+        // A bad expression still loads, with its error showing
+        let mut app = App::new();
+        app.apply_start_options(&StartOptions {
+            expression: Some("2*(3".to_string()),
+            ..StartOptions::default()
+        });
+        assert_eq!(app.calculator_module.current_expression, "2*(3");
+        assert!(app.calculator_module.error_message.is_some());
+    }
+
+    #[test]
+    fn clear_after_an_error_first_dismisses_it() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("2+*3".to_string());
+        app.calculate();
+        app.button_position = Some((0, 0));
+        assert_eq!(app.get_calculator_buttons()[0][0], ("C", "c"));
+
+        app.press_button();
+        assert_eq!(app.calculator_module.error_message, None);
+        assert_eq!(app.calculator_module.current_expression, "2+*3");
+        app.press_button();
+        assert_eq!(app.calculator_module.current_expression, "");
+    }
+
+    #[test]
+    fn programmer_keys_type_bitwise_operators() {
+        let mut app = App::new();
+        app.calculator_module.mode = crate::calculator::CalculatorMode::Programmer;
+        let click = |app: &mut App, label: &str| {
+            let buttons = app.get_calculator_buttons();
+            let (row, col) = buttons
+                .iter()
+                .enumerate()
+                .find_map(|(row, keys)| {
+                    let col = keys.iter().position(|(shown, _)| *shown == label)?;
+                    Some((row, col))
+                })
+                .unwrap();
+            app.scroll_offset = 0;
+            app.button_position = Some((row, col));
+            app.press_button();
+        };
+        for label in ["1", "F", "<<", "4", "OR", "3", "XOR", "2"] {
+            click(&mut app, label);
+        }
+        assert_eq!(app.calculator_module.current_expression, "0x1F<<4|3 xor 2");
+        assert_eq!(app.calculator_module.current_result, "497");
+        for label in ["AC", "NOT", "0"] {
+            click(&mut app, label);
+        }
+        assert_eq!(app.calculator_module.current_result, "-1");
+
+        // The same operators can be typed
+        app.state = AppState::Typing;
+        app.calculator_module.set_expression(String::new());
+        for c in "~1<<4|3&1>>0".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        assert_eq!(app.calculator_module.current_expression, "~1<<4|3&1>>0");
+        assert_eq!(app.calculator_module.current_result, "-31");
+    }
+
+    #[test]
+    fn financial_keys_store_and_compute_tvm_values() {
+        let mut app = App::new();
+        for _ in 0..3 {
+            app.toggle_mode();
+        }
+        assert_eq!(
+            app.calculator_module.mode,
+            crate::calculator::CalculatorMode::Financial
+        );
+        let click = |app: &mut App, label: &str| {
+            let buttons = app.get_calculator_buttons();
+            let (row, col) = buttons
+                .iter()
+                .enumerate()
+                .find_map(|(row, keys)| {
+                    let col = keys.iter().position(|(shown, _)| *shown == label)?;
+                    Some((row, col))
+                })
+                .unwrap();
+            app.button_position = Some((row, col));
+            app.press_button();
+        };
+        // 360 monthly payments on 200000 at 6% a year
+        for label in ["3", "6", "0", "N", "6", "÷", "1", "2", "I/Y", "2", "0", "0"] {
+            click(&mut app, label);
+        }
+        app.calculator_module.insert_str("000");
+        app.calculator_module.update_result();
+        click(&mut app, "PV");
+        assert_eq!(app.status_message, "PV = 200000");
+        assert_eq!(app.calculator_module.current_expression, "");
+        click(&mut app, "CPT");
+        assert!(app.tvm.computing);
+        click(&mut app, "PMT");
+        assert_eq!(app.status_message, "PMT = -1199.1010503055");
+        assert_eq!(app.calculator_module.current_result, "-1199.1010503055");
+        assert_eq!(app.tvm.solved, Some(TvmField::Pmt));
+
+        // Halving the payment, the loan is never paid off
+        click(&mut app, "C");
+        app.calculator_module.insert_str("-500");
+        app.calculator_module.update_result();
+        click(&mut app, "PMT");
+        assert_eq!(app.tvm.solved, None);
+        click(&mut app, "CPT");
+        click(&mut app, "N");
+        assert_eq!(app.status_message, "No N fits the other values");
+        assert_eq!(app.tvm.get(TvmField::N), 360.0);
+    }
+
+    #[test]
+    fn basic_second_keypad_has_percentage_keys() {
+        let mut app = App::new();
+        app.toggle_second_function();
+        let buttons = app.get_calculator_buttons();
+        assert_eq!(
+            buttons[3],
+            vec![
+                ("%chg", "pctchange"),
+                ("markup", "markup"),
+                ("margin", "margin"),
+                ("π", "pi")
+            ]
+        );
+        app.button_position = Some((3, 0));
+        app.press_button();
+        app.calculator_module.insert_str("80, 100)");
+        app.calculator_module.update_result();
+        assert_eq!(
+            app.calculator_module.current_expression,
+            "pctchange(80, 100)"
+        );
+        assert_eq!(app.calculator_module.current_result, "25");
+    }
+
+    #[test]
+    fn constants_picker_inserts_at_the_cursor() {
+        let mut app = App::new();
+        app.state = AppState::Typing;
+        for c in "2*".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Char('k'), KeyModifiers::CONTROL);
+        assert!(matches!(app.popup, Some(Popup::Constants(_))));
+        for _ in 0..4 {
+            press(&mut app, KeyCode::Down, KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert!(app.popup.is_none());
+        assert_eq!(app.calculator_module.current_expression, "2*c");
+        assert_eq!(app.calculator_module.current_result, "599584916");
+        assert_eq!(
+            app.status_message,
+            "Inserted c: speed of light, 299792458 m/s"
+        );
+
+        // Constants need no definition to be graphed
+        app.calculator_module.set_expression("g*x^2".to_string());
+        app.enter_graph_mode();
+        assert_eq!(app.state, AppState::Graph);
+    }
+
+    #[test]
+    fn modulo_sign_toggles_from_the_palette() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("-7 % 3".to_string());
+        app.calculator_module.update_result();
+        assert_eq!(app.calculator_module.current_result, "-1");
+        app.run_command(Command::ToggleModulo);
+        assert_eq!(app.calculator_module.current_result, "2");
+        assert!(app.status_message.contains("-7 % 3 = 2"));
+        app.run_command(Command::ToggleModulo);
+        assert_eq!(
+            app.calculator_module.modulo_behavior,
+            ModuloBehavior::Truncated
+        );
+    }
+
+    #[test]
+    fn solving_lists_every_root_in_the_graph_range() {
+        let mut app = App::new();
+        app.calculator_module
+            .set_expression("solve(x^2 - 2, x, 1)".to_string());
+        app.calculate();
+        assert_eq!(app.calculator_module.current_result, "1.4142135624");
+        assert_eq!(app.status_message, "Roots for -10 to 10: -1.41421, 1.41421");
+
+        // One root needs no list, and the range follows the graph's
+        app.status_message.clear();
+        app.calculator_module
+            .set_expression("solve(x^3 - 8, x)".to_string());
+        app.calculate();
+        assert_eq!(app.calculator_module.current_result, "2");
+        assert_eq!(app.status_message, "");
+        app.graph_x_min = 0.0;
+        app.calculator_module
+            .set_expression("solve(sin(x), x, 3)".to_string());
+        app.calculate();
+        assert_eq!(
+            app.status_message,
+            "Roots for 0 to 10: 0, 3.14159, 6.28319, 9.42478"
+        );
+    }
+
+    #[test]
+    fn unit_converter_calculates_into_history() {
+        let mut app = App::new();
+        app.calculator_module.set_expression("2*50".to_string());
+        app.calculator_module.update_result();
+        press(&mut app, KeyCode::Char('U'), KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Convert);
+        assert_eq!(app.converter.input, "100");
+
+        // Up wraps round to data sizes, which start from bits to bytes
+        for code in [KeyCode::Up, KeyCode::Tab, KeyCode::Tab] {
+            press(&mut app, code, KeyModifiers::NONE);
+        }
+        assert_eq!(app.converter.result(), Some(12.5));
+        press(&mut app, KeyCode::Char('s'), KeyModifiers::NONE);
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Normal);
+        let entry = app.calculator_module.history.last().unwrap();
+        assert_eq!(entry.expression, "convert(100, \"B\", \"bit\")");
+        assert_eq!(entry.result, "800");
+
+        // The selection is kept for next time
+        app.open_converter();
+        assert_eq!(app.converter.to_unit().symbol, "bit");
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Normal);
+    }
+
+    #[test]
+    fn matrix_editor_inserts_into_the_expression() {
+        let mut app = App::new();
+        press(&mut app, KeyCode::Char('M'), KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Matrix);
+        for code in [
+            KeyCode::Char('2'),
+            KeyCode::Right,
+            KeyCode::Char('1'),
+            KeyCode::Down,
+            KeyCode::Char('3'),
+            KeyCode::Char('c'),
+        ] {
+            press(&mut app, code, KeyModifiers::NONE);
+        }
+        assert_eq!(app.matrix_editor.matrix.size(), (2, 3));
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Typing);
+        assert_eq!(
+            app.calculator_module.current_expression,
+            "[[2, 1, 0], [0, 3, 0]]"
+        );
+
+        // It lands at the cursor, to carry on from there
+        app.calculator_module
+            .set_expression("det( * [[1, 0], [0, 1], [0, 0]])".to_string());
+        app.calculator_module.set_cursor(4);
+        app.open_matrix_editor();
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        app.calculate();
+        assert_eq!(app.calculator_module.current_result, "6");
+
+        // The grid is kept between visits
+        app.open_matrix_editor();
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.matrix_editor.matrix.get(1, 1), 3.0);
+    }
+
+    #[test]
+    fn date_calculator_calculates_in_the_calculator() {
+        let mut app = App::new();
+        press(&mut app, KeyCode::Char('D'), KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Dates);
+        assert_eq!(app.dates.between(), Some(0));
+        app.dates.start = "2024-01-01".to_string();
+        press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+        for _ in 0..10 {
+            press(&mut app, KeyCode::Backspace, KeyModifiers::NONE);
+        }
+        for c in "2024-06-15".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.calculator_module.current_result, "166");
+        assert_eq!(
+            app.calculator_module.history[0].expression,
+            "days_between(\"2024-01-01\", \"2024-06-15\")"
+        );
+
+        // With the days selected, Enter moves the start by them
+        app.open_dates();
+        press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+        for c in "90".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(app.calculator_module.current_result, "\"2024-03-31\"");
+
+        app.open_dates();
+        press(&mut app, KeyCode::Backspace, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Backspace, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Dates);
+        assert_eq!(
+            app.status_message,
+            "Enter dates as YYYY-MM-DD and a whole number of days"
+        );
+    }
+
+    #[test]
+    fn statistics_table_plots_its_pairs() {
+        let mut app = App::new();
+        press(&mut app, KeyCode::Char('S'), KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Stats);
+        // x then y on each row; the last y is still typed when plotting
+        for (x, y) in [("1", "2"), ("3", "5")] {
+            for c in x.chars() {
+                press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+            }
+            press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+            for c in y.chars() {
+                press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+            }
+            if x == "1" {
+                press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+                press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+            }
+        }
+        press(&mut app, KeyCode::Char('g'), KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Graph);
+        assert_eq!(app.graph_module.scatter, [(1.0, 2.0), (3.0, 5.0)]);
+        // The line through them is drawn with them
+        assert_eq!(app.graph_expression, "0.5 + 1.5*x");
+        assert!(!app.graph_module.points.is_empty());
+        // The view fits the points, with a tenth of their spread either side
+        assert_eq!((app.graph_x_min, app.graph_x_max), (0.8, 3.2),);
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Stats);
+        // Two points don't pin down a parabola, so only the points are plotted
+        press(&mut app, KeyCode::Char('f'), KeyModifiers::NONE);
+        assert_eq!(app.stats.model, stats::Model::Quadratic);
+        app.graph_data();
+        assert_eq!(app.graph_expression, "");
+        assert!(app.status_message.starts_with("Plotted 2 points - "));
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+
+        // Graphing an expression afterwards plots it alone
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Normal);
+        app.calculator_module.set_expression("x^2".to_string());
+        app.enter_graph_mode();
+        assert!(app.graph_module.scatter.is_empty());
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.stats.pairs().len(), 2);
+    }
+}
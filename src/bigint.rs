@@ -0,0 +1,537 @@
+//! Arbitrary-precision integers for the opt-in big-integer mode.
+//!
+//! [`evaluate`] works out the integer subset of a parsed expression (`+ - * % ^ !`
+//! and integral variables) exactly. Anything outside that subset returns `None` so the
+//! caller can fall back to the f64 evaluator.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Neg;
+
+use crate::calculator::ModuloBehavior;
+use crate::eval::{BinaryOp, Evaluator, Expr, PostfixOp, Value};
+
+/// Limb base; a power of ten keeps decimal formatting cheap.
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+/// Default for the digit limit, past which results fall back to f64 rather than
+/// growing without bound.
+pub const MAX_DIGITS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    /// Little-endian base-10^9 limbs without trailing zeros; zero has no limbs.
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        Self {
+            negative: false,
+            limbs: Vec::new(),
+        }
+    }
+
+    pub fn from_u64(mut value: u64) -> Self {
+        let mut limbs = Vec::new();
+        while value > 0 {
+            limbs.push((value % BASE) as u32);
+            value /= BASE;
+        }
+        Self {
+            negative: false,
+            limbs,
+        }
+    }
+
+    /// Exact conversion of a whole f64 up to 2^53; past that the f64 has already
+    /// rounded away its lowest digits.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if value.fract() != 0.0 || value.abs() > 2f64.powi(53) {
+            return None;
+        }
+        Some(Self::from_u64(value.abs() as u64).with_sign(value < 0.0))
+    }
+
+    /// Parse a string of ASCII digits.
+    pub fn parse_digits(digits: &str) -> Option<Self> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut limbs = Vec::with_capacity(digits.len() / BASE_DIGITS + 1);
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(BASE_DIGITS);
+            limbs.push(digits[start..end].parse().ok()?);
+            end = start;
+        }
+        Some(
+            Self {
+                negative: false,
+                limbs,
+            }
+            .normalized(),
+        )
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn to_u32(&self) -> Option<u32> {
+        match self.limbs[..] {
+            _ if self.negative => None,
+            [] => Some(0),
+            [limb] => Some(limb),
+            [low, high] => u32::try_from(high as u64 * BASE + low as u64).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn digit_count(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => (self.limbs.len() - 1) * BASE_DIGITS + top.to_string().len(),
+            None => 1,
+        }
+    }
+
+    fn with_sign(mut self, negative: bool) -> Self {
+        self.negative = negative && !self.is_zero();
+        self
+    }
+
+    fn normalized(mut self) -> Self {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        let negative = self.negative;
+        self.with_sign(negative)
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return Self {
+                negative: self.negative,
+                limbs: add_mag(&self.limbs, &other.limbs),
+            };
+        }
+        match cmp_mag(&self.limbs, &other.limbs) {
+            Ordering::Equal => Self::zero(),
+            Ordering::Greater => Self {
+                negative: self.negative,
+                limbs: sub_mag(&self.limbs, &other.limbs),
+            }
+            .normalized(),
+            Ordering::Less => Self {
+                negative: other.negative,
+                limbs: sub_mag(&other.limbs, &self.limbs),
+            }
+            .normalized(),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&-other.clone())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        Self {
+            negative: self.negative != other.negative,
+            limbs: mul_mag(&self.limbs, &other.limbs),
+        }
+        .normalized()
+    }
+
+    /// Truncated remainder, matching f64 `%`: the result takes the dividend's sign.
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let (_, remainder) = divrem_mag(&self.limbs, &other.limbs);
+        Some(
+            Self {
+                negative: self.negative,
+                limbs: remainder,
+            }
+            .normalized(),
+        )
+    }
+
+    /// Quotient truncated toward zero, matching [`Self::rem`].
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, _) = divrem_mag(&self.limbs, &other.limbs);
+        Some(
+            Self {
+                negative: self.negative != other.negative,
+                limbs: quotient,
+            }
+            .normalized(),
+        )
+    }
+
+    /// Greatest common divisor, never negative; zero only when both are zero.
+    pub fn gcd(&self, other: &Self) -> Self {
+        let (mut a, mut b) = (
+            self.clone().with_sign(false),
+            other.clone().with_sign(false),
+        );
+        while let Some(remainder) = a.rem(&b) {
+            (a, b) = (b, remainder);
+        }
+        a
+    }
+
+    pub fn pow(&self, mut exponent: u32, max_digits: usize) -> Option<Self> {
+        let estimate = (self.digit_count() as u64).saturating_mul(exponent as u64);
+        if estimate > max_digits as u64 + exponent as u64 {
+            return None;
+        }
+        let mut result = Self::from_u64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+        Some(result)
+    }
+
+    /// This wrapped into a two's complement word of `bits` bits, the way
+    /// [`WordSize::wrap`](crate::programmer::WordSize::wrap) wraps an f64.
+    pub fn wrap(&self, bits: u32) -> Self {
+        let half = Self::from_u64(1 << (bits - 1));
+        let modulus = half.add(&half);
+        let wrapped = self.rem(&modulus).expect("the modulus isn't zero");
+        if !wrapped.sub(&half).is_negative() {
+            wrapped.sub(&modulus)
+        } else if wrapped.add(&half).is_negative() {
+            wrapped.add(&modulus)
+        } else {
+            wrapped
+        }
+    }
+
+    pub fn factorial(n: u32, max_digits: usize) -> Option<Self> {
+        let mut result = Self::from_u64(1);
+        for k in 2..=n as u64 {
+            result = result.mul(&Self::from_u64(k));
+            if result.digit_count() > max_digits {
+                return None;
+            }
+        }
+        Some(result)
+    }
+}
+
+impl Neg for BigInt {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        let negative = !self.negative;
+        self.with_sign(negative)
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((top, rest)) = self.limbs.split_last() else {
+            return f.write_str("0");
+        };
+        if self.negative {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", top)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        out.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        out.push(carry as u32);
+    }
+    out
+}
+
+/// `a - b` for `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += BASE as i64;
+            borrow = 1;
+        }
+        out.push(diff as u32);
+    }
+    out
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut out = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = out[i + j] + x as u64 * y as u64 + carry;
+            out[i + j] = cur % BASE;
+            carry = cur / BASE;
+        }
+        out[i + b.len()] += carry;
+    }
+    out.into_iter().map(|limb| limb as u32).collect()
+}
+
+fn mul_small(a: &[u32], factor: u64) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for &limb in a {
+        let cur = limb as u64 * factor + carry;
+        out.push((cur % BASE) as u32);
+        carry = cur / BASE;
+    }
+    while carry > 0 {
+        out.push((carry % BASE) as u32);
+        carry /= BASE;
+    }
+    while out.last() == Some(&0) {
+        out.pop();
+    }
+    out
+}
+
+/// Schoolbook long division, finding each quotient limb by binary search.
+fn divrem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for i in (0..a.len()).rev() {
+        remainder.insert(0, a[i]);
+        while remainder.last() == Some(&0) {
+            remainder.pop();
+        }
+        let (mut low, mut high) = (0u64, BASE - 1);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if cmp_mag(&mul_small(b, mid), &remainder) == Ordering::Greater {
+                high = mid - 1;
+            } else {
+                low = mid;
+            }
+        }
+        quotient[i] = low as u32;
+        remainder = sub_mag(&remainder, &mul_small(b, low));
+        while remainder.last() == Some(&0) {
+            remainder.pop();
+        }
+    }
+    while quotient.last() == Some(&0) {
+        quotient.pop();
+    }
+    (quotient, remainder)
+}
+
+/// Evaluate `tree` exactly if it only uses whole numbers and `+ - * % ^ !`, and no
+/// result along the way is longer than `max_digits`. Variables and `%` are read as
+/// `evaluator` reads them, and in programmer mode every value wraps to its word.
+pub fn evaluate(tree: &Expr, evaluator: &Evaluator, max_digits: usize) -> Option<BigInt> {
+    let eval = |expr: &Expr| evaluate(expr, evaluator, max_digits);
+    let value = match tree {
+        Expr::Literal(_, text) => BigInt::parse_digits(text)?,
+        // Hex, binary and octal literals
+        Expr::Number(value) => BigInt::from_f64(*value)?,
+        Expr::Variable(_) => match evaluator.eval_value(tree).ok()? {
+            Value::Number(value) => BigInt::from_f64(value)?,
+            _ => return None,
+        },
+        Expr::Negate(operand) => -eval(operand)?,
+        Expr::Binary(BinaryOp::Add, left, right) => eval(left)?.add(&eval(right)?),
+        Expr::Binary(BinaryOp::Subtract, left, right) => eval(left)?.sub(&eval(right)?),
+        Expr::Binary(BinaryOp::Multiply, left, right) => eval(left)?.mul(&eval(right)?),
+        Expr::Binary(BinaryOp::Remainder, left, right) => {
+            let divisor = eval(right)?;
+            let remainder = eval(left)?.rem(&divisor)?;
+            match evaluator.modulo {
+                // The floored modulo takes the divisor's sign
+                ModuloBehavior::Floored
+                    if !remainder.is_zero() && remainder.is_negative() != divisor.is_negative() =>
+                {
+                    remainder.add(&divisor)
+                }
+                _ => remainder,
+            }
+        }
+        // A negative exponent isn't an integer result
+        Expr::Binary(BinaryOp::Power, base, exponent) => {
+            eval(base)?.pow(eval(exponent)?.to_u32()?, max_digits)?
+        }
+        Expr::Postfix(PostfixOp::Factorial, operand) => {
+            BigInt::factorial(eval(operand)?.to_u32()?, max_digits)?
+        }
+        _ => return None,
+    };
+    let value = match evaluator.integers {
+        Some(word) => value.wrap(word.bits()),
+        None => value,
+    };
+    (value.digit_count() <= max_digits).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::programmer::WordSize;
+    use std::collections::BTreeMap;
+
+    fn eval_with(expr: &str, evaluator: &Evaluator, max_digits: usize) -> Option<String> {
+        let tree = evaluator.parse(expr).ok()?;
+        evaluate(&tree, evaluator, max_digits).map(|n| n.to_string())
+    }
+
+    fn eval(expr: &str) -> Option<String> {
+        eval_with(expr, &Evaluator::new(&BTreeMap::new()), MAX_DIGITS)
+    }
+
+    #[test]
+    fn exact_integer_arithmetic() {
+        assert_eq!(eval("2^64 - 1").as_deref(), Some("18446744073709551615"));
+        assert_eq!(
+            eval("2^100").as_deref(),
+            Some("1267650600228229401496703205376")
+        );
+        assert_eq!(eval("25!").as_deref(), Some("15511210043330985984000000"));
+        assert_eq!(
+            eval("30!").as_deref(),
+            Some("265252859812191058636308480000000")
+        );
+        assert_eq!(eval("0!").as_deref(), Some("1"));
+        assert_eq!(eval("-7 % 3").as_deref(), Some("-1"));
+        assert_eq!(eval("2^64 % 1000000007").as_deref(), Some("582344008"));
+        assert_eq!(eval("3(4 - 10)").as_deref(), Some("-18"));
+        assert_eq!(eval("-2^2").as_deref(), Some("-4"));
+        assert_eq!(eval("(-2)^2").as_deref(), Some("4"));
+        assert_eq!(eval("2^3^2").as_deref(), Some("512"));
+        assert_eq!(eval("2**100"), eval("2^100"));
+        assert_eq!(
+            eval("1000000000 * 1000000000 - 1").as_deref(),
+            Some("999999999999999999")
+        );
+        assert_eq!(
+            eval("123_456_789_012_345_678_901 + 0xFF").as_deref(),
+            Some("123456789012345679156")
+        );
+    }
+
+    #[test]
+    fn settings_are_read_as_the_evaluator_reads_them() {
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        let floored = Evaluator {
+            modulo: ModuloBehavior::Floored,
+            ..evaluator
+        };
+        assert_eq!(
+            eval_with("-7 % 3", &floored, MAX_DIGITS).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            eval_with("7 % -3", &floored, MAX_DIGITS).as_deref(),
+            Some("-2")
+        );
+        let percent = Evaluator {
+            percent: crate::calculator::PercentBehavior::Percent,
+            ..evaluator
+        };
+        assert_eq!(eval_with("50%", &percent, MAX_DIGITS), None);
+
+        // Programmer mode wraps every value to its word
+        let word = |size| Evaluator {
+            integers: Some(size),
+            ..evaluator
+        };
+        assert_eq!(
+            eval_with("2^70", &word(WordSize::Bits64), MAX_DIGITS).as_deref(),
+            Some("0")
+        );
+        assert_eq!(
+            eval_with("2^63", &word(WordSize::Bits64), MAX_DIGITS).as_deref(),
+            Some("-9223372036854775808")
+        );
+        assert_eq!(
+            eval_with("127 + 1", &word(WordSize::Bits8), MAX_DIGITS).as_deref(),
+            Some("-128")
+        );
+        assert_eq!(
+            eval_with("(-129) * 1", &word(WordSize::Bits8), MAX_DIGITS).as_deref(),
+            Some("127")
+        );
+    }
+
+    #[test]
+    fn division_truncates_and_gcd_is_positive() {
+        let n = |value: i64| BigInt::from_u64(value.unsigned_abs()).with_sign(value < 0);
+        assert_eq!(n(-7).div(&n(2)), Some(n(-3)));
+        assert_eq!(n(7).div(&n(0)), None);
+        let big = BigInt::parse_digits("1000000000000000000000").unwrap();
+        assert_eq!(big.div(&n(1_000_000_000_000)), Some(n(1_000_000_000)));
+        assert_eq!(n(-12).gcd(&n(18)), n(6));
+        assert_eq!(n(0).gcd(&n(-5)), n(5));
+        assert!(n(0).gcd(&n(0)).is_zero());
+    }
+
+    #[test]
+    fn non_integer_input_falls_back() {
+        for expr in [
+            "1.5 + 1", "7 / 2", "2^-1", "sqrt(4)", "pi", "5 % 0", "(1 + 2", "1e3",
+        ] {
+            assert_eq!(eval(expr), None, "{}", expr);
+        }
+        let variables = BTreeMap::from([("n".to_string(), 20.0), ("h".to_string(), 0.5)]);
+        let evaluator = Evaluator::new(&variables);
+        assert_eq!(
+            eval_with("n!", &evaluator, MAX_DIGITS).as_deref(),
+            Some("2432902008176640000")
+        );
+        assert_eq!(eval_with("h * 2", &evaluator, MAX_DIGITS), None);
+        // Whole numbers past 2^53 have already been rounded as f64s
+        assert_eq!(eval("0x20000000000002"), None);
+        // Past the digit limit the f64 evaluator takes over
+        assert_eq!(eval_with("25!", &evaluator, 20), None);
+        assert_eq!(eval_with("10^15 * 10^15", &evaluator, 20), None);
+    }
+}
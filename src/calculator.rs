@@ -1,25 +1,739 @@
-use anyhow::Result;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+use std::time::{Duration, Instant};
+
+use crate::bigint::{self, BigInt};
+use crate::constants::CONSTANTS;
+use crate::dates;
+use crate::eval::{
+    self, ANS, EXPECTED_OPERATOR, Evaluator, Expr, FUNCTIONS, INTERVAL_FUNCTIONS,
+    INVERSE_ANGLE_FUNCTIONS, PostfixOp, SERIES_FUNCTIONS, TEXT_FUNCTIONS, Token, Value,
+    is_function, tokenize, uses_angle_mode,
+};
+use crate::matrix::Matrix;
+use crate::programmer::WordSize;
+use crate::random::Rng;
+use crate::rational::{self, Rational};
+use crate::units;
 
 #[derive(Debug, Clone)]
 pub struct CalculationEntry {
+    /// The expression with canonical spacing; see [`normalize`].
     pub expression: String,
+    /// The expression exactly as typed, when that differs from `expression`.
+    pub raw: Option<String>,
     pub result: String,
     pub timestamp: DateTime<Local>,
+    pub context: Option<EntryContext>,
+    /// How long evaluating it took.
+    pub duration: Duration,
+    /// How many digits of the result survived cancellation, when too few to trust it
+    /// all; see [`reliable_digits`].
+    pub reliable_digits: Option<u32>,
+}
+
+impl CalculationEntry {
+    /// The expression as it was typed.
+    pub fn typed(&self) -> &str {
+        self.raw.as_deref().unwrap_or(&self.expression)
+    }
+}
+
+/// The evaluation context an entry depended on, kept so it can be read (and restored) later.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryContext {
+    /// Set only when the expression used an angle-dependent function.
+    pub angle_mode: Option<AngleMode>,
+    /// Values of the variables the expression referenced, in order of first use.
+    pub bindings: Vec<(String, f64)>,
+}
+
+impl fmt::Display for EntryContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(angle_mode) = self.angle_mode {
+            parts.push(angle_mode.label().to_string());
+        }
+        for (name, value) in &self.bindings {
+            parts.push(format!("{}={}", name, format_result(*value)));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
 pub struct CalculatorModule {
     pub current_expression: String,
     pub current_result: String,
+    /// Full-precision number behind `current_result`, when it is one.
+    pub result_value: Option<f64>,
+    /// Show results rounded to this many significant figures.
+    pub sig_figs: Option<usize>,
+    /// How the result is written out; only the display changes.
+    pub result_format: ResultFormat,
     pub history: Vec<CalculationEntry>,
     pub error_message: Option<String>,
+    /// A likely fix for the current error, shown next to it.
+    pub error_hint: Option<String>,
+    /// The error behind `error_message`, when it came from evaluation.
+    pub error: Option<EvalError>,
+    /// Where in the expression `error` was found, in chars.
+    pub error_column: Option<usize>,
     pub mode: CalculatorMode,
+    pub angle_mode: AngleMode,
+    /// The width programmer mode's integers wrap to.
+    pub word_size: WordSize,
+    pub variables: BTreeMap<String, f64>,
+    /// Full-precision value of the last successful calculation.
+    pub last_value: Option<f64>,
+    /// Evaluate integer-only expressions exactly instead of in f64.
+    pub big_integers: bool,
+    /// Show results as exact fractions when the expression only uses rational
+    /// arithmetic.
+    pub fractions: bool,
+    /// Longest exact integer, in digits, before falling back to f64.
+    pub max_digits: usize,
+    /// How `%` is read; see [`PercentBehavior`].
+    pub percent_behavior: PercentBehavior,
+    /// Which sign `%` between operands takes; see [`ModuloBehavior`].
+    pub modulo_behavior: ModuloBehavior,
+    /// Separators for shown results; see [`DigitGrouping`].
+    pub digit_grouping: DigitGrouping,
+    /// The result previews the expression with a dangling operator trimmed off.
+    pub partial_preview: bool,
+    /// Why a half-typed expression has no preview, when its brackets don't balance or
+    /// it doesn't parse, to show in place of the result.
+    pub preview_issue: Option<String>,
+    /// Check calculated results for cancellation, the loss of digits from subtracting
+    /// nearly equal values.
+    pub cancellation_check: bool,
+    /// Digits of the calculated result that survived cancellation, when it lost too
+    /// many; cleared once the expression is edited.
+    pub reliable_digits: Option<u32>,
+    /// Expression whose last calculation failed on a missing paren; calculating it again
+    /// closes the parens.
+    autoclose: Option<String>,
+    /// The expression as it was before a recall, clear or calculate last replaced it.
+    pub alternate_expression: Option<String>,
+    /// Characters between the cursor and the end of the expression, so edits that
+    /// replace the whole expression leave the cursor at the end.
+    cursor_from_end: usize,
+    /// Where `rand`, `randint` and `randn` draw from.
+    pub rng: Rng,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CalculatorMode {
     Basic,
     Scientific,
+    /// Integers only, with hex digits and bitwise operators on the keypad and each
+    /// result read out in several bases.
+    Programmer,
+    /// Time-value-of-money keys (N, I/Y, PV, PMT, FV) beside the numbers, storing the
+    /// result into each or working one out from the others.
+    Financial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            AngleMode::Radians => "RAD",
+            AngleMode::Degrees => "DEG",
+        }
+    }
+
+    pub fn to_radians(self, value: f64) -> f64 {
+        match self {
+            AngleMode::Radians => value,
+            AngleMode::Degrees => value.to_radians(),
+        }
+    }
+
+    /// `radians` in this mode's unit.
+    pub fn from_radians(self, radians: f64) -> f64 {
+        match self {
+            AngleMode::Radians => radians,
+            AngleMode::Degrees => radians.to_degrees(),
+        }
+    }
+}
+
+/// How `%` reads in an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PercentBehavior {
+    /// `a % b` is the remainder; a trailing `%` is an error.
+    #[default]
+    Modulo,
+    /// `%` always divides what comes before it by 100, as in a spreadsheet.
+    Percent,
+    /// Modulo when an operand directly follows, otherwise a percent. A percent added to
+    /// or taken off a running total is a percent of that total, so `50+10%` is 55.
+    Contextual,
+}
+
+impl PercentBehavior {
+    pub fn name(self) -> &'static str {
+        match self {
+            PercentBehavior::Modulo => "modulo",
+            PercentBehavior::Percent => "percent",
+            PercentBehavior::Contextual => "contextual",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Modulo, Self::Percent, Self::Contextual]
+            .into_iter()
+            .find(|behavior| behavior.name() == name)
+    }
+}
+
+/// How a numeric result is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResultFormat {
+    /// Plain digits, such as `1234500`.
+    #[default]
+    Decimal,
+    /// One digit before the point and a power of ten, such as `1.2345e6`.
+    Scientific,
+    /// Like scientific, with the exponent a multiple of 3, such as `1.2345e6` or
+    /// `12.3e3`.
+    Engineering,
+    /// The nearest simple fraction, such as `3/8`, when one matches.
+    Fraction,
+}
+
+impl ResultFormat {
+    const ALL: [ResultFormat; 4] = [
+        ResultFormat::Decimal,
+        ResultFormat::Scientific,
+        ResultFormat::Engineering,
+        ResultFormat::Fraction,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ResultFormat::Decimal => "decimal",
+            ResultFormat::Scientific => "scientific",
+            ResultFormat::Engineering => "engineering",
+            ResultFormat::Fraction => "fraction",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    /// The format after this one, back around to decimal.
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|&format| format == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Which sign `a % b` takes when the operands' signs differ.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ModuloBehavior {
+    /// The truncated remainder, with the sign of `a`, as in C and Rust: `-7 % 3` is -1.
+    #[default]
+    Truncated,
+    /// The floored modulo, with the sign of `b`, as in Python and `mod()`: `-7 % 3`
+    /// is 2.
+    Floored,
+}
+
+impl ModuloBehavior {
+    pub fn name(self) -> &'static str {
+        match self {
+            ModuloBehavior::Truncated => "truncated",
+            ModuloBehavior::Floored => "floored",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Truncated, Self::Floored]
+            .into_iter()
+            .find(|behavior| behavior.name() == name)
+    }
+}
+
+/// Which separators group a number's digits, as the locale writes them. Only shown
+/// results are grouped; typed numbers are read the same under every grouping, with
+/// underscores to group their digits.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DigitGrouping {
+    /// No grouping: `1234567.89`.
+    #[default]
+    Off,
+    /// Commas between thousands and a decimal point: `1,234,567.89`.
+    Comma,
+    /// Periods between thousands and a decimal comma: `1.234.567,89`.
+    Period,
+}
+
+impl DigitGrouping {
+    pub fn name(self) -> &'static str {
+        match self {
+            DigitGrouping::Off => "off",
+            DigitGrouping::Comma => "comma",
+            DigitGrouping::Period => "period",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Off, Self::Comma, Self::Period]
+            .into_iter()
+            .find(|grouping| grouping.name() == name)
+    }
+
+    /// The group separator and decimal point, when digits are grouped.
+    pub fn separators(self) -> Option<(char, char)> {
+        match self {
+            DigitGrouping::Off => None,
+            DigitGrouping::Comma => Some((',', '.')),
+            DigitGrouping::Period => Some(('.', ',')),
+        }
+    }
+}
+
+/// Digits a result must lose to cancellation before it is flagged.
+const CANCELLATION_LOSS: f64 = 6.0;
+
+/// Why an expression couldn't be evaluated or graphed. Callers match on the kind rather
+/// than reading the message; [`EvalError::is_syntax`] separates typos from the rest.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    InvalidCharacter(char),
+    /// Digits that don't form a number, like `1.2.3`.
+    InvalidNumber(String),
+    /// A token that can't go where it is; `pos` is its column in the statement,
+    /// counted in chars from 0.
+    Syntax {
+        pos: usize,
+        expected: &'static str,
+    },
+    /// The expression stops where more was needed.
+    UnexpectedEnd {
+        expected: &'static str,
+    },
+    /// A `)` was needed at column `pos`, counted like [`EvalError::Syntax`]'s.
+    MissingParen {
+        pos: usize,
+    },
+    UndefinedVariable(String),
+    UnknownFunction(String),
+    Arity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    DivisionByZero,
+    /// `arg` is outside what `func` accepts. `reason` says so, with `{func}` and `{arg}`
+    /// standing for the two.
+    Domain {
+        func: &'static str,
+        arg: f64,
+        reason: &'static str,
+    },
+    /// A text function such as `ratio` used inside a larger expression.
+    TextInExpression(String),
+    /// A text function's result assigned to a variable.
+    TextAssignment(String),
+    /// A list where a single number is needed, such as in a comparison or as one of
+    /// several arguments.
+    UnexpectedList,
+    /// Two lists combined value by value, of these lengths.
+    ListLengths(usize, usize),
+    /// A list, matrix or date assigned to a variable, which holds only numbers.
+    ListAssignment,
+    /// A matrix where a number or list is needed.
+    UnexpectedMatrix,
+    /// Matrices, or a matrix and a list, of sizes `op` can't combine, as rows by
+    /// columns.
+    MatrixSizes {
+        op: &'static str,
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+    /// A matrix of this many rows and columns where a square one is needed.
+    NotSquare(usize, usize),
+    /// Matrix rows of different lengths, or with no values.
+    RaggedMatrix,
+    /// A date or time where a number, list or matrix is needed, or two combined by an
+    /// operator that can't.
+    UnexpectedDate,
+    /// A value with an uncertainty where a plain number is needed, or combined by an
+    /// operator that can't carry its error.
+    UnexpectedUncertain,
+    /// Something other than a date or time where a date function needs one.
+    ExpectedDate,
+    /// Quoted text that isn't a date written as `YYYY-MM-DD`, nor a time with
+    /// `HH:MM:SS` after it.
+    InvalidDate(String),
+    /// A result too large for f64, with its base-10 logarithm.
+    Overflow {
+        log10: f64,
+    },
+    /// A unit `convert` doesn't know.
+    UnknownUnit(String),
+    /// `solve` found nowhere its expression is zero.
+    NoRoot,
+    /// Units that measure different things, by symbol.
+    IncompatibleUnits {
+        from: &'static str,
+        to: &'static str,
+    },
+}
+
+impl EvalError {
+    /// `arg` is outside what `func` accepts, with nothing more to say about why.
+    pub fn undefined(func: &'static str, arg: f64) -> Self {
+        EvalError::Domain {
+            func,
+            arg,
+            reason: "{func}() is undefined for {arg}",
+        }
+    }
+
+    /// Whether the expression is malformed, as opposed to well formed but with no
+    /// value; the UI shows the two differently.
+    pub fn is_syntax(&self) -> bool {
+        matches!(
+            self,
+            EvalError::InvalidCharacter(_)
+                | EvalError::InvalidNumber(_)
+                | EvalError::Syntax { .. }
+                | EvalError::UnexpectedEnd { .. }
+                | EvalError::MissingParen { .. }
+        )
+    }
+
+    /// Turn a parser position, a token index, into a column using the tokens' offsets.
+    /// The index past the last token is `end`, the column after the text.
+    pub(crate) fn locate(self, offsets: &[usize], end: usize) -> Self {
+        let column = |pos: usize| offsets.get(pos).copied().unwrap_or(end);
+        match self {
+            EvalError::Syntax { pos, expected } => EvalError::Syntax {
+                pos: column(pos),
+                expected,
+            },
+            EvalError::MissingParen { pos } => EvalError::MissingParen { pos: column(pos) },
+            error => error,
+        }
+    }
+
+    /// The column the error was found at, when it has one.
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            EvalError::Syntax { pos, .. } | EvalError::MissingParen { pos } => Some(*pos),
+            _ => None,
+        }
+    }
+}
+
+pub type EvalResult<T> = std::result::Result<T, EvalError>;
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::InvalidCharacter(ch) => write!(f, "Invalid character: {}", ch),
+            EvalError::InvalidNumber(text) => write!(f, "Invalid number: {}", text),
+            EvalError::Syntax { pos, expected } => {
+                write!(f, "Expected {} at column {}", expected, pos + 1)
+            }
+            EvalError::UnexpectedEnd { expected } => {
+                write!(f, "Unexpected end of expression, expected {}", expected)
+            }
+            EvalError::MissingParen { pos } => write!(f, "Expected ) at column {}", pos + 1),
+            EvalError::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
+            EvalError::UnknownFunction(name) => write!(f, "Unknown function: {}", name),
+            EvalError::Arity {
+                name,
+                expected,
+                got,
+            } => {
+                let count = match eval::arity(name) {
+                    (fewest, most) if most == fewest + 1 => format!("{} or {}", fewest, most),
+                    (fewest, usize::MAX) => format!("at least {}", fewest),
+                    (fewest, most) if fewest < most => format!("{} to {}", fewest, most),
+                    _ => expected.to_string(),
+                };
+                let plural = if *expected == 1 && !count.contains(' ') {
+                    ""
+                } else {
+                    "s"
+                };
+                write!(
+                    f,
+                    "{}() takes {} argument{}, got {}",
+                    name, count, plural, got
+                )
+            }
+            EvalError::DivisionByZero => f.write_str("Division by zero"),
+            EvalError::Domain { func, arg, reason } => f.write_str(
+                &reason
+                    .replace("{func}", func)
+                    .replace("{arg}", &format_result(*arg)),
+            ),
+            EvalError::TextInExpression(name) => {
+                write!(
+                    f,
+                    "{}() gives text, so it must be the whole expression",
+                    name
+                )
+            }
+            EvalError::TextAssignment(name) => {
+                write!(f, "Can't assign the text result of {}()", name)
+            }
+            EvalError::UnexpectedList => f.write_str("Expected a number here, not a list"),
+            EvalError::ListLengths(left, right) => {
+                write!(f, "Lists of different lengths: {} and {}", left, right)
+            }
+            EvalError::ListAssignment => {
+                f.write_str("Can't assign a list, matrix, date or uncertain value to a variable")
+            }
+            EvalError::UnexpectedMatrix => f.write_str("Expected a number here, not a matrix"),
+            EvalError::MatrixSizes { op, left, right } => write!(
+                f,
+                "Matrix sizes don't fit: {}×{} {} {}×{}",
+                left.0, left.1, op, right.0, right.1
+            ),
+            EvalError::NotSquare(rows, cols) => {
+                write!(f, "Needs a square matrix, not {}×{}", rows, cols)
+            }
+            EvalError::RaggedMatrix => {
+                f.write_str("Every row of a matrix needs the same number of values")
+            }
+            EvalError::UnexpectedDate => f.write_str("Expected a number here, not a date"),
+            EvalError::UnexpectedUncertain => {
+                f.write_str("Expected a number here, not a value with an uncertainty")
+            }
+            EvalError::ExpectedDate => f.write_str("Expected a date here, such as \"2024-06-15\""),
+            EvalError::InvalidDate(text) => {
+                write!(
+                    f,
+                    "Not a date: \"{}\" (use YYYY-MM-DD, and HH:MM:SS for a time)",
+                    text
+                )
+            }
+            EvalError::Overflow { log10 } if log10.is_finite() => write!(
+                f,
+                "Too large to calculate with ({})",
+                format_magnitude(*log10)
+            ),
+            EvalError::Overflow { .. } => f.write_str("Too large to calculate with"),
+            EvalError::UnknownUnit(unit) => write!(f, "Unknown unit: {}", unit),
+            EvalError::NoRoot => f.write_str("No root found"),
+            EvalError::IncompatibleUnits { from, to } => {
+                write!(f, "Can't convert {} to {}", from, to)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A likely fix for `error` in `expr`, for showing alongside the error.
+pub fn suggest(error: &EvalError, expr: &str) -> Option<String> {
+    match error {
+        EvalError::InvalidCharacter(',') => {
+            Some("use . for decimals; commas only separate function arguments".to_string())
+        }
+        EvalError::InvalidCharacter('×') => Some("use * to multiply".to_string()),
+        EvalError::InvalidCharacter('÷') => Some("use / to divide".to_string()),
+        EvalError::InvalidCharacter(_) => None,
+        EvalError::MissingParen { .. } if expr.matches('(').count() > expr.matches(')').count() => {
+            Some("calculate again (= or Enter) to auto-close".to_string())
+        }
+        EvalError::MissingParen { .. } => None,
+        EvalError::UnknownFunction(name) => {
+            closest_name(name).map(|known| format!("did you mean {}?", known))
+        }
+        EvalError::UndefinedVariable(name) if name == ANS => {
+            Some("ans is the last result; calculate something first".to_string())
+        }
+        EvalError::UndefinedVariable(name) => match closest_name(name) {
+            Some(known) => Some(format!("did you mean {}?", known)),
+            None => Some(format!("assign it first, e.g. {} = 1", name)),
+        },
+        EvalError::Syntax {
+            expected: EXPECTED_OPERATOR,
+            ..
+        } => Some("use * to multiply".to_string()),
+        EvalError::Arity { expected, got, .. } if got < expected => {
+            Some("separate arguments with commas".to_string())
+        }
+        EvalError::TextInExpression(name) => Some(format!("calculate {}() on its own", name)),
+        EvalError::UnexpectedList => {
+            Some("sum, prod, mean, min and max turn a list into a number".to_string())
+        }
+        EvalError::UnexpectedMatrix => Some("det turns a matrix into a number".to_string()),
+        EvalError::UnexpectedDate => {
+            Some("days_between or subtracting gives the days between dates".to_string())
+        }
+        EvalError::UnexpectedUncertain => {
+            Some("+ - * / ^ and functions of one number carry the error".to_string())
+        }
+        EvalError::MatrixSizes { op: "*", .. } => {
+            Some("the first needs as many columns as the second has rows".to_string())
+        }
+        EvalError::Overflow { .. } => {
+            Some("big-integer mode (Ctrl+p) works with exact integers".to_string())
+        }
+        EvalError::UnknownUnit(_) => Some("the unit converter (U) lists every unit".to_string()),
+        EvalError::NoRoot => {
+            Some("give a guess near a root, or two values either side of one".to_string())
+        }
+        EvalError::IncompatibleUnits { from, to } => {
+            let category = |symbol| units::find_unit(symbol).map(|unit| unit.category.label());
+            Some(format!(
+                "{} is a {} unit and {} a {} one",
+                from,
+                category(from)?,
+                to,
+                category(to)?
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// The built-in name nearest to `name`, if one is close enough to be a typo of it.
+fn closest_name(name: &str) -> Option<&'static str> {
+    // Very short names are more likely to be variables than misspelt functions
+    if name.chars().count() < 3 {
+        return None;
+    }
+    let max_distance = if name.chars().count() <= 4 { 1 } else { 2 };
+    FUNCTIONS
+        .iter()
+        .chain(TEXT_FUNCTIONS)
+        .copied()
+        .chain(CONVERSIONS.iter().map(|conversion| conversion.name))
+        .chain(CONSTANTS.iter().map(|constant| constant.name))
+        .map(|known| (levenshtein(name, known), known))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, known)| known)
+}
+
+/// Edit distance between `a` and `b`: the fewest single-char inserts, deletes and
+/// substitutions that turn one into the other.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Largest term `ratio` will use when looking for a simple integer ratio.
+const MAX_RATIO_TERM: u64 = 1000;
+
+/// Largest term the fraction result format writes.
+const MAX_FRACTION_TERM: u64 = 1_000_000;
+
+/// A named unary transform offered by the result conversion popup.
+///
+/// Each one is also callable by name in expressions, so recalled entries such as
+/// `deg(1.5708)` evaluate the same way they were recorded.
+pub struct Conversion {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub apply: fn(f64) -> f64,
+}
+
+pub const CONVERSIONS: &[Conversion] = &[
+    Conversion {
+        name: "deg",
+        label: "to degrees",
+        apply: f64::to_degrees,
+    },
+    Conversion {
+        name: "rad",
+        label: "to radians",
+        apply: f64::to_radians,
+    },
+    Conversion {
+        name: "mulpi",
+        label: "×π",
+        apply: |x| x * std::f64::consts::PI,
+    },
+    Conversion {
+        name: "divpi",
+        label: "÷π",
+        apply: |x| x / std::f64::consts::PI,
+    },
+    Conversion {
+        name: "pct",
+        label: "to percent",
+        apply: |x| x * 100.0,
+    },
+    Conversion {
+        name: "db",
+        label: "to dB (10·log10)",
+        apply: |x| 10.0 * x.log10(),
+    },
+];
+
+pub fn find_conversion(name: &str) -> Option<&'static Conversion> {
+    CONVERSIONS.iter().find(|c| c.name == name)
+}
+
+/// What a statement evaluates to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Number(f64),
+    /// Exact result from big-integer mode.
+    Integer(BigInt),
+    /// Exact result from fraction mode, when it isn't a whole number.
+    Fraction(Rational),
+    /// A number too large for f64, as its base-10 logarithm.
+    Huge(f64),
+    Text(String),
+    /// The values of a list, such as `[1, 2, 3] * 2`.
+    List(Vec<f64>),
+    Matrix(Matrix),
+    Date(NaiveDate),
+    Time(DateTime<FixedOffset>),
+    /// A value and its standard error.
+    Uncertain(f64, f64),
+}
+
+impl Outcome {
+    /// The numeric value, rounded to f64 for exact results; `None` for text and for
+    /// numbers too large to hold.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Outcome::Number(value) => Some(*value),
+            Outcome::Integer(value) => Some(value.to_f64()),
+            Outcome::Fraction(value) => Some(value.to_f64()),
+            Outcome::Huge(_)
+            | Outcome::Text(_)
+            | Outcome::List(_)
+            | Outcome::Matrix(_)
+            | Outcome::Date(_)
+            | Outcome::Time(_)
+            | Outcome::Uncertain(..) => None,
+        }
+    }
 }
 
 impl Default for CalculatorModule {
@@ -33,63 +747,251 @@ impl CalculatorModule {
         Self {
             current_expression: String::new(),
             current_result: String::from("0"),
+            result_value: Some(0.0),
+            sig_figs: None,
+            result_format: ResultFormat::default(),
             history: Vec::new(),
             error_message: None,
+            error_column: None,
+            error_hint: None,
+            error: None,
             mode: CalculatorMode::Basic,
+            angle_mode: AngleMode::Radians,
+            word_size: WordSize::default(),
+            variables: BTreeMap::new(),
+            last_value: None,
+            big_integers: false,
+            fractions: false,
+            max_digits: bigint::MAX_DIGITS,
+            percent_behavior: PercentBehavior::default(),
+            modulo_behavior: ModuloBehavior::default(),
+            digit_grouping: DigitGrouping::default(),
+            partial_preview: false,
+            preview_issue: None,
+            cancellation_check: true,
+            reliable_digits: None,
+            autoclose: None,
+            alternate_expression: None,
+            cursor_from_end: 0,
+            rng: Rng::from_clock(),
         }
     }
 
     pub fn append_digit(&mut self, digit: char) {
-        self.error_message = None;
-        self.current_expression.push(digit);
-        self.update_result();
+        self.insert_str(&digit.to_string());
+    }
+
+    /// Type a hex digit, first making the number before the cursor a `0x` literal if it
+    /// isn't one already.
+    pub fn append_hex_digit(&mut self, digit: char) {
+        let before = self.text_before_cursor();
+        let word = before
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric())
+            .len();
+        let hex = before[word..].starts_with("0x") || before[word..].starts_with("0X");
+        if !hex && before[word..].bytes().all(|b| b.is_ascii_digit()) {
+            self.current_expression.insert_str(word, "0x");
+        }
+        self.insert_str(&digit.to_string());
     }
 
     pub fn append_operator(&mut self, op: &str) {
-        self.error_message = None;
+        self.clear_error();
         // Allow leading unary minus via buttons
-        if self.current_expression.is_empty() {
+        let Some(last_char) = self.text_before_cursor().chars().last() else {
             if op == "-" {
-                self.current_expression.push('-');
+                self.insert_at_cursor("-");
             }
             return;
+        };
+        let before = self.text_before_cursor();
+        // A second * or / makes Python's ** or //; after that operators replace both chars
+        if before.ends_with("**") || before.ends_with("//") {
+            self.remove_before_cursor();
+        } else if matches!(op, "*" | "/") && before.ends_with(op) {
+            self.insert_at_cursor(op);
+            return;
         }
-        if !self.current_expression.is_empty() {
-            let last_char = self.current_expression.chars().last().unwrap();
-            if "+-*/^%".contains(last_char) {
-                self.current_expression.pop();
-            }
-            self.current_expression.push_str(op);
+        if "+-*/^%".contains(last_char) {
+            self.remove_before_cursor();
         }
+        self.insert_at_cursor(op);
     }
 
     pub fn append_decimal(&mut self) {
-        self.error_message = None;
-        let parts: Vec<&str> = self
-            .current_expression
-            .split(|c: char| "+-*/^%".contains(c))
-            .collect();
-        if let Some(last_part) = parts.last()
+        self.clear_error();
+        let before = self.text_before_cursor();
+        let last_part = before.rsplit(|c: char| "+-*/^%".contains(c)).next();
+        if let Some(last_part) = last_part
             && !last_part.contains('.')
         {
             if last_part.is_empty() {
-                self.current_expression.push_str("0.");
+                self.insert_at_cursor("0.");
             } else {
-                self.current_expression.push('.');
+                self.insert_at_cursor(".");
+            }
+        }
+    }
+
+    /// Insert text at the cursor and refresh the live result.
+    pub fn insert_str(&mut self, text: &str) {
+        self.clear_error();
+        self.insert_at_cursor(text);
+        self.update_result();
+    }
+
+    /// Insert the previous result as a parenthesized operand, at full precision.
+    pub fn insert_last_result(&mut self) -> bool {
+        match self.last_value {
+            Some(value) => {
+                // f64's Display is the shortest string that parses back to the same value
+                self.insert_str(&format!("({})", value));
+                true
             }
+            None => false,
         }
     }
 
     pub fn backspace(&mut self) {
-        self.error_message = None;
-        self.current_expression.pop();
+        // The first press after an error only dismisses it
+        if self.acknowledge_error(true) {
+            return;
+        }
+        self.remove_before_cursor();
+        self.update_result();
+    }
+
+    /// Delete the character under the cursor.
+    pub fn delete(&mut self) {
+        self.clear_error();
+        let cursor = self.cursor();
+        if cursor < self.current_expression.chars().count() {
+            let at = self.byte_index(cursor);
+            self.current_expression.remove(at);
+            self.cursor_from_end -= 1;
+            self.update_result();
+        }
+    }
+
+    /// Replace the chars in `range` with `text`, leaving the cursor after it.
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        self.clear_error();
+        let bytes = self.byte_index(range.start)..self.byte_index(range.end);
+        self.current_expression.replace_range(bytes, text);
+        self.set_cursor(range.start + text.chars().count());
+        self.update_result();
+    }
+
+    /// Replace the whole expression, leaving the cursor at its end.
+    pub fn set_expression(&mut self, expression: String) {
+        self.current_expression = expression;
+        self.cursor_from_end = 0;
+    }
+
+    /// Replace the whole expression, keeping the old one as the alternate to swap back to.
+    fn replace_expression(&mut self, expression: String) {
+        if !self.current_expression.trim().is_empty() && self.current_expression != expression {
+            self.alternate_expression = Some(std::mem::take(&mut self.current_expression));
+        }
+        self.set_expression(expression);
+    }
+
+    /// Swap the expression with the alternate one and show its result. Returns false
+    /// when there is no alternate.
+    pub fn swap_alternate(&mut self) -> bool {
+        let Some(alternate) = self.alternate_expression.take() else {
+            return false;
+        };
+        let current = std::mem::replace(&mut self.current_expression, alternate);
+        self.alternate_expression = (!current.trim().is_empty()).then_some(current);
+        self.cursor_from_end = 0;
         self.update_result();
+        true
+    }
+
+    /// Cursor position as a char index into `current_expression`.
+    pub fn cursor(&self) -> usize {
+        let len = self.current_expression.chars().count();
+        len - self.cursor_from_end.min(len)
+    }
+
+    pub fn set_cursor(&mut self, index: usize) {
+        let len = self.current_expression.chars().count();
+        self.cursor_from_end = len - index.min(len);
+    }
+
+    pub fn move_cursor_left(&mut self) {
+        self.set_cursor(self.cursor().saturating_sub(1));
+    }
+
+    pub fn move_cursor_right(&mut self) {
+        self.set_cursor(self.cursor() + 1);
+    }
+
+    pub fn move_cursor_home(&mut self) {
+        self.set_cursor(0);
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.cursor_from_end = 0;
+    }
+
+    fn text_before_cursor(&self) -> &str {
+        &self.current_expression[..self.byte_index(self.cursor())]
+    }
+
+    fn byte_index(&self, char_index: usize) -> usize {
+        self.current_expression
+            .char_indices()
+            .nth(char_index)
+            .map_or(self.current_expression.len(), |(i, _)| i)
+    }
+
+    fn insert_at_cursor(&mut self, text: &str) {
+        // The cursor is tracked from the end, so it stays after the inserted text
+        let at = self.byte_index(self.cursor());
+        self.current_expression.insert_str(at, text);
+    }
+
+    fn remove_before_cursor(&mut self) {
+        let cursor = self.cursor();
+        if cursor > 0 {
+            let at = self.byte_index(cursor - 1);
+            self.current_expression.remove(at);
+        }
     }
 
     pub fn clear(&mut self) {
-        self.current_expression.clear();
+        self.replace_expression(String::new());
         self.current_result = String::from("0");
+        self.result_value = Some(0.0);
+        self.partial_preview = false;
+        self.preview_issue = None;
+        self.reliable_digits = None;
+        self.clear_error();
+    }
+
+    fn clear_error(&mut self) {
+        self.error = None;
+        self.error_column = None;
         self.error_message = None;
+        self.error_hint = None;
+    }
+
+    /// Dismiss the error, keeping the expression to fix and showing its live result
+    /// again. With `jump`, the cursor moves to where the error was found. Returns
+    /// whether there was an error to dismiss.
+    pub fn acknowledge_error(&mut self, jump: bool) -> bool {
+        if self.error_message.is_none() {
+            return false;
+        }
+        let column = self.error_column;
+        self.clear_error();
+        self.update_result();
+        if jump && let Some(column) = column {
+            self.set_cursor(column);
+        }
+        true
     }
 
     pub fn clear_all(&mut self) {
@@ -97,43 +999,174 @@ impl CalculatorModule {
         self.history.clear();
     }
 
-    pub fn calculate(&mut self) {
-        if self.current_expression.is_empty() {
-            return;
+    /// Evaluate each `;`-separated statement in order, recording every one in history.
+    ///
+    /// Returns the statements evaluated before any error, paired with their outcomes.
+    pub fn calculate(&mut self) -> Vec<(String, Outcome)> {
+        self.partial_preview = false;
+        self.preview_issue = None;
+        self.reliable_digits = None;
+        // Calculating again right after a missing paren error closes the open parens
+        if self.autoclose.take().as_ref() == Some(&self.current_expression) {
+            let open = self.current_expression.matches('(').count();
+            let closed = self.current_expression.matches(')').count();
+            let closing = ")".repeat(open.saturating_sub(closed));
+            self.set_expression(format!("{}{}", self.current_expression, closing));
+        }
+        let source = self.current_expression.clone();
+        let mut results = Vec::new();
+        for (start, statement) in split_statements(&source) {
+            match self.run_statement(statement) {
+                Ok(outcome) => results.push((statement.to_string(), outcome)),
+                Err(error) => {
+                    if matches!(error, EvalError::MissingParen { .. }) {
+                        self.autoclose = Some(source.clone());
+                    }
+                    self.error_message = Some(format!("Error: {}", error));
+                    self.error_hint = suggest(&error, statement);
+                    self.error_column = error_column(start, statement, &error);
+                    self.error = Some(error);
+                    self.current_result = String::from("Error");
+                    self.result_value = None;
+                    return results;
+                }
+            }
         }
 
-        match self.evaluate_expression(&self.current_expression) {
-            Ok(result) => {
-                let result_str = format_result(result);
-                self.history.push(CalculationEntry {
-                    expression: self.current_expression.clone(),
-                    result: result_str.clone(),
-                    timestamp: Local::now(),
-                });
+        match results.last() {
+            // Text and approximate magnitudes can't be edited further, so the
+            // expression stays put.
+            Some((_, outcome @ (Outcome::Text(_) | Outcome::Huge(_)))) => {
+                self.current_result = outcome.to_string();
+                self.result_value = None;
+            }
+            Some((_, outcome)) => {
+                self.result_value = outcome.as_f64();
+                let result_str = outcome.to_string();
                 self.current_result = result_str.clone();
-                self.current_expression = result_str;
-                self.error_message = None;
+                self.replace_expression(result_str);
             }
-            Err(e) => {
-                self.error_message = Some(format!("Error: {}", e));
-                self.current_result = String::from("Error");
+            None => return results,
+        }
+        self.clear_error();
+        results
+    }
+
+    fn run_statement(&mut self, statement: &str) -> EvalResult<Outcome> {
+        let assignment = split_assignment(statement);
+        let body = assignment.map_or(statement, |(_, body)| body);
+        let started = Instant::now();
+        let largest_term = Cell::new(0.0);
+        let evaluator = Evaluator {
+            largest_term: self.cancellation_check.then_some(&largest_term),
+            ..self.evaluator()
+        };
+        let outcome = evaluate(body, assignment.is_some(), &evaluator, self.exact())?;
+        let duration = started.elapsed();
+        let reliable = outcome
+            .as_f64()
+            .and_then(|result| reliable_digits(largest_term.get(), result));
+
+        let context = self.capture_context(body);
+        let expression =
+            normalize(statement, self.percent_behavior).unwrap_or_else(|| statement.to_string());
+        self.history.push(CalculationEntry {
+            raw: (expression != statement).then(|| statement.to_string()),
+            expression,
+            result: outcome.to_string(),
+            timestamp: Local::now(),
+            context,
+            duration,
+            reliable_digits: reliable,
+        });
+        self.reliable_digits = reliable;
+        if let Some(result) = outcome.as_f64() {
+            if let Some((name, _)) = assignment {
+                self.variables.insert(name.to_string(), result);
             }
+            self.last_value = Some(result);
         }
+        Ok(outcome)
+    }
+
+    /// Whether the expression holds input that hasn't been evaluated into history yet.
+    pub fn has_unevaluated_expression(&self) -> bool {
+        let expr = self.current_expression.trim();
+        !expr.is_empty()
+            && !self
+                .history
+                .iter()
+                .any(|entry| entry.typed() == expr || entry.result == expr)
     }
 
     pub fn update_result(&mut self) {
+        self.partial_preview = false;
+        self.preview_issue = None;
+        self.reliable_digits = None;
         if self.current_expression.is_empty() {
             self.current_result = String::from("0");
+            self.result_value = Some(0.0);
             return;
         }
 
-        match self.evaluate_expression(&self.current_expression) {
-            Ok(result) => {
-                self.current_result = format_result(result);
-                self.error_message = None;
+        // Preview the last statement, applying earlier assignments to a scratch copy. The
+        // last one is still being typed, so a dangling operator is ignored.
+        let statements: Vec<&str> = split_statements(&self.current_expression)
+            .map(|(_, statement)| statement)
+            .collect();
+        let mut variables = self.variables.clone();
+        let mut ans = self.last_value;
+        // Random draws come from a copy, so the preview shows what calculating will give
+        let rng = self.rng.clone();
+        let mut preview = None;
+        let mut failure = None;
+        for (i, &statement) in statements.iter().enumerate() {
+            let assignment = split_assignment(statement);
+            let mut body = assignment.map_or(statement, |(_, body)| body);
+            if i + 1 == statements.len() {
+                let trimmed = trim_dangling(body, self.percent_behavior);
+                if trimmed.is_empty() {
+                    preview = None;
+                    break;
+                }
+                self.partial_preview = trimmed.len() < body.trim_end().len();
+                body = trimmed;
+            }
+            let evaluator = Evaluator {
+                variables: &variables,
+                ans,
+                rng: Some(&rng),
+                ..self.evaluator()
+            };
+            match evaluate(body, assignment.is_some(), &evaluator, self.exact()) {
+                Ok(outcome) => {
+                    if let Some(value) = outcome.as_f64() {
+                        if let Some((name, _)) = assignment {
+                            variables.insert(name.to_string(), value);
+                        }
+                        ans = Some(value);
+                    }
+                    preview = Some(outcome);
+                }
+                Err(error) => {
+                    preview = None;
+                    failure = Some(error);
+                    break;
+                }
+            }
+        }
+
+        match preview {
+            Some(outcome) => {
+                self.current_result = outcome.to_string();
+                self.result_value = outcome.as_f64();
+                self.clear_error();
             }
-            Err(_) => {
+            None => {
+                self.partial_preview = false;
                 self.current_result = self.current_expression.clone();
+                self.result_value = None;
+                self.preview_issue = preview_issue(&self.current_expression, failure);
             }
         }
     }
@@ -141,9 +1174,12 @@ impl CalculatorModule {
     pub fn apply_function(&mut self, func: &str) {
         if let Ok(current_val) = self.current_result.parse::<f64>() {
             let result = match func {
-                "sin" => current_val.to_radians().sin(),
-                "cos" => current_val.to_radians().cos(),
-                "tan" => current_val.to_radians().tan(),
+                "sin" => self.angle_mode.to_radians(current_val).sin(),
+                "cos" => self.angle_mode.to_radians(current_val).cos(),
+                "tan" => self.angle_mode.to_radians(current_val).tan(),
+                "asin" => self.angle_mode.from_radians(current_val.asin()),
+                "acos" => self.angle_mode.from_radians(current_val.acos()),
+                "atan" => self.angle_mode.from_radians(current_val.atan()),
                 "sqrt" => current_val.sqrt(),
                 "log" => current_val.log10(),
                 "ln" => current_val.ln(),
@@ -155,299 +1191,2651 @@ impl CalculatorModule {
             };
 
             let result_str = format_result(result);
+            let angle_mode = uses_angle_mode(func).then_some(self.angle_mode);
             self.history.push(CalculationEntry {
                 expression: format!("{}({})", func, current_val),
+                raw: None,
                 result: result_str.clone(),
                 timestamp: Local::now(),
+                context: angle_mode.map(|angle_mode| EntryContext {
+                    angle_mode: Some(angle_mode),
+                    bindings: Vec::new(),
+                }),
+                duration: Duration::ZERO,
+                reliable_digits: None,
             });
-            self.current_expression = result_str.clone();
+            self.last_value = Some(result);
+            self.reliable_digits = None;
+            self.replace_expression(result_str.clone());
             self.current_result = result_str;
+            self.result_value = Some(result);
+            self.partial_preview = false;
+        }
+    }
+
+    /// Apply a named conversion to the current result through the normal calculate path,
+    /// so it lands in history as e.g. `deg(1.5708) = 90`.
+    pub fn apply_conversion(&mut self, name: &str) -> bool {
+        if find_conversion(name).is_none() || self.current_result.parse::<f64>().is_err() {
+            return false;
         }
+        self.set_expression(format!("{}({})", name, self.current_result));
+        self.calculate();
+        self.error_message.is_none()
     }
 
     pub fn toggle_mode(&mut self) {
         self.mode = match self.mode {
             CalculatorMode::Basic => CalculatorMode::Scientific,
-            CalculatorMode::Scientific => CalculatorMode::Basic,
+            CalculatorMode::Scientific => CalculatorMode::Programmer,
+            CalculatorMode::Programmer => CalculatorMode::Financial,
+            CalculatorMode::Financial => CalculatorMode::Basic,
         };
+        // Programmer mode truncates, so the preview changes with it
+        if self.error.is_none() {
+            self.update_result();
+        }
     }
 
-    pub fn recall_from_history(&mut self, index: usize) {
-        if index < self.history.len() {
-            // Recall the original expression, then update the live result
-            self.current_expression = self.history[index].expression.clone();
-            match self.evaluate_expression(&self.current_expression) {
-                Ok(result) => {
-                    self.current_result = format_result(result);
-                    self.error_message = None;
-                }
-                Err(e) => {
-                    self.error_message = Some(format!("Error: {}", e));
-                    self.current_result = String::from("Error");
-                }
-            }
+    /// Step programmer mode's word size through 8, 16, 32 and 64 bits.
+    pub fn cycle_word_size(&mut self) {
+        self.word_size = self.word_size.next();
+        if self.error.is_none() {
+            self.update_result();
         }
     }
 
-    pub fn copy_result_to_clipboard(&self) -> Result<String> {
-        Ok(self.current_result.clone())
+    pub fn toggle_angle_mode(&mut self) {
+        self.angle_mode = match self.angle_mode {
+            AngleMode::Radians => AngleMode::Degrees,
+            AngleMode::Degrees => AngleMode::Radians,
+        };
+        self.update_result();
+    }
+
+    /// The result as shown: in the result format, rounded when a significant-figure
+    /// mode is on, and with its digits grouped. The second value is true when it was
+    /// rounded.
+    pub fn displayed_result(&self) -> (String, bool) {
+        let (text, rounded) = self.formatted_result();
+        (group_digits(&text, self.digit_grouping), rounded)
     }
 
-    fn evaluate_expression(&self, expr: &str) -> Result<f64> {
-        let expr = expr.trim();
-        if expr.is_empty() {
-            return Ok(0.0);
+    fn formatted_result(&self) -> (String, bool) {
+        let Some(value) = self.result_value else {
+            return (self.current_result.clone(), false);
+        };
+        match (self.result_format, self.sig_figs) {
+            (ResultFormat::Scientific, figures) => {
+                (format_scientific(value, figures), figures.is_some())
+            }
+            (ResultFormat::Engineering, figures) => {
+                (format_engineering(value, figures), figures.is_some())
+            }
+            (ResultFormat::Fraction, _) if let Some(fraction) = format_fraction(value) => {
+                (fraction, false)
+            }
+            (_, Some(figures)) => (format_sig_figs(value, figures), true),
+            _ => (self.current_result.clone(), false),
         }
+    }
 
-        let tokens = tokenize(expr)?;
-        let (result, _) = parse_expression(&tokens, 0)?;
-        Ok(result)
+    /// Step the result format through decimal, scientific, engineering and fraction.
+    pub fn cycle_result_format(&mut self) {
+        self.result_format = self.result_format.next();
     }
-}
 
-fn format_result(value: f64) -> String {
-    if value.is_infinite() {
-        return "Infinity".to_string();
+    /// Step the significant-figure mode through off, 2, 3, 4, 6 and 8 figures.
+    pub fn cycle_sig_figs(&mut self) {
+        const STEPS: [usize; 5] = [2, 3, 4, 6, 8];
+        self.sig_figs = match self.sig_figs {
+            None => Some(STEPS[0]),
+            Some(figures) => STEPS.iter().copied().find(|&step| step > figures),
+        };
     }
-    if value.is_nan() {
-        return "NaN".to_string();
+
+    pub fn recall_from_history(&mut self, index: usize) {
+        if index < self.history.len() {
+            // Recall the expression as typed, then update the live result
+            self.replace_expression(self.history[index].typed().to_string());
+            self.partial_preview = false;
+            self.preview_issue = None;
+            let assignment = split_assignment(&self.current_expression);
+            let body = assignment.map_or(self.current_expression.as_str(), |(_, body)| body);
+            match self.evaluate(body, assignment.is_some()) {
+                Ok(outcome) => {
+                    self.current_result = outcome.to_string();
+                    self.result_value = outcome.as_f64();
+                    self.clear_error();
+                }
+                Err(error) => self.show_error(error),
+            }
+        }
     }
 
-    let s = format!("{:.10}", value);
-    let s = s.trim_end_matches('0').trim_end_matches('.');
-    s.to_string()
+    /// Show `error`, found in the whole expression, in place of the result.
+    pub fn show_error(&mut self, error: EvalError) {
+        self.clear_error();
+        self.error_message = Some(format!("Error: {}", error));
+        self.error_column = error_column(0, &self.current_expression, &error);
+        self.error = Some(error);
+        self.current_result = String::from("Error");
+        self.result_value = None;
+    }
+
+    /// Captured context of an entry that differs from the current settings, if any.
+    pub fn context_changes(&self, context: &EntryContext) -> Option<EntryContext> {
+        let angle_mode = context
+            .angle_mode
+            .filter(|&angle_mode| angle_mode != self.angle_mode);
+        let bindings: Vec<(String, f64)> = context
+            .bindings
+            .iter()
+            .filter(|(name, value)| self.variables.get(name) != Some(value))
+            .cloned()
+            .collect();
+        if angle_mode.is_none() && bindings.is_empty() {
+            None
+        } else {
+            Some(EntryContext {
+                angle_mode,
+                bindings,
+            })
+        }
+    }
+
+    pub fn restore_context(&mut self, context: &EntryContext) {
+        if let Some(angle_mode) = context.angle_mode {
+            self.angle_mode = angle_mode;
+        }
+        for (name, value) in &context.bindings {
+            self.variables.insert(name.clone(), *value);
+        }
+        self.update_result();
+    }
+
+    pub fn copy_result_to_clipboard(&self) -> String {
+        self.current_result.clone()
+    }
+
+    fn capture_context(&self, expr: &str) -> Option<EntryContext> {
+        let (tokens, _) = tokenize(expr, self.percent_behavior).ok()?;
+        let uses_angles = tokens
+            .iter()
+            .any(|t| matches!(t, Token::Ident(name) if uses_angle_mode(name)));
+        let bindings: Vec<(String, f64)> = free_variables(expr)
+            .into_iter()
+            .filter_map(|name| self.variables.get(&name).map(|&value| (name, value)))
+            .collect();
+        if !uses_angles && bindings.is_empty() {
+            return None;
+        }
+        Some(EntryContext {
+            angle_mode: uses_angles.then_some(self.angle_mode),
+            bindings,
+        })
+    }
+
+    /// Evaluate a standalone expression against the current variables and settings.
+    pub fn evaluate_str(&self, expr: &str) -> EvalResult<Outcome> {
+        self.evaluate(expr, false)
+    }
+
+    fn evaluate(&self, expr: &str, assigning: bool) -> EvalResult<Outcome> {
+        evaluate(expr, assigning, &self.evaluator(), self.exact())
+    }
+
+    /// An evaluator for the current variables and settings.
+    pub fn evaluator(&self) -> Evaluator<'_> {
+        Evaluator {
+            variables: &self.variables,
+            bindings: [None; 2],
+            angle_mode: self.angle_mode,
+            percent: self.percent_behavior,
+            modulo: self.modulo_behavior,
+            ans: self.last_value,
+            integers: (self.mode == CalculatorMode::Programmer).then_some(self.word_size),
+            largest_term: None,
+            rng: Some(&self.rng),
+        }
+    }
+
+    /// Which exact evaluators are on; fractions have no place among programmer mode's
+    /// integers.
+    fn exact(&self) -> Exact {
+        Exact {
+            integers: self.big_integers,
+            fractions: self.fractions && self.mode != CalculatorMode::Programmer,
+            max_digits: self.max_digits,
+        }
+    }
 }
 
-fn tokenize(expr: &str) -> Result<Vec<Token>> {
-    let mut tokens = Vec::new();
-    let mut chars = expr.chars().peekable();
-    let mut num_buf = String::new();
-    let mut ident_buf = String::new();
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Number(value) => f.write_str(&format_result(*value)),
+            Outcome::Integer(value) => write!(f, "{}", value),
+            Outcome::Fraction(value) => write!(f, "{}", value),
+            Outcome::Huge(log10) => f.write_str(&format_magnitude(*log10)),
+            Outcome::Text(text) => f.write_str(text),
+            Outcome::List(values) => {
+                let values: Vec<String> =
+                    values.iter().map(|&value| format_result(value)).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            Outcome::Matrix(matrix) => write!(f, "{}", matrix),
+            Outcome::Date(date) => f.write_str(&dates::format_date(*date)),
+            Outcome::Time(time) => f.write_str(&dates::format_time(*time)),
+            Outcome::Uncertain(value, error) => f.write_str(&format_uncertain(*value, *error)),
+        }
+    }
+}
 
-    while let Some(&ch) = chars.peek() {
+/// How many significant digits of `result` can be trusted when the largest value added
+/// or subtracted on the way to it was `largest`: each power of ten between them is a
+/// digit of f64's 15 lost to cancellation. `None` unless at least [`CANCELLATION_LOSS`]
+/// digits were lost; an exact zero says nothing about its digits and isn't flagged.
+pub fn reliable_digits(largest: f64, result: f64) -> Option<u32> {
+    if result == 0.0 || !result.is_finite() || !largest.is_finite() {
+        return None;
+    }
+    let lost = (largest / result.abs()).log10();
+    (lost >= CANCELLATION_LOSS).then(|| (f64::DIGITS as f64 - lost).round().max(0.0) as u32)
+}
+
+/// The exact evaluators tried before f64.
+#[derive(Debug, Clone, Copy)]
+struct Exact {
+    /// Big-integer mode, for integer-only expressions.
+    integers: bool,
+    /// Fraction mode, for rational ones.
+    fractions: bool,
+    /// Most digits an exact result may have.
+    max_digits: usize,
+}
+
+/// Evaluate `expr`, which may also be a single call to a text function like `ratio`.
+/// Expressions the `exact` evaluators handle are worked out exactly.
+fn evaluate(
+    expr: &str,
+    assigning: bool,
+    evaluator: &Evaluator,
+    exact: Exact,
+) -> EvalResult<Outcome> {
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Ok(Outcome::Number(0.0));
+    }
+
+    let tree = evaluator.parse(expr)?;
+    if let Expr::Call(name, args) = &tree
+        && TEXT_FUNCTIONS.contains(&name.as_str())
+    {
+        if assigning {
+            return Err(EvalError::TextAssignment(name.clone()));
+        }
+        let args = evaluator.eval_values(args)?;
+        return eval::call_text_function(name, args).map(Outcome::Text);
+    }
+    if exact.integers
+        && let Some(value) = bigint::evaluate(&tree, evaluator, exact.max_digits)
+    {
+        return Ok(Outcome::Integer(value));
+    }
+    // Whole numbers are left to f64, which shows them the same way
+    if exact.fractions
+        && let Some(value) = rational::evaluate(&tree, evaluator, exact.max_digits)
+        && !value.is_integer()
+    {
+        return Ok(Outcome::Fraction(value));
+    }
+    match evaluator.eval_value(&tree) {
+        // A factorial on its own still has a magnitude worth showing
+        Err(EvalError::Overflow { log10 })
+            if matches!(&tree, Expr::Postfix(PostfixOp::Factorial, operand)
+                if matches!(**operand, Expr::Number(_) | Expr::Literal(..))) =>
+        {
+            Ok(Outcome::Huge(log10))
+        }
+        Ok(
+            Value::List(_)
+            | Value::Matrix(_)
+            | Value::Date(_)
+            | Value::Time(_)
+            | Value::Uncertain(..),
+        ) if assigning => Err(EvalError::ListAssignment),
+        Ok(Value::List(values)) => Ok(Outcome::List(values)),
+        Ok(Value::Matrix(matrix)) => Ok(Outcome::Matrix(matrix)),
+        Ok(Value::Date(date)) => Ok(Outcome::Date(date)),
+        Ok(Value::Time(time)) => Ok(Outcome::Time(time)),
+        Ok(Value::Uncertain(value, error)) => Ok(Outcome::Uncertain(value, error)),
+        result => result.and_then(Value::number).map(Outcome::Number),
+    }
+}
+
+/// Column in the expression where `error` was found in `statement`, which starts at
+/// column `start`: the offending token for a syntax error, the end of the statement
+/// when more was needed, and `None` for errors that aren't about one place.
+fn error_column(start: usize, statement: &str, error: &EvalError) -> Option<usize> {
+    // Columns count from the start of the evaluated text, which is trimmed and ends the
+    // statement, so everything before it is what the two lengths differ by
+    let body = split_assignment(statement)
+        .map_or(statement, |(_, body)| body)
+        .trim_start();
+    let start = start + statement.chars().count() - body.chars().count();
+    let body = body.trim_end();
+    match error {
+        EvalError::UnexpectedEnd { .. } => Some(start + body.chars().count()),
+        _ => error.column().map(|pos| start + pos),
+    }
+}
+
+/// The non-empty statements of a `;`-separated expression, each with the column it
+/// starts at.
+fn split_statements(expr: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut column = 0;
+    expr.split(';').filter_map(move |part| {
+        let start = column + part.chars().take_while(|c| c.is_whitespace()).count();
+        column += part.chars().count() + 1;
+        let statement = part.trim();
+        (!statement.is_empty()).then_some((start, statement))
+    })
+}
+
+/// Char indices of the brackets in `expr` without a partner: closers with no opener of
+/// their kind before them and openers never closed. Brackets in quoted text don't count.
+pub fn unbalanced_brackets(expr: &str) -> Vec<usize> {
+    let mut unbalanced = Vec::new();
+    let mut open: Vec<(usize, char)> = Vec::new();
+    let mut quoted = false;
+    for (i, ch) in expr.chars().enumerate() {
         match ch {
-            '0'..='9' | '.' => {
-                num_buf.push(ch);
-                chars.next();
-            }
-            'a'..='z' | 'A'..='Z' | 'π' => {
-                // flush number buffer
-                if !num_buf.is_empty() {
-                    tokens.push(Token::Number(num_buf.parse()?));
-                    num_buf.clear();
-                }
-                ident_buf.push(ch);
-                chars.next();
-                // collect full identifier
-                while let Some(&nc) = chars.peek() {
-                    if nc.is_alphanumeric() || nc == '_' {
-                        ident_buf.push(nc);
-                        chars.next();
-                    } else {
-                        break;
-                    }
-                }
-                let ident = ident_buf.to_lowercase();
-                ident_buf.clear();
-                match ident.as_str() {
-                    // constants
-                    "pi" | "π" => tokens.push(Token::Number(std::f64::consts::PI)),
-                    "e" => tokens.push(Token::Number(std::f64::consts::E)),
-                    // recognized function names become identifiers; parsing will handle call
-                    _ => tokens.push(Token::Ident(ident)),
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '(' | '[' => open.push((i, ch)),
+            ')' | ']' => {
+                let opener = if ch == ')' { '(' } else { '[' };
+                if open.last().is_some_and(|&(_, last)| last == opener) {
+                    open.pop();
+                } else {
+                    unbalanced.push(i);
                 }
             }
-            '+' | '-' | '*' | '/' | '^' | '%' | '(' | ')' => {
-                if !num_buf.is_empty() {
-                    tokens.push(Token::Number(num_buf.parse()?));
-                    num_buf.clear();
-                }
-                tokens.push(match ch {
-                    '+' => Token::Plus,
-                    '-' => Token::Minus,
-                    '*' => Token::Multiply,
-                    '/' => Token::Divide,
-                    '^' => Token::Power,
-                    '%' => Token::Modulo,
-                    '(' => Token::LParen,
-                    ')' => Token::RParen,
-                    _ => unreachable!(),
-                });
-                chars.next();
+            _ => {}
+        }
+    }
+    unbalanced.extend(open.into_iter().map(|(i, _)| i));
+    unbalanced.sort_unstable();
+    unbalanced
+}
+
+/// What keeps `expr` from previewing, given the error it `failed` with, if any: a
+/// stray closing bracket, brackets left open, or a syntax error. An error in working
+/// it out, such as dividing by 0, isn't an issue with what's typed.
+fn preview_issue(expr: &str, failed: Option<EvalError>) -> Option<String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let unbalanced = unbalanced_brackets(expr);
+    if let Some(&stray) = unbalanced.iter().find(|&&i| matches!(chars[i], ')' | ']')) {
+        return Some(format!("unmatched {}", chars[stray]));
+    }
+    match unbalanced.len() {
+        0 => failed
+            .filter(EvalError::is_syntax)
+            .map(|error| error.to_string()),
+        1 => Some("1 bracket open".to_string()),
+        open => Some(format!("{} brackets open", open)),
+    }
+}
+
+/// Drop a dangling binary operator from the end of a half-typed expression, along with an
+/// open paren that has nothing in it yet and the function name before it, so `3*(` and
+/// `3*sin(` both come back as `3`. A postfix `!`, or `%` when it isn't modulo, is
+/// complete and stays.
+fn trim_dangling(expr: &str, percent: PercentBehavior) -> &str {
+    let mut rest = expr.trim_end();
+    loop {
+        let trimmed = if let Some(before) =
+            rest.strip_suffix(['+', '-', '*', '/', '^', ',', '&', '|', '<', '>', '~', '±'])
+        {
+            before
+        } else if percent == PercentBehavior::Modulo
+            && let Some(before) = rest.strip_suffix('%')
+        {
+            before
+        } else if let Some(before) = rest.strip_suffix('(') {
+            // Like the tokenizer, digits ahead of the first letter belong to a number
+            let word = before.len()
+                - before
+                    .chars()
+                    .rev()
+                    .take_while(|&c| c.is_alphanumeric() || c == '_')
+                    .map(char::len_utf8)
+                    .sum::<usize>();
+            match before[word..].find(|c: char| c.is_alphabetic()) {
+                Some(start) => &before[..word + start],
+                None => before,
+            }
+        } else {
+            return rest;
+        };
+        rest = trimmed.trim_end();
+    }
+}
+
+/// What can come next in a half-typed `expr`, for new users: an operand after an
+/// operator, the argument a function wants, or how many parens are still open. `None`
+/// when the expression is complete or empty, or already has an error to show.
+pub fn hint_for(expr: &str, angle_mode: AngleMode, percent: PercentBehavior) -> Option<String> {
+    const OPERAND: &str = "enter a number, ( or a function";
+    let body = split_assignment(expr).map_or(expr, |(_, body)| body);
+    let statement = body.rsplit(';').next().unwrap_or(body);
+    let (tokens, _) = tokenize(statement, percent).ok()?;
+    let open = tokens
+        .iter()
+        .map(|token| match token {
+            Token::LParen => 1,
+            Token::RParen => -1,
+            _ => 0,
+        })
+        .sum::<i32>();
+
+    let hint = match tokens.as_slice() {
+        [] => return None,
+        [.., Token::Ident(name), Token::LParen] if uses_angle_mode(name) => {
+            let unit = match angle_mode {
+                AngleMode::Radians => "radians",
+                AngleMode::Degrees => "degrees",
+            };
+            let (call, verb) = match name.as_str() {
+                "atan2" => ("atan2(y, x)", "gives"),
+                _ if INVERSE_ANGLE_FUNCTIONS.contains(&name.as_str()) => (name.as_str(), "gives"),
+                _ => (name.as_str(), "takes"),
+            };
+            format!(
+                "argument expected - {} {} {} ({} mode)",
+                call,
+                verb,
+                unit,
+                angle_mode.label()
+            )
+        }
+        [.., Token::Ident(name), Token::LParen] if eval::arity(name) == (2, 2) => {
+            format!("{} takes two arguments: a, b", name)
+        }
+        [.., Token::Ident(name), Token::LParen] if name == "convert" => {
+            "convert takes a value, then two units in quotes: \"km\", \"mi\"".to_string()
+        }
+        [.., Token::Ident(name), Token::LParen] if INTERVAL_FUNCTIONS.contains(&name.as_str()) => {
+            format!("{} takes an expression in x, then the interval: a, b", name)
+        }
+        [.., Token::Ident(name), Token::LParen] if name == "solve" => {
+            "solve takes an expression, the name to solve for, then a guess: x^2-2, x, 1"
+                .to_string()
+        }
+        [.., Token::Ident(name), Token::LParen] if SERIES_FUNCTIONS.contains(&name.as_str()) => {
+            format!(
+                "{} takes a counter, from, to, then an expression: k, 1, 10, k^2",
+                name
+            )
+        }
+        [.., Token::Ident(name), Token::LParen] if is_function(name) => {
+            format!("argument expected for {}", name)
+        }
+        [.., Token::Comma] => "enter the next argument".to_string(),
+        [
+            ..,
+            Token::LParen
+            | Token::Plus
+            | Token::Minus
+            | Token::Multiply
+            | Token::Divide
+            | Token::FloorDivide
+            | Token::Power
+            | Token::Modulo
+            | Token::PlusMinus,
+        ] => OPERAND.to_string(),
+        [.., Token::Ident(name)] if is_function(name) => format!("add ( to call {}", name),
+        [.., Token::Ident(name)] => {
+            let matches: Vec<&str> = FUNCTIONS
+                .iter()
+                .chain(TEXT_FUNCTIONS)
+                .copied()
+                .filter(|function| function.starts_with(name.as_str()))
+                .take(4)
+                .collect();
+            if matches.is_empty() {
+                return paren_hint(open);
             }
-            ' ' => {
-                chars.next();
+            format!("functions: {}", matches.join(", "))
+        }
+        _ => return paren_hint(open),
+    };
+    Some(hint)
+}
+
+/// How many parens are still open, once the expression is otherwise complete.
+fn paren_hint(open: i32) -> Option<String> {
+    match open {
+        ..=0 => None,
+        1 => Some("1 paren open - ) closes it".to_string()),
+        _ => Some(format!("{} parens open - ) closes each", open)),
+    }
+}
+
+/// `statement` with canonical spacing: spaces around binary operators except a tight
+/// `^`, none inside parens or before a call's `(`, `, ` between arguments, `**` written
+/// `^` and `pi` written `π`. Everything else, parens included, stays as typed, and numbers
+/// keep their underscores. `None` when the statement doesn't tokenize.
+pub fn normalize(statement: &str, percent: PercentBehavior) -> Option<String> {
+    let (name, body) = match split_assignment(statement) {
+        Some((name, body)) => (Some(name), body.trim()),
+        None => (None, statement.trim()),
+    };
+    let (tokens, offsets) = tokenize(body, percent).ok()?;
+    let chars: Vec<char> = body.chars().collect();
+
+    let mut out = name.map_or_else(String::new, |name| format!("{} = ", name));
+    let mut previous: Option<&Token> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        let end = offsets.get(i + 1).copied().unwrap_or(chars.len());
+        // Implicit multiplication has no text of its own
+        if offsets[i] == end && matches!(token, Token::Multiply) {
+            continue;
+        }
+        let text: String = chars[offsets[i]..end]
+            .iter()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let operand_before = previous.is_some_and(Token::ends_operand);
+        match token {
+            Token::Plus | Token::Minus if !operand_before => out.push_str(&text),
+            Token::Plus
+            | Token::Minus
+            | Token::Multiply
+            | Token::Divide
+            | Token::FloorDivide
+            | Token::Modulo
+            | Token::BitAnd
+            | Token::BitOr
+            | Token::BitXor
+            | Token::ShiftLeft
+            | Token::ShiftRight
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::Equal
+            | Token::NotEqual
+            | Token::PlusMinus
+            | Token::And
+            | Token::Or => {
+                out.push(' ');
+                out.push_str(&text);
+                out.push(' ');
             }
-            _ => {
-                return Err(anyhow::anyhow!("Invalid character: {}", ch));
+            Token::Not => out.push_str("not "),
+            Token::Comma => out.push_str(", "),
+            Token::Power => out.push('^'),
+            Token::Number(_) if text.eq_ignore_ascii_case("pi") => out.push('π'),
+            Token::Ident(name) => out.push_str(name),
+            // Spaces inside the quotes are part of the text
+            Token::Text(quoted) => {
+                out.push('"');
+                out.push_str(quoted);
+                out.push('"');
             }
+            _ => out.push_str(&text),
         }
+        previous = Some(token);
     }
+    Some(out)
+}
 
-    if !num_buf.is_empty() {
-        tokens.push(Token::Number(num_buf.parse()?));
+/// Split `name = body` into its parts when `name` is a valid variable name.
+fn split_assignment(expr: &str) -> Option<(&str, &str)> {
+    let (name, body) = expr.split_once('=')?;
+    // `x == 1` compares
+    if body.starts_with('=') {
+        return None;
     }
+    let name = name.trim();
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_function(name)
+        && !matches!(name, "pi" | "e" | ANS);
+    valid.then_some((name, body))
+}
 
-    // Add implicit multiplication tokens
-    let mut result = Vec::new();
+/// Names of the variables an expression refers to, in order of first use. The counter
+/// of a `sum` or `prod` and the unknown of a `solve` aren't, inside their call.
+pub fn free_variables(expr: &str) -> Vec<String> {
+    let Ok((tokens, _)) = tokenize(expr, PercentBehavior::Modulo) else {
+        return Vec::new();
+    };
+    let bound = bound_names(&tokens);
+    let mut names: Vec<String> = Vec::new();
     for (i, token) in tokens.iter().enumerate() {
-        result.push(token.clone());
-
-        // Check if we need to add implicit multiplication
-        if i < tokens.len() - 1 {
-            match (token, &tokens[i + 1]) {
-                // Number followed by opening parenthesis: 3( -> 3*(
-                (Token::Number(_), Token::LParen) => {
-                    result.push(Token::Multiply);
-                }
-                // Closing parenthesis followed by number: )3 -> )*3
-                (Token::RParen, Token::Number(_)) => {
-                    result.push(Token::Multiply);
+        if let Token::Ident(name) = token {
+            let is_call = matches!(tokens.get(i + 1), Some(Token::LParen)) && is_function(name);
+            let is_bound = bound
+                .iter()
+                .any(|(bound, span)| *bound == name && span.contains(&i));
+            if !is_call && !is_bound && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// The names `sum`, `prod` and `solve` calls bind, each with the tokens of the call it
+/// is bound over.
+fn bound_names(tokens: &[Token]) -> Vec<(&String, RangeInclusive<usize>)> {
+    let mut bound = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Ident(name) = token else {
+            continue;
+        };
+        // Which argument is the name
+        let argument = match name.as_str() {
+            _ if !matches!(tokens.get(i + 1), Some(Token::LParen)) => continue,
+            "solve" => 1,
+            _ if SERIES_FUNCTIONS.contains(&name.as_str()) => 0,
+            _ => continue,
+        };
+        let (mut depth, mut at, mut name_at, mut end) = (0, 0, None, tokens.len() - 1);
+        for (j, token) in tokens.iter().enumerate().skip(i + 1) {
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 1 => {
+                    end = j;
+                    break;
                 }
-                // Closing parenthesis followed by opening parenthesis: )( -> )*(
-                (Token::RParen, Token::LParen) => {
-                    result.push(Token::Multiply);
+                Token::RParen => depth -= 1,
+                Token::Comma if depth == 1 => at += 1,
+                Token::Ident(_) if depth == 1 && at == argument && name_at.is_none() => {
+                    name_at = Some(j)
                 }
                 _ => {}
             }
         }
+        if let Some(Token::Ident(name)) = name_at.map(|j| &tokens[j]) {
+            bound.push((name, i + 1..=end));
+        }
     }
+    bound
+}
 
-    Ok(result)
+/// The hex, binary and octal literals in `expr`, as written, with their values.
+pub fn radix_literals(expr: &str) -> Vec<(String, f64)> {
+    let Ok((tokens, offsets)) = tokenize(expr, PercentBehavior::Modulo) else {
+        return Vec::new();
+    };
+    let chars: Vec<char> = expr.chars().collect();
+    tokens
+        .iter()
+        .zip(offsets)
+        .filter_map(|(token, at)| {
+            let &Token::Number(value) = token else {
+                return None;
+            };
+            let text: String = chars[at..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect();
+            let prefixed = text.len() > 2
+                && text.starts_with('0')
+                && matches!(text.as_bytes()[1], b'x' | b'X' | b'b' | b'B' | b'o' | b'O');
+            prefixed.then_some((text, value))
+        })
+        .collect()
 }
 
-#[derive(Debug, Clone)]
-enum Token {
-    Number(f64),
-    Plus,
-    Minus,
-    Multiply,
-    Divide,
-    Power,
-    Modulo,
-    LParen,
-    RParen,
-    Ident(String),
+/// Magnitudes from which results are written in scientific notation, such as `1e-7`
+/// and `6.02214076e23`, rather than as ten decimals that round to 0 or a run of digits
+/// the float doesn't hold.
+const SCIENTIFIC_BELOW: f64 = 1e-6;
+const SCIENTIFIC_FROM: f64 = 1e15;
+
+pub fn format_result(value: f64) -> String {
+    if value.is_infinite() {
+        return "Infinity".to_string();
+    }
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value != 0.0 && !(SCIENTIFIC_BELOW..SCIENTIFIC_FROM).contains(&value.abs()) {
+        let s = format!("{:.10e}", value);
+        let (mantissa, exponent) = s.split_once('e').unwrap_or((&s, "0"));
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        return format!("{}e{}", mantissa, exponent);
+    }
+
+    let s = format!("{:.10}", value);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    s.to_string()
 }
 
-fn parse_expression(tokens: &[Token], mut pos: usize) -> Result<(f64, usize)> {
-    let (mut left, new_pos) = parse_term(tokens, pos)?;
-    pos = new_pos;
+/// `value ± error` with the error rounded to two significant figures, less a trailing
+/// zero, and the value to the same place: `9.8123 ± 0.0213` is `9.812 ± 0.021` and
+/// `9.81 ± 0.02` stays as it is. Digits finer than the error mean nothing.
+pub fn format_uncertain(value: f64, error: f64) -> String {
+    if error == 0.0 || !error.is_finite() || !value.is_finite() {
+        return format!("{} ± {}", format_result(value), format_result(error));
+    }
+    let mut place = error.log10().floor() as i32 - 1;
+    let mut digits = (error / 10f64.powi(place)).round();
+    while digits % 10.0 == 0.0 {
+        digits /= 10.0;
+        place += 1;
+    }
+    let scale = 10f64.powi(place);
+    // Adding 0 turns a rounded -0 into 0
+    let value = (value / scale).round() * scale + 0.0;
+    let decimals = (-place).max(0) as usize;
+    format!("{:.*} ± {:.*}", decimals, value, decimals, digits * scale)
+}
 
-    while pos < tokens.len() {
-        match tokens[pos] {
-            Token::Plus => {
-                pos += 1;
-                let (right, next_pos) = parse_term(tokens, pos)?;
-                left += right;
-                pos = next_pos;
+/// `text` with the whole part of each decimal number in it split into thousands, and
+/// its point written the grouping's way. Digits straight after a letter, a point or `^`,
+/// as in `0xFF10` or `10^1234`, are left alone, and so are the numbers of a list and
+/// quoted text such as a date.
+pub fn group_digits(text: &str, grouping: DigitGrouping) -> String {
+    let Some((separator, point)) = grouping.separators() else {
+        return text.to_string();
+    };
+    let chars: Vec<char> = text.chars().collect();
+    let mut grouped = String::with_capacity(text.len() + text.len() / 3);
+    let mut i = 0;
+    while i < chars.len() {
+        // A list's commas separate its values and a date's digits aren't a number, so
+        // both are left as they are
+        if let Some(close) = match chars[i] {
+            '[' => Some(']'),
+            '"' => Some('"'),
+            _ => None,
+        } {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|&c| c == close)
+                .map_or(chars.len(), |len| i + len + 2);
+            grouped.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+        let follows_word =
+            i > 0 && (chars[i - 1].is_alphanumeric() || "._^".contains(chars[i - 1]));
+        if !chars[i].is_ascii_digit() || follows_word {
+            grouped.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let end = chars[i..]
+            .iter()
+            .position(|c| !c.is_ascii_digit())
+            .map_or(chars.len(), |len| i + len);
+        for (k, &digit) in chars[i..end].iter().enumerate() {
+            if k > 0 && (end - i - k) % 3 == 0 {
+                grouped.push(separator);
             }
-            Token::Minus => {
-                pos += 1;
-                let (right, next_pos) = parse_term(tokens, pos)?;
-                left -= right;
-                pos = next_pos;
+            grouped.push(digit);
+        }
+        i = end;
+        if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+            grouped.push(point);
+            i += 1;
+            while let Some(&digit) = chars.get(i).filter(|c| c.is_ascii_digit()) {
+                grouped.push(digit);
+                i += 1;
             }
-            _ => break,
         }
     }
-
-    Ok((left, pos))
+    grouped
 }
 
-fn parse_term(tokens: &[Token], mut pos: usize) -> Result<(f64, usize)> {
-    let (mut left, new_pos) = parse_factor(tokens, pos)?;
-    pos = new_pos;
+/// `value` rounded to `figures` significant figures, ties going to the even digit.
+/// Trailing zeros are kept since they are significant, so 9.996 to 3 figures is "10.0".
+pub fn format_sig_figs(value: f64, figures: usize) -> String {
+    if !value.is_finite() {
+        return format_result(value);
+    }
+    // Float formatting rounds the exact binary value half-to-even
+    let sci = format!("{:.*e}", figures.max(1) - 1, value.abs());
+    let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let sign = if value < 0.0 { "-" } else { "" };
 
-    while pos < tokens.len() {
-        match tokens[pos] {
-            Token::Multiply => {
-                pos += 1;
-                let (right, next_pos) = parse_factor(tokens, pos)?;
-                left *= right;
-                pos = next_pos;
-            }
-            Token::Divide => {
-                pos += 1;
-                let (right, next_pos) = parse_factor(tokens, pos)?;
-                if right == 0.0 {
-                    return Err(anyhow::anyhow!("Division by zero"));
-                }
-                left /= right;
-                pos = next_pos;
-            }
-            Token::Modulo => {
-                pos += 1;
-                let (right, next_pos) = parse_factor(tokens, pos)?;
-                left %= right;
-                pos = next_pos;
+    let body = match exponent {
+        // Too many zeros either way to read easily
+        ..-4 | 15.. => format!("{}e{}", mantissa, exponent),
+        ..0 => format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits),
+        _ => {
+            let whole = exponent as usize + 1;
+            if whole >= digits.len() {
+                format!("{}{}", digits, "0".repeat(whole - digits.len()))
+            } else {
+                format!("{}.{}", &digits[..whole], &digits[whole..])
             }
-            _ => break,
         }
+    };
+    format!("{}{}", sign, body)
+}
+
+/// `value` as a mantissa and a power of ten, such as `1.2345e6`: rounded to `figures`
+/// significant figures, or otherwise to 10 with trailing zeros dropped.
+pub fn format_scientific(value: f64, figures: Option<usize>) -> String {
+    if !value.is_finite() {
+        return format_result(value);
     }
+    if let Some(figures) = figures {
+        return format!("{:.*e}", figures.max(1) - 1, value);
+    }
+    let sci = format!("{:.9e}", value);
+    let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+    format!("{}e{}", mantissa, exponent)
+}
 
-    Ok((left, pos))
+/// Like [`format_scientific`], with the point moved right so the exponent is a
+/// multiple of 3, as in `12.3e3` or `450e-9`.
+pub fn format_engineering(value: f64, figures: Option<usize>) -> String {
+    let sci = format_scientific(value, figures);
+    let Some((mantissa, exponent)) = sci.split_once('e') else {
+        return sci;
+    };
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let shift = exponent.rem_euclid(3) as usize;
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    // Zeros fill out the whole part when there are fewer digits than places moved
+    while digits.len() < shift + 1 {
+        digits.push('0');
+    }
+    let (whole, fraction) = digits.split_at(shift + 1);
+    let point = if fraction.is_empty() { "" } else { "." };
+    format!(
+        "{}{}{}{}e{}",
+        sign,
+        whole,
+        point,
+        fraction,
+        exponent - shift as i32
+    )
 }
 
-fn parse_factor(tokens: &[Token], mut pos: usize) -> Result<(f64, usize)> {
-    let (mut base, new_pos) = parse_primary(tokens, pos)?;
-    pos = new_pos;
+/// `value` as a fraction in lowest terms, such as `-3/8`, when one with terms at most
+/// `MAX_FRACTION_TERM` matches it to within a few float roundings, so irrationals
+/// don't pass for fractions; whole numbers are themselves.
+pub fn format_fraction(value: f64) -> Option<String> {
+    if !value.is_finite() {
+        return None;
+    }
+    if value.fract() == 0.0 {
+        return Some(format_result(value));
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    let (num, den) = small_fraction(value.abs(), MAX_FRACTION_TERM, 1e-14)?;
+    Some(format!("{}{}/{}", sign, num, den))
+}
 
-    while pos < tokens.len() {
-        if let Token::Power = tokens[pos] {
-            pos += 1;
-            let (exponent, next_pos) = parse_primary(tokens, pos)?;
-            base = base.powf(exponent);
-            pos = next_pos;
-        } else {
-            break;
+/// log10(n!) for a whole number `n`, summed exactly for small `n` and from Stirling's
+/// series past that, where its error is far below f64 precision.
+pub fn log10_factorial(n: f64) -> f64 {
+    if n < 20.0 {
+        return (2..=n as u32).map(|k| (k as f64).log10()).sum();
+    }
+    let ln = n * n.ln() - n + 0.5 * (std::f64::consts::TAU * n).ln() + 1.0 / (12.0 * n)
+        - 1.0 / (360.0 * n.powi(3))
+        + 1.0 / (1260.0 * n.powi(5));
+    ln / std::f64::consts::LN_10
+}
+
+/// A number given by its base-10 logarithm, as an approximate `≈ 4.0239e+2567`.
+pub fn format_magnitude(log10: f64) -> String {
+    let mut exponent = log10.floor();
+    let mut mantissa = 10f64.powf(log10 - exponent);
+    // Rounding to four places can carry into the next power of ten
+    if (mantissa * 1e4).round() >= 1e5 {
+        mantissa /= 10.0;
+        exponent += 1.0;
+    }
+    let sign = if exponent < 0.0 { '-' } else { '+' };
+    format!("≈ {:.4}e{}{}", mantissa, sign, exponent.abs())
+}
+
+/// `value` limited to `[lo, hi]`. NaN stays NaN.
+pub fn clamp(value: f64, lo: f64, hi: f64) -> EvalResult<f64> {
+    if lo.is_nan() || hi.is_nan() || lo > hi {
+        return Err(EvalError::Domain {
+            func: "clamp",
+            arg: lo,
+            reason: "clamp() needs its lower bound no greater than its upper",
+        });
+    }
+    Ok(value.clamp(lo, hi))
+}
+
+/// Modulo that takes the sign of the divisor, so `mod(-7, 3)` is 2 where `-7 % 3`, a
+/// truncated remainder, is -1.
+pub fn floored_mod(a: f64, b: f64) -> EvalResult<f64> {
+    if b == 0.0 {
+        return Err(EvalError::DivisionByZero);
+    }
+    let r = a % b;
+    Ok(if r == 0.0 {
+        0.0
+    } else if (r < 0.0) != (b < 0.0) {
+        r + b
+    } else {
+        r
+    })
+}
+
+/// `value` rounded to `figures` significant figures, as shown by [`format_sig_figs`].
+pub fn round_sig_figs(value: f64, figures: f64) -> EvalResult<f64> {
+    if figures.fract() != 0.0 || !(1.0..=17.0).contains(&figures) {
+        return Err(EvalError::Domain {
+            func: "sigfig",
+            arg: figures,
+            reason: "sigfig() needs a whole number of figures from 1 to 17",
+        });
+    }
+    if !value.is_finite() {
+        return Ok(value);
+    }
+    // Scientific notation always reads back as a float
+    Ok(format!("{:.*e}", figures as usize - 1, value)
+        .parse()
+        .unwrap_or(value))
+}
+
+/// `value` rounded to `digits` decimal places, or to tens, hundreds and so on when
+/// `digits` is negative. Halfway cases round away from zero.
+pub fn round_decimals(value: f64, digits: f64) -> EvalResult<f64> {
+    if digits.fract() != 0.0 || digits.abs() > f64::MAX_10_EXP as f64 {
+        return Err(EvalError::Domain {
+            func: "round",
+            arg: digits,
+            reason: "round() needs a whole number of decimal places",
+        });
+    }
+    if !value.is_finite() || digits > f64::DIGITS as f64 + 2.0 {
+        return Ok(value);
+    }
+    if digits < 0.0 {
+        let unit = 10f64.powi(-digits as i32);
+        return Ok((value / unit).round() * unit);
+    }
+    // Dividing by a power of ten, rather than multiplying by its inexact inverse,
+    // gives back the nearest f64 to the rounded decimal
+    let unit = 10f64.powi(digits as i32);
+    Ok((value * unit).round() / unit)
+}
+
+/// Whether `a` and `b` agree to within `tolerance` of the larger magnitude. Against an
+/// exact zero, where no relative gap is small, `tolerance` is an absolute bound instead.
+/// Infinities only match themselves and NaN matches nothing.
+pub fn approx_equal(a: f64, b: f64, tolerance: f64) -> EvalResult<bool> {
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(EvalError::Domain {
+            func: "approx",
+            arg: tolerance,
+            reason: "approx() needs a tolerance of zero or more",
+        });
+    }
+    if a == b {
+        return Ok(true);
+    }
+    if !a.is_finite() || !b.is_finite() {
+        return Ok(false);
+    }
+    let gap = (a - b).abs();
+    if a == 0.0 || b == 0.0 {
+        return Ok(gap <= tolerance);
+    }
+    Ok(gap <= tolerance * a.abs().max(b.abs()))
+}
+
+/// How many representable f64 values lie between `a` and `b`, so adjacent floats are 1
+/// apart. The two zeros count as the same value. Infinities and NaN aren't counted
+/// among the floats, so have no distance from anything.
+pub fn ulp_distance(a: f64, b: f64) -> EvalResult<f64> {
+    if let Some(arg) = [a, b].into_iter().find(|v| !v.is_finite()) {
+        return Err(EvalError::Domain {
+            func: "ulps",
+            arg,
+            reason: "ulps() needs finite values, not {arg}",
+        });
+    }
+    // Negative floats' bits count up away from zero; flip them to count down instead
+    let ordered = |value: f64| {
+        let bits = value.to_bits() as i64;
+        if bits < 0 { i64::MIN - bits } else { bits }
+    };
+    Ok(ordered(a).abs_diff(ordered(b)) as f64)
+}
+
+/// Symmetric percent difference: the gap between `a` and `b` relative to their mean.
+pub fn percent_difference(a: f64, b: f64) -> f64 {
+    if a == b {
+        return 0.0;
+    }
+    (a - b).abs() / ((a.abs() + b.abs()) / 2.0) * 100.0
+}
+
+/// The percent `new` is up (or down, negative) on `old`.
+pub fn percent_change(old: f64, new: f64) -> EvalResult<f64> {
+    if old == 0.0 {
+        return Err(EvalError::undefined("pctchange", old));
+    }
+    Ok((new - old) / old.abs() * 100.0)
+}
+
+/// The percent of `price` left as profit after `cost`.
+pub fn margin(price: f64, cost: f64) -> EvalResult<f64> {
+    if price == 0.0 {
+        return Err(EvalError::undefined("margin", price));
+    }
+    Ok((price - cost) / price * 100.0)
+}
+
+/// Format `a / b` as a simplified integer ratio such as `3:4`.
+///
+/// Whole numbers are reduced exactly; other values are matched against small integer
+/// ratios within a relative epsilon, falling back to a decimal `q:1` ratio.
+pub fn format_ratio(a: f64, b: f64) -> EvalResult<String> {
+    if !a.is_finite() || !b.is_finite() {
+        return Err(EvalError::Domain {
+            func: "ratio",
+            arg: if a.is_finite() { b } else { a },
+            reason: "ratio() needs finite values",
+        });
+    }
+    if b == 0.0 {
+        return Err(EvalError::DivisionByZero);
+    }
+    let sign = if (a < 0.0) != (b < 0.0) && a != 0.0 {
+        "-"
+    } else {
+        ""
+    };
+    let (a, b) = (a.abs(), b.abs());
+
+    let exact = |v: f64| v.fract() == 0.0 && v < (1u64 << 53) as f64;
+    let (num, den) = if exact(a) && exact(b) {
+        let divisor = gcd(a as u64, b as u64);
+        (a as u64 / divisor, b as u64 / divisor)
+    } else {
+        match small_fraction(a / b, MAX_RATIO_TERM, 1e-9) {
+            Some(fraction) => fraction,
+            None => return Ok(format!("{}{}:1", sign, format_result(a / b))),
+        }
+    };
+    Ok(format!("{}{}:{}", sign, num, den))
+}
+
+/// Closest fraction to `q` with both terms at most `max_term`, if one is within a
+/// relative `tolerance`. Walks the continued-fraction convergents, which are already in
+/// lowest terms.
+fn small_fraction(q: f64, max_term: u64, tolerance: f64) -> Option<(u64, u64)> {
+    let (mut prev_num, mut num) = (0u64, 1u64);
+    let (mut prev_den, mut den) = (1u64, 0u64);
+    let mut x = q;
+    for _ in 0..32 {
+        let whole = x.floor();
+        if whole > max_term as f64 {
+            return None;
+        }
+        let term = whole as u64;
+        (prev_num, num) = (num, term * num + prev_num);
+        (prev_den, den) = (den, term * den + prev_den);
+        if num > max_term || den > max_term {
+            return None;
+        }
+        if (num as f64 / den as f64 - q).abs() <= tolerance * q {
+            return Some((num, den));
         }
+        let rest = x - whole;
+        if rest == 0.0 {
+            return None;
+        }
+        x = 1.0 / rest;
     }
+    None
+}
 
-    Ok((base, pos))
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
 }
 
-fn parse_primary(tokens: &[Token], pos: usize) -> Result<(f64, usize)> {
-    if pos >= tokens.len() {
-        return Err(anyhow::anyhow!("Unexpected end of expression"));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::EXPECTED_OPERAND;
+
+    #[test]
+    fn last_result_is_inserted_at_full_precision() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("1/3".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_expression, "0.3333333333");
+
+        calc.clear();
+        assert!(calc.insert_last_result());
+        calc.append_operator("*");
+        calc.append_digit('3');
+        calc.calculate();
+        assert_eq!(calc.last_value, Some(1.0));
+    }
+
+    #[test]
+    fn last_result_requires_a_previous_calculation() {
+        let mut calc = CalculatorModule::new();
+        assert!(!calc.insert_last_result());
+        assert!(calc.current_expression.is_empty());
+    }
+
+    #[test]
+    fn ans_is_the_previous_result() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("ans*2".to_string());
+        assert_eq!(
+            calc.evaluate_str("ans*2"),
+            Err(EvalError::UndefinedVariable("ans".to_string()))
+        );
+        assert_eq!(
+            suggest(&EvalError::UndefinedVariable("ans".to_string()), "ans*2").as_deref(),
+            Some("ans is the last result; calculate something first")
+        );
+
+        calc.set_expression("2+3".to_string());
+        calc.calculate();
+        calc.set_expression("ans*2+1".to_string());
+        calc.update_result();
+        assert_eq!(calc.current_result, "11");
+        calc.calculate();
+        assert_eq!(calc.last_value, Some(11.0));
+
+        // Each statement sees the one before it, in the preview as when calculated
+        calc.set_expression("4; ans^2; ANS+1".to_string());
+        calc.update_result();
+        assert_eq!(calc.current_result, "17");
+        calc.calculate();
+        assert_eq!(calc.current_expression, "17");
+
+        // It can't be assigned to
+        assert_eq!(split_assignment("ans = 3"), None);
     }
 
-    match &tokens[pos] {
-        Token::Number(n) => Ok((*n, pos + 1)),
-        Token::Minus => {
-            let (value, new_pos) = parse_primary(tokens, pos + 1)?;
-            Ok((-value, new_pos))
+    #[test]
+    fn edits_happen_at_the_cursor() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("12".to_string());
+        calc.move_cursor_left();
+        calc.append_operator("+");
+        assert_eq!(calc.current_expression, "1+2");
+        assert_eq!(calc.cursor(), 2);
+
+        calc.move_cursor_home();
+        calc.insert_str("(");
+        calc.move_cursor_end();
+        calc.insert_str(")");
+        assert_eq!(calc.current_expression, "(1+2)");
+        calc.backspace();
+        calc.move_cursor_home();
+        calc.delete();
+        assert_eq!(calc.current_expression, "1+2");
+    }
+
+    #[test]
+    fn ratio_is_simplified() {
+        assert_eq!(format_ratio(6.0, 8.0).unwrap(), "3:4");
+        assert_eq!(format_ratio(1.0, 3.0).unwrap(), "1:3");
+        assert_eq!(format_ratio(0.1, 0.3).unwrap(), "1:3");
+        assert_eq!(format_ratio(-2.0, 4.0).unwrap(), "-1:2");
+        assert_eq!(
+            format_ratio(1.0, std::f64::consts::PI).unwrap(),
+            "0.3183098862:1"
+        );
+        assert_eq!(format_ratio(1.0, 0.0), Err(EvalError::DivisionByZero));
+    }
+
+    #[test]
+    fn ratio_is_a_text_result() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("ratio(6, 8)".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "3:4");
+        assert_eq!(calc.history[0].result, "3:4");
+        assert_eq!(calc.last_value, None);
+
+        calc.set_expression("ratio(1, 2) + 1".to_string());
+        calc.calculate();
+        assert_eq!(
+            calc.error,
+            Some(EvalError::TextInExpression("ratio".to_string()))
+        );
+
+        calc.set_expression("pctdiff(90, 110)".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "20");
+    }
+
+    #[test]
+    fn percentages_of_prices() {
+        let mut calc = CalculatorModule::new();
+        for (expr, result) in [
+            ("pctchange(80, 100)", "25"),
+            ("pctchange(100, 80)", "-20"),
+            ("pctchange(-50, -25)", "50"),
+            ("markup(80, 25)", "100"),
+            ("margin(100, 80)", "20"),
+            ("margin(markup(80, 25), 80)", "20"),
+        ] {
+            calc.set_expression(expr.to_string());
+            calc.calculate();
+            assert_eq!(calc.current_result, result, "{}", expr);
         }
-        Token::LParen => {
-            let (value, new_pos) = parse_expression(tokens, pos + 1)?;
-            if new_pos >= tokens.len() || !matches!(tokens[new_pos], Token::RParen) {
-                return Err(anyhow::anyhow!("Missing closing parenthesis"));
+
+        calc.set_expression("pctchange(0, 5)".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::undefined("pctchange", 0.0)));
+        calc.set_expression("margin(0, 5)".to_string());
+        calc.calculate();
+        assert_eq!(
+            calc.error.map(|error| error.to_string()).as_deref(),
+            Some("margin() is undefined for 0")
+        );
+    }
+
+    #[test]
+    fn lists_show_their_values_and_read_back() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("[1, 2.5, 1000] * 2".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "[2, 5, 2000]");
+        assert_eq!(calc.current_expression, "[2, 5, 2000]");
+        assert_eq!(calc.history[0].result, "[2, 5, 2000]");
+        assert_eq!(calc.result_value, None);
+
+        // The result is an expression again, and grouping leaves the commas alone
+        calc.digit_grouping = DigitGrouping::Comma;
+        assert_eq!(calc.displayed_result().0, "[2, 5, 2000]");
+        calc.set_expression(format!("mean({}) + 1_000", calc.current_expression));
+        calc.calculate();
+        assert_eq!(calc.current_result, "1669");
+
+        calc.set_expression("a = [1, 2]".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::ListAssignment));
+        assert!(!calc.variables.contains_key("a"));
+    }
+
+    #[test]
+    fn matrices_show_as_they_are_typed() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("inv([[2, 0], [0, 4]]) * 2".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "[[1, 0], [0, 0.5]]");
+        assert_eq!(calc.result_value, None);
+        calc.set_expression(format!("det({})", calc.current_expression));
+        calc.calculate();
+        assert_eq!(calc.current_result, "0.5");
+
+        calc.set_expression("m = identity(2)".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::ListAssignment));
+        calc.set_expression("det([[1, 2], [2, 4]]) + inv([[1, 2], [2, 4]])".to_string());
+        calc.calculate();
+        assert_eq!(
+            calc.error.as_ref().map(ToString::to_string).as_deref(),
+            Some("inv() needs a matrix whose determinant isn't 0")
+        );
+    }
+
+    #[test]
+    fn cash_flows_give_npv_and_irr() {
+        let mut calc = CalculatorModule::new();
+        let result = |calc: &mut CalculatorModule, expr: &str| {
+            calc.set_expression(expr.to_string());
+            calc.calculate();
+            calc.current_result.clone()
+        };
+        let flows = "[-1000, 300, 400, 500]";
+        assert_eq!(
+            result(&mut calc, &format!("npv(percent(10), {})", flows)),
+            "-21.0368144252"
+        );
+        assert_eq!(
+            result(&mut calc, "npv(0.1, -1000, 300, [400, 500])"),
+            "-21.0368144252"
+        );
+        assert_eq!(
+            result(&mut calc, &format!("round(irr({}) * 100, 4)", flows)),
+            "8.8963"
+        );
+
+        result(&mut calc, "irr([100, 50])");
+        assert_eq!(
+            calc.error.as_ref().map(ToString::to_string).as_deref(),
+            Some("irr() needs cash flows that change sign, so some rate balances them")
+        );
+        result(&mut calc, "npv(-1, [1, 2])");
+        assert_eq!(calc.error, Some(EvalError::undefined("npv", -1.0)));
+        result(&mut calc, "npv([1, 2])");
+        assert!(matches!(calc.error, Some(EvalError::Arity { .. })));
+    }
+
+    #[test]
+    fn dates_show_quoted_and_read_back() {
+        let mut calc = CalculatorModule::new();
+        calc.digit_grouping = DigitGrouping::Comma;
+        calc.set_expression("date_add(\"2024-01-01\", 1000) + 1".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "\"2026-09-28\"");
+        assert_eq!(calc.displayed_result().0, "\"2026-09-28\"");
+        assert_eq!(calc.result_value, None);
+        calc.set_expression(format!("{} - \"2024-01-01\"", calc.current_expression));
+        calc.calculate();
+        assert_eq!(calc.current_result, "1001");
+
+        calc.set_expression("weekday(\"2024-06-15\")".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "Saturday");
+        calc.set_expression("d = today()".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::ListAssignment));
+
+        // Times too, their fraction of a second left alone by a decimal comma
+        calc.digit_grouping = DigitGrouping::Period;
+        calc.set_expression("tz(unix(1718452800 + 1/2), 2)".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "\"2024-06-15 14:00:00.500 +02:00\"");
+        assert_eq!(calc.displayed_result().0, calc.current_result);
+        calc.set_expression(format!("to_unix({})", calc.current_expression));
+        calc.calculate();
+        assert_eq!(calc.displayed_result().0, "1.718.452.800,5");
+    }
+
+    #[test]
+    fn uncertain_results_round_to_their_error() {
+        assert_eq!(format_uncertain(9.81, 0.02), "9.81 ± 0.02");
+        assert_eq!(format_uncertain(9.8123, 0.0213), "9.812 ± 0.021");
+        assert_eq!(format_uncertain(9876.5, 123.0), "9880 ± 120");
+        assert_eq!(format_uncertain(1.0, 0.0996), "1.0 ± 0.1");
+        assert_eq!(format_uncertain(-0.001, 0.5), "0.0 ± 0.5");
+        assert_eq!(format_uncertain(2.5, 0.0), "2.5 ± 0");
+
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("(9.81±0.02) * 2".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "19.62 ± 0.04");
+        assert_eq!(calc.history[0].result, "19.62 ± 0.04");
+        assert_eq!(calc.result_value, None);
+        // The result reads back, and typing hints the error still to come
+        calc.set_expression(format!("{} / 2", calc.current_expression));
+        calc.calculate();
+        assert_eq!(calc.current_result, "9.81 ± 0.02");
+        calc.set_expression("g = 9.81 +/- 0.02".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::ListAssignment));
+        assert_eq!(
+            hint_for("sin(1 ± ", AngleMode::Radians, PercentBehavior::Modulo).as_deref(),
+            Some("enter a number, ( or a function")
+        );
+    }
+
+    #[test]
+    fn random_draws_repeat_from_a_seed() {
+        let draws = |seed| {
+            let mut calc = CalculatorModule::new();
+            calc.rng = Rng::seeded(seed);
+            calc.set_expression("rand() + randint(1, 6) + randn()".to_string());
+            calc.update_result();
+            // Previewing doesn't draw, so calculating gives what was shown
+            let preview = calc.current_result.clone();
+            calc.calculate();
+            assert_eq!(calc.current_result, preview);
+            for _ in 0..2 {
+                calc.set_expression("rand()".to_string());
+                calc.calculate();
             }
-            Ok((value, new_pos + 1))
+            calc.history
+                .iter()
+                .map(|entry| entry.result.clone())
+                .collect::<Vec<_>>()
+        };
+        let first = draws(2024);
+        assert_eq!(first, draws(2024));
+        assert_ne!(first, draws(2025));
+        assert_ne!(first[1], first[2]);
+
+        let calc = CalculatorModule::new();
+        assert!(matches!(
+            calc.evaluate_str("rand(1)"),
+            Err(EvalError::Arity { got: 1, .. })
+        ));
+        assert_eq!(
+            calc.evaluate_str("randint(2.2, 2.8)")
+                .unwrap_err()
+                .to_string(),
+            "randint() needs finite bounds with a whole number between"
+        );
+    }
+
+    #[test]
+    fn number_theory_of_whole_numbers() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("factor(360)".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "2^3 * 3^2 * 5");
+        // The factorization reads back as the number
+        calc.set_expression(calc.current_result.clone());
+        calc.calculate();
+        assert_eq!(calc.current_result, "360");
+
+        calc.set_expression("gcd(12, 18) + lcm(4, 6) + isprime(97)".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "19");
+        calc.set_expression("factor(12) * 2".to_string());
+        calc.calculate();
+        assert_eq!(
+            calc.error,
+            Some(EvalError::TextInExpression("factor".to_string()))
+        );
+        assert_eq!(
+            calc.evaluate_str("gcd(7.5, 3)").unwrap_err().to_string(),
+            "gcd() needs whole numbers up to 2^53, not 7.5"
+        );
+        assert!(matches!(
+            calc.evaluate_str("isprime(2^61 - 1)"),
+            Err(EvalError::Domain {
+                func: "isprime",
+                ..
+            })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("factor(1, 2)"),
+            Err(EvalError::Arity { got: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn statements_are_evaluated_in_order() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("a=3; b=4; a*b;".to_string());
+        calc.update_result();
+        assert_eq!(calc.current_result, "12");
+        assert!(calc.variables.is_empty());
+
+        let results = calc.calculate();
+        let statements: Vec<&str> = results.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(statements, ["a=3", "b=4", "a*b"]);
+        assert_eq!(results[2].1, Outcome::Number(12.0));
+        assert_eq!(calc.history.len(), 3);
+        assert_eq!(calc.current_expression, "12");
+        assert_eq!(calc.variables.get("b"), Some(&4.0));
+    }
+
+    #[test]
+    fn big_integer_mode_is_exact() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("2^64 - 1".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "1.8446744074e19");
+
+        calc.big_integers = true;
+        calc.set_expression("2^64 - 1".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "18446744073709551615");
+        assert_eq!(calc.current_expression, "18446744073709551615");
+
+        calc.set_expression("20!".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "2432902008176640000");
+
+        calc.set_expression("7/2".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "3.5");
+
+        // Programmer mode still wraps to its word
+        calc.mode = CalculatorMode::Programmer;
+        calc.set_expression("2^70 + 2^63".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "-9223372036854775808");
+    }
+
+    #[test]
+    fn fraction_mode_keeps_results_exact() {
+        let mut calc = CalculatorModule::new();
+        calc.fractions = true;
+        calc.set_expression("1/3 + 1/6".to_string());
+        calc.update_result();
+        assert_eq!(calc.current_result, "1/2");
+        assert_eq!(calc.result_value, Some(0.5));
+        calc.calculate();
+        assert_eq!(calc.current_expression, "1/2");
+        assert_eq!(calc.history[0].result, "1/2");
+        assert_eq!(calc.last_value, Some(0.5));
+
+        // The same result shown as a decimal, and back
+        calc.fractions = false;
+        calc.update_result();
+        assert_eq!(calc.current_result, "0.5");
+        calc.fractions = true;
+        calc.update_result();
+        assert_eq!(calc.current_result, "1/2");
+
+        for (expression, expected) in [
+            ("0.1 + 0.2", "3/10"),
+            ("(2/3)^-2", "9/4"),
+            ("1/3 * 3", "1"),
+            ("sqrt(1/4)", "0.5"),
+            ("10 % 4", "2"),
+        ] {
+            assert_eq!(calc.evaluate_str(expression).unwrap().to_string(), expected);
         }
-        Token::Ident(name) => {
-            // function call: ident '(' expr ')'
-            if pos + 1 < tokens.len() && matches!(tokens[pos + 1], Token::LParen) {
-                let (arg, np) = parse_expression(tokens, pos + 2)?; // skip ident + '('
-                if np >= tokens.len() || !matches!(tokens[np], Token::RParen) {
-                    return Err(anyhow::anyhow!("Missing closing parenthesis"));
-                }
-                let val = match name.as_str() {
-                    "sin" => arg.sin(),
-                    "cos" => arg.cos(),
-                    "tan" => arg.tan(),
-                    "sqrt" => arg.sqrt(),
-                    "log" => arg.log10(),
-                    "ln" => arg.ln(),
-                    "exp" => arg.exp(),
-                    "abs" => arg.abs(),
-                    _ => return Err(anyhow::anyhow!("Unknown function: {}", name)),
-                };
-                Ok((val, np + 1))
-            } else {
-                Err(anyhow::anyhow!("Unexpected identifier: {}", name))
+        // Programmer mode works in integers, so there is nothing to keep
+        calc.mode = CalculatorMode::Programmer;
+        assert_eq!(calc.evaluate_str("1/3 + 1/6").unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn previews_that_fail_say_why() {
+        let mut calc = CalculatorModule::new();
+        for (expression, issue) in [
+            ("sin(", Some("1 bracket open")),
+            ("(1+(2", Some("2 brackets open")),
+            ("1+2)", Some("unmatched )")),
+            ("[1, 2)", Some("unmatched )")),
+            ("2 + * 3", Some("Expected a number, name or ( at column 5")),
+            // A preview of what's complete, and errors that aren't in the typing
+            ("3*(", None),
+            ("1/0", None),
+            ("convert(1, \"(\", \"m\")", None),
+        ] {
+            calc.set_expression(expression.to_string());
+            calc.update_result();
+            assert_eq!(calc.preview_issue.as_deref(), issue, "{}", expression);
+        }
+        calc.calculate();
+        assert_eq!(calc.preview_issue, None);
+        assert_eq!(unbalanced_brackets("(1]) + \")\""), [2]);
+        assert_eq!(unbalanced_brackets("[(1]"), [0, 1, 3]);
+    }
+
+    #[test]
+    fn preview_ignores_a_dangling_operator() {
+        let mut calc = CalculatorModule::new();
+        for (expression, preview) in [
+            ("3+", "3"),
+            ("3*(", "3"),
+            ("2^", "2"),
+            ("2 + sin(", "2"),
+            ("3*-", "3"),
+            ("1; 4-", "4"),
+            ("0xF0 | ~", "240"),
+            ("1 <<", "1"),
+        ] {
+            calc.set_expression(expression.to_string());
+            calc.update_result();
+            assert_eq!(calc.current_result, preview, "{}", expression);
+            assert!(calc.partial_preview, "{}", expression);
+        }
+
+        // Nothing left to show: the raw text stays, and it isn't marked partial
+        calc.set_expression("sin(".to_string());
+        calc.update_result();
+        assert_eq!(calc.current_result, "sin(");
+        assert!(!calc.partial_preview);
+
+        // Complete expressions, including negative literals and factorials, are left alone
+        for (expression, preview) in [("-3", "-3"), ("2*-3", "-6"), ("3!", "6")] {
+            calc.set_expression(expression.to_string());
+            calc.update_result();
+            assert_eq!(calc.current_result, preview, "{}", expression);
+            assert!(!calc.partial_preview, "{}", expression);
+        }
+
+        // calculate still rejects the dangling operator
+        calc.set_expression("3+".to_string());
+        calc.calculate();
+        assert!(matches!(calc.error, Some(EvalError::UnexpectedEnd { .. })));
+    }
+
+    #[test]
+    fn errors_come_with_suggested_fixes() {
+        let unknown = |name: &str| EvalError::UnknownFunction(name.to_string());
+        let undefined = |name: &str| EvalError::UndefinedVariable(name.to_string());
+        assert_eq!(
+            suggest(&unknown("sine"), "sine(1)").as_deref(),
+            Some("did you mean sin?")
+        );
+        assert_eq!(
+            suggest(&undefined("sqr"), "sqr").as_deref(),
+            Some("did you mean sqrt?")
+        );
+        assert_eq!(suggest(&unknown("frobnicate"), "frobnicate(1)"), None);
+        assert_eq!(
+            suggest(&undefined("y"), "2*y").as_deref(),
+            Some("assign it first, e.g. y = 1")
+        );
+        assert!(suggest(&EvalError::InvalidCharacter(','), "3,5").is_some());
+        assert_eq!(suggest(&EvalError::InvalidCharacter('#'), "#"), None);
+        assert!(suggest(&EvalError::MissingParen { pos: 4 }, "(1+2").is_some());
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+
+        // The errors themselves come from evaluation, and the hint reaches the module
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("3,5".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::InvalidCharacter(',')));
+        assert!(calc.error_hint.is_some());
+
+        // Calculating again after a missing paren closes it
+        calc.set_expression("2*(3+4".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::MissingParen { pos: 6 }));
+        calc.calculate();
+        assert_eq!(calc.error, None);
+        assert_eq!(calc.error_message, None);
+        assert_eq!(calc.error_hint, None);
+        assert_eq!(calc.current_result, "14");
+    }
+
+    #[test]
+    fn history_keeps_a_normalized_form_and_the_typed_one() {
+        for (typed, normalized) in [
+            ("3.14159*2", "3.14159 * 2"),
+            ("2 +2", "2 + 2"),
+            ("sin (x)", "sin(x)"),
+            ("2*PI", "2 * π"),
+            ("-3^ -2", "-3^-2"),
+            ("2( 3+4 )(5)", "2(3 + 4)(5)"),
+            ("mod(7 ,-3)", "mod(7, -3)"),
+            ("x=2 *  e", "x = 2 * e"),
+            ("5!-50%o", "5! - 50%o"),
+            ("1 2 + 3", "12 + 3"),
+            ("0xF0|~x<<2", "0xF0 | ~x << 2"),
+            ("5 xor  3&1", "5 xor 3 & 1"),
+        ] {
+            assert_eq!(
+                normalize(typed, PercentBehavior::Modulo).as_deref(),
+                Some(normalized)
+            );
+        }
+        assert_eq!(
+            normalize("50+10%", PercentBehavior::Contextual).as_deref(),
+            Some("50 + 10%")
+        );
+        assert_eq!(normalize("2 # 3", PercentBehavior::Modulo), None);
+
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("2 +2".to_string());
+        calc.calculate();
+        assert_eq!(calc.history[0].expression, "2 + 2");
+        assert_eq!(calc.history[0].typed(), "2 +2");
+        calc.recall_from_history(0);
+        assert_eq!(calc.current_expression, "2 +2");
+        assert!(!calc.has_unevaluated_expression());
+
+        calc.set_expression("1 + 1".to_string());
+        calc.calculate();
+        assert_eq!(calc.history[1].raw, None);
+    }
+
+    #[test]
+    fn alternate_expression_swaps_back_and_forth() {
+        let mut calc = CalculatorModule::new();
+        assert!(!calc.swap_alternate());
+
+        // Calculating replaces the expression with its result
+        calc.set_expression("2+3".to_string());
+        calc.calculate();
+        assert_eq!(calc.alternate_expression.as_deref(), Some("2+3"));
+        assert!(calc.swap_alternate());
+        assert_eq!(calc.current_expression, "2+3");
+        assert_eq!(calc.current_result, "5");
+        calc.swap_alternate();
+        assert_eq!(calc.current_expression, "5");
+
+        // Recalling swaps out what was being typed
+        calc.set_expression("7*6".to_string());
+        calc.recall_from_history(0);
+        assert_eq!(calc.current_expression, "2+3");
+        calc.swap_alternate();
+        assert_eq!(calc.current_expression, "7*6");
+        assert_eq!(calc.current_result, "42");
+
+        // Clearing keeps the cleared expression; an empty one is never the alternate
+        calc.clear();
+        assert_eq!(calc.alternate_expression.as_deref(), Some("7*6"));
+        calc.swap_alternate();
+        assert_eq!(calc.current_expression, "7*6");
+        assert_eq!(calc.alternate_expression, None);
+
+        // A failed calculation leaves the expression, and the alternate, alone
+        calc.set_expression("1/0".to_string());
+        calc.calculate();
+        assert_eq!(calc.alternate_expression, None);
+    }
+
+    #[test]
+    fn hints_follow_the_tail_of_the_expression() {
+        let hint = |expr: &str| hint_for(expr, AngleMode::Radians, PercentBehavior::Modulo);
+        let operand = Some("enter a number, ( or a function".to_string());
+        for (expr, expected) in [
+            ("", None),
+            ("2+3", None),
+            ("2+", operand.clone()),
+            ("2*(", operand.clone()),
+            ("7 %", operand.clone()),
+            ("x = 2^", operand.clone()),
+            (
+                "sin(",
+                Some("argument expected - sin takes radians (RAD mode)".to_string()),
+            ),
+            ("sqrt(", Some("argument expected for sqrt".to_string())),
+            ("mod(", Some("mod takes two arguments: a, b".to_string())),
+            ("mod(7,", Some("enter the next argument".to_string())),
+            (
+                "convert(",
+                Some("convert takes a value, then two units in quotes: \"km\", \"mi\"".to_string()),
+            ),
+            ("2*sq", Some("functions: sqrt".to_string())),
+            ("abs", Some("add ( to call abs".to_string())),
+            ("(2*(3+1", Some("2 parens open - ) closes each".to_string())),
+            ("sin(1", Some("1 paren open - ) closes it".to_string())),
+            ("a=1; 3-", operand.clone()),
+            ("total", None),
+            ("2 # 3", None),
+        ] {
+            assert_eq!(hint(expr), expected, "{:?}", expr);
+        }
+        assert_eq!(
+            hint_for("cos(", AngleMode::Degrees, PercentBehavior::Modulo).as_deref(),
+            Some("argument expected - cos takes degrees (DEG mode)")
+        );
+        assert_eq!(
+            hint_for("atan2(", AngleMode::Degrees, PercentBehavior::Modulo).as_deref(),
+            Some("argument expected - atan2(y, x) gives degrees (DEG mode)")
+        );
+    }
+
+    #[test]
+    fn functions_take_several_arguments() {
+        let calc = CalculatorModule::new();
+        let value = |expr: &str| calc.evaluate_str(expr).unwrap().as_f64().unwrap();
+        assert_eq!(value("min(3, 1, 2)"), 1.0);
+        assert_eq!(value("max(-4, 2^3, 7)"), 8.0);
+        assert_eq!(value("max(5)"), 5.0);
+        assert_eq!(value("min(1, 2, 3, 4, 5, 0)"), 0.0);
+        assert_eq!(value("clamp(7, 0, 5)"), 5.0);
+        assert_eq!(value("clamp(-7, -1, 1)"), -1.0);
+        assert_eq!(value("clamp(0.5, 0, 1)"), 0.5);
+        assert_eq!(
+            calc.evaluate_str("clamp(1, 5, 0)").unwrap_err().to_string(),
+            "clamp() needs its lower bound no greater than its upper"
+        );
+        assert_eq!(value("log(1000)"), 3.0);
+        assert_eq!(value("log(10, 1000)"), 3.0);
+        assert_eq!(value("log(2, 1024)"), 10.0);
+        assert!((value("log(3, 81)") - 4.0).abs() < 1e-15);
+        assert_eq!(value("round(2.5)"), 3.0);
+        assert_eq!(value("round(1.23456, 2)"), 1.23);
+        assert_eq!(value("round(-1234.5, -2)"), -1200.0);
+        assert_eq!(value("round(0.1 + 0.2, 15)"), 0.3);
+        assert_eq!(
+            calc.evaluate_str("round(1, 0.5)").unwrap_err().to_string(),
+            "round() needs a whole number of decimal places"
+        );
+
+        let message = |expr: &str| calc.evaluate_str(expr).unwrap_err().to_string();
+        assert_eq!(
+            message("log(2, 8, 1)"),
+            "log() takes 1 or 2 arguments, got 3"
+        );
+        assert_eq!(
+            message("approx(1)"),
+            "approx() takes 2 or 3 arguments, got 1"
+        );
+        assert_eq!(
+            message("atan2(1, 2, 3)"),
+            "atan2() takes 2 arguments, got 3"
+        );
+        assert_eq!(message("sqrt(1, 2)"), "sqrt() takes 1 argument, got 2");
+        assert_eq!(message("fmin(x, 1)"), "fmin() takes 3 arguments, got 2");
+        assert_eq!(message("ratio(1)"), "ratio() takes 2 arguments, got 1");
+    }
+
+    #[test]
+    fn programmer_mode_works_in_integers() {
+        let mut calc = CalculatorModule::new();
+        calc.toggle_mode();
+        calc.toggle_mode();
+        assert_eq!(calc.mode, CalculatorMode::Programmer);
+        calc.set_expression("7/2 + 0.9".to_string());
+        calc.update_result();
+        assert_eq!(calc.current_result, "3");
+        assert_eq!(calc.evaluate_str("-7/2").unwrap(), Outcome::Number(-3.0));
+        assert_eq!(
+            calc.evaluate_str("and(0xF0, 0x3C)").unwrap(),
+            Outcome::Number(48.0)
+        );
+        assert_eq!(
+            calc.evaluate_str("xor(shl(1, 4), ~0)").unwrap(),
+            Outcome::Number(-17.0)
+        );
+        assert_eq!(
+            calc.evaluate_str("shr(1, 64)").unwrap_err().to_string(),
+            "shr() shifts whole numbers by 0 to 63 places"
+        );
+
+        // Hex digits turn the number they're typed into into a hex literal
+        calc.set_expression("2*1".to_string());
+        calc.append_hex_digit('F');
+        calc.append_digit('0');
+        assert_eq!(calc.current_expression, "2*0x1F0");
+        calc.set_expression("0xA".to_string());
+        calc.append_hex_digit('B');
+        assert_eq!(calc.current_expression, "0xAB");
+
+        // Results wrap to the word size, in every step of the calculation
+        calc.cycle_word_size();
+        assert_eq!(calc.word_size, WordSize::Bits8);
+        assert_eq!(
+            calc.evaluate_str("127 + 1").unwrap(),
+            Outcome::Number(-128.0)
+        );
+        assert_eq!(
+            calc.evaluate_str("(200 + 100) - 100").unwrap(),
+            Outcome::Number(-56.0)
+        );
+        assert_eq!(
+            calc.evaluate_str("~0 & 0xFF").unwrap(),
+            Outcome::Number(-1.0)
+        );
+        calc.cycle_word_size();
+        assert_eq!(calc.evaluate_str("0xFFFF").unwrap(), Outcome::Number(-1.0));
+        calc.cycle_word_size();
+        calc.cycle_word_size();
+        assert_eq!(calc.word_size, WordSize::Bits64);
+
+        // Leaving the mode brings fractions back
+        calc.set_expression("7/2".to_string());
+        calc.toggle_mode();
+        assert_eq!(calc.mode, CalculatorMode::Financial);
+        assert_eq!(calc.current_result, "3.5");
+        // Bitwise functions need integers outside programmer mode too
+        assert_eq!(
+            calc.evaluate_str("or(1.5, 1)").unwrap_err().to_string(),
+            "or() needs whole numbers, not 1.5"
+        );
+    }
+
+    #[test]
+    fn inverse_trig_gives_angles_in_the_angle_mode() {
+        let mut calc = CalculatorModule::new();
+        let value = |calc: &CalculatorModule, expr: &str| calc.evaluate_str(expr).unwrap().as_f64();
+        let close = |got: Option<f64>, expected: f64| (got.unwrap() - expected).abs() < 1e-12;
+        assert!(close(value(&calc, "asin(1)"), std::f64::consts::FRAC_PI_2));
+        assert!(close(
+            value(&calc, "atan2(1, -1)"),
+            3.0 * std::f64::consts::FRAC_PI_4
+        ));
+
+        calc.toggle_angle_mode();
+        assert!(close(value(&calc, "asin(0.5)"), 30.0));
+        assert!(close(value(&calc, "acos(-1)"), 180.0));
+        assert!(close(value(&calc, "atan(1)"), 45.0));
+        assert!(close(value(&calc, "atan2(-1, 0)"), -90.0));
+        assert!(close(value(&calc, "sin(asin(0.25))"), 0.25));
+        assert_eq!(
+            calc.evaluate_str("asin(2)"),
+            Err(EvalError::undefined("asin", 2.0))
+        );
+        assert_eq!(
+            calc.evaluate_str("atan2(1)"),
+            Err(EvalError::Arity {
+                name: "atan2".to_string(),
+                expected: 2,
+                got: 1
+            })
+        );
+
+        // The keypad applies them to the current result
+        calc.set_expression("1".to_string());
+        calc.calculate();
+        calc.apply_function("atan");
+        assert_eq!(calc.current_result, "45");
+        let context = calc.history.last().unwrap().context.as_ref().unwrap();
+        assert_eq!(context.angle_mode, Some(AngleMode::Degrees));
+    }
+
+    #[test]
+    fn huge_factorials_show_their_magnitude() {
+        // log10 of 100!, 170! and 1000!, and their known leading digits
+        for (n, log10, shown) in [
+            (100.0, 157.970_003_654_715_75, "≈ 9.3326e+157"),
+            (170.0, 306.860_781_994_828_3, "≈ 7.2574e+306"),
+            (1000.0, 2_567.604_644_222_132, "≈ 4.0239e+2567"),
+        ] {
+            assert!((log10_factorial(n) - log10).abs() < 1e-9, "{}!", n);
+            assert_eq!(format_magnitude(log10), shown);
+        }
+        assert_eq!(log10_factorial(5.0), 120f64.log10());
+        assert_eq!(format_magnitude(4.999_999_9), "≈ 1.0000e+5");
+        assert_eq!(format_magnitude(-3.5), "≈ 3.1623e-4");
+
+        let mut calc = CalculatorModule::new();
+        assert_eq!(
+            calc.evaluate_str("1000!").unwrap().to_string(),
+            "≈ 4.0239e+2567"
+        );
+        assert!(matches!(
+            calc.evaluate_str("1000! / 2"),
+            Err(EvalError::Overflow { .. })
+        ));
+        calc.set_expression("171!".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_expression, "171!");
+        assert_eq!(calc.current_result, "≈ 1.2410e+309");
+        assert_eq!(calc.last_value, None);
+
+        // Exact while the digits fit, approximate past the limit
+        calc.big_integers = true;
+        assert_eq!(calc.evaluate_str("30!").unwrap().to_string().len(), 33);
+        calc.max_digits = 30;
+        assert!(matches!(calc.evaluate_str("30!"), Ok(Outcome::Number(_))));
+        assert!(matches!(calc.evaluate_str("200!"), Ok(Outcome::Huge(_))));
+    }
+
+    #[test]
+    fn missing_parens_point_past_what_they_close() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("max(1, 2 ".to_string());
+        calc.calculate();
+        assert_eq!(calc.error, Some(EvalError::MissingParen { pos: 8 }));
+        assert_eq!(calc.error_column, Some(8));
+        assert_eq!(
+            calc.error_message.as_deref(),
+            Some("Error: Expected ) at column 9")
+        );
+
+        // Inside a later statement the column counts from the start of the line
+        calc.set_expression("a=2; (a+1".to_string());
+        calc.calculate();
+        assert_eq!(calc.error_column, Some(9));
+    }
+
+    #[test]
+    fn backspace_after_an_error_first_dismisses_it() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("a=1; 2+*3".to_string());
+        calc.calculate();
+        assert!(calc.error.is_some());
+        assert_eq!(calc.error_column, Some(7));
+
+        // The first press keeps the expression and goes to the error
+        calc.backspace();
+        assert_eq!(calc.error_message, None);
+        assert_eq!(calc.current_expression, "a=1; 2+*3");
+        assert_eq!(calc.cursor(), 7);
+        calc.backspace();
+        assert_eq!(calc.current_expression, "a=1; 2*3");
+
+        // Errors without a place leave the cursor where it is
+        calc.set_expression("nope + 1".to_string());
+        calc.calculate();
+        calc.move_cursor_home();
+        calc.backspace();
+        assert_eq!((calc.cursor(), calc.error.as_ref()), (0, None));
+        calc.set_expression("(1+2".to_string());
+        calc.calculate();
+        calc.move_cursor_home();
+        calc.backspace();
+        assert_eq!(calc.cursor(), 4);
+    }
+
+    #[test]
+    fn python_power_and_floor_division() {
+        let calc = CalculatorModule::new();
+        for (expression, expected) in [
+            ("2**10", "1024"),
+            ("9**0.5", "3"),
+            ("7//2", "3"),
+            ("-7//2", "-4"),
+            ("7.5//2.5", "3"),
+            ("1 + 9//4*2", "5"),
+        ] {
+            assert_eq!(
+                calc.evaluate_str(expression).unwrap().to_string(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(calc.evaluate_str("1//0"), Err(EvalError::DivisionByZero));
+        assert_eq!(
+            normalize("2**3//x", PercentBehavior::Modulo).as_deref(),
+            Some("2^3 // x")
+        );
+
+        // Typing the operator twice doubles it; another operator replaces both chars
+        let mut typed = CalculatorModule::new();
+        typed.append_digit('2');
+        typed.append_operator("*");
+        typed.append_operator("*");
+        assert_eq!(typed.current_expression, "2**");
+        typed.append_operator("/");
+        assert_eq!(typed.current_expression, "2/");
+        typed.append_operator("/");
+        typed.append_operator("/");
+        assert_eq!(typed.current_expression, "2/");
+    }
+
+    #[test]
+    fn errors_say_what_went_wrong_and_where() {
+        let calc = CalculatorModule::new();
+        let syntax = |pos, expected| Err(EvalError::Syntax { pos, expected });
+        let arity = |name: &str, expected, got| {
+            Err(EvalError::Arity {
+                name: name.to_string(),
+                expected,
+                got,
+            })
+        };
+        for (expression, expected) in [
+            ("x 3", syntax(2, EXPECTED_OPERATOR)),
+            ("2 * (3 + 4) x 5", syntax(14, EXPECTED_OPERATOR)),
+            ("2 * )", syntax(4, EXPECTED_OPERAND)),
+            // Columns count chars, not bytes
+            ("π *, 2", syntax(3, EXPECTED_OPERAND)),
+            (
+                "2 *",
+                Err(EvalError::UnexpectedEnd {
+                    expected: EXPECTED_OPERAND,
+                }),
+            ),
+            ("1.2.3", Err(EvalError::InvalidNumber("1.2.3".to_string()))),
+            ("sin(1, 2)", arity("sin", 1, 2)),
+            ("mod(1)", arity("mod", 2, 1)),
+            ("1/0", Err(EvalError::DivisionByZero)),
+            (
+                "(-1)!",
+                Err(EvalError::Domain {
+                    func: "factorial",
+                    arg: -1.0,
+                    reason: "Factorial needs a non-negative integer",
+                }),
+            ),
+            ("1 # 2", Err(EvalError::InvalidCharacter('#'))),
+        ] {
+            assert_eq!(calc.evaluate_str(expression), expected, "{}", expression);
+        }
+
+        assert_eq!(
+            EvalError::Syntax {
+                pos: 2,
+                expected: EXPECTED_OPERATOR
             }
+            .to_string(),
+            "Expected an operator at column 3"
+        );
+        assert!(EvalError::MissingParen { pos: 0 }.is_syntax());
+        assert!(!EvalError::DivisionByZero.is_syntax());
+        assert_eq!(
+            suggest(&arity("mod", 2, 1).unwrap_err(), "mod(1)").as_deref(),
+            Some("separate arguments with commas")
+        );
+    }
+
+    #[test]
+    fn percent_follows_the_configured_behavior() {
+        let mut calc = CalculatorModule::new();
+        let mut eval = |behavior, expression: &str| {
+            calc.percent_behavior = behavior;
+            calc.evaluate_str(expression)
+                .ok()
+                .and_then(|outcome| outcome.as_f64())
+        };
+        use PercentBehavior::{Contextual, Modulo, Percent};
+        for (behavior, expression, expected) in [
+            (Contextual, "50%", Some(0.5)),
+            (Contextual, "50%8", Some(2.0)),
+            (Contextual, "50+10%", Some(55.0)),
+            (Contextual, "50-10%", Some(45.0)),
+            (Contextual, "50*10%", Some(5.0)),
+            (Contextual, "50+10%*2", Some(50.2)),
+            (Contextual, "(50+10%)*2", Some(110.0)),
+            (Contextual, "-10%", Some(-0.1)),
+            (Modulo, "50%8", Some(2.0)),
+            (Modulo, "50%", None),
+            (Percent, "50+10%", Some(50.1)),
+            (Percent, "50%8", None),
+            (Modulo, "percent(50)", Some(0.5)),
+            (Modulo, "5%o", Some(0.005)),
+            (Percent, "2*5%o", Some(0.01)),
+        ] {
+            assert_eq!(
+                eval(behavior, expression),
+                expected,
+                "{} ({:?})",
+                expression,
+                behavior
+            );
+        }
+    }
+
+    #[test]
+    fn functions_reject_arguments_without_a_real_result() {
+        let calc = CalculatorModule::new();
+        for (expression, func, arg) in [
+            ("sqrt(-1)", "sqrt", -1.0),
+            ("ln(-1)", "ln", -1.0),
+            ("ln(0)", "ln", 0.0),
+            ("log(-0.5)", "log", -0.5),
+            ("log(2, 0)", "log", 0.0),
+            ("log(1, 5)", "log", 1.0),
+            ("asin(2)", "asin", 2.0),
+            ("acos(-1.5)", "acos", -1.5),
+        ] {
+            assert_eq!(
+                calc.evaluate_str(expression),
+                Err(EvalError::undefined(func, arg)),
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(
+            calc.evaluate_str("sqrt(-4)").unwrap_err().to_string(),
+            "sqrt() is undefined for -4"
+        );
+        // The edges of each domain are still in it
+        for (expression, expected) in [("sqrt(0)", "0"), ("ln(1)", "0"), ("acos(1)", "0")] {
+            assert_eq!(calc.evaluate_str(expression).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn results_f64_cant_hold_are_errors() {
+        let calc = CalculatorModule::new();
+        let message = |expression| calc.evaluate_str(expression).unwrap_err().to_string();
+        assert_eq!(
+            message("(-8)^(1/3)"),
+            "A negative number can only be raised to a whole power, not 0.3333333333"
+        );
+        assert_eq!(
+            message("(-1)^0.5"),
+            "A negative number can only be raised to a whole power, not 0.5"
+        );
+        assert_eq!(calc.evaluate_str("0^-1"), Err(EvalError::DivisionByZero));
+        assert_eq!(
+            message("10^400"),
+            "Too large to calculate with (≈ 1.0000e+400)"
+        );
+        assert_eq!(
+            message("exp(1000)"),
+            "Too large to calculate with (≈ 1.9701e+434)"
+        );
+        assert_eq!(
+            message("1e308*10"),
+            "Too large to calculate with (≈ 1.0000e+309)"
+        );
+        assert_eq!(message("1e400-1e400"), "- has no result for these values");
+        assert_eq!(
+            message("sum([1e308, 1e308])"),
+            "Too large to calculate with"
+        );
+        // Whole powers of negatives, and powers of 0, still have values
+        for (expression, expected) in [("(-8)^3", "-512"), ("(-2)^-1", "-0.5"), ("0^0", "1")] {
+            assert_eq!(calc.evaluate_str(expression).unwrap().to_string(), expected);
         }
-        _ => Err(anyhow::anyhow!("Unexpected token")),
+    }
+
+    #[test]
+    fn remainder_truncates_and_mod_floors() {
+        let mut calc = CalculatorModule::new();
+        for (expression, expected) in [
+            ("7 % 3", "1"),
+            ("-7 % 3", "-1"),
+            ("7 % -3", "1"),
+            ("-7 % -3", "-1"),
+            ("mod(7, 3)", "1"),
+            ("mod(-7, 3)", "2"),
+            ("mod(7, -3)", "-2"),
+            ("mod(-7, -3)", "-1"),
+            ("mod(-6, 3)", "0"),
+            ("mod(-7.5, 2)", "0.5"),
+        ] {
+            calc.big_integers = false;
+            assert_eq!(calc.evaluate_str(expression).unwrap().to_string(), expected);
+            // Exact integers follow the same conventions
+            calc.big_integers = true;
+            assert_eq!(calc.evaluate_str(expression).unwrap().to_string(), expected);
+        }
+        for expression in ["mod(1, 0)", "7 % 0", "7 // 0"] {
+            assert_eq!(
+                calc.evaluate_str(expression),
+                Err(EvalError::DivisionByZero),
+                "{}",
+                expression
+            );
+        }
+
+        // Floored, `%` agrees with `mod`, for exact integers too
+        calc.modulo_behavior = ModuloBehavior::Floored;
+        for (expression, expected) in [
+            ("-7 % 3", "2"),
+            ("7 % -3", "-2"),
+            ("-7 % -3", "-1"),
+            ("-7.5 % 2", "0.5"),
+        ] {
+            assert_eq!(calc.evaluate_str(expression).unwrap().to_string(), expected);
+        }
+        assert_eq!(calc.evaluate_str("1 % 0"), Err(EvalError::DivisionByZero));
+    }
+
+    #[test]
+    fn approx_and_ulps_compare_floats() {
+        let calc = CalculatorModule::new();
+        for (expression, expected) in [
+            ("approx(sqrt(2)^2, 2)", "1"),
+            ("approx(1, 1.001)", "0"),
+            ("approx(1, 1.001, 0.01)", "1"),
+            ("approx(-1, 1, 3)", "1"),
+            ("approx(0, 10^-12)", "1"),
+            ("approx(0, 10^-6)", "0"),
+            ("ulps(1, 1)", "0"),
+            ("ulps(0, -0)", "0"),
+            ("ulps(sqrt(2)^2, 2)", "1"),
+            ("ulps(-1, 1)", "9.2143648376e18"),
+        ] {
+            assert_eq!(
+                calc.evaluate_str(expression).unwrap().to_string(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+
+        // Denormals are spaced one ulp apart all the way down to zero, on both sides
+        let smallest = f64::from_bits(1);
+        assert_eq!(ulp_distance(0.0, smallest), Ok(1.0));
+        assert_eq!(ulp_distance(-smallest, smallest), Ok(2.0));
+        assert_eq!(ulp_distance(f64::MIN_POSITIVE, 0.0), Ok(2f64.powi(52)));
+        assert_eq!(
+            ulp_distance(-f64::MAX, f64::MAX),
+            Ok(2f64.powi(64) - 2f64.powi(53))
+        );
+        assert!(ulp_distance(f64::MAX, f64::INFINITY).is_err());
+        assert_eq!(
+            calc.evaluate_str("ulps(1e400, 1e308)")
+                .unwrap_err()
+                .to_string(),
+            "ulps() needs finite values, not Infinity"
+        );
+        // Relative tolerance still applies to denormals, though they are coarse
+        assert!(!approx_equal(smallest, 2.0 * smallest, 0.1).unwrap());
+        assert!(approx_equal(smallest, 2.0 * smallest, 0.5).unwrap());
+        assert!(!approx_equal(smallest, -smallest, 0.0).unwrap());
+
+        // Expressions can't produce infinities or NaN, but results passed in can be
+        let inf = f64::INFINITY;
+        assert!(approx_equal(inf, inf, 1e-9).unwrap());
+        assert!(!approx_equal(inf, -inf, 1e300).unwrap());
+        assert!(!approx_equal(f64::MAX, inf, 1.0).unwrap());
+        assert!(!approx_equal(f64::NAN, f64::NAN, 1.0).unwrap());
+        assert!(matches!(
+            ulp_distance(f64::NAN, 1.0),
+            Err(EvalError::Domain { func: "ulps", .. })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("approx(1, 2, -1)"),
+            Err(EvalError::Domain { func: "approx", .. })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("approx(1)"),
+            Err(EvalError::Arity { expected: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn interval_functions_search_an_expression_in_x() {
+        let mut calc = CalculatorModule::new();
+        calc.variables.insert("x".to_string(), 5.0);
+        calc.variables.insert("k".to_string(), 3.0);
+        for (expression, expected) in [
+            ("fmin(sin(x), 0, 2*pi)", "-1"),
+            ("fmax(sin(x), 0, 2*pi)", "1"),
+            ("fmax(x^3, -1, 2)", "8"),
+            ("fmin(x^3, 2, -1)", "-1"),
+            ("fmean(x^3, -1, 2)", "1.25"),
+            // Other names keep their values, and so does x outside the body
+            ("fmin((x - k)^2 + 1, 0, x)", "1"),
+            ("fmax(mod(x, 1), 0, 1) + 1", "2"),
+        ] {
+            assert_eq!(
+                calc.evaluate_str(expression).unwrap().to_string(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(calc.variables["x"], 5.0);
+        // Zero but for rounding error, which shows at this magnitude
+        assert!(matches!(
+            calc.evaluate_str("fmean(sin(x), 0, 2*pi)"),
+            Ok(Outcome::Number(mean)) if mean.abs() < 1e-12
+        ));
+
+        assert_eq!(
+            calc.evaluate_str("fmin(1/x, -1, 1)"),
+            Err(EvalError::DivisionByZero)
+        );
+        assert!(matches!(
+            calc.evaluate_str("fmin(x, 1)"),
+            Err(EvalError::Arity { got: 2, .. })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("fmean(x)"),
+            Err(EvalError::Arity { got: 1, .. })
+        ));
+        assert_eq!(
+            calc.evaluate_str("fmin(x, 0"),
+            Err(EvalError::MissingParen { pos: 9 })
+        );
+    }
+
+    #[test]
+    fn sum_and_prod_count_over_a_bound_name() {
+        let mut calc = CalculatorModule::new();
+        calc.variables.insert("k".to_string(), 100.0);
+        calc.variables.insert("n".to_string(), 5.0);
+        for (expression, expected) in [
+            ("sum(k, 1, 100, k^2)", "338350"),
+            ("prod(k, 1, n, k)", "120"),
+            ("sum(i, 0, 10, 2^-i)", "1.9990234375"),
+            // Nested counters, and the outer variable of the same name left alone
+            ("sum(i, 1, 3, prod(j, 1, i, j))", "9"),
+            ("sum(k, 1, 3, k) + k", "106"),
+            // Counting up from past the end adds or multiplies nothing
+            ("sum(k, 5, 1, k)", "0"),
+            ("prod(k, 5, 1, k)", "1"),
+        ] {
+            assert_eq!(
+                calc.evaluate_str(expression).unwrap().to_string(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(calc.variables["k"], 100.0);
+
+        assert!(matches!(
+            calc.evaluate_str("sum(k, 1, 2.5, k)"),
+            Err(EvalError::Domain {
+                func: "sum",
+                arg: 2.5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("prod(k, 1, 1e9, k)"),
+            Err(EvalError::Domain { func: "prod", .. })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("sum(2, 1, 3, k)"),
+            Err(EvalError::Syntax { pos: 4, .. })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("sum(k, 1, 3)"),
+            Err(EvalError::Arity { got: 3, .. })
+        ));
+        assert_eq!(free_variables("sum(k, 1, n, k*x) + k"), ["n", "x", "k"]);
+        assert_eq!(
+            hint_for("sum(", AngleMode::Radians, PercentBehavior::Modulo).as_deref(),
+            Some("sum takes a counter, from, to, then an expression: k, 1, 10, k^2")
+        );
+    }
+
+    #[test]
+    fn solve_finds_where_an_expression_is_zero() {
+        let mut calc = CalculatorModule::new();
+        calc.variables.insert("x".to_string(), 7.0);
+        calc.variables.insert("a".to_string(), 9.0);
+        let root = |expression: &str| match calc.evaluate_str(expression) {
+            Ok(Outcome::Number(value)) => value,
+            other => panic!("{}: {:?}", expression, other),
+        };
+        assert!((root("solve(x^2 - 2, x)") - 2f64.sqrt()).abs() < 1e-12);
+        assert!((root("solve(x^2 - 2, x, -3)") + 2f64.sqrt()).abs() < 1e-12);
+        // Within a bracket, and with other variables at their values
+        assert!((root("solve(cos(t), t, 3, 6)") - 1.5 * std::f64::consts::PI).abs() < 1e-12);
+        assert!((root("solve(y^2 - a, y, 0, 10)") - 3.0).abs() < 1e-12);
+        // Undefined points are passed over, and Newton's method finds touching roots
+        assert!((root("solve(1/x - 2, x)") - 0.5).abs() < 1e-12);
+        assert!((root("solve((x - 1)^2, x, 4)") - 1.0).abs() < 1e-6);
+        assert_eq!(calc.variables["x"], 7.0);
+
+        assert_eq!(
+            calc.evaluate_str("solve(x^2 + 1, x)"),
+            Err(EvalError::NoRoot)
+        );
+        assert_eq!(
+            calc.evaluate_str("solve(x - 5, x, 0, 1)"),
+            Err(EvalError::NoRoot)
+        );
+        assert!(suggest(&EvalError::NoRoot, "").is_some());
+        assert!(matches!(
+            calc.evaluate_str("solve(x - 5)"),
+            Err(EvalError::Arity { got: 1, .. })
+        ));
+        assert!(matches!(
+            calc.evaluate_str("solve(x - 5, 2)"),
+            Err(EvalError::Syntax { .. })
+        ));
+        assert_eq!(free_variables("solve(t^2 - a, t) + t"), ["a", "t"]);
+        assert_eq!(free_variables("sum(k, 1, n, k*x) + k"), ["n", "x", "k"]);
+    }
+
+    #[test]
+    fn sig_figs_round_for_display_only() {
+        for (value, figures, expected) in [
+            (123456.0, 3, "123000"),
+            (1234.5678, 6, "1234.57"),
+            (0.000123456, 2, "0.00012"),
+            (1.5e-7, 2, "1.5e-7"),
+            (6.02214076e23, 4, "6.022e23"),
+            (-42.195, 3, "-42.2"),
+            (0.0, 3, "0.00"),
+            (0.0, 1, "0"),
+            (9.99, 2, "10"),
+            (9.996, 3, "10.0"),
+            (99.95001, 3, "100"),
+            // Ties go to the even digit
+            (0.125, 2, "0.12"),
+            (0.375, 2, "0.38"),
+            (2.5, 1, "2"),
+        ] {
+            assert_eq!(format_sig_figs(value, figures), expected, "{}", value);
+        }
+
+        let mut calc = CalculatorModule::new();
+        calc.sig_figs = Some(3);
+        calc.set_expression("2/3".to_string());
+        calc.calculate();
+        assert_eq!(calc.displayed_result(), ("0.667".to_string(), true));
+        assert_eq!(calc.last_value, Some(2.0 / 3.0));
+        assert_eq!(
+            calc.evaluate_str("sigfig(2/3, 2)").unwrap().to_string(),
+            "0.67"
+        );
+        assert_eq!(
+            calc.evaluate_str("sigfig(1, 0)").unwrap_err().to_string(),
+            "sigfig() needs a whole number of figures from 1 to 17"
+        );
+    }
+
+    #[test]
+    fn extreme_results_are_shown_in_scientific_notation() {
+        for (value, shown) in [
+            (1e-12, "1e-12"),
+            (-2.5e-7, "-2.5e-7"),
+            (0.000_001, "0.000001"),
+            (123_456_789_012_345.0, "123456789012345"),
+            (1e15, "1e15"),
+            (1e300, "1e300"),
+            (-1.234_567_890_123_4e20, "-1.2345678901e20"),
+            (0.0, "0"),
+        ] {
+            assert_eq!(format_result(value), shown);
+        }
+        // What is shown reads back as the number
+        let calc = CalculatorModule::new();
+        assert_eq!(calc.evaluate_str("1e-12 * 3").unwrap().to_string(), "3e-12");
+    }
+
+    #[test]
+    fn result_formats_change_only_the_display() {
+        for (value, scientific, engineering) in [
+            (1234500.0, "1.2345e6", "1.2345e6"),
+            (12345.0, "1.2345e4", "12.345e3"),
+            (100000.0, "1e5", "100e3"),
+            (-0.00045, "-4.5e-4", "-450e-6"),
+            (0.1 + 0.2, "3e-1", "300e-3"),
+            (0.0, "0e0", "0e0"),
+            (f64::INFINITY, "Infinity", "Infinity"),
+        ] {
+            assert_eq!(format_scientific(value, None), scientific);
+            assert_eq!(format_engineering(value, None), engineering);
+        }
+        assert_eq!(format_scientific(2.0 / 3.0, Some(3)), "6.67e-1");
+        assert_eq!(format_engineering(12345.0, Some(2)), "12e3");
+        assert_eq!(format_fraction(0.375).as_deref(), Some("3/8"));
+        assert_eq!(format_fraction(-1.0 / 3.0).as_deref(), Some("-1/3"));
+        assert_eq!(format_fraction(4.0).as_deref(), Some("4"));
+        assert_eq!(format_fraction(0.1 + 0.2).as_deref(), Some("3/10"));
+        assert_eq!(format_fraction(std::f64::consts::PI), None);
+        assert_eq!(format_fraction(2f64.sqrt()), None);
+
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("1/8 + 1/4".to_string());
+        calc.calculate();
+        let mut shown = Vec::new();
+        for _ in 0..4 {
+            calc.cycle_result_format();
+            shown.push(calc.displayed_result().0);
+        }
+        assert_eq!(shown, ["3.75e-1", "375e-3", "3/8", "0.375"]);
+        assert_eq!(calc.result_format, ResultFormat::Decimal);
+        assert_eq!(calc.current_result, "0.375");
+        // A value with no simple fraction stays in decimal
+        calc.result_format = ResultFormat::Fraction;
+        calc.set_expression("pi".to_string());
+        calc.calculate();
+        assert_eq!(calc.displayed_result().0, calc.current_result);
+    }
+
+    #[test]
+    fn digit_grouping_shows_thousands_in_results() {
+        assert_eq!(
+            group_digits("-1234567.891", DigitGrouping::Comma),
+            "-1,234,567.891"
+        );
+        assert_eq!(
+            group_digits("1234567.891", DigitGrouping::Period),
+            "1.234.567,891"
+        );
+        assert_eq!(group_digits("123", DigitGrouping::Comma), "123");
+        assert_eq!(group_digits("12345/7", DigitGrouping::Comma), "12,345/7");
+        // Exponents, fractions of a point and hex digits keep theirs
+        assert_eq!(
+            group_digits("1.2345e1000", DigitGrouping::Comma),
+            "1.2345e1000"
+        );
+        assert_eq!(group_digits("0x12345", DigitGrouping::Comma), "0x12345");
+        assert_eq!(group_digits("1234567", DigitGrouping::Off), "1234567");
+
+        let mut calc = CalculatorModule::new();
+        calc.digit_grouping = DigitGrouping::Comma;
+        calc.set_expression("1_234_567 * 2 + 0.5".to_string());
+        calc.calculate();
+        assert_eq!(calc.displayed_result().0, "2,469,134.5");
+        // The result itself stays plain, so it reads back under any grouping
+        assert_eq!(calc.current_result, "2469134.5");
+        // Typed numbers aren't read with the separators, which could mean either
+        calc.set_expression("max(1,234)".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "234");
+        // Exact integers keep every digit
+        calc.big_integers = true;
+        calc.set_expression("123_456_789_012_345_678_901 + 1".to_string());
+        calc.calculate();
+        assert_eq!(calc.current_result, "123456789012345678902");
+        assert_eq!(calc.displayed_result().0, "123,456,789,012,345,678,902");
+    }
+
+    #[test]
+    fn comparisons_are_not_assignments() {
+        let mut calc = CalculatorModule::new();
+        calc.set_expression("x = 2; x == 2; x!=2".to_string());
+        let results = calc.calculate();
+        assert_eq!(results.len(), 3);
+        assert_eq!(calc.variables.get("x"), Some(&2.0));
+        assert_eq!(calc.current_result, "0");
+        assert_eq!(calc.history[1].expression, "x == 2");
+        assert_eq!(
+            normalize("not x<0 and y>=1", PercentBehavior::Modulo).as_deref(),
+            Some("not x < 0 and y >= 1")
+        );
+    }
+
+    #[test]
+    fn cancellation_is_flagged_on_calculate() {
+        let mut calc = CalculatorModule::new();
+        let mut reliable = |expression: &str| {
+            calc.set_expression(expression.to_string());
+            calc.calculate();
+            assert!(calc.error_message.is_none(), "{}", expression);
+            calc.reliable_digits
+        };
+        // 1+10^-13 keeps only the first few digits of 10^-13, which the 1 then cancels
+        assert_eq!(reliable("(1+10^-13)-1"), Some(2));
+        assert_eq!(reliable("0.1+0.2-0.3"), Some(0));
+        for stable in [
+            "0.1+0.2",
+            "2+3",
+            "1-1",
+            "10^15+1-10^15",
+            "1-0.999",
+            "sin(pi)",
+            "1/3",
+        ] {
+            assert_eq!(reliable(stable), None, "{}", stable);
+        }
+
+        // Only calculating checks, and editing drops the flag
+        calc.set_expression("(1+10^-13)-1".to_string());
+        calc.update_result();
+        assert_eq!(calc.reliable_digits, None);
+        calc.calculate();
+        assert_eq!(calc.history.last().unwrap().reliable_digits, Some(2));
+        calc.append_digit('5');
+        assert_eq!(calc.reliable_digits, None);
+
+        calc.cancellation_check = false;
+        calc.set_expression("(1+10^-13)-1".to_string());
+        calc.calculate();
+        assert_eq!(calc.reliable_digits, None);
     }
 }
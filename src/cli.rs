@@ -0,0 +1,307 @@
+//! Command-line arguments, and the modes that run without the TUI.
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::calculator::{AngleMode, CalculatorMode};
+use crate::graph::{self, Charset, GraphModule};
+use crate::share;
+
+pub const USAGE: &str = "usage: tcalc [-e EXPR] [--mode basic|scientific|programmer] [--angle rad|deg]
+       tcalc --plot EXPR [--size WxH] [--xrange MIN:MAX] [--yrange MIN:MAX] [--charset unicode|ascii|braille] [--labels]";
+
+/// What to run, from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// The interactive calculator.
+    Tui(StartOptions),
+    /// Print a plot to stdout and exit.
+    Plot(PlotOptions),
+}
+
+/// State the interactive calculator starts in.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StartOptions {
+    /// Loaded for editing in Typing mode, not evaluated into history.
+    pub expression: Option<String>,
+    pub mode: Option<CalculatorMode>,
+    pub angle_mode: Option<AngleMode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotOptions {
+    pub expression: String,
+    pub width: usize,
+    pub height: usize,
+    pub x_range: (f64, f64),
+    /// Fitted to the curve when not given.
+    pub y_range: Option<(f64, f64)>,
+    pub charset: Charset,
+    /// Print the range ends beside and below the plot.
+    pub labels: bool,
+}
+
+impl PlotOptions {
+    fn new(expression: String) -> Self {
+        Self {
+            expression,
+            width: 80,
+            height: 24,
+            x_range: (-10.0, 10.0),
+            y_range: None,
+            charset: Charset::Unicode,
+            labels: false,
+        }
+    }
+}
+
+/// Parse the arguments after the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command> {
+    let mut args = args.into_iter();
+    let mut start = StartOptions::default();
+    let mut plot: Option<PlotOptions> = None;
+    let mut size = None;
+    let mut x_range = None;
+    let mut y_range = None;
+    let mut charset = None;
+    let mut labels = false;
+
+    while let Some(arg) = args.next() {
+        // Values may start with '-' (a negative range), so always take the next argument
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| anyhow!("{} needs a value\n{}", name, USAGE))
+        };
+        match arg.as_str() {
+            "-e" | "--expression" => start.expression = Some(value(&arg)?),
+            "--mode" => start.mode = Some(share::parse_mode(&value("--mode")?)?),
+            "--angle" => start.angle_mode = Some(share::parse_angle(&value("--angle")?)?),
+            "--plot" => plot = Some(PlotOptions::new(value("--plot")?)),
+            "--size" => size = Some(parse_size(&value("--size")?)?),
+            "--xrange" => x_range = Some(parse_range("--xrange", &value("--xrange")?)?),
+            "--yrange" => y_range = Some(parse_range("--yrange", &value("--yrange")?)?),
+            "--charset" => {
+                let name = value("--charset")?;
+                charset = Some(Charset::from_name(&name).ok_or_else(|| {
+                    anyhow!("--charset must be unicode, ascii or braille, not {}", name)
+                })?);
+            }
+            "--labels" => labels = true,
+            _ => bail!("unknown argument {}\n{}", arg, USAGE),
+        }
+    }
+
+    let Some(mut options) = plot else {
+        if size.is_some() || x_range.is_some() || y_range.is_some() || charset.is_some() || labels {
+            bail!("plot options need --plot\n{}", USAGE);
+        }
+        return Ok(Command::Tui(start));
+    };
+    if start != StartOptions::default() {
+        bail!("-e, --mode and --angle start the calculator, so they can't go with --plot");
+    }
+    if let Some((width, height)) = size {
+        options.width = width;
+        options.height = height;
+    }
+    if let Some(range) = x_range {
+        options.x_range = range;
+    }
+    options.y_range = y_range;
+    options.charset = charset.unwrap_or_default();
+    options.labels = labels;
+    Ok(Command::Plot(options))
+}
+
+fn parse_size(text: &str) -> Result<(usize, usize)> {
+    text.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w >= 2 && h >= 2)
+        .ok_or_else(|| anyhow!("--size must be WIDTHxHEIGHT, at least 2x2, not {}", text))
+}
+
+fn parse_range(name: &str, text: &str) -> Result<(f64, f64)> {
+    text.split_once(':')
+        .and_then(|(lo, hi)| Some((lo.trim().parse().ok()?, hi.trim().parse().ok()?)))
+        .filter(|&(lo, hi): &(f64, f64)| lo.is_finite() && hi.is_finite() && lo < hi)
+        .ok_or_else(|| anyhow!("{} must be MIN:MAX with MIN < MAX, not {}", name, text))
+}
+
+/// Render `options.expression` as text, one line per row.
+pub fn plot(options: &PlotOptions) -> Result<String> {
+    let mut graph = GraphModule::new();
+    (graph.x_min, graph.x_max) = options.x_range;
+
+    // Leave room for the labels: the y range ends on the left, the x range ends below
+    let (gutter, rows) = if options.labels {
+        (10, options.height.saturating_sub(1))
+    } else {
+        (0, options.height)
+    };
+    let columns = options.width.saturating_sub(gutter);
+    if columns < 2 || rows < 2 {
+        bail!("{}x{} is too small to plot", options.width, options.height);
+    }
+    // Braille fits two dots across each cell
+    let samples = match options.charset {
+        Charset::Braille => columns * 2,
+        _ => columns,
+    };
+    let samples = u16::try_from(samples).map_err(|_| anyhow!("plot is too wide"))?;
+
+    let (y_min, y_max) = match options.y_range {
+        Some(range) => range,
+        None => {
+            // Sample with an open y range, then fit it to what came back
+            (graph.y_min, graph.y_max) = (f64::NEG_INFINITY, f64::INFINITY);
+            graph.generate_points(&options.expression, samples, rows as u16)?;
+            let (lo, hi) = graph
+                .points
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+                    (lo.min(p.y), hi.max(p.y))
+                });
+            if lo > hi {
+                return Err(undefined_error(&graph, options));
+            }
+            let pad = if hi - lo > 0.0 { (hi - lo) * 0.05 } else { 1.0 };
+            (lo - pad, hi + pad)
+        }
+    };
+    (graph.y_min, graph.y_max) = (y_min, y_max);
+    graph.generate_points(&options.expression, samples, rows as u16)?;
+    // Only a curve in x has a domain; one traced or drawn where it's 0 just has points
+    if graph.points.is_empty() && graph.domain.iter().all(|interval| !interval.defined) {
+        return Err(undefined_error(&graph, options));
+    }
+
+    let mut grid = Vec::new();
+    graph::draw_grid(&mut grid, columns, rows, &graph, options.charset, None);
+
+    let mut out = String::new();
+    for (row, cells) in grid.chunks(columns).enumerate() {
+        if options.labels {
+            let label = match row {
+                0 => format_label(y_max),
+                _ if row == rows - 1 => format_label(y_min),
+                _ => String::new(),
+            };
+            out.push_str(&format!("{:>w$} ", label, w = gutter - 1));
+        }
+        out.extend(cells);
+        out.push('\n');
+    }
+    if options.labels {
+        let (lo, hi) = (format_label(graph.x_min), format_label(graph.x_max));
+        let fill = columns.saturating_sub(lo.len() + hi.len()).max(1);
+        out.push_str(&format!("{:gutter$}{}{:fill$}{}\n", "", lo, "", hi));
+    }
+    Ok(out)
+}
+
+/// The evaluator's own complaint when there is one, so a typo reads as a typo.
+fn undefined_error(graph: &GraphModule, options: &PlotOptions) -> anyhow::Error {
+    let x = (options.x_range.0 + options.x_range.1) / 2.0;
+    let first = graph::curves(&options.expression)
+        .next()
+        .unwrap_or_default();
+    if graph::polar(first).is_some() || graph::is_parametric(first) || graph::is_implicit(first) {
+        return anyhow!("{} has no points in view", options.expression);
+    }
+    match graph.evaluate_at(x, &options.expression) {
+        Err(e) => e.into(),
+        Ok(_) => anyhow!(
+            "{} is undefined for x in [{}, {}]",
+            options.expression,
+            graph.x_min,
+            graph.x_max
+        ),
+    }
+}
+
+fn format_label(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0" } else { text }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn start_options_preset_the_calculator() {
+        assert_eq!(
+            parse(args("-e 340*1.21 --mode scientific --angle deg")).unwrap(),
+            Command::Tui(StartOptions {
+                expression: Some("340*1.21".to_string()),
+                mode: Some(CalculatorMode::Scientific),
+                angle_mode: Some(AngleMode::Degrees),
+            })
+        );
+        assert!(parse(args("--mode fancy")).is_err());
+        assert!(parse(args("-e")).is_err());
+        assert!(parse(args("--plot x --angle deg")).is_err());
+    }
+
+    #[test]
+    fn plot_arguments_parse_and_render() {
+        assert_eq!(
+            parse(args("")).unwrap(),
+            Command::Tui(StartOptions::default())
+        );
+        let Command::Plot(options) =
+            parse(args("--plot x --size 21x5 --xrange -1:1 --charset ascii")).unwrap()
+        else {
+            panic!("expected a plot");
+        };
+        assert_eq!(options.x_range, (-1.0, 1.0));
+        assert_eq!((options.width, options.height), (21, 5));
+
+        // A line through the origin, from bottom left to top right
+        let rows: Vec<String> = plot(&options).unwrap().lines().map(String::from).collect();
+        assert_eq!(rows.len(), 5);
+        assert!(rows[0].trim_end().ends_with('*') && rows[4].starts_with('*'));
+        assert_eq!(rows[2].chars().nth(10), Some('*'));
+
+        for bad in ["--size 80by24", "--xrange 2:1", "--charset emoji"] {
+            assert!(
+                parse(args(&format!("--plot x {}", bad))).is_err(),
+                "{}",
+                bad
+            );
+        }
+        assert!(parse(args("--labels")).is_err());
+        let typo = parse(args("--plot sinn(x)")).unwrap();
+        let Command::Plot(options) = typo else {
+            unreachable!()
+        };
+        assert!(plot(&options).is_err());
+
+        // Curves that aren't functions of x fit the y range to their points too
+        let Command::Plot(options) =
+            parse(args("--plot x --size 21x5 --xrange -1:1 --charset ascii")).unwrap()
+        else {
+            unreachable!()
+        };
+        for expression in ["x^2 + y^2 - 0.25", "[cos(t)/2, sin(t)/2]", "r = 0.5"] {
+            let options = PlotOptions {
+                expression: expression.to_string(),
+                ..options.clone()
+            };
+            let text = plot(&options).unwrap();
+            assert!(text.contains('*'), "{}", expression);
+        }
+        let options = PlotOptions {
+            expression: "x^2 + y^2 + 1".to_string(),
+            ..options
+        };
+        assert_eq!(
+            plot(&options).unwrap_err().to_string(),
+            "x^2 + y^2 + 1 has no points in view"
+        );
+    }
+}
@@ -0,0 +1,17 @@
+use std::io::{self, Write};
+
+use crate::share::base64_encode;
+
+/// Copy text to the system clipboard using the OSC 52 terminal escape sequence.
+///
+/// Most modern terminals (and tmux with `set-clipboard on`) honor it; on terminals that
+/// don't, the sequence is silently ignored.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(
+        stdout,
+        "\x1b]52;c;{}\x07",
+        base64_encode(text.as_bytes(), false)
+    )?;
+    stdout.flush()
+}
@@ -0,0 +1,615 @@
+use anyhow::Result;
+
+use crate::bigint;
+use crate::calculator::{
+    AngleMode, CalculatorMode, DigitGrouping, ModuloBehavior, PercentBehavior, ResultFormat,
+};
+use crate::glyphs::GlyphSet;
+use crate::keymap::Chord;
+use crate::palette::Command;
+use crate::programmer::WordSize;
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+/// User settings read from `$XDG_CONFIG_HOME/tcalc/config` (or `~/.config/tcalc/config`).
+///
+/// The file holds `key = value` lines; blank lines and lines starting with `#` are
+/// skipped, and unknown keys are ignored so older builds can read newer files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// How long an arrow key must be held before it starts repeating.
+    pub nav_initial_delay_ms: u64,
+    /// Time between repeats once a held arrow key is repeating.
+    pub nav_repeat_interval_ms: u64,
+    /// Ask before quitting with an unevaluated expression or unsaved history.
+    pub confirm_quit: bool,
+    /// Height of a terminal cell divided by its width, used by the graph aspect lock.
+    pub cell_aspect_ratio: f64,
+    /// Animation frame rate; the clock only runs while something is animating.
+    pub tick_rate_hz: u64,
+    /// Blink the Typing mode cursor.
+    pub cursor_blink: bool,
+    /// How `%` is read in expressions.
+    pub percent_behavior: PercentBehavior,
+    /// Which sign `%` between operands takes.
+    pub modulo_behavior: ModuloBehavior,
+    /// Longest exact integer big-integer mode works with before falling back to f64.
+    pub max_digits: usize,
+    /// Show results rounded to this many significant figures; 0 shows them in full.
+    pub sig_figs: usize,
+    /// How results are written out.
+    pub result_format: ResultFormat,
+    /// Separators between a shown result's thousands; typed numbers don't use them.
+    pub digit_grouping: DigitGrouping,
+    /// Use the keypad-free mini layout at any terminal size, not just small ones.
+    pub mini_layout: bool,
+    /// Chart the trend of recent results in the title bar.
+    pub sparkline: bool,
+    /// List history expressions with canonical spacing rather than as typed.
+    pub normalize_history: bool,
+    /// Suggest what can come next while typing an expression.
+    pub typing_hints: bool,
+    /// Keep a dragged graph gliding briefly after the mouse button is released.
+    pub graph_momentum: bool,
+    /// Draw gridlines across the graph at its axis ticks.
+    pub graph_gridlines: bool,
+    /// Calculations and graph redraws slower than this show how long they took.
+    pub slow_calculation_ms: u64,
+    /// Symbols for the keypad and help; `None` picks them from the locale.
+    pub glyphs: Option<GlyphSet>,
+    /// After a palette command or button click, mention the key that does it directly.
+    pub shortcut_tips: bool,
+    /// Keys typed as another key in Typing mode, for symbols a keyboard layout makes
+    /// hard to reach; later pairs override earlier ones for the same key.
+    pub typing_aliases: Vec<(char, char)>,
+    /// Keys bound to palette commands in place of their defaults, from `bind` lines.
+    pub key_bindings: Vec<(Command, Vec<Chord>)>,
+    /// Flag calculated results that lost digits to subtracting nearly equal values.
+    pub cancellation_check: bool,
+    /// Seed for `rand` and the like, so each session draws the same numbers; `None`
+    /// seeds from the clock.
+    pub random_seed: Option<u64>,
+}
+
+/// Alternate spellings accepted without any configuration: `:` and `÷` divide, `×` and
+/// `·` multiply, and the spacing circumflex some layouts send for `^` is a power.
+pub const DEFAULT_TYPING_ALIASES: &[(char, char)] =
+    &[(':', '/'), ('÷', '/'), ('×', '*'), ('·', '*'), ('ˆ', '^')];
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            nav_initial_delay_ms: 250,
+            nav_repeat_interval_ms: 80,
+            confirm_quit: true,
+            cell_aspect_ratio: 2.0,
+            tick_rate_hz: 30,
+            cursor_blink: true,
+            percent_behavior: PercentBehavior::Modulo,
+            modulo_behavior: ModuloBehavior::Truncated,
+            max_digits: bigint::MAX_DIGITS,
+            sig_figs: 0,
+            result_format: ResultFormat::Decimal,
+            digit_grouping: DigitGrouping::Off,
+            mini_layout: false,
+            sparkline: false,
+            normalize_history: true,
+            typing_hints: true,
+            graph_momentum: false,
+            graph_gridlines: false,
+            slow_calculation_ms: 50,
+            glyphs: None,
+            shortcut_tips: true,
+            typing_aliases: DEFAULT_TYPING_ALIASES.to_vec(),
+            key_bindings: Vec::new(),
+            cancellation_check: true,
+            random_seed: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("tcalc").join("config"))
+    }
+
+    /// `session` beside the config file, where macros are kept between runs.
+    pub fn session_path() -> Option<PathBuf> {
+        Self::path().map(|path| path.with_file_name("session"))
+    }
+
+    /// The config and session files that exist, which [`Self::back_up`] would move.
+    pub fn files() -> Vec<PathBuf> {
+        [Self::path(), Self::session_path()]
+            .into_iter()
+            .flatten()
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// Move the config and session files aside to `config.bak` and `session.bak`, so
+    /// the defaults apply from the next start. Returns where each file that was there
+    /// went.
+    pub fn back_up() -> Result<Vec<PathBuf>> {
+        move_aside([Self::path(), Self::session_path()].into_iter().flatten())
+    }
+
+    /// Load the config file, falling back to the defaults when there isn't one.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                Self::parse(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::anyhow!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("line {}: expected key = value", number + 1))?;
+            let value = value.trim();
+            let parse_ms = |value: &str| {
+                value.parse::<u64>().map_err(|_| {
+                    anyhow::anyhow!("line {}: {} must be milliseconds", number + 1, key.trim())
+                })
+            };
+            let parse_bool = |value: &str| match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(anyhow::anyhow!(
+                    "line {}: {} must be true or false",
+                    number + 1,
+                    key.trim()
+                )),
+            };
+            match key.trim() {
+                "nav_initial_delay_ms" => config.nav_initial_delay_ms = parse_ms(value)?,
+                "nav_repeat_interval_ms" => config.nav_repeat_interval_ms = parse_ms(value)?,
+                "slow_calculation_ms" => config.slow_calculation_ms = parse_ms(value)?,
+                "confirm_quit" => config.confirm_quit = parse_bool(value)?,
+                "cursor_blink" => config.cursor_blink = parse_bool(value)?,
+                "mini_layout" => config.mini_layout = parse_bool(value)?,
+                "sparkline" => config.sparkline = parse_bool(value)?,
+                "normalize_history" => config.normalize_history = parse_bool(value)?,
+                "typing_hints" => config.typing_hints = parse_bool(value)?,
+                "graph_momentum" => config.graph_momentum = parse_bool(value)?,
+                "graph_gridlines" => config.graph_gridlines = parse_bool(value)?,
+                "shortcut_tips" => config.shortcut_tips = parse_bool(value)?,
+                "cancellation_check" => config.cancellation_check = parse_bool(value)?,
+                "typing_aliases" => {
+                    // Pairs like `x* p^`: the key, then what it types
+                    for pair in value.split_whitespace() {
+                        let mut chars = pair.chars();
+                        let (Some(key), Some(typed), None) =
+                            (chars.next(), chars.next(), chars.next())
+                        else {
+                            return Err(anyhow::anyhow!(
+                                "line {}: typing_aliases are pairs of a key and what it types, like x*",
+                                number + 1
+                            ));
+                        };
+                        config.typing_aliases.push((key, typed));
+                    }
+                }
+                "bind" => {
+                    // A command and the keys that run it, like `bind = graph Ctrl+t F5`
+                    let mut words = value.split_whitespace();
+                    let command = words.next().and_then(Command::from_name).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "line {}: bind takes a command name, such as graph, then its keys",
+                            number + 1
+                        )
+                    })?;
+                    let chords = words
+                        .map(|keys| {
+                            Chord::parse(keys).ok_or_else(|| {
+                                anyhow::anyhow!("line {}: {} is not a key", number + 1, keys)
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    config.key_bindings.push((command, chords));
+                }
+                "percent_behavior" => {
+                    config.percent_behavior =
+                        PercentBehavior::from_name(value).ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "line {}: percent_behavior must be modulo, percent or contextual",
+                                number + 1
+                            )
+                        })?
+                }
+                "modulo_behavior" => {
+                    config.modulo_behavior = ModuloBehavior::from_name(value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "line {}: modulo_behavior must be truncated or floored",
+                            number + 1
+                        )
+                    })?
+                }
+                "result_format" => {
+                    config.result_format = ResultFormat::from_name(value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "line {}: result_format must be decimal, scientific, engineering or fraction",
+                            number + 1
+                        )
+                    })?
+                }
+                "digit_grouping" => {
+                    config.digit_grouping = DigitGrouping::from_name(value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "line {}: digit_grouping must be off, comma or period",
+                            number + 1
+                        )
+                    })?
+                }
+                "glyphs" => {
+                    config.glyphs = match value {
+                        "auto" => None,
+                        _ => Some(GlyphSet::from_name(value).ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "line {}: glyphs must be auto, unicode or ascii",
+                                number + 1
+                            )
+                        })?),
+                    }
+                }
+                "tick_rate_hz" => {
+                    config.tick_rate_hz = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&rate| (1..=1000).contains(&rate))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "line {}: tick_rate_hz must be between 1 and 1000",
+                                number + 1
+                            )
+                        })?
+                }
+                "max_digits" => {
+                    config.max_digits = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&digits| (1..=1_000_000).contains(&digits))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "line {}: max_digits must be between 1 and 1000000",
+                                number + 1
+                            )
+                        })?
+                }
+                "sig_figs" => {
+                    config.sig_figs = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&figures| figures <= 17)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "line {}: sig_figs must be between 0 (off) and 17",
+                                number + 1
+                            )
+                        })?
+                }
+                "random_seed" => {
+                    config.random_seed = match value {
+                        "none" => None,
+                        _ => Some(value.parse::<u64>().map_err(|_| {
+                            anyhow::anyhow!(
+                                "line {}: random_seed must be a whole number or none",
+                                number + 1
+                            )
+                        })?),
+                    }
+                }
+                "cell_aspect_ratio" => {
+                    config.cell_aspect_ratio = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "line {}: cell_aspect_ratio must be a positive number",
+                                number + 1
+                            )
+                        })?
+                }
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Everything that can be changed while tcalc runs, apart from history, variables and
+/// macros: the config it started with and what the keys and palette have toggled since.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub config: Config,
+    pub mode: CalculatorMode,
+    pub angle_mode: AngleMode,
+    pub word_size: WordSize,
+    pub percent_behavior: PercentBehavior,
+    pub modulo_behavior: ModuloBehavior,
+    pub max_digits: usize,
+    pub sig_figs: Option<usize>,
+    pub result_format: ResultFormat,
+    pub digit_grouping: DigitGrouping,
+    pub big_integers: bool,
+    pub fractions: bool,
+    pub cancellation_check: bool,
+    pub glyphs: GlyphSet,
+    pub second_function_mode: bool,
+    pub aspect_lock: bool,
+    pub show_cursor_coords: bool,
+    pub gridlines: bool,
+}
+
+impl Settings {
+    /// The settings a fresh start with `config` has.
+    pub fn from_config(config: Config) -> Self {
+        Self {
+            mode: CalculatorMode::Basic,
+            angle_mode: AngleMode::Radians,
+            word_size: WordSize::default(),
+            percent_behavior: config.percent_behavior,
+            modulo_behavior: config.modulo_behavior,
+            max_digits: config.max_digits,
+            sig_figs: (config.sig_figs > 0).then_some(config.sig_figs),
+            result_format: config.result_format,
+            digit_grouping: config.digit_grouping,
+            big_integers: false,
+            fractions: false,
+            cancellation_check: config.cancellation_check,
+            glyphs: config.glyphs.unwrap_or_default(),
+            second_function_mode: false,
+            aspect_lock: false,
+            show_cursor_coords: true,
+            gridlines: config.graph_gridlines,
+            config,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::from_config(Config::default())
+    }
+}
+
+/// Rename each of `paths` that exists to the same name with a `.bak` extension,
+/// returning the new names.
+fn move_aside(paths: impl IntoIterator<Item = PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut moved = Vec::new();
+    for path in paths {
+        let backup = path.with_extension("bak");
+        match fs::rename(&path, &backup) {
+            Ok(()) => moved.push(backup),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow::anyhow!("{}: {}", path.display(), e)),
+        }
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Keymap;
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    #[test]
+    fn skips_comments_blank_lines_and_unknown_keys() {
+        let config =
+            Config::parse("# tcalc settings\n\ntheme = dark\nconfirm_quit = false\n").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                confirm_quit: false,
+                ..Config::default()
+            }
+        );
+        assert!(Config::parse("confirm_quit").is_err());
+    }
+
+    #[test]
+    fn millisecond_settings_are_whole_numbers() {
+        let config = Config::parse(
+            "nav_initial_delay_ms = 400\nnav_repeat_interval_ms=30\nslow_calculation_ms = 0",
+        )
+        .unwrap();
+        assert_eq!(config.nav_initial_delay_ms, 400);
+        assert_eq!(config.nav_repeat_interval_ms, 30);
+        assert_eq!(config.slow_calculation_ms, 0);
+        assert!(Config::parse("nav_initial_delay_ms = soon").is_err());
+        assert!(Config::parse("nav_repeat_interval_ms = -5").is_err());
+    }
+
+    #[test]
+    fn switches_are_true_or_false() {
+        type Switch = fn(&Config) -> bool;
+        let switches: &[(&str, Switch)] = &[
+            ("confirm_quit", |c| c.confirm_quit),
+            ("cursor_blink", |c| c.cursor_blink),
+            ("mini_layout", |c| c.mini_layout),
+            ("sparkline", |c| c.sparkline),
+            ("normalize_history", |c| c.normalize_history),
+            ("typing_hints", |c| c.typing_hints),
+            ("graph_momentum", |c| c.graph_momentum),
+            ("graph_gridlines", |c| c.graph_gridlines),
+            ("shortcut_tips", |c| c.shortcut_tips),
+            ("cancellation_check", |c| c.cancellation_check),
+        ];
+        for (key, value) in switches {
+            for on in [true, false] {
+                let config = Config::parse(&format!("{} = {}", key, on)).unwrap();
+                assert_eq!(value(&config), on, "{}", key);
+            }
+            assert!(Config::parse(&format!("{} = yes", key)).is_err(), "{}", key);
+        }
+    }
+
+    #[test]
+    fn typing_aliases_add_to_the_defaults() {
+        let aliases = Keymap::new(&Config::parse("typing_aliases = x* p^  :%").unwrap());
+        assert_eq!(aliases.alias('x'), Some('*'));
+        assert_eq!(aliases.alias('p'), Some('^'));
+        assert_eq!(aliases.alias(':'), Some('%'));
+        assert_eq!(aliases.alias('×'), Some('*'));
+        assert_eq!(Keymap::new(&Config::default()).alias('x'), None);
+        assert!(Config::parse("typing_aliases = x*y").is_err());
+    }
+
+    #[test]
+    fn bind_lines_give_a_command_its_keys() {
+        assert_eq!(
+            Config::parse("bind = graph Ctrl+t F5\nbind = toggle_angle_mode")
+                .unwrap()
+                .key_bindings,
+            [
+                (
+                    Command::Graph,
+                    vec![
+                        Chord::new(KeyCode::Char('t'), KeyModifiers::CONTROL),
+                        Chord::new(KeyCode::F(5), KeyModifiers::NONE)
+                    ]
+                ),
+                (Command::ToggleAngleMode, vec![])
+            ]
+        );
+        assert!(Config::parse("bind = graph Ctrl+Hyper+t").is_err());
+        assert!(Config::parse("bind = plot Ctrl+t").is_err());
+    }
+
+    #[test]
+    fn percent_behavior_is_read_by_name() {
+        assert_eq!(
+            Config::parse("percent_behavior = contextual")
+                .unwrap()
+                .percent_behavior,
+            PercentBehavior::Contextual
+        );
+        assert!(Config::parse("percent_behavior = sometimes").is_err());
+    }
+
+    #[test]
+    fn modulo_behavior_is_read_by_name() {
+        assert_eq!(
+            Config::parse("modulo_behavior = floored")
+                .unwrap()
+                .modulo_behavior,
+            ModuloBehavior::Floored
+        );
+        assert!(Config::parse("modulo_behavior = rounded").is_err());
+    }
+
+    #[test]
+    fn result_format_is_read_by_name() {
+        assert_eq!(
+            Config::parse("result_format = engineering")
+                .unwrap()
+                .result_format,
+            ResultFormat::Engineering
+        );
+        assert!(Config::parse("result_format = roman").is_err());
+    }
+
+    #[test]
+    fn digit_grouping_is_read_by_name() {
+        assert_eq!(
+            Config::parse("digit_grouping = period")
+                .unwrap()
+                .digit_grouping,
+            DigitGrouping::Period
+        );
+        assert!(Config::parse("digit_grouping = space").is_err());
+    }
+
+    #[test]
+    fn glyphs_are_auto_or_a_named_set() {
+        assert_eq!(
+            Config::parse("glyphs = ascii").unwrap().glyphs,
+            Some(GlyphSet::Ascii)
+        );
+        assert_eq!(Config::parse("glyphs = auto").unwrap().glyphs, None);
+        assert!(Config::parse("glyphs = emoji").is_err());
+    }
+
+    #[test]
+    fn tick_rate_is_between_1_and_1000() {
+        assert_eq!(Config::parse("tick_rate_hz = 60").unwrap().tick_rate_hz, 60);
+        assert!(Config::parse("tick_rate_hz = 0").is_err());
+        assert!(Config::parse("tick_rate_hz = 1001").is_err());
+    }
+
+    #[test]
+    fn max_digits_is_between_1_and_a_million() {
+        assert_eq!(Config::parse("max_digits = 50").unwrap().max_digits, 50);
+        assert!(Config::parse("max_digits = 0").is_err());
+        assert!(Config::parse("max_digits = 1000001").is_err());
+    }
+
+    #[test]
+    fn sig_figs_are_0_to_17() {
+        assert_eq!(Config::parse("sig_figs = 3").unwrap().sig_figs, 3);
+        assert!(Config::parse("sig_figs = 18").is_err());
+    }
+
+    #[test]
+    fn random_seed_is_a_whole_number_or_none() {
+        assert_eq!(
+            Config::parse("random_seed = 42").unwrap().random_seed,
+            Some(42)
+        );
+        assert_eq!(
+            Config::parse("random_seed = none").unwrap().random_seed,
+            None
+        );
+        assert!(Config::parse("random_seed = -1").is_err());
+    }
+
+    #[test]
+    fn cell_aspect_ratio_is_positive() {
+        assert_eq!(
+            Config::parse("cell_aspect_ratio = 2.2")
+                .unwrap()
+                .cell_aspect_ratio,
+            2.2
+        );
+        assert!(Config::parse("cell_aspect_ratio = 0").is_err());
+        assert!(Config::parse("cell_aspect_ratio = inf").is_err());
+    }
+
+    #[test]
+    fn backing_up_moves_config_and_session_aside() {
+        let dir = std::env::temp_dir().join(format!("tcalc-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (config, session) = (dir.join("config"), dir.join("session"));
+        fs::write(&config, "mode = basic\n").unwrap();
+        fs::write(&session, "macro_a = 1\n").unwrap();
+
+        let moved = move_aside([config.clone(), session.clone()]).unwrap();
+        assert_eq!(moved, [dir.join("config.bak"), dir.join("session.bak")]);
+        assert!(!config.exists() && !session.exists());
+        assert_eq!(fs::read_to_string(&moved[1]).unwrap(), "macro_a = 1\n");
+        // Files that aren't there are passed over
+        assert_eq!(
+            move_aside([config, session]).unwrap(),
+            Vec::<PathBuf>::new()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
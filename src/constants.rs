@@ -0,0 +1,119 @@
+//! Named physical and mathematical constants, usable by name in expressions and
+//! listed by the constants picker.
+//!
+//! `pi` and `e` are read as numbers by the tokenizer, as they always have been. The
+//! rest are looked up only when no variable has the name, so `c = 3` still makes `c`
+//! a variable.
+
+pub struct Constant {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub value: f64,
+    /// SI unit of the value, empty for pure numbers.
+    pub unit: &'static str,
+}
+
+pub const CONSTANTS: &[Constant] = &[
+    Constant {
+        name: "pi",
+        label: "pi",
+        value: std::f64::consts::PI,
+        unit: "",
+    },
+    Constant {
+        name: "tau",
+        label: "tau (2 pi)",
+        value: std::f64::consts::TAU,
+        unit: "",
+    },
+    Constant {
+        name: "e",
+        label: "Euler's number",
+        value: std::f64::consts::E,
+        unit: "",
+    },
+    Constant {
+        name: "phi",
+        label: "golden ratio",
+        value: 1.618033988749895,
+        unit: "",
+    },
+    Constant {
+        name: "c",
+        label: "speed of light",
+        value: 299_792_458.0,
+        unit: "m/s",
+    },
+    Constant {
+        name: "g",
+        label: "standard gravity",
+        value: 9.80665,
+        unit: "m/s²",
+    },
+    Constant {
+        name: "h",
+        label: "Planck constant",
+        value: 6.626_070_15e-34,
+        unit: "J·s",
+    },
+    Constant {
+        name: "k_b",
+        label: "Boltzmann constant",
+        value: 1.380_649e-23,
+        unit: "J/K",
+    },
+    Constant {
+        name: "avogadro",
+        label: "Avogadro constant",
+        value: 6.022_140_76e23,
+        unit: "1/mol",
+    },
+];
+
+/// The constant called `name`, which the tokenizer has already lowercased.
+pub fn find_constant(name: &str) -> Option<&'static Constant> {
+    CONSTANTS.iter().find(|constant| constant.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::format_result;
+
+    #[test]
+    fn constants_have_distinct_lowercase_names() {
+        for (i, constant) in CONSTANTS.iter().enumerate() {
+            assert_eq!(constant.name, constant.name.to_lowercase());
+            assert!(CONSTANTS[..i].iter().all(|c| c.name != constant.name));
+            assert!(find_constant(constant.name).is_some());
+        }
+        assert_eq!(
+            find_constant("phi").map(|c| c.value),
+            Some((1.0 + 5f64.sqrt()) / 2.0)
+        );
+        assert!(find_constant("planck").is_none());
+    }
+
+    #[test]
+    fn constants_display_as_the_nonzero_values_they_are() {
+        for constant in CONSTANTS {
+            let shown = format_result(constant.value);
+            let value: f64 = shown.parse().unwrap();
+            assert!(value != 0.0, "{} shows as {}", constant.name, shown);
+            assert!(
+                (value / constant.value - 1.0).abs() < 1e-9,
+                "{} shows as {}",
+                constant.name,
+                shown
+            );
+        }
+        assert_eq!(
+            format_result(find_constant("h").unwrap().value),
+            "6.62607015e-34"
+        );
+        assert_eq!(
+            format_result(find_constant("avogadro").unwrap().value),
+            "6.02214076e23"
+        );
+    }
+}
@@ -0,0 +1,428 @@
+//! Dates and times: the `"2024-06-15"` and `"2024-06-15 13:45:00 +00:00"` values
+//! expressions work with, the functions on them, and the date calculator panel. A
+//! duration is a plain number, of days for a date and of seconds for a time, so adding
+//! one or taking two apart is ordinary arithmetic.
+
+use chrono::{
+    DateTime, Datelike, Days, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeDelta, Weekday,
+};
+
+use crate::calculator::{EvalError, EvalResult};
+use crate::eval::{BinaryOp, Value};
+
+/// Functions of dates and times that give one or a number; `weekday` gives text
+/// instead.
+pub const DATE_FUNCTIONS: &[&str] = &[
+    "days_between",
+    "date_add",
+    "today",
+    "unix",
+    "to_unix",
+    "tz",
+    "local",
+];
+
+/// How dates are written, in expressions and results.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// How times are written in results: to the second, any fraction of one, and the
+/// offset from UTC. The offset may be left off when typing one, for UTC.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f %:z";
+
+/// [`TIME_FORMAT`] without the offset.
+const UTC_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// The date `text` names, written as `YYYY-MM-DD`.
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text.trim(), DATE_FORMAT).ok()
+}
+
+/// `date` as it's typed in an expression, quotes and all.
+pub fn format_date(date: NaiveDate) -> String {
+    format!("\"{}\"", date.format(DATE_FORMAT))
+}
+
+/// The time `text` names, written as `YYYY-MM-DD HH:MM:SS` and an offset such as
+/// `+05:30`, or without one for UTC.
+pub fn parse_time(text: &str) -> Option<DateTime<FixedOffset>> {
+    let text = text.trim();
+    DateTime::parse_from_str(text, TIME_FORMAT)
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(text, UTC_TIME_FORMAT)
+                .ok()
+                .map(|time| time.and_utc().fixed_offset())
+        })
+}
+
+/// `time` as it's typed in an expression, quotes and all.
+pub fn format_time(time: DateTime<FixedOffset>) -> String {
+    format!("\"{}\"", time.format(TIME_FORMAT))
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+pub fn weekday_name(date: NaiveDate) -> &'static str {
+    match date.weekday() {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// Days from `from` to `to`, negative when `to` comes first.
+pub fn days_between(from: NaiveDate, to: NaiveDate) -> f64 {
+    (to - from).num_days() as f64
+}
+
+/// `date` moved by `days`, which must be a whole number that keeps it in range.
+pub fn add_days(date: NaiveDate, days: f64) -> EvalResult<NaiveDate> {
+    let out_of_range = EvalError::undefined("date_add", days);
+    if days.fract() != 0.0 || days.abs() > u32::MAX as f64 {
+        return Err(out_of_range);
+    }
+    let moved = match days < 0.0 {
+        true => date.checked_sub_days(Days::new(-days as u64)),
+        false => date.checked_add_days(Days::new(days as u64)),
+    };
+    moved.ok_or(out_of_range)
+}
+
+/// A duration of `seconds`, which may have a fraction, or `None` when it's too long for
+/// any time to move by.
+fn duration(seconds: f64) -> Option<TimeDelta> {
+    if !seconds.is_finite() || seconds.abs() > 1e15 {
+        return None;
+    }
+    let nanos = (seconds.fract() * 1e9).round() as i64;
+    TimeDelta::try_seconds(seconds.trunc() as i64)?.checked_add(&TimeDelta::nanoseconds(nanos))
+}
+
+/// Seconds from `from` to `to`, negative when `to` comes first.
+pub fn seconds_between(from: DateTime<FixedOffset>, to: DateTime<FixedOffset>) -> f64 {
+    let apart = to - from;
+    apart.num_seconds() as f64 + f64::from(apart.subsec_nanos()) / 1e9
+}
+
+/// `time` moved by `seconds`, `func` being what to blame when that leaves the range of
+/// times.
+fn add_seconds(
+    time: DateTime<FixedOffset>,
+    seconds: f64,
+    func: &'static str,
+) -> EvalResult<DateTime<FixedOffset>> {
+    duration(seconds)
+        .and_then(|duration| time.checked_add_signed(duration))
+        .ok_or(EvalError::undefined(func, seconds))
+}
+
+/// `left op right` where either is a date or time. Numbers added to or subtracted from
+/// a date are days and from a time seconds; a date less a date gives days, and times,
+/// or a time and a date taken as midnight UTC, give seconds. `compare` works out a
+/// comparison of how far apart they are against 0.
+pub(crate) fn combine(
+    op: BinaryOp,
+    left: Value,
+    right: Value,
+    compare: impl Fn(BinaryOp, f64, f64) -> EvalResult<f64>,
+) -> EvalResult<Value> {
+    match (op, left, right) {
+        (BinaryOp::Add, Value::Date(date), Value::Number(days))
+        | (BinaryOp::Add, Value::Number(days), Value::Date(date)) => {
+            add_days(date, days).map(Value::Date)
+        }
+        (BinaryOp::Subtract, Value::Date(date), Value::Number(days)) => {
+            add_days(date, -days).map(Value::Date)
+        }
+        (BinaryOp::Add, Value::Time(time), Value::Number(seconds))
+        | (BinaryOp::Add, Value::Number(seconds), Value::Time(time)) => {
+            add_seconds(time, seconds, "+").map(Value::Time)
+        }
+        (BinaryOp::Subtract, Value::Time(time), Value::Number(seconds)) => {
+            add_seconds(time, -seconds, "-").map(Value::Time)
+        }
+        (BinaryOp::Subtract, Value::Date(to), Value::Date(from)) => {
+            Ok(Value::Number(days_between(from, to)))
+        }
+        (
+            op,
+            left @ (Value::Date(_) | Value::Time(_)),
+            right @ (Value::Date(_) | Value::Time(_)),
+        ) if op == BinaryOp::Subtract || op.is_comparison() => {
+            let apart = seconds_between(right.time()?, left.time()?);
+            match op {
+                BinaryOp::Subtract => Ok(Value::Number(apart)),
+                _ => compare(op, apart, 0.0).map(Value::Number),
+            }
+        }
+        _ => Err(EvalError::UnexpectedDate),
+    }
+}
+
+/// The time `seconds` after the start of 1970 in UTC, as Unix timestamps count.
+fn from_unix(seconds: f64) -> EvalResult<DateTime<FixedOffset>> {
+    add_seconds(DateTime::UNIX_EPOCH.fixed_offset(), seconds, "unix")
+}
+
+/// `time` shown `hours` ahead of UTC, which must be a whole number of minutes and
+/// less than a day.
+fn in_zone(time: DateTime<FixedOffset>, hours: f64) -> EvalResult<DateTime<FixedOffset>> {
+    let minutes = hours * 60.0;
+    let offset = (minutes.fract() == 0.0)
+        .then(|| FixedOffset::east_opt((minutes * 60.0) as i32))
+        .flatten()
+        .filter(|_| hours.abs() < 24.0)
+        .ok_or(EvalError::undefined("tz", hours))?;
+    Ok(time.with_timezone(&offset))
+}
+
+/// Call `name`, one of [`DATE_FUNCTIONS`], on `args`, whose count the caller has
+/// checked.
+pub fn call_date(name: &str, args: Vec<Value>) -> EvalResult<Value> {
+    let mut args = args.into_iter();
+    let mut next = || args.next().unwrap_or(Value::Number(0.0));
+    match name {
+        "days_between" => {
+            let (from, to) = (next().date()?, next().date()?);
+            Ok(Value::Number(days_between(from, to)))
+        }
+        "date_add" => {
+            let date = next().date()?;
+            add_days(date, next().number()?).map(Value::Date)
+        }
+        "unix" => from_unix(next().number()?).map(Value::Time),
+        "to_unix" => {
+            let epoch = DateTime::UNIX_EPOCH.fixed_offset();
+            Ok(Value::Number(seconds_between(epoch, next().time()?)))
+        }
+        "tz" => {
+            let time = next().time()?;
+            in_zone(time, next().number()?).map(Value::Time)
+        }
+        "local" => Ok(Value::Time(
+            next().time()?.with_timezone(&Local).fixed_offset(),
+        )),
+        _ => Ok(Value::Date(today())),
+    }
+}
+
+/// The day of the week of a date, as text.
+pub fn call_weekday(arg: Value) -> EvalResult<String> {
+    arg.date().map(|date| weekday_name(date).to_string())
+}
+
+/// A field of the date calculator.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DateField {
+    #[default]
+    Start,
+    End,
+    Days,
+}
+
+/// The date calculator panel: the days between two dates, and a date moved by a number
+/// of days, as typed.
+#[derive(Debug, Clone, Default)]
+pub struct DateCalculator {
+    pub start: String,
+    pub end: String,
+    /// Days to move the start date by.
+    pub days: String,
+    pub focus: DateField,
+}
+
+impl DateCalculator {
+    /// Fill in any empty date with today's.
+    pub fn fill_today(&mut self) {
+        let today = today().format(DATE_FORMAT).to_string();
+        for field in [&mut self.start, &mut self.end] {
+            if field.is_empty() {
+                field.clone_from(&today);
+            }
+        }
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus = match self.focus {
+            DateField::Start => DateField::End,
+            DateField::End => DateField::Days,
+            DateField::Days => DateField::Start,
+        };
+    }
+
+    pub fn focus_prev(&mut self) {
+        self.focus = match self.focus {
+            DateField::Start => DateField::Days,
+            DateField::End => DateField::Start,
+            DateField::Days => DateField::End,
+        };
+    }
+
+    fn field(&mut self) -> &mut String {
+        match self.focus {
+            DateField::Start => &mut self.start,
+            DateField::End => &mut self.end,
+            DateField::Days => &mut self.days,
+        }
+    }
+
+    /// Type `c` into the focused field: digits and dashes for a date, a whole number of
+    /// days otherwise.
+    pub fn push(&mut self, c: char) {
+        let is_date = self.focus != DateField::Days;
+        let field = self.field();
+        let fits = match is_date {
+            true => (c.is_ascii_digit() || c == '-') && field.len() < 10,
+            false => c.is_ascii_digit() || (c == '-' && field.is_empty()),
+        };
+        if fits {
+            field.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.field().pop();
+    }
+
+    /// Set the focused date to today; the days go back to none.
+    pub fn set_today(&mut self) {
+        let today = today().format(DATE_FORMAT).to_string();
+        match self.focus {
+            DateField::Days => self.days.clear(),
+            _ => *self.field() = today,
+        }
+    }
+
+    pub fn start_date(&self) -> Option<NaiveDate> {
+        parse_date(&self.start)
+    }
+
+    pub fn end_date(&self) -> Option<NaiveDate> {
+        parse_date(&self.end)
+    }
+
+    /// Days from the start date to the end date.
+    pub fn between(&self) -> Option<i64> {
+        Some((self.end_date()? - self.start_date()?).num_days())
+    }
+
+    /// The start date moved by the days.
+    pub fn shifted(&self) -> Option<NaiveDate> {
+        let days: f64 = self.days.parse().ok()?;
+        add_days(self.start_date()?, days).ok()
+    }
+
+    /// The focused calculation as an expression: the days between the dates, or the
+    /// start date moved by the days when they are focused.
+    pub fn expression(&self) -> Option<String> {
+        let start = format_date(self.start_date()?);
+        match self.focus {
+            DateField::Days => {
+                self.shifted()?;
+                Some(format!("date_add({}, {})", start, self.days))
+            }
+            _ => Some(format!(
+                "days_between({}, {})",
+                start,
+                format_date(self.end_date()?)
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        parse_date(text).unwrap()
+    }
+
+    #[test]
+    fn dates_move_by_whole_days() {
+        assert_eq!(days_between(date("2024-01-01"), date("2024-06-15")), 166.0);
+        assert_eq!(days_between(date("2024-03-01"), date("2024-02-28")), -2.0);
+        assert_eq!(add_days(date("2024-01-31"), 30.0), Ok(date("2024-03-01")));
+        assert_eq!(add_days(date("2025-01-01"), -1.0), Ok(date("2024-12-31")));
+        assert!(add_days(date("2024-01-01"), 0.5).is_err());
+        assert!(add_days(date("2024-01-01"), 1e12).is_err());
+        assert_eq!(weekday_name(date("2024-06-15")), "Saturday");
+        assert_eq!(format_date(date("2024-6-5")), "\"2024-06-05\"");
+        assert_eq!(parse_date("2024-02-30"), None);
+        assert_eq!(parse_date("June 5"), None);
+    }
+
+    #[test]
+    fn times_count_seconds_from_the_epoch() {
+        let time = |text: &str| parse_time(text).unwrap();
+        let noon = time("2024-06-15 12:00:00");
+        assert_eq!(noon, time("2024-06-15 17:30:00 +05:30"));
+        assert_eq!(format_time(noon), "\"2024-06-15 12:00:00 +00:00\"");
+        assert_eq!(from_unix(1718452800.0), Ok(noon));
+        assert_eq!(
+            format_time(from_unix(1.25).unwrap()),
+            "\"1970-01-01 00:00:01.250 +00:00\""
+        );
+        assert_eq!(
+            parse_time("1970-01-01 00:00:01.250 +00:00"),
+            from_unix(1.25).ok()
+        );
+        assert_eq!(seconds_between(noon, time("2024-06-16 12:00:01")), 86401.0);
+
+        let shifted = in_zone(noon, -4.0).unwrap();
+        assert_eq!(shifted, noon);
+        assert_eq!(format_time(shifted), "\"2024-06-15 08:00:00 -04:00\"");
+        assert_eq!(
+            format_time(in_zone(noon, 5.75).unwrap()),
+            "\"2024-06-15 17:45:00 +05:45\""
+        );
+        assert!(in_zone(noon, 24.0).is_err());
+        assert!(in_zone(noon, 0.01).is_err());
+        assert!(from_unix(1e300).is_err());
+        assert_eq!(parse_time("2024-06-15"), None);
+    }
+
+    #[test]
+    fn calculator_fields_give_expressions() {
+        let mut dates = DateCalculator::default();
+        for c in "2024-01-01x".chars() {
+            dates.push(c);
+        }
+        dates.focus_next();
+        for c in "2024-03-01".chars() {
+            dates.push(c);
+        }
+        assert_eq!(dates.between(), Some(60));
+        assert_eq!(
+            dates.expression().as_deref(),
+            Some("days_between(\"2024-01-01\", \"2024-03-01\")")
+        );
+
+        dates.focus_next();
+        for c in "-1-0".chars() {
+            dates.push(c);
+        }
+        assert_eq!(dates.days, "-10");
+        assert_eq!(dates.shifted(), Some(date("2023-12-22")));
+        assert_eq!(
+            dates.expression().as_deref(),
+            Some("date_add(\"2024-01-01\", -10)")
+        );
+
+        // Half-typed dates give nothing, and today fills in what's empty
+        dates.focus_prev();
+        dates.backspace();
+        assert_eq!(dates.between(), None);
+        assert_eq!(dates.expression(), None);
+        dates.end.clear();
+        dates.fill_today();
+        assert_eq!(dates.end_date(), Some(today()));
+        assert_eq!(dates.start, "2024-01-01");
+    }
+}
@@ -0,0 +1,2791 @@
+//! The expression engine shared by the calculator and the graph. Text is split into
+//! tokens and parsed into an [`Expr`] tree once; an [`Evaluator`] then works the tree
+//! out against variable bindings as often as needed, such as once per graph sample.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::f64::consts::{LOG10_2, LOG10_E};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime};
+
+use crate::calculator::{
+    AngleMode, EvalError, EvalResult, ModuloBehavior, PercentBehavior, approx_equal, clamp,
+    find_conversion, floored_mod, format_ratio, log10_factorial, margin, percent_change,
+    percent_difference, round_decimals, round_sig_figs, ulp_distance,
+};
+use crate::constants::find_constant;
+use crate::dates::{self, DATE_FUNCTIONS};
+use crate::finance::{self, FINANCE_FUNCTIONS};
+use crate::matrix::{self, MATRIX_FUNCTIONS, Matrix};
+use crate::number_theory::{self, NUMBER_THEORY_FUNCTIONS};
+use crate::programmer::{self, BITWISE_FUNCTIONS, WordSize};
+use crate::random::{self, RANDOM_FUNCTIONS, Rng};
+use crate::uncertainty;
+use crate::units::{self, Unit};
+use crate::{numeric, precision};
+
+/// What an [`EvalError::Syntax`] wanted: an operand where an expression starts, an
+/// operator between two operands.
+pub(crate) const EXPECTED_OPERAND: &str = "a number, name or (";
+pub(crate) const EXPECTED_OPERATOR: &str = "an operator";
+
+/// Functions that take an angle, in the angle mode's unit.
+pub(crate) const ANGLE_FUNCTIONS: &[&str] = &["sin", "cos", "tan"];
+
+/// Functions that give an angle, in the angle mode's unit.
+pub(crate) const INVERSE_ANGLE_FUNCTIONS: &[&str] = &["asin", "acos", "atan", "atan2"];
+
+/// Built-in function names handled directly by the parser.
+pub const FUNCTIONS: &[&str] = &[
+    "sin",
+    "cos",
+    "tan",
+    "asin",
+    "acos",
+    "atan",
+    "atan2",
+    "sqrt",
+    "log",
+    "ln",
+    "exp",
+    "abs",
+    "pctdiff",
+    "pctchange",
+    "markup",
+    "margin",
+    "percent",
+    "mod",
+    "sigfig",
+    "approx",
+    "ulps",
+    "fmin",
+    "fmax",
+    "fmean",
+    "min",
+    "max",
+    "round",
+    "clamp",
+    "and",
+    "or",
+    "xor",
+    "shl",
+    "shr",
+    "convert",
+    "sum",
+    "prod",
+    "solve",
+    "gcd",
+    "lcm",
+    "isprime",
+    "rand",
+    "randint",
+    "randn",
+    "if",
+    "mean",
+    "det",
+    "inv",
+    "transpose",
+    "identity",
+    "days_between",
+    "date_add",
+    "today",
+    "unix",
+    "to_unix",
+    "tz",
+    "local",
+    "npv",
+    "irr",
+];
+
+/// Functions of [`FUNCTIONS`] whose first argument is an expression in `x`, evaluated
+/// across the interval the other two give rather than once.
+pub(crate) const INTERVAL_FUNCTIONS: &[&str] = &["fmin", "fmax", "fmean"];
+
+/// Functions of [`FUNCTIONS`] that name a counter, then its first and last values and
+/// an expression to add up or multiply for each whole number between.
+pub(crate) const SERIES_FUNCTIONS: &[&str] = &["sum", "prod"];
+
+/// Functions of [`FUNCTIONS`] that reduce their arguments to one number, with the values
+/// of any list among them taken one by one. `sum` and `prod` do so when given just one
+/// argument, such as `sum([1, 2, 3])`.
+pub(crate) const LIST_FUNCTIONS: &[&str] = &["sum", "prod", "mean", "min", "max"];
+
+/// The most terms a `sum` or `prod` adds up, so a typo can't hang the calculator.
+const MAX_SERIES_TERMS: f64 = 1e6;
+/// Why a series with bounds that aren't whole, or too far apart, fails.
+const SERIES_BOUNDS: &str = "{func}() counts between whole numbers, at most a million of them";
+
+/// The fewest and most arguments of each function that doesn't take exactly one.
+const ARITIES: &[(&str, usize, usize)] = &[
+    ("pctdiff", 2, 2),
+    ("pctchange", 2, 2),
+    ("markup", 2, 2),
+    ("margin", 2, 2),
+    ("mod", 2, 2),
+    ("sigfig", 2, 2),
+    ("ulps", 2, 2),
+    ("atan2", 2, 2),
+    ("ratio", 2, 2),
+    // The tolerance is optional
+    ("approx", 2, 3),
+    // An optional base before the argument
+    ("log", 1, 2),
+    // Optional decimal places after the argument
+    ("round", 1, 2),
+    ("min", 1, usize::MAX),
+    ("max", 1, usize::MAX),
+    ("mean", 1, usize::MAX),
+    ("clamp", 3, 3),
+    ("if", 3, 3),
+    ("and", 2, 2),
+    ("or", 2, 2),
+    ("xor", 2, 2),
+    ("shl", 2, 2),
+    ("shr", 2, 2),
+    ("gcd", 2, 2),
+    ("rand", 0, 0),
+    ("today", 0, 0),
+    ("days_between", 2, 2),
+    ("date_add", 2, 2),
+    ("tz", 2, 2),
+    // A rate, then cash flows as a list or one by one
+    ("npv", 2, usize::MAX),
+    ("irr", 1, usize::MAX),
+    ("randn", 0, 0),
+    ("randint", 2, 2),
+    ("lcm", 2, 2),
+    ("fmin", 3, 3),
+    ("fmax", 3, 3),
+    ("fmean", 3, 3),
+    ("convert", 3, 3),
+    ("sum", 4, 4),
+    ("prod", 4, 4),
+    // An optional guess, or two values bracketing the root
+    ("solve", 2, 4),
+];
+
+/// Relative tolerance `approx` uses when none is given.
+const APPROX_TOLERANCE: f64 = 1e-9;
+
+/// Functions that produce text rather than a number, so they must make up the whole
+/// expression.
+pub const TEXT_FUNCTIONS: &[&str] = &["ratio", "factor", "weekday"];
+
+/// The name that stands for the last calculated result.
+pub const ANS: &str = "ans";
+
+/// The fewest and most arguments `name` takes.
+pub(crate) fn arity(name: &str) -> (usize, usize) {
+    ARITIES
+        .iter()
+        .find(|(known, ..)| *known == name)
+        .map_or((1, 1), |&(_, fewest, most)| (fewest, most))
+}
+
+/// Whether the result of calling `name` depends on the angle mode.
+pub(crate) fn uses_angle_mode(name: &str) -> bool {
+    ANGLE_FUNCTIONS.contains(&name) || INVERSE_ANGLE_FUNCTIONS.contains(&name)
+}
+
+/// Whether `name` is callable as a function in expressions.
+pub fn is_function(name: &str) -> bool {
+    FUNCTIONS.contains(&name) || TEXT_FUNCTIONS.contains(&name) || find_conversion(name).is_some()
+}
+
+/// A parsed expression, ready to evaluate as many times as needed, against whatever
+/// variables are bound at the time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// A number as typed, such as `12` or `0.25`, with its text (underscores left out)
+    /// for the exact modes to read without rounding.
+    Literal(f64, String),
+    /// `[a, b, c]`: a list of numbers.
+    List(Vec<Expr>),
+    /// `"2024-06-15"`: a date, read as the expression is parsed.
+    Date(NaiveDate),
+    /// `"2024-06-15 13:45:00 +00:00"`: a time.
+    Time(DateTime<FixedOffset>),
+    /// A name looked up among the variables when evaluated.
+    Variable(String),
+    Negate(Box<Expr>),
+    /// `~`: the bitwise complement.
+    Complement(Box<Expr>),
+    /// `not`: 1 when the operand is false (zero or NaN), else 0.
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `total + n%` or `total - n%` in contextual percent mode: `n` percent of the
+    /// total added to or taken off it.
+    PercentOf {
+        total: Box<Expr>,
+        percent: f64,
+        subtract: bool,
+    },
+    Postfix(PostfixOp, Box<Expr>),
+    /// A built-in function or conversion, including text functions like `ratio`, which
+    /// only evaluate as the whole expression.
+    Call(String, Vec<Expr>),
+    /// `fmin`, `fmax` or `fmean`: `body` evaluated across `[a, b]` with `x` bound.
+    Interval {
+        func: &'static str,
+        body: Box<Expr>,
+        a: Box<Expr>,
+        b: Box<Expr>,
+    },
+    /// `sum` or `prod`: `body` for each whole number from `from` to `to`, with `counter`
+    /// bound to it.
+    Series {
+        func: &'static str,
+        counter: String,
+        from: Box<Expr>,
+        to: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// `solve`: where `body` is zero as `var` varies, searched from `start`: a guess, two
+    /// values bracketing the root, or neither.
+    Solve {
+        var: String,
+        body: Box<Expr>,
+        start: Vec<Expr>,
+    },
+    /// `if(condition, then, otherwise)`; only the branch taken is evaluated.
+    If {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+    /// `convert(value, "from", "to")`, with the units looked up when parsed.
+    Convert {
+        value: Box<Expr>,
+        from: &'static Unit,
+        to: &'static Unit,
+    },
+}
+
+impl Expr {
+    /// Whether the value can jump where a condition flips: there is an `if`, a
+    /// comparison or a logical operator outside any call that samples its argument.
+    pub fn has_conditions(&self) -> bool {
+        match self {
+            Expr::If { .. } | Expr::Not(_) => true,
+            Expr::Binary(op, left, right) => {
+                op.is_comparison()
+                    || matches!(op, BinaryOp::And | BinaryOp::Or)
+                    || left.has_conditions()
+                    || right.has_conditions()
+            }
+            Expr::Negate(operand) | Expr::Complement(operand) | Expr::Postfix(_, operand) => {
+                operand.has_conditions()
+            }
+            Expr::PercentOf { total, .. } => total.has_conditions(),
+            Expr::Call(_, args) => args.iter().any(Expr::has_conditions),
+            Expr::Convert { value, .. } => value.has_conditions(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// `//`: the floor of the quotient.
+    FloorDivide,
+    /// `%` as modulo: the remainder truncated toward zero, like Rust's `%`.
+    Remainder,
+    Power,
+    /// `±` (or `+/-`): a value with an uncertainty.
+    PlusMinus,
+    /// `&`, `|` and `xor`, on whole numbers.
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// `<<` and `>>`, on whole numbers.
+    ShiftLeft,
+    ShiftRight,
+    /// `<`, `<=`, `>`, `>=`, `==` and `!=`: 1 when true, else 0.
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    /// `and` and `or`: 1 or 0, evaluating the right only when it decides the result.
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub(crate) fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual
+        )
+    }
+
+    /// How the operator is written, for messages.
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::FloorDivide => "//",
+            BinaryOp::Remainder => "%",
+            BinaryOp::Power => "^",
+            BinaryOp::PlusMinus => "±",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "xor",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        }
+    }
+
+    /// The bitwise function this operator is spelled as in calls, for the bitwise
+    /// operators.
+    fn bitwise_function(self) -> Option<&'static str> {
+        match self {
+            BinaryOp::BitAnd => Some("and"),
+            BinaryOp::BitOr => Some("or"),
+            BinaryOp::BitXor => Some("xor"),
+            BinaryOp::ShiftLeft => Some("shl"),
+            BinaryOp::ShiftRight => Some("shr"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostfixOp {
+    Factorial,
+    /// `%`: divide by 100.
+    Percent,
+    /// `%o`: divide by 1000.
+    Permille,
+}
+
+/// What an expression evaluates to: a number, the numbers of a list such as
+/// `[1, 2, 3]`, or a matrix, a list of lists such as `[[1, 2], [3, 4]]`. Operators and
+/// functions of one number work on each value of a list or matrix, except that `*`
+/// multiplies matrices and `^` raises them as in linear algebra. A date such as
+/// `"2024-06-15"` only takes days added or subtracted, and other dates compared; a
+/// time such as `"2024-06-15 13:45:00 +00:00"` does the same in seconds. A value with
+/// an uncertainty, such as `9.81±0.02`, carries its error through arithmetic and
+/// functions of one number; see [`uncertainty`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    List(Vec<f64>),
+    Matrix(Matrix),
+    Date(NaiveDate),
+    Time(DateTime<FixedOffset>),
+    /// A value and its standard error.
+    Uncertain(f64, f64),
+}
+
+impl Value {
+    /// The number, or an error when this is a list or matrix.
+    pub fn number(self) -> EvalResult<f64> {
+        match self {
+            Value::Number(value) => Ok(value),
+            Value::List(_) => Err(EvalError::UnexpectedList),
+            Value::Matrix(_) => Err(EvalError::UnexpectedMatrix),
+            Value::Date(_) | Value::Time(_) => Err(EvalError::UnexpectedDate),
+            Value::Uncertain(..) => Err(EvalError::UnexpectedUncertain),
+        }
+    }
+
+    /// The date, a time giving the day it falls on where it's shown, or an error when
+    /// this is neither.
+    pub fn date(self) -> EvalResult<NaiveDate> {
+        match self {
+            Value::Date(date) => Ok(date),
+            Value::Time(time) => Ok(time.date_naive()),
+            _ => Err(EvalError::ExpectedDate),
+        }
+    }
+
+    /// The time, a date being its midnight in UTC, or an error when this is neither.
+    pub fn time(self) -> EvalResult<DateTime<FixedOffset>> {
+        match self {
+            Value::Date(date) => Ok(date.and_time(NaiveTime::MIN).and_utc().fixed_offset()),
+            Value::Time(time) => Ok(time),
+            _ => Err(EvalError::ExpectedDate),
+        }
+    }
+
+    /// The values, a number being a list of one and a matrix read a row at a time; a
+    /// date, time or uncertain value has none.
+    fn values(&self) -> &[f64] {
+        match self {
+            Value::Number(value) => std::slice::from_ref(value),
+            Value::List(values) => values,
+            Value::Matrix(matrix) => matrix.values(),
+            Value::Date(_) | Value::Time(_) | Value::Uncertain(..) => &[],
+        }
+    }
+
+    /// `f` applied to the number, or to each value of the list or matrix in place. An
+    /// uncertain value's error is scaled by the slope of `f`.
+    fn map(self, mut f: impl FnMut(f64) -> EvalResult<f64>) -> EvalResult<Value> {
+        match self {
+            Value::Number(value) => f(value).map(Value::Number),
+            Value::List(mut values) => {
+                for value in &mut values {
+                    *value = f(*value)?;
+                }
+                Ok(Value::List(values))
+            }
+            Value::Matrix(matrix) => matrix.map(f).map(Value::Matrix),
+            Value::Date(_) | Value::Time(_) => Err(EvalError::UnexpectedDate),
+            Value::Uncertain(value, error) => uncertainty::propagate(value, error, f),
+        }
+    }
+}
+
+/// `f` applied to `left` and `right` pair by pair: a number pairs with each value of a
+/// list or matrix, and two lists or two matrices pair up value by value, so they must
+/// be the same size. `op` names the operator when they aren't.
+fn elementwise(
+    left: Value,
+    right: Value,
+    op: &'static str,
+    mut f: impl FnMut(f64, f64) -> EvalResult<f64>,
+) -> EvalResult<Value> {
+    match (left, right) {
+        (Value::Date(_) | Value::Time(_), _) | (_, Value::Date(_) | Value::Time(_)) => {
+            Err(EvalError::UnexpectedDate)
+        }
+        (Value::Uncertain(..), _) | (_, Value::Uncertain(..)) => {
+            Err(EvalError::UnexpectedUncertain)
+        }
+        (Value::Number(left), right) => right.map(|right| f(left, right)),
+        (left, Value::Number(right)) => left.map(|left| f(left, right)),
+        (Value::Matrix(left), Value::Matrix(right)) => left.zip(&right, op, f).map(Value::Matrix),
+        (Value::Matrix(_), Value::List(_)) | (Value::List(_), Value::Matrix(_)) => {
+            Err(EvalError::UnexpectedList)
+        }
+        (Value::List(mut left), Value::List(right)) => {
+            if left.len() != right.len() {
+                return Err(EvalError::ListLengths(left.len(), right.len()));
+            }
+            for (value, right) in left.iter_mut().zip(right) {
+                *value = f(*value, right)?;
+            }
+            Ok(Value::List(left))
+        }
+    }
+}
+
+/// Evaluates expressions against variable bindings, under the settings that change
+/// what an expression means.
+#[derive(Clone, Copy)]
+pub struct Evaluator<'a> {
+    pub variables: &'a BTreeMap<String, f64>,
+    /// The last two names bound on top of `variables`, newest first, such as `x` while
+    /// a graph is sampled, or `x` and `y` while an implicit curve is traced.
+    pub bindings: [Option<(&'a str, f64)>; 2],
+    pub angle_mode: AngleMode,
+    pub percent: PercentBehavior,
+    pub modulo: ModuloBehavior,
+    /// What [`ANS`] refers to; `None` before anything has been calculated.
+    pub ans: Option<f64>,
+    /// Truncate every value toward zero and wrap it to this word, as programmer mode
+    /// works in fixed-width integers.
+    pub integers: Option<WordSize>,
+    /// Where to keep the largest magnitude added or subtracted so far, when checking
+    /// for cancellation.
+    pub largest_term: Option<&'a Cell<f64>>,
+    /// Where `rand` and the like draw from; without one, each draw is seeded from the
+    /// clock.
+    pub rng: Option<&'a Rng>,
+}
+
+impl<'a> Evaluator<'a> {
+    /// An evaluator with the default settings: radians, `%` as modulo, no checks.
+    pub fn new(variables: &'a BTreeMap<String, f64>) -> Self {
+        Self {
+            variables,
+            bindings: [None; 2],
+            angle_mode: AngleMode::Radians,
+            percent: PercentBehavior::default(),
+            modulo: ModuloBehavior::default(),
+            ans: None,
+            integers: None,
+            largest_term: None,
+            rng: None,
+        }
+    }
+
+    /// This evaluator with `name` bound to `value`, over the variable of that name if
+    /// there is one. The name bound before stays bound, and any before that is dropped.
+    pub fn bind(self, name: &'a str, value: f64) -> Self {
+        Self {
+            bindings: [Some((name, value)), self.bindings[0]],
+            ..self
+        }
+    }
+
+    /// Parse `expr` into a tree; an empty one is 0. Syntax errors give their column in
+    /// `expr`.
+    pub fn parse(&self, expr: &str) -> EvalResult<Expr> {
+        if expr.trim().is_empty() {
+            return Ok(Expr::Number(0.0));
+        }
+        let (tokens, offsets) = tokenize(expr, self.percent)?;
+        let text_end = expr.trim_end().chars().count();
+        let (tree, end) = parse_expression(&tokens, 0).map_err(|e| e.locate(&offsets, text_end))?;
+        match tokens.get(end) {
+            None => Ok(tree),
+            // Commas only separate function arguments
+            Some(Token::Comma) => Err(EvalError::InvalidCharacter(',')),
+            Some(_) => Err(EvalError::Syntax {
+                pos: offsets[end],
+                expected: EXPECTED_OPERATOR,
+            }),
+        }
+    }
+
+    /// Parse and evaluate `expr`; an empty one is 0.
+    pub fn evaluate(&self, expr: &str) -> EvalResult<f64> {
+        self.eval(&self.parse(expr)?)
+    }
+
+    /// Evaluate `expr` to a number; a list is an error.
+    pub fn eval(&self, expr: &Expr) -> EvalResult<f64> {
+        self.eval_value(expr)?.number()
+    }
+
+    /// Evaluate `expr`, which may give a list.
+    pub fn eval_value(&self, expr: &Expr) -> EvalResult<Value> {
+        let value = self.eval_node(expr)?;
+        match self.integers {
+            Some(word) => value.map(|value| Ok(word.wrap(value.trunc()))),
+            None => Ok(value),
+        }
+    }
+
+    fn eval_node(&self, expr: &Expr) -> EvalResult<Value> {
+        match expr {
+            Expr::Number(value) | Expr::Literal(value, _) => Ok(Value::Number(*value)),
+            Expr::Date(date) => Ok(Value::Date(*date)),
+            Expr::Time(time) => Ok(Value::Time(*time)),
+            Expr::List(items) => {
+                // Numbers make a list; lists, one per row, make a matrix
+                let mut values = Vec::with_capacity(items.len());
+                let mut rows = Vec::new();
+                for item in items {
+                    match self.eval_value(item)? {
+                        Value::Number(value) => values.push(value),
+                        Value::List(row) => rows.push(row),
+                        Value::Matrix(_) => return Err(EvalError::UnexpectedMatrix),
+                        Value::Date(_) | Value::Time(_) => {
+                            return Err(EvalError::UnexpectedDate);
+                        }
+                        Value::Uncertain(..) => return Err(EvalError::UnexpectedUncertain),
+                    }
+                }
+                match (values.is_empty(), rows.is_empty()) {
+                    (_, true) => Ok(Value::List(values)),
+                    (true, false) => Matrix::from_rows(rows).map(Value::Matrix),
+                    (false, false) => Err(EvalError::UnexpectedList),
+                }
+            }
+            Expr::Variable(name) => match self
+                .bindings
+                .iter()
+                .flatten()
+                .find(|(bound, _)| bound == name)
+            {
+                Some(&(_, value)) => Ok(Value::Number(value)),
+                _ if name == ANS => self
+                    .ans
+                    .map(Value::Number)
+                    .ok_or_else(|| EvalError::UndefinedVariable(name.clone())),
+                _ => self
+                    .variables
+                    .get(name)
+                    .copied()
+                    .or_else(|| find_constant(name).map(|constant| constant.value))
+                    .map(Value::Number)
+                    .ok_or_else(|| EvalError::UndefinedVariable(name.clone())),
+            },
+            Expr::Negate(operand) => self.eval_value(operand)?.map(|value| Ok(-value)),
+            Expr::Complement(operand) => self
+                .eval_value(operand)?
+                .map(|value| programmer::call_bitwise("not", &[value])),
+            Expr::Not(operand) => self
+                .eval_value(operand)?
+                .map(|value| Ok(truth(!is_true(value)))),
+            // A guard such as `x != 0 and 1/x > 2` keeps the right side from failing
+            Expr::Binary(op @ (BinaryOp::And | BinaryOp::Or), left, right) => {
+                let left = is_true(self.eval(left)?);
+                let holds = if left == (*op == BinaryOp::Or) {
+                    left
+                } else {
+                    is_true(self.eval(right)?)
+                };
+                Ok(Value::Number(truth(holds)))
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval_value(left)?;
+                let right = self.eval_value(right)?;
+                match (op, left, right) {
+                    (BinaryOp::PlusMinus, left, right) => uncertainty::with_error(left, right),
+                    (BinaryOp::Multiply, Value::Matrix(left), Value::Matrix(right)) => {
+                        left.multiply(&right).map(Value::Matrix)
+                    }
+                    // A list is a column on the right of a matrix, a row on its left
+                    (BinaryOp::Multiply, Value::Matrix(left), Value::List(right)) => {
+                        let column =
+                            Matrix::from_rows(right.into_iter().map(|v| vec![v]).collect())?;
+                        Ok(Value::List(left.multiply(&column)?.values().to_vec()))
+                    }
+                    (BinaryOp::Multiply, Value::List(left), Value::Matrix(right)) => {
+                        let row = Matrix::from_rows(vec![left])?;
+                        Ok(Value::List(row.multiply(&right)?.values().to_vec()))
+                    }
+                    (BinaryOp::Power, Value::Matrix(left), Value::Number(right)) => {
+                        left.power(right).map(Value::Matrix)
+                    }
+                    (BinaryOp::Divide | BinaryOp::Power, _, Value::Matrix(_)) => {
+                        Err(EvalError::UnexpectedMatrix)
+                    }
+                    (op, left @ (Value::Date(_) | Value::Time(_)), right)
+                    | (op, left, right @ (Value::Date(_) | Value::Time(_))) => {
+                        dates::combine(*op, left, right, |op, left, right| {
+                            self.binary(op, left, right)
+                        })
+                    }
+                    (op, left @ Value::Uncertain(..), right)
+                    | (op, left, right @ Value::Uncertain(..)) => {
+                        uncertainty::combine(*op, left, right, |op, left, right| {
+                            self.binary(op, left, right)
+                        })
+                    }
+                    (op, left, right) => {
+                        let name = match op {
+                            BinaryOp::Add => "+",
+                            BinaryOp::Subtract => "-",
+                            _ => "and",
+                        };
+                        elementwise(left, right, name, |left, right| {
+                            self.binary(*op, left, right)
+                        })
+                    }
+                }
+            }
+            Expr::PercentOf {
+                total,
+                percent,
+                subtract,
+            } => {
+                let sign = if *subtract { -1.0 } else { 1.0 };
+                self.eval_value(total)?
+                    .map(|total| Ok(total + sign * total * percent / 100.0))
+            }
+            Expr::Postfix(op, operand) => self.eval_value(operand)?.map(|value| match op {
+                PostfixOp::Factorial => factorial(value),
+                PostfixOp::Percent => Ok(value / 100.0),
+                PostfixOp::Permille => Ok(value / 1000.0),
+            }),
+            Expr::Call(name, _) if TEXT_FUNCTIONS.contains(&name.as_str()) => {
+                Err(EvalError::TextInExpression(name.clone()))
+            }
+            Expr::Call(name, args)
+                if let Some(&func) = FINANCE_FUNCTIONS.iter().find(|&&f| f == name) =>
+            {
+                check_arity(func, args.len())?;
+                let value = finance::call_finance(func, self.eval_values(args)?)?;
+                totalled(func, value).map(Value::Number)
+            }
+            Expr::Call(name, args)
+                if let Some(&func) = DATE_FUNCTIONS.iter().find(|&&f| f == name) =>
+            {
+                check_arity(func, args.len())?;
+                dates::call_date(func, self.eval_values(args)?)
+            }
+            Expr::Call(name, args)
+                if let Some(&func) = MATRIX_FUNCTIONS.iter().find(|&&f| f == name) =>
+            {
+                check_arity(func, args.len())?;
+                matrix::call_matrix(func, self.eval_value(&args[0])?)
+            }
+            Expr::Call(name, args)
+                if let Some(&func) = LIST_FUNCTIONS.iter().find(|&&f| f == name) =>
+            {
+                // `sum` and `prod` only parse as calls when given the one list
+                if !SERIES_FUNCTIONS.contains(&func) {
+                    check_arity(func, args.len())?;
+                }
+                totalled(func, self.reduce(func, args)?).map(Value::Number)
+            }
+            Expr::Call(name, args) if let [arg] = args.as_slice() => match self.eval_value(arg)? {
+                Value::Number(value) => self.call(name, &[value]).map(Value::Number),
+                list => {
+                    if !is_function(name) {
+                        return Err(EvalError::UnknownFunction(name.clone()));
+                    }
+                    check_arity(name, 1)?;
+                    list.map(|value| self.call(name, &[value]))
+                }
+            },
+            Expr::Call(name, args) => {
+                // Most built-ins take at most three arguments, so a graph sampling
+                // one allocates nothing per sample
+                let mut stack = [0.0; 3];
+                let heap;
+                let values = if args.len() <= stack.len() {
+                    for (slot, arg) in stack.iter_mut().zip(args) {
+                        *slot = self.eval(arg)?;
+                    }
+                    &stack[..args.len()]
+                } else {
+                    heap = self.eval_all(args)?;
+                    &heap[..]
+                };
+                self.call(name, values).map(Value::Number)
+            }
+            Expr::Interval { func, body, a, b } => {
+                totalled(func, self.interval(func, body, a, b)?).map(Value::Number)
+            }
+            Expr::Series {
+                func,
+                counter,
+                from,
+                to,
+                body,
+            } => totalled(func, self.series(func, counter, from, to, body)?).map(Value::Number),
+            Expr::Solve { var, body, start } => self.solve(var, body, start).map(Value::Number),
+            Expr::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if is_true(self.eval(condition)?) {
+                    self.eval_value(then)
+                } else {
+                    self.eval_value(otherwise)
+                }
+            }
+            Expr::Convert { value, from, to } => self
+                .eval_value(value)?
+                .map(|value| Ok(units::convert(value, from, to))),
+        }
+    }
+
+    /// Call the built-in `name` on `values`, drawing from the generator for `rand` and
+    /// the like.
+    fn call(&self, name: &str, values: &[f64]) -> EvalResult<f64> {
+        if let Some(&func) = RANDOM_FUNCTIONS.iter().find(|&&func| func == name) {
+            check_arity(name, values.len())?;
+            return match self.rng {
+                Some(rng) => random::call_random(func, values, rng),
+                None => random::call_random(func, values, &Rng::from_clock()),
+            };
+        }
+        let value = call_function(name, values, self.angle_mode)?;
+        let log10 = || match (name, values) {
+            ("exp", &[arg]) => arg * LOG10_E,
+            _ => f64::INFINITY,
+        };
+        finite(value, log10, || {
+            let func = FUNCTIONS.iter().find(|&&func| func == name);
+            EvalError::undefined(func.copied().unwrap_or("function"), values[0])
+        })
+    }
+
+    /// Reduce the values of `args` with `func`, one of [`LIST_FUNCTIONS`], taking each
+    /// value of a list in turn. Evaluated one argument at a time, so numbers alone
+    /// allocate nothing.
+    fn reduce(&self, func: &'static str, args: &[Expr]) -> EvalResult<f64> {
+        let mut total = match func {
+            "prod" => 1.0,
+            "min" => f64::INFINITY,
+            "max" => f64::NEG_INFINITY,
+            _ => 0.0,
+        };
+        let mut count = 0;
+        for arg in args {
+            let value = self.eval_value(arg)?;
+            match value {
+                Value::Date(_) | Value::Time(_) => return Err(EvalError::UnexpectedDate),
+                Value::Uncertain(..) => return Err(EvalError::UnexpectedUncertain),
+                _ => {}
+            }
+            for &value in value.values() {
+                total = match func {
+                    "prod" => total * value,
+                    "min" => total.min(value),
+                    "max" => total.max(value),
+                    _ => total + value,
+                };
+            }
+            count += value.values().len();
+        }
+        match func {
+            "sum" | "prod" => Ok(total),
+            _ if count == 0 => Err(EvalError::Domain {
+                func,
+                arg: f64::NAN,
+                reason: "{func}() needs at least one value",
+            }),
+            "mean" => Ok(total / count as f64),
+            _ => Ok(total),
+        }
+    }
+
+    /// Which way each condition met while evaluating `expr` went, folded into one
+    /// number: where two values of `x` give the same, they took the same branch of every
+    /// `if` and comparison. A condition that fails to evaluate counts as false.
+    pub fn branches(&self, expr: &Expr) -> u64 {
+        let mut bits = 0;
+        self.trace_branches(expr, &mut bits);
+        bits
+    }
+
+    fn trace_branches(&self, expr: &Expr, bits: &mut u64) {
+        let record = |bits: &mut u64, holds: bool| *bits = bits.rotate_left(1) ^ u64::from(holds);
+        match expr {
+            Expr::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.trace_branches(condition, bits);
+                let holds = self.eval(condition).is_ok_and(is_true);
+                record(bits, holds);
+                self.trace_branches(if holds { then } else { otherwise }, bits);
+            }
+            Expr::Binary(op @ (BinaryOp::And | BinaryOp::Or), left, right) => {
+                self.trace_branches(left, bits);
+                // The right is only evaluated when the left leaves the result open
+                let left = self.eval(left).is_ok_and(is_true);
+                if left != (*op == BinaryOp::Or) {
+                    self.trace_branches(right, bits);
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                self.trace_branches(left, bits);
+                self.trace_branches(right, bits);
+                if op.is_comparison() {
+                    record(bits, self.eval(expr).is_ok_and(is_true));
+                }
+            }
+            Expr::Negate(operand)
+            | Expr::Complement(operand)
+            | Expr::Not(operand)
+            | Expr::Postfix(_, operand) => self.trace_branches(operand, bits),
+            Expr::PercentOf { total, .. } => self.trace_branches(total, bits),
+            Expr::Call(_, args) => {
+                for arg in args {
+                    self.trace_branches(arg, bits);
+                }
+            }
+            Expr::Convert { value, .. } => self.trace_branches(value, bits),
+            _ => {}
+        }
+    }
+
+    /// Each of `exprs` evaluated in order, stopping at the first error.
+    pub fn eval_all(&self, exprs: &[Expr]) -> EvalResult<Vec<f64>> {
+        exprs.iter().map(|expr| self.eval(expr)).collect()
+    }
+
+    /// Like [`Evaluator::eval_all`], but keeping lists, matrices and dates as they are.
+    pub fn eval_values(&self, exprs: &[Expr]) -> EvalResult<Vec<Value>> {
+        exprs.iter().map(|expr| self.eval_value(expr)).collect()
+    }
+
+    fn binary(&self, op: BinaryOp, left: f64, right: f64) -> EvalResult<f64> {
+        if let Some(func) = op.bitwise_function() {
+            return programmer::call_bitwise(func, &[left, right]);
+        }
+        let value = match op {
+            BinaryOp::Add | BinaryOp::Subtract => {
+                let right = if op == BinaryOp::Subtract {
+                    -right
+                } else {
+                    right
+                };
+                let (sum, error) = precision::two_sum(left, right);
+                if let Some(largest) = self.largest_term {
+                    // Whole numbers that add up exactly lose nothing, however much they cancel
+                    if error != 0.0 || left.fract() != 0.0 || right.fract() != 0.0 {
+                        largest.set(largest.get().max(left.abs()).max(right.abs()));
+                    }
+                }
+                sum
+            }
+            BinaryOp::Multiply => left * right,
+            BinaryOp::Divide | BinaryOp::FloorDivide => {
+                if right == 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                let quotient = left / right;
+                if op == BinaryOp::FloorDivide {
+                    quotient.floor()
+                } else {
+                    quotient
+                }
+            }
+            BinaryOp::Remainder => match self.modulo {
+                ModuloBehavior::Truncated if right == 0.0 => {
+                    return Err(EvalError::DivisionByZero);
+                }
+                ModuloBehavior::Truncated => left % right,
+                ModuloBehavior::Floored => return floored_mod(left, right),
+            },
+            BinaryOp::Power if left == 0.0 && right < 0.0 => {
+                return Err(EvalError::DivisionByZero);
+            }
+            // A fractional power of a negative number has no real value
+            BinaryOp::Power if left < 0.0 && right.is_finite() && right.fract() != 0.0 => {
+                return Err(EvalError::Domain {
+                    func: "^",
+                    arg: right,
+                    reason: "A negative number can only be raised to a whole power, not {arg}",
+                });
+            }
+            BinaryOp::Power => left.powf(right),
+            BinaryOp::Less => truth(left < right),
+            BinaryOp::LessEqual => truth(left <= right),
+            BinaryOp::Greater => truth(left > right),
+            BinaryOp::GreaterEqual => truth(left >= right),
+            BinaryOp::Equal => truth(left == right),
+            BinaryOp::NotEqual => truth(left != right),
+            _ => unreachable!("bitwise, logical and ± operators are handled elsewhere"),
+        };
+        let log10 = || match op {
+            BinaryOp::Multiply => left.abs().log10() + right.abs().log10(),
+            BinaryOp::Divide | BinaryOp::FloorDivide => left.abs().log10() - right.abs().log10(),
+            BinaryOp::Power => right * left.abs().log10(),
+            // Halved first, so the sum doesn't overflow again
+            _ => (left / 2.0 + right / 2.0).abs().log10() + LOG10_2,
+        };
+        finite(value, log10, || EvalError::Domain {
+            func: op.symbol(),
+            arg: right,
+            reason: "{func} has no result for these values",
+        })
+    }
+
+    /// Evaluate `sum` or `prod`. The counter is bound over any variable of its name, and
+    /// counting up from past the last value gives the empty sum, 0, or product, 1.
+    fn series(
+        &self,
+        func: &'static str,
+        counter: &str,
+        from: &Expr,
+        to: &Expr,
+        body: &Expr,
+    ) -> EvalResult<f64> {
+        let (from, to) = (self.eval(from)?, self.eval(to)?);
+        if let Some(bound) = [from, to]
+            .into_iter()
+            .find(|bound| !bound.is_finite() || bound.fract() != 0.0)
+        {
+            return Err(EvalError::Domain {
+                func,
+                arg: bound,
+                reason: SERIES_BOUNDS,
+            });
+        }
+        if to - from >= MAX_SERIES_TERMS {
+            return Err(EvalError::Domain {
+                func,
+                arg: to,
+                reason: SERIES_BOUNDS,
+            });
+        }
+
+        self.keeping_binding(|scope| {
+            let product = func == "prod";
+            let mut total = if product { 1.0 } else { 0.0 };
+            let mut k = from;
+            while k <= to {
+                let term = scope.bind(counter, k).eval(body)?;
+                total = if product { total * term } else { total + term };
+                k += 1.0;
+            }
+            Ok(total)
+        })
+    }
+
+    /// Evaluate `solve`: within the bracket when given two values, otherwise nearest the
+    /// guess, or 0.
+    fn solve(&self, var: &str, body: &Expr, start: &[Expr]) -> EvalResult<f64> {
+        let start = self.eval_all(start)?;
+        if let Some(&value) = start.iter().find(|value| !value.is_finite()) {
+            return Err(EvalError::Domain {
+                func: "solve",
+                arg: value,
+                reason: "solve() needs a finite guess or bracket",
+            });
+        }
+        self.keeping_binding(|scope| {
+            let mut f = scope.equation(var, body);
+            let root = match *start.as_slice() {
+                [a, b] => {
+                    let (a, b) = (a.min(b), a.max(b));
+                    match numeric::bisect(&mut f, a, b)? {
+                        Some(root) => Some(root),
+                        None => numeric::roots(&mut f, a, b)?.first().copied(),
+                    }
+                }
+                [guess] => numeric::root_near(&mut f, guess)?,
+                _ => numeric::root_near(&mut f, 0.0)?,
+            };
+            root.ok_or(EvalError::NoRoot)
+        })
+    }
+
+    /// Every root on `[a, b]` of the equation `expr` solves, when it is a `solve` call,
+    /// to list beside the one root it gives.
+    pub fn solve_roots(&self, expr: &str, a: f64, b: f64) -> Option<Vec<f64>> {
+        let Ok(Expr::Solve { var, body, .. }) = self.parse(expr) else {
+            return None;
+        };
+        self.keeping_binding(|scope| numeric::roots(scope.equation(&var, &body), a, b).ok())
+    }
+
+    /// `body` as a function of `var` for the root finders. Where it is undefined it is
+    /// NaN, so the search passes over it rather than stopping.
+    fn equation<'b>(
+        &'b self,
+        var: &'b str,
+        body: &'b Expr,
+    ) -> impl FnMut(f64) -> EvalResult<f64> + 'b {
+        move |x| match self.bind(var, x).eval(body) {
+            Err(EvalError::DivisionByZero | EvalError::Domain { .. }) => Ok(f64::NAN),
+            result => result,
+        }
+    }
+
+    /// Call `f` with this evaluator's bindings made its variables, so that what `f` binds
+    /// on top, such as a counter, doesn't hide them.
+    fn keeping_binding<T>(&self, f: impl FnOnce(Evaluator<'_>) -> T) -> T {
+        if self.bindings == [None; 2] {
+            return f(*self);
+        }
+        let mut variables = self.variables.clone();
+        // Oldest first, so the newest of two bindings of one name wins
+        for (name, value) in self.bindings.iter().rev().flatten() {
+            variables.insert(name.to_string(), *value);
+        }
+        f(Evaluator {
+            variables: &variables,
+            bindings: [None; 2],
+            ..*self
+        })
+    }
+
+    /// Evaluate `fmin`, `fmax` or `fmean`. The body sees `x` set to each point sampled;
+    /// everything else sees the variables as they are.
+    fn interval(&self, func: &'static str, body: &Expr, a: &Expr, b: &Expr) -> EvalResult<f64> {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        if let Some(bound) = [a, b].into_iter().find(|bound| !bound.is_finite()) {
+            return Err(EvalError::undefined(func, bound));
+        }
+
+        // The body is sampled, not calculated, so it isn't checked for cancellation
+        let scope = Evaluator {
+            largest_term: None,
+            ..*self
+        };
+        let mut at = |x: f64| scope.bind("x", x).eval(body);
+        let (a, b) = (a.min(b), a.max(b));
+        match func {
+            "fmin" => Ok(numeric::minimum(&mut at, a, b)?.1),
+            "fmax" => Ok(numeric::maximum(&mut at, a, b)?.1),
+            _ => numeric::mean(&mut at, a, b),
+        }
+    }
+}
+
+fn parse_expression(tokens: &[Token], pos: usize) -> EvalResult<(Expr, usize)> {
+    parse_binary(tokens, pos, 0)
+}
+
+/// How tightly a prefix `-` or `~` holds its operand: less than `^`, so `-2^2` is
+/// `-(2^2)` as in written maths, and more than any other operator.
+const PREFIX_POWER: u8 = 21;
+
+/// How tightly a prefix `not` holds its operand: less than a comparison and more than
+/// `and`, so `not x < 0 and y` is `(not (x < 0)) and y`, as in Python.
+const NOT_POWER: u8 = 5;
+
+/// The binary operator `token` stands for, with how tightly it binds its left and its
+/// right operand. Loosest first, as in Python: `or`, `and`, the comparisons, the bitwise
+/// operators (`|`, `xor`, `&`, then the shifts), then `+ -`, then `* / // %`, then `^`.
+/// Binding the right a little tighter groups a run to the left, `1-2-3` as `(1-2)-3`;
+/// `^` does the opposite, so `2^3^2` is `2^(3^2)`.
+fn binding_power(token: &Token) -> Option<(BinaryOp, u8, u8)> {
+    let (op, left) = match token {
+        Token::Or => (BinaryOp::Or, 1),
+        Token::And => (BinaryOp::And, 3),
+        Token::Less => (BinaryOp::Less, 7),
+        Token::LessEqual => (BinaryOp::LessEqual, 7),
+        Token::Greater => (BinaryOp::Greater, 7),
+        Token::GreaterEqual => (BinaryOp::GreaterEqual, 7),
+        Token::Equal => (BinaryOp::Equal, 7),
+        Token::NotEqual => (BinaryOp::NotEqual, 7),
+        Token::BitOr => (BinaryOp::BitOr, 9),
+        Token::BitXor => (BinaryOp::BitXor, 11),
+        Token::BitAnd => (BinaryOp::BitAnd, 13),
+        Token::ShiftLeft => (BinaryOp::ShiftLeft, 15),
+        Token::ShiftRight => (BinaryOp::ShiftRight, 15),
+        Token::Plus => (BinaryOp::Add, 17),
+        Token::Minus => (BinaryOp::Subtract, 17),
+        Token::Multiply => (BinaryOp::Multiply, 19),
+        Token::Divide => (BinaryOp::Divide, 19),
+        Token::PlusMinus => (BinaryOp::PlusMinus, 21),
+        Token::FloorDivide => (BinaryOp::FloorDivide, 19),
+        Token::Modulo => (BinaryOp::Remainder, 19),
+        Token::Power => return Some((BinaryOp::Power, 24, 23)),
+        _ => return None,
+    };
+    Some((op, left, left + 1))
+}
+
+/// Operands joined by the operators that bind at least `min_power` tightly, each
+/// taking as much of what follows as binds tighter than it (a Pratt parser).
+fn parse_binary(tokens: &[Token], pos: usize, min_power: u8) -> EvalResult<(Expr, usize)> {
+    let (mut left, mut pos) = parse_prefix(tokens, pos)?;
+    // The right operand of the last comparison, which a chained one compares again
+    let mut compared: Option<Expr> = None;
+    while let Some((op, left_power, right_power)) = tokens.get(pos).and_then(binding_power) {
+        if left_power < min_power {
+            break;
+        }
+        pos += 1;
+        if let (
+            BinaryOp::Add | BinaryOp::Subtract,
+            Some(Token::Number(percent) | Token::Literal(percent, _)),
+            Some(Token::PercentOf),
+        ) = (op, tokens.get(pos), tokens.get(pos + 1))
+        {
+            left = Expr::PercentOf {
+                total: Box::new(left),
+                percent: *percent,
+                subtract: op == BinaryOp::Subtract,
+            };
+            pos += 2;
+            continue;
+        }
+        let (right, next_pos) = parse_binary(tokens, pos, right_power)?;
+        pos = next_pos;
+        if !op.is_comparison() {
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+            compared = None;
+            continue;
+        }
+        // `a < b < c` is `a < b and b < c`, as in written maths
+        let next = right.clone();
+        left = match compared.replace(next) {
+            Some(middle) => {
+                let comparison = Expr::Binary(op, Box::new(middle), Box::new(right));
+                Expr::Binary(BinaryOp::And, Box::new(left), Box::new(comparison))
+            }
+            None => Expr::Binary(op, Box::new(left), Box::new(right)),
+        };
+    }
+    Ok((left, pos))
+}
+
+/// An operand after any number of prefix `-`, `~` and `not`.
+fn parse_prefix(tokens: &[Token], pos: usize) -> EvalResult<(Expr, usize)> {
+    let (op, power): (fn(Box<Expr>) -> Expr, u8) = match tokens.get(pos) {
+        Some(Token::Minus) => (Expr::Negate, PREFIX_POWER),
+        Some(Token::Complement) => (Expr::Complement, PREFIX_POWER),
+        Some(Token::Not) => (Expr::Not, NOT_POWER),
+        _ => return parse_postfix(tokens, pos),
+    };
+    let (value, new_pos) = parse_binary(tokens, pos + 1, power)?;
+    Ok((op(Box::new(value)), new_pos))
+}
+
+/// A primary followed by any number of `!`, `%` and `%o`.
+fn parse_postfix(tokens: &[Token], pos: usize) -> EvalResult<(Expr, usize)> {
+    let (mut value, mut pos) = parse_primary(tokens, pos)?;
+    loop {
+        let op = match tokens.get(pos) {
+            Some(Token::Factorial) => PostfixOp::Factorial,
+            Some(Token::Percent | Token::PercentOf) => PostfixOp::Percent,
+            Some(Token::Permille) => PostfixOp::Permille,
+            _ => break,
+        };
+        value = Expr::Postfix(op, Box::new(value));
+        pos += 1;
+    }
+    Ok((value, pos))
+}
+
+fn parse_primary(tokens: &[Token], pos: usize) -> EvalResult<(Expr, usize)> {
+    if pos >= tokens.len() {
+        return Err(EvalError::UnexpectedEnd {
+            expected: EXPECTED_OPERAND,
+        });
+    }
+
+    match &tokens[pos] {
+        Token::Number(n) => Ok((Expr::Number(*n), pos + 1)),
+        Token::Literal(n, text) => Ok((Expr::Literal(*n, text.clone()), pos + 1)),
+        Token::LParen => {
+            let (value, new_pos) = parse_expression(tokens, pos + 1)?;
+            if new_pos >= tokens.len() || !matches!(tokens[new_pos], Token::RParen) {
+                return Err(EvalError::MissingParen { pos: new_pos });
+            }
+            Ok((value, new_pos + 1))
+        }
+        Token::LBracket => parse_list(tokens, pos + 1),
+        Token::Text(text) => dates::parse_date(text)
+            .map(Expr::Date)
+            .or_else(|| dates::parse_time(text).map(Expr::Time))
+            .map(|date| (date, pos + 1))
+            .ok_or_else(|| EvalError::InvalidDate(text.clone())),
+        Token::Ident(name) => {
+            // function call: ident '(' expr (',' expr)* ')'
+            if pos + 1 < tokens.len() && matches!(tokens[pos + 1], Token::LParen) {
+                if let Some(&func) = INTERVAL_FUNCTIONS.iter().find(|f| **f == name) {
+                    return parse_interval_function(func, tokens, pos + 2);
+                }
+                if name == "convert" {
+                    return parse_convert(tokens, pos + 2);
+                }
+                if let Some(&func) = SERIES_FUNCTIONS.iter().find(|f| **f == name) {
+                    return parse_series(func, tokens, pos + 2);
+                }
+                if name == "solve" {
+                    return parse_solve(tokens, pos + 2);
+                }
+                if name == "if" {
+                    return parse_if(tokens, pos + 2);
+                }
+                let (args, np) = parse_arguments(tokens, pos + 2)?; // skip ident + '('
+                Ok((Expr::Call(name.clone(), args), np))
+            } else {
+                Ok((Expr::Variable(name.clone()), pos + 1))
+            }
+        }
+        _ => Err(EvalError::Syntax {
+            pos,
+            expected: EXPECTED_OPERAND,
+        }),
+    }
+}
+
+/// What ends each value of a list.
+const EXPECTED_LIST_END: &str = "a , or ]";
+
+/// Parse a list's comma-separated values, from after its `[` through the `]`.
+fn parse_list(tokens: &[Token], pos: usize) -> EvalResult<(Expr, usize)> {
+    let mut items = Vec::new();
+    if let Some(Token::RBracket) = tokens.get(pos) {
+        return Ok((Expr::List(items), pos + 1));
+    }
+    let mut pos = pos;
+    loop {
+        let (value, np) = parse_expression(tokens, pos)?;
+        items.push(value);
+        match tokens.get(np) {
+            Some(Token::Comma) => pos = np + 1,
+            Some(Token::RBracket) => return Ok((Expr::List(items), np + 1)),
+            Some(_) => {
+                return Err(EvalError::Syntax {
+                    pos: np,
+                    expected: EXPECTED_LIST_END,
+                });
+            }
+            None => {
+                return Err(EvalError::UnexpectedEnd {
+                    expected: EXPECTED_LIST_END,
+                });
+            }
+        }
+    }
+}
+
+/// Parse comma-separated arguments up to and including the closing parenthesis.
+fn parse_arguments(tokens: &[Token], pos: usize) -> EvalResult<(Vec<Expr>, usize)> {
+    let mut args = Vec::new();
+    if let Some(Token::RParen) = tokens.get(pos) {
+        return Ok((args, pos + 1));
+    }
+    let mut pos = pos;
+    loop {
+        let (value, np) = parse_expression(tokens, pos)?;
+        args.push(value);
+        match tokens.get(np) {
+            Some(Token::Comma) => pos = np + 1,
+            Some(Token::RParen) => return Ok((args, np + 1)),
+            _ => return Err(EvalError::MissingParen { pos: np }),
+        }
+    }
+}
+
+/// Parse `fmin`, `fmax` or `fmean` from the start of its first argument, `body`,
+/// through the closing parenthesis.
+fn parse_interval_function(
+    func: &'static str,
+    tokens: &[Token],
+    body: usize,
+) -> EvalResult<(Expr, usize)> {
+    let (body, end) = parse_expression(tokens, body)?;
+    let (bounds, np) = match tokens.get(end) {
+        Some(Token::Comma) => parse_arguments(tokens, end + 1)?,
+        Some(Token::RParen) => (Vec::new(), end + 1),
+        Some(_) => {
+            return Err(EvalError::Syntax {
+                pos: end,
+                expected: EXPECTED_OPERATOR,
+            });
+        }
+        None => return Err(EvalError::MissingParen { pos: end }),
+    };
+    check_arity(func, bounds.len() + 1)?;
+    let Ok([a, b]) = <[Expr; 2]>::try_from(bounds) else {
+        unreachable!("interval functions take three arguments");
+    };
+    let tree = Expr::Interval {
+        func,
+        body: Box::new(body),
+        a: Box::new(a),
+        b: Box::new(b),
+    };
+    Ok((tree, np))
+}
+
+/// What the first argument of `sum` and `prod` has to be.
+const EXPECTED_COUNTER: &str = "a name to count with, like k";
+
+/// Parse `sum` or `prod` from the start of its first argument, the counter, through
+/// the closing parenthesis. Given just one argument, it is a list to add up or multiply.
+fn parse_series(func: &'static str, tokens: &[Token], start: usize) -> EvalResult<(Expr, usize)> {
+    if !matches!(
+        (tokens.get(start), tokens.get(start + 1)),
+        (Some(Token::Ident(_)), Some(Token::Comma))
+    ) {
+        let (args, np) = parse_arguments(tokens, start)?;
+        if args.len() == 1 {
+            return Ok((Expr::Call(func.to_string(), args), np));
+        }
+    }
+    let counter = match tokens.get(start) {
+        Some(Token::Ident(name)) if !is_function(name) => name.clone(),
+        Some(_) => {
+            return Err(EvalError::Syntax {
+                pos: start,
+                expected: EXPECTED_COUNTER,
+            });
+        }
+        None => {
+            return Err(EvalError::UnexpectedEnd {
+                expected: EXPECTED_COUNTER,
+            });
+        }
+    };
+    let (args, np) = match tokens.get(start + 1) {
+        Some(Token::Comma) => parse_arguments(tokens, start + 2)?,
+        Some(Token::RParen) => (Vec::new(), start + 2),
+        Some(_) => {
+            return Err(EvalError::Syntax {
+                pos: start + 1,
+                expected: ",",
+            });
+        }
+        None => return Err(EvalError::MissingParen { pos: start + 1 }),
+    };
+    check_arity(func, args.len() + 1)?;
+    let Ok([from, to, body]) = <[Expr; 3]>::try_from(args) else {
+        unreachable!("sum and prod take four arguments");
+    };
+    let tree = Expr::Series {
+        func,
+        counter,
+        from: Box::new(from),
+        to: Box::new(to),
+        body: Box::new(body),
+    };
+    Ok((tree, np))
+}
+
+/// Parse `if` from the start of its first argument, the condition, through the closing
+/// parenthesis.
+fn parse_if(tokens: &[Token], start: usize) -> EvalResult<(Expr, usize)> {
+    let (args, np) = parse_arguments(tokens, start)?;
+    check_arity("if", args.len())?;
+    let Ok([condition, then, otherwise]) = <[Expr; 3]>::try_from(args) else {
+        unreachable!("if takes three arguments");
+    };
+    let tree = Expr::If {
+        condition: Box::new(condition),
+        then: Box::new(then),
+        otherwise: Box::new(otherwise),
+    };
+    Ok((tree, np))
+}
+
+/// What the second argument of `solve` has to be.
+const EXPECTED_UNKNOWN: &str = "the name to solve for, like x";
+
+/// Parse `solve` from the start of its first argument, the expression, through the
+/// closing parenthesis.
+fn parse_solve(tokens: &[Token], start: usize) -> EvalResult<(Expr, usize)> {
+    let (body, end) = parse_expression(tokens, start)?;
+    match tokens.get(end) {
+        Some(Token::Comma) => {}
+        Some(Token::RParen) => {
+            check_arity("solve", 1)?;
+            unreachable!("solve takes at least two arguments");
+        }
+        Some(_) => {
+            return Err(EvalError::Syntax {
+                pos: end,
+                expected: EXPECTED_OPERATOR,
+            });
+        }
+        None => return Err(EvalError::MissingParen { pos: end }),
+    }
+    let var = match tokens.get(end + 1) {
+        Some(Token::Ident(name)) if !is_function(name) => name.clone(),
+        Some(_) => {
+            return Err(EvalError::Syntax {
+                pos: end + 1,
+                expected: EXPECTED_UNKNOWN,
+            });
+        }
+        None => {
+            return Err(EvalError::UnexpectedEnd {
+                expected: EXPECTED_UNKNOWN,
+            });
+        }
+    };
+    let (start, np) = match tokens.get(end + 2) {
+        Some(Token::Comma) => parse_arguments(tokens, end + 3)?,
+        Some(Token::RParen) => (Vec::new(), end + 3),
+        Some(_) => {
+            return Err(EvalError::Syntax {
+                pos: end + 2,
+                expected: ",",
+            });
+        }
+        None => return Err(EvalError::MissingParen { pos: end + 2 }),
+    };
+    check_arity("solve", start.len() + 2)?;
+    let tree = Expr::Solve {
+        var,
+        body: Box::new(body),
+        start,
+    };
+    Ok((tree, np))
+}
+
+/// What a unit argument of `convert` has to be.
+const EXPECTED_UNIT: &str = "a unit in quotes, like \"km\"";
+
+/// Parse `convert` from the start of its first argument, the value, through the closing
+/// parenthesis. The units must be quoted and measure the same thing.
+fn parse_convert(tokens: &[Token], start: usize) -> EvalResult<(Expr, usize)> {
+    let (value, mut pos) = parse_expression(tokens, start)?;
+    let mut units = Vec::with_capacity(2);
+    loop {
+        match tokens.get(pos) {
+            Some(Token::Comma) => {
+                if units.len() == 2 {
+                    check_arity("convert", 4)?;
+                }
+                pos += 1;
+            }
+            Some(Token::RParen) => {
+                check_arity("convert", units.len() + 1)?;
+                break;
+            }
+            Some(_) => {
+                return Err(EvalError::Syntax {
+                    pos,
+                    expected: EXPECTED_OPERATOR,
+                });
+            }
+            None => return Err(EvalError::MissingParen { pos }),
+        }
+        match tokens.get(pos) {
+            Some(Token::Text(text)) => {
+                let unit =
+                    units::find_unit(text).ok_or_else(|| EvalError::UnknownUnit(text.clone()))?;
+                units.push(unit);
+                pos += 1;
+            }
+            Some(_) => {
+                return Err(EvalError::Syntax {
+                    pos,
+                    expected: EXPECTED_UNIT,
+                });
+            }
+            None => {
+                return Err(EvalError::UnexpectedEnd {
+                    expected: EXPECTED_UNIT,
+                });
+            }
+        }
+    }
+    let &[from, to] = units.as_slice() else {
+        unreachable!("convert takes two units");
+    };
+    if from.category != to.category {
+        return Err(EvalError::IncompatibleUnits {
+            from: from.symbol,
+            to: to.symbol,
+        });
+    }
+    let tree = Expr::Convert {
+        value: Box::new(value),
+        from,
+        to,
+    };
+    Ok((tree, pos + 1))
+}
+
+/// Split `expr` into tokens, along with the column each one starts at. Implicit
+/// multiplications take the column of the token after them.
+pub(crate) fn tokenize(
+    expr: &str,
+    percent: PercentBehavior,
+) -> EvalResult<(Vec<Token>, Vec<usize>)> {
+    let mut tokens = Vec::new();
+    let mut offsets = Vec::new();
+    let mut chars = expr.chars().enumerate().peekable();
+    let mut num_buf = String::new();
+    let mut num_start = 0;
+    let mut ident_buf = String::new();
+
+    while let Some(&(at, ch)) = chars.peek() {
+        match ch {
+            '0' if num_buf.is_empty() && radix_prefix(chars.clone().skip(1)).is_some() => {
+                let value = radix_literal(&mut chars)?;
+                tokens.push(Token::Number(value));
+                offsets.push(at);
+            }
+            '0'..='9' | '.' => {
+                if num_buf.is_empty() {
+                    num_start = at;
+                }
+                num_buf.push(ch);
+                chars.next();
+            }
+            // `1_000_000`; an underscore only groups digits
+            '_' if !num_buf.is_empty()
+                && chars
+                    .clone()
+                    .nth(1)
+                    .is_some_and(|(_, c)| c.is_ascii_digit()) =>
+            {
+                chars.next();
+            }
+            // `1e6`, `2.5E-4`; an `e` not followed by an exponent is Euler's number
+            'e' | 'E' if !num_buf.is_empty() && starts_exponent(chars.clone().skip(1)) => {
+                num_buf.push('e');
+                chars.next();
+                if let Some((_, sign)) = chars.next_if(|&(_, c)| c == '+' || c == '-') {
+                    num_buf.push(sign);
+                }
+            }
+            'a'..='z' | 'A'..='Z' | 'π' => {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                ident_buf.push(ch);
+                chars.next();
+                // collect full identifier
+                while let Some(&(_, nc)) = chars.peek() {
+                    if nc.is_alphanumeric() || nc == '_' {
+                        ident_buf.push(nc);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let ident = ident_buf.to_lowercase();
+                ident_buf.clear();
+                offsets.push(at);
+                match ident.as_str() {
+                    // constants
+                    "pi" | "π" => tokens.push(Token::Number(std::f64::consts::PI)),
+                    "e" => tokens.push(Token::Number(std::f64::consts::E)),
+                    // `xor` between operands is the operator; elsewhere it's the function
+                    "xor" if tokens.last().is_some_and(Token::ends_operand) => {
+                        tokens.push(Token::BitXor)
+                    }
+                    // So are `and` and `or`, which are logical where `and()` and `or()`
+                    // are bitwise
+                    "and" if tokens.last().is_some_and(Token::ends_operand) => {
+                        tokens.push(Token::And)
+                    }
+                    "or" if tokens.last().is_some_and(Token::ends_operand) => {
+                        tokens.push(Token::Or)
+                    }
+                    "not" => tokens.push(Token::Not),
+                    // recognized function names become identifiers; parsing will handle call
+                    _ => tokens.push(Token::Ident(ident)),
+                }
+            }
+            '%' => {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                chars.next();
+                let rest = chars.clone().map(|(_, c)| c).collect::<String>();
+                let mut after = rest.chars();
+                offsets.push(at);
+                if after.next() == Some('o')
+                    && !after
+                        .next()
+                        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+                {
+                    // `%o` is per mille in every mode
+                    chars.next();
+                    tokens.push(Token::Permille);
+                } else {
+                    tokens.push(percent_token(&tokens, rest.trim_start(), percent));
+                }
+            }
+            '=' | '!' if chars.clone().nth(1).is_some_and(|(_, c)| c == '=') => {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                chars.nth(1);
+                tokens.push(if ch == '=' {
+                    Token::Equal
+                } else {
+                    Token::NotEqual
+                });
+                offsets.push(at);
+            }
+            // `+/-` spells `±` where there's no key for it
+            '±' | '+'
+                if ch == '±' || chars.clone().skip(1).map(|(_, c)| c).take(2).eq(['/', '-']) =>
+            {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                chars.next();
+                if ch == '+' {
+                    chars.nth(1);
+                }
+                tokens.push(Token::PlusMinus);
+                offsets.push(at);
+            }
+            '+' | '-' | '*' | '/' | '^' | '!' | '(' | ')' | '[' | ']' | ',' => {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                chars.next();
+                // Python's `**` and `//`
+                let doubled = matches!(ch, '*' | '/') && chars.next_if(|&(_, c)| c == ch).is_some();
+                tokens.push(match ch {
+                    '+' => Token::Plus,
+                    '-' => Token::Minus,
+                    '*' if doubled => Token::Power,
+                    '/' if doubled => Token::FloorDivide,
+                    '*' => Token::Multiply,
+                    '/' => Token::Divide,
+                    '^' => Token::Power,
+                    '!' => Token::Factorial,
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    ',' => Token::Comma,
+                    _ => unreachable!(),
+                });
+                offsets.push(at);
+            }
+            '&' | '|' | '~' | '<' | '>' => {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                chars.next();
+                let token = match ch {
+                    '&' => Token::BitAnd,
+                    '|' => Token::BitOr,
+                    '~' => Token::Complement,
+                    // Shifts are doubled, and the comparisons may take an `=`
+                    _ if chars.next_if(|&(_, c)| c == ch).is_some() => match ch {
+                        '<' => Token::ShiftLeft,
+                        _ => Token::ShiftRight,
+                    },
+                    _ if chars.next_if(|&(_, c)| c == '=').is_some() => match ch {
+                        '<' => Token::LessEqual,
+                        _ => Token::GreaterEqual,
+                    },
+                    '<' => Token::Less,
+                    _ => Token::Greater,
+                };
+                tokens.push(token);
+                offsets.push(at);
+            }
+            '"' => {
+                flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => text.push(c),
+                        None => {
+                            return Err(EvalError::UnexpectedEnd {
+                                expected: "a closing \"",
+                            });
+                        }
+                    }
+                }
+                tokens.push(Token::Text(text));
+                offsets.push(at);
+            }
+            ' ' => {
+                chars.next();
+            }
+            _ => {
+                return Err(EvalError::InvalidCharacter(ch));
+            }
+        }
+    }
+
+    flush_number(&mut num_buf, num_start, &mut tokens, &mut offsets)?;
+
+    Ok(insert_implicit_multiplication(tokens, offsets))
+}
+
+/// What the implicit multiplication rules see of a token.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    /// A number or constant.
+    Number,
+    /// A name that isn't a function, such as a variable.
+    Name,
+    /// A function's name, which a `(` after calls rather than multiplies.
+    Function,
+    /// `(` or `[`, which starts an operand.
+    Open,
+    /// `)` or `]`, which ends one.
+    Close,
+}
+
+/// Adjacent pairs read as a product when nothing is written between them. Checked in
+/// one pass over neighbouring tokens, so each rule only ever sees two tokens; nothing
+/// here matches `*`, which keeps the pass idempotent.
+const IMPLICIT_MULTIPLY: &[(Operand, Operand)] = &[
+    // 3(x+1) -> 3*(x+1)
+    (Operand::Number, Operand::Open),
+    // (x+1)3 -> (x+1)*3
+    (Operand::Close, Operand::Number),
+    // (a+b)(a-b) -> (a+b)*(a-b)
+    (Operand::Close, Operand::Open),
+    // 2pi -> 2*pi, as pi is already a number
+    (Operand::Number, Operand::Number),
+    // 2x -> 2*x
+    (Operand::Number, Operand::Name),
+    // 3sin(x) -> 3*sin(x)
+    (Operand::Number, Operand::Function),
+    // (x+1)x -> (x+1)*x
+    (Operand::Close, Operand::Name),
+    // (x+1)sin(x) -> (x+1)*sin(x)
+    (Operand::Close, Operand::Function),
+    // x(x+1) -> x*(x+1), while sin(x) stays a call
+    (Operand::Name, Operand::Open),
+];
+
+impl Token {
+    fn operand(&self) -> Option<Operand> {
+        match self {
+            Token::Number(_) | Token::Literal(..) => Some(Operand::Number),
+            Token::Ident(name) if is_function(name) => Some(Operand::Function),
+            Token::Ident(_) => Some(Operand::Name),
+            Token::LParen | Token::LBracket => Some(Operand::Open),
+            Token::RParen | Token::RBracket => Some(Operand::Close),
+            _ => None,
+        }
+    }
+
+    /// Whether an operand can end with this token, so that what follows it is an
+    /// operator.
+    pub(crate) fn ends_operand(&self) -> bool {
+        matches!(
+            self,
+            Token::Number(_)
+                | Token::Literal(..)
+                | Token::Ident(_)
+                | Token::RParen
+                | Token::RBracket
+                | Token::Factorial
+                | Token::Percent
+                | Token::PercentOf
+                | Token::Permille
+                | Token::Text(_)
+        )
+    }
+}
+
+/// Whether `IMPLICIT_MULTIPLY` puts a `*` between `left` and `right`.
+fn implies_multiply(left: &Token, right: &Token) -> bool {
+    left.operand()
+        .zip(right.operand())
+        .is_some_and(|pair| IMPLICIT_MULTIPLY.contains(&pair))
+}
+
+/// Insert a `*` between each pair matched by `IMPLICIT_MULTIPLY`, at the column of the
+/// token after it.
+fn insert_implicit_multiplication(
+    tokens: Vec<Token>,
+    offsets: Vec<usize>,
+) -> (Vec<Token>, Vec<usize>) {
+    let mut result: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut result_offsets = Vec::with_capacity(offsets.len());
+    for (token, at) in tokens.into_iter().zip(offsets) {
+        if result
+            .last()
+            .is_some_and(|last| implies_multiply(last, &token))
+        {
+            result.push(Token::Multiply);
+            result_offsets.push(at);
+        }
+        result.push(token);
+        result_offsets.push(at);
+    }
+    (result, result_offsets)
+}
+
+/// The radix a literal's prefix letter gives, from the text after its leading `0`, as
+/// (column, char) pairs. `None` unless a digit of that radix follows the letter, so
+/// `0x` alone is still 0 next to `x`.
+fn radix_prefix(mut rest: impl Iterator<Item = (usize, char)>) -> Option<u32> {
+    let radix = match rest.next()?.1 {
+        'x' | 'X' => 16,
+        'b' | 'B' => 2,
+        'o' | 'O' => 8,
+        _ => return None,
+    };
+    rest.next()
+        .is_some_and(|(_, c)| c.is_digit(radix))
+        .then_some(radix)
+}
+
+/// Read a `0x`, `0b` or `0o` literal from `chars`, which starts at its `0`.
+fn radix_literal(
+    chars: &mut std::iter::Peekable<impl Iterator<Item = (usize, char)> + Clone>,
+) -> EvalResult<f64> {
+    let radix = radix_prefix(chars.clone().skip(1)).unwrap_or(10);
+    let mut text = String::new();
+    text.extend(chars.next().map(|(_, c)| c));
+    text.extend(chars.next().map(|(_, c)| c));
+    let digits_start = text.len();
+    // Letters, digits and points run on to the end of the literal, so `0b102` is one
+    // bad number rather than 0b10 next to 2, and `0x1.8` one rather than 0x1 times .8
+    while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '.') {
+        text.push(c);
+    }
+    u64::from_str_radix(&text[digits_start..], radix)
+        .map(|value| value as f64)
+        .map_err(|_| EvalError::InvalidNumber(text))
+}
+
+/// Whether the text after an `e` in a number, as (column, char) pairs, is its
+/// exponent: digits, optionally signed.
+fn starts_exponent(mut rest: impl Iterator<Item = (usize, char)>) -> bool {
+    match rest.next() {
+        Some((_, '+' | '-')) => rest.next().is_some_and(|(_, c)| c.is_ascii_digit()),
+        Some((_, c)) => c.is_ascii_digit(),
+        None => false,
+    }
+}
+
+/// Push the digits collected so far, which started at column `at`, as a number token.
+fn flush_number(
+    buf: &mut String,
+    at: usize,
+    tokens: &mut Vec<Token>,
+    offsets: &mut Vec<usize>,
+) -> EvalResult<()> {
+    if buf.is_empty() {
+        return Ok(());
+    }
+    let value = buf
+        .parse()
+        .map_err(|_| EvalError::InvalidNumber(buf.clone()))?;
+    tokens.push(Token::Literal(value, std::mem::take(buf)));
+    offsets.push(at);
+    Ok(())
+}
+
+/// Read a `%` given the tokens before it and the text after it.
+fn percent_token(before: &[Token], after: &str, percent: PercentBehavior) -> Token {
+    match percent {
+        PercentBehavior::Modulo => Token::Modulo,
+        PercentBehavior::Percent => Token::Percent,
+        PercentBehavior::Contextual => {
+            let operand_follows = after.starts_with(|c: char| {
+                c.is_ascii_digit() || c.is_alphabetic() || "(.π".contains(c)
+            });
+            let ends_sum = after.is_empty() || after.starts_with([')', '+', '-', ',']);
+            match before {
+                _ if operand_follows => Token::Modulo,
+                [
+                    ..,
+                    Token::Number(_)
+                    | Token::Literal(..)
+                    | Token::Ident(_)
+                    | Token::RParen
+                    | Token::Factorial,
+                    Token::Plus | Token::Minus,
+                    Token::Number(_) | Token::Literal(..),
+                ] if ends_sum => Token::PercentOf,
+                _ => Token::Percent,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Token {
+    /// A constant such as `pi`, or a hex, binary or octal literal.
+    Number(f64),
+    /// A decimal number as typed, with its text; see [`Expr::Literal`].
+    Literal(f64, String),
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    /// `//`: the floor of the quotient.
+    FloorDivide,
+    Power,
+    Modulo,
+    /// `±` and `+/-`.
+    PlusMinus,
+    Factorial,
+    /// Postfix `%`: divide by 100.
+    Percent,
+    /// Postfix `%` on a number added to or subtracted from a total: that percent of it.
+    PercentOf,
+    /// Postfix `%o`: divide by 1000.
+    Permille,
+    LParen,
+    RParen,
+    /// `[` and `]` around a list.
+    LBracket,
+    RBracket,
+    Comma,
+    /// `&`, `|` and `xor` between operands.
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// `<<` and `>>`.
+    ShiftLeft,
+    ShiftRight,
+    /// Prefix `~`.
+    Complement,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    /// `and` and `or` between operands.
+    And,
+    Or,
+    /// Prefix `not`.
+    Not,
+    Ident(String),
+    /// Text in double quotes, such as the units given to `convert`.
+    Text(String),
+}
+
+/// Whether `value` counts as true: anything but zero and NaN.
+fn is_true(value: f64) -> bool {
+    value != 0.0 && !value.is_nan()
+}
+
+fn factorial(n: f64) -> EvalResult<f64> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(EvalError::Domain {
+            func: "factorial",
+            arg: n,
+            reason: "Factorial needs a non-negative integer",
+        });
+    }
+    // 170! is the largest that fits in an f64
+    if n > 170.0 {
+        return Err(EvalError::Overflow {
+            log10: log10_factorial(n),
+        });
+    }
+    Ok((2..=n as u32).fold(1.0, |acc, k| acc * k as f64))
+}
+
+/// `value` when it's finite. An infinity is an overflow, about `log10` digits long where
+/// that's known and infinite where it isn't, and NaN the error `undefined` gives.
+fn finite(
+    value: f64,
+    log10: impl FnOnce() -> f64,
+    undefined: impl FnOnce() -> EvalError,
+) -> EvalResult<f64> {
+    if value.is_nan() {
+        Err(undefined())
+    } else if value.is_infinite() {
+        Err(EvalError::Overflow { log10: log10() })
+    } else {
+        Ok(value)
+    }
+}
+
+/// `value`, the result of `func` over many values, when it's finite; see [`finite`].
+fn totalled(func: &'static str, value: f64) -> EvalResult<f64> {
+    finite(
+        value,
+        || f64::INFINITY,
+        || EvalError::undefined(func, f64::NAN),
+    )
+}
+
+fn call_function(name: &str, args: &[f64], angle_mode: AngleMode) -> EvalResult<f64> {
+    if !is_function(name) {
+        return Err(EvalError::UnknownFunction(name.to_string()));
+    }
+    check_arity(name, args.len())?;
+    if let Some(&func) = BITWISE_FUNCTIONS.iter().find(|&&func| func == name) {
+        return programmer::call_bitwise(func, args);
+    }
+    if let Some(&func) = NUMBER_THEORY_FUNCTIONS.iter().find(|&&func| func == name) {
+        return number_theory::call_number_theory(func, args);
+    }
+    match (name, args) {
+        ("approx", &[a, b]) => return approx_equal(a, b, APPROX_TOLERANCE).map(truth),
+        ("approx", &[a, b, tolerance]) => return approx_equal(a, b, tolerance).map(truth),
+        ("log", &[base, arg]) => {
+            if base <= 0.0 || base == 1.0 {
+                return Err(EvalError::undefined("log", base));
+            }
+            if arg <= 0.0 {
+                return Err(EvalError::undefined("log", arg));
+            }
+            return Ok(log_base(base, arg));
+        }
+        ("round", &[arg]) => return round_decimals(arg, 0.0),
+        ("round", &[arg, digits]) => return round_decimals(arg, digits),
+        ("clamp", &[arg, lo, hi]) => return clamp(arg, lo, hi),
+        (_, &[a, b]) => {
+            return match name {
+                "pctdiff" => Ok(percent_difference(a, b)),
+                "pctchange" => percent_change(a, b),
+                "markup" => Ok(a * (1.0 + b / 100.0)),
+                "margin" => margin(a, b),
+                "sigfig" => round_sig_figs(a, b),
+                "ulps" => ulp_distance(a, b),
+                "atan2" => Ok(angle_mode.from_radians(a.atan2(b))),
+                _ => floored_mod(a, b),
+            };
+        }
+        _ => {}
+    }
+
+    let &[arg] = args else {
+        unreachable!("{} takes one argument", name);
+    };
+    // Arguments without a real result, rather than the NaN or infinity f64 gives
+    let outside = match name {
+        "asin" if arg.abs() > 1.0 => Some("asin"),
+        "acos" if arg.abs() > 1.0 => Some("acos"),
+        "sqrt" if arg < 0.0 => Some("sqrt"),
+        "log" if arg <= 0.0 => Some("log"),
+        "ln" if arg <= 0.0 => Some("ln"),
+        _ => None,
+    };
+    if let Some(func) = outside {
+        return Err(EvalError::undefined(func, arg));
+    }
+    let value = match name {
+        "sin" => angle_mode.to_radians(arg).sin(),
+        "cos" => angle_mode.to_radians(arg).cos(),
+        "tan" => angle_mode.to_radians(arg).tan(),
+        "asin" => angle_mode.from_radians(arg.asin()),
+        "acos" => angle_mode.from_radians(arg.acos()),
+        "atan" => angle_mode.from_radians(arg.atan()),
+        "sqrt" => arg.sqrt(),
+        "log" => arg.log10(),
+        "ln" => arg.ln(),
+        "exp" => arg.exp(),
+        "abs" => arg.abs(),
+        "percent" => arg / 100.0,
+        _ => match find_conversion(name) {
+            Some(conversion) => (conversion.apply)(arg),
+            None => return Err(EvalError::UnknownFunction(name.to_string())),
+        },
+    };
+    Ok(value)
+}
+
+pub(crate) fn call_text_function(name: &str, args: Vec<Value>) -> EvalResult<String> {
+    if !TEXT_FUNCTIONS.contains(&name) {
+        return Err(EvalError::UnknownFunction(name.to_string()));
+    }
+    check_arity(name, args.len())?;
+    let mut args = args.into_iter();
+    let mut next = || args.next().unwrap_or(Value::Number(0.0));
+    match name {
+        "ratio" => format_ratio(next().number()?, next().number()?),
+        "factor" => number_theory::factorize(next().number()?),
+        _ => dates::call_weekday(next()),
+    }
+}
+
+/// 1 for true and 0 for false, as comparisons give.
+fn truth(holds: bool) -> f64 {
+    if holds { 1.0 } else { 0.0 }
+}
+
+/// `arg`'s logarithm to `base`, exact where `log10` and `log2` are.
+fn log_base(base: f64, arg: f64) -> f64 {
+    if base == 10.0 {
+        arg.log10()
+    } else if base == 2.0 {
+        arg.log2()
+    } else {
+        arg.ln() / base.ln()
+    }
+}
+
+/// An [`EvalError::Arity`] unless `got` arguments suit `name`. The error expects the
+/// nearest count `name` takes.
+fn check_arity(name: &str, got: usize) -> EvalResult<()> {
+    let (fewest, most) = arity(name);
+    if (fewest..=most).contains(&got) {
+        return Ok(());
+    }
+    Err(EvalError::Arity {
+        name: name.to_string(),
+        expected: got.clamp(fewest, most),
+        got,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trees_are_evaluated_against_the_bindings_of_the_moment() {
+        let mut variables = BTreeMap::new();
+        variables.insert("x".to_string(), 2.0);
+        let tree = Evaluator::new(&variables)
+            .parse("exp(x) - max + 3(x+1)")
+            .unwrap();
+        variables.insert("max".to_string(), 1.0);
+        for x in [0.0, 1.5, -4.0] {
+            variables.insert("x".to_string(), x);
+            let value = Evaluator::new(&variables).eval(&tree).unwrap();
+            assert_eq!(value, x.exp() - 1.0 + 3.0 * (x + 1.0));
+        }
+
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        assert_eq!(evaluator.evaluate("sin(pi/2) + e^0"), Ok(2.0));
+        assert_eq!(evaluator.evaluate("  "), Ok(0.0));
+        assert_eq!(
+            evaluator.evaluate("x + 1"),
+            Err(EvalError::UndefinedVariable("x".to_string()))
+        );
+        // Two names can be bound at once, and a sum's counter doesn't hide either
+        let both = evaluator.bind("x", 2.0).bind("y", 3.0);
+        assert_eq!(both.evaluate("x * y"), Ok(6.0));
+        assert_eq!(both.evaluate("sum(n, 1, 2, x + y)"), Ok(10.0));
+        // Parsing finds malformed input before anything is evaluated
+        assert_eq!(
+            evaluator.parse("y 2"),
+            Err(EvalError::Syntax {
+                pos: 2,
+                expected: EXPECTED_OPERATOR
+            })
+        );
+    }
+
+    #[test]
+    fn constants_give_way_to_variables() {
+        let mut variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        assert_eq!(evaluator.evaluate("c"), Ok(299_792_458.0));
+        assert_eq!(evaluator.evaluate("tau / 2"), Ok(std::f64::consts::PI));
+        assert_eq!(
+            evaluator.evaluate("K_B * avogadro"),
+            Ok(1.380649e-23 * 6.02214076e23)
+        );
+        variables.insert("c".to_string(), 3.0);
+        let evaluator = Evaluator::new(&variables);
+        assert_eq!(evaluator.evaluate("c * g"), Ok(3.0 * 9.80665));
+        assert_eq!(evaluator.bind("g", 1.0).evaluate("g"), Ok(1.0));
+    }
+
+    #[test]
+    fn powers_group_to_the_right_and_bind_tighter_than_minus() {
+        let variables = BTreeMap::from([("x".to_string(), 3.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("2^3^2"), 512.0);
+        assert_eq!(value("2**3**2"), 512.0);
+        assert_eq!(value("(2^3)^2"), 64.0);
+        assert_eq!(value("-2^2"), -4.0);
+        assert_eq!(value("(-2)^2"), 4.0);
+        assert_eq!(value("-x^2"), -9.0);
+        assert_eq!(value("2x^2"), 18.0);
+        // A minus in the exponent takes only what binds tighter than it
+        assert_eq!(value("2^-1"), 0.5);
+        assert_eq!(value("2^-1^2"), 0.5);
+        assert_eq!(value("2^-1*4"), 2.0);
+        assert_eq!(value("-3!"), -6.0);
+        assert_eq!(value("2^3!"), 64.0);
+        // Everything else still groups to the left
+        assert_eq!(value("10-4-3"), 3.0);
+        assert_eq!(value("64/4/2"), 8.0);
+        assert_eq!(value("-2*3+1"), -5.0);
+        assert_eq!(value("1 - -2"), 3.0);
+    }
+
+    #[test]
+    fn bitwise_operators_bind_more_loosely_than_arithmetic() {
+        let variables = BTreeMap::from([("x".to_string(), 6.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("(0xFF << 4) | 0x0A"), 4090.0);
+        assert_eq!(value("1 + 2 << 3"), 24.0);
+        assert_eq!(value("256 >> 2 >> 1"), 32.0);
+        // `|` is loosest, then `xor`, then `&`
+        assert_eq!(value("1 | 6 & 3"), 3.0);
+        assert_eq!(value("5 xor 3 & 1"), 4.0);
+        assert_eq!(value("1 | 2 xor 3"), 1.0);
+        assert_eq!(value("~0"), -1.0);
+        assert_eq!(value("~x & 0xF"), 9.0);
+        assert_eq!(value("-~5"), 6.0);
+        assert_eq!(value("x xor 1"), 7.0);
+        // `xor` is still the function where no operand comes before it
+        assert_eq!(value("xor(x, 1) xor 1"), 6.0);
+        assert_eq!(value("3! & 2"), 2.0);
+
+        assert_eq!(
+            evaluator.evaluate("1.5 & 1").unwrap_err().to_string(),
+            "and() needs whole numbers, not 1.5"
+        );
+        assert_eq!(
+            evaluator.evaluate("1 << 64").unwrap_err().to_string(),
+            "shl() shifts whole numbers by 0 to 63 places"
+        );
+        // A lone `<` compares, more loosely than the bitwise operators
+        assert_eq!(value("3 < 1 << 2"), 1.0);
+        assert_eq!(value("6 == 4 | 2"), 1.0);
+        assert!(evaluator.evaluate("1 &").is_err());
+        assert!(evaluator.evaluate("| 1").is_err());
+    }
+
+    #[test]
+    fn convert_takes_quoted_units_of_one_kind() {
+        let variables = BTreeMap::from([("d".to_string(), 10.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("convert(100, \"C\", \"F\")"), 212.0);
+        assert_eq!(value("convert(d / 2, \"ft\", \"in\") + 1"), 61.0);
+        assert_eq!(value("2 * convert(1, \"KiB\", \"byte\")"), 2048.0);
+        assert_eq!(value("convert(1852, \"m\", \"nautical mile\")"), 1.0);
+
+        assert_eq!(
+            evaluator.evaluate("convert(1, \"km\", \"kg\")"),
+            Err(EvalError::IncompatibleUnits {
+                from: "km",
+                to: "kg"
+            })
+        );
+        assert_eq!(
+            evaluator.evaluate("convert(1, \"km\", \"furlong\")"),
+            Err(EvalError::UnknownUnit("furlong".to_string()))
+        );
+        assert_eq!(
+            evaluator.evaluate("convert(1, km, \"mi\")"),
+            Err(EvalError::Syntax {
+                pos: 11,
+                expected: EXPECTED_UNIT
+            })
+        );
+        assert_eq!(
+            evaluator.evaluate("convert(1, \"km\")"),
+            Err(EvalError::Arity {
+                name: "convert".to_string(),
+                expected: 3,
+                got: 2
+            })
+        );
+        assert!(matches!(
+            evaluator.evaluate("convert(1, \"km\", \"mi\", 2)"),
+            Err(EvalError::Arity { got: 4, .. })
+        ));
+        assert_eq!(
+            evaluator.evaluate("convert(1, \"km"),
+            Err(EvalError::UnexpectedEnd {
+                expected: "a closing \""
+            })
+        );
+        // Text only makes sense as a unit
+        assert!(evaluator.evaluate("\"km\" + 1").is_err());
+    }
+
+    #[test]
+    fn names_multiply_without_a_star() {
+        let variables = BTreeMap::from([("x".to_string(), 3.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("2x"), 6.0);
+        assert_eq!(value("2pi"), std::f64::consts::TAU);
+        assert_eq!(value("3sin(0)+1"), 1.0);
+        assert_eq!(value("x(x+1)"), 12.0);
+        assert_eq!(value("(x-1)x^2"), 18.0);
+        assert_eq!(value("2tau"), 2.0 * std::f64::consts::TAU);
+        // Function names are still called, and a name before one still needs an operator
+        assert_eq!(value("sqrt(x+1)"), 2.0);
+        assert!(matches!(
+            evaluator.evaluate("x 2"),
+            Err(EvalError::Syntax { pos: 2, .. })
+        ));
+        assert_eq!(
+            evaluator.evaluate("y(2)"),
+            Err(EvalError::UndefinedVariable("y".to_string()))
+        );
+    }
+
+    #[test]
+    fn exponents_are_read_only_where_digits_follow_the_e() {
+        let variables = BTreeMap::from([("x".to_string(), 2.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("1e6"), 1e6);
+        assert_eq!(value("1.5e-3"), 1.5e-3);
+        assert_eq!(value("2.5E+4"), 2.5e4);
+        assert_eq!(value(".5e1"), 5.0);
+        // Anything else after the e leaves it Euler's number
+        assert_eq!(value("e"), std::f64::consts::E);
+        assert_eq!(value("2*e"), 2.0 * std::f64::consts::E);
+        assert_eq!(value("e^2"), std::f64::consts::E.powi(2));
+        assert_eq!(value("2(e-1)"), 2.0 * (std::f64::consts::E - 1.0));
+        assert_eq!(value("2e"), 2.0 * std::f64::consts::E);
+        assert_eq!(value("2e-x"), 2.0 * std::f64::consts::E - 2.0);
+        assert_eq!(
+            evaluator.evaluate("1e2.5"),
+            Err(EvalError::InvalidNumber("1e2.5".to_string()))
+        );
+    }
+
+    #[test]
+    fn hex_binary_and_octal_literals() {
+        let variables = BTreeMap::from([("x".to_string(), 2.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("0xFF"), 255.0);
+        assert_eq!(value("0Xff + 0b1010"), 265.0);
+        assert_eq!(value("0o755"), 493.0);
+        assert_eq!(value("0x1e5"), 485.0);
+        assert_eq!(value("0x10(2)"), 32.0);
+        assert_eq!(value("0.5"), 0.5);
+        for bad in [
+            "0b102",
+            "0o78",
+            "0xFG",
+            "0x10000000000000000",
+            "0x1.8",
+            "0x10.5",
+            "0b11.1",
+        ] {
+            assert_eq!(
+                evaluator.evaluate(bad),
+                Err(EvalError::InvalidNumber(bad.to_string())),
+                "{}",
+                bad
+            );
+        }
+        // Without a digit after it, the letter is a name of its own
+        assert_eq!(value("0x"), 0.0);
+        assert_eq!(value("0x(3)"), 0.0);
+        assert_eq!(
+            crate::calculator::radix_literals("0xff*2 + 0o7 - 10"),
+            [("0xff".to_string(), 255.0), ("0o7".to_string(), 7.0)]
+        );
+    }
+
+    /// A small xorshift generator, so the properties below see many inputs but the same
+    /// ones on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn implicit_multiplication_only_adds_products_between_operands() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let multiplies = |tokens: &[Token]| {
+            tokens
+                .windows(2)
+                .filter(|pair| matches!(pair, [Token::Multiply, Token::Multiply]))
+                .count()
+        };
+        for _ in 0..2000 {
+            let len = rng.below(12);
+            let tokens: Vec<Token> = (0..len)
+                .map(|_| match rng.below(7) {
+                    0 => Token::Number(rng.below(10) as f64),
+                    1 => Token::LParen,
+                    2 => Token::RParen,
+                    3 => Token::Multiply,
+                    4 => Token::Plus,
+                    5 => Token::Ident("sin".to_string()),
+                    _ => Token::Ident("x".to_string()),
+                })
+                .collect();
+            let (once, offsets) =
+                insert_implicit_multiplication(tokens.clone(), (0..len).collect());
+            let matched = tokens
+                .windows(2)
+                .filter(|pair| implies_multiply(&pair[0], &pair[1]))
+                .count();
+            assert_eq!(once.len(), tokens.len() + matched, "{:?}", tokens);
+            assert_eq!(offsets.len(), once.len());
+            assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert_eq!(multiplies(&once), multiplies(&tokens), "{:?}", tokens);
+
+            let (twice, _) = insert_implicit_multiplication(once.clone(), offsets);
+            assert_eq!(format!("{:?}", twice), format!("{:?}", once));
+        }
+
+        let variables = BTreeMap::from([("x".to_string(), 1.5)]);
+        let evaluator = Evaluator::new(&variables);
+        for _ in 0..500 {
+            let a = format!("{}.{}", rng.below(1000), rng.below(100));
+            let b = format!(
+                "{}{}.{}",
+                ["", "-"][rng.below(2)],
+                rng.below(1000),
+                rng.below(10)
+            );
+            for (implicit, explicit) in [
+                (format!("{}({})", a, b), format!("{}*({})", a, b)),
+                (format!("({}){}", b, a), format!("({})*{}", b, a)),
+                (format!("({})({})", a, b), format!("({})*({})", a, b)),
+                (format!("{}x", a), format!("{}*x", a)),
+                (format!("{}sin({})", a, b), format!("{}*sin({})", a, b)),
+                (format!("({})x", b), format!("({})*x", b)),
+                (format!("x({})", b), format!("x*({})", b)),
+            ] {
+                assert_eq!(
+                    evaluator.evaluate(&implicit),
+                    evaluator.evaluate(&explicit),
+                    "{}",
+                    implicit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn grouped_digits_read_as_one_number() {
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        assert_eq!(
+            evaluator.evaluate("1_000_000 + 0.000_5"),
+            Ok(1_000_000.000_5)
+        );
+        // Commas only ever separate arguments
+        assert_eq!(
+            evaluator.evaluate("1,234"),
+            Err(EvalError::InvalidCharacter(','))
+        );
+        assert_eq!(evaluator.evaluate("max(1,234)"), Ok(234.0));
+    }
+
+    #[test]
+    fn comparisons_and_logic_give_one_or_zero() {
+        let variables = BTreeMap::from([("x".to_string(), -3.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.evaluate(expr).unwrap();
+        assert_eq!(value("2 < 3"), 1.0);
+        assert_eq!(value("2 >= 3"), 0.0);
+        assert_eq!(value("1 + 1 == 2"), 1.0);
+        // `!=` is one operator, so a factorial compared needs a space
+        assert_eq!(value("5!=120"), 1.0);
+        assert_eq!(value("5! == 120"), 1.0);
+        assert_eq!(value("1 == 2 or 1 != 1"), 0.0);
+        // Chains compare each neighbour, as in written maths
+        assert_eq!(value("3 > 2 > 1"), 1.0);
+        assert_eq!(value("-5 < x <= -4"), 0.0);
+        // `and` binds tighter than `or`, and `not` takes a whole comparison
+        assert_eq!(value("1 or 0 and 0"), 1.0);
+        assert_eq!(value("not x < 0"), 0.0);
+        assert_eq!(value("not 0 and 2"), 1.0);
+        // Called, `and` and `or` are still bitwise
+        assert_eq!(value("and(6, 3) + or(4, 1)"), 7.0);
+
+        assert_eq!(value("if(x < 0, -x, x^2)"), 3.0);
+        assert_eq!(value("if(x, 1, 2) + if(0, 1, 2)"), 3.0);
+        // Only the branch taken, and the right of `and` or `or` when needed, is worked out
+        assert_eq!(value("if(x == 0, 0, 1/x)"), -1.0 / 3.0);
+        assert_eq!(value("x == 0 and 1/0"), 0.0);
+        assert_eq!(value("x != 0 or 1/0"), 1.0);
+        assert_eq!(
+            evaluator.evaluate("if(x > 0, 1)"),
+            Err(EvalError::Arity {
+                name: "if".to_string(),
+                expected: 3,
+                got: 2
+            })
+        );
+    }
+
+    #[test]
+    fn lists_work_value_by_value() {
+        let variables = BTreeMap::from([("x".to_string(), 2.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.eval_value(&evaluator.parse(expr).unwrap());
+        let list = |values: &[f64]| Ok(Value::List(values.to_vec()));
+        assert_eq!(value("[1, 2, 3]"), list(&[1.0, 2.0, 3.0]));
+        assert_eq!(value("[]"), list(&[]));
+        assert_eq!(value("[1, 2, 3] * x + 1"), list(&[3.0, 5.0, 7.0]));
+        assert_eq!(value("10 - [1, 2]"), list(&[9.0, 8.0]));
+        assert_eq!(value("[1, 2] ^ [3, 2]"), list(&[1.0, 4.0]));
+        assert_eq!(value("-[3, 4]!"), list(&[-6.0, -24.0]));
+        assert_eq!(value("[1, 2, 3] > 1"), list(&[0.0, 1.0, 1.0]));
+        assert_eq!(value("2[1, 2]"), list(&[2.0, 4.0]));
+        // Functions of one number take each value of a list
+        assert_eq!(value("sqrt([4, 9])"), list(&[2.0, 3.0]));
+        assert_eq!(value("if(x, [1], [2])"), list(&[1.0]));
+
+        assert_eq!(value("sum([1, 2, 3])"), Ok(Value::Number(6.0)));
+        assert_eq!(value("sum([1, 2] * 2)"), Ok(Value::Number(6.0)));
+        assert_eq!(value("prod([2, 3, 4])"), Ok(Value::Number(24.0)));
+        assert_eq!(value("mean([1, 2, 3, 6])"), Ok(Value::Number(3.0)));
+        assert_eq!(value("mean(1, [2, 3], x)"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            value("max([4, 9], 5) - min([3, 1])"),
+            Ok(Value::Number(8.0))
+        );
+        assert_eq!(value("sum([])"), Ok(Value::Number(0.0)));
+        // The counted sum is still there beside the list one
+        assert_eq!(value("sum(k, 1, 3, k)"), Ok(Value::Number(6.0)));
+
+        assert_eq!(
+            value("[1, 2] + [1, 2, 3]"),
+            Err(EvalError::ListLengths(2, 3))
+        );
+        assert_eq!(value("[[1], 2]"), Err(EvalError::UnexpectedList));
+        assert_eq!(value("[1] and 1"), Err(EvalError::UnexpectedList));
+        assert_eq!(value("mod([5, 6], 4)"), Err(EvalError::UnexpectedList));
+        assert!(matches!(
+            value("mean([])"),
+            Err(EvalError::Domain { func: "mean", .. })
+        ));
+        assert_eq!(
+            evaluator.parse("[1, 2"),
+            Err(EvalError::UnexpectedEnd {
+                expected: EXPECTED_LIST_END
+            })
+        );
+        assert_eq!(evaluator.evaluate("[1, 2]"), Err(EvalError::UnexpectedList));
+    }
+
+    #[test]
+    fn matrices_multiply_and_invert() {
+        let variables = BTreeMap::from([("x".to_string(), 2.0)]);
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.eval_value(&evaluator.parse(expr).unwrap());
+        let matrix = |rows: &[&[f64]]| {
+            Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).map(Value::Matrix)
+        };
+        let a = "[[1, 2], [3, 4]]";
+        assert_eq!(value(a), matrix(&[&[1.0, 2.0], &[3.0, 4.0]]));
+        // `*` is the matrix product; other operators go value by value
+        assert_eq!(
+            value(&format!("{} * {}", a, a)),
+            matrix(&[&[7.0, 10.0], &[15.0, 22.0]])
+        );
+        assert_eq!(
+            value(&format!("{0} ^ 2 == {0} * {0}", a)),
+            matrix(&[&[1.0, 1.0], &[1.0, 1.0]])
+        );
+        assert_eq!(
+            value(&format!("{} + x", a)),
+            matrix(&[&[3.0, 4.0], &[5.0, 6.0]])
+        );
+        assert_eq!(
+            value(&format!("{} - identity(2)", a)),
+            matrix(&[&[0.0, 2.0], &[3.0, 3.0]])
+        );
+        assert_eq!(value(&format!("det({})", a)), Ok(Value::Number(-2.0)));
+        assert_eq!(
+            value(&format!("round(inv({}) * {})", a, a)),
+            matrix(&[&[1.0, 0.0], &[0.0, 1.0]])
+        );
+        assert_eq!(value("transpose([1, 2])"), matrix(&[&[1.0], &[2.0]]));
+        assert_eq!(value("det([[x, 1], [1, x]])"), Ok(Value::Number(3.0)));
+        assert_eq!(value("sum([[1, 2], [3, 4]])"), Ok(Value::Number(10.0)));
+        // A list is a column on the right and a row on the left
+        assert_eq!(
+            value(&format!("{} * [1, 1]", a)),
+            Ok(Value::List(vec![3.0, 7.0]))
+        );
+        assert_eq!(
+            value(&format!("[1, 1] * {}", a)),
+            Ok(Value::List(vec![4.0, 6.0]))
+        );
+        assert_eq!(value("inv(4) + det(3)"), Ok(Value::Number(3.25)));
+
+        assert_eq!(value("[[1, 2], [3]]"), Err(EvalError::RaggedMatrix));
+        assert_eq!(
+            value(&format!("{} * [[1, 2, 3]]", a)),
+            Err(EvalError::MatrixSizes {
+                op: "*",
+                left: (2, 2),
+                right: (1, 3)
+            })
+        );
+        assert_eq!(
+            value(&format!("{} + identity(3)", a)),
+            Err(EvalError::MatrixSizes {
+                op: "+",
+                left: (2, 2),
+                right: (3, 3)
+            })
+        );
+        assert_eq!(value("det([[1, 2]])"), Err(EvalError::NotSquare(1, 2)));
+        assert_eq!(
+            value(&format!("1 / {}", a)),
+            Err(EvalError::UnexpectedMatrix)
+        );
+        assert_eq!(
+            evaluator.evaluate(&format!("det({}) + {}", a, a)),
+            Err(EvalError::UnexpectedMatrix)
+        );
+    }
+
+    #[test]
+    fn dates_take_days_added_and_apart() {
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.eval_value(&evaluator.parse(expr).unwrap());
+        let date = |text: &str| Ok(Value::Date(dates::parse_date(text).unwrap()));
+        assert_eq!(
+            value("days_between(\"2024-01-01\", \"2024-06-15\")"),
+            Ok(Value::Number(166.0))
+        );
+        assert_eq!(value("date_add(\"2024-01-01\", 90)"), date("2024-03-31"));
+        // Days add and subtract as plain numbers, and dates take apart to days
+        assert_eq!(value("\"2024-03-01\" - 1"), date("2024-02-29"));
+        assert_eq!(value("7 * 2 + \"2024-12-25\""), date("2025-01-08"));
+        assert_eq!(
+            value("(\"2024-12-23\" - \"2024-01-01\") / 7"),
+            Ok(Value::Number(51.0))
+        );
+        assert_eq!(
+            value("\"2024-01-02\" > \"2024-01-01\""),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(value("today() - today()"), Ok(Value::Number(0.0)));
+        assert_eq!(
+            call_text_function("weekday", vec![date("2024-06-15").unwrap()]),
+            Ok("Saturday".to_string())
+        );
+
+        assert_eq!(value("\"2024-01-01\" * 2"), Err(EvalError::UnexpectedDate));
+        assert_eq!(value("date_add(5, 5)"), Err(EvalError::ExpectedDate));
+        assert_eq!(
+            value("sqrt(\"2024-01-01\")"),
+            Err(EvalError::UnexpectedDate)
+        );
+        assert_eq!(
+            evaluator.parse("days_between(\"2024-13-01\", today())"),
+            Err(EvalError::InvalidDate("2024-13-01".to_string()))
+        );
+    }
+
+    #[test]
+    fn times_convert_to_and_from_unix_timestamps() {
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.eval_value(&evaluator.parse(expr).unwrap());
+        let time = |text: &str| Ok(Value::Time(dates::parse_time(text).unwrap()));
+        assert_eq!(value("unix(1718452800)"), time("2024-06-15 12:00:00"));
+        assert_eq!(
+            value("to_unix(\"2024-06-15 17:30:00 +05:30\")"),
+            Ok(Value::Number(1718452800.0))
+        );
+        assert_eq!(value("to_unix(\"1970-01-02\")"), Ok(Value::Number(86400.0)));
+        // Numbers added to a time are seconds, and times take apart to seconds
+        assert_eq!(
+            value("unix(1718452800) + 3600"),
+            time("2024-06-15 13:00:00")
+        );
+        assert_eq!(
+            value("\"2024-06-15 12:00:00\" - \"2024-06-15\""),
+            Ok(Value::Number(43200.0))
+        );
+        assert_eq!(value("tz(unix(0), -5) == unix(0)"), Ok(Value::Number(1.0)));
+        assert_eq!(value("tz(unix(0), -5)"), time("1969-12-31 19:00:00 -05:00"));
+        assert_eq!(
+            value("days_between(tz(unix(0), -5), \"1970-01-01\")"),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(value("to_unix(local(unix(1e9)))"), Ok(Value::Number(1e9)));
+
+        assert_eq!(
+            value("tz(unix(0), 30)"),
+            Err(EvalError::undefined("tz", 30.0))
+        );
+        assert_eq!(value("unix(0) * 2"), Err(EvalError::UnexpectedDate));
+        assert_eq!(value("unix(0) + unix(0)"), Err(EvalError::UnexpectedDate));
+        assert_eq!(value("to_unix(5)"), Err(EvalError::ExpectedDate));
+    }
+
+    #[test]
+    fn uncertainties_carry_through_arithmetic_and_functions() {
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        let value = |expr: &str| evaluator.eval_value(&evaluator.parse(expr)?);
+        assert_eq!(value("9.81±0.02"), Ok(Value::Uncertain(9.81, 0.02)));
+        assert_eq!(value("9.81 +/- 0.02"), Ok(Value::Uncertain(9.81, 0.02)));
+        // `±` binds tighter than `*` and a sign, looser than `^`
+        assert_eq!(value("2*3±1"), Ok(Value::Uncertain(6.0, 2.0)));
+        assert_eq!(value("-3±2^2"), Ok(Value::Uncertain(-3.0, 4.0)));
+        let Ok(Value::Uncertain(sum, error)) = value("(3±0.3) + (4±0.4)") else {
+            panic!("not uncertain");
+        };
+        assert_eq!(sum, 7.0);
+        assert!((error - 0.5).abs() < 1e-9, "{}", error);
+        let Ok(Value::Uncertain(root, error)) = value("sqrt(100±2)") else {
+            panic!("not uncertain");
+        };
+        assert_eq!(root, 10.0);
+        assert!((error - 0.1).abs() < 1e-9, "{}", error);
+
+        assert_eq!(value("1±0.1 < 2"), Err(EvalError::UnexpectedUncertain));
+        assert_eq!(value("[1±0.1, 2]"), Err(EvalError::UnexpectedUncertain));
+        assert_eq!(value("max(1±0.1, 2)"), Err(EvalError::UnexpectedUncertain));
+        assert_eq!(
+            value("[1, 2] * (1±0.1)"),
+            Err(EvalError::UnexpectedUncertain)
+        );
+        assert!(matches!(
+            value("1 ± -1"),
+            Err(EvalError::Domain { func: "±", .. })
+        ));
+        assert!(matches!(value("1 +/"), Err(EvalError::Syntax { .. })));
+    }
+}
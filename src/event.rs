@@ -0,0 +1,797 @@
+//! Events that drive the app: terminal input, ticks of the animation clock and jobs
+//! finishing, all delivered through one channel the main loop sleeps on.
+
+use anyhow::Result;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::graph::Feature;
+use crate::keymap::Chord;
+use crate::macros::{self, MAX_STEPS, Pending, Step};
+use crate::ui::LayoutMode;
+use crate::{App, AppState, Popup, QuitTrigger, ui};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppEvent {
+    /// A key, mouse or resize event from the terminal.
+    Input(Event),
+    /// One step of the animation clock, only sent while something is animating.
+    Tick,
+    /// The second changed, so the clock in the title is due a redraw.
+    Clock,
+    /// A job finished; its outcome, or without one its description as given to
+    /// [`crate::jobs::Jobs::start`].
+    JobFinished(String),
+    /// Reading the terminal failed, which ends the app.
+    InputFailed(String),
+}
+
+/// The channel every event arrives through, and the threads feeding it.
+pub struct Events {
+    sender: Sender<AppEvent>,
+    receiver: Receiver<AppEvent>,
+    /// Tells the timer thread the tick interval; `None` when there is no timer.
+    timer: Option<Sender<Option<Duration>>>,
+    /// The interval the timer was last told.
+    interval: Option<Duration>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Events {
+    /// A channel with nothing feeding it yet; see [`Self::spawn_input`] and
+    /// [`Self::spawn_timer`], or send events through [`Self::sender`].
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            timer: None,
+            interval: None,
+        }
+    }
+
+    /// A handle for other threads, such as jobs, to send events with.
+    pub fn sender(&self) -> Sender<AppEvent> {
+        self.sender.clone()
+    }
+
+    /// Forward terminal input from a thread of its own.
+    pub fn spawn_input(&self) {
+        let sender = self.sender();
+        thread::spawn(move || {
+            loop {
+                let event = match event::read() {
+                    Ok(event) => AppEvent::Input(event),
+                    Err(err) => AppEvent::InputFailed(err.to_string()),
+                };
+                let failed = matches!(event, AppEvent::InputFailed(_));
+                if sender.send(event).is_err() || failed {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Send Ticks at the interval [`Self::run`] keeps it told about, and a Clock at
+    /// the turn of each second while there are no Ticks to redraw it.
+    pub fn spawn_timer(&mut self) {
+        let sender = self.sender();
+        let (control, intervals) = mpsc::channel();
+        self.timer = Some(control);
+        thread::spawn(move || {
+            let mut interval = None;
+            loop {
+                let wait = interval.unwrap_or_else(until_next_second);
+                match intervals.recv_timeout(wait) {
+                    Ok(changed) => interval = changed,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let event = match interval {
+                            Some(_) => AppEvent::Tick,
+                            None => AppEvent::Clock,
+                        };
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+    }
+
+    /// Draw, then sleep until the next event and handle it, until the app quits.
+    /// A failed draw or terminal read asks the app to quit and is returned.
+    pub fn run(
+        &mut self,
+        app: &mut App,
+        mut draw: impl FnMut(&mut App) -> Result<()>,
+    ) -> Result<()> {
+        while !app.should_quit {
+            if let Err(err) = draw(app) {
+                app.request_quit(QuitTrigger::Error);
+                return Err(err);
+            }
+            self.set_interval(app.tick_interval());
+            // `self` holds a sender, so the channel can't close under us
+            let Ok(event) = self.receiver.recv() else {
+                break;
+            };
+            if let AppEvent::InputFailed(message) = event {
+                app.request_quit(QuitTrigger::Error);
+                return Err(anyhow::anyhow!(message));
+            }
+            app.handle_event(event);
+        }
+        Ok(())
+    }
+
+    fn set_interval(&mut self, interval: Option<Duration>) {
+        if interval == self.interval {
+            return;
+        }
+        self.interval = interval;
+        if let Some(timer) = &self.timer {
+            // A timer that has stopped has nothing left to tell
+            let _ = timer.send(interval);
+        }
+    }
+}
+
+/// Time left until the wall clock's next whole second.
+fn until_next_second() -> Duration {
+    let into_second = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.subsec_nanos());
+    Duration::from_nanos(u64::from(1_000_000_000 - into_second))
+}
+
+/// Keys that work on the Typing mode selection rather than clearing it.
+fn is_selection_key(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    match code {
+        KeyCode::Left | KeyCode::Right => modifiers.contains(KeyModifiers::SHIFT),
+        KeyCode::Char('e' | 'E') => modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+impl App {
+    pub fn handle_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Tick => self.tick(),
+            AppEvent::Input(Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            })) => self.handle_key(code, modifiers, kind),
+            AppEvent::Input(Event::Mouse(mouse_event)) => self.handle_mouse(mouse_event),
+            AppEvent::JobFinished(outcome) => self.status_message = outcome,
+            // Only a redraw is needed, and that follows every event
+            AppEvent::Input(_) | AppEvent::Clock | AppEvent::InputFailed(_) => {}
+        }
+        self.update_typing_hint(Instant::now());
+    }
+
+    /// Macro record and replay keys, and the register letter that follows them.
+    /// Returns true when the key was used up here.
+    fn handle_macro_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        if let Some(pending) = self.macros.pending.take() {
+            match (code, pending) {
+                (KeyCode::Char(register @ 'a'..='z'), Pending::Store) => {
+                    let len = self.macros.store(register);
+                    self.status_message = match self.macros.save() {
+                        Ok(()) => format!("Stored {} steps in macro {}", len, register),
+                        Err(e) => format!(
+                            "Stored {} steps in macro {} for this run, but saving failed: {}",
+                            len, register, e
+                        ),
+                    };
+                }
+                (KeyCode::Char(register @ 'a'..='z'), Pending::Replay) => {
+                    self.replay_macro(register)
+                }
+                (KeyCode::Esc, Pending::Store) => {
+                    self.status_message = "Macro discarded".to_string()
+                }
+                (KeyCode::Esc, Pending::Replay) => self.status_message = "Ready".to_string(),
+                _ => {
+                    self.macros.pending = Some(pending);
+                    self.status_message = "Choose a macro register a-z (Esc cancels)".to_string();
+                }
+            }
+            return true;
+        }
+
+        if macros::is_record_key(code, modifiers) {
+            if self.macros.is_recording() {
+                self.macros.stop_recording();
+                self.status_message = "Press a-z to store the macro (Esc discards it)".to_string();
+            } else {
+                self.macros.start_recording();
+                self.status_message = "Recording macro - Ctrl+Shift+R to stop".to_string();
+            }
+            return true;
+        }
+        if macros::is_replay_key(code, modifiers) {
+            self.macros.pending = Some(Pending::Replay);
+            self.status_message = "Replay macro from register a-z".to_string();
+            return true;
+        }
+        false
+    }
+
+    /// Add a step to the macro being recorded, if one is.
+    pub fn record_step(&mut self, step: Step) {
+        if !self.macros.record(step) {
+            self.status_message = format!(
+                "Macro reached {} steps - press a-z to store it (Esc discards it)",
+                MAX_STEPS
+            );
+        }
+    }
+
+    /// Run a stored macro's steps again: commands and typed text directly, other keys
+    /// through [`App::handle_event`].
+    pub fn replay_macro(&mut self, register: char) {
+        let Some(steps) = self.macros.get(register).map(<[Step]>::to_vec) else {
+            self.status_message = format!("Macro {} is empty", register);
+            return;
+        };
+        self.macros.set_replaying(true);
+        for step in steps {
+            match step {
+                Step::Command(command) => {
+                    self.record_step(step);
+                    self.run_command(command);
+                }
+                Step::Typed(c) => {
+                    self.statement_results.clear();
+                    self.handle_state_key(KeyCode::Char(c), KeyModifiers::NONE);
+                }
+                Step::Key(chord) => self.handle_event(AppEvent::Input(Event::Key(KeyEvent::new(
+                    chord.code,
+                    chord.modifiers,
+                )))),
+            }
+            if self.should_quit || self.macros.pending.is_some() {
+                break;
+            }
+        }
+        self.macros.set_replaying(false);
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers, kind: KeyEventKind) {
+        if kind == KeyEventKind::Release {
+            self.nav_repeat.release(code);
+        }
+        if kind != KeyEventKind::Press {
+            return;
+        }
+        self.statement_results.clear();
+        self.restart_blink();
+
+        if self.handle_macro_key(code, modifiers) {
+            return;
+        }
+        let key = Step::Key(Chord::new(code, modifiers));
+        if let Some(trigger) = self.quit_trigger(code, modifiers) {
+            self.record_step(key);
+            self.request_quit(trigger);
+            return;
+        }
+        if let Some(popup) = &self.popup {
+            // The palette records the command it runs rather than what was typed into it
+            if !matches!(popup, Popup::Palette(_)) {
+                self.record_step(key);
+            }
+            self.handle_popup_key(code);
+            return;
+        }
+        if code == KeyCode::Char('p') && modifiers.contains(KeyModifiers::CONTROL) {
+            self.open_palette();
+            return;
+        }
+        if code == KeyCode::Char('o')
+            && modifiers.contains(KeyModifiers::CONTROL)
+            && self.state != AppState::Graph
+        {
+            self.record_step(key);
+            self.swap_expressions();
+            return;
+        }
+
+        if !is_selection_key(code, modifiers) {
+            self.selection_anchor = None;
+        }
+        if let Some(command) = self.keymap.command(self.state, code, modifiers) {
+            self.record_step(Step::Command(command));
+            self.run_command(command);
+            return;
+        }
+
+        let (code, modifiers) = match code {
+            KeyCode::Char(c) if self.state == AppState::Typing => {
+                // AltGr arrives as Ctrl+Alt on some platforms; the char is already resolved
+                let modifiers = if modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+                    modifiers - KeyModifiers::CONTROL - KeyModifiers::ALT
+                } else {
+                    modifiers
+                };
+                let aliased = (!modifiers.contains(KeyModifiers::CONTROL))
+                    .then(|| self.keymap.alias(c))
+                    .flatten();
+                (KeyCode::Char(aliased.unwrap_or(c)), modifiers)
+            }
+            _ => (code, modifiers),
+        };
+        self.handle_state_key(code, modifiers);
+    }
+
+    /// What a key does in the current state, once commands and aliases are resolved.
+    /// Characters typed in Typing mode are recorded as text, other keys as they are.
+    fn handle_state_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        self.record_step(match code {
+            KeyCode::Char(c)
+                if self.state == AppState::Typing && !modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                Step::Typed(c)
+            }
+            _ => Step::Key(Chord::new(code, modifiers)),
+        });
+        match self.state {
+            AppState::Normal => match code {
+                // Esc only reaches here while help is open; otherwise it quits.
+                KeyCode::Esc => self.toggle_help(),
+                KeyCode::Char('?') => {
+                    self.toggle_help();
+                }
+                KeyCode::Char('`') => {
+                    self.state = AppState::Typing;
+                    self.status_message = "Typing mode - type expressions, ` to exit".to_string();
+                }
+                KeyCode::Up => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    if self.show_history {
+                        self.history_next();
+                    } else {
+                        self.button_up();
+                    }
+                }
+                KeyCode::Down => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    if self.show_history {
+                        self.history_prev();
+                    } else {
+                        self.button_down();
+                    }
+                }
+                KeyCode::Left => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    if !self.show_history {
+                        self.button_left();
+                    }
+                }
+                KeyCode::Right => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    if !self.show_history {
+                        self.button_right();
+                    }
+                }
+                KeyCode::Enter | KeyCode::Char(' ') => {
+                    if self.show_history {
+                        self.recall_from_history();
+                    } else {
+                        self.press_button();
+                    }
+                }
+                KeyCode::Char('m') => self.toggle_mode(),
+                KeyCode::Char('2') => self.toggle_second_function(),
+                KeyCode::Char('h') => self.toggle_history(),
+                KeyCode::Char('r') if self.show_history => {
+                    self.recall_from_history();
+                }
+                _ => {}
+            },
+            AppState::Typing => match code {
+                KeyCode::Esc if self.show_help => self.toggle_help(),
+                KeyCode::Esc if self.show_history => self.toggle_history(),
+                // There is no keypad to go back to in the mini layout
+                KeyCode::Char('`') | KeyCode::Esc if self.layout_mode == LayoutMode::Mini => {
+                    self.status_message = "Mini layout is typing only - Ctrl+q quits".to_string();
+                }
+                KeyCode::Char('`') | KeyCode::Esc => {
+                    self.state = AppState::Normal;
+                    self.status_message = "Button navigation mode".to_string();
+                }
+                KeyCode::Up => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.history_next()
+                }
+                KeyCode::Down => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.history_prev()
+                }
+                KeyCode::Char(c @ '0'..='9') => self.calculator_module.append_digit(c),
+                KeyCode::Char('.') => self.calculator_module.append_decimal(),
+                KeyCode::Char('+') => self.calculator_module.append_operator("+"),
+                KeyCode::Char('-') => self.calculator_module.append_operator("-"),
+                KeyCode::Char('*') => self.calculator_module.append_operator("*"),
+                KeyCode::Char('/') => self.calculator_module.append_operator("/"),
+                KeyCode::Char('^') => self.calculator_module.append_operator("^"),
+                KeyCode::Char('%') => self.calculator_module.append_operator("%"),
+                KeyCode::Char('(') => self.calculator_module.insert_str("("),
+                KeyCode::Char(')') => self.calculator_module.insert_str(")"),
+                KeyCode::Char(c @ ('[' | ']')) => self.calculator_module.insert_str(&c.to_string()),
+                KeyCode::Char('=') => self.calculator_module.insert_str("="),
+                KeyCode::Char(',') => self.calculator_module.insert_str(","),
+                KeyCode::Char(';') => self.calculator_module.insert_str(";"),
+                KeyCode::Char('"') => self.calculator_module.insert_str("\""),
+                // Bitwise and comparison operators, and `±` where a layout has it
+                KeyCode::Char(c @ ('&' | '|' | '<' | '>' | '~' | '!' | '±')) => {
+                    self.calculator_module.insert_str(&c.to_string())
+                }
+                KeyCode::Char('$') => self.insert_last_result(),
+                KeyCode::Char('r') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.insert_last_result()
+                }
+                KeyCode::Left if modifiers.contains(KeyModifiers::SHIFT) => {
+                    self.extend_selection(true)
+                }
+                KeyCode::Right if modifiers.contains(KeyModifiers::SHIFT) => {
+                    self.extend_selection(false)
+                }
+                KeyCode::Char('e' | 'E') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    let replace =
+                        code == KeyCode::Char('E') || modifiers.contains(KeyModifiers::SHIFT);
+                    self.evaluate_selection(replace)
+                }
+                KeyCode::Left => self.calculator_module.move_cursor_left(),
+                KeyCode::Right => self.calculator_module.move_cursor_right(),
+                KeyCode::Home => self.calculator_module.move_cursor_home(),
+                KeyCode::End => self.calculator_module.move_cursor_end(),
+                KeyCode::Enter if self.show_history => self.recall_from_history(),
+                KeyCode::Enter => self.calculate(),
+                KeyCode::Backspace => self.calculator_module.backspace(),
+                KeyCode::Delete => self.calculator_module.delete(),
+                KeyCode::Char('m') => self.toggle_mode(),
+                KeyCode::Char('h') => self.toggle_history(),
+                KeyCode::Char('?') => {
+                    self.toggle_help();
+                }
+                // In Typing mode, allow letters to build identifiers (functions/variables)
+                KeyCode::Char(c) if c.is_ascii_alphabetic() => {
+                    self.calculator_module.insert_str(&c.to_string());
+                }
+                _ => {}
+            },
+            AppState::Graph => match code {
+                KeyCode::Esc if self.show_help => self.toggle_help(),
+                KeyCode::Esc => self.exit_graph_mode(),
+                KeyCode::Char('?') => self.toggle_help(),
+                KeyCode::Up => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.pan_graph(0.0, 1.0)
+                }
+                KeyCode::Down => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.pan_graph(0.0, -1.0)
+                }
+                KeyCode::Left => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.pan_graph(-1.0, 0.0)
+                }
+                KeyCode::Right => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.pan_graph(1.0, 0.0)
+                }
+                KeyCode::Char('+') => self.zoom_graph(1.2),
+                KeyCode::Char('-') => self.zoom_graph(0.8),
+                KeyCode::Char('r') => self.reset_graph_view(),
+                KeyCode::Char('A') => self.toggle_aspect_lock(),
+                KeyCode::Char('c') => {
+                    self.show_cursor_coords = !self.show_cursor_coords;
+                }
+                KeyCode::Char('t') => self.toggle_tangent(),
+                KeyCode::Char('g') => self.toggle_gridlines(),
+                KeyCode::Char('z') => self.toggle_findings(Feature::Roots),
+                KeyCode::Char('i') => self.toggle_findings(Feature::Intersections),
+                KeyCode::Char('m') => self.toggle_findings(Feature::Extrema),
+                KeyCode::Char('b') => self.drop_bound(),
+                KeyCode::Char('B') => self.clear_bounds(),
+                _ => {}
+            },
+            AppState::Convert => match code {
+                KeyCode::Esc if self.show_help => self.toggle_help(),
+                KeyCode::Esc | KeyCode::Char('U') => self.exit_converter(),
+                KeyCode::Char('?') => self.toggle_help(),
+                KeyCode::Tab | KeyCode::Right => self.converter.focus_next(),
+                KeyCode::BackTab | KeyCode::Left => self.converter.focus_prev(),
+                KeyCode::Up => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.converter.select(-1)
+                }
+                KeyCode::Down => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.converter.select(1)
+                }
+                KeyCode::Char('s') => self.converter.swap(),
+                KeyCode::Char(c) => self.converter.push(c),
+                KeyCode::Backspace => self.converter.backspace(),
+                KeyCode::Enter => self.use_conversion(),
+                _ => {}
+            },
+            AppState::Stats => match code {
+                KeyCode::Esc if self.show_help => self.toggle_help(),
+                KeyCode::Esc | KeyCode::Char('S') => self.exit_stats(),
+                KeyCode::Char('?') => self.toggle_help(),
+                KeyCode::Up => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.stats.move_rows(-1)
+                }
+                KeyCode::Down => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.stats.move_rows(1)
+                }
+                KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right => {
+                    self.stats.switch_column()
+                }
+                KeyCode::Enter => self.stats.move_rows(1),
+                KeyCode::Backspace => self.stats.backspace(),
+                KeyCode::Delete => self.stats.delete_row(),
+                KeyCode::Char('g') => self.graph_data(),
+                KeyCode::Char('f') => self.cycle_fit_model(),
+                KeyCode::Char('C') => {
+                    self.stats.clear();
+                    self.status_message = "Statistics table cleared".to_string();
+                }
+                KeyCode::Char(c) => self.stats.push(c),
+                _ => {}
+            },
+            AppState::Matrix => match code {
+                KeyCode::Esc if self.show_help => self.toggle_help(),
+                KeyCode::Esc | KeyCode::Char('M') => self.exit_matrix_editor(),
+                KeyCode::Char('?') => self.toggle_help(),
+                KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    let (rows, cols) = match code {
+                        KeyCode::Up => (-1, 0),
+                        KeyCode::Down => (1, 0),
+                        KeyCode::Left => (0, -1),
+                        _ => (0, 1),
+                    };
+                    self.matrix_editor.move_by(rows, cols)
+                }
+                KeyCode::Tab => self.matrix_editor.next_cell(),
+                KeyCode::Enter => self.insert_matrix(),
+                KeyCode::Backspace => self.matrix_editor.backspace(),
+                KeyCode::Char('r') => self.matrix_editor.resize(1, 0),
+                KeyCode::Char('R') => self.matrix_editor.resize(-1, 0),
+                KeyCode::Char('c') => self.matrix_editor.resize(0, 1),
+                KeyCode::Char('C') => self.matrix_editor.resize(0, -1),
+                KeyCode::Char('z') => {
+                    self.matrix_editor.clear();
+                    self.status_message = "Matrix cleared to zeros".to_string();
+                }
+                KeyCode::Char(c) => self.matrix_editor.push(c),
+                _ => {}
+            },
+            AppState::Dates => match code {
+                KeyCode::Esc if self.show_help => self.toggle_help(),
+                KeyCode::Esc | KeyCode::Char('D') => self.exit_dates(),
+                KeyCode::Char('?') => self.toggle_help(),
+                KeyCode::Tab => self.dates.focus_next(),
+                KeyCode::BackTab => self.dates.focus_prev(),
+                KeyCode::Up => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.dates.focus_prev()
+                }
+                KeyCode::Down => {
+                    if !self.can_navigate(code) {
+                        return;
+                    }
+                    self.dates.focus_next()
+                }
+                KeyCode::Char('t') => self.dates.set_today(),
+                KeyCode::Char(c) => self.dates.push(c),
+                KeyCode::Backspace => self.dates.backspace(),
+                KeyCode::Enter => self.use_date_calculation(),
+                _ => {}
+            },
+        }
+    }
+
+    fn handle_mouse(&mut self, mouse_event: MouseEvent) {
+        let (x, y) = (mouse_event.column, mouse_event.row);
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Left)
+                if self.state == AppState::Graph && self.popup.is_none() =>
+            {
+                self.start_graph_drag(x, y);
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.state == AppState::Graph => {
+                self.drag_graph(x, y);
+                let graph_area = ui::graph_plot_area(self.terminal_size, self.layout_mode);
+                self.update_graph_cursor(x, y, graph_area);
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.end_graph_drag(),
+            MouseEventKind::Down(MouseButton::Left) => {
+                if self.popup.is_none() && self.click_expression(x, y) {
+                    self.restart_blink();
+                } else if let Some((row, col)) =
+                    self.mouse_to_button_coords(x, y, self.terminal_size)
+                {
+                    // Set position temporarily for button press
+                    self.button_position = Some((row, col));
+                    match self.button_command(row, col) {
+                        Some(command) => self.with_shortcut_tip(command, App::press_button),
+                        None => self.press_button(),
+                    }
+                    // Clear selection after mouse click to avoid persistent selection
+                    self.button_position = None;
+                }
+            }
+            MouseEventKind::Moved => {
+                // Track mouse position for hover effects
+                self.mouse_position = Some((x, y));
+                // Update graph cursor if in graph mode
+                if self.state == AppState::Graph {
+                    let graph_area = ui::graph_plot_area(self.terminal_size, self.layout_mode);
+                    self.update_graph_cursor(x, y, graph_area);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> AppEvent {
+        AppEvent::Input(Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    fn quit() -> AppEvent {
+        key(KeyCode::Char('q'), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn run_draws_once_per_event_until_quit() {
+        let mut events = Events::new();
+        let sender = events.sender();
+        for c in "`1+2".chars() {
+            sender
+                .send(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        sender
+            .send(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        sender.send(AppEvent::Clock).unwrap();
+        sender.send(quit()).unwrap();
+        // Never handled: the loop stops at the quit
+        sender
+            .send(key(KeyCode::Char('9'), KeyModifiers::NONE))
+            .unwrap();
+
+        let mut app = App::new();
+        let mut frames = 0;
+        events
+            .run(&mut app, |_| {
+                frames += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(frames, 7);
+        assert_eq!(app.calculator_module.history.len(), 1);
+        assert_eq!(app.calculator_module.current_expression, "3");
+    }
+
+    #[test]
+    fn finished_jobs_wake_the_loop() {
+        let mut events = Events::new();
+        let mut app = App::new();
+        app.jobs.notify(events.sender());
+        drop(app.jobs.start("export to plot.csv"));
+        events.sender().send(quit()).unwrap();
+
+        let mut frames = 0;
+        events
+            .run(&mut app, |_| {
+                frames += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(frames, 2);
+        assert_eq!(events.receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+        assert_eq!(app.status_message, "export to plot.csv");
+    }
+
+    #[test]
+    fn failures_end_the_loop() {
+        let mut events = Events::new();
+        let mut app = App::new();
+        let err = events
+            .run(&mut app, |_| Err(anyhow::anyhow!("terminal gone")))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "terminal gone");
+        assert!(app.should_quit);
+
+        let mut app = App::new();
+        events
+            .sender()
+            .send(AppEvent::InputFailed("read failed".to_string()))
+            .unwrap();
+        let err = events.run(&mut app, |_| Ok(())).unwrap_err();
+        assert_eq!(err.to_string(), "read failed");
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn timer_ticks_while_animating() {
+        let mut events = Events::new();
+        events.spawn_timer();
+        let sender = events.sender();
+        let mut app = App::new();
+        // The Typing mode cursor blinks, which keeps the clock ticking
+        app.state = AppState::Typing;
+        let blinking = app.tick_interval();
+        assert!(blinking.is_some());
+
+        let mut frames = 0;
+        events
+            .run(&mut app, |_| {
+                frames += 1;
+                if frames == 4 {
+                    sender.send(quit()).unwrap();
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(events.interval, blinking);
+    }
+}
@@ -0,0 +1,324 @@
+//! Financial mode's time-value-of-money solver. Five values describe a loan, saving
+//! plan or annuity, and any one of them follows from the other four:
+//!
+//! `PV·(1+i)^N + PMT·((1+i)^N − 1)/i + FV = 0`
+//!
+//! with `i` the interest per period (I/Y as a fraction) and payments at the end of each
+//! period. As on an HP or TI calculator, money paid out is negative and money received
+//! positive, so a loan's PV and its payments have opposite signs.
+//!
+//! `npv` and `irr` bring the same to expressions, for a series of cash flows one period
+//! apart.
+
+use crate::calculator::{EvalError, EvalResult};
+use crate::eval::Value;
+
+/// Functions of cash flows, given as a list or one by one.
+pub const FINANCE_FUNCTIONS: &[&str] = &["npv", "irr"];
+
+/// One of the values of a [`Tvm`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TvmField {
+    /// Number of periods.
+    N,
+    /// Interest per period, in percent.
+    Rate,
+    /// Present value.
+    Pv,
+    /// Payment each period.
+    Pmt,
+    /// Future value.
+    Fv,
+}
+
+impl TvmField {
+    pub const ALL: [TvmField; 5] = [
+        TvmField::N,
+        TvmField::Rate,
+        TvmField::Pv,
+        TvmField::Pmt,
+        TvmField::Fv,
+    ];
+
+    /// The key it's on, as financial calculators label it.
+    pub fn label(self) -> &'static str {
+        match self {
+            TvmField::N => "N",
+            TvmField::Rate => "I/Y",
+            TvmField::Pv => "PV",
+            TvmField::Pmt => "PMT",
+            TvmField::Fv => "FV",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The most an interest rate searched for may be, per period, as a fraction.
+const MAX_RATE: f64 = 100.0;
+
+/// The five values, all 0 at first.
+#[derive(Debug, Clone, Default)]
+pub struct Tvm {
+    values: [f64; 5],
+    /// The value last worked out from the others.
+    pub solved: Option<TvmField>,
+    /// Whether CPT was pressed, so the next value key works that value out rather than
+    /// storing into it.
+    pub computing: bool,
+}
+
+impl Tvm {
+    pub fn get(&self, field: TvmField) -> f64 {
+        self.values[field.index()]
+    }
+
+    pub fn set(&mut self, field: TvmField, value: f64) {
+        self.values[field.index()] = value;
+        if self.solved == Some(field) {
+            self.solved = None;
+        }
+    }
+
+    /// Work `field` out from the other four and keep it, or `None` when no value fits
+    /// them.
+    pub fn compute(&mut self, field: TvmField) -> Option<f64> {
+        let value = self.solve(field).filter(|value| value.is_finite())?;
+        self.values[field.index()] = value;
+        self.solved = Some(field);
+        Some(value)
+    }
+
+    /// What `field` must be for the other four, without keeping it.
+    pub fn solve(&self, field: TvmField) -> Option<f64> {
+        let [n, rate, pv, pmt, fv] = self.values;
+        let i = rate / 100.0;
+        match field {
+            TvmField::N if i == 0.0 => (pmt != 0.0).then(|| -(pv + fv) / pmt),
+            TvmField::N => {
+                let growth = (pmt - fv * i) / (pmt + pv * i);
+                (growth > 0.0).then(|| growth.ln() / i.ln_1p())
+            }
+            TvmField::Rate => solve_rate(n, pv, pmt, fv).map(|i| i * 100.0),
+            TvmField::Pv => {
+                let (growth, annuity) = factors(n, i);
+                Some(-(pmt * annuity + fv) / growth)
+            }
+            TvmField::Pmt => {
+                let (growth, annuity) = factors(n, i);
+                (annuity != 0.0).then(|| -(pv * growth + fv) / annuity)
+            }
+            TvmField::Fv => {
+                let (growth, annuity) = factors(n, i);
+                Some(-(pv * growth + pmt * annuity))
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Tvm::default();
+    }
+}
+
+/// What `flows`, the first now and each later one a period after the last, are worth
+/// now at `rate` per period.
+pub fn npv(rate: f64, flows: &[f64]) -> f64 {
+    let growth = 1.0 + rate;
+    // Horner's rule from the last flow back, one division per period
+    flows
+        .iter()
+        .rev()
+        .fold(0.0, |total, &flow| total / growth + flow)
+}
+
+/// The rate per period at which `flows` are worth nothing now. Only flows that change
+/// sign have one: without both money out and money in, no rate balances them.
+pub fn irr(flows: &[f64]) -> Option<f64> {
+    let changes_sign = flows.iter().any(|&flow| flow > 0.0) && flows.iter().any(|&flow| flow < 0.0);
+    if !changes_sign {
+        return None;
+    }
+    find_rate(|rate| npv(rate, flows))
+}
+
+/// The numbers of `args`, lists taken value by value.
+fn cash_flows(args: impl Iterator<Item = Value>) -> EvalResult<Vec<f64>> {
+    let mut flows = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Number(flow) => flows.push(flow),
+            Value::List(values) => flows.extend(values),
+            other => flows.push(other.number()?),
+        }
+    }
+    Ok(flows)
+}
+
+/// Call `name`, one of [`FINANCE_FUNCTIONS`], on `args`, whose count the caller has
+/// checked.
+pub fn call_finance(name: &'static str, args: Vec<Value>) -> EvalResult<f64> {
+    let mut args = args.into_iter();
+    if name == "npv" {
+        let rate = args.next().unwrap_or(Value::Number(0.0)).number()?;
+        if rate <= -1.0 {
+            return Err(EvalError::undefined(name, rate));
+        }
+        return Ok(npv(rate, &cash_flows(args)?));
+    }
+    irr(&cash_flows(args)?).ok_or(EvalError::Domain {
+        func: name,
+        arg: f64::NAN,
+        reason: "irr() needs cash flows that change sign, so some rate balances them",
+    })
+}
+
+/// `(1+i)^n`, what a present value grows to, and `((1+i)^n − 1)/i`, what payments of 1
+/// add up to, over `n` periods at `i` per period.
+fn factors(n: f64, i: f64) -> (f64, f64) {
+    if i == 0.0 {
+        return (1.0, n);
+    }
+    let growth = (n * i.ln_1p()).exp();
+    (growth, (n * i.ln_1p()).exp_m1() / i)
+}
+
+/// What's left over at the end at interest `i`: 0 at the rate that fits.
+fn balance(n: f64, i: f64, pv: f64, pmt: f64, fv: f64) -> f64 {
+    let (growth, annuity) = factors(n, i);
+    pv * growth + pmt * annuity + fv
+}
+
+/// The interest per period, as a fraction, at which the values balance.
+fn solve_rate(n: f64, pv: f64, pmt: f64, fv: f64) -> Option<f64> {
+    if n <= 0.0 {
+        return None;
+    }
+    find_rate(|i| balance(n, i, pv, pmt, fv))
+}
+
+/// The rate, as a fraction above -1, where `f` is 0: the one nearest 0 where it changes
+/// sign, narrowed down by bisection.
+fn find_rate(f: impl Fn(f64) -> f64) -> Option<f64> {
+    // Rates spread out from 0 both ways, finer near 0 where most answers are
+    let mut steps = vec![0.0];
+    let mut step = 1e-4;
+    while step < MAX_RATE {
+        steps.push(step);
+        if step < 0.99 {
+            steps.push(-step);
+        }
+        step *= 1.5;
+    }
+    steps.sort_by(f64::total_cmp);
+    // Pairs of neighbouring rates, nearest 0 first
+    let mut pairs: Vec<(f64, f64)> = steps.windows(2).map(|pair| (pair[0], pair[1])).collect();
+    pairs.sort_by(|a, b| {
+        a.0.abs()
+            .min(a.1.abs())
+            .total_cmp(&b.0.abs().min(b.1.abs()))
+    });
+    let (mut low, mut high) = pairs
+        .into_iter()
+        .find(|&(low, high)| f(low).signum() != f(high).signum() || f(low) == 0.0)?;
+    if f(low) == 0.0 {
+        return Some(low);
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if f(mid) == 0.0 || high - low < 1e-15 {
+            return Some(mid);
+        }
+        if f(mid).signum() == f(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tvm(values: [f64; 5]) -> Tvm {
+        Tvm {
+            values,
+            ..Tvm::default()
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-6 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn any_value_follows_from_the_others() {
+        // A 30-year mortgage of 200,000 at 6% a year, paid monthly
+        let mortgage = [360.0, 0.5, 200_000.0, -1199.1010503055, 0.0];
+        for field in TvmField::ALL {
+            let mut unknown = tvm(mortgage);
+            unknown.values[field.index()] = 0.0;
+            let value = unknown.compute(field).unwrap();
+            assert!(
+                close(value, mortgage[field.index()]),
+                "{}: {}",
+                field.label(),
+                value
+            );
+            assert_eq!(unknown.solved, Some(field));
+        }
+
+        // Saving 100 a period with no interest, and a lump sum doubling
+        assert_eq!(
+            tvm([0.0, 0.0, 0.0, -100.0, 1000.0]).solve(TvmField::N),
+            Some(10.0)
+        );
+        let doubling = tvm([0.0, 0.0, -1.0, 0.0, 2.0]);
+        assert!(close(
+            tvm([10.0, 0.0, -1.0, 0.0, 2.0])
+                .solve(TvmField::Rate)
+                .unwrap(),
+            7.1773462536
+        ));
+        assert_eq!(doubling.solve(TvmField::Rate), None);
+    }
+
+    #[test]
+    fn impossible_values_have_no_solution() {
+        // Payments too small to ever cover the interest
+        assert_eq!(tvm([0.0, 1.0, 1000.0, -5.0, 0.0]).solve(TvmField::N), None);
+        // Money only ever paid out
+        assert_eq!(
+            tvm([12.0, 0.0, -100.0, -10.0, -5.0]).solve(TvmField::Rate),
+            None
+        );
+        let mut none = Tvm::default();
+        assert_eq!(none.compute(TvmField::Pmt), None);
+        assert_eq!(none.solved, None);
+    }
+
+    #[test]
+    fn cash_flows_discount_from_the_first() {
+        let flows = [-1000.0, 300.0, 400.0, 500.0];
+        assert!(close(npv(0.1, &flows), -21.0368144));
+        assert_eq!(npv(0.0, &flows), 200.0);
+        let rate = irr(&flows).unwrap();
+        assert!(close(rate, 0.0889633947), "{}", rate);
+        assert!(npv(rate, &flows).abs() < 1e-9);
+        // A loss that never recovers has no rate
+        assert_eq!(irr(&[-100.0, 10.0, -10.0]), None);
+        assert_eq!(irr(&[100.0, 50.0]), None);
+        // Nor do flows that never change sign, however many rates zero them
+        assert_eq!(irr(&[]), None);
+        assert_eq!(irr(&[-100.0]), None);
+        assert_eq!(irr(&[0.0, 0.0, 0.0]), None);
+        assert_eq!(irr(&[0.0, -50.0, 0.0]), None);
+        assert!(matches!(
+            call_finance("irr", vec![Value::List(vec![0.0, 0.0])]),
+            Err(EvalError::Domain { func: "irr", .. })
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+//! Symbols drawn on the keypad, in help and in history, and their plain ASCII spellings
+//! for terminals whose locale or font can't show them.
+
+use std::borrow::Cow;
+use std::env;
+
+/// Each symbol with its ASCII stand-in. Longer symbols come first so a run of arrows is
+/// spelled out as a whole.
+const SUBSTITUTIONS: &[(&str, &str)] = &[
+    ("↑↓←→", "arrows"),
+    ("↑↓", "Up/Down"),
+    ("←→", "Left/Right"),
+    ("⌫", "<="),
+    ("×", "x"),
+    ("÷", "/"),
+    ("−", "-"),
+    ("√", "sqrt"),
+    ("π", "pi"),
+    ("²", "^2"),
+    ("≈", "~"),
+    ("⚠", "!"),
+    ("🐢", "(slow)"),
+];
+
+/// Short forms for keypad labels squeezed into narrow buttons. Labels of three chars
+/// or fewer, like `2nd`, are left alone.
+const ABBREVIATIONS: &[(&str, &str)] = &[("Graph", "Gr"), ("Copy", "Cp"), ("sqrt", "√")];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GlyphSet {
+    #[default]
+    Unicode,
+    Ascii,
+}
+
+impl GlyphSet {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unicode" => Some(GlyphSet::Unicode),
+            "ascii" => Some(GlyphSet::Ascii),
+            _ => None,
+        }
+    }
+
+    /// The set the locale can show, from the first of `LC_ALL`, `LC_CTYPE` and `LANG`
+    /// that is set. Windows terminals don't use these and are assumed to handle Unicode.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            return GlyphSet::Unicode;
+        }
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty());
+        Self::for_locale(locale.as_deref())
+    }
+
+    /// Unicode for a UTF-8 locale such as `en_US.UTF-8`; ASCII for anything else,
+    /// including no locale at all, which means "C".
+    pub fn for_locale(locale: Option<&str>) -> Self {
+        let utf8 = locale.is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        });
+        if utf8 {
+            GlyphSet::Unicode
+        } else {
+            GlyphSet::Ascii
+        }
+    }
+
+    /// `text` with its symbols spelled in this set. Labels and help are written with the
+    /// Unicode symbols, so that set borrows them unchanged.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        if self == GlyphSet::Unicode || text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+        let mut out = text.to_string();
+        for (symbol, ascii) in SUBSTITUTIONS {
+            if out.contains(symbol) {
+                out = out.replace(symbol, ascii);
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    /// `label` spelled in this set, abbreviated when that is wider than `width` cells.
+    /// A label with no short form is cut at a char boundary.
+    pub fn fit(self, label: &str, width: usize) -> Cow<'_, str> {
+        let spelled = self.apply(label);
+        if spelled.chars().count() <= width {
+            return spelled;
+        }
+        let short = ABBREVIATIONS
+            .iter()
+            .find(|(long, _)| *long == spelled || *long == label)
+            .map(|(_, short)| self.apply(short).into_owned())
+            .filter(|short| short.chars().count() <= width);
+        match short {
+            Some(short) => Cow::Owned(short),
+            None => Cow::Owned(spelled.chars().take(width).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_spells_out_every_symbol() {
+        let ascii = GlyphSet::Ascii;
+        assert_eq!(ascii.apply("⌫"), "<=");
+        assert_eq!(ascii.apply("x²"), "x^2");
+        assert_eq!(ascii.apply("√ exp abs"), "sqrt exp abs");
+        assert_eq!(ascii.apply("2 × π ÷ 4 − 1"), "2 x pi / 4 - 1");
+        assert_eq!(ascii.apply("↑↓←→"), "arrows");
+        assert_eq!(ascii.apply("Shift+←→"), "Shift+Left/Right");
+        assert!(ascii.apply("Graph").is_ascii());
+        assert_eq!(ascii.apply("⚠ cancellation"), "! cancellation");
+        assert_eq!(GlyphSet::Unicode.apply("x²"), "x²");
+
+        assert_eq!(GlyphSet::for_locale(Some("en_US.UTF-8")), GlyphSet::Unicode);
+        assert_eq!(GlyphSet::for_locale(Some("de_DE.utf8")), GlyphSet::Unicode);
+        assert_eq!(GlyphSet::for_locale(Some("C")), GlyphSet::Ascii);
+        assert_eq!(
+            GlyphSet::for_locale(Some("en_US.ISO-8859-1")),
+            GlyphSet::Ascii
+        );
+        assert_eq!(GlyphSet::for_locale(None), GlyphSet::Ascii);
+    }
+
+    #[test]
+    fn narrow_labels_use_short_forms() {
+        let unicode = GlyphSet::Unicode;
+        assert_eq!(unicode.fit("Graph", 5), "Graph");
+        assert_eq!(unicode.fit("Graph", 3), "Gr");
+        assert_eq!(unicode.fit("2nd", 3), "2nd");
+        assert_eq!(unicode.fit("Copy", 2), "Cp");
+        // ASCII has no one-cell root, so it is cut instead
+        assert_eq!(GlyphSet::Ascii.fit("√", 3), "sqr");
+        assert_eq!(unicode.fit("√", 1), "√");
+        assert_eq!(unicode.fit("Graph", 1), "G");
+    }
+}
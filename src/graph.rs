@@ -1,13 +1,915 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
+use ratatui::layout::Rect;
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+use std::io::Write;
+
+use crate::calculator::{
+    EvalResult, ModuloBehavior, format_result, free_variables, round_sig_figs,
+};
+use crate::eval::{Evaluator, Expr};
 
 #[derive(Debug, Clone)]
 pub struct GraphPoint {
     pub x: f64,
     pub y: f64,
+    /// Whether a line joins this point to the one before: they are on the same branch
+    /// of a piecewise expression, or neighbouring samples of a parametric curve, with
+    /// nothing undefined or out of view between.
+    pub joined: bool,
+}
+
+/// The visible world-space window of the graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x_min: f64,
+    pub x_max: f64,
+    pub y_min: f64,
+    pub y_max: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            x_min: -10.0,
+            x_max: 10.0,
+            y_min: -10.0,
+            y_max: 10.0,
+        }
+    }
+}
+
+/// A run of x values over which the expression was either defined or not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DomainInterval {
+    pub start: f64,
+    pub end: f64,
+    pub defined: bool,
+}
+
+/// Group per-sample outcomes (`x`, whether it evaluated to a finite value) into runs.
+///
+/// Neighbouring runs meet halfway between their edge samples; the first and last runs
+/// start and end at the outermost samples.
+pub fn classify_domain(samples: &[(f64, bool)], intervals: &mut Vec<DomainInterval>) {
+    intervals.clear();
+    for window in samples.windows(2) {
+        let ((x0, defined), (x1, next_defined)) = (window[0], window[1]);
+        if defined != next_defined {
+            let start = intervals.last().map_or(samples[0].0, |last| last.end);
+            intervals.push(DomainInterval {
+                start,
+                end: (x0 + x1) / 2.0,
+                defined,
+            });
+        }
+    }
+    if let Some(&(x, defined)) = samples.last() {
+        let start = intervals.last().map_or(samples[0].0, |last| last.end);
+        intervals.push(DomainInterval {
+            start,
+            end: x,
+            defined,
+        });
+    }
+}
+
+/// Footer note naming where the expression is undefined, e.g. "undefined for x < 2".
+pub fn describe_undefined(intervals: &[DomainInterval]) -> Option<String> {
+    let (first, last) = (intervals.first()?, intervals.last()?);
+    if let [only] = intervals {
+        return (!only.defined).then(|| "undefined everywhere in view".to_string());
+    }
+    let parts: Vec<String> = intervals
+        .iter()
+        .filter(|interval| !interval.defined)
+        .map(|interval| {
+            if interval == first {
+                format!("x < {}", format_bound(interval.end))
+            } else if interval == last {
+                format!("x > {}", format_bound(interval.start))
+            } else {
+                format!(
+                    "{} < x < {}",
+                    format_bound(interval.start),
+                    format_bound(interval.end)
+                )
+            }
+        })
+        .collect();
+    match parts.len() {
+        0 => None,
+        1 | 2 => Some(format!("undefined for {}", parts.join(" and "))),
+        n => Some(format!("undefined for {} and {} more", parts[0], n - 1)),
+    }
+}
+
+fn format_bound(x: f64) -> String {
+    let s = format!("{:.3}", x);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0" } else { s }.to_string()
+}
+
+/// Glyphs a plot is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Charset {
+    /// Box-drawing axes and a dot per cell, as in graph mode.
+    #[default]
+    Unicode,
+    /// Plain ASCII, for places that can't show anything else.
+    Ascii,
+    /// Braille patterns: 2×4 dots per cell, for twice the horizontal and four times the
+    /// vertical resolution. Sample at twice the cell width to use it.
+    Braille,
+}
+
+impl Charset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unicode" => Some(Charset::Unicode),
+            "ascii" => Some(Charset::Ascii),
+            "braille" => Some(Charset::Braille),
+            _ => None,
+        }
+    }
+
+    /// Glyphs for the x-axis, y-axis, origin, a point, an undefined stretch of the x-axis,
+    /// the cursor, a data point, a tangent line, a tick on the x-axis and on the y-axis,
+    /// an upright and a level gridline, a point found on the curve, the area under it
+    /// between two bounds, a bound and the polar grid.
+    fn glyphs(self) -> [char; 16] {
+        match self {
+            Charset::Unicode | Charset::Braille => [
+                '─', '│', '┼', '●', '░', '×', '◆', '·', '┬', '├', '┊', '┈', '◉', '▒', '┆', '∙',
+            ],
+            Charset::Ascii => [
+                '-', '|', '+', '*', '~', 'x', 'o', '.', '+', '+', ':', ':', '@', '#', '!', ':',
+            ],
+        }
+    }
+}
+
+/// The distance between ticks on an axis spanning `span`: 1, 2 or 5 times a power of
+/// ten, the smallest that makes no more than `most` ticks.
+pub fn tick_step(span: f64, most: usize) -> f64 {
+    let rough = span / most.max(1) as f64;
+    let power = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0]
+        .into_iter()
+        .map(|factor| factor * power)
+        .find(|&step| step >= rough)
+        .unwrap_or(10.0 * power)
+}
+
+/// `value`, a multiple of `step`, as a tick label: with as many decimals as `step` has.
+pub fn tick_label(value: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    // Adding 0 turns -0 into 0
+    format!("{:.*}", decimals, value + 0.0)
+}
+
+/// Ticks `step` apart for an axis of `cells` spanning `min..=max`: each one's cell and
+/// value.
+fn axis_ticks(min: f64, max: f64, cells: u16, step: f64) -> Vec<(usize, f64)> {
+    if !step.is_finite() || step <= 0.0 {
+        return Vec::new();
+    }
+    let (low, high) = (min.min(max), min.max(max));
+    ((low / step).ceil() as i64..=(high / step).floor() as i64)
+        .filter_map(|k| {
+            let value = k as f64 * step;
+            Some((usize::from(to_cell(value, min, max, cells)?), value))
+        })
+        .collect()
+}
+
+/// Cell (or dot) index of `value` along an axis of `cells` spanning `min..=max`.
+fn to_cell(value: f64, min: f64, max: f64, cells: u16) -> Option<u16> {
+    let ratio = (value - min) / (max - min);
+    (0.0..=1.0)
+        .contains(&ratio)
+        .then(|| (ratio * cells.saturating_sub(1) as f64).round() as u16)
+}
+
+/// Maps between world coordinates and the cells of a screen area. The first and last
+/// columns sit exactly on `x_min` and `x_max`, the top and bottom rows on `y_max` and
+/// `y_min`, with the cells between evenly spaced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    view: Viewport,
+    area: Rect,
+}
+
+impl Transform {
+    pub fn new(view: Viewport, area: Rect) -> Self {
+        Self { view, area }
+    }
+
+    /// The cell nearest (`x`, `y`), or `None` when the point is out of view.
+    pub fn to_screen(&self, x: f64, y: f64) -> Option<(u16, u16)> {
+        let col = to_cell(x, self.view.x_min, self.view.x_max, self.area.width)?;
+        let row = to_cell(y, self.view.y_max, self.view.y_min, self.area.height)?;
+        Some((self.area.x + col, self.area.y + row))
+    }
+
+    /// The world point cell (`col`, `row`) stands for. Cells outside the area map to
+    /// its nearest edge.
+    pub fn to_world(&self, col: u16, row: u16) -> (f64, f64) {
+        let along = |cell: u16, start: u16, cells: u16| {
+            let steps = cells.saturating_sub(1).max(1);
+            cell.saturating_sub(start).min(steps) as f64 / steps as f64
+        };
+        // Weighting both ends lands exactly on them at the first and last cells
+        let lerp = |from: f64, to: f64, t: f64| from * (1.0 - t) + to * t;
+        let view = self.view;
+        (
+            lerp(
+                view.x_min,
+                view.x_max,
+                along(col, self.area.x, self.area.width),
+            ),
+            lerp(
+                view.y_max,
+                view.y_min,
+                along(row, self.area.y, self.area.height),
+            ),
+        )
+    }
+}
+
+/// Draw `graph`'s gridlines, axes and their ticks, undefined stretches, tangent, points
+/// and data points into `grid`, a row-major buffer of `width` × `height` cells, marking
+/// `cursor` when given. The buffer is refilled in place.
+pub fn draw_grid(
+    grid: &mut Vec<char>,
+    width: usize,
+    height: usize,
+    graph: &GraphModule,
+    charset: Charset,
+    cursor: Option<(f64, f64)>,
+) {
+    grid.clear();
+    grid.resize(width * height, ' ');
+    if width == 0 || height == 0 {
+        return;
+    }
+    let [
+        h_axis,
+        v_axis,
+        origin,
+        point,
+        band,
+        cursor_glyph,
+        datum,
+        touching,
+        x_tick,
+        y_tick,
+        upright,
+        level,
+        found,
+        shade,
+        bound,
+        ring,
+    ] = charset.glyphs();
+    let cells = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+    let view = graph.viewport();
+    let transform = Transform::new(view, Rect::new(0, 0, cells(width), cells(height)));
+    let axis_row = to_cell(0.0, view.y_max, view.y_min, cells(height)).map(usize::from);
+    let axis_col = to_cell(0.0, view.x_min, view.x_max, cells(width)).map(usize::from);
+
+    // Ticks at round values, spaced so their labels fit between them, or the wider
+    // spacing on both axes for a square grid
+    let mut x_step = tick_step(view.x_max - view.x_min, width / 8);
+    let mut y_step = tick_step(view.y_max - view.y_min, height / 3);
+    if graph.square_ticks {
+        x_step = x_step.max(y_step);
+        y_step = x_step;
+    }
+    let x_ticks = match width / 8 {
+        0 | 1 => Vec::new(),
+        _ => axis_ticks(view.x_min, view.x_max, cells(width), x_step),
+    };
+    let y_ticks = match height / 3 {
+        0 | 1 => Vec::new(),
+        _ => axis_ticks(view.y_max, view.y_min, cells(height), y_step),
+    };
+    if graph.polar {
+        // Circles a tick apart, and spokes along the diagonals to go with the axes, each
+        // through every cell it crosses: those the circle's radius or the spoke's line
+        // falls within
+        let step = x_step.max(y_step);
+        let half_x = (view.x_max - view.x_min) / (2 * width.saturating_sub(1).max(1)) as f64;
+        let half_y = (view.y_max - view.y_min) / (2 * height.saturating_sub(1).max(1)) as f64;
+        let spokes = [1.0, 3.0].map(|k: f64| (k * TAU / 8.0).sin_cos());
+        for row in 0..height {
+            for col in 0..width {
+                let (x, y) = transform.to_world(cells(col), cells(row));
+                let corners = [
+                    (x - half_x, y - half_y),
+                    (x + half_x, y - half_y),
+                    (x - half_x, y + half_y),
+                    (x + half_x, y + half_y),
+                ];
+                let near = (x.abs() - half_x)
+                    .max(0.0)
+                    .hypot((y.abs() - half_y).max(0.0));
+                let far = corners.iter().map(|&(x, y)| x.hypot(y)).fold(0.0, f64::max);
+                let circle = (near / step).floor() < (far / step).floor();
+                let spoke = spokes.iter().any(|&(sin, cos)| {
+                    let sides = corners.map(|(x, y)| cos * y - sin * x);
+                    sides.iter().any(|&side| side <= 0.0) && sides.iter().any(|&side| side >= 0.0)
+                });
+                if circle || spoke {
+                    grid[row * width + col] = ring;
+                }
+            }
+        }
+    } else if graph.gridlines {
+        for &(row, _) in &y_ticks {
+            grid[row * width..(row + 1) * width].fill(level);
+        }
+        for &(col, _) in &x_ticks {
+            for row in 0..height {
+                grid[row * width + col] = upright;
+            }
+        }
+    }
+
+    // The x-axis (or the bottom row when it is out of view) carries the undefined band
+    let band_row = axis_row.unwrap_or(height - 1);
+    for col in 0..width {
+        let (x, _) = transform.to_world(cells(col), 0);
+        if !graph.is_defined_at(x) {
+            grid[band_row * width + col] = band;
+        } else if axis_row.is_some() {
+            grid[band_row * width + col] = h_axis;
+        }
+    }
+    if let Some(col) = axis_col {
+        for row in 0..height {
+            grid[row * width + col] = v_axis;
+        }
+        if let Some(row) = axis_row {
+            grid[row * width + col] = origin;
+        }
+    }
+
+    if graph.ticks {
+        // Marks where the axes are in view, with labels beside them, or along the bottom
+        // and left edges when they aren't
+        let label_row = match axis_row {
+            Some(row) if row + 1 < height => row + 1,
+            Some(row) => row.saturating_sub(1),
+            None => height - 1,
+        };
+        let mut free = 0;
+        for &(col, value) in &x_ticks {
+            if let Some(row) = axis_row
+                && grid[row * width + col] == h_axis
+            {
+                grid[row * width + col] = x_tick;
+            }
+            let label: Vec<char> = tick_label(value, x_step).chars().collect();
+            let start = col
+                .saturating_sub(label.len() / 2)
+                .min(width.saturating_sub(label.len()));
+            let end = start + label.len();
+            // Labels keep a space between them and off the y-axis
+            if start < free || end > width || axis_col.is_some_and(|c| (start..end).contains(&c)) {
+                continue;
+            }
+            grid[label_row * width + start..label_row * width + end].copy_from_slice(&label);
+            free = end + 1;
+        }
+        for &(row, value) in &y_ticks {
+            if let Some(col) = axis_col
+                && grid[row * width + col] == v_axis
+            {
+                grid[row * width + col] = y_tick;
+            }
+            if row == label_row || axis_row == Some(row) {
+                continue;
+            }
+            let label: Vec<char> = tick_label(value, y_step).chars().collect();
+            let start = match axis_col {
+                Some(col) if col + 1 < width => col + 1,
+                Some(col) => col.saturating_sub(label.len()),
+                None => 0,
+            };
+            let end = (start + label.len()).min(width);
+            grid[row * width + start..row * width + end].copy_from_slice(&label[..end - start]);
+        }
+    }
+
+    // The area between the bounds is shaded from the x-axis to the curve, leaving the
+    // axis showing, with the bounds drawn down the plot
+    let row_of = |y: f64| {
+        let y = y.clamp(view.y_min, view.y_max);
+        ((view.y_max - y) / (view.y_max - view.y_min) * (height - 1) as f64).round() as usize
+    };
+    if let Some(integral) = &graph.integral {
+        for &(x, y) in &integral.samples {
+            let (Some(col), Some(y)) = (to_cell(x, view.x_min, view.x_max, cells(width)), y) else {
+                continue;
+            };
+            let (zero, top) = (row_of(0.0), row_of(y));
+            for row in zero.min(top)..=zero.max(top) {
+                if axis_row != Some(row) {
+                    grid[row * width + usize::from(col)] = shade;
+                }
+            }
+        }
+    }
+    for &x in &graph.bounds {
+        if let Some(col) = to_cell(x, view.x_min, view.x_max, cells(width)) {
+            for row in 0..height {
+                grid[row * width + usize::from(col)] = bound;
+            }
+        }
+    }
+
+    // The tangent goes under the curve, each column filled across the rows the line
+    // passes through in it, so a steep one stays unbroken
+    if let Some(tangent) = graph.tangent {
+        // Columns and rows as fractions, the first and last on the edges of the view
+        let x_step = (view.x_max - view.x_min) / width.saturating_sub(1).max(1) as f64;
+        let row_at = |col: f64| {
+            let y = tangent.y_at(view.x_min + col * x_step);
+            (view.y_max - y) / (view.y_max - view.y_min) * (height - 1) as f64
+        };
+        for col in 0..width {
+            let (top, bottom) = (row_at(col as f64 - 0.5), row_at(col as f64 + 0.5));
+            let first = top.min(bottom).round().max(0.0);
+            let last = top.max(bottom).round().min((height - 1) as f64);
+            if first <= last {
+                for row in first as usize..=last as usize {
+                    grid[row * width + col] = touching;
+                }
+            }
+        }
+    }
+
+    // Braille plots into dots, 2×4 to a cell; otherwise each point takes a whole cell
+    let braille = charset == Charset::Braille;
+    let (across, down) = if braille { (2, 4) } else { (1, 1) };
+    let dots = Transform::new(
+        view,
+        Rect::new(0, 0, cells(width * across), cells(height * down)),
+    );
+    let mut plot = |dx: usize, dy: usize| {
+        let cell = &mut grid[(dy / down) * width + dx / across];
+        if !braille {
+            *cell = point;
+            return;
+        }
+        let bits = match *cell as u32 {
+            dots @ 0x2800..=0x28FF => dots - 0x2800,
+            _ => 0,
+        };
+        // Dot numbering runs down the left column, then the right, then the bottom row
+        let dot = match (dx % 2, dy % 4) {
+            (0, 3) => 0x40,
+            (1, 3) => 0x80,
+            (0, row) => 1 << row,
+            (_, row) => 8 << row,
+        };
+        *cell = char::from_u32(0x2800 + (bits | dot)).unwrap_or(point);
+    };
+    let mut previous_row = None;
+    for p in &graph.points {
+        let Some((dx, dy)) = dots.to_screen(p.x, p.y) else {
+            previous_row = None;
+            continue;
+        };
+        let (dx, dy) = (usize::from(dx), usize::from(dy));
+        // A joined point fills the rows from the last one, so a steep branch stays unbroken
+        if p.joined
+            && let Some(last) = previous_row
+        {
+            for row in dy.min(last) + 1..dy.max(last) {
+                plot(dx, row);
+            }
+        }
+        plot(dx, dy);
+        previous_row = Some(dy);
+    }
+
+    // Data points and points found on the curve take whole cells, over it, so they
+    // stand out from it
+    for &(x, y) in &graph.scatter {
+        if let Some((col, row)) = transform.to_screen(x, y) {
+            grid[usize::from(row) * width + usize::from(col)] = datum;
+        }
+    }
+    for point in graph.findings.iter().flat_map(|findings| &findings.points) {
+        if let Some((col, row)) = transform.to_screen(point.x, point.y) {
+            grid[usize::from(row) * width + usize::from(col)] = found;
+        }
+    }
+
+    if let Some((x, y)) = cursor
+        && let Some((col, row)) = transform.to_screen(x, y)
+    {
+        grid[usize::from(row) * width + usize::from(col)] = cursor_glyph;
+    }
+}
+
+/// The line touching a curve at (`x`, `y`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tangent {
+    pub x: f64,
+    pub y: f64,
+    pub slope: f64,
+}
+
+impl Tangent {
+    /// Where the line crosses the y-axis.
+    pub fn intercept(&self) -> f64 {
+        self.y - self.slope * self.x
+    }
+
+    pub fn y_at(&self, x: f64) -> f64 {
+        self.y + self.slope * (x - self.x)
+    }
+
+    /// The line as `y = mx + b`, to six significant figures, leaving out a slope of 1
+    /// and an intercept of 0.
+    pub fn equation(&self) -> String {
+        let (slope, intercept) = (six_figures(self.slope), six_figures(self.intercept()));
+        let term = match slope.as_str() {
+            "0" => return format!("y = {}", intercept),
+            "1" => "x".to_string(),
+            "-1" => "-x".to_string(),
+            _ => format!("{}x", slope),
+        };
+        match intercept.strip_prefix('-') {
+            _ if intercept == "0" => format!("y = {}", term),
+            Some(magnitude) => format!("y = {} - {}", term, magnitude),
+            None => format!("y = {} + {}", term, intercept),
+        }
+    }
+
+    /// Where the tangent touches and its equation, for the status line.
+    pub fn describe(&self) -> String {
+        format!(
+            "Tangent at ({}, {}): {}",
+            six_figures(self.x),
+            six_figures(self.y),
+            self.equation()
+        )
+    }
+}
+
+/// `value` to six significant figures, a rounded -0 shown as 0. Searches land a hair
+/// off zero, such as a minimum of `x^2` at 1e-24, so anything that close reads as 0.
+fn six_figures(value: f64) -> String {
+    let value = if value.abs() < 1e-10 { 0.0 } else { value };
+    format_result(round_sig_figs(value, 6.0).unwrap_or(value) + 0.0)
+}
+
+/// Samples taken across the t range of a parametric curve, or the turn of a polar one.
+const TRACE_SAMPLES: usize = 1000;
+/// Samples taken across the x range when looking for points on the curve.
+const SEARCH_SAMPLES: usize = 1000;
+/// Intervals Simpson's rule splits an integral into; even, as it needs.
+const INTEGRAL_STEPS: usize = 1024;
+
+/// The area under the first curve between two bounds, shaded on the plot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Integral {
+    pub from: f64,
+    pub to: f64,
+    /// By Simpson's rule, or `None` where the curve is undefined between the bounds.
+    pub area: Option<f64>,
+    /// The curve at evenly spaced x from `from` to `to`.
+    samples: Vec<(f64, Option<f64>)>,
+}
+
+impl Integral {
+    /// The bounds and the area, for the status line.
+    pub fn describe(&self) -> String {
+        let (from, to) = (six_figures(self.from), six_figures(self.to));
+        match self.area {
+            Some(area) => format!("∫ from {} to {} = {}", from, to, six_figures(area)),
+            None => format!(
+                "∫ from {} to {} is undefined: the curve has a gap between",
+                from, to
+            ),
+        }
+    }
+}
+
+/// The curves of a graph expression, one per `;`-separated statement: `x^2; 2x + 1`
+/// plots two.
+pub fn curves(expression: &str) -> impl Iterator<Item = &str> {
+    expression
+        .split(';')
+        .map(str::trim)
+        .filter(|curve| !curve.is_empty())
+}
+
+/// Whether `curve` is parametric, a list such as `[cos(t), sin(t)]` giving x and y in
+/// terms of `t`, rather than a function of `x`.
+pub fn is_parametric(curve: &str) -> bool {
+    curve.trim_start().starts_with('[')
+}
+
+/// The right-hand side of a polar curve, `r = f(theta)`, or `None` for any other.
+pub fn polar(curve: &str) -> Option<&str> {
+    let rest = curve.trim_start().strip_prefix('r')?.trim_start();
+    let rest = rest.strip_prefix('=')?;
+    (!rest.starts_with('=')).then_some(rest)
+}
+
+/// How a curve that isn't a function of x is traced out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trace {
+    /// `[x(t), y(t)]`, with t over a range.
+    Parametric(f64, f64),
+    /// `r = f(theta)`, with theta over a full turn.
+    Polar,
+    /// `f(x, y)`, drawn where it's 0.
+    Implicit,
+}
+
+/// Whether `curve` is an implicit relation, such as `x^2 + y^2 - 25`: one in `y` as well
+/// as `x`, plotted where it's 0.
+pub fn is_implicit(curve: &str) -> bool {
+    polar(curve).is_none()
+        && !is_parametric(curve)
+        && free_variables(curve).iter().any(|name| name == "y")
+}
+
+/// Write the curves of `expression` as CSV, sampled at `samples` evenly spaced x from
+/// `from` to `to`: a header, then a row per sample with x and each curve's y, left blank
+/// where that curve is undefined. Only curves in x can be written so. Checks
+/// `cancelled` before each row, and returns `Ok(false)` when it stopped early.
+pub fn write_csv(
+    out: &mut impl Write,
+    evaluator: &Evaluator,
+    expression: &str,
+    (from, to): (f64, f64),
+    samples: usize,
+    cancelled: impl Fn() -> bool,
+) -> Result<bool> {
+    let mut trees = Vec::new();
+    for curve in curves(expression) {
+        if polar(curve).is_some() || is_parametric(curve) || is_implicit(curve) {
+            bail!("only curves in x can be exported, not {}", curve);
+        }
+        trees.push(evaluator.parse(curve)?);
+    }
+    if trees.is_empty() {
+        bail!("there is no curve to export");
+    }
+
+    write!(out, "x")?;
+    for curve in curves(expression) {
+        // Quoted, as a curve such as max(x, 1) has commas of its own
+        write!(out, ",\"{}\"", curve.replace('"', "\"\""))?;
+    }
+    writeln!(out)?;
+    let step = (to - from) / samples.saturating_sub(1).max(1) as f64;
+    for i in 0..samples {
+        if cancelled() {
+            return Ok(false);
+        }
+        let x = from + step * i as f64;
+        write!(out, "{}", x)?;
+        for tree in &trees {
+            match evaluator.bind("x", x).eval(tree) {
+                Ok(y) if y.is_finite() => write!(out, ",{}", y)?,
+                _ => write!(out, ",")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(true)
+}
+
+/// Where `f` is 0 across `view`, by marching squares over a lattice `columns` by `rows`
+/// squares: the curve crosses each edge whose corners differ in sign, at a point found
+/// between them by bisection, and the crossings of a square are joined in pairs. One
+/// `f` doesn't close in on 0 at, across a pole or a jump, is left out.
+fn contour(
+    f: impl Fn(f64, f64) -> Option<f64>,
+    view: Viewport,
+    columns: usize,
+    rows: usize,
+    points: &mut Vec<GraphPoint>,
+) {
+    let (columns, rows) = (columns.max(1), rows.max(1));
+    let at = |i: usize, j: usize| {
+        let (across, up) = (i as f64 / columns as f64, j as f64 / rows as f64);
+        (
+            view.x_min * (1.0 - across) + view.x_max * across,
+            view.y_min * (1.0 - up) + view.y_max * up,
+        )
+    };
+    let values: Vec<Option<f64>> = (0..=rows)
+        .flat_map(|j| (0..=columns).map(move |i| (i, j)))
+        .map(|(i, j)| {
+            let (x, y) = at(i, j);
+            f(x, y)
+        })
+        .collect();
+    for j in 0..rows {
+        for i in 0..columns {
+            // Corners anticlockwise from the bottom left; edge `k` runs from corner `k`
+            let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+            let Some(v) = corners
+                .iter()
+                .map(|&(i, j)| values[j * (columns + 1) + i])
+                .collect::<Option<Vec<f64>>>()
+            else {
+                continue;
+            };
+            let crossings: Vec<(f64, f64)> = (0..4)
+                .filter_map(|k| {
+                    let (a, b) = (k, (k + 1) % 4);
+                    if (v[a] > 0.0) == (v[b] > 0.0) {
+                        return None;
+                    }
+                    let ((x0, y0), (x1, y1)) = (
+                        at(corners[a].0, corners[a].1),
+                        at(corners[b].0, corners[b].1),
+                    );
+                    let along = |t: f64| (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+                    // A corner the curve runs through exactly is where it crosses
+                    let (mut lo, mut hi) = match (v[a] == 0.0, v[b] == 0.0) {
+                        (true, _) => (0.0, 0.0),
+                        (_, true) => (1.0, 1.0),
+                        _ => (0.0, 1.0),
+                    };
+                    let (mut lo_value, mut hi_value) = (v[a], v[b]);
+                    for _ in 0..40 {
+                        let mid = (lo + hi) / 2.0;
+                        let (x, y) = along(mid);
+                        match f(x, y) {
+                            Some(value) if (value > 0.0) == (v[a] > 0.0) => {
+                                (lo, lo_value) = (mid, value)
+                            }
+                            Some(value) => (hi, hi_value) = (mid, value),
+                            None => return None,
+                        }
+                    }
+                    // The nearer 0 of the ends it narrowed down to
+                    let (t, value) = if lo_value.abs() <= hi_value.abs() {
+                        (lo, lo_value)
+                    } else {
+                        (hi, hi_value)
+                    };
+                    (value.abs() <= v[a].abs().min(v[b].abs())).then(|| along(t))
+                })
+                .collect();
+            // A saddle, with every edge crossed, is split the way the middle of the square
+            // leans, cutting off the two corners on its other side
+            let segments: &[&[usize]] = match crossings.len() {
+                2 => &[&[0, 1]],
+                4 if (v.iter().sum::<f64>() > 0.0) == (v[0] > 0.0) => &[&[0, 1], &[2, 3]],
+                4 => &[&[3, 0], &[1, 2]],
+                1 => &[&[0]],
+                3 => &[&[0], &[1], &[2]],
+                _ => &[],
+            };
+            for segment in segments {
+                for (n, &end) in segment.iter().enumerate() {
+                    let (x, y) = crossings[end];
+                    points.push(GraphPoint {
+                        x,
+                        y,
+                        joined: n > 0,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The t range of a parametric curve: 0 to 2π for `[x(t), y(t)]`, or `from` to `to` for
+/// `[x(t), y(t), from, to]`. `None` for a curve in x.
+fn t_range(evaluator: &Evaluator, tree: &Expr) -> EvalResult<Option<(f64, f64)>> {
+    Ok(match tree {
+        Expr::List(items) if items.len() == 2 => Some((0.0, TAU)),
+        Expr::List(items) if items.len() == 4 => {
+            Some((evaluator.eval(&items[2])?, evaluator.eval(&items[3])?))
+        }
+        _ => None,
+    })
+}
+
+/// A kind of point on the curve to look for across the x range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    /// Where a curve crosses the x-axis.
+    Roots,
+    /// Where two curves cross each other.
+    Intersections,
+    /// Where a curve turns around: its local minima and maxima.
+    Extrema,
+}
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Roots => "Roots",
+            Feature::Intersections => "Intersections",
+            Feature::Extrema => "Extrema",
+        }
+    }
+
+    /// The name of one of them.
+    fn singular(self) -> &'static str {
+        match self {
+            Feature::Roots => "root",
+            Feature::Intersections => "intersection",
+            Feature::Extrema => "extremum",
+        }
+    }
+}
+
+/// A point found on a curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Found {
+    pub x: f64,
+    pub y: f64,
+    /// Which sort it is, for a feature with more than one, such as "min" or "max".
+    pub kind: Option<&'static str>,
+}
+
+/// The points of a [`Feature`] found across the x range, left to right, marked on the
+/// plot and listed beside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Findings {
+    pub feature: Feature,
+    pub points: Vec<Found>,
+}
+
+impl Findings {
+    /// One line per point, for the list beside the plot.
+    pub fn lines(&self) -> Vec<String> {
+        if self.points.is_empty() {
+            return vec!["None in view".to_string()];
+        }
+        self.points
+            .iter()
+            .map(|found| {
+                let point = format!("({}, {})", six_figures(found.x), six_figures(found.y));
+                match (self.feature, found.kind) {
+                    (Feature::Roots, _) => format!("x = {}", six_figures(found.x)),
+                    (_, Some(kind)) => format!("{} {}", kind, point),
+                    (_, None) => point,
+                }
+            })
+            .collect()
+    }
+
+    /// How many were found, for the status line.
+    pub fn summary(&self) -> String {
+        match self.points.len() {
+            0 => format!("No {} in view", self.feature.name().to_lowercase()),
+            1 => format!("1 {} in view", self.feature.singular()),
+            count => format!("{} {} in view", count, self.feature.name().to_lowercase()),
+        }
+    }
 }
 
 pub struct GraphModule {
     pub points: Vec<GraphPoint>,
+    /// Data plotted as points alongside the expression, such as the statistics table's
+    /// (x, y) pairs.
+    pub scatter: Vec<(f64, f64)>,
+    /// Where the expression is defined across the sampled x range.
+    pub domain: Vec<DomainInterval>,
+    /// The tangent drawn over the curve, from `t` in graph mode.
+    pub tangent: Option<Tangent>,
+    /// Points found on the curve in view, such as its roots.
+    pub findings: Option<Findings>,
+    /// The x bounds dropped on the plot, at most two.
+    pub bounds: Vec<f64>,
+    /// The area under the curve between the two bounds, once both are down.
+    pub integral: Option<Integral>,
+    /// Mark round values along the axes and label them.
+    pub ticks: bool,
+    /// Draw lines across the plot at the ticks.
+    pub gridlines: bool,
+    /// Space the ticks the same on both axes, so that with the aspect locked the
+    /// gridlines make squares.
+    pub square_ticks: bool,
+    /// Whether a polar curve is plotted, drawn over circles about the origin and spokes
+    /// through it in place of the gridlines.
+    pub polar: bool,
+    /// Outcome of each sample in the last generation, kept to reuse its allocation.
+    samples: Vec<(f64, bool)>,
+    /// Expression, viewport and sample counts across and down the points were last
+    /// generated for.
+    generated: Option<(String, Viewport, (u16, u16))>,
+    /// Values for names other than `x`, such as the calculator's variables.
+    variables: BTreeMap<String, f64>,
+    /// The calculator's last result, for expressions that use `ans`.
+    ans: Option<f64>,
+    /// Which sign `%` takes, as in the calculator.
+    modulo: ModuloBehavior,
     pub x_min: f64,
     pub x_max: f64,
     pub y_min: f64,
@@ -24,6 +926,21 @@ impl GraphModule {
     pub fn new() -> Self {
         Self {
             points: Vec::new(),
+            scatter: Vec::new(),
+            domain: Vec::new(),
+            tangent: None,
+            findings: None,
+            bounds: Vec::new(),
+            integral: None,
+            ticks: false,
+            gridlines: false,
+            square_ticks: false,
+            polar: false,
+            samples: Vec::new(),
+            generated: None,
+            variables: BTreeMap::new(),
+            ans: None,
+            modulo: ModuloBehavior::default(),
             x_min: -10.0,
             x_max: 10.0,
             y_min: -10.0,
@@ -31,277 +948,1047 @@ impl GraphModule {
         }
     }
 
-    pub fn generate_points(&mut self, expression: &str, width: u16, _height: u16) -> Result<()> {
-        self.points.clear();
+    /// Sample `expression` across the x range into `points` and `domain`. Nothing is
+    /// redone when the expression, viewport and size match the last call. The
+    /// expression is parsed once and its tree evaluated at each sample; one that doesn't
+    /// parse is an error, and leaves nothing plotted. An empty one plots no curve, leaving
+    /// just the data points. Several curves separated by `;` are all plotted, the
+    /// domain following the first. A parametric curve, `[x(t), y(t)]`, is traced across
+    /// its t range instead, and a polar one, `r = f(theta)`, across a turn, each sample
+    /// joined to the last. An implicit relation in `x` and `y` is drawn where it's 0, by
+    /// marching squares over a lattice `width` by `height` squares.
+    pub fn generate_points(&mut self, expression: &str, width: u16, height: u16) -> EvalResult<()> {
+        let viewport = self.viewport();
+        let size = (width, height);
+        match &mut self.generated {
+            Some((last, last_viewport, last_size))
+                if last == expression && *last_viewport == viewport && *last_size == size =>
+            {
+                return Ok(());
+            }
+            Some((last, last_viewport, last_size)) => {
+                last.clear();
+                last.push_str(expression);
+                *last_viewport = viewport;
+                *last_size = size;
+            }
+            None => self.generated = Some((expression.to_string(), viewport, size)),
+        }
 
+        self.polar = curves(expression).any(|curve| polar(curve).is_some());
+        // At most one point per sample, so after the first pass these never reallocate
+        self.points.clear();
+        self.samples.clear();
+        if curves(expression).next().is_none() {
+            self.domain.clear();
+            return Ok(());
+        }
+        // Borrows only the bindings, leaving the sample buffers free to fill
+        let evaluator = Evaluator {
+            ans: self.ans,
+            modulo: self.modulo,
+            ..Evaluator::new(&self.variables)
+        };
+        let trees = match curves(expression)
+            .map(|curve| {
+                if let Some(r) = polar(curve) {
+                    return Ok((evaluator.parse(r)?, Some(Trace::Polar)));
+                }
+                let tree = evaluator.parse(curve)?;
+                if is_implicit(curve) {
+                    return Ok((tree, Some(Trace::Implicit)));
+                }
+                let trace =
+                    t_range(&evaluator, &tree)?.map(|(from, to)| Trace::Parametric(from, to));
+                Ok((tree, trace))
+            })
+            .collect::<EvalResult<Vec<_>>>()
+        {
+            Ok(trees) => trees,
+            Err(e) => {
+                self.domain.clear();
+                self.generated = None;
+                return Err(e);
+            }
+        };
+        self.points.reserve(width as usize * trees.len());
         let x_range = self.x_max - self.x_min;
+        let in_view = |y: &f64| (self.y_min..=self.y_max).contains(y);
 
-        // Generate points for the graph
-        for i in 0..width {
-            let x = self.x_min + (i as f64 / width as f64) * x_range;
+        for (index, (tree, trace)) in trees.iter().enumerate() {
+            if let Some(trace) = *trace {
+                let (from, to) = match trace {
+                    Trace::Parametric(from, to) => (from, to),
+                    Trace::Polar => (0.0, TAU),
+                    Trace::Implicit => {
+                        // An open y range, as the command line samples with to fit it,
+                        // takes the x range's
+                        let mut view = viewport;
+                        if !(view.y_min.is_finite() && view.y_max.is_finite()) {
+                            (view.y_min, view.y_max) = (view.x_min, view.x_max);
+                        }
+                        let f = |x: f64, y: f64| {
+                            evaluator
+                                .bind("x", x)
+                                .bind("y", y)
+                                .eval(tree)
+                                .ok()
+                                .filter(|value| value.is_finite())
+                        };
+                        contour(f, view, width.into(), height.into(), &mut self.points);
+                        continue;
+                    }
+                };
+                let at = |name: &'static str, item: &Expr, value: f64| {
+                    evaluator
+                        .bind(name, value)
+                        .eval(item)
+                        .ok()
+                        .filter(|value| value.is_finite())
+                };
+                let mut last_x = None;
+                for i in 0..=TRACE_SAMPLES {
+                    let t = from + (to - from) * (i as f64 / TRACE_SAMPLES as f64);
+                    let point = match (trace, tree) {
+                        (Trace::Polar, _) => {
+                            at("theta", tree, t).map(|r| (r * t.cos(), r * t.sin()))
+                        }
+                        (_, Expr::List(items)) => at("t", &items[0], t).zip(at("t", &items[1], t)),
+                        _ => None,
+                    };
+                    let point = point
+                        .filter(|&(x, y)| (self.x_min..=self.x_max).contains(&x) && in_view(&y));
+                    let Some((x, y)) = point else {
+                        last_x = None;
+                        continue;
+                    };
+                    // Filling in rows only makes a line between samples a column or
+                    // less apart; one leaping across the plot stays a gap
+                    let joined =
+                        last_x.is_some_and(|last: f64| (x - last).abs() * width as f64 <= x_range);
+                    self.points.push(GraphPoint { x, y, joined });
+                    last_x = Some(x);
+                }
+                continue;
+            }
+            let y_at = |x: f64| {
+                evaluator
+                    .bind("x", x)
+                    .eval(tree)
+                    .ok()
+                    .filter(|y| y.is_finite())
+            };
+            // A piecewise expression is drawn as unbroken branches, each running right up
+            // to where a condition flips, but never joined across one
+            let piecewise = tree.has_conditions();
+            let branch_at = |x: f64| evaluator.bind("x", x).branches(tree);
+            let mut last_branch = None;
+            let mut joinable = false;
+
+            // Generate points for the graph
+            for i in 0..width {
+                let x = self.x_min + (i as f64 / width as f64) * x_range;
+                let y = y_at(x);
+                if index == 0 {
+                    self.samples.push((x, y.is_some()));
+                }
 
-            // Replace 'x' with the current x value in the expression
-            let expr_with_x = expression.replace('x', &format!("({})", x));
+                if piecewise && y.is_some() {
+                    let branch = branch_at(x);
+                    if let Some((last_x, last)) = last_branch
+                        && last != branch
+                    {
+                        let (mut lo, mut hi) = (last_x, x);
+                        for _ in 0..40 {
+                            let mid = (lo + hi) / 2.0;
+                            if branch_at(mid) == last {
+                                lo = mid;
+                            } else {
+                                hi = mid;
+                            }
+                        }
+                        // End the old branch and start the new one on either side of the flip
+                        for (end, joined) in [(lo, joinable), (hi, false)] {
+                            joinable = false;
+                            if let Some(y) = y_at(end).filter(in_view) {
+                                self.points.push(GraphPoint { x: end, y, joined });
+                                joinable = true;
+                            }
+                        }
+                    }
+                    last_branch = Some((x, branch));
+                } else {
+                    last_branch = None;
+                }
 
-            match self.evaluate_expression(&expr_with_x) {
-                Ok(y) => {
-                    // Only add points that are within the y range
-                    if y >= self.y_min && y <= self.y_max && y.is_finite() {
-                        self.points.push(GraphPoint { x, y });
+                // Only add points that are within the y range
+                match y.filter(in_view) {
+                    Some(y) => {
+                        self.points.push(GraphPoint {
+                            x,
+                            y,
+                            joined: piecewise && joinable,
+                        });
+                        joinable = true;
                     }
+                    None => joinable = false,
                 }
-                Err(_) => {
-                    // Skip invalid points
-                    continue;
+            }
+        }
+
+        classify_domain(&self.samples, &mut self.domain);
+        let y_at = |x: f64| {
+            evaluator
+                .bind("x", x)
+                .eval(&trees[0].0)
+                .ok()
+                .filter(|y| y.is_finite())
+        };
+        // Pin each boundary down between the samples either side of it
+        let step = x_range / width.max(1) as f64;
+        for i in 1..self.domain.len() {
+            let boundary = self.domain[i].start;
+            let (mut lo, mut hi) = (boundary - step / 2.0, boundary + step / 2.0);
+            let lo_defined = self.domain[i - 1].defined;
+            for _ in 0..40 {
+                let mid = (lo + hi) / 2.0;
+                let defined = y_at(mid).is_some();
+                if defined == lo_defined {
+                    lo = mid;
+                } else {
+                    hi = mid;
                 }
             }
+            self.domain[i - 1].end = hi;
+            self.domain[i].start = hi;
         }
 
         Ok(())
     }
 
-    pub fn get_point_at_x(&self, x: f64, expression: &str) -> Option<f64> {
-        let expr_with_x = expression.replace('x', &format!("({})", x));
-        self.evaluate_expression(&expr_with_x).ok()
+    /// Bind names other than `x`, and `ans` to the last result; the next
+    /// `generate_points` resamples when they changed.
+    pub fn set_variables(&mut self, variables: &BTreeMap<String, f64>, ans: Option<f64>) {
+        if self.variables != *variables || self.ans != ans {
+            self.variables = variables.clone();
+            self.ans = ans;
+            self.generated = None;
+        }
+    }
+
+    /// Take `%` as `modulo` does; the next `generate_points` resamples when it changed.
+    pub fn set_modulo(&mut self, modulo: ModuloBehavior) {
+        if self.modulo != modulo {
+            self.modulo = modulo;
+            self.generated = None;
+        }
+    }
+
+    /// A view of all the data points with a margin around them, or `None` without any.
+    pub fn data_view(&self) -> Option<Viewport> {
+        let (first, rest) = self.scatter.split_first()?;
+        let mut view = Viewport {
+            x_min: first.0,
+            x_max: first.0,
+            y_min: first.1,
+            y_max: first.1,
+        };
+        for &(x, y) in rest {
+            view.x_min = view.x_min.min(x);
+            view.x_max = view.x_max.max(x);
+            view.y_min = view.y_min.min(y);
+            view.y_max = view.y_max.max(y);
+        }
+        // A tenth of the spread each side, or a unit when all the values are equal
+        let pad = |min: f64, max: f64| if max > min { (max - min) / 10.0 } else { 1.0 };
+        let (x_pad, y_pad) = (pad(view.x_min, view.x_max), pad(view.y_min, view.y_max));
+        Some(Viewport {
+            x_min: view.x_min - x_pad,
+            x_max: view.x_max + x_pad,
+            y_min: view.y_min - y_pad,
+            y_max: view.y_max + y_pad,
+        })
     }
 
-    fn evaluate_expression(&self, expr: &str) -> Result<f64> {
-        let expr = expr.trim();
-        if expr.is_empty() {
-            return Ok(0.0);
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            x_min: self.x_min,
+            x_max: self.x_max,
+            y_min: self.y_min,
+            y_max: self.y_max,
         }
+    }
 
-        let tokens = self.tokenize(expr)?;
-        let (result, _) = self.parse_expression(&tokens, 0)?;
-        Ok(result)
+    /// Whether the last generated samples found the expression defined at `x`.
+    pub fn is_defined_at(&self, x: f64) -> bool {
+        self.domain
+            .iter()
+            .find(|interval| x <= interval.end)
+            .or(self.domain.last())
+            .is_none_or(|interval| interval.defined)
     }
 
-    fn tokenize(&self, expr: &str) -> Result<Vec<Token>> {
-        let mut tokens = Vec::new();
-        let mut chars = expr.chars().peekable();
-        let mut num_buf = String::new();
-        let mut ident_buf = String::new();
+    pub fn get_point_at_x(&self, x: f64, expression: &str) -> Option<f64> {
+        self.evaluate_at(x, expression).ok()
+    }
+
+    /// The tangent to `expression` at `x`, or `None` where it's undefined or too steep.
+    /// The slope is a five-point central difference over a step a hundred-thousandth of
+    /// the x range, so it follows the features in view.
+    pub fn tangent_at(&self, x: f64, expression: &str) -> Option<Tangent> {
+        let y = self.get_point_at_x(x, expression)?;
+        let step = (self.x_max - self.x_min) * 1e-5;
+        let at = |offset: f64| self.get_point_at_x(x + offset * step, expression);
+        let slope = (8.0 * (at(1.0)? - at(-1.0)?) - (at(2.0)? - at(-2.0)?)) / (12.0 * step);
+        (y.is_finite() && slope.is_finite()).then_some(Tangent { x, y, slope })
+    }
 
-        while let Some(&ch) = chars.peek() {
-            match ch {
-                '0'..='9' | '.' => {
-                    num_buf.push(ch);
-                    chars.next();
+    /// The points of `feature` on the curves of `expression` across the x range, left
+    /// to right. Intersections are those of every pair of curves.
+    pub fn find(&self, feature: Feature, expression: &str) -> EvalResult<Findings> {
+        let curves = curves(expression)
+            .map(|curve| self.curve(curve))
+            .collect::<EvalResult<Vec<_>>>()?;
+        let found = |x: f64, y: f64, kind: Option<&'static str>| Found { x, y, kind };
+        let mut points = Vec::new();
+        for (i, f) in curves.iter().enumerate() {
+            match feature {
+                Feature::Roots => {
+                    points.extend(self.roots(f).into_iter().map(|x| found(x, 0.0, None)))
                 }
-                'a'..='z' | 'A'..='Z' | 'π' => {
-                    if !num_buf.is_empty() {
-                        tokens.push(Token::Number(num_buf.parse()?));
-                        num_buf.clear();
-                    }
-                    ident_buf.push(ch);
-                    chars.next();
-                    while let Some(&nc) = chars.peek() {
-                        if nc.is_alphanumeric() || nc == '_' {
-                            ident_buf.push(nc);
-                            chars.next();
-                        } else {
-                            break;
+                Feature::Intersections => {
+                    for g in &curves[i + 1..] {
+                        let apart = |x: f64| Some(f(x)? - g(x)?);
+                        for x in self.roots(apart) {
+                            points.extend(f(x).map(|y| found(x, y, None)));
                         }
                     }
-                    let ident = ident_buf.to_lowercase();
-                    ident_buf.clear();
-                    match ident.as_str() {
-                        "pi" | "π" => tokens.push(Token::Number(std::f64::consts::PI)),
-                        "e" => tokens.push(Token::Number(std::f64::consts::E)),
-                        _ => tokens.push(Token::Ident(ident)),
-                    }
                 }
-                '+' | '-' | '*' | '/' | '^' | '%' | '(' | ')' => {
-                    if !num_buf.is_empty() {
-                        tokens.push(Token::Number(num_buf.parse()?));
-                        num_buf.clear();
+                Feature::Extrema => {
+                    for (x, maximum) in self.extrema(f) {
+                        let kind = if maximum { "max" } else { "min" };
+                        points.extend(f(x).map(|y| found(x, y, Some(kind))));
                     }
-                    tokens.push(match ch {
-                        '+' => Token::Plus,
-                        '-' => Token::Minus,
-                        '*' => Token::Multiply,
-                        '/' => Token::Divide,
-                        '^' => Token::Power,
-                        '%' => Token::Modulo,
-                        '(' => Token::LParen,
-                        ')' => Token::RParen,
-                        _ => unreachable!(),
-                    });
-                    chars.next();
-                }
-                ' ' => {
-                    chars.next();
-                }
-                _ => {
-                    return Err(anyhow::anyhow!("Invalid character: {}", ch));
                 }
             }
         }
+        points.sort_by(|a, b| a.x.total_cmp(&b.x));
+        Ok(Findings { feature, points })
+    }
 
-        if !num_buf.is_empty() {
-            tokens.push(Token::Number(num_buf.parse()?));
-        }
+    /// The area under the first curve of `expression` from `from` to `to`, negative where
+    /// the curve is below the x-axis or the bounds are the wrong way round.
+    pub fn integrate(&self, expression: &str, from: f64, to: f64) -> EvalResult<Integral> {
+        let first = curves(expression).next().unwrap_or(expression);
+        let f = self.curve(first)?;
+        let step = (to - from) / INTEGRAL_STEPS as f64;
+        let samples: Vec<(f64, Option<f64>)> = (0..=INTEGRAL_STEPS)
+            .map(|i| {
+                let x = if i == INTEGRAL_STEPS {
+                    to
+                } else {
+                    from + step * i as f64
+                };
+                (x, f(x))
+            })
+            .collect();
+        let area = samples
+            .iter()
+            .enumerate()
+            .try_fold(0.0, |sum, (i, &(_, y))| {
+                let weight = match i {
+                    0 | INTEGRAL_STEPS => 1.0,
+                    _ if i % 2 == 1 => 4.0,
+                    _ => 2.0,
+                };
+                Some(sum + weight * y?)
+            })
+            .map(|sum| sum * step / 3.0);
+        Ok(Integral {
+            from,
+            to,
+            area,
+            samples,
+        })
+    }
 
-        // Add implicit multiplication tokens
-        let mut result = Vec::new();
-        for (i, token) in tokens.iter().enumerate() {
-            result.push(token.clone());
+    /// `expression` as a function of `x`, parsed once: `None` where it's undefined.
+    fn curve(&self, expression: &str) -> EvalResult<impl Fn(f64) -> Option<f64> + '_> {
+        let evaluator = Evaluator {
+            ans: self.ans,
+            modulo: self.modulo,
+            ..Evaluator::new(&self.variables)
+        };
+        let tree = evaluator.parse(expression)?;
+        Ok(move |x: f64| {
+            evaluator
+                .bind("x", x)
+                .eval(&tree)
+                .ok()
+                .filter(|y| y.is_finite())
+        })
+    }
 
-            // Check if we need to add implicit multiplication
-            if i < tokens.len() - 1 {
-                match (token, &tokens[i + 1]) {
-                    // Number followed by opening parenthesis: 3( -> 3*(
-                    (Token::Number(_), Token::LParen) => {
-                        result.push(Token::Multiply);
-                    }
-                    // Closing parenthesis followed by number: )3 -> )*3
-                    (Token::RParen, Token::Number(_)) => {
-                        result.push(Token::Multiply);
-                    }
-                    // Closing parenthesis followed by opening parenthesis: )( -> )*(
-                    (Token::RParen, Token::LParen) => {
-                        result.push(Token::Multiply);
+    /// Where `f` is 0 across the x range: samples it lands on exactly, the first of a
+    /// run where it stays there, and sign changes between neighbouring samples narrowed
+    /// down by bisection. A sign change that doesn't shrink toward 0, at a pole or a
+    /// jump, isn't a root.
+    fn roots(&self, f: impl Fn(f64) -> Option<f64>) -> Vec<f64> {
+        let mut roots = Vec::new();
+        let mut previous: Option<(f64, f64)> = None;
+        for i in 0..=SEARCH_SAMPLES {
+            let x = self.search_x(i);
+            let y = f(x);
+            if y == Some(0.0) {
+                if previous.is_none_or(|(_, last_y)| last_y != 0.0) {
+                    roots.push(x);
+                }
+            } else if let (Some((last_x, last_y)), Some(y)) = (previous, y)
+                && last_y != 0.0
+                && last_y.signum() != y.signum()
+            {
+                let (mut lo, mut hi) = (last_x, x);
+                for _ in 0..100 {
+                    let mid = (lo + hi) / 2.0;
+                    match f(mid) {
+                        Some(value) if value.signum() == last_y.signum() => lo = mid,
+                        Some(_) => hi = mid,
+                        None => break,
                     }
-                    _ => {}
+                }
+                let root = (lo + hi) / 2.0;
+                if f(root).is_some_and(|value| value.abs() < last_y.abs().min(y.abs())) {
+                    roots.push(root);
                 }
             }
+            previous = y.map(|y| (x, y));
         }
-
-        Ok(result)
+        roots
     }
 
-    fn parse_expression(&self, tokens: &[Token], mut pos: usize) -> Result<(f64, usize)> {
-        let (mut left, new_pos) = self.parse_term(tokens, pos)?;
-        pos = new_pos;
-
-        while pos < tokens.len() {
-            match tokens[pos] {
-                Token::Plus => {
-                    pos += 1;
-                    let (right, next_pos) = self.parse_term(tokens, pos)?;
-                    left += right;
-                    pos = next_pos;
-                }
-                Token::Minus => {
-                    pos += 1;
-                    let (right, next_pos) = self.parse_term(tokens, pos)?;
-                    left -= right;
-                    pos = next_pos;
+    /// Where `f` turns around across the x range, at a sample higher (or lower) than
+    /// both its neighbours, narrowed down between them by golden-section search: each
+    /// one's x and whether it's a maximum. A turn that runs off far past the samples,
+    /// at a pole, isn't one.
+    fn extrema(&self, f: impl Fn(f64) -> Option<f64>) -> Vec<(f64, bool)> {
+        let samples: Vec<(f64, Option<f64>)> = (0..=SEARCH_SAMPLES)
+            .map(|i| (self.search_x(i), f(self.search_x(i))))
+            .collect();
+        let mut extrema = Vec::new();
+        for window in samples.windows(3) {
+            let [(from, Some(before)), (_, Some(y)), (to, Some(after))] = *window else {
+                continue;
+            };
+            let maximum = y > before && y > after;
+            let minimum = y < before && y < after;
+            if !(maximum || minimum) {
+                continue;
+            }
+            // Search for the top of `f`, or of `-f` for a minimum
+            let sign = if maximum { 1.0 } else { -1.0 };
+            let ratio = (5f64.sqrt() - 1.0) / 2.0;
+            let (mut lo, mut hi) = (from, to);
+            for _ in 0..100 {
+                let (left, right) = (hi - ratio * (hi - lo), lo + ratio * (hi - lo));
+                match (f(left), f(right)) {
+                    (Some(a), Some(b)) if a * sign > b * sign => hi = right,
+                    (Some(_), Some(_)) => lo = left,
+                    _ => break,
                 }
-                _ => break,
+            }
+            let x = (lo + hi) / 2.0;
+            if f(x).is_some_and(|top| (top - y).abs() <= (y - before).abs() + (y - after).abs()) {
+                extrema.push((x, maximum));
             }
         }
+        extrema
+    }
 
-        Ok((left, pos))
+    /// The `i`th of the samples taken across the x range when looking for points.
+    fn search_x(&self, i: usize) -> f64 {
+        let t = i as f64 / SEARCH_SAMPLES as f64;
+        self.x_min * (1.0 - t) + self.x_max * t
     }
 
-    fn parse_term(&self, tokens: &[Token], mut pos: usize) -> Result<(f64, usize)> {
-        let (mut left, new_pos) = self.parse_factor(tokens, pos)?;
-        pos = new_pos;
+    /// Evaluate `expression`, or its first curve, with `x` bound, keeping the error when
+    /// it fails.
+    pub fn evaluate_at(&self, x: f64, expression: &str) -> EvalResult<f64> {
+        let evaluator = Evaluator {
+            ans: self.ans,
+            modulo: self.modulo,
+            ..Evaluator::new(&self.variables)
+        };
+        let first = curves(expression).next().unwrap_or(expression);
+        evaluator.bind("x", x).evaluate(first)
+    }
+}
 
-        while pos < tokens.len() {
-            match tokens[pos] {
-                Token::Multiply => {
-                    pos += 1;
-                    let (right, next_pos) = self.parse_factor(tokens, pos)?;
-                    left *= right;
-                    pos = next_pos;
-                }
-                Token::Divide => {
-                    pos += 1;
-                    let (right, next_pos) = self.parse_factor(tokens, pos)?;
-                    if right == 0.0 {
-                        return Err(anyhow::anyhow!("Division by zero"));
-                    }
-                    left /= right;
-                    pos = next_pos;
-                }
-                Token::Modulo => {
-                    pos += 1;
-                    let (right, next_pos) = self.parse_factor(tokens, pos)?;
-                    left %= right;
-                    pos = next_pos;
-                }
-                _ => break,
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
 
-        Ok((left, pos))
+    fn classify(defined: &[bool]) -> Vec<DomainInterval> {
+        let samples: Vec<(f64, bool)> = defined
+            .iter()
+            .enumerate()
+            .map(|(i, &defined)| (i as f64, defined))
+            .collect();
+        let mut intervals = Vec::new();
+        classify_domain(&samples, &mut intervals);
+        intervals
     }
 
-    fn parse_factor(&self, tokens: &[Token], mut pos: usize) -> Result<(f64, usize)> {
-        let (mut base, new_pos) = self.parse_primary(tokens, pos)?;
-        pos = new_pos;
+    #[test]
+    fn domain_gaps_are_found_at_either_end_and_inside() {
+        let run = |start, end, defined| DomainInterval {
+            start,
+            end,
+            defined,
+        };
 
-        while pos < tokens.len() {
-            if let Token::Power = tokens[pos] {
-                pos += 1;
-                let (exponent, next_pos) = self.parse_primary(tokens, pos)?;
-                base = base.powf(exponent);
-                pos = next_pos;
-            } else {
-                break;
-            }
-        }
+        let leading = classify(&[false, false, true, true]);
+        assert_eq!(leading, [run(0.0, 1.5, false), run(1.5, 3.0, true)]);
+        assert_eq!(
+            describe_undefined(&leading).as_deref(),
+            Some("undefined for x < 1.5")
+        );
+
+        let trailing = classify(&[true, true, true, false]);
+        assert_eq!(trailing, [run(0.0, 2.5, true), run(2.5, 3.0, false)]);
+        assert_eq!(
+            describe_undefined(&trailing).as_deref(),
+            Some("undefined for x > 2.5")
+        );
+
+        let interior = classify(&[true, false, false, true]);
+        assert_eq!(
+            interior,
+            [
+                run(0.0, 0.5, true),
+                run(0.5, 2.5, false),
+                run(2.5, 3.0, true)
+            ]
+        );
+        assert_eq!(
+            describe_undefined(&interior).as_deref(),
+            Some("undefined for 0.5 < x < 2.5")
+        );
 
-        Ok((base, pos))
+        assert_eq!(describe_undefined(&classify(&[true, true])), None);
+        assert_eq!(classify(&[]), []);
     }
 
-    fn parse_primary(&self, tokens: &[Token], pos: usize) -> Result<(f64, usize)> {
-        if pos >= tokens.len() {
-            return Err(anyhow::anyhow!("Unexpected end of expression"));
-        }
+    #[test]
+    fn boundaries_are_refined_between_samples() {
+        let mut graph = GraphModule::new();
+        graph.generate_points("sqrt(x-2)", 100, 50).unwrap();
+        assert_eq!(
+            describe_undefined(&graph.domain).as_deref(),
+            Some("undefined for x < 2")
+        );
+        assert!(!graph.is_defined_at(1.9));
+        assert!(graph.is_defined_at(2.1));
+    }
+
+    #[test]
+    fn piecewise_branches_run_to_the_flip_and_join_within() {
+        let mut graph = GraphModule::new();
+        graph
+            .generate_points("if(x < 1, x, x + 5)", 100, 50)
+            .unwrap();
+        let left_end = graph.points.iter().rfind(|p| p.x < 1.0).unwrap();
+        let right_start = graph.points.iter().find(|p| p.x >= 1.0).unwrap();
+        assert!((left_end.x - 1.0).abs() < 1e-9 && (left_end.y - 1.0).abs() < 1e-9);
+        assert!((right_start.y - 6.0).abs() < 1e-9 && !right_start.joined);
+        // Only the first point and the start of the second branch stand alone
+        assert_eq!(graph.points.iter().filter(|p| !p.joined).count(), 2);
+        graph.generate_points("x^2", 100, 50).unwrap();
+        assert!(graph.points.iter().all(|p| !p.joined));
+
+        // A steep branch fills its column; a jump leaves the axis showing
+        let mut column = |expression: &str, col: usize| -> String {
+            graph.generate_points(expression, 20, 21).unwrap();
+            let mut grid = Vec::new();
+            draw_grid(&mut grid, 21, 21, &graph, Charset::Ascii, None);
+            (0..21).map(|row| grid[row * 21 + col]).collect()
+        };
+        assert_eq!(&column("if(x < 0, 0, 10x)", 11)[..10], "**********");
+        assert_eq!(&column("if(x < 0, -5, 5)", 10)[5..16], "*||||+||||*");
+    }
+
+    #[test]
+    fn tangents_touch_the_curve() {
+        let graph = GraphModule::new();
+        let tangent = graph.tangent_at(3.0, "x^2").unwrap();
+        assert!((tangent.slope - 6.0).abs() < 1e-9, "{}", tangent.slope);
+        assert_eq!(tangent.y, 9.0);
+        assert_eq!(tangent.equation(), "y = 6x - 9");
+        assert_eq!(tangent.describe(), "Tangent at (3, 9): y = 6x - 9");
+        let line = |x, y, slope| Tangent { x, y, slope }.equation();
+        assert_eq!(line(0.0, 0.0, 1.0), "y = x");
+        assert_eq!(line(0.0, 2.5, -1.0), "y = -x + 2.5");
+        assert_eq!(line(1.0, 4.0, 0.0), "y = 4");
+        assert_eq!(line(1.0, 0.5, 1.0 / 3.0), "y = 0.333333x + 0.166667");
+        // Undefined or vertical there
+        assert_eq!(graph.tangent_at(-1.0, "sqrt(x)"), None);
+        assert_eq!(graph.tangent_at(0.0, "1/x"), None);
+
+        // Drawn under the curve, through every row it crosses
+        let mut graph = GraphModule::new();
+        graph.generate_points("0", 21, 21).unwrap();
+        graph.tangent = Some(Tangent {
+            x: 0.0,
+            y: 0.0,
+            slope: 3.0,
+        });
+        let mut grid = Vec::new();
+        draw_grid(&mut grid, 21, 21, &graph, Charset::Ascii, None);
+        let column = |col: usize| -> String { (0..21).map(|row| grid[row * 21 + col]).collect() };
+        assert_eq!(&column(11)[5..11], " ....*");
+        assert_eq!(column(10).matches('.').count(), 3);
+        assert_eq!(grid[10 * 21 + 10], '*');
+    }
+
+    #[test]
+    fn ticks_fall_on_round_values() {
+        assert_eq!(tick_step(20.0, 5), 5.0);
+        assert_eq!(tick_step(20.0, 2), 10.0);
+        assert_eq!(tick_step(1.0, 4), 0.5);
+        assert_eq!(tick_step(0.03, 10), 0.005);
+        assert_eq!(tick_label(0.30000000000000004, 0.1), "0.3");
+        assert_eq!(tick_label(-0.0, 1.0), "0");
+        assert_eq!(tick_label(250.0, 50.0), "250");
+
+        // Marked on the axes and labelled beside them, 0 left to the origin
+        let mut graph = GraphModule::new();
+        graph.ticks = true;
+        let mut grid = Vec::new();
+        draw_grid(&mut grid, 41, 9, &graph, Charset::Ascii, None);
+        let rows: Vec<String> = grid.chunks(41).map(String::from_iter).collect();
+        assert_eq!(rows[0], format!("{:20}+10{:18}", "", ""));
+        assert_eq!(rows[4], "+---------+---------+---------+---------+");
+        assert_eq!(rows[5], "-10      -5         |         5        10");
+        assert_eq!(rows[8].trim(), "+-10");
+
+        // Gridlines cross at the ticks; labels go to the edges when the axes are out of view
+        graph.gridlines = true;
+        (graph.x_min, graph.x_max, graph.y_min, graph.y_max) = (1.0, 3.0, 5.0, 7.0);
+        draw_grid(&mut grid, 41, 9, &graph, Charset::Ascii, None);
+        let rows: Vec<String> = grid.chunks(41).map(String::from_iter).collect();
+        assert_eq!(rows[0], format!("7{}", ":".repeat(40)));
+        assert_eq!(rows[1], ":         ".repeat(4) + ":");
+        assert_eq!(rows[8], "1.0::::::1.5:::::::2.0:::::::2.5::::::3.0");
+
+        // A square grid takes the wider spacing on both axes
+        graph.square_ticks = true;
+        draw_grid(&mut grid, 41, 9, &graph, Charset::Ascii, None);
+        let rows: Vec<String> = grid.chunks(41).map(String::from_iter).collect();
+        assert_eq!(rows[8], "1:::::::::::::::::::2:::::::::::::::::::3");
+        assert_eq!(rows[1], ":                   :                   :");
+    }
+
+    #[test]
+    fn roots_are_sign_changes_that_close_in_on_zero() {
+        let graph = GraphModule::new();
+        let roots = |expression: &str| -> Vec<String> {
+            let findings = graph.find(Feature::Roots, expression).unwrap();
+            assert!(findings.points.iter().all(|found| found.y == 0.0));
+            findings.lines()
+        };
+        assert_eq!(roots("x^2 - 2"), ["x = -1.41421", "x = 1.41421"]);
+        assert_eq!(
+            roots("sin(x)"),
+            [
+                "-9.42478", "-6.28319", "-3.14159", "0", "3.14159", "6.28319", "9.42478"
+            ]
+            .map(|x| format!("x = {}", x))
+        );
+        // Poles and jumps change sign without a root, and a flat stretch at 0 is one
+        assert_eq!(roots("1/x"), ["None in view"]);
+        assert_eq!(roots("tan(x)").len(), 7);
+        assert_eq!(roots("if(x < 1, -1, 1)"), ["None in view"]);
+        assert_eq!(roots("if(x < 0, -1, if(x < 3, 0, 1))"), ["x = 0"]);
+        assert!(graph.find(Feature::Roots, "x +").is_err());
+
+        let mut findings = graph.find(Feature::Roots, "x - 1").unwrap();
+        assert_eq!(findings.summary(), "1 root in view");
+        findings.points.clear();
+        assert_eq!(findings.summary(), "No roots in view");
+    }
+
+    #[test]
+    fn extrema_are_where_curves_turn_around() {
+        let graph = GraphModule::new();
+        let found = |expression: &str| graph.find(Feature::Extrema, expression).unwrap();
+        assert_eq!(found("x^3 - 3x").lines(), ["max (-1, 2)", "min (1, -2)"]);
+        assert_eq!(found("(x - 2.5)^2").lines(), ["min (2.5, 0)"]);
+        assert_eq!(found("cos(x)").points.len(), 7);
+        assert_eq!(found("cos(x)").summary(), "7 extrema in view");
+        // Nothing turns on a line, and a pole isn't a peak
+        assert_eq!(found("2x + 1").lines(), ["None in view"]);
+        assert_eq!(found("1/(x - 0.001)^2").summary(), "No extrema in view");
+        assert_eq!(found("x^2 + 1").summary(), "1 extremum in view");
+    }
+
+    #[test]
+    fn integrals_are_shaded_between_their_bounds() {
+        let mut graph = GraphModule::new();
+        let area = |expression: &str, from, to| {
+            graph
+                .integrate(expression, from, to)
+                .unwrap()
+                .area
+                .map(six_figures)
+        };
+        assert_eq!(area("x^2", 0.0, 3.0).as_deref(), Some("9"));
+        assert_eq!(area("sin(x)", 0.0, PI).as_deref(), Some("2"));
+        // Below the axis counts against, and the bounds may be either way round
+        assert_eq!(area("x", -2.0, 1.0).as_deref(), Some("-1.5"));
+        assert_eq!(area("x^2; x", 3.0, 0.0).as_deref(), Some("-9"));
+        assert_eq!(area("1/x", -1.0, 1.0), None);
+        assert!(graph.integrate("x +", 0.0, 1.0).is_err());
+        assert_eq!(
+            graph.integrate("2", 0.0, 1.5).unwrap().describe(),
+            "∫ from 0 to 1.5 = 3"
+        );
+
+        // Shaded from the axis up to the curve, with the bounds across the plot
+        graph.generate_points("x", 21, 21).unwrap();
+        graph.bounds = vec![2.0, 6.0];
+        graph.integral = Some(graph.integrate("x", 2.0, 6.0).unwrap());
+        let mut grid = Vec::new();
+        draw_grid(&mut grid, 21, 21, &graph, Charset::Ascii, None);
+        let column = |col: usize| -> String { (0..21).map(|row| grid[row * 21 + col]).collect() };
+        assert_eq!(column(11), " ".repeat(9) + "*-" + &" ".repeat(10));
+        assert_eq!(&column(13)[5..12], "  *##- ");
+        assert_eq!(&column(15)[4..12], " *####- ");
+        assert_eq!(column(12), "!".repeat(8) + "*" + &"!".repeat(12));
+        assert_eq!(column(16), "!".repeat(4) + "*" + &"!".repeat(16));
+    }
+
+    #[test]
+    fn curves_intersect_wherever_they_cross() {
+        let mut graph = GraphModule::new();
+        let found = |expression: &str| {
+            graph
+                .find(Feature::Intersections, expression)
+                .unwrap()
+                .lines()
+        };
+        assert_eq!(found("x^2; 2x + 3"), ["(-1, 1)", "(3, 9)"]);
+        // Every pair, left to right
+        assert_eq!(found("x; -x; 2"), ["(-2, 2)", "(0, 0)", "(2, 2)"]);
+        assert_eq!(found("x^2 + 1; x - 5"), ["None in view"]);
+        assert_eq!(found("x^2"), ["None in view"]);
+        // Roots of each curve, and the first is the one evaluated alone
+        assert_eq!(
+            graph.find(Feature::Roots, "x - 1; x + 1").unwrap().lines(),
+            ["x = -1", "x = 1"]
+        );
+        assert_eq!(graph.get_point_at_x(2.0, "x^2; x"), Some(4.0));
+
+        // Each curve is plotted, the domain following the first
+        graph.generate_points("sqrt(x); -x", 20, 20).unwrap();
+        assert_eq!(graph.points.iter().filter(|p| p.y < 0.0).count(), 9);
+        assert!(!graph.is_defined_at(-5.0));
+        assert!(graph.generate_points("x; x +", 20, 20).is_err());
+    }
+
+    #[test]
+    fn parametric_curves_are_traced_across_t() {
+        let mut graph = GraphModule::new();
+        // A circle of radius 5, over 0 to 2π by default
+        graph.generate_points("[5cos(t), 5sin(t)]", 40, 20).unwrap();
+        assert_eq!(graph.points.len(), TRACE_SAMPLES + 1);
+        assert!(
+            graph
+                .points
+                .iter()
+                .all(|p| (p.x.hypot(p.y) - 5.0).abs() < 1e-9)
+        );
+        assert!(graph.points.iter().skip(1).all(|p| p.joined));
+        assert!(graph.domain.is_empty());
+
+        // A projectile path over a range of its own, cut off where it leaves the view
+        graph
+            .generate_points("[3t, 12t - 5t^2, 0, 3]", 40, 20)
+            .unwrap();
+        let last = graph.points.last().unwrap();
+        assert!((last.x - 9.0).abs() < 1e-9 && (last.y + 9.0).abs() < 1e-9);
+        graph
+            .generate_points("[3t, 12t - 5t^2, 0, 4]", 40, 20)
+            .unwrap();
+        assert!(graph.points.iter().all(|p| p.y >= -10.0 && p.x <= 10.0));
+        assert!(graph.points.len() < TRACE_SAMPLES);
+
+        // Alongside a curve in x, and with a range that isn't a number
+        graph.generate_points("x; [t, -t, -1, 1]", 40, 20).unwrap();
+        assert_eq!(
+            graph
+                .points
+                .iter()
+                .filter(|p| p.y < 0.0 && p.x > 0.0)
+                .count(),
+            500
+        );
+        assert!(graph.generate_points("[t, t, 0, [1]]", 40, 20).is_err());
+        assert!(is_parametric(" [t, t]") && !is_parametric("x"));
+    }
+
+    #[test]
+    fn implicit_relations_are_drawn_where_they_are_zero() {
+        assert!(is_implicit("x^2 + y^2 - 25") && is_implicit("y"));
+        assert!(!is_implicit("x^2") && !is_implicit("[y, t]") && !is_implicit("r = y"));
+        assert!(!is_implicit("sum(y, y, 1, 3)"));
+
+        let mut graph = GraphModule::new();
+        graph.generate_points("x^2 + y^2 - 25", 40, 20).unwrap();
+        assert!(graph.points.len() > 80);
+        assert!(
+            graph
+                .points
+                .iter()
+                .all(|p| (p.x.hypot(p.y) - 5.0).abs() < 1e-6)
+        );
+        // The crossings of a square are joined in pairs
+        assert_eq!(
+            graph.points.iter().filter(|p| p.joined).count() * 2,
+            graph.points.len()
+        );
+        assert!(graph.domain.is_empty());
+
+        // Both branches of a hyperbola, through lattice points exactly on them too
+        graph.generate_points("x*y - 4", 40, 20).unwrap();
+        assert!(graph.points.iter().all(|p| (p.x * p.y - 4.0).abs() < 1e-6));
+        assert!(graph.points.iter().any(|p| p.x < 0.0) && graph.points.iter().any(|p| p.x > 0.0));
+        assert!(graph.points.iter().any(|p| p.x == 2.0 && p.y == 2.0));
+
+        // A pole changes sign without crossing 0, and a saddle is split without a gap
+        graph.generate_points("1/(x^2 + y^2 - 25)", 40, 20).unwrap();
+        assert!(graph.points.is_empty());
+        graph.generate_points("x^2 - y^2", 41, 21).unwrap();
+        assert!(
+            graph
+                .points
+                .iter()
+                .all(|p| (p.x.abs() - p.y.abs()).abs() < 1e-6)
+        );
+        assert!(
+            graph
+                .points
+                .iter()
+                .any(|p| p.x.abs() < 0.5 && p.y.abs() < 0.5)
+        );
+        assert_eq!(
+            graph.points.iter().filter(|p| p.joined).count() * 2,
+            graph.points.len()
+        );
+
+        // A defined y is still the vertical coordinate, and an open y range takes x's
+        graph.set_variables(&BTreeMap::from([("y".to_string(), 100.0)]), None);
+        (graph.y_min, graph.y_max) = (f64::NEG_INFINITY, f64::INFINITY);
+        graph.generate_points("x + y", 20, 20).unwrap();
+        assert!(
+            graph
+                .points
+                .iter()
+                .all(|p| (p.x + p.y).abs() < 1e-9 && p.y.abs() <= 10.0)
+        );
+        assert!(!graph.points.is_empty());
+    }
+
+    #[test]
+    fn polar_curves_are_traced_over_a_turn() {
+        assert_eq!(polar(" r = 2theta"), Some(" 2theta"));
+        assert_eq!(polar("r == 1"), None);
+        assert_eq!(polar("rate = 1"), None);
+
+        let mut graph = GraphModule::new();
+        graph.generate_points("r = 5", 40, 20).unwrap();
+        assert!(graph.polar);
+        assert_eq!(graph.points.len(), TRACE_SAMPLES + 1);
+        assert!(
+            graph
+                .points
+                .iter()
+                .all(|p| (p.x.hypot(p.y) - 5.0).abs() < 1e-9)
+        );
+        // A spiral ends a turn out, and a negative radius points the other way
+        graph.generate_points("r = theta", 40, 20).unwrap();
+        let last = graph.points.last().unwrap();
+        assert!((last.x - TAU).abs() < 1e-9 && last.y.abs() < 1e-9);
+        graph.generate_points("r = -1", 40, 20).unwrap();
+        assert!((graph.points[0].x + 1.0).abs() < 1e-9);
+        assert!(graph.generate_points("r = 1 +", 40, 20).is_err());
+
+        // Circles a tick apart and the diagonals are drawn in place of gridlines, until
+        // nothing polar is plotted
+        graph.generate_points("r = 1; x", 40, 20).unwrap();
+        graph.gridlines = true;
+        let mut grid = Vec::new();
+        draw_grid(&mut grid, 41, 21, &graph, Charset::Ascii, None);
+        let transform = Transform::new(graph.viewport(), Rect::new(0, 0, 41, 21));
+        let at = |grid: &[char], x: f64, y: f64| {
+            let (col, row) = transform.to_screen(x, y).unwrap();
+            grid[usize::from(row) * 41 + usize::from(col)]
+        };
+        let (sin, cos) = 0.35_f64.sin_cos();
+        assert_eq!(at(&grid, 5.0 * cos, 5.0 * sin), ':');
+        assert_eq!(at(&grid, -8.0, 8.0), ':');
+        assert_eq!(at(&grid, 2.0, 7.0), ' ');
+        graph.generate_points("x", 40, 20).unwrap();
+        assert!(!graph.polar);
+        draw_grid(&mut grid, 41, 21, &graph, Charset::Ascii, None);
+        assert_eq!(at(&grid, -8.0, 8.0), ' ');
+    }
 
-        match &tokens[pos] {
-            Token::Number(n) => Ok((*n, pos + 1)),
-            Token::Minus => {
-                let (value, new_pos) = self.parse_primary(tokens, pos + 1)?;
-                Ok((-value, new_pos))
+    #[test]
+    fn transform_round_trips_and_pins_the_corners() {
+        for (view, area) in [
+            (Viewport::default(), Rect::new(1, 1, 78, 18)),
+            (
+                Viewport {
+                    x_min: -0.3,
+                    x_max: 7.9,
+                    y_min: 1e6,
+                    y_max: 1e6 + 3.0,
+                },
+                Rect::new(5, 2, 13, 7),
+            ),
+            (Viewport::default(), Rect::new(0, 0, 1, 1)),
+        ] {
+            let transform = Transform::new(view, area);
+            let (right, bottom) = (area.right() - 1, area.bottom() - 1);
+            assert_eq!(transform.to_world(area.x, area.y), (view.x_min, view.y_max));
+            if area.width > 1 && area.height > 1 {
+                assert_eq!(transform.to_world(right, bottom), (view.x_max, view.y_min));
             }
-            Token::LParen => {
-                let (value, new_pos) = self.parse_expression(tokens, pos + 1)?;
-                if new_pos >= tokens.len() || !matches!(tokens[new_pos], Token::RParen) {
-                    return Err(anyhow::anyhow!("Missing closing parenthesis"));
+            assert_eq!(
+                transform.to_screen(view.x_min, view.y_max),
+                Some((area.x, area.y))
+            );
+            assert_eq!(
+                transform.to_screen(view.x_max, view.y_min),
+                Some((right, bottom))
+            );
+
+            // Every cell maps back to itself, and any point lands within a cell of where
+            // its nearest cell says
+            for col in area.left()..area.right() {
+                for row in area.top()..area.bottom() {
+                    let (x, y) = transform.to_world(col, row);
+                    assert_eq!(transform.to_screen(x, y), Some((col, row)));
                 }
-                Ok((value, new_pos + 1))
             }
-            Token::Ident(name) => {
-                if pos + 1 < tokens.len() && matches!(tokens[pos + 1], Token::LParen) {
-                    let (arg, np) = self.parse_expression(tokens, pos + 2)?; // after ident + '('
-                    if np >= tokens.len() || !matches!(tokens[np], Token::RParen) {
-                        return Err(anyhow::anyhow!("Missing closing parenthesis"));
-                    }
-                    let val = match name.as_str() {
-                        "sin" => arg.sin(),
-                        "cos" => arg.cos(),
-                        "tan" => arg.tan(),
-                        "sqrt" => arg.sqrt(),
-                        "log" => arg.log10(),
-                        "ln" => arg.ln(),
-                        "exp" => arg.exp(),
-                        "abs" => arg.abs(),
-                        _ => return Err(anyhow::anyhow!("Unknown function: {}", name)),
-                    };
-                    Ok((val, np + 1))
-                } else {
-                    Err(anyhow::anyhow!("Unexpected identifier: {}", name))
-                }
+            for step in 0..=200 {
+                let t = step as f64 / 200.0;
+                let x = view.x_min + t * (view.x_max - view.x_min);
+                let y = view.y_max - t * (view.y_max - view.y_min);
+                let (col, row) = transform.to_screen(x, y).unwrap();
+                let (back_x, back_y) = transform.to_world(col, row);
+                let cell_x = (view.x_max - view.x_min) / area.width.saturating_sub(1).max(1) as f64;
+                let cell_y =
+                    (view.y_max - view.y_min) / area.height.saturating_sub(1).max(1) as f64;
+                assert!((back_x - x).abs() <= cell_x && (back_y - y).abs() <= cell_y);
             }
-            _ => Err(anyhow::anyhow!("Unexpected token")),
+            assert_eq!(transform.to_screen(view.x_max + 1.0, view.y_min), None);
         }
     }
-}
 
-#[derive(Debug, Clone)]
-enum Token {
-    Number(f64),
-    Plus,
-    Minus,
-    Multiply,
-    Divide,
-    Power,
-    Modulo,
-    LParen,
-    RParen,
-    Ident(String),
+    #[test]
+    fn remainder_matches_the_calculator() {
+        let mut graph = GraphModule::new();
+        let mut calc = crate::calculator::CalculatorModule::new();
+        for (expression, modulo) in ["x%3", "-x%3", "x%-2", "(x-5)%2.5", "2*x%3"]
+            .into_iter()
+            .flat_map(|expression| {
+                [ModuloBehavior::Truncated, ModuloBehavior::Floored]
+                    .map(|modulo| (expression, modulo))
+            })
+        {
+            graph.set_modulo(modulo);
+            calc.modulo_behavior = modulo;
+            for x in [-7.0, -4.5, -1.0, 0.0, 2.5, 7.0] {
+                calc.variables.insert("x".to_string(), x);
+                let computed = calc.evaluate_str(expression).unwrap().as_f64();
+                assert_eq!(
+                    graph.get_point_at_x(x, expression),
+                    computed,
+                    "{} at x = {}",
+                    expression,
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn python_operators_plot_like_the_calculator() {
+        let graph = GraphModule::new();
+        assert_eq!(graph.get_point_at_x(3.0, "x**2"), Some(9.0));
+        assert_eq!(graph.get_point_at_x(-7.0, "x//2"), Some(-4.0));
+        assert_eq!(graph.get_point_at_x(7.0, "x//2 + 1"), Some(4.0));
+    }
+
+    #[test]
+    fn functions_and_constants_plot_like_the_calculator() {
+        let graph = GraphModule::new();
+        assert_eq!(
+            graph.get_point_at_x(1.0, "exp(x)"),
+            Some(std::f64::consts::E)
+        );
+        assert_eq!(graph.get_point_at_x(0.5, "sin(pi*x)"), Some(1.0));
+        assert_eq!(graph.get_point_at_x(2.0, "1e3*x + 2.5e-1"), Some(2000.25));
+        assert_eq!(graph.get_point_at_x(4.0, "mod(-x, 3) + x!"), Some(26.0));
+        // A ramp that levels off at both ends
+        assert_eq!(graph.get_point_at_x(-3.0, "clamp(x, -1, 2)"), Some(-1.0));
+        assert_eq!(graph.get_point_at_x(1.5, "clamp(x, -1, 2)"), Some(1.5));
+        assert_eq!(graph.get_point_at_x(4.0, "max(0, min(x, 2))"), Some(2.0));
+        assert!(graph.evaluate_at(1.0, "ratio(x, 2)").is_err());
+        // A series sees x as well as its own counter
+        assert_eq!(graph.get_point_at_x(2.0, "sum(k, 1, 3, x^k)"), Some(14.0));
+    }
+
+    #[test]
+    fn expressions_that_dont_parse_plot_nothing() {
+        let mut graph = GraphModule::new();
+        graph.generate_points("x^2", 100, 50).unwrap();
+        assert!(!graph.points.is_empty());
+        let error = graph.generate_points("x^2 +", 100, 50).unwrap_err();
+        assert!(error.to_string().starts_with("Unexpected end"), "{}", error);
+        assert!(graph.points.is_empty() && graph.domain.is_empty());
+        // Nothing was cached, so the same text fails again rather than being skipped
+        assert!(graph.generate_points("x^2 +", 100, 50).is_err());
+    }
+
+    #[test]
+    fn csv_export_has_a_column_per_curve_and_stops_when_cancelled() {
+        let variables = BTreeMap::new();
+        let evaluator = Evaluator::new(&variables);
+        let export = |expression, rows: usize| {
+            let mut out = Vec::new();
+            let written = std::cell::Cell::new(0);
+            let done = write_csv(&mut out, &evaluator, expression, (-1.0, 1.0), 3, || {
+                written.set(written.get() + 1);
+                written.get() > rows
+            });
+            done.map(|done| (done, String::from_utf8(out).unwrap()))
+        };
+
+        assert_eq!(
+            export("x^2; sqrt(x); max(x, 0)", 3).unwrap(),
+            (
+                true,
+                "x,\"x^2\",\"sqrt(x)\",\"max(x, 0)\"\n-1,1,,0\n0,0,0,0\n1,1,1,1\n".to_string()
+            )
+        );
+        assert_eq!(
+            export("2x", 1).unwrap(),
+            (false, "x,\"2x\"\n-1,-2\n".to_string())
+        );
+        assert!(export("[cos(t), sin(t)]", 3).is_err());
+        assert!(export("x +", 3).is_err());
+        assert!(export("", 3).is_err());
+    }
 }
@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+
+/// Events for the same key closer together than this are treated as the terminal
+/// auto-repeating a held key; a longer gap means the key was released in between.
+const HOLD_GAP: Duration = Duration::from_millis(100);
+
+/// Typematic filter for navigation keys.
+///
+/// The first press of a key is accepted immediately. While the key is held, repeats are
+/// dropped until `initial_delay` has passed and are then let through at most once per
+/// `interval`. Most terminals only report presses, so a release is inferred from a pause
+/// longer than [`HOLD_GAP`] or from a different key arriving.
+#[derive(Debug, Clone)]
+pub struct RepeatFilter<K> {
+    initial_delay: Duration,
+    interval: Duration,
+    held: Option<Held<K>>,
+}
+
+#[derive(Debug, Clone)]
+struct Held<K> {
+    key: K,
+    pressed_at: Instant,
+    last_seen: Instant,
+    last_accepted: Instant,
+    repeating: bool,
+}
+
+impl<K: Copy + PartialEq> RepeatFilter<K> {
+    pub fn new(initial_delay: Duration, interval: Duration) -> Self {
+        Self {
+            initial_delay,
+            interval,
+            held: None,
+        }
+    }
+
+    pub fn initial_delay(&self) -> Duration {
+        self.initial_delay
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Record a key event at `now` and report whether it should take effect.
+    pub fn accept(&mut self, key: K, now: Instant) -> bool {
+        let held = match &mut self.held {
+            Some(held)
+                if held.key == key && now.saturating_duration_since(held.last_seen) <= HOLD_GAP =>
+            {
+                held
+            }
+            _ => {
+                self.held = Some(Held {
+                    key,
+                    pressed_at: now,
+                    last_seen: now,
+                    last_accepted: now,
+                    repeating: false,
+                });
+                return true;
+            }
+        };
+
+        held.last_seen = now;
+        let due = if held.repeating {
+            now.saturating_duration_since(held.last_accepted) >= self.interval
+        } else {
+            now.saturating_duration_since(held.pressed_at) >= self.initial_delay
+        };
+        if due {
+            held.repeating = true;
+            held.last_accepted = now;
+        }
+        due
+    }
+
+    /// Forget the held key, for terminals that report key releases.
+    pub fn release(&mut self, key: K) {
+        if self.held.as_ref().is_some_and(|held| held.key == key) {
+            self.held = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Step {
+        Press(char, u64),
+        Release(char),
+    }
+    use Step::{Press, Release};
+
+    /// Run `steps` (timestamps in ms) through a 250 ms / 50 ms filter and collect
+    /// whether each press was accepted.
+    fn run(steps: &[Step]) -> Vec<bool> {
+        let start = Instant::now();
+        let mut filter = RepeatFilter::new(Duration::from_millis(250), Duration::from_millis(50));
+        let mut accepted = Vec::new();
+        for step in steps {
+            match *step {
+                Press(key, ms) => {
+                    accepted.push(filter.accept(key, start + Duration::from_millis(ms)))
+                }
+                Release(key) => filter.release(key),
+            }
+        }
+        accepted
+    }
+
+    #[test]
+    fn follows_the_typematic_model() {
+        let cases: &[(&str, &[Step], &[bool])] = &[
+            ("single press", &[Press('a', 0)], &[true]),
+            (
+                "separate taps",
+                &[Press('a', 0), Press('a', 150), Press('a', 300)],
+                &[true, true, true],
+            ),
+            (
+                "hold waits for the initial delay",
+                &[
+                    Press('a', 0),
+                    Press('a', 30),
+                    Press('a', 60),
+                    Press('a', 90),
+                    Press('a', 120),
+                    Press('a', 150),
+                    Press('a', 180),
+                    Press('a', 210),
+                    Press('a', 240),
+                    Press('a', 270),
+                ],
+                &[
+                    true, false, false, false, false, false, false, false, false, true,
+                ],
+            ),
+            (
+                "hold repeats at the interval",
+                &[
+                    Press('a', 0),
+                    Press('a', 90),
+                    Press('a', 180),
+                    Press('a', 250),
+                    Press('a', 280),
+                    Press('a', 300),
+                    Press('a', 330),
+                    Press('a', 350),
+                ],
+                &[true, false, false, true, false, true, false, true],
+            ),
+            (
+                "another key is a fresh press",
+                &[Press('a', 0), Press('b', 20), Press('a', 40)],
+                &[true, true, true],
+            ),
+            (
+                "explicit release ends the hold",
+                &[Press('a', 0), Release('a'), Press('a', 20)],
+                &[true, true],
+            ),
+            (
+                "releasing another key keeps the hold",
+                &[Press('a', 0), Release('b'), Press('a', 20)],
+                &[true, false],
+            ),
+            (
+                "a pause releases and re-arms the delay",
+                &[
+                    Press('a', 0),
+                    Press('a', 80),
+                    Press('a', 300),
+                    Press('a', 380),
+                ],
+                &[true, false, true, false],
+            ),
+        ];
+
+        for (name, steps, expected) in cases {
+            assert_eq!(run(steps), *expected, "{}", name);
+        }
+    }
+}
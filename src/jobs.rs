@@ -0,0 +1,197 @@
+//! Work running off the event loop, tracked so quitting can warn about it, cancel it and
+//! wait briefly for it to stop.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::event::AppEvent;
+
+/// How often `Jobs::shutdown` checks whether its threads have finished.
+const JOIN_POLL: Duration = Duration::from_millis(5);
+
+/// Descriptions of the jobs still running, by id.
+type Registry = Arc<Mutex<Vec<(u64, String)>>>;
+
+/// A running job's side of the bookkeeping: it asks whether to stop, and dropping it
+/// marks the job finished.
+pub struct JobToken {
+    id: u64,
+    running: Registry,
+    cancelled: Arc<AtomicBool>,
+    /// Where to say the job finished, and what to say: its outcome once it reports
+    /// one, its description until then.
+    finished: Option<(Sender<AppEvent>, String)>,
+}
+
+impl JobToken {
+    /// Whether the job should stop early; long jobs check this between steps.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Say `outcome` in place of the description when the job finishes.
+    fn report(&mut self, outcome: String) {
+        if let Some((_, message)) = &mut self.finished {
+            *message = outcome;
+        }
+    }
+}
+
+impl Drop for JobToken {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.running.lock() {
+            running.retain(|(id, _)| *id != self.id);
+        }
+        if let Some((sender, message)) = self.finished.take() {
+            // Nobody is listening once the app has quit
+            let _ = sender.send(AppEvent::JobFinished(message));
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    running: Registry,
+    cancelled: Arc<AtomicBool>,
+    next_id: u64,
+    threads: Vec<JoinHandle<()>>,
+    notify: Option<Sender<AppEvent>>,
+}
+
+impl Jobs {
+    /// Send a [`AppEvent::JobFinished`] through `sender` as each job from now on ends,
+    /// so the event loop wakes to show its result.
+    pub fn notify(&mut self, sender: Sender<AppEvent>) {
+        self.notify = Some(sender);
+    }
+
+    /// Register a job described as `description`, e.g. "export to plot.csv". It counts
+    /// as in flight until the returned token is dropped.
+    pub fn start(&mut self, description: impl Into<String>) -> JobToken {
+        let id = self.next_id;
+        self.next_id += 1;
+        let description = description.into();
+        if let Ok(mut running) = self.running.lock() {
+            running.push((id, description.clone()));
+        }
+        JobToken {
+            id,
+            running: Arc::clone(&self.running),
+            cancelled: Arc::clone(&self.cancelled),
+            finished: self.notify.clone().map(|sender| (sender, description)),
+        }
+    }
+
+    /// Run `work` on its own thread as a job; see [`Self::start`]. What it returns is
+    /// sent on as the job's outcome, such as "Exported 1000 rows to plot.csv".
+    pub fn spawn<F>(&mut self, description: impl Into<String>, work: F)
+    where
+        F: FnOnce(&JobToken) -> String + Send + 'static,
+    {
+        let mut token = self.start(description);
+        self.threads.retain(|thread| !thread.is_finished());
+        self.threads.push(thread::spawn(move || {
+            let outcome = work(&token);
+            token.report(outcome);
+        }));
+    }
+
+    /// How many jobs haven't finished yet.
+    pub fn in_flight(&self) -> usize {
+        self.running.lock().map_or(0, |running| running.len())
+    }
+
+    /// What the running jobs are doing, oldest first.
+    pub fn descriptions(&self) -> Vec<String> {
+        self.running.lock().map_or_else(
+            |_| Vec::new(),
+            |running| running.iter().map(|(_, text)| text.clone()).collect(),
+        )
+    }
+
+    /// Ask every running job to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Cancel everything and wait up to `timeout` for the job threads to end. Threads
+    /// still running after that are left behind. Returns whether they all ended.
+    pub fn shutdown(&mut self, timeout: Duration) -> bool {
+        self.cancel();
+        let deadline = Instant::now() + timeout;
+        while self.threads.iter().any(|thread| !thread.is_finished()) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(JOIN_POLL);
+        }
+        for thread in self.threads.drain(..) {
+            // A job that panicked has nothing left to clean up
+            let _ = thread.join();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_count_jobs_until_dropped() {
+        let mut jobs = Jobs::default();
+        let export = jobs.start("export to plot.csv");
+        let sample = jobs.start("sampling sin(x)");
+        assert_eq!(jobs.in_flight(), 2);
+        drop(export);
+        assert_eq!(jobs.descriptions(), ["sampling sin(x)"]);
+        drop(sample);
+        assert_eq!(jobs.in_flight(), 0);
+    }
+
+    #[test]
+    fn shutdown_cancels_and_waits_for_threads() {
+        let mut jobs = Jobs::default();
+        jobs.spawn("export to plot.csv", |token| {
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            "Export cancelled".to_string()
+        });
+        assert_eq!(jobs.in_flight(), 1);
+        assert!(jobs.shutdown(Duration::from_secs(5)));
+        assert_eq!(jobs.in_flight(), 0);
+
+        // A job that never checks is given up on after the timeout
+        let (release, wait) = std::sync::mpsc::channel::<()>();
+        jobs.spawn("stuck", move |_| {
+            let _ = wait.recv();
+            String::new()
+        });
+        assert!(!jobs.shutdown(Duration::from_millis(20)));
+        assert_eq!(jobs.descriptions(), ["stuck"]);
+        drop(release);
+    }
+
+    #[test]
+    fn finished_jobs_send_their_outcome() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut jobs = Jobs::default();
+        jobs.notify(sender);
+        drop(jobs.start("sampling sin(x)"));
+        jobs.spawn("export to plot.csv", |_| "Exported 3 rows".to_string());
+        assert!(jobs.shutdown(Duration::from_secs(5)));
+
+        let messages: Vec<_> = receiver
+            .try_iter()
+            .map(|event| match event {
+                AppEvent::JobFinished(message) => message,
+                _ => panic!("expected only finished jobs"),
+            })
+            .collect();
+        assert_eq!(messages, ["sampling sin(x)", "Exported 3 rows"]);
+    }
+}
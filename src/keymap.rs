@@ -0,0 +1,623 @@
+//! Key bindings for each app state, grouped into the sections shown by the help overlay,
+//! and the keymap built from them and the config that key handling reads.
+
+use crate::AppState;
+use crate::config::Config;
+use crate::palette::Command;
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::fmt;
+
+pub struct Binding {
+    pub keys: &'static str,
+    pub action: &'static str,
+    /// The palette command these keys run, if any.
+    pub command: Option<Command>,
+}
+
+pub struct Section {
+    pub title: &'static str,
+    pub bindings: &'static [Binding],
+}
+
+const fn bind(keys: &'static str, action: &'static str) -> Binding {
+    Binding {
+        keys,
+        action,
+        command: None,
+    }
+}
+
+const fn bind_command(keys: &'static str, action: &'static str, command: Command) -> Binding {
+    Binding {
+        keys,
+        action,
+        command: Some(command),
+    }
+}
+
+const NAVIGATION: Section = Section {
+    title: "Navigation",
+    bindings: &[
+        bind("↑↓←→", "Navigate buttons (history when shown)"),
+        bind("Enter", "Press selected button"),
+        bind("Space", "Press selected button"),
+        bind("Mouse", "Click button directly"),
+    ],
+};
+
+const MODES: Section = Section {
+    title: "Modes",
+    bindings: &[
+        bind("`", "Toggle typing mode"),
+        bind("m", "Cycle Basic/Scientific/Programmer/Financial modes"),
+        bind_command("d", "Toggle angle mode (RAD/DEG)", Command::ToggleAngleMode),
+        bind_command(
+            "f",
+            "Cycle significant figures (off, 2, 3, 4, 6, 8)",
+            Command::CycleSigFigs,
+        ),
+        bind_command(
+            "n",
+            "Cycle result format (decimal, sci, eng, fraction)",
+            Command::CycleResultFormat,
+        ),
+        bind_command(
+            "F",
+            "Toggle fraction mode (fraction or decimal result)",
+            Command::ToggleFractions,
+        ),
+        bind_command(
+            "w",
+            "Cycle programmer word size (8, 16, 32, 64 bits)",
+            Command::CycleWordSize,
+        ),
+        bind("h", "Toggle calculation history"),
+        bind("2", "Toggle secondary functions (2nd)"),
+    ],
+};
+
+const OPERATIONS: Section = Section {
+    title: "Operations",
+    bindings: &[
+        bind("c", "Clear current expression (first dismisses an error)"),
+        bind("C", "Clear all (expression and history)"),
+        bind("⌫", "Backspace (after an error: jump to it)"),
+        bind_command(
+            "u",
+            "Convert result (deg, rad, ×π, ÷π, %, dB)",
+            Command::ConvertResult,
+        ),
+        bind_command(
+            "U",
+            "Unit converter (length, mass, temperature, data)",
+            Command::UnitConverter,
+        ),
+        bind_command(
+            "S",
+            "Statistics table (one- and two-variable stats)",
+            Command::Statistics,
+        ),
+        bind_command(
+            "M",
+            "Matrix editor (then det, inv, transpose)",
+            Command::MatrixEditor,
+        ),
+        bind_command(
+            "D",
+            "Date calculator (days between, date plus days)",
+            Command::DateCalculator,
+        ),
+        bind_command(
+            "k",
+            "Insert a constant (c, g, h, k_b, avogadro, phi, tau)",
+            Command::InsertConstant,
+        ),
+        bind("r", "Recall from history"),
+    ],
+};
+
+const EDITING: Section = Section {
+    title: "Editing",
+    bindings: &[
+        bind("0-9 . + - * / ^ %", "Type into the expression"),
+        bind(
+            ": × ÷ ·",
+            "Also divide and multiply (add more with typing_aliases)",
+        ),
+        bind(
+            "a-z ( ) [ ] , ; = \"",
+            "Functions, variables and statements",
+        ),
+        bind("←→", "Move the cursor (also Home/End, or click)"),
+        bind("Shift+←→", "Select part of the expression"),
+        bind(
+            "Ctrl+e",
+            "Evaluate the selection (Ctrl+Shift+e: replace it)",
+        ),
+        bind("Backspace", "Delete before the cursor (Delete: after)"),
+        bind("$", "Insert last result (also Ctrl+r)"),
+        bind_command("Ctrl+k", "Insert a constant", Command::InsertConstant),
+        bind("Enter", "Calculate"),
+        bind("↑↓", "Browse history"),
+        bind("` / Esc", "Back to button navigation"),
+    ],
+};
+
+const EXPRESSIONS: Section = Section {
+    title: "Expressions",
+    bindings: &[
+        bind("x, y, z", "Primary variables (2nd function mode)"),
+        bind("a, b, c", "Secondary variables (2nd function mode)"),
+        bind("x = 3", "Assign a variable (Typing mode, then Enter)"),
+        bind("a=3; a*2", "Several statements, each result listed"),
+        bind("2x, 3sin(x), x(x+1)", "Products without a *"),
+        bind("π, e", "Pi (3.14159) and Euler's number (2.71828)"),
+        bind(
+            "c g h k_b ...",
+            "Constants (k lists them); a variable of the name wins",
+        ),
+        bind("n!", "Factorial"),
+        bind("a ** b, a // b", "Power and floor division, as in Python"),
+        bind(
+            "a % b, mod(a, b)",
+            "Remainder (sign of a, or b when floored), modulo (sign of b)",
+        ),
+        bind("percent(x), x%o", "Percent (x/100) and per mille (x/1000)"),
+        bind("sin cos tan", "Trigonometric functions"),
+        bind("log ln", "Logarithmic functions"),
+        bind("√ exp abs", "Square root, exponential, absolute value"),
+        bind("pctdiff(a, b)", "Percent difference"),
+        bind("pctchange(old, new)", "Percent change"),
+        bind("markup(cost, pct)", "Cost plus pct percent"),
+        bind("margin(price, cost)", "Profit margin, in percent"),
+        bind("sigfig(x, n)", "x rounded to n significant figures"),
+        bind(
+            "approx(a, b, tol)",
+            "1 if a and b agree within tol (1e-9), else 0",
+        ),
+        bind("ulps(a, b)", "Representable floats between a and b"),
+        bind(
+            "fmin fmax fmean",
+            "Of an expression in x over a..b, e.g. fmax(sin(x), 0, 1)",
+        ),
+        bind("sum(k, 1, n, k^2)", "Sum (or prod, product) over k = 1..n"),
+        bind(
+            "solve(x^2-2, x, 1)",
+            "Root nearest a guess (or between two values)",
+        ),
+        bind(
+            "gcd lcm isprime",
+            "Whole-number functions; factor(360) gives 2^3 * 3^2 * 5",
+        ),
+        bind(
+            "rand() randint(a, b)",
+            "Random numbers (randn: normal); random_seed repeats them",
+        ),
+        bind(
+            "< <= == != and or not",
+            "Comparisons and logic, giving 1 or 0",
+        ),
+        bind(
+            "if(x<0, -x, x^2)",
+            "The second value if true, else the third",
+        ),
+        bind("[1, 2, 3] * 2", "Lists, worked out value by value"),
+        bind(
+            "sum mean min max",
+            "Of a list, e.g. mean([1, 2, 3]) (also prod)",
+        ),
+        bind(
+            "[[1, 2], [3, 4]]",
+            "A matrix; * multiplies and ^ raises it (M edits one)",
+        ),
+        bind(
+            "det inv transpose",
+            "Matrix functions; identity(n) makes one",
+        ),
+        bind(
+            "\"2024-06-15\" + 90",
+            "A date; add days, or subtract dates for the days between",
+        ),
+        bind(
+            "days_between date_add",
+            "Date functions; weekday(date), today() (D for a panel)",
+        ),
+        bind(
+            "unix(ts) to_unix(t)",
+            "Unix timestamps to times (+ seconds) and back",
+        ),
+        bind(
+            "tz(t, -5) local(t)",
+            "A time shown at a UTC offset in hours",
+        ),
+        bind(
+            "9.81±0.02 or 9.81 +/- 0.02",
+            "A value with its error, carried through arithmetic",
+        ),
+        bind(
+            "npv(0.1, [-100, 60, 60])",
+            "Net present value of cash flows; irr(flows) their rate",
+        ),
+        bind("ratio(a, b)", "Simplified ratio, e.g. 3:4"),
+        bind(
+            "convert(x, \"km\", \"mi\")",
+            "Convert units (U lists them all)",
+        ),
+    ],
+};
+
+const GRAPH_VIEW: Section = Section {
+    title: "View",
+    bindings: &[
+        bind("↑↓←→", "Pan graph view"),
+        bind("Drag", "Pan with the mouse"),
+        bind("+/-", "Zoom in/out"),
+        bind("r", "Reset view (to fit the data when plotted)"),
+        bind("A", "Lock aspect so circles look round"),
+        bind("c", "Toggle coordinate display"),
+        bind("t", "Tangent at the cursor's x and its equation"),
+        bind("g", "Toggle gridlines at the axis ticks"),
+        bind("z", "Mark and list the roots in view"),
+        bind("i", "Mark and list where curves (x^2; 2x) intersect"),
+        bind("m", "Mark and list the local minima and maxima in view"),
+        bind(
+            "b / B",
+            "Drop an integration bound at the cursor (drag to move) / clear",
+        ),
+    ],
+};
+
+const CONVERTER: Section = Section {
+    title: "Converter",
+    bindings: &[
+        bind("Tab / ←→", "Move between the category, from and to lists"),
+        bind("↑↓", "Choose in the list"),
+        bind("0-9 . -", "Type the value (Backspace deletes)"),
+        bind("s", "Swap the from and to units"),
+        bind("Enter", "Calculate it as convert(...) in the calculator"),
+    ],
+};
+
+const STATS_TABLE: Section = Section {
+    title: "Data",
+    bindings: &[
+        bind("0-9 . - e", "Type a value into the selected cell"),
+        bind("Enter / ↑↓", "Enter it and move down or up"),
+        bind("Tab / ←→", "Switch between the x and y columns"),
+        bind("Backspace", "Delete a typed digit, or blank the cell"),
+        bind("Delete", "Remove the selected row"),
+        bind(
+            "f",
+            "Cycle the fitted curve (linear, quadratic, exponential)",
+        ),
+        bind("g", "Plot the (x, y) pairs and the fit (Esc comes back)"),
+        bind("C", "Clear the whole table"),
+    ],
+};
+
+const MATRIX_EDITOR: Section = Section {
+    title: "Matrix",
+    bindings: &[
+        bind("0-9 . - e", "Type a value into the selected cell"),
+        bind("↑↓←→", "Enter it and move around the grid"),
+        bind("Tab", "Enter it and move to the next cell"),
+        bind("Backspace", "Delete a typed digit, or zero the cell"),
+        bind("r / R", "Add or remove a row"),
+        bind("c / C", "Add or remove a column"),
+        bind("z", "Zero every cell"),
+        bind("Enter", "Insert the matrix at the cursor"),
+    ],
+};
+
+const DATE_CALCULATOR: Section = Section {
+    title: "Dates",
+    bindings: &[
+        bind("0-9 -", "Type a date as YYYY-MM-DD, or the days"),
+        bind("Tab / ↑↓", "Move between the start, end and days"),
+        bind("Backspace", "Delete a typed character"),
+        bind("t", "Set the selected date to today"),
+        bind(
+            "Enter",
+            "Calculate days_between(...), or date_add(...) on the days",
+        ),
+    ],
+};
+
+const CALCULATOR_GENERAL: Section = Section {
+    title: "General",
+    bindings: &[
+        bind("?", "Show this help (Esc to close)"),
+        bind("Ctrl+p", "Command palette (share, import, settings)"),
+        bind_command("Ctrl+g", "Graph current expression", Command::Graph),
+        bind("Ctrl+o", "Swap with the previous expression"),
+        bind("Ctrl+Shift+r", "Record a macro; again to store it (a-z)"),
+        bind("Ctrl+Shift+p", "Replay a macro from register a-z"),
+        bind("q / Esc", "Quit application (Normal mode)"),
+        bind("Ctrl+q", "Quit without confirming"),
+    ],
+};
+
+const GRAPH_GENERAL: Section = Section {
+    title: "General",
+    bindings: &[
+        bind("?", "Show this help (Esc to close)"),
+        bind("Ctrl+p", "Command palette (share, import, settings)"),
+        bind("Esc", "Exit graph mode"),
+        bind("Ctrl+q", "Quit without confirming"),
+    ],
+};
+
+const CONVERTER_GENERAL: Section = Section {
+    title: "General",
+    bindings: &[
+        bind("?", "Show this help (Esc to close)"),
+        bind("Ctrl+p", "Command palette (share, import, settings)"),
+        bind("Esc / U", "Back to the calculator"),
+        bind("Ctrl+q", "Quit without confirming"),
+    ],
+};
+
+const STATS_GENERAL: Section = Section {
+    title: "General",
+    bindings: &[
+        bind("?", "Show this help (Esc to close)"),
+        bind("Ctrl+p", "Command palette (share, import, settings)"),
+        bind("Esc / S", "Back to the calculator (the data is kept)"),
+        bind("Ctrl+q", "Quit without confirming"),
+    ],
+};
+
+const MATRIX_GENERAL: Section = Section {
+    title: "General",
+    bindings: &[
+        bind("?", "Show this help (Esc to close)"),
+        bind("Ctrl+p", "Command palette (share, import, settings)"),
+        bind("Esc / M", "Back to the calculator (the matrix is kept)"),
+        bind("Ctrl+q", "Quit without confirming"),
+    ],
+};
+
+const DATES_GENERAL: Section = Section {
+    title: "General",
+    bindings: &[
+        bind("?", "Show this help (Esc to close)"),
+        bind("Ctrl+p", "Command palette (share, import, settings)"),
+        bind("Esc / D", "Back to the calculator (the dates are kept)"),
+        bind("Ctrl+q", "Quit without confirming"),
+    ],
+};
+
+/// Help sections for `state`, most specific first.
+pub fn sections(state: AppState) -> &'static [Section] {
+    match state {
+        AppState::Normal => &[
+            NAVIGATION,
+            MODES,
+            OPERATIONS,
+            EXPRESSIONS,
+            CALCULATOR_GENERAL,
+        ],
+        AppState::Typing => &[EDITING, EXPRESSIONS, CALCULATOR_GENERAL],
+        AppState::Graph => &[GRAPH_VIEW, GRAPH_GENERAL],
+        AppState::Convert => &[CONVERTER, CONVERTER_GENERAL],
+        AppState::Stats => &[STATS_TABLE, STATS_GENERAL],
+        AppState::Matrix => &[MATRIX_EDITOR, MATRIX_GENERAL],
+        AppState::Dates => &[DATE_CALCULATOR, DATES_GENERAL],
+    }
+}
+
+/// A key and the modifiers held with it, written like `d`, `Ctrl+g` or `Alt+Enter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+/// Keys with names, as the config and help write them.
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Esc, "Esc"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::BackTab, "BackTab"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Char(' '), "Space"),
+];
+
+const MODIFIER_NAMES: &[(KeyModifiers, &str)] = &[
+    (KeyModifiers::CONTROL, "Ctrl+"),
+    (KeyModifiers::ALT, "Alt+"),
+    (KeyModifiers::SHIFT, "Shift+"),
+];
+
+impl Chord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// Read a chord written as [`Chord`]'s `Display` writes it. `None` for keys without
+    /// a name here, such as media keys.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut key = text;
+        // A key of `+` itself follows the last modifier, as in `Ctrl++`
+        while let Some((modifier, rest)) = MODIFIER_NAMES.iter().find_map(|&(modifier, name)| {
+            let rest = key.strip_prefix(name).filter(|rest| !rest.is_empty())?;
+            Some((modifier, rest))
+        }) {
+            modifiers |= modifier;
+            key = rest;
+        }
+        let code = match KEY_NAMES.iter().find(|(_, name)| *name == key) {
+            Some(&(code, _)) => code,
+            None => match key.strip_prefix('F').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=24) => KeyCode::F(n),
+                _ => {
+                    let mut chars = key.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => KeyCode::Char(c),
+                        _ => return None,
+                    }
+                }
+            },
+        };
+        Some(Self { code, modifiers })
+    }
+
+    /// Whether this chord can be written down and read back.
+    pub fn is_named(self) -> bool {
+        matches!(self.code, KeyCode::Char(_) | KeyCode::F(_))
+            || KEY_NAMES.iter().any(|&(code, _)| code == self.code)
+    }
+
+    /// Whether a key press is this chord. Shift is part of a typed character, such as
+    /// `F` or `(`, so it only counts for other keys.
+    pub fn matches(self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let held = |modifiers: KeyModifiers| match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        self.code == code && held(self.modifiers) == held(modifiers)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &(modifier, name) in MODIFIER_NAMES {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match KEY_NAMES.iter().find(|&&(code, _)| code == self.code) {
+            Some((_, name)) => f.write_str(name),
+            None => match self.code {
+                KeyCode::Char(c) => write!(f, "{}", c),
+                KeyCode::F(n) => write!(f, "F{}", n),
+                _ => f.write_str("?"),
+            },
+        }
+    }
+}
+
+/// The states whose keys can run palette commands, and be bound to them in the config.
+const COMMAND_STATES: [AppState; 2] = [AppState::Normal, AppState::Typing];
+
+/// What keys do, as key handling looks it up: the default bindings listed in help with
+/// the config's `bind` lines and typing aliases over them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    /// Keys that run a command, and the state they do it in.
+    commands: Vec<(AppState, Chord, Command)>,
+    /// Keys typed as another key in Typing mode; later pairs win.
+    aliases: Vec<(char, char)>,
+}
+
+impl Keymap {
+    pub fn new(config: &Config) -> Self {
+        let mut commands = Vec::new();
+        for state in COMMAND_STATES {
+            for binding in sections(state).iter().flat_map(|section| section.bindings) {
+                if let Some(command) = binding.command {
+                    let chord = Chord::parse(binding.keys).expect("help names one key");
+                    commands.push((state, chord, command));
+                }
+            }
+        }
+        // A binding in the config replaces the command's keys. Typing mode types plain
+        // keys, so only those with Ctrl or Alt work there.
+        for (command, chords) in &config.key_bindings {
+            commands.retain(|&(_, _, bound)| bound != *command);
+            for &chord in chords {
+                let typed = !chord
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                for state in COMMAND_STATES {
+                    if !(typed && state == AppState::Typing) {
+                        commands.push((state, chord, *command));
+                    }
+                }
+            }
+        }
+        Self {
+            commands,
+            aliases: config.typing_aliases.clone(),
+        }
+    }
+
+    /// The command a key press runs in `state`, if any.
+    pub fn command(
+        &self,
+        state: AppState,
+        code: KeyCode,
+        modifiers: KeyModifiers,
+    ) -> Option<Command> {
+        self.commands
+            .iter()
+            .find(|&&(bound, chord, _)| bound == state && chord.matches(code, modifiers))
+            .map(|&(_, _, command)| command)
+    }
+
+    /// Keys that run `command` directly in `state`, in the order help lists them.
+    pub fn shortcuts(&self, state: AppState, command: Command) -> Vec<String> {
+        self.commands
+            .iter()
+            .filter(|&&(bound, _, to)| bound == state && to == command)
+            .map(|(_, chord, _)| chord.to_string())
+            .collect()
+    }
+
+    /// The key `key` stands for in Typing mode, if it is an alias.
+    pub fn alias(&self, key: char) -> Option<char> {
+        self.aliases
+            .iter()
+            .rev()
+            .find(|(alias, _)| *alias == key)
+            .map(|&(_, typed)| typed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_read_back_as_they_are_written() {
+        for text in [
+            "d",
+            "F",
+            "Ctrl+g",
+            "Ctrl+Shift+r",
+            "Alt+Enter",
+            "Ctrl++",
+            "Space",
+            "F12",
+        ] {
+            assert_eq!(Chord::parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(
+            Chord::parse("Ctrl+k"),
+            Some(Chord::new(KeyCode::Char('k'), KeyModifiers::CONTROL))
+        );
+        for text in ["", "Ctrl+", "Hyper+x", "F25", "Enter2"] {
+            assert_eq!(Chord::parse(text), None, "{}", text);
+        }
+        // Terminals may or may not report the shift of a typed character
+        let upper = Chord::parse("F").unwrap();
+        assert!(upper.matches(KeyCode::Char('F'), KeyModifiers::SHIFT));
+        assert!(!upper.matches(KeyCode::Char('F'), KeyModifiers::CONTROL));
+        assert!(
+            !Chord::parse("Up")
+                .unwrap()
+                .matches(KeyCode::Up, KeyModifiers::SHIFT)
+        );
+    }
+}
@@ -0,0 +1,33 @@
+pub mod bigint;
+pub mod calculator;
+pub mod cli;
+pub mod clipboard;
+pub mod config;
+pub mod constants;
+pub mod dates;
+pub mod eval;
+pub mod event;
+pub mod finance;
+pub mod glyphs;
+pub mod graph;
+pub mod input;
+pub mod jobs;
+pub mod keymap;
+pub mod macros;
+pub mod matrix;
+pub mod number_theory;
+pub mod numeric;
+pub mod palette;
+pub mod precision;
+pub mod programmer;
+pub mod random;
+pub mod rational;
+pub mod share;
+pub mod stats;
+pub mod ui;
+pub mod uncertainty;
+pub mod units;
+
+mod app;
+
+pub use app::{App, AppState, ConfirmAction, Popup, QuitTrigger};
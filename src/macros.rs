@@ -0,0 +1,307 @@
+//! Keyboard macros: what key presses did, recorded into lettered registers and
+//! replayed later. Registers are kept in the session file beside the config, so they last between runs.
+
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{fs, io};
+
+use crate::keymap::Chord;
+use crate::palette::Command;
+
+/// Longest macro that can be recorded; recording stops by itself when it is reached.
+pub const MAX_STEPS: usize = 256;
+
+/// One recorded action. Commands and typed text are kept as what they did, so a macro
+/// replays the same way after keys are rebound or aliased.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// A command run from a key binding or the palette, written by its config name.
+    Command(Command),
+    /// A character typed in Typing mode after aliases, written inside quotes.
+    Typed(char),
+    /// Any other key, written as the keymap writes keys.
+    Key(Chord),
+}
+
+/// What the next letter key picks a register for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pending {
+    Store,
+    Replay,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Macros {
+    recording: Option<Vec<Step>>,
+    /// Steps waiting for a register after recording stopped.
+    finished: Vec<Step>,
+    pub pending: Option<Pending>,
+    registers: BTreeMap<char, Vec<Step>>,
+    replaying: bool,
+    /// Where registers are saved when one is stored; `None` keeps them for this run.
+    file: Option<PathBuf>,
+}
+
+/// Whether `code` is Ctrl+Shift+`letter`. Terminals report the shift either as an
+/// uppercase letter or as the modifier.
+fn ctrl_shift(code: KeyCode, modifiers: KeyModifiers, letter: char) -> bool {
+    modifiers.contains(KeyModifiers::CONTROL)
+        && match code {
+            KeyCode::Char(c) if c == letter.to_ascii_uppercase() => true,
+            KeyCode::Char(c) => c == letter && modifiers.contains(KeyModifiers::SHIFT),
+            _ => false,
+        }
+}
+
+/// Ctrl+Shift+R, which starts and stops recording.
+pub fn is_record_key(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    ctrl_shift(code, modifiers, 'r')
+}
+
+/// Ctrl+Shift+P, which replays the register named by the next key.
+pub fn is_replay_key(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    ctrl_shift(code, modifiers, 'p')
+}
+
+impl Macros {
+    /// The registers saved in `path`, which stored ones are saved back to. A missing
+    /// file has none.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let registers = match fs::read_to_string(&path) {
+            Ok(text) => {
+                parse_registers(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(anyhow::anyhow!("{}: {}", path.display(), e)),
+        };
+        Ok(Self {
+            registers,
+            file: Some(path),
+            ..Self::default()
+        })
+    }
+
+    /// Write every register to the session file, if there is one.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, format_registers(&self.registers))
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replaying
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// End recording and wait for a register to store it in.
+    pub fn stop_recording(&mut self) {
+        self.finished = self.recording.take().unwrap_or_default();
+        self.pending = Some(Pending::Store);
+    }
+
+    /// Add a step to the macro being recorded. Returns false once the macro is full,
+    /// which ends the recording. Keys the session file can't name, such as media keys,
+    /// do nothing in tcalc and are left out.
+    pub fn record(&mut self, step: Step) -> bool {
+        let Some(steps) = &mut self.recording else {
+            return true;
+        };
+        if let Step::Key(chord) = step
+            && !chord.is_named()
+        {
+            return true;
+        }
+        steps.push(step);
+        if steps.len() < MAX_STEPS {
+            return true;
+        }
+        self.stop_recording();
+        false
+    }
+
+    /// Store the finished recording under `register`, returning its length.
+    pub fn store(&mut self, register: char) -> usize {
+        let steps = std::mem::take(&mut self.finished);
+        let len = steps.len();
+        self.registers.insert(register, steps);
+        len
+    }
+
+    pub fn get(&self, register: char) -> Option<&[Step]> {
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+
+    /// Forget every register, keeping where they're saved.
+    pub fn clear(&mut self) {
+        self.registers.clear();
+    }
+
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+}
+
+/// One `macro_<register> = <steps>` line per register, the steps separated by spaces
+/// and each run of typed text quoted, as in `"sqrt(2)" Enter graph`.
+fn format_registers(registers: &BTreeMap<char, Vec<Step>>) -> String {
+    registers
+        .iter()
+        .map(|(register, steps)| {
+            let mut words: Vec<String> = Vec::new();
+            let mut text: Option<String> = None;
+            for step in steps {
+                let word = match step {
+                    Step::Typed(c) => {
+                        let text = text.get_or_insert_with(String::new);
+                        if matches!(c, '"' | '\\') {
+                            text.push('\\');
+                        }
+                        text.push(*c);
+                        continue;
+                    }
+                    Step::Command(command) => command.name().to_string(),
+                    Step::Key(chord) => chord.to_string(),
+                };
+                words.extend(text.take().map(|text| format!("\"{}\"", text)));
+                words.push(word);
+            }
+            words.extend(text.map(|text| format!("\"{}\"", text)));
+            format!("macro_{} = {}\n", register, words.join(" "))
+        })
+        .collect()
+}
+
+/// The steps written by [`format_registers`] on one line.
+fn parse_steps(value: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c) => steps.push(Step::Typed(c)),
+                        None => return Err("typed text ends in \\".to_string()),
+                    },
+                    Some(c) => steps.push(Step::Typed(c)),
+                    None => return Err("typed text is missing its closing quote".to_string()),
+                }
+            },
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    word.push(c);
+                }
+                let step = match Command::from_name(&word) {
+                    Some(command) => Step::Command(command),
+                    None => Step::Key(
+                        Chord::parse(&word)
+                            .ok_or_else(|| format!("{} is not a key or command", word))?,
+                    ),
+                };
+                steps.push(step);
+            }
+        }
+    }
+    Ok(steps)
+}
+
+fn parse_registers(text: &str) -> Result<BTreeMap<char, Vec<Step>>> {
+    let mut registers = BTreeMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("line {}: expected key = value", number + 1))?;
+        // Other keys are for other parts of the session
+        let Some(register) = key.trim().strip_prefix("macro_") else {
+            continue;
+        };
+        let register = match register.chars().collect::<Vec<_>>()[..] {
+            [register @ 'a'..='z'] => register,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "line {}: macro registers are a to z",
+                    number + 1
+                ));
+            }
+        };
+        let steps =
+            parse_steps(value).map_err(|e| anyhow::anyhow!("line {}: {}", number + 1, e))?;
+        registers.insert(register, steps);
+    }
+    Ok(registers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_last_between_runs() {
+        let path = std::env::temp_dir()
+            .join(format!("tcalc-macros-{}", std::process::id()))
+            .join("session");
+        let mut macros = Macros::load(path.clone()).unwrap();
+        assert_eq!(macros.get('a'), None);
+
+        let typed = "sqrt(2) + 1";
+        macros.start_recording();
+        for c in typed.chars() {
+            macros.record(Step::Typed(c));
+        }
+        macros.record(Step::Key(Chord::new(KeyCode::Enter, KeyModifiers::NONE)));
+        macros.record(Step::Command(Command::Graph));
+        macros.record(Step::Key(Chord::new(
+            KeyCode::Media(crossterm::event::MediaKeyCode::Play),
+            KeyModifiers::NONE,
+        )));
+        macros.stop_recording();
+        macros.store('a');
+        macros.start_recording();
+        macros.record(Step::Typed('"'));
+        macros.record(Step::Typed('\\'));
+        macros.record(Step::Key(Chord::new(
+            KeyCode::Char('+'),
+            KeyModifiers::CONTROL,
+        )));
+        macros.stop_recording();
+        macros.store('z');
+        macros.save().unwrap();
+
+        let loaded = Macros::load(path.clone()).unwrap();
+        for register in ['a', 'z'] {
+            assert_eq!(loaded.get(register), macros.get(register));
+        }
+        assert_eq!(loaded.get('a').map(<[_]>::len), Some(typed.len() + 2));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "macro_a = \"sqrt(2) + 1\" Enter graph\nmacro_z = \"\\\"\\\\\" Ctrl++\n"
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert!(parse_registers("macro_A = 1").is_err());
+        assert!(parse_registers("macro_a = Ctrl+Hyper+x").is_err());
+        assert!(parse_registers("macro_a = \"sqrt(2").is_err());
+        assert_eq!(parse_registers("theme = dark").unwrap(), BTreeMap::new());
+    }
+}
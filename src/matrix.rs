@@ -0,0 +1,507 @@
+//! Matrices: the values `[[1, 2], [3, 4]]` evaluates to, the linear algebra on them,
+//! and the grid editor that types one in a cell at a time.
+
+use std::fmt;
+
+use crate::calculator::{EvalError, EvalResult, format_result};
+use crate::eval::Value;
+use crate::stats;
+
+/// Functions of a matrix; `identity` makes one instead.
+pub const MATRIX_FUNCTIONS: &[&str] = &["det", "inv", "transpose", "identity"];
+
+/// The most rows or columns the editor makes, and `identity` gives.
+pub const MAX_SIZE: usize = 10;
+
+/// Why `inv` fails on a matrix with no inverse.
+const SINGULAR: &str = "inv() needs a matrix whose determinant isn't 0";
+
+/// Call `name`, one of [`MATRIX_FUNCTIONS`], on `value`. A number is a 1×1 matrix and a
+/// list a single row; `identity` takes the size instead.
+pub fn call_matrix(name: &'static str, value: Value) -> EvalResult<Value> {
+    let matrix = match value {
+        Value::Number(size) if name == "identity" => {
+            if size.fract() != 0.0 || !(1.0..=MAX_SIZE as f64).contains(&size) {
+                return Err(EvalError::undefined(name, size));
+            }
+            return Ok(Value::Matrix(Matrix::identity(size as usize)));
+        }
+        _ if name == "identity" => return value.number().map(Value::Number),
+        Value::Number(value) => match name {
+            "inv" if value == 0.0 => {
+                return Err(EvalError::Domain {
+                    func: name,
+                    arg: value,
+                    reason: SINGULAR,
+                });
+            }
+            "inv" => return Ok(Value::Number(1.0 / value)),
+            _ => return Ok(Value::Number(value)),
+        },
+        Value::List(values) => Matrix::from_rows(vec![values])?,
+        Value::Matrix(matrix) => matrix,
+        Value::Date(_) | Value::Time(_) => return Err(EvalError::UnexpectedDate),
+        Value::Uncertain(..) => return Err(EvalError::UnexpectedUncertain),
+    };
+    match name {
+        "det" => matrix.determinant().map(Value::Number),
+        "inv" => matrix.inverse().map(Value::Matrix),
+        _ => Ok(Value::Matrix(matrix.transpose())),
+    }
+}
+
+/// A matrix of at least one row and column, stored a row at a time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    values: Vec<f64>,
+}
+
+impl Matrix {
+    /// A matrix of `rows` by `cols` zeros.
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            values: vec![0.0; rows * cols],
+        }
+    }
+
+    pub fn identity(size: usize) -> Self {
+        let mut matrix = Self::zeros(size, size);
+        for i in 0..size {
+            matrix.values[i * size + i] = 1.0;
+        }
+        matrix
+    }
+
+    /// The matrix with `rows` as its rows, which must be the same, non-zero length.
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> EvalResult<Self> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if cols == 0 || rows.iter().any(|row| row.len() != cols) {
+            return Err(EvalError::RaggedMatrix);
+        }
+        Ok(Self {
+            rows: rows.len(),
+            cols,
+            values: rows.concat(),
+        })
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.values[row * self.cols + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[f64] {
+        &self.values[row * self.cols..(row + 1) * self.cols]
+    }
+
+    /// Every value, a row at a time.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// `f` applied to each value in place.
+    pub fn map(mut self, mut f: impl FnMut(f64) -> EvalResult<f64>) -> EvalResult<Self> {
+        for value in &mut self.values {
+            *value = f(*value)?;
+        }
+        Ok(self)
+    }
+
+    /// `f` applied to the values of `self` and `other` pair by pair, for two matrices of
+    /// the same size.
+    pub fn zip(
+        mut self,
+        other: &Matrix,
+        op: &'static str,
+        mut f: impl FnMut(f64, f64) -> EvalResult<f64>,
+    ) -> EvalResult<Self> {
+        if self.size() != other.size() {
+            return Err(EvalError::MatrixSizes {
+                op,
+                left: self.size(),
+                right: other.size(),
+            });
+        }
+        for (value, &other) in self.values.iter_mut().zip(&other.values) {
+            *value = f(*value, other)?;
+        }
+        Ok(self)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut transposed = Self::zeros(self.cols, self.rows);
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                transposed.values[col * self.rows + row] = self.get(row, col);
+            }
+        }
+        transposed
+    }
+
+    /// The matrix product `self * other`; `self` needs as many columns as `other` has
+    /// rows.
+    pub fn multiply(&self, other: &Matrix) -> EvalResult<Self> {
+        if self.cols != other.rows {
+            return Err(EvalError::MatrixSizes {
+                op: "*",
+                left: self.size(),
+                right: other.size(),
+            });
+        }
+        let mut product = Self::zeros(self.rows, other.cols);
+        for row in 0..self.rows {
+            for col in 0..other.cols {
+                product.values[row * other.cols + col] = (0..self.cols)
+                    .map(|k| self.get(row, k) * other.get(k, col))
+                    .sum();
+            }
+        }
+        Ok(product)
+    }
+
+    fn square(&self) -> EvalResult<usize> {
+        match self.size() {
+            (rows, cols) if rows == cols => Ok(rows),
+            (rows, cols) => Err(EvalError::NotSquare(rows, cols)),
+        }
+    }
+
+    /// The determinant, by elimination with partial pivoting. A pivot lost in rounding
+    /// counts as zero, so a singular matrix gives exactly 0.
+    pub fn determinant(&self) -> EvalResult<f64> {
+        let size = self.square()?;
+        let mut rows = self.clone();
+        let mut det = 1.0;
+        for col in 0..size {
+            let Some(pivot) = rows.pivot(col) else {
+                return Ok(0.0);
+            };
+            if pivot != col {
+                rows.swap_rows(pivot, col);
+                det = -det;
+            }
+            let lead = rows.get(col, col);
+            det *= lead;
+            for row in col + 1..size {
+                let factor = rows.get(row, col) / lead;
+                for k in col..size {
+                    rows.values[row * size + k] -= factor * rows.get(col, k);
+                }
+            }
+        }
+        Ok(det)
+    }
+
+    /// The inverse, by Gauss-Jordan elimination; a singular matrix has none.
+    pub fn inverse(&self) -> EvalResult<Self> {
+        let size = self.square()?;
+        let mut rows = self.clone();
+        let mut inverse = Self::identity(size);
+        for col in 0..size {
+            let pivot = rows.pivot(col).ok_or(EvalError::Domain {
+                func: "inv",
+                arg: 0.0,
+                reason: SINGULAR,
+            })?;
+            rows.swap_rows(pivot, col);
+            inverse.swap_rows(pivot, col);
+            let lead = rows.get(col, col);
+            for k in 0..size {
+                rows.values[col * size + k] /= lead;
+                inverse.values[col * size + k] /= lead;
+            }
+            for row in (0..size).filter(|&row| row != col) {
+                let factor = rows.get(row, col);
+                for k in 0..size {
+                    rows.values[row * size + k] -= factor * rows.get(col, k);
+                    inverse.values[row * size + k] -= factor * inverse.get(col, k);
+                }
+            }
+        }
+        Ok(inverse)
+    }
+
+    /// `self` multiplied by itself `exponent` times, which must be a whole number; a
+    /// negative one raises the inverse.
+    pub fn power(&self, exponent: f64) -> EvalResult<Self> {
+        let size = self.square()?;
+        if exponent.fract() != 0.0 || !exponent.is_finite() {
+            return Err(EvalError::Domain {
+                func: "^",
+                arg: exponent,
+                reason: "A matrix can only be raised to a whole power",
+            });
+        }
+        let mut base = match exponent < 0.0 {
+            true => self.inverse()?,
+            false => self.clone(),
+        };
+        let mut remaining = exponent.abs();
+        let mut result = Self::identity(size);
+        // Square and multiply, so a large power takes few products
+        while remaining >= 1.0 {
+            if remaining % 2.0 == 1.0 {
+                result = result.multiply(&base)?;
+            }
+            remaining = (remaining / 2.0).floor();
+            if remaining >= 1.0 {
+                base = base.multiply(&base)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// The row from `col` down with the largest value in `col`, unless every one is too
+    /// small beside the matrix's largest value to tell from rounding.
+    fn pivot(&self, col: usize) -> Option<usize> {
+        let largest = self.values.iter().fold(0.0, |max: f64, v| max.max(v.abs()));
+        let tolerance = self.rows as f64 * f64::EPSILON * largest;
+        (col..self.rows)
+            .max_by(|&a, &b| self.get(a, col).abs().total_cmp(&self.get(b, col).abs()))
+            .filter(|&row| self.get(row, col).abs() > tolerance)
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for col in 0..self.cols {
+            self.values.swap(a * self.cols + col, b * self.cols + col);
+        }
+    }
+}
+
+/// As it's typed: `[[1, 2], [3, 4]]`.
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<String> = (0..self.rows)
+            .map(|row| {
+                let values: Vec<String> = self
+                    .row(row)
+                    .iter()
+                    .map(|&value| format_result(value))
+                    .collect();
+                format!("[{}]", values.join(", "))
+            })
+            .collect();
+        write!(f, "[{}]", rows.join(", "))
+    }
+}
+
+/// A matrix typed in a cell at a time, to insert into the expression.
+#[derive(Debug, Clone)]
+pub struct MatrixEditor {
+    pub matrix: Matrix,
+    pub row: usize,
+    pub col: usize,
+    /// What is typed into the selected cell and not yet entered.
+    pub input: String,
+}
+
+impl Default for MatrixEditor {
+    fn default() -> Self {
+        Self {
+            matrix: Matrix::zeros(2, 2),
+            row: 0,
+            col: 0,
+            input: String::new(),
+        }
+    }
+}
+
+impl MatrixEditor {
+    /// Enter what is typed into the selected cell. Text that isn't a number leaves the
+    /// cell as it was.
+    pub fn commit(&mut self) {
+        let input = std::mem::take(&mut self.input);
+        if let Ok(value) = input.parse::<f64>()
+            && value.is_finite()
+        {
+            let cols = self.matrix.cols;
+            self.matrix.values[self.row * cols + self.col] = value;
+        }
+    }
+
+    /// Enter the typed value and move by `rows` and `cols`, staying inside the matrix.
+    pub fn move_by(&mut self, rows: isize, cols: isize) {
+        self.commit();
+        self.row = self
+            .row
+            .saturating_add_signed(rows)
+            .min(self.matrix.rows - 1);
+        self.col = self
+            .col
+            .saturating_add_signed(cols)
+            .min(self.matrix.cols - 1);
+    }
+
+    /// Enter the typed value and move to the next cell, along the row and then down,
+    /// back to the first after the last.
+    pub fn next_cell(&mut self) {
+        self.commit();
+        self.col += 1;
+        if self.col == self.matrix.cols {
+            self.col = 0;
+            self.row = (self.row + 1) % self.matrix.rows;
+        }
+    }
+
+    /// Type `c` into the selected cell, ignoring anything that can't be part of a number.
+    pub fn push(&mut self, c: char) {
+        stats::push_number(&mut self.input, c);
+    }
+
+    /// Delete the last typed character, or zero the cell when nothing is typed.
+    pub fn backspace(&mut self) {
+        if self.input.pop().is_none() {
+            let cols = self.matrix.cols;
+            self.matrix.values[self.row * cols + self.col] = 0.0;
+        }
+    }
+
+    /// Change the size by `rows` and `cols`, from 1 to [`MAX_SIZE`] each way, keeping
+    /// the values that still fit and filling new cells with zeros.
+    pub fn resize(&mut self, rows: isize, cols: isize) {
+        self.commit();
+        let fit = |size: usize, step: isize| size.saturating_add_signed(step).clamp(1, MAX_SIZE);
+        let (new_rows, new_cols) = (fit(self.matrix.rows, rows), fit(self.matrix.cols, cols));
+        let mut resized = Matrix::zeros(new_rows, new_cols);
+        for row in 0..new_rows.min(self.matrix.rows) {
+            for col in 0..new_cols.min(self.matrix.cols) {
+                resized.values[row * new_cols + col] = self.matrix.get(row, col);
+            }
+        }
+        self.matrix = resized;
+        self.row = self.row.min(new_rows - 1);
+        self.col = self.col.min(new_cols - 1);
+    }
+
+    /// Zero every cell, keeping the size.
+    pub fn clear(&mut self) {
+        let (rows, cols) = self.matrix.size();
+        self.matrix = Matrix::zeros(rows, cols);
+        self.input.clear();
+    }
+
+    /// The matrix as a literal to insert into an expression, each value in full.
+    pub fn expression(&self) -> String {
+        let rows: Vec<String> = (0..self.matrix.rows)
+            .map(|row| {
+                // f64's Display is the shortest string that parses back to the same value
+                let values: Vec<String> = self
+                    .matrix
+                    .row(row)
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect();
+                format!("[{}]", values.join(", "))
+            })
+            .collect();
+        format!("[{}]", rows.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[f64]]) -> Matrix {
+        Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).unwrap()
+    }
+
+    #[test]
+    fn matrix_arithmetic() {
+        let a = matrix(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        let b = matrix(&[&[0.0, 1.0], &[1.0, 0.0]]);
+        assert_eq!(a.multiply(&b), Ok(matrix(&[&[2.0, 1.0], &[4.0, 3.0]])));
+        assert_eq!(a.transpose(), matrix(&[&[1.0, 3.0], &[2.0, 4.0]]));
+        assert_eq!(a.determinant(), Ok(-2.0));
+        let inverse = a.inverse().unwrap();
+        let expected = [-2.0, 1.0, 1.5, -0.5];
+        assert!(
+            inverse
+                .values()
+                .iter()
+                .zip(expected)
+                .all(|(value, expected)| (value - expected).abs() < 1e-12),
+            "{:?}",
+            inverse
+        );
+        assert_eq!(inverse.to_string(), "[[-2, 1], [1.5, -0.5]]");
+        assert_eq!(a.power(0.0), Ok(Matrix::identity(2)));
+        assert_eq!(a.power(3.0), a.multiply(&a).unwrap().multiply(&a));
+        assert_eq!(a.power(-1.0), a.inverse());
+        assert_eq!(a.to_string(), "[[1, 2], [3, 4]]");
+
+        // A row-by-column product needs matching sizes
+        let wide = matrix(&[&[1.0, 2.0, 3.0]]);
+        assert_eq!(wide.multiply(&wide.transpose()), Ok(matrix(&[&[14.0]])));
+        assert_eq!(
+            wide.multiply(&wide),
+            Err(EvalError::MatrixSizes {
+                op: "*",
+                left: (1, 3),
+                right: (1, 3)
+            })
+        );
+        assert_eq!(wide.determinant(), Err(EvalError::NotSquare(1, 3)));
+        assert_eq!(
+            a.power(0.5).unwrap_err().to_string(),
+            "A matrix can only be raised to a whole power"
+        );
+        assert_eq!(
+            Matrix::from_rows(vec![vec![1.0], vec![1.0, 2.0]]),
+            Err(EvalError::RaggedMatrix)
+        );
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        // Rounding leaves a tiny pivot, which still counts as zero
+        let singular = matrix(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]);
+        assert_eq!(singular.determinant(), Ok(0.0));
+        assert_eq!(singular.inverse().unwrap_err().to_string(), SINGULAR);
+        // Pivoting finds the nonzero row beneath a zero
+        let swapped = matrix(&[&[0.0, 1.0], &[1.0, 0.0]]);
+        assert_eq!(swapped.determinant(), Ok(-1.0));
+        assert_eq!(swapped.inverse(), Ok(swapped.clone()));
+    }
+
+    #[test]
+    fn editor_types_cells_and_resizes() {
+        let mut editor = MatrixEditor::default();
+        for c in "1.5".chars() {
+            editor.push(c);
+        }
+        editor.next_cell();
+        editor.push('-');
+        editor.push('2');
+        editor.move_by(1, -5);
+        assert_eq!(editor.matrix, matrix(&[&[1.5, -2.0], &[0.0, 0.0]]));
+        assert_eq!((editor.row, editor.col), (1, 0));
+
+        // Growing keeps the values, shrinking drops the cut-off ones
+        editor.resize(1, 1);
+        editor.move_by(5, 5);
+        assert_eq!((editor.row, editor.col), (2, 2));
+        editor.push('9');
+        editor.resize(0, -1);
+        assert_eq!(editor.matrix.size(), (3, 2));
+        assert_eq!(editor.matrix.get(2, 1), 0.0);
+        assert_eq!(editor.col, 1);
+        assert_eq!(editor.expression(), "[[1.5, -2], [0, 0], [0, 0]]");
+
+        // Tab runs along each row, then back to the start
+        editor.row = 2;
+        editor.next_cell();
+        assert_eq!((editor.row, editor.col), (0, 0));
+        editor.backspace();
+        assert_eq!(editor.matrix.get(0, 0), 0.0);
+        editor.resize(-5, -5);
+        assert_eq!(editor.expression(), "[[0]]");
+    }
+}
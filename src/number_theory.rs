@@ -0,0 +1,232 @@
+//! Whole-number functions: greatest common divisors, least common multiples, primality
+//! and prime factorizations. Values are f64 underneath, so these work on the whole
+//! numbers up to 2^53, which an f64 holds exactly, and refuse anything else: past it,
+//! `2^61 - 1` has already rounded to `2^61`.
+
+use crate::calculator::{EvalError, EvalResult};
+
+/// Functions of whole numbers that give a number; `factor` gives text instead.
+pub const NUMBER_THEORY_FUNCTIONS: &[&str] = &["gcd", "lcm", "isprime"];
+
+/// Miller-Rabin witnesses that together settle every number below 2^64.
+const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// The size of `value` as a whole number, or a domain error for `func`.
+fn magnitude(func: &'static str, value: f64) -> EvalResult<u64> {
+    if value.fract() == 0.0 && value.abs() <= 2f64.powi(53) {
+        Ok(value.abs() as u64)
+    } else {
+        Err(EvalError::Domain {
+            func,
+            arg: value,
+            reason: "{func}() needs whole numbers up to 2^53, not {arg}",
+        })
+    }
+}
+
+/// Call `name`, one of [`NUMBER_THEORY_FUNCTIONS`], on `args`, whose count the caller
+/// has checked.
+pub fn call_number_theory(name: &'static str, args: &[f64]) -> EvalResult<f64> {
+    match *args {
+        [a, b] => {
+            let (a, b) = (magnitude(name, a)?, magnitude(name, b)?);
+            let divisor = gcd(a, b);
+            Ok(match name {
+                "gcd" => divisor as f64,
+                _ if divisor == 0 => 0.0,
+                // Divided first, so only a result past 2^53 rounds
+                _ => (a / divisor) as f64 * b as f64,
+            })
+        }
+        [n] => {
+            let whole = magnitude(name, n)?;
+            Ok(if n > 0.0 && is_prime(whole) { 1.0 } else { 0.0 })
+        }
+        _ => unreachable!("{} takes one or two arguments", name),
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Whether `n` is prime, by Miller-Rabin with witnesses that leave no doubt below 2^64.
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for &p in &WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let (mut odd, mut twos) = (n - 1, 0);
+    while odd.is_multiple_of(2) {
+        odd /= 2;
+        twos += 1;
+    }
+    WITNESSES.iter().all(|&witness| {
+        let mut x = pow_mod(witness, odd, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..twos).any(|_| {
+            x = mul_mod(x, x, n);
+            x == n - 1
+        })
+    })
+}
+
+/// A nontrivial factor of `n`, which is odd, composite and has no small factors, by
+/// Pollard's rho method.
+fn rho_factor(n: u64) -> u64 {
+    for c in 1.. {
+        let step = |x: u64| ((mul_mod(x, x, n) as u128 + c) % n as u128) as u64;
+        let (mut slow, mut fast, mut divisor) = (2, 2, 1);
+        while divisor == 1 {
+            slow = step(slow);
+            fast = step(step(fast));
+            divisor = gcd(slow.abs_diff(fast), n);
+        }
+        // A cycle that closed without finding a factor; try another polynomial
+        if divisor != n {
+            return divisor;
+        }
+    }
+    unreachable!("some polynomial splits every composite")
+}
+
+/// The prime factors of `n`, with repeats, in no particular order.
+fn prime_factors(mut n: u64, factors: &mut Vec<u64>) {
+    for &p in &WITNESSES {
+        while n.is_multiple_of(p) {
+            factors.push(p);
+            n /= p;
+        }
+    }
+    let mut pending = vec![n];
+    while let Some(n) = pending.pop() {
+        if n == 1 {
+            continue;
+        }
+        if is_prime(n) {
+            factors.push(n);
+        } else {
+            let divisor = rho_factor(n);
+            pending.extend([divisor, n / divisor]);
+        }
+    }
+}
+
+/// `n` as a product of prime powers, smallest first, such as `2^3 * 5`. The text reads
+/// back as `n`: a negative number starts with a minus, and 0 and 1 are themselves.
+pub fn factorize(n: f64) -> EvalResult<String> {
+    let whole = magnitude("factor", n)?;
+    let sign = if n < 0.0 { "-" } else { "" };
+    if whole < 2 {
+        return Ok(format!("{}{}", sign, whole));
+    }
+    let mut factors = Vec::new();
+    prime_factors(whole, &mut factors);
+    factors.sort_unstable();
+    let mut powers: Vec<(u64, u32)> = Vec::new();
+    for p in factors {
+        match powers.last_mut() {
+            Some((last, count)) if *last == p => *count += 1,
+            _ => powers.push((p, 1)),
+        }
+    }
+    let terms: Vec<String> = powers
+        .iter()
+        .map(|&(p, count)| match count {
+            1 => p.to_string(),
+            _ => format!("{}^{}", p, count),
+        })
+        .collect();
+    Ok(format!("{}{}", sign, terms.join(" * ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisors_and_multiples_of_whole_numbers() {
+        assert_eq!(call_number_theory("gcd", &[12.0, 18.0]), Ok(6.0));
+        assert_eq!(call_number_theory("gcd", &[-12.0, 18.0]), Ok(6.0));
+        assert_eq!(call_number_theory("gcd", &[0.0, 5.0]), Ok(5.0));
+        assert_eq!(call_number_theory("lcm", &[4.0, 6.0]), Ok(12.0));
+        assert_eq!(call_number_theory("lcm", &[0.0, 6.0]), Ok(0.0));
+        assert_eq!(
+            call_number_theory("lcm", &[2f64.powi(40), 3f64.powi(25)]),
+            Ok(2f64.powi(40) * 3f64.powi(25))
+        );
+        assert_eq!(
+            call_number_theory("gcd", &[1.5, 3.0])
+                .unwrap_err()
+                .to_string(),
+            "gcd() needs whole numbers up to 2^53, not 1.5"
+        );
+        assert!(call_number_theory("lcm", &[2f64.powi(64), 1.0]).is_err());
+        assert_eq!(call_number_theory("gcd", &[2f64.powi(53), 6.0]), Ok(2.0));
+        assert!(call_number_theory("gcd", &[f64::NAN, 1.0]).is_err());
+    }
+
+    #[test]
+    fn primes_and_factorizations() {
+        let primes: Vec<u64> = (0..30).filter(|&n| is_prime(n)).collect();
+        assert_eq!(primes, [2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert!(is_prime(18_446_744_073_709_551_557));
+        // Strong pseudoprimes to the first few bases
+        assert!(!is_prime(3_215_031_751));
+        assert!(!is_prime(3_825_123_056_546_413_051));
+        assert_eq!(call_number_theory("isprime", &[97.0]), Ok(1.0));
+        assert_eq!(call_number_theory("isprime", &[-7.0]), Ok(0.0));
+        assert!(call_number_theory("isprime", &[7.5]).is_err());
+        // 2^61 - 1 is prime, but as an f64 it's already 2^61, so it's refused
+        assert_eq!(
+            call_number_theory("isprime", &[2f64.powi(61) - 1.0])
+                .unwrap_err()
+                .to_string(),
+            "isprime() needs whole numbers up to 2^53, not 2.3058430092e18"
+        );
+        assert!(is_prime((1 << 61) - 1));
+
+        assert_eq!(factorize(40.0).unwrap(), "2^3 * 5");
+        assert_eq!(factorize(-12.0).unwrap(), "-2^2 * 3");
+        assert_eq!(factorize(97.0).unwrap(), "97");
+        assert_eq!(factorize(1.0).unwrap(), "1");
+        assert_eq!(factorize(0.0).unwrap(), "0");
+        // Two large primes, and a repeated one
+        assert_eq!(
+            factorize(9_007_192_808_095_747.0).unwrap(),
+            "4194301 * 2147483647"
+        );
+        assert_eq!(factorize(4_194_301f64.powi(2)).unwrap(), "4194301^2");
+        assert_eq!(
+            factorize(600_851_475_143.0).unwrap(),
+            "71 * 839 * 1471 * 6857"
+        );
+        assert!(factorize(0.5).is_err());
+    }
+}
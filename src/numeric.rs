@@ -0,0 +1,296 @@
+//! Searches over a function of one variable on an interval: its smallest and largest
+//! values, its average and its roots. The function may fail, and the first failure ends
+//! the search.
+
+/// Evenly spaced samples across the interval; even, as Simpson's rule needs.
+const SAMPLES: usize = 256;
+/// How many of the lowest sampled dips are refined by golden-section search.
+const REFINED: usize = 4;
+/// Golden-section steps per dip, each shrinking the bracket to 0.618 of its width.
+const REFINE_STEPS: usize = 50;
+const INV_PHI: f64 = 0.618_033_988_749_895;
+/// Bisection steps, each halving the bracket; enough to reach neighbouring floats.
+const BISECT_STEPS: usize = 1100;
+/// Newton steps before giving up on settling.
+const NEWTON_STEPS: usize = 60;
+/// Times the search around a guess doubles its reach before trying Newton's method.
+const EXPANSIONS: usize = 64;
+
+/// Where on `[a, b]` `f` is smallest, and its value there. The interval is sampled
+/// evenly, then each of the lowest dips between samples is narrowed down. NaN values
+/// are passed over; when every value is NaN the result is `(a, NaN)`.
+pub fn minimum<E>(
+    mut f: impl FnMut(f64) -> Result<f64, E>,
+    a: f64,
+    b: f64,
+) -> Result<(f64, f64), E> {
+    if a == b {
+        return Ok((a, f(a)?));
+    }
+    let step = (b - a) / SAMPLES as f64;
+    let mut samples = Vec::with_capacity(SAMPLES + 1);
+    for i in 0..=SAMPLES {
+        // The last sample is `b` itself, not `b` give or take a rounding error
+        let x = if i == SAMPLES { b } else { a + step * i as f64 };
+        samples.push((x, f(x)?));
+    }
+
+    let mut best = samples
+        .iter()
+        .copied()
+        .filter(|(_, y)| !y.is_nan())
+        .min_by(|p, q| p.1.total_cmp(&q.1))
+        .unwrap_or((a, f64::NAN));
+    let mut dips: Vec<usize> = (1..SAMPLES)
+        .filter(|&i| samples[i].1 <= samples[i - 1].1 && samples[i].1 <= samples[i + 1].1)
+        .collect();
+    dips.sort_by(|&i, &j| samples[i].1.total_cmp(&samples[j].1));
+    for &i in dips.iter().take(REFINED) {
+        let (x, y) = golden_section(&mut f, samples[i - 1].0, samples[i + 1].0)?;
+        if y < best.1 {
+            best = (x, y);
+        }
+    }
+    Ok(best)
+}
+
+/// Where on `[a, b]` `f` is largest, and its value there; see [`minimum`].
+pub fn maximum<E>(
+    mut f: impl FnMut(f64) -> Result<f64, E>,
+    a: f64,
+    b: f64,
+) -> Result<(f64, f64), E> {
+    let (x, y) = minimum(|x| f(x).map(|y| -y), a, b)?;
+    Ok((x, -y))
+}
+
+/// The average of `f` over `[a, b]`: its integral by Simpson's rule, divided by the
+/// width. Exact for polynomials up to cubics.
+pub fn mean<E>(mut f: impl FnMut(f64) -> Result<f64, E>, a: f64, b: f64) -> Result<f64, E> {
+    if a == b {
+        return f(a);
+    }
+    let step = (b - a) / SAMPLES as f64;
+    let mut sum = f(a)? + f(b)?;
+    for i in 1..SAMPLES {
+        let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+        sum += weight * f(a + step * i as f64)?;
+    }
+    Ok(sum * step / 3.0 / (b - a))
+}
+
+/// A root of `f` between `lo` and `hi` by bisection, when `f` has opposite signs at the
+/// two. `None` when it doesn't, when `f` is undefined (NaN) on the way, or when the sign
+/// change turns out to be a pole, like `1/x`'s at 0.
+pub fn bisect<E>(
+    mut f: impl FnMut(f64) -> Result<f64, E>,
+    mut lo: f64,
+    mut hi: f64,
+) -> Result<Option<f64>, E> {
+    let (mut f_lo, mut f_hi) = (f(lo)?, f(hi)?);
+    if f_lo == 0.0 || f_hi == 0.0 {
+        return Ok(Some(if f_lo == 0.0 { lo } else { hi }));
+    }
+    if f_lo.is_nan() || f_hi.is_nan() || f_lo.signum() == f_hi.signum() {
+        return Ok(None);
+    }
+    let limit = f_lo.abs().max(f_hi.abs());
+    for _ in 0..BISECT_STEPS {
+        let mid = lo + (hi - lo) / 2.0;
+        if mid == lo || mid == hi {
+            break;
+        }
+        let f_mid = f(mid)?;
+        if f_mid == 0.0 {
+            return Ok(Some(mid));
+        }
+        if f_mid.is_nan() {
+            return Ok(None);
+        }
+        if f_mid.signum() == f_lo.signum() {
+            (lo, f_lo) = (mid, f_mid);
+        } else {
+            (hi, f_hi) = (mid, f_mid);
+        }
+    }
+    let (root, f_root) = if f_lo.abs() <= f_hi.abs() {
+        (lo, f_lo)
+    } else {
+        (hi, f_hi)
+    };
+    Ok((f_root.abs() <= limit).then_some(root))
+}
+
+/// A root of `f` by Newton's method from `x`, with the slope estimated from nearby
+/// values. `None` when it doesn't settle.
+pub fn newton<E>(mut f: impl FnMut(f64) -> Result<f64, E>, mut x: f64) -> Result<Option<f64>, E> {
+    for _ in 0..NEWTON_STEPS {
+        let y = f(x)?;
+        if y == 0.0 {
+            return Ok(Some(x));
+        }
+        let h = 1e-7 * x.abs().max(1.0);
+        let slope = (f(x + h)? - f(x - h)?) / (2.0 * h);
+        let step = y / slope;
+        if !step.is_finite() {
+            return Ok(None);
+        }
+        x -= step;
+        if step.abs() <= 1e-15 * x.abs().max(1.0) {
+            return Ok(Some(x));
+        }
+    }
+    Ok(None)
+}
+
+/// A root of `f` close to `guess`. Rings of doubling width around the guess are tried
+/// for a change of sign, the nearer first, and bisected; a root that only touches zero,
+/// like `x^2`'s, is left to Newton's method from the guess.
+pub fn root_near<E>(
+    mut f: impl FnMut(f64) -> Result<f64, E>,
+    guess: f64,
+) -> Result<Option<f64>, E> {
+    if f(guess)? == 0.0 {
+        return Ok(Some(guess));
+    }
+    let (mut inner, mut reach) = (0.0, 1e-3 * guess.abs().max(1.0));
+    for _ in 0..EXPANSIONS {
+        for (lo, hi) in [
+            (guess + inner, guess + reach),
+            (guess - reach, guess - inner),
+        ] {
+            if let Some(root) = bisect(&mut f, lo, hi)? {
+                return Ok(Some(root));
+            }
+        }
+        (inner, reach) = (reach, reach * 2.0);
+    }
+    newton(f, guess)
+}
+
+/// The roots of `f` on `[a, b]`, in order: wherever evenly spaced samples change sign,
+/// narrowed down by bisection, and any sample that is exactly zero.
+pub fn roots<E>(mut f: impl FnMut(f64) -> Result<f64, E>, a: f64, b: f64) -> Result<Vec<f64>, E> {
+    let step = (b - a) / SAMPLES as f64;
+    let mut found = Vec::new();
+    let mut last = (a, f(a)?);
+    if last.1 == 0.0 {
+        found.push(a);
+    }
+    for i in 1..=SAMPLES {
+        let x = if i == SAMPLES { b } else { a + step * i as f64 };
+        let y = f(x)?;
+        if y == 0.0 {
+            found.push(x);
+        } else if last.1 != 0.0
+            && last.1.signum() != y.signum()
+            && let Some(root) = bisect(&mut f, last.0, x)?
+        {
+            found.push(root);
+        }
+        last = (x, y);
+    }
+    Ok(found)
+}
+
+/// The lowest point of `f` between `lo` and `hi`, assuming it has a single dip there.
+fn golden_section<E>(
+    f: &mut impl FnMut(f64) -> Result<f64, E>,
+    mut lo: f64,
+    mut hi: f64,
+) -> Result<(f64, f64), E> {
+    let mut c = hi - INV_PHI * (hi - lo);
+    let mut d = lo + INV_PHI * (hi - lo);
+    let (mut fc, mut fd) = (f(c)?, f(d)?);
+    for _ in 0..REFINE_STEPS {
+        if fc < fd {
+            hi = d;
+            (d, fd) = (c, fc);
+            c = hi - INV_PHI * (hi - lo);
+            fc = f(c)?;
+        } else {
+            lo = c;
+            (c, fc) = (d, fd);
+            d = lo + INV_PHI * (hi - lo);
+            fd = f(d)?;
+        }
+    }
+    Ok(if fc < fd { (c, fc) } else { (d, fd) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::f64::consts::{PI, TAU};
+
+    fn ok(f: fn(f64) -> f64) -> impl FnMut(f64) -> Result<f64, Infallible> {
+        move |x| Ok(f(x))
+    }
+
+    #[test]
+    fn sine_over_a_full_turn() {
+        let (x, low) = minimum(ok(f64::sin), 0.0, TAU).unwrap();
+        assert!((low + 1.0).abs() < 1e-12 && (x - 1.5 * PI).abs() < 1e-6);
+        let (x, high) = maximum(ok(f64::sin), 0.0, TAU).unwrap();
+        assert!((high - 1.0).abs() < 1e-12 && (x - 0.5 * PI).abs() < 1e-6);
+        assert!(mean(ok(f64::sin), 0.0, TAU).unwrap().abs() < 1e-12);
+    }
+
+    #[test]
+    fn dips_between_samples_are_found() {
+        // The bottom at 1/3 falls between samples, and no sample is within 1e-6 of it
+        let (x, low) = minimum(ok(|x| (x - 1.0 / 3.0).powi(2)), 0.0, 1.0).unwrap();
+        assert!((x - 1.0 / 3.0).abs() < 1e-7, "{}", x);
+        assert!(low < 1e-14);
+    }
+
+    #[test]
+    fn monotone_functions_peak_at_the_ends() {
+        let cube = |x: f64| x.powi(3);
+        assert_eq!(minimum(ok(cube), -1.0, 2.0).unwrap(), (-1.0, -1.0));
+        assert_eq!(maximum(ok(cube), -1.0, 2.0).unwrap(), (2.0, 8.0));
+        assert!((mean(ok(cube), -1.0, 2.0).unwrap() - 1.25).abs() < 1e-12);
+        assert_eq!(minimum(ok(cube), 3.0, 3.0).unwrap(), (3.0, 27.0));
+    }
+
+    #[test]
+    fn roots_by_bisection_with_newton_to_fall_back_on() {
+        let close = |root: Option<f64>, expected: f64| {
+            root.is_some_and(|root| (root - expected).abs() < 1e-12)
+        };
+        let two = |x: f64| x * x - 2.0;
+        assert!(close(bisect(ok(two), 0.0, 2.0).unwrap(), 2f64.sqrt()));
+        assert_eq!(bisect(ok(two), 2.0, 3.0).unwrap(), None);
+        // Poles change sign too, but aren't roots
+        assert_eq!(bisect(ok(|x| 1.0 / x), -1.0, 2.0).unwrap(), None);
+
+        // The nearer root to the guess, on either side
+        assert!(close(root_near(ok(two), 1.0).unwrap(), 2f64.sqrt()));
+        assert!(close(root_near(ok(two), -5.0).unwrap(), -(2f64.sqrt())));
+        assert!(close(root_near(ok(f64::cos), 3.0).unwrap(), PI / 2.0));
+        assert!(close(root_near(ok(|x| x - 1e6), 0.0).unwrap(), 1e6));
+        // A root that touches zero without crossing is left to Newton's method
+        let touch = root_near(ok(|x| (x - 1.0).powi(2)), 3.0).unwrap().unwrap();
+        assert!((touch - 1.0).abs() < 1e-6, "{}", touch);
+        assert_eq!(root_near(ok(|x| x * x + 1.0), 0.0).unwrap(), None);
+
+        let all = roots(ok(f64::sin), -4.0, 7.0).unwrap();
+        assert_eq!(all.len(), 4, "{:?}", all);
+        for (root, expected) in all.into_iter().zip([-PI, 0.0, PI, TAU]) {
+            assert!(close(Some(root), expected), "{}", root);
+        }
+        assert!(roots(ok(f64::tan), 1.0, 2.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn failures_and_nan_samples() {
+        let failing = |x: f64| if x > 0.5 { Err("too far") } else { Ok(x) };
+        assert_eq!(minimum(failing, 0.0, 1.0), Err("too far"));
+        assert_eq!(mean(failing, 0.0, 1.0), Err("too far"));
+
+        let (_, low) = minimum(ok(f64::sqrt), -1.0, 3.0).unwrap();
+        assert_eq!(low, 0.0);
+        assert!(minimum(ok(|_| f64::NAN), 0.0, 1.0).unwrap().1.is_nan());
+    }
+}
@@ -0,0 +1,195 @@
+/// Actions reachable from the command palette (Ctrl+P).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Share,
+    ImportShare,
+    ToggleAngleMode,
+    ConvertResult,
+    UnitConverter,
+    Statistics,
+    MatrixEditor,
+    DateCalculator,
+    InsertConstant,
+    PrecisionCheck,
+    ShowKeyRepeat,
+    ToggleBigIntegers,
+    ToggleFractions,
+    ToggleCancellationCheck,
+    ToggleModulo,
+    CycleSigFigs,
+    CycleResultFormat,
+    CycleDigitGrouping,
+    CycleWordSize,
+    ClearExpression,
+    ClearAll,
+    ResetSettings,
+    Graph,
+    ExportGraph,
+}
+
+impl Command {
+    pub const ALL: &'static [Command] = &[
+        Command::Share,
+        Command::ImportShare,
+        Command::ToggleAngleMode,
+        Command::ConvertResult,
+        Command::UnitConverter,
+        Command::Statistics,
+        Command::MatrixEditor,
+        Command::DateCalculator,
+        Command::InsertConstant,
+        Command::PrecisionCheck,
+        Command::ShowKeyRepeat,
+        Command::ToggleBigIntegers,
+        Command::ToggleFractions,
+        Command::ToggleCancellationCheck,
+        Command::ToggleModulo,
+        Command::CycleSigFigs,
+        Command::CycleResultFormat,
+        Command::CycleDigitGrouping,
+        Command::CycleWordSize,
+        Command::ClearExpression,
+        Command::ClearAll,
+        Command::ResetSettings,
+        Command::Graph,
+        Command::ExportGraph,
+    ];
+
+    /// What the config calls the command when binding keys to it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Share => "share",
+            Command::ImportShare => "import_share",
+            Command::ToggleAngleMode => "toggle_angle_mode",
+            Command::ConvertResult => "convert_result",
+            Command::UnitConverter => "unit_converter",
+            Command::Statistics => "statistics",
+            Command::MatrixEditor => "matrix_editor",
+            Command::DateCalculator => "date_calculator",
+            Command::InsertConstant => "insert_constant",
+            Command::PrecisionCheck => "precision_check",
+            Command::ShowKeyRepeat => "show_key_repeat",
+            Command::ToggleBigIntegers => "toggle_big_integers",
+            Command::ToggleFractions => "toggle_fractions",
+            Command::ToggleCancellationCheck => "toggle_cancellation_check",
+            Command::ToggleModulo => "toggle_modulo",
+            Command::CycleSigFigs => "cycle_sig_figs",
+            Command::CycleResultFormat => "cycle_result_format",
+            Command::CycleDigitGrouping => "cycle_digit_grouping",
+            Command::CycleWordSize => "cycle_word_size",
+            Command::ClearExpression => "clear_expression",
+            Command::ClearAll => "clear_all",
+            Command::ResetSettings => "reset_settings",
+            Command::Graph => "graph",
+            Command::ExportGraph => "export_graph",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Command::ALL
+            .iter()
+            .copied()
+            .find(|command| command.name() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Command::Share => "Share: copy expression as share string",
+            Command::ImportShare => "Import share string",
+            Command::ToggleAngleMode => "Toggle angle mode (DEG/RAD)",
+            Command::ConvertResult => "Convert result (degrees, radians, π, %, dB)",
+            Command::UnitConverter => "Unit converter (length, mass, temperature, data)",
+            Command::Statistics => "Statistics (data table, one- and two-variable stats)",
+            Command::MatrixEditor => "Matrix editor (det, inv, transpose)",
+            Command::DateCalculator => "Date calculator (days between, date plus days)",
+            Command::InsertConstant => "Insert a constant (c, g, h, k_b, avogadro, phi, tau)",
+            Command::PrecisionCheck => "Precision check: compare f64 with exact methods",
+            Command::ShowKeyRepeat => "Show key repeat profile",
+            Command::ToggleBigIntegers => "Toggle big-integer mode (exact + - * ^ % !)",
+            Command::ToggleFractions => "Toggle fraction mode (1/3 + 1/6 gives 1/2)",
+            Command::ToggleCancellationCheck => {
+                "Toggle cancellation check (flag digits lost to subtraction)"
+            }
+            Command::ToggleModulo => "Toggle % sign (remainder like C, or modulo like Python)",
+            Command::CycleSigFigs => "Cycle significant figures (off, 2, 3, 4, 6, 8)",
+            Command::CycleResultFormat => {
+                "Cycle result format (decimal, scientific, engineering, fraction)"
+            }
+            Command::CycleDigitGrouping => {
+                "Cycle digit grouping of results (off, 1,234.5, 1.234,5)"
+            }
+            Command::CycleWordSize => "Cycle programmer word size (8, 16, 32, 64 bits)",
+            Command::ClearExpression => "Clear expression",
+            Command::ClearAll => "Clear all (expression and history)",
+            Command::ResetSettings => "Reset settings to defaults (keeps history)",
+            Command::Graph => "Graph current expression",
+            Command::ExportGraph => "Export graph to plot.csv (x and y columns)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandPalette {
+    pub filter: String,
+    pub selected: usize,
+}
+
+impl CommandPalette {
+    /// Commands whose label contains the filter text, case-insensitively.
+    pub fn matches(&self) -> Vec<Command> {
+        let filter = self.filter.to_lowercase();
+        Command::ALL
+            .iter()
+            .copied()
+            .filter(|cmd| cmd.label().to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    pub fn selected_command(&self) -> Option<Command> {
+        self.matches().get(self.selected).copied()
+    }
+
+    pub fn select_next(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.filter.push(ch);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.filter.pop();
+        self.selected = 0;
+    }
+}
+
+/// Selection state for a popup that offers a fixed list of choices.
+#[derive(Debug, Clone, Default)]
+pub struct Chooser {
+    pub selected: usize,
+}
+
+impl Chooser {
+    pub fn select_next(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + 1) % len;
+        }
+    }
+
+    pub fn select_prev(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + len - 1) % len;
+        }
+    }
+}
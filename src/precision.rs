@@ -0,0 +1,377 @@
+//! Evaluating an expression more than one way, to tell a float artifact from a real
+//! result: plain f64, exact integers when they apply, and double-double arithmetic,
+//! which carries about 32 significant digits.
+
+use std::collections::BTreeMap;
+
+use crate::bigint;
+use crate::calculator::CalculatorModule;
+
+/// One way of evaluating the expression and what it gave.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub method: &'static str,
+    pub value: String,
+}
+
+/// The expression evaluated in f64, as exact integers when only integers are involved,
+/// and in double-double when it only uses arithmetic; each result is shown with every
+/// digit its method can tell apart.
+pub fn compare(calc: &CalculatorModule, expr: &str) -> Vec<Reading> {
+    let mut plain = CalculatorModule::new();
+    plain.variables = calc.variables.clone();
+    plain.angle_mode = calc.angle_mode;
+    plain.percent_behavior = calc.percent_behavior;
+    plain.modulo_behavior = calc.modulo_behavior;
+    let f64_value = match plain.evaluate_str(expr) {
+        Ok(outcome) => match outcome.as_f64() {
+            Some(value) => value.to_string(),
+            None => outcome.to_string(),
+        },
+        Err(error) => format!("Error: {}", error),
+    };
+
+    let mut readings = vec![Reading {
+        method: "f64",
+        value: f64_value,
+    }];
+    let evaluator = plain.evaluator();
+    if let Ok(tree) = evaluator.parse(expr)
+        && let Some(exact) = bigint::evaluate(&tree, &evaluator, calc.max_digits)
+    {
+        readings.push(Reading {
+            method: "exact integer",
+            value: exact.to_string(),
+        });
+    }
+    let double = match evaluate(expr, &calc.variables) {
+        Some(value) => value.to_f64().to_string(),
+        None => "n/a (arithmetic and sqrt only)".to_string(),
+    };
+    readings.push(Reading {
+        method: "double-double",
+        value: double,
+    });
+    readings
+}
+
+/// For each char of `value`, whether it differs from the char at the same place in
+/// `reference`; chars past the end of `reference` differ.
+pub fn differing(reference: &str, value: &str) -> Vec<bool> {
+    let mut reference = reference.chars();
+    value.chars().map(|c| reference.next() != Some(c)).collect()
+}
+
+/// An unevaluated sum `hi + lo` with `|lo|` at most half an ulp of `hi`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Double {
+    hi: f64,
+    lo: f64,
+}
+
+/// `a + b` exactly, as the rounded sum and its error.
+pub(crate) fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_part = sum - a;
+    (sum, (a - (sum - b_part)) + (b - b_part))
+}
+
+/// Like `two_sum` when `|a| >= |b|`.
+fn quick_two_sum(a: f64, b: f64) -> Double {
+    let sum = a + b;
+    Double {
+        hi: sum,
+        lo: b - (sum - a),
+    }
+}
+
+impl Double {
+    const PI: Double = Double {
+        hi: std::f64::consts::PI,
+        lo: 1.224_646_799_147_353_2e-16,
+    };
+    const E: Double = Double {
+        hi: std::f64::consts::E,
+        lo: 1.445_646_891_729_250_2e-16,
+    };
+
+    fn new(value: f64) -> Self {
+        Double { hi: value, lo: 0.0 }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    fn neg(self) -> Self {
+        Double {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+
+    fn add(self, other: Double) -> Self {
+        let (sum, error) = two_sum(self.hi, other.hi);
+        quick_two_sum(sum, error + self.lo + other.lo)
+    }
+
+    fn mul(self, other: Double) -> Self {
+        let product = self.hi * other.hi;
+        let error = self.hi.mul_add(other.hi, -product);
+        quick_two_sum(product, error + self.hi * other.lo + self.lo * other.hi)
+    }
+
+    fn div(self, other: Double) -> Option<Self> {
+        if other.hi == 0.0 {
+            return None;
+        }
+        // Long division, one f64 digit at a time
+        let first = self.hi / other.hi;
+        let rest = self.add(other.mul(Double::new(first)).neg());
+        let second = rest.hi / other.hi;
+        Some(quick_two_sum(first, second))
+    }
+
+    /// Integer powers by squaring; anything else goes through f64.
+    fn pow(self, exponent: Double) -> Option<Self> {
+        let n = exponent.to_f64();
+        if n.fract() != 0.0 || n.abs() > 2f64.powi(53) {
+            return Some(Double::new(self.to_f64().powf(n)));
+        }
+        let (mut base, mut n, mut result) = (self, n.abs() as u64, Double::new(1.0));
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(base);
+            }
+            base = base.mul(base);
+            n >>= 1;
+        }
+        if exponent.hi < 0.0 {
+            Double::new(1.0).div(result)
+        } else {
+            Some(result)
+        }
+    }
+
+    /// One Newton step from the f64 root.
+    fn sqrt(self) -> Option<Self> {
+        if self.hi < 0.0 {
+            return None;
+        }
+        if self.hi == 0.0 {
+            return Some(self);
+        }
+        let root = Double::new(self.hi.sqrt());
+        let correction = self.add(root.mul(root).neg()).hi / (2.0 * root.hi);
+        Some(root.add(Double::new(correction)))
+    }
+
+    /// A decimal literal, possibly with an exponent, scaled exactly rather than parsed
+    /// straight to f64.
+    fn parse(text: &str) -> Option<Self> {
+        let (number, exponent) = text.split_once(['e', 'E']).unwrap_or((text, "0"));
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let digits = format!("{}{}", whole, fraction);
+        let exponent: i32 = exponent.parse().ok()?;
+        if digits.len() > 30 || exponent.abs() > 300 {
+            return text.parse().ok().map(Double::new);
+        }
+        let mantissa: u128 = digits.parse().ok()?;
+        let hi = mantissa as f64;
+        let lo = (mantissa as i128 - hi as i128) as f64;
+        let value = quick_two_sum(hi, lo);
+        let power = exponent - fraction.len() as i32;
+        let scale = Double::new(10.0).pow(Double::new(power.abs() as f64))?;
+        if power < 0 {
+            value.div(scale)
+        } else {
+            Some(value.mul(scale))
+        }
+    }
+}
+
+/// Evaluate `expr` in double-double. `None` when it uses anything besides numbers,
+/// variables, `pi`, `e`, `+ - * / ^`, parens and `sqrt`, or divides by zero.
+fn evaluate(expr: &str, variables: &BTreeMap<String, f64>) -> Option<Double> {
+    let chars: Vec<char> = expr
+        .replace("**", "^")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let mut parser = Parser {
+        chars: &chars,
+        pos: 0,
+        variables,
+    };
+    let value = parser.expression()?;
+    (parser.pos == chars.len()).then_some(value)
+}
+
+/// Same precedence as the calculator: a right-associative `^` binds tightest, then
+/// unary minus, then `* /`, then `+ -`.
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+    variables: &'a BTreeMap<String, f64>,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.chars.get(self.pos) == Some(&c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expression(&mut self) -> Option<Double> {
+        let mut left = self.term()?;
+        loop {
+            if self.eat('+') {
+                left = left.add(self.term()?);
+            } else if self.eat('-') {
+                left = left.add(self.term()?.neg());
+            } else {
+                return Some(left);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Option<Double> {
+        let mut left = self.unary()?;
+        loop {
+            if self.eat('*') {
+                left = left.mul(self.unary()?);
+            } else if self.eat('/') {
+                left = left.div(self.unary()?)?;
+            } else {
+                return Some(left);
+            }
+        }
+    }
+
+    /// A power after any number of signs, which bind more loosely than `^`.
+    fn unary(&mut self) -> Option<Double> {
+        if self.eat('-') {
+            return Some(self.unary()?.neg());
+        }
+        self.eat('+');
+        self.factor()
+    }
+
+    fn factor(&mut self) -> Option<Double> {
+        let base = self.primary()?;
+        if !self.eat('^') {
+            return Some(base);
+        }
+        // `^` groups to the right, and its exponent may be negated
+        base.pow(self.unary()?)
+    }
+
+    fn primary(&mut self) -> Option<Double> {
+        if self.eat('(') {
+            let value = self.expression()?;
+            return self.eat(')').then_some(value);
+        }
+        let start = self.pos;
+        let first = *self.chars.get(start)?;
+        if first.is_ascii_digit() || first == '.' {
+            while self
+                .chars
+                .get(self.pos)
+                .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+            {
+                self.pos += 1;
+            }
+            // An exponent, as in `2.5e-4`
+            let exponent = match self.chars.get(self.pos + 1..) {
+                Some(['+' | '-', digit, ..] | [digit, ..]) if digit.is_ascii_digit() => {
+                    matches!(self.chars[self.pos], 'e' | 'E')
+                }
+                _ => false,
+            };
+            if exponent {
+                self.pos += 2;
+                while self.chars.get(self.pos).is_some_and(char::is_ascii_digit) {
+                    self.pos += 1;
+                }
+            }
+            let text: String = self.chars[start..self.pos].iter().collect();
+            return Double::parse(&text);
+        }
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.to_lowercase().as_str() {
+            "" => None,
+            "pi" | "π" => Some(Double::PI),
+            "e" => Some(Double::E),
+            "sqrt" if self.eat('(') => {
+                let value = self.expression()?;
+                self.eat(')').then_some(())?;
+                value.sqrt()
+            }
+            name => self.variables.get(name).copied().map(Double::new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn double(expr: &str) -> Option<f64> {
+        evaluate(expr, &BTreeMap::new()).map(Double::to_f64)
+    }
+
+    #[test]
+    fn double_double_sees_past_float_artifacts() {
+        assert_eq!(0.1 + 0.2, 0.300_000_000_000_000_04);
+        assert_eq!(double("0.1+0.2"), Some(0.3));
+        assert_eq!(10f64.powi(16) + 1.0 - 10f64.powi(16), 0.0);
+        assert_eq!(double("10^16+1-10^16"), Some(1.0));
+        // f64 rounds 1+10^-15 to 1+1.11e-15, which compounds to about 3.035
+        assert_eq!(
+            double("(1+10^-15)^(10^15)").map(|v| (v * 1e6).round()),
+            Some(2_718_282.0)
+        );
+        assert_eq!(double("sqrt(2)^2"), Some(2.0));
+        assert_eq!(double("1e-1+2e-1"), Some(0.3));
+        assert_eq!(double("2.5E+3"), Some(2500.0));
+        assert_eq!(double("2e"), None);
+        assert_eq!(double("-2^2"), Some(-4.0));
+        assert_eq!(double("2^3^2"), Some(512.0));
+        assert_eq!(double("1/0"), None);
+        assert_eq!(double("sin(1)"), None);
+    }
+
+    #[test]
+    fn readings_mark_where_methods_disagree() {
+        let mut calc = CalculatorModule::new();
+        calc.big_integers = true;
+        let readings = compare(&calc, "0.1+0.2");
+        let values: Vec<&str> = readings.iter().map(|r| r.value.as_str()).collect();
+        assert_eq!(values, ["0.30000000000000004", "0.3"]);
+
+        let methods: Vec<&str> = compare(&calc, "2^60+1").iter().map(|r| r.method).collect();
+        assert_eq!(methods, ["f64", "exact integer", "double-double"]);
+
+        assert_eq!(
+            differing("0.30000000000000004", "0.3"),
+            [false, false, false]
+        );
+        assert_eq!(
+            differing("0.3", "0.30000000000000004")
+                .iter()
+                .filter(|d| **d)
+                .count(),
+            16
+        );
+        assert!(differing("1152921504606846976", "1152921504606846977")[18]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Programmer mode's integer view of numbers: the word size results wrap to, the bases
+//! they are read out in, and the bitwise functions. Values are f64 underneath, so these
+//! work on the integers an f64 holds, and refuse anything else.
+
+use crate::calculator::{EvalError, EvalResult};
+
+/// The bases each result is shown in, with their labels.
+pub const BASES: &[(&str, u32)] = &[("HEX", 16), ("DEC", 10), ("OCT", 8), ("BIN", 2)];
+
+/// Functions that work on the bits of integers.
+pub const BITWISE_FUNCTIONS: &[&str] = &["and", "or", "xor", "not", "shl", "shr"];
+
+/// The width of the signed integers programmer mode works in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WordSize {
+    Bits8,
+    Bits16,
+    Bits32,
+    #[default]
+    Bits64,
+}
+
+impl WordSize {
+    pub fn bits(self) -> u32 {
+        match self {
+            WordSize::Bits8 => 8,
+            WordSize::Bits16 => 16,
+            WordSize::Bits32 => 32,
+            WordSize::Bits64 => 64,
+        }
+    }
+
+    pub fn label(self) -> String {
+        format!("{}-bit", self.bits())
+    }
+
+    /// The next size up, going round from 64 bits to 8.
+    pub fn next(self) -> Self {
+        match self {
+            WordSize::Bits8 => WordSize::Bits16,
+            WordSize::Bits16 => WordSize::Bits32,
+            WordSize::Bits32 => WordSize::Bits64,
+            WordSize::Bits64 => WordSize::Bits8,
+        }
+    }
+
+    /// The whole number `value` wrapped into this word as two's complement, the way an
+    /// overflowing register wraps: 128 is -128 in 8 bits. Infinities and NaN are left
+    /// for the caller to report.
+    pub fn wrap(self, value: f64) -> f64 {
+        if !value.is_finite() {
+            return value;
+        }
+        let modulus = 2f64.powi(self.bits() as i32);
+        let half = modulus / 2.0;
+        // `%` is exact, and so is moving a remainder between half and the modulus in
+        // by one modulus, where `rem_euclid` would round -1 up to 2^64
+        let wrapped = value % modulus;
+        if wrapped >= half {
+            wrapped - modulus
+        } else if wrapped < -half {
+            wrapped + modulus
+        } else {
+            wrapped
+        }
+    }
+}
+
+/// `value` as an integer, when it is a whole number in the range of an `i64`. Past
+/// 2^53 the f64 it came from has already rounded away the lowest bits.
+pub fn to_integer(value: f64) -> Option<i64> {
+    // -2^63 is the one bound that fits
+    let bound = 2f64.powi(63);
+    (value.fract() == 0.0 && (-bound..bound).contains(&value)).then_some(value as i64)
+}
+
+/// `value` written in `radix`, with binary split into groups of four digits. Decimal
+/// keeps its sign; the other bases show a negative value's two's complement in `word`,
+/// as its bits are stored.
+pub fn format_radix(value: i64, radix: u32, word: WordSize) -> String {
+    if radix == 10 {
+        return value.to_string();
+    }
+    let bits = (value as u64) & (u64::MAX >> (64 - word.bits()));
+    match radix {
+        16 => format!("{:X}", bits),
+        8 => format!("{:o}", bits),
+        _ => group(&format!("{:b}", bits), 4),
+    }
+}
+
+/// `value` in each of [`BASES`] as a `word`, or `None` when it isn't an integer.
+pub fn readouts(value: f64, word: WordSize) -> Option<Vec<(&'static str, String)>> {
+    let value = to_integer(word.wrap(value))?;
+    Some(
+        BASES
+            .iter()
+            .map(|&(label, radix)| (label, format_radix(value, radix, word)))
+            .collect(),
+    )
+}
+
+/// Call the bitwise function `name` on `args`, whose count the caller has checked.
+pub fn call_bitwise(name: &'static str, args: &[f64]) -> EvalResult<f64> {
+    let mut integers = [0; 2];
+    for (slot, &arg) in integers.iter_mut().zip(args) {
+        *slot = to_integer(arg).ok_or(EvalError::Domain {
+            func: name,
+            arg,
+            reason: "{func}() needs whole numbers, not {arg}",
+        })?;
+    }
+    let [a, b] = integers;
+    let value = match name {
+        "and" => a & b,
+        "or" => a | b,
+        "xor" => a ^ b,
+        "not" => !a,
+        _ => {
+            let places = u32::try_from(b)
+                .ok()
+                .filter(|&places| places < i64::BITS)
+                .ok_or(EvalError::Domain {
+                    func: name,
+                    arg: args[1],
+                    reason: "{func}() shifts whole numbers by 0 to 63 places",
+                })?;
+            if name == "shl" {
+                a << places
+            } else {
+                a >> places
+            }
+        }
+    };
+    Ok(value as f64)
+}
+
+/// `digits` with a space between each group of `size`, counted from the right.
+fn group(digits: &str, size: usize) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / size);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(size) {
+            out.push(' ');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_are_read_out_in_every_base() {
+        let qword = WordSize::Bits64;
+        assert_eq!(
+            readouts(255.0, qword).unwrap(),
+            [
+                ("HEX", "FF".to_string()),
+                ("DEC", "255".to_string()),
+                ("OCT", "377".to_string()),
+                ("BIN", "1111 1111".to_string()),
+            ]
+        );
+        assert_eq!(format_radix(0b1_0000, 2, qword), "1 0000");
+        assert_eq!(format_radix(0, 16, qword), "0");
+        assert_eq!(readouts(2.5, qword), None);
+        assert_eq!(readouts(f64::INFINITY, qword), None);
+        assert_eq!(readouts(f64::NAN, qword), None);
+        assert_eq!(to_integer(-(2f64.powi(63))), Some(i64::MIN));
+        assert_eq!(to_integer(2f64.powi(63)), None);
+    }
+
+    #[test]
+    fn negative_values_read_out_in_twos_complement() {
+        let byte = WordSize::Bits8;
+        assert_eq!(
+            readouts(-1.0, byte).unwrap(),
+            [
+                ("HEX", "FF".to_string()),
+                ("DEC", "-1".to_string()),
+                ("OCT", "377".to_string()),
+                ("BIN", "1111 1111".to_string()),
+            ]
+        );
+        assert_eq!(format_radix(-10, 2, byte), "1111 0110");
+        assert_eq!(format_radix(-10, 10, byte), "-10");
+        assert_eq!(format_radix(-1, 16, WordSize::Bits16), "FFFF");
+        assert_eq!(
+            format_radix(i64::MIN, 16, WordSize::Bits64),
+            "8000000000000000"
+        );
+        // A value too wide for the word is read out as it wraps
+        assert_eq!(readouts(200.0, byte).unwrap()[1].1, "-56");
+    }
+
+    #[test]
+    fn values_wrap_to_the_word_size() {
+        let byte = WordSize::Bits8;
+        assert_eq!(byte.wrap(127.0), 127.0);
+        assert_eq!(byte.wrap(128.0), -128.0);
+        assert_eq!(byte.wrap(-129.0), 127.0);
+        assert_eq!(byte.wrap(256.0 * 3.0 + 5.0), 5.0);
+        assert_eq!(WordSize::Bits16.wrap(65535.0), -1.0);
+        assert_eq!(WordSize::Bits32.wrap(2f64.powi(31)), -(2f64.powi(31)));
+        assert_eq!(WordSize::Bits64.wrap(2f64.powi(63)), -(2f64.powi(63)));
+        assert_eq!(WordSize::Bits64.wrap(2f64.powi(70)), 0.0);
+        assert_eq!(WordSize::Bits64.wrap(-1.0), -1.0);
+        assert_eq!(WordSize::Bits64.wrap(-(2f64.powi(63))), -(2f64.powi(63)));
+        assert_eq!(
+            WordSize::Bits64.wrap(-(2f64.powi(63)) - 2048.0),
+            2f64.powi(63) - 2048.0
+        );
+        assert!(byte.wrap(f64::NAN).is_nan());
+        assert_eq!(byte.wrap(f64::INFINITY), f64::INFINITY);
+        assert_eq!(WordSize::Bits64.next(), WordSize::Bits8);
+    }
+
+    #[test]
+    fn bitwise_functions_need_integers() {
+        assert_eq!(call_bitwise("and", &[12.0, 10.0]), Ok(8.0));
+        assert_eq!(call_bitwise("or", &[12.0, 10.0]), Ok(14.0));
+        assert_eq!(call_bitwise("xor", &[12.0, 10.0]), Ok(6.0));
+        assert_eq!(call_bitwise("not", &[0.0]), Ok(-1.0));
+        assert_eq!(call_bitwise("shl", &[255.0, 4.0]), Ok(4080.0));
+        assert_eq!(call_bitwise("shr", &[-16.0, 2.0]), Ok(-4.0));
+        assert_eq!(
+            call_bitwise("and", &[1.5, 1.0]).unwrap_err().to_string(),
+            "and() needs whole numbers, not 1.5"
+        );
+        assert_eq!(
+            call_bitwise("shl", &[1.0, 64.0]).unwrap_err().to_string(),
+            "shl() shifts whole numbers by 0 to 63 places"
+        );
+        assert!(call_bitwise("shr", &[1.0, -1.0]).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Random numbers for `rand`, `randint` and `randn`. The generator is small and
+//! seedable, so a calculation started from the same seed gives the same numbers.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::calculator::{EvalError, EvalResult};
+
+/// Functions that draw from the generator.
+pub const RANDOM_FUNCTIONS: &[&str] = &["rand", "randint", "randn"];
+
+/// The widest `randint` range, so every whole number in it is an f64.
+const MAX_SPAN: f64 = 9_007_199_254_740_992.0;
+/// Why `randint` fails on bounds outside those.
+const RANDINT_BOUNDS: &str = "randint() needs finite bounds with a whole number between";
+
+/// A SplitMix64 generator. Drawing only needs a shared reference, so evaluators can
+/// hold one alongside their other borrowed state.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    /// A generator that gives the same numbers every time for the same `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            state: Cell::new(seed),
+        }
+    }
+
+    /// A generator seeded from the clock, different for every one made.
+    pub fn from_clock() -> Self {
+        static MADE: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        // Spread the count across the bits, so two made in the same tick still differ
+        let count = MADE.fetch_add(1, Ordering::Relaxed);
+        Self::seeded(nanos ^ count.wrapping_mul(0xD1B5_4A32_D192_ED03))
+    }
+
+    fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform on `[0, 1)`, from the top 53 bits.
+    pub fn uniform(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A whole number from `lo` to `hi`, each equally likely.
+    fn between(&self, lo: f64, hi: f64) -> f64 {
+        let span = (hi - lo + 1.0) as u64;
+        // Reject the top sliver that would favour the low end
+        let zone = u64::MAX - u64::MAX % span;
+        loop {
+            let draw = self.next_u64();
+            if draw < zone {
+                return lo + (draw % span) as f64;
+            }
+        }
+    }
+
+    /// Standard normal, by the Box-Muller transform.
+    pub fn normal(&self) -> f64 {
+        // 1 - u is in (0, 1], so its log is finite
+        let radius = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+        radius * (std::f64::consts::TAU * self.uniform()).cos()
+    }
+}
+
+/// Call `name`, one of [`RANDOM_FUNCTIONS`], on `args`, whose count the caller has
+/// checked, drawing from `rng`.
+pub fn call_random(name: &'static str, args: &[f64], rng: &Rng) -> EvalResult<f64> {
+    match (name, args) {
+        ("randint", &[a, b]) => {
+            let (lo, hi) = (a.min(b).ceil(), a.max(b).floor());
+            if let Some(&arg) = args.iter().find(|arg| !arg.is_finite()) {
+                return Err(EvalError::Domain {
+                    func: name,
+                    arg,
+                    reason: RANDINT_BOUNDS,
+                });
+            }
+            if lo > hi || hi - lo >= MAX_SPAN {
+                return Err(EvalError::Domain {
+                    func: name,
+                    arg: b,
+                    reason: RANDINT_BOUNDS,
+                });
+            }
+            Ok(rng.between(lo, hi))
+        }
+        ("randn", _) => Ok(rng.normal()),
+        _ => Ok(rng.uniform()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_generators_repeat_themselves() {
+        let draws = |seed| {
+            let rng = Rng::seeded(seed);
+            [rng.uniform(), rng.normal(), rng.between(1.0, 6.0)]
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+        // A clone carries on from the same place without moving the original
+        let rng = Rng::seeded(7);
+        let peek = rng.clone().uniform();
+        assert_eq!(rng.uniform(), peek);
+        assert_ne!(Rng::from_clock().uniform(), Rng::from_clock().uniform());
+    }
+
+    #[test]
+    fn draws_stay_in_range() {
+        let rng = Rng::seeded(1);
+        let mut faces = [0; 6];
+        for _ in 0..6000 {
+            let u = rng.uniform();
+            assert!((0.0..1.0).contains(&u));
+            let face = call_random("randint", &[1.0, 6.0], &rng).unwrap();
+            faces[face as usize - 1] += 1;
+        }
+        assert!(faces.iter().all(|&count| (850..1150).contains(&count)));
+        // Bounds are rounded inward and may come in either order
+        assert_eq!(call_random("randint", &[3.5, 2.5], &rng), Ok(3.0));
+        assert!(call_random("randint", &[2.2, 2.8], &rng).is_err());
+        assert!(call_random("randint", &[0.0, f64::INFINITY], &rng).is_err());
+
+        let samples: Vec<f64> = (0..10_000).map(|_| rng.normal()).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 10_000.0;
+        assert!(mean.abs() < 0.05 && (variance - 1.0).abs() < 0.05);
+    }
+}
@@ -0,0 +1,237 @@
+//! Exact fractions for the opt-in fraction mode.
+//!
+//! [`evaluate`] works out the rational subset of a parsed expression (`+ - * / ^ !`
+//! with whole-number powers and factorials, decimals and integral variables) exactly,
+//! so `1/3 + 1/6` is `1/2`. Anything outside that subset returns `None` so the caller
+//! can fall back to the f64 evaluator.
+
+use std::fmt;
+use std::ops::Neg;
+
+use crate::bigint::{self, BigInt};
+use crate::eval::{BinaryOp, Evaluator, Expr, PostfixOp};
+
+/// A fraction in lowest terms, with the sign on the numerator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rational {
+    numerator: BigInt,
+    /// Always positive.
+    denominator: BigInt,
+}
+
+impl Rational {
+    /// `numerator / denominator` in lowest terms, or `None` for a zero denominator.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Option<Self> {
+        if denominator.is_zero() {
+            return None;
+        }
+        let divisor = numerator.gcd(&denominator);
+        let (mut numerator, mut denominator) =
+            (numerator.div(&divisor)?, denominator.div(&divisor)?);
+        if denominator.is_negative() {
+            (numerator, denominator) = (-numerator, -denominator);
+        }
+        Some(Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    pub fn integer(value: BigInt) -> Self {
+        Self {
+            numerator: value,
+            denominator: BigInt::from_u64(1),
+        }
+    }
+
+    /// Whether this is a whole number, which needs no fraction to show.
+    pub fn is_integer(&self) -> bool {
+        self.denominator == BigInt::from_u64(1)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator.to_f64() / self.denominator.to_f64()
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        Self::new(
+            self.numerator
+                .mul(&other.denominator)
+                .add(&other.numerator.mul(&self.denominator)),
+            self.denominator.mul(&other.denominator),
+        )
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        Self::new(
+            self.numerator.mul(&other.numerator),
+            self.denominator.mul(&other.denominator),
+        )
+    }
+
+    fn recip(&self) -> Option<Self> {
+        Self::new(self.denominator.clone(), self.numerator.clone())
+    }
+
+    /// This to a whole-number power; a negative one is a power of the reciprocal.
+    fn pow(&self, exponent: &Self, max_digits: usize) -> Option<Self> {
+        if !exponent.is_integer() {
+            return None;
+        }
+        let base = if exponent.numerator.is_negative() {
+            self.recip()?
+        } else {
+            self.clone()
+        };
+        let power = exponent.numerator.clone();
+        let power = if power.is_negative() { -power } else { power }.to_u32()?;
+        Some(Self {
+            numerator: base.numerator.pow(power, max_digits)?,
+            denominator: base.denominator.pow(power, max_digits)?,
+        })
+    }
+
+    fn digit_count(&self) -> usize {
+        self.numerator.digit_count() + self.denominator.digit_count()
+    }
+}
+
+impl Neg for Rational {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            numerator: -self.numerator,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// A number as typed, such as `0.25` or `1.5e-3`, as a fraction.
+fn decimal(text: &str, max_digits: usize) -> Option<Rational> {
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (text, 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = BigInt::parse_digits(&format!("{}{}", whole, fraction))?;
+    let shift = exponent.checked_sub(i32::try_from(fraction.len()).ok()?)?;
+    let scale = BigInt::from_u64(10).pow(shift.unsigned_abs(), max_digits)?;
+    if shift < 0 {
+        Rational::new(digits, scale)
+    } else {
+        Some(Rational::integer(digits.mul(&scale)))
+    }
+}
+
+/// Evaluate `tree` exactly if it only uses `+ - * / ^ !` on typed numbers and whole
+/// variables, with whole-number powers and factorials, and no numerator or denominator
+/// along the way is longer than `max_digits`. Programmer mode works in whole numbers,
+/// so it has no fractions.
+pub fn evaluate(tree: &Expr, evaluator: &Evaluator, max_digits: usize) -> Option<Rational> {
+    if evaluator.integers.is_some() {
+        return None;
+    }
+    let eval = |expr: &Expr| evaluate(expr, evaluator, max_digits);
+    let value = match tree {
+        Expr::Literal(_, text) => decimal(text, max_digits)?,
+        Expr::Number(_) | Expr::Variable(_) => {
+            Rational::integer(bigint::evaluate(tree, evaluator, max_digits)?)
+        }
+        Expr::Negate(operand) => -eval(operand)?,
+        Expr::Binary(BinaryOp::Add, left, right) => eval(left)?.add(&eval(right)?)?,
+        Expr::Binary(BinaryOp::Subtract, left, right) => eval(left)?.add(&-eval(right)?)?,
+        Expr::Binary(BinaryOp::Multiply, left, right) => eval(left)?.mul(&eval(right)?)?,
+        Expr::Binary(BinaryOp::Divide, left, right) => eval(left)?.mul(&eval(right)?.recip()?)?,
+        Expr::Binary(BinaryOp::Power, base, exponent) => {
+            eval(base)?.pow(&eval(exponent)?, max_digits)?
+        }
+        Expr::Postfix(PostfixOp::Factorial, operand) => {
+            let value = eval(operand)?;
+            if !value.is_integer() {
+                return None;
+            }
+            Rational::integer(BigInt::factorial(value.numerator.to_u32()?, max_digits)?)
+        }
+        _ => return None,
+    };
+    (value.digit_count() <= max_digits).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bigint::MAX_DIGITS;
+    use crate::programmer::WordSize;
+    use std::collections::BTreeMap;
+
+    fn exact(expr: &str, evaluator: &Evaluator, max_digits: usize) -> Option<Rational> {
+        evaluate(&evaluator.parse(expr).ok()?, evaluator, max_digits)
+    }
+
+    fn eval(expr: &str) -> Option<String> {
+        exact(expr, &Evaluator::new(&BTreeMap::new()), MAX_DIGITS).map(|q| q.to_string())
+    }
+
+    #[test]
+    fn exact_fraction_arithmetic() {
+        assert_eq!(eval("1/3 + 1/6").as_deref(), Some("1/2"));
+        assert_eq!(eval("2/4").as_deref(), Some("1/2"));
+        assert_eq!(eval("1/3 - 1/2").as_deref(), Some("-1/6"));
+        assert_eq!(eval("-1/-3").as_deref(), Some("1/3"));
+        assert_eq!(eval("0.1 + 0.2").as_deref(), Some("3/10"));
+        assert_eq!(eval(".5 * 3").as_deref(), Some("3/2"));
+        assert_eq!(eval("(2/3)^2").as_deref(), Some("4/9"));
+        assert_eq!(eval("2^-2").as_deref(), Some("1/4"));
+        assert_eq!(eval("(2/3)**-1").as_deref(), Some("3/2"));
+        assert_eq!(eval("3!/4").as_deref(), Some("3/2"));
+        assert_eq!(eval("1/3(1 + 2)").as_deref(), Some("1"));
+        assert_eq!(eval("-2^2").as_deref(), Some("-4"));
+        assert_eq!(eval("2^-1^2").as_deref(), Some("1/2"));
+        assert_eq!(eval("2^3^2").as_deref(), Some("512"));
+        assert_eq!(eval("1/3 * 3").as_deref(), Some("1"));
+        assert_eq!(eval("1.5e-3").as_deref(), Some("3/2000"));
+        assert_eq!(eval("1_000.5 / 3").as_deref(), Some("667/2"));
+        assert_eq!(
+            exact("1/3", &Evaluator::new(&BTreeMap::new()), MAX_DIGITS).map(|q| q.to_f64()),
+            Some(1.0 / 3.0)
+        );
+    }
+
+    #[test]
+    fn inexact_input_falls_back() {
+        for expr in [
+            "1/0", "2^0.5", "0^-1", "(1/2)!", "sqrt(4)", "pi", "7 % 2", "7 // 2", "1e999999",
+            "1.2.3", "(1 + 2",
+        ] {
+            assert_eq!(eval(expr), None, "{}", expr);
+        }
+        let variables = BTreeMap::from([("n".to_string(), 3.0), ("h".to_string(), 0.5)]);
+        let evaluator = Evaluator::new(&variables);
+        assert_eq!(
+            exact("1/n", &evaluator, MAX_DIGITS)
+                .map(|q| q.to_string())
+                .as_deref(),
+            Some("1/3")
+        );
+        assert_eq!(exact("h / 3", &evaluator, MAX_DIGITS), None);
+        // Past the digit limit the f64 evaluator takes over
+        assert_eq!(exact("1/7^30", &evaluator, 20), None);
+        // Programmer mode divides whole numbers
+        let programmer = Evaluator {
+            integers: Some(WordSize::Bits64),
+            ..evaluator
+        };
+        assert_eq!(exact("7/2", &programmer, MAX_DIGITS), None);
+    }
+}
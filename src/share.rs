@@ -0,0 +1,343 @@
+use anyhow::Result;
+
+use crate::calculator::{AngleMode, CalculatorMode};
+use crate::graph::Viewport;
+
+/// Version byte written at the start of every share payload.
+pub const SHARE_VERSION: u8 = 1;
+
+/// Calculator state that can be exchanged as a compact share string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharePayload {
+    pub expression: String,
+    pub mode: Option<CalculatorMode>,
+    pub angle_mode: Option<AngleMode>,
+    pub variables: Vec<(String, f64)>,
+    pub viewport: Option<Viewport>,
+}
+
+impl SharePayload {
+    /// One line per field, used for the preview shown before importing.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!("Expression: {}", self.expression)];
+        if let Some(mode) = self.mode {
+            lines.push(format!("Mode: {}", mode_name(mode)));
+        }
+        if let Some(angle_mode) = self.angle_mode {
+            lines.push(format!("Angle mode: {}", angle_mode.label()));
+        }
+        for (name, value) in &self.variables {
+            lines.push(format!("Variable: {} = {}", name, value));
+        }
+        if let Some(v) = self.viewport {
+            lines.push(format!(
+                "Graph view: x[{}, {}] y[{}, {}]",
+                v.x_min, v.x_max, v.y_min, v.y_max
+            ));
+        }
+        lines
+    }
+}
+
+/// Serialize the payload as a version byte followed by `key=value` lines, base64url encoded.
+pub fn encode(payload: &SharePayload) -> String {
+    let mut body = String::new();
+    push_field(&mut body, "expr", &payload.expression);
+    if let Some(mode) = payload.mode {
+        push_field(&mut body, "mode", mode_name(mode));
+    }
+    if let Some(angle_mode) = payload.angle_mode {
+        push_field(&mut body, "angle", angle_name(angle_mode));
+    }
+    if !payload.variables.is_empty() {
+        let vars: Vec<String> = payload
+            .variables
+            .iter()
+            .map(|(name, value)| format!("{}:{}", name, value))
+            .collect();
+        push_field(&mut body, "vars", &vars.join(","));
+    }
+    if let Some(v) = payload.viewport {
+        let view = format!("{},{},{},{}", v.x_min, v.x_max, v.y_min, v.y_max);
+        push_field(&mut body, "view", &view);
+    }
+
+    let mut bytes = vec![SHARE_VERSION];
+    bytes.extend_from_slice(body.as_bytes());
+    base64_encode(&bytes, true)
+}
+
+/// Parse a share string. Unknown fields are ignored so newer builds can add fields.
+pub fn decode(text: &str) -> Result<SharePayload> {
+    let bytes = base64_decode(text.trim(), true)?;
+    let (&version, body) = bytes
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty share string"))?;
+    if version != SHARE_VERSION {
+        return Err(anyhow::anyhow!("Unsupported share version: {}", version));
+    }
+    let body = std::str::from_utf8(body)?;
+
+    let mut expression = None;
+    let mut mode = None;
+    let mut angle_mode = None;
+    let mut variables = Vec::new();
+    let mut viewport = None;
+    for line in body.lines().filter(|line| !line.is_empty()) {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Malformed field: {}", line))?;
+        let value = unescape(value)?;
+        match key {
+            "expr" => expression = Some(value),
+            "mode" => mode = Some(parse_mode(&value)?),
+            "angle" => angle_mode = Some(parse_angle(&value)?),
+            "vars" => variables = parse_variables(&value)?,
+            "view" => viewport = Some(parse_viewport(&value)?),
+            _ => {}
+        }
+    }
+
+    Ok(SharePayload {
+        expression: expression.ok_or_else(|| anyhow::anyhow!("Missing expression"))?,
+        mode,
+        angle_mode,
+        variables,
+        viewport,
+    })
+}
+
+fn push_field(body: &mut String, key: &str, value: &str) {
+    body.push_str(key);
+    body.push('=');
+    for ch in value.chars() {
+        match ch {
+            '\\' => body.push_str("\\\\"),
+            '\n' => body.push_str("\\n"),
+            _ => body.push(ch),
+        }
+    }
+    body.push('\n');
+}
+
+fn unescape(value: &str) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            _ => return Err(anyhow::anyhow!("Invalid escape in share string")),
+        }
+    }
+    Ok(out)
+}
+
+fn mode_name(mode: CalculatorMode) -> &'static str {
+    match mode {
+        CalculatorMode::Basic => "basic",
+        CalculatorMode::Scientific => "scientific",
+        CalculatorMode::Programmer => "programmer",
+        CalculatorMode::Financial => "financial",
+    }
+}
+
+pub(crate) fn parse_mode(value: &str) -> Result<CalculatorMode> {
+    match value {
+        "basic" => Ok(CalculatorMode::Basic),
+        "scientific" => Ok(CalculatorMode::Scientific),
+        "programmer" => Ok(CalculatorMode::Programmer),
+        "financial" => Ok(CalculatorMode::Financial),
+        _ => Err(anyhow::anyhow!("Unknown mode: {}", value)),
+    }
+}
+
+fn angle_name(angle_mode: AngleMode) -> &'static str {
+    match angle_mode {
+        AngleMode::Radians => "rad",
+        AngleMode::Degrees => "deg",
+    }
+}
+
+pub(crate) fn parse_angle(value: &str) -> Result<AngleMode> {
+    match value {
+        "rad" => Ok(AngleMode::Radians),
+        "deg" => Ok(AngleMode::Degrees),
+        _ => Err(anyhow::anyhow!("Unknown angle mode: {}", value)),
+    }
+}
+
+fn parse_variables(value: &str) -> Result<Vec<(String, f64)>> {
+    value
+        .split(',')
+        .map(|binding| {
+            let (name, number) = binding
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Malformed variable: {}", binding))?;
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(anyhow::anyhow!("Invalid variable name: {}", name));
+            }
+            Ok((name.to_string(), number.parse()?))
+        })
+        .collect()
+}
+
+fn parse_viewport(value: &str) -> Result<Viewport> {
+    let bounds = value
+        .split(',')
+        .map(|part| part.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match bounds[..] {
+        [x_min, x_max, y_min, y_max]
+            if bounds.iter().all(|b| b.is_finite()) && x_min < x_max && y_min < y_max =>
+        {
+            Ok(Viewport {
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+            })
+        }
+        _ => Err(anyhow::anyhow!("Invalid graph view: {}", value)),
+    }
+}
+
+const STANDARD_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Base64 encode `data`. The URL-safe variant omits padding.
+pub fn base64_encode(data: &[u8], url_safe: bool) -> String {
+    let alphabet = if url_safe {
+        URL_SAFE_ALPHABET
+    } else {
+        STANDARD_ALPHABET
+    };
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..=chunk.len() {
+            out.push(alphabet[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+        if !url_safe {
+            for _ in chunk.len()..3 {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode base64 produced by [`base64_encode`]; trailing padding is accepted either way.
+pub fn base64_decode(text: &str, url_safe: bool) -> Result<Vec<u8>> {
+    let alphabet = if url_safe {
+        URL_SAFE_ALPHABET
+    } else {
+        STANDARD_ALPHABET
+    };
+    let text = text.trim_end_matches('=');
+    if text.len() % 4 == 1 {
+        return Err(anyhow::anyhow!("Truncated share string"));
+    }
+
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for ch in text.bytes() {
+        let value = alphabet
+            .iter()
+            .position(|&a| a == ch)
+            .ok_or_else(|| anyhow::anyhow!("Invalid character in share string: {}", ch as char))?;
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SharePayload {
+        SharePayload {
+            expression: "sin(x) + 2".to_string(),
+            mode: Some(CalculatorMode::Scientific),
+            angle_mode: Some(AngleMode::Degrees),
+            variables: vec![("a".to_string(), 0.1), ("k_2".to_string(), -3e-7)],
+            viewport: Some(Viewport {
+                x_min: -3.5,
+                x_max: 6.25,
+                y_min: -1.0,
+                y_max: 1.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn round_trips_all_fields() {
+        let payload = sample();
+        let encoded = encode(&payload);
+        assert!(
+            encoded
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_eq!(decode(&encoded).unwrap(), payload);
+    }
+
+    #[test]
+    fn ignores_unknown_fields() {
+        let mut bytes = vec![SHARE_VERSION];
+        bytes.extend_from_slice(b"theme=dark\nexpr=1+2\nfuture=x,y\n");
+        let payload = decode(&base64_encode(&bytes, true)).unwrap();
+        assert_eq!(payload.expression, "1+2");
+        assert_eq!(payload.mode, None);
+        assert_eq!(payload.angle_mode, None);
+        assert!(payload.variables.is_empty());
+        assert_eq!(payload.viewport, None);
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        let encode_raw = |version: u8, body: &str| {
+            let mut bytes = vec![version];
+            bytes.extend_from_slice(body.as_bytes());
+            base64_encode(&bytes, true)
+        };
+
+        assert!(decode("").is_err());
+        assert!(decode("not base64!").is_err());
+        assert!(decode("A").is_err());
+        assert!(decode(&encode_raw(99, "expr=1\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "mode=basic\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "expr=1\nnoequals\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "expr=1\nview=1,2,3\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "expr=1\nview=5,1,0,1\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "expr=1\\q\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "expr=1\nvars=x\n")).is_err());
+        assert!(decode(&encode_raw(SHARE_VERSION, "expr=1\nangle=grad\n")).is_err());
+    }
+
+    #[test]
+    fn base64_matches_known_vectors() {
+        assert_eq!(base64_encode(b"foobar", false), "Zm9vYmFy");
+        assert_eq!(base64_encode(b"fooba", false), "Zm9vYmE=");
+        assert_eq!(base64_encode(b"foob", false), "Zm9vYg==");
+        assert_eq!(base64_encode(&[0xfb, 0xff], true), "-_8");
+        assert_eq!(base64_decode("Zm9vYg==", false).unwrap(), b"foob");
+    }
+}
@@ -0,0 +1,536 @@
+//! The statistics panel: a table of x and y values, typed in a cell at a time, with the
+//! one-variable statistics of a column, the two-variable statistics of the rows that
+//! have both, and least-squares fits through them.
+
+use crate::calculator;
+
+/// A column of the data table.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Column {
+    #[default]
+    X,
+    Y,
+}
+
+impl Column {
+    pub fn label(self) -> &'static str {
+        match self {
+            Column::X => "x",
+            Column::Y => "y",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Column::X => 0,
+            Column::Y => 1,
+        }
+    }
+}
+
+/// Rows of x and y values, either of which may be blank, and the cell being edited.
+#[derive(Debug, Clone, Default)]
+pub struct DataTable {
+    pub rows: Vec<[Option<f64>; 2]>,
+    /// The selected row, which may be one past the last to add a new one.
+    pub row: usize,
+    pub column: Column,
+    /// What is typed into the selected cell and not yet entered.
+    pub input: String,
+    /// The curve fitted through the pairs.
+    pub model: Model,
+}
+
+impl DataTable {
+    /// The value entered at `row` in `column`, leaving aside anything typed.
+    pub fn cell(&self, row: usize, column: Column) -> Option<f64> {
+        self.rows.get(row)?[column.index()]
+    }
+
+    /// Enter what is typed into the selected cell, adding a row when it is past the
+    /// last. Text that isn't a number leaves the cell as it was.
+    pub fn commit(&mut self) {
+        let input = std::mem::take(&mut self.input);
+        let Ok(value) = input.parse::<f64>() else {
+            return;
+        };
+        if !value.is_finite() {
+            return;
+        }
+        if self.row == self.rows.len() {
+            self.rows.push([None; 2]);
+        }
+        self.rows[self.row][self.column.index()] = Some(value);
+    }
+
+    /// Enter the typed value and move `step` rows, no further than one past the last,
+    /// where typing starts a new row.
+    pub fn move_rows(&mut self, step: isize) {
+        self.commit();
+        self.row = self.row.saturating_add_signed(step).min(self.rows.len());
+    }
+
+    /// Enter the typed value and move to the other column.
+    pub fn switch_column(&mut self) {
+        self.commit();
+        self.column = match self.column {
+            Column::X => Column::Y,
+            Column::Y => Column::X,
+        };
+    }
+
+    /// Type `c` into the selected cell, ignoring anything that can't be part of a number.
+    pub fn push(&mut self, c: char) {
+        push_number(&mut self.input, c);
+    }
+
+    /// Delete the last typed character, or blank the cell when nothing is typed.
+    pub fn backspace(&mut self) {
+        if self.input.pop().is_none()
+            && let Some(row) = self.rows.get_mut(self.row)
+        {
+            row[self.column.index()] = None;
+        }
+    }
+
+    /// Remove the selected row, discarding anything typed.
+    pub fn delete_row(&mut self) {
+        self.input.clear();
+        if self.row < self.rows.len() {
+            self.rows.remove(self.row);
+        }
+    }
+
+    /// Empty the table, keeping the choice of model.
+    pub fn clear(&mut self) {
+        *self = Self {
+            model: self.model,
+            ..Self::default()
+        };
+    }
+
+    /// The values entered in `column`, in order, skipping blanks.
+    pub fn values(&self, column: Column) -> Vec<f64> {
+        self.rows
+            .iter()
+            .filter_map(|row| row[column.index()])
+            .collect()
+    }
+
+    /// The (x, y) pairs of the rows with both filled in.
+    pub fn pairs(&self) -> Vec<(f64, f64)> {
+        self.rows.iter().filter_map(|&[x, y]| x.zip(y)).collect()
+    }
+}
+
+/// Add `c` to `input`, a number being typed, if it can go next: digits, one point, a
+/// leading `-` and an exponent such as `e-3` after a digit.
+pub(crate) fn push_number(input: &mut String, c: char) {
+    let exponent = input.find(['e', 'E']);
+    let fits = c.is_ascii_digit()
+        || (c == '.' && !input.contains('.') && exponent.is_none())
+        || (c == '-' && (input.is_empty() || input.ends_with(['e', 'E'])))
+        || (matches!(c, 'e' | 'E')
+            && exponent.is_none()
+            && input.ends_with(|c: char| c.is_ascii_digit()));
+    if fits {
+        input.push(c);
+    }
+}
+
+/// One-variable statistics of a list of values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub sum: f64,
+    pub mean: f64,
+    /// The sample standard deviation, dividing by n - 1; `None` for a single value.
+    pub sample_sd: Option<f64>,
+    /// The population standard deviation, dividing by n.
+    pub population_sd: f64,
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+}
+
+/// The statistics of `values`, or `None` when there are none. The quartiles are the
+/// medians of the lower and upper halves, leaving out the middle value of an odd count,
+/// as most calculators do.
+pub fn summarize(values: &[f64]) -> Option<Summary> {
+    if values.is_empty() {
+        return None;
+    }
+    let count = values.len();
+    let n = count as f64;
+    let sum: f64 = values.iter().sum();
+    let mean = sum / n;
+    // Squared deviations from the mean, so a large offset doesn't swamp the spread
+    let squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let half = count / 2;
+    let (lower, upper) = (&sorted[..half], &sorted[count - half..]);
+    Some(Summary {
+        count,
+        sum,
+        mean,
+        sample_sd: (count > 1).then(|| (squares / (n - 1.0)).sqrt()),
+        population_sd: (squares / n).sqrt(),
+        min: sorted[0],
+        q1: median(lower).unwrap_or(sorted[0]),
+        median: median(&sorted).unwrap_or(sorted[0]),
+        q3: median(upper).unwrap_or(sorted[0]),
+        max: sorted[count - 1],
+    })
+}
+
+/// The middle of `sorted`, or the mean of its middle two.
+fn median(sorted: &[f64]) -> Option<f64> {
+    let half = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[half]),
+        _ => Some((sorted[half - 1] + sorted[half]) / 2.0),
+    }
+}
+
+/// Two-variable statistics of paired values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paired {
+    pub count: usize,
+    pub mean_x: f64,
+    pub mean_y: f64,
+    pub sum_xy: f64,
+    /// The sample covariance, dividing by n - 1; `None` for a single pair.
+    pub covariance: Option<f64>,
+    /// Pearson's correlation coefficient; `None` unless both x and y vary.
+    pub correlation: Option<f64>,
+}
+
+/// The statistics of `pairs`, or `None` when there are none.
+pub fn pair_stats(pairs: &[(f64, f64)]) -> Option<Paired> {
+    if pairs.is_empty() {
+        return None;
+    }
+    let count = pairs.len();
+    let n = count as f64;
+    let mean_x = pairs.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    for &(x, y) in pairs {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        sxx += dx * dx;
+        syy += dy * dy;
+        sxy += dx * dy;
+    }
+    Some(Paired {
+        count,
+        mean_x,
+        mean_y,
+        sum_xy: pairs.iter().map(|&(x, y)| x * y).sum(),
+        covariance: (count > 1).then(|| sxy / (n - 1.0)),
+        correlation: (sxx > 0.0 && syy > 0.0).then(|| (sxy / (sxx * syy).sqrt()).clamp(-1.0, 1.0)),
+    })
+}
+
+/// The shape of curve fitted through the pairs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Model {
+    #[default]
+    Linear,
+    Quadratic,
+    Exponential,
+}
+
+impl Model {
+    pub fn label(self) -> &'static str {
+        match self {
+            Model::Linear => "Linear",
+            Model::Quadratic => "Quadratic",
+            Model::Exponential => "Exponential",
+        }
+    }
+
+    pub fn equation(self) -> &'static str {
+        match self {
+            Model::Linear => "y = a + bx",
+            Model::Quadratic => "y = a + bx + cx²",
+            Model::Exponential => "y = a·e^(bx)",
+        }
+    }
+
+    /// What the pairs need for this model to fit them.
+    pub fn requirement(self) -> &'static str {
+        match self {
+            Model::Linear => "Needs two different x values",
+            Model::Quadratic => "Needs three different x values",
+            Model::Exponential => "Needs two different x values, every y above 0",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Model::Linear => Model::Quadratic,
+            Model::Quadratic => Model::Exponential,
+            Model::Exponential => Model::Linear,
+        }
+    }
+}
+
+/// A least-squares fit of a [`Model`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    pub model: Model,
+    /// a, b and, for a quadratic, c, as in the model's equation.
+    pub coefficients: Vec<f64>,
+    /// The share of the spread in y the curve accounts for; `None` when y doesn't vary.
+    pub r_squared: Option<f64>,
+}
+
+impl Fit {
+    /// The fitted curve's value at `x`.
+    pub fn at(&self, x: f64) -> f64 {
+        match (self.model, self.coefficients.as_slice()) {
+            (Model::Exponential, &[a, b, ..]) => a * (b * x).exp(),
+            (_, coefficients) => coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c),
+        }
+    }
+
+    /// The curve as an expression in x, for graphing, with its coefficients to 10
+    /// significant figures.
+    pub fn expression(&self) -> String {
+        let c = |i: usize| coefficient(self.coefficients[i]);
+        match self.model {
+            Model::Exponential => format!("{}*exp({}*x)", c(0), c(1)),
+            _ => {
+                let mut expression = c(0);
+                for (i, &value) in self.coefficients.iter().enumerate().skip(1) {
+                    let sign = if value < 0.0 { '-' } else { '+' };
+                    let power = if i == 1 {
+                        String::new()
+                    } else {
+                        format!("^{}", i)
+                    };
+                    expression += &format!(" {} {}*x{}", sign, coefficient(value.abs()), power);
+                }
+                expression
+            }
+        }
+    }
+}
+
+/// `value` to 10 significant figures, without trailing zeros.
+fn coefficient(value: f64) -> String {
+    let text = calculator::format_sig_figs(value, 10);
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, ""));
+    let mantissa = match mantissa.contains('.') {
+        true => mantissa.trim_end_matches('0').trim_end_matches('.'),
+        false => mantissa,
+    };
+    match exponent {
+        "" => mantissa.to_string(),
+        _ => format!("{}e{}", mantissa, exponent),
+    }
+}
+
+/// The least-squares fit of `model` through `pairs`, or `None` when they don't pin it
+/// down. An exponential is fitted as a line through ln y, but its R² is against y
+/// itself, so the models compare like for like.
+pub fn fit(model: Model, pairs: &[(f64, f64)]) -> Option<Fit> {
+    let coefficients = match model {
+        Model::Linear => {
+            let (a, b) = line(pairs.iter().copied())?;
+            vec![a, b]
+        }
+        Model::Quadratic => parabola(pairs)?,
+        Model::Exponential => {
+            if pairs.iter().any(|&(_, y)| y <= 0.0) {
+                return None;
+            }
+            let (ln_a, b) = line(pairs.iter().map(|&(x, y)| (x, y.ln())))?;
+            vec![ln_a.exp(), b]
+        }
+    };
+    let mut fit = Fit {
+        model,
+        coefficients,
+        r_squared: None,
+    };
+    let mean_y = pairs.iter().map(|&(_, y)| y).sum::<f64>() / pairs.len() as f64;
+    let total: f64 = pairs.iter().map(|&(_, y)| (y - mean_y).powi(2)).sum();
+    let residual: f64 = pairs.iter().map(|&(x, y)| (y - fit.at(x)).powi(2)).sum();
+    fit.r_squared = (total > 0.0).then(|| (1.0 - residual / total).max(0.0));
+    Some(fit)
+}
+
+/// The intercept and slope of the least-squares line through `pairs`.
+fn line(pairs: impl Iterator<Item = (f64, f64)> + Clone) -> Option<(f64, f64)> {
+    let n = pairs.clone().count() as f64;
+    let mean_x = pairs.clone().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.clone().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut sxx, mut sxy) = (0.0, 0.0);
+    for (x, y) in pairs {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+/// a, b and c of the least-squares parabola through `pairs`.
+fn parabola(pairs: &[(f64, f64)]) -> Option<Vec<f64>> {
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    // Fit y = p + qu + ru² with u = x - mean_x, which keeps the sums small and makes
+    // the sum of u zero, then expand back out
+    let (mut s2, mut s3, mut s4, mut t0, mut t1, mut t2) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for &(x, y) in pairs {
+        let u = x - mean_x;
+        s2 += u * u;
+        s3 += u * u * u;
+        s4 += u * u * u * u;
+        t0 += y;
+        t1 += u * y;
+        t2 += u * u * y;
+    }
+    // Cramer's rule on [[n, 0, s2], [0, s2, s3], [s2, s3, s4]]
+    let det = n * (s2 * s4 - s3 * s3) - s2 * s2 * s2;
+    if det <= 1e-12 * n * s2 * s4 {
+        return None;
+    }
+    let p = (t0 * (s2 * s4 - s3 * s3) + s2 * (s3 * t1 - s2 * t2)) / det;
+    let q = (n * (s4 * t1 - s3 * t2) + s2 * (s3 * t0 - s2 * t1)) / det;
+    let r = (n * (s2 * t2 - s3 * t1) - s2 * s2 * t0) / det;
+    Some(vec![
+        p - q * mean_x + r * mean_x * mean_x,
+        q - 2.0 * r * mean_x,
+        r,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_are_typed_entered_and_cleared() {
+        let mut table = DataTable::default();
+        for c in "1x2.5.".chars() {
+            table.push(c);
+        }
+        assert_eq!(table.input, "12.5");
+        table.move_rows(1);
+        table.switch_column();
+        for c in "-3e-2".chars() {
+            table.push(c);
+        }
+        // Moving away enters the cell, here on a new row
+        table.move_rows(-1);
+        assert_eq!(table.rows, [[Some(12.5), None], [None, Some(-0.03)]]);
+        assert_eq!(table.row, 0);
+        table.push('7');
+        table.move_rows(1);
+        assert_eq!(table.cell(0, Column::Y), Some(7.0));
+        assert_eq!(table.pairs(), [(12.5, 7.0)]);
+        assert_eq!(table.values(Column::Y), [7.0, -0.03]);
+        // Moving down stops one past the last row; a lone `-` isn't entered
+        table.move_rows(5);
+        table.push('-');
+        table.move_rows(1);
+        assert_eq!((table.rows.len(), table.row), (2, 2));
+
+        table.row = 0;
+        table.backspace();
+        assert_eq!(table.cell(0, Column::Y), None);
+        assert_eq!(table.pairs(), []);
+        table.delete_row();
+        assert_eq!(table.rows, [[None, Some(-0.03)]]);
+    }
+
+    #[test]
+    fn one_variable_statistics() {
+        let summary = summarize(&[7.0, 1.0, 3.0, 9.0, 5.0]).unwrap();
+        assert_eq!(summary.count, 5);
+        assert_eq!(summary.sum, 25.0);
+        assert_eq!(summary.mean, 5.0);
+        assert_eq!(summary.sample_sd, Some(10f64.sqrt()));
+        assert_eq!(summary.population_sd, 8f64.sqrt());
+        assert_eq!((summary.min, summary.max), (1.0, 9.0));
+        // The middle value of an odd count is left out of both halves
+        assert_eq!((summary.q1, summary.median, summary.q3), (2.0, 5.0, 8.0));
+
+        let even = summarize(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!((even.q1, even.median, even.q3), (2.0, 3.5, 5.0));
+        let single = summarize(&[4.0]).unwrap();
+        assert_eq!(single.sample_sd, None);
+        assert_eq!((single.q1, single.median, single.q3), (4.0, 4.0, 4.0));
+        assert_eq!(summarize(&[]), None);
+
+        // A large offset doesn't swamp a small spread
+        let offset = summarize(&[1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0]).unwrap();
+        assert_eq!(offset.sample_sd, Some(30f64.sqrt()));
+    }
+
+    #[test]
+    fn two_variable_statistics() {
+        let paired = pair_stats(&[(1.0, 2.0), (2.0, 4.0), (3.0, 6.0)]).unwrap();
+        assert_eq!((paired.mean_x, paired.mean_y), (2.0, 4.0));
+        assert_eq!(paired.sum_xy, 28.0);
+        assert_eq!(paired.covariance, Some(2.0));
+        assert_eq!(paired.correlation, Some(1.0));
+
+        let falling = pair_stats(&[(1.0, 3.0), (2.0, 1.0), (3.0, 2.0)]).unwrap();
+        assert_eq!(falling.correlation, Some(-0.5));
+        // Nothing to correlate when one side doesn't vary
+        let flat = pair_stats(&[(1.0, 5.0), (2.0, 5.0)]).unwrap();
+        assert_eq!(flat.correlation, None);
+        assert_eq!(pair_stats(&[(1.0, 1.0)]).unwrap().covariance, None);
+        assert_eq!(pair_stats(&[]), None);
+    }
+
+    #[test]
+    fn fits_lines_parabolas_and_exponentials() {
+        let close = |fit: &Fit, expected: &[f64]| {
+            fit.coefficients
+                .iter()
+                .zip(expected)
+                .all(|(c, e)| (c - e).abs() < 1e-9)
+        };
+        let line = fit(Model::Linear, &[(1.0, 2.0), (2.0, 3.0), (3.0, 5.0)]).unwrap();
+        assert!(close(&line, &[0.3333333333333333, 1.5]), "{:?}", line);
+        assert!((line.r_squared.unwrap() - 27.0 / 28.0).abs() < 1e-12);
+        assert_eq!(line.expression(), "0.3333333333 + 1.5*x");
+
+        // A parabola through exact points comes back, even far from the origin
+        let points: Vec<(f64, f64)> = (0..5)
+            .map(|i| 1000.0 + i as f64)
+            .map(|x| (x, 2.0 - 3.0 * x + 0.5 * x * x))
+            .collect();
+        let parabola = fit(Model::Quadratic, &points).unwrap();
+        assert!((parabola.at(1002.0) - points[2].1).abs() < 1e-6);
+        assert!((parabola.coefficients[2] - 0.5).abs() < 1e-9);
+        assert!((parabola.r_squared.unwrap() - 1.0).abs() < 1e-9);
+        let simple = fit(Model::Quadratic, &[(-1.0, 1.0), (0.0, -2.0), (1.0, 1.0)]).unwrap();
+        assert_eq!(simple.expression(), "-2 + 0*x + 3*x^2");
+
+        let decay = fit(Model::Exponential, &[(0.0, 8.0), (1.0, 4.0), (2.0, 2.0)]).unwrap();
+        assert!(
+            close(&decay, &[8.0, -std::f64::consts::LN_2]),
+            "{:?}",
+            decay
+        );
+        assert_eq!(decay.expression(), "8*exp(-0.6931471806*x)");
+
+        // Too few distinct x values, or a y the exponential can't take
+        assert_eq!(fit(Model::Linear, &[(1.0, 2.0), (1.0, 3.0)]), None);
+        assert_eq!(fit(Model::Quadratic, &[(1.0, 2.0), (2.0, 3.0)]), None);
+        assert_eq!(fit(Model::Exponential, &[(1.0, 2.0), (2.0, 0.0)]), None);
+        let flat = fit(Model::Linear, &[(1.0, 4.0), (2.0, 4.0)]).unwrap();
+        assert_eq!(flat.r_squared, None);
+    }
+}
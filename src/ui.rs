@@ -1,48 +1,263 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use chrono::NaiveDate;
 use ratatui::{
     Frame,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{
+        Block, Borders, Clear, HighlightSpacing, List, ListItem, ListState, Padding, Paragraph,
+        Wrap,
+    },
 };
 
-use crate::{App, AppState};
+use crate::calculator::{self, CONVERSIONS, CalculatorMode, Outcome, ResultFormat};
+use crate::constants::CONSTANTS;
+use crate::dates::{self, DateField};
+use crate::finance::TvmField;
+use crate::programmer;
+use crate::stats::{self, Column, Model};
+use crate::units::{Category, Field};
+use crate::{App, AppState, Popup, graph, keymap, precision};
+
+/// Smallest terminal the interface is drawn in; below this a resize notice is shown.
+pub const MIN_WIDTH: u16 = 20;
+pub const MIN_HEIGHT: u16 = 5;
+/// Most keypad rows shown at once; the rest scroll into view.
+pub const VISIBLE_BUTTON_ROWS: usize = 6;
+
+/// Below this size the keypad is dropped for the mini layout.
+const MINI_WIDTH: u16 = 40;
+const MINI_HEIGHT: u16 = 15;
+
+/// Below this size the keypad and display drop their borders to fit.
+const COMPACT_WIDTH: u16 = 60;
+const COMPACT_HEIGHT: u16 = 30;
+
+/// From this width history opens in a pane beside the calculator instead of over it.
+const SPLIT_WIDTH: u16 = 100;
+/// Columns the calculator keeps when the history pane widens for long entries.
+const SPLIT_KEYPAD_WIDTH: u16 = 20;
+
+/// How much of the interface fits in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LayoutMode {
+    /// Bordered display and keypad.
+    #[default]
+    Full,
+    /// Borderless display and keypad.
+    Compact,
+    /// Expression, result and status only, typed into; history opens over it.
+    Mini,
+}
+
+/// Layout for a terminal of this size; `force_mini` picks the mini layout at any size.
+pub fn layout_mode(terminal_size: Rect, force_mini: bool) -> LayoutMode {
+    if force_mini || terminal_size.width < MINI_WIDTH || terminal_size.height < MINI_HEIGHT {
+        LayoutMode::Mini
+    } else if terminal_size.width < COMPACT_WIDTH || terminal_size.height < COMPACT_HEIGHT {
+        LayoutMode::Compact
+    } else {
+        LayoutMode::Full
+    }
+}
+
+/// Whether the calculator view uses the compact, borderless keypad layout.
+pub fn is_compact(terminal_size: Rect) -> bool {
+    terminal_size.width < COMPACT_WIDTH || terminal_size.height < COMPACT_HEIGHT
+}
 
-fn create_colored_expression(expression: &str) -> Vec<Span<'_>> {
+/// Whether open history shares the screen with the calculator rather than covering it.
+pub fn is_split(terminal_size: Rect) -> bool {
+    terminal_size.width >= SPLIT_WIDTH
+}
+
+/// Colors for matching paren pairs, by nesting depth.
+const PAREN_COLORS: [Color; 4] = [
+    Color::Magenta,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::LightGreen,
+];
+
+/// One span per char, colored by token; each paren pair takes the color of its depth and
+/// a bracket without a partner, a closer with nothing to close or an opener never
+/// closed, is shown as an error. Underscores grouping a number's digits are dimmed.
+fn create_colored_expression(expression: &str) -> Vec<Span<'static>> {
     let mut spans = Vec::new();
-    let chars = expression.chars();
-
-    for ch in chars {
-        let color = match ch {
-            '0'..='9' | '.' => Color::White,                        // Numbers
-            '+' | '-' | '−' | '*' | '/' | '^' | '%' => Color::Cyan, // Operators
-            '(' | ')' => Color::Magenta,                            // Parentheses
-            _ => Color::White,                                      // Default
+    let chars: Vec<char> = expression.chars().collect();
+    let unbalanced = calculator::unbalanced_brackets(expression);
+    let mut depth = 0;
+    let mut quoted = false;
+
+    for (i, &ch) in chars.iter().enumerate() {
+        // Brackets in a quoted unit name are just text
+        if ch == '"' {
+            quoted = !quoted;
+        }
+        let groups_digits = ch == '_'
+            && i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(char::is_ascii_digit);
+        let style = match ch {
+            _ if groups_digits => Style::default().fg(Color::DarkGray),
+            '(' | ')' if quoted => Style::default().fg(Color::White),
+            '0'..='9' | '.' => Style::default().fg(Color::White), // Numbers
+            '+' | '-' | '−' | '*' | '/' | '^' | '%' => Style::default().fg(Color::Cyan), // Operators
+            _ if unbalanced.contains(&i) => {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            }
+            '(' => {
+                let style = Style::default().fg(PAREN_COLORS[depth % PAREN_COLORS.len()]);
+                depth += 1;
+                style
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                Style::default().fg(PAREN_COLORS[depth % PAREN_COLORS.len()])
+            }
+            _ => Style::default().fg(Color::White), // Default
         };
 
-        spans.push(Span::styled(ch.to_string(), Style::default().fg(color)));
+        spans.push(Span::styled(ch.to_string(), style));
     }
 
     spans
 }
 
+const EXPRESSION_PREFIX: &str = "Expression: ";
+const PREFIX_WIDTH: u16 = EXPRESSION_PREFIX.len() as u16;
+
+/// Where the expression's characters were drawn in the last frame, so a click on the
+/// display can be turned back into a cursor position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpressionColumns {
+    area: Rect,
+    /// Start column and width of each visible cell, the first being char `first`.
+    cells: Vec<(u16, u16)>,
+    first: usize,
+    len: usize,
+}
+
+impl ExpressionColumns {
+    /// Char index under a click, or the nearest end of the visible text for clicks on
+    /// the padding or past the last visible cell. `None` when the click is off the expression line.
+    pub fn index_at(&self, column: u16, row: u16) -> Option<usize> {
+        if row != self.area.y || column < self.area.x || column >= self.area.right() {
+            return None;
+        }
+        let hit = self
+            .cells
+            .iter()
+            .position(|&(start, width)| column < start + width);
+        Some(match hit {
+            Some(cell) if column >= self.cells[cell].0 => (self.first + cell).min(self.len),
+            Some(_) => self.first,
+            None => (self.first + self.cells.len()).min(self.len),
+        })
+    }
+
+    /// Screen column of char `index`, or just past the last char for `len`. `None` when
+    /// it is scrolled out of view.
+    fn x_of(&self, index: usize) -> Option<u16> {
+        let cell = index.checked_sub(self.first)?;
+        match self.cells.get(cell) {
+            Some(&(start, _)) => Some(start),
+            None if index == self.len => self
+                .cells
+                .last()
+                .map_or(
+                    Some(self.area.right().saturating_sub(1)),
+                    |&(start, width)| Some(start + width),
+                )
+                .filter(|&x| x < self.area.right()),
+            None => None,
+        }
+    }
+}
+
+/// Lay out cells of the given display widths on one row of `area`: right-aligned when they
+/// fit, otherwise scrolled just far enough that the `cursor` cell is visible. Returns the
+/// first visible cell, the padding before it and the column map for `len` chars.
+fn layout_expression(
+    widths: &[u16],
+    cursor: usize,
+    len: usize,
+    area: Rect,
+) -> (usize, u16, ExpressionColumns) {
+    let total: u16 = widths.iter().sum();
+    let (first, padding) = if total <= area.width {
+        (0, area.width - total)
+    } else {
+        let cursor = cursor.min(widths.len() - 1);
+        let mut first = cursor;
+        let mut used = widths[cursor];
+        while first > 0 && used + widths[first - 1] <= area.width {
+            first -= 1;
+            used += widths[first];
+        }
+        (first, 0)
+    };
+
+    let mut cells = Vec::new();
+    let mut column = area.x + padding;
+    for &width in &widths[first..] {
+        if column + width > area.right() {
+            break;
+        }
+        cells.push((column, width));
+        column += width;
+    }
+    (
+        first,
+        padding,
+        ExpressionColumns {
+            area,
+            cells,
+            first,
+            len,
+        },
+    )
+}
+
 pub fn draw(f: &mut Frame, app: &App, terminal_size: Rect) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3), // Title
-            Constraint::Min(0),    // Main content
-            Constraint::Length(3), // Status
-        ])
-        .split(f.area());
+    *app.expression_columns.borrow_mut() = None;
+    app.button_rects.borrow_mut().clear();
+    if terminal_size.width < MIN_WIDTH || terminal_size.height < MIN_HEIGHT {
+        draw_resize_notice(f, terminal_size);
+        return;
+    }
+    if layout_mode(terminal_size, app.config.mini_layout) == LayoutMode::Mini {
+        draw_mini(f, app, f.area());
+        return;
+    }
+
+    let chunks = screen_layout(f.area());
 
     draw_title(f, chunks[0], app);
 
     match app.state {
+        _ if app.show_help => draw_help(f, app, chunks[1]),
         AppState::Graph => draw_graph(f, app, chunks[1], terminal_size),
+        AppState::Convert => draw_converter(f, app, chunks[1]),
+        AppState::Stats => draw_stats(f, app, chunks[1]),
+        AppState::Matrix => draw_matrix_editor(f, app, chunks[1]),
+        AppState::Dates => draw_dates(f, app, chunks[1]),
         _ => {
-            if app.show_help {
-                draw_help(f, app, chunks[1]);
+            if app.show_history && is_split(terminal_size) {
+                // History takes half the screen, or more to fit long entries
+                let widest = history_items(app).iter().map(ListItem::width).max();
+                let history_width = (widest.unwrap_or(0) as u16 + 2).clamp(
+                    chunks[1].width / 2,
+                    chunks[1].width.saturating_sub(SPLIT_KEYPAD_WIDTH),
+                );
+                let panes =
+                    Layout::horizontal([Constraint::Min(0), Constraint::Length(history_width)])
+                        .split(chunks[1]);
+                draw_calculator(f, app, panes[0], terminal_size);
+                draw_history(f, app, panes[1]);
             } else if app.show_history {
                 draw_history(f, app, chunks[1]);
             } else {
@@ -52,12 +267,61 @@ pub fn draw(f: &mut Frame, app: &App, terminal_size: Rect) {
     }
 
     draw_status(f, app, chunks[2]);
+
+    if let Some(popup) = &app.popup {
+        draw_popup(f, popup, &app.calculator_module.current_result, f.area());
+    }
+}
+
+/// Block levels for [`sparkline`], lowest first.
+const SPARK_LEVELS: [char; 7] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+
+/// One block per value, scaled so the smallest value gets the lowest block and the
+/// largest the highest. A flat series sits in the middle.
+pub fn sparkline(values: &[f64]) -> String {
+    let (min, max) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    let top = (SPARK_LEVELS.len() - 1) as f64;
+    values
+        .iter()
+        .map(|&value| {
+            let level = if max > min {
+                ((value - min) / (max - min) * top).round()
+            } else {
+                top / 2.0
+            };
+            SPARK_LEVELS[level as usize]
+        })
+        .collect()
+}
+
+/// Most recent numeric results to chart in the title.
+const SPARK_LENGTH: usize = 24;
+
+/// Trend of the last numeric results, oldest first, at most `width` of them.
+fn history_sparkline(app: &App, width: usize) -> String {
+    let mut values: Vec<f64> = app
+        .calculator_module
+        .history
+        .iter()
+        .rev()
+        .filter_map(|entry| entry.result.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .take(SPARK_LENGTH.min(width))
+        .collect();
+    values.reverse();
+    sparkline(&values)
 }
 
 fn draw_title(f: &mut Frame, area: Rect, app: &App) {
     let mode_str = match app.calculator_module.mode {
         crate::calculator::CalculatorMode::Basic => "Basic",
         crate::calculator::CalculatorMode::Scientific => "Scientific",
+        crate::calculator::CalculatorMode::Programmer => "Programmer",
+        crate::calculator::CalculatorMode::Financial => "Financial",
     };
 
     let state_str = match app.state {
@@ -70,49 +334,284 @@ fn draw_title(f: &mut Frame, area: Rect, app: &App) {
         }
         AppState::Typing => "Typing Mode",
         AppState::Graph => "Graph Mode",
+        AppState::Convert => "Unit Converter",
+        AppState::Stats => "Statistics",
+        AppState::Matrix => "Matrix Editor",
+        AppState::Dates => "Date Calculator",
     };
 
     let title_text = format!(
-        "Calculator | Mode: {} | {} | {}",
+        "Calculator | Mode: {} | {}{}{} | {} | {}",
         mode_str,
+        app.calculator_module.angle_mode.label(),
+        if app.calculator_module.big_integers {
+            " | BIG"
+        } else {
+            ""
+        },
+        if app.calculator_module.fractions {
+            " | FRAC"
+        } else {
+            ""
+        },
         state_str,
         chrono::Local::now().format("%H:%M:%S")
     );
 
+    let mut block = Block::default().borders(Borders::ALL);
+    if app.config.sparkline {
+        // Drawn on the top border, inside its corners and padding
+        let room = (area.width as usize).saturating_sub(4);
+        let spark = history_sparkline(app, room);
+        if spark.chars().count() > 1 {
+            block = block.title(
+                Line::styled(format!(" {} ", spark), Style::default().fg(Color::Green))
+                    .right_aligned(),
+            );
+        }
+    }
+
     let title = Paragraph::new(title_text)
         .style(
             Style::default()
                 .fg(Color::Cyan)
                 .add_modifier(Modifier::BOLD),
         )
-        .block(Block::default().borders(Borders::ALL));
+        .block(block);
     f.render_widget(title, area);
 }
 
+/// The mini layout: a bordered box holding the expression, the result and one status
+/// line. Help, graphs and history take over the whole box.
+fn draw_mini(f: &mut Frame, app: &App, area: Rect) {
+    match app.state {
+        _ if app.show_help => draw_help(f, app, area),
+        AppState::Graph => draw_graph_area(f, app, area),
+        AppState::Convert => draw_converter(f, app, area),
+        AppState::Stats => draw_stats(f, app, area),
+        AppState::Matrix => draw_matrix_editor(f, app, area),
+        AppState::Dates => draw_dates(f, app, area),
+        _ => {
+            let block = Block::default()
+                .title(format!(
+                    " tcalc | {}{} ",
+                    app.calculator_module.angle_mode.label(),
+                    if app.calculator_module.big_integers {
+                        " | BIG"
+                    } else {
+                        ""
+                    }
+                ))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan));
+            let inner = block.inner(area);
+            f.render_widget(block, area);
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(3), // Expression and result
+                    Constraint::Min(0),
+                    Constraint::Length(1), // Status
+                ])
+                .split(inner);
+            draw_display(f, app, chunks[0], true);
+            f.render_widget(Paragraph::new(Line::from(status_spans(app))), chunks[2]);
+
+            if app.show_history {
+                f.render_widget(Clear, area);
+                draw_history(f, app, area);
+            }
+        }
+    }
+
+    if let Some(popup) = &app.popup {
+        draw_popup(f, popup, &app.calculator_module.current_result, area);
+    }
+}
+
+fn draw_resize_notice(f: &mut Frame, area: Rect) {
+    let notice = Paragraph::new(vec![
+        Line::styled(
+            "Terminal too small",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        Line::from(format!(
+            "Resize your terminal (needs ≥ {}×{})",
+            MIN_WIDTH, MIN_HEIGHT
+        )),
+        Line::styled(
+            format!("Current: {}×{}", area.width, area.height),
+            Style::default().fg(Color::Gray),
+        ),
+    ])
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true });
+    f.render_widget(notice, area);
+}
+
 fn draw_calculator(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
+    let compact = is_compact(terminal_size);
+    let bases_height = match app.calculator_module.mode {
+        CalculatorMode::Programmer if compact => 1,
+        CalculatorMode::Programmer => 4,
+        CalculatorMode::Financial if compact => 1,
+        CalculatorMode::Financial => 5,
+        _ => 0,
+    };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(6), // Display
-            Constraint::Min(0),    // Button grid
+            Constraint::Length(if compact { 3 } else { 6 }), // Display
+            Constraint::Length(bases_height),                // Programmer readouts or TVM
+            Constraint::Min(0),                              // Button grid
         ])
         .split(area);
 
-    draw_display(f, app, chunks[0]);
-    draw_buttons(f, app, chunks[1], terminal_size);
+    draw_display(f, app, chunks[0], compact);
+    match app.calculator_module.mode {
+        CalculatorMode::Programmer => draw_bases(f, app, chunks[1], compact),
+        CalculatorMode::Financial => draw_tvm(f, app, chunks[1], compact),
+        _ => {}
+    }
+    draw_buttons(f, app, chunks[2], terminal_size);
 }
 
-fn draw_display(f: &mut Frame, app: &App, area: Rect) {
+/// Financial mode's time-value-of-money values as a form, two columns of them, the one
+/// last worked out highlighted. Compact, they share one line.
+fn draw_tvm(f: &mut Frame, app: &App, area: Rect, compact: bool) {
+    let tvm = &app.tvm;
+    let label_style = Style::default().fg(Color::Gray);
+    let field = |field: TvmField| {
+        let style = match tvm.solved == Some(field) {
+            true => Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+            false => Style::default().fg(Color::White),
+        };
+        let value = calculator::format_result(tvm.get(field));
+        vec![
+            Span::styled(format!("{:<4}", field.label()), label_style),
+            Span::styled(format!("{:<16}", value), style),
+        ]
+    };
+    let title = match tvm.computing {
+        true => "TVM - CPT: pick the value to work out",
+        false => "TVM (payments at period end)",
+    };
+    if compact {
+        let mut spans = vec![Span::styled(
+            if tvm.computing { "CPT " } else { "TVM " },
+            Style::default().fg(Color::Cyan),
+        )];
+        for field in TvmField::ALL.map(field) {
+            spans.extend(field);
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+        return;
+    }
+    let [n, rate, pv, pmt, fv] = TvmField::ALL;
+    let lines = vec![
+        Line::from([field(n), field(pmt)].concat()),
+        Line::from([field(rate), field(fv)].concat()),
+        Line::from(field(pv)),
+    ];
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(if tvm.computing {
+            Color::Yellow
+        } else {
+            Color::DarkGray
+        }));
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Programmer mode's readout of the result in each base: binary, the longest, on a
+/// line of its own, or everything on one line when compact. The word size titles the
+/// box, or leads the line when compact.
+fn draw_bases(f: &mut Frame, app: &App, area: Rect, compact: bool) {
+    let label_style = Style::default().fg(Color::Gray);
+    let value_style = Style::default().fg(Color::Green);
+    let word = app.calculator_module.word_size;
+    let readouts = app
+        .calculator_module
+        .result_value
+        .and_then(|value| programmer::readouts(value, word));
+    let lines: Vec<Line> = match readouts {
+        Some(readouts) => {
+            let mut lines = vec![Vec::new()];
+            if compact {
+                lines[0].push(Span::styled(word.label(), label_style));
+            }
+            for (label, digits) in readouts {
+                if label == "BIN" && !compact {
+                    lines.push(Vec::new());
+                }
+                let spans = lines.last_mut().expect("starts with a line");
+                if !spans.is_empty() {
+                    spans.push(Span::raw("   "));
+                }
+                spans.push(Span::styled(format!("{} ", label), label_style));
+                spans.push(Span::styled(digits, value_style));
+            }
+            lines.into_iter().map(Line::from).collect()
+        }
+        None => vec![Line::styled("not an integer", label_style)],
+    };
+    let block = if compact {
+        Block::default()
+    } else {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Green))
+            .title(format!(" {} (w) ", word.label()))
+    };
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_display(f: &mut Frame, app: &App, area: Rect, compact: bool) {
+    if !app.statement_results.is_empty() {
+        draw_statement_results(f, &app.statement_results, area, compact);
+        return;
+    }
+
+    // Compact mode drops the boxes: one line each, with a separator under the result
+    let (expression_block, result_block, border_width) = if compact {
+        (
+            Block::default(),
+            Block::default()
+                .borders(Borders::BOTTOM)
+                .border_style(Style::default().fg(Color::Green)),
+            0,
+        )
+    } else {
+        (
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Blue)),
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Green)),
+            2,
+        )
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3), // Expression
-            Constraint::Length(3), // Result
-        ])
+        .constraints(if compact {
+            [Constraint::Length(1), Constraint::Length(2)]
+        } else {
+            [
+                Constraint::Length(3), // Expression
+                Constraint::Length(3), // Result
+            ]
+        })
         .split(area);
 
     // Expression display with better styling
-    let expression = if app.calculator_module.current_expression.is_empty() {
+    let typing = app.state == AppState::Typing;
+    let expression = if app.calculator_module.current_expression.is_empty() && !typing {
         "0".to_string()
     } else {
         app.calculator_module.current_expression.clone()
@@ -120,39 +619,97 @@ fn draw_display(f: &mut Frame, app: &App, area: Rect) {
 
     // Create expression spans with right-aligned content
     let mut expression_spans = vec![Span::styled(
-        "Expression: ",
+        EXPRESSION_PREFIX,
         Style::default().fg(Color::Gray),
     )];
-    let content_spans = create_colored_expression(&expression);
+    let mut content_spans = create_colored_expression(&expression);
+
+    // Show the edit cursor in Typing mode as a reversed cell, over any selection
+    if typing {
+        if let Some(selection) = app.selection() {
+            for span in content_spans
+                .iter_mut()
+                .take(selection.end)
+                .skip(selection.start)
+            {
+                span.style = span.style.bg(Color::DarkGray);
+            }
+        }
+        let cursor = app.calculator_module.cursor();
+        let cursor_style = if app.cursor_visible() {
+            Modifier::REVERSED
+        } else {
+            Modifier::empty()
+        };
+        match content_spans.get_mut(cursor) {
+            Some(span) => span.style = span.style.add_modifier(cursor_style),
+            None => content_spans.push(Span::styled(
+                " ",
+                Style::default().add_modifier(cursor_style),
+            )),
+        }
+    }
 
-    // Calculate available width for right-aligned content
-    let available_width = chunks[0].width.saturating_sub(14); // 12 for "Expression: " + 2 for borders
-    let content_text: String = content_spans
+    // Mark the character an error points at, for the caret drawn under it below
+    let error_column = app
+        .calculator_module
+        .error_message
+        .as_ref()
+        .and(app.calculator_module.error_column);
+    if let Some(span) = error_column.and_then(|column| content_spans.get_mut(column)) {
+        span.style = span
+            .style
+            .fg(error_color(app))
+            .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    }
+
+    // Right-align the expression after the prefix, scrolling long ones to keep the cursor in view
+    let inner = expression_block.inner(chunks[0]);
+    let text_area = Rect {
+        x: inner.x + PREFIX_WIDTH.min(inner.width),
+        width: inner.width.saturating_sub(PREFIX_WIDTH),
+        height: inner.height.min(1),
+        ..inner
+    };
+    let widths: Vec<u16> = content_spans
         .iter()
-        .map(|span| span.content.clone())
+        .map(|span| span.width() as u16)
         .collect();
-
-    if content_text.len() <= available_width as usize {
-        // Content fits, right-align it with padding
-        let padding_needed = available_width.saturating_sub(content_text.len() as u16);
-        let padding = " ".repeat(padding_needed as usize);
-        expression_spans.push(Span::styled(padding, Style::default()));
-        expression_spans.extend(content_spans);
-    } else {
-        // Content too long, just add it (will overflow gracefully)
-        expression_spans.extend(content_spans);
+    let (first, padding, columns) = layout_expression(
+        &widths,
+        app.calculator_module.cursor(),
+        expression.chars().count(),
+        text_area,
+    );
+    expression_spans.push(Span::raw(" ".repeat(padding as usize)));
+    expression_spans.extend(content_spans.into_iter().skip(first));
+    let caret_x = error_column.and_then(|column| columns.x_of(column));
+    if typing {
+        *app.expression_columns.borrow_mut() = Some(columns);
     }
 
-    let expression_para = Paragraph::new(vec![Line::from(expression_spans)]).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Blue)),
-    );
+    let expression_para =
+        Paragraph::new(vec![Line::from(expression_spans)]).block(expression_block);
     f.render_widget(expression_para, chunks[0]);
 
+    // A caret on the box's bottom border points up at where the error is
+    if let Some(x) = caret_x.filter(|_| !compact && chunks[0].height >= 3) {
+        let y = chunks[0].bottom() - 1;
+        if let Some(cell) = f.buffer_mut().cell_mut((x, y)) {
+            cell.set_char('^').set_fg(error_color(app));
+        }
+    }
+
     // Result display with better styling
     let result_style = if app.calculator_module.error_message.is_some() {
-        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        Style::default()
+            .fg(error_color(app))
+            .add_modifier(Modifier::BOLD)
+    } else if app.calculator_module.partial_preview {
+        // Previewing a half-typed expression: dimmed until it is complete
+        Style::default()
+            .fg(Color::Green)
+            .add_modifier(Modifier::DIM)
     } else {
         Style::default()
             .fg(Color::Green)
@@ -163,31 +720,144 @@ fn draw_display(f: &mut Frame, app: &App, area: Rect) {
     let mut result_spans = vec![Span::styled("Result: ", Style::default().fg(Color::Gray))];
 
     // Calculate available width for right-aligned content
-    let available_width = chunks[1].width.saturating_sub(10); // 8 for "Result: " + 2 for borders
-    let result_text = &app.calculator_module.current_result;
+    let available_width = chunks[1].width.saturating_sub(8 + border_width); // "Result: " + borders
+    let (mut result_text, rounded) = app.calculator_module.displayed_result();
+    // A half-typed expression that can't be previewed says why, rather than echoing it
+    let preview_issue = app
+        .calculator_module
+        .preview_issue
+        .as_ref()
+        .filter(|_| app.calculator_module.error_message.is_none());
+    let result_style = match preview_issue {
+        Some(issue) => {
+            result_text = app.glyphs.apply(&format!("⚠ {}", issue)).into_owned();
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::DIM)
+        }
+        None => result_style,
+    };
+    // Rounded or reformatted results say how, so they aren't mistaken for the full value
+    let mut formats = Vec::new();
+    let format = app.calculator_module.result_format;
+    if format != ResultFormat::Decimal && app.calculator_module.result_value.is_some() {
+        formats.push(format.name().to_string());
+    }
+    if let Some(figures) = app.calculator_module.sig_figs.filter(|_| rounded) {
+        formats.push(format!("{} s.f.", figures));
+    }
+    let sig_fig_note = if formats.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", formats.join(", "))
+    };
+    // A result that lost digits to cancellation says how many are left
+    let cancellation_note = match app.calculator_module.reliable_digits {
+        Some(digits) if app.calculator_module.error_message.is_none() => {
+            app.glyphs.apply(&cancellation_badge(digits)).into_owned()
+        }
+        _ => String::new(),
+    };
+    // Hex, binary and octal literals show what they were read as
+    let literals = calculator::radix_literals(&app.calculator_module.current_expression);
+    let literal_note = if literals.is_empty() || app.calculator_module.error_message.is_some() {
+        String::new()
+    } else {
+        let readings: Vec<String> = literals
+            .iter()
+            .map(|(text, value)| format!("{} = {}", text, calculator::format_result(*value)))
+            .collect();
+        format!(" ({})", readings.join(", "))
+    };
+    let shown_len = result_text.chars().count()
+        + sig_fig_note.len()
+        + literal_note.len()
+        + cancellation_note.chars().count();
 
-    if result_text.len() <= available_width as usize {
+    if shown_len <= available_width as usize {
         // Content fits, right-align it with padding
-        let padding_needed = available_width.saturating_sub(result_text.len() as u16);
+        let padding_needed = available_width.saturating_sub(shown_len as u16);
         let padding = " ".repeat(padding_needed as usize);
         result_spans.push(Span::styled(padding, Style::default()));
-        result_spans.push(Span::styled(result_text.clone(), result_style));
-    } else {
-        // Content too long, just add it (will overflow gracefully)
-        result_spans.push(Span::styled(result_text.clone(), result_style));
     }
+    // Content too long is just added (will overflow gracefully)
+    result_spans.push(Span::styled(result_text, result_style));
+    if !sig_fig_note.is_empty() {
+        result_spans.push(Span::styled(
+            sig_fig_note,
+            Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+        ));
+    }
+    if !literal_note.is_empty() {
+        result_spans.push(Span::styled(
+            literal_note,
+            Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+        ));
+    }
+    if !cancellation_note.is_empty() {
+        result_spans.push(Span::styled(
+            cancellation_note,
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+
+    let result_para = Paragraph::new(vec![Line::from(result_spans)]).block(result_block);
+    f.render_widget(result_para, chunks[1]);
+}
 
-    let result_para = Paragraph::new(vec![Line::from(result_spans)]).block(
+/// One row per statement of a multi-statement calculation, with values aligned on the
+/// right. When there are more statements than rows, the last ones are shown.
+fn draw_statement_results(f: &mut Frame, results: &[(String, Outcome)], area: Rect, compact: bool) {
+    let block = if compact {
+        Block::default()
+    } else {
         Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Green)),
-    );
-    f.render_widget(result_para, chunks[1]);
+            .border_style(Style::default().fg(Color::Green))
+    };
+    let inner = block.inner(area);
+    let rows = inner.height as usize;
+    let hidden = results.len().saturating_sub(rows);
+    let block = match (compact, hidden) {
+        (true, _) => block,
+        (false, 0) => block.title(" Results "),
+        (false, _) => block.title(format!(" Results ({} more above) ", hidden)),
+    };
+
+    let values: Vec<String> = results
+        .iter()
+        .map(|(_, outcome)| outcome.to_string())
+        .collect();
+    let value_width = values.iter().map(|v| v.chars().count()).max().unwrap_or(0);
+    let lines: Vec<Line> = results[hidden..]
+        .iter()
+        .zip(&values[hidden..])
+        .map(|((statement, _), value)| {
+            let padding = (inner.width as usize)
+                .saturating_sub(statement.chars().count() + value_width)
+                .max(1);
+            Line::from(vec![
+                Span::styled(statement.clone(), Style::default().fg(Color::Gray)),
+                Span::raw(" ".repeat(padding)),
+                Span::styled(
+                    format!("{:>width$}", value, width = value_width),
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD),
+                ),
+            ])
+        })
+        .collect();
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
 }
 
 fn draw_buttons(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
+    let compact = is_compact(terminal_size);
     let buttons = app.get_calculator_buttons();
-    let max_rows = 6; // Maximum visible rows
+    // At most six rows, fewer when the display above leaves less room
+    let max_rows =
+        (area.height / if compact { 1 } else { 3 }).clamp(1, VISIBLE_BUTTON_ROWS as u16) as usize;
     let visible_buttons = if buttons.len() > max_rows {
         &buttons[app.scroll_offset..(app.scroll_offset + max_rows).min(buttons.len())]
     } else {
@@ -196,7 +866,10 @@ fn draw_buttons(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Length(3); visible_buttons.len()])
+        .constraints(vec![
+            Constraint::Length(if compact { 1 } else { 3 });
+            visible_buttons.len()
+        ])
         .split(area);
 
     for (row_idx, row) in visible_buttons.iter().enumerate() {
@@ -204,8 +877,16 @@ fn draw_buttons(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
             .direction(Direction::Horizontal)
             .constraints(vec![Constraint::Percentage(25); row.len()])
             .split(chunks[row_idx]);
+        // A row whose labels don't fit inside boxes is drawn like a compact one
+        let squeezed = !compact
+            && row.iter().zip(row_chunks.iter()).any(|((label, _), rect)| {
+                app.glyphs.apply(label).chars().count() + 2 > rect.width as usize
+            });
+        let row_compact = compact || squeezed;
 
         for (col_idx, (label, _)) in row.iter().enumerate() {
+            let rect = row_chunks[col_idx];
+            app.button_rects.borrow_mut().push((rect, row_idx, col_idx));
             let is_selected = if let Some((selected_row, selected_col)) = app.button_position {
                 let actual_row = app.scroll_offset + selected_row;
                 actual_row == app.scroll_offset + row_idx && selected_col == col_idx
@@ -214,17 +895,10 @@ fn draw_buttons(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
             };
 
             // Check if mouse is hovering over this button
-            let is_hovered = if let Some((mouse_x, mouse_y)) = app.mouse_position {
-                if let Some((hover_row, hover_col)) =
-                    app.mouse_to_button_coords(mouse_x, mouse_y, terminal_size.width)
-                {
-                    hover_row == row_idx && hover_col == col_idx
-                } else {
-                    false
-                }
-            } else {
-                false
-            };
+            let is_hovered = app.mouse_position.is_some_and(|(mouse_x, mouse_y)| {
+                app.accepts_button_clicks(terminal_size)
+                    && rect.contains(Position::new(mouse_x, mouse_y))
+            });
 
             // Enhanced button styling with color coding for text and borders only
             let (text_color, border_color, is_bold) = if is_selected || is_hovered {
@@ -232,9 +906,13 @@ fn draw_buttons(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
                 (Color::Yellow, Color::Yellow, true)
             } else {
                 // Normal colors
+                let programmer = app.calculator_module.mode == CalculatorMode::Programmer
+                    && !app.second_function_mode;
                 let (color, border) = match *label {
-                    "C" | "CE" | "⌫" => (Color::Red, Color::Red), // Clear buttons
-                    "=" => (Color::Green, Color::Green),          // Equals
+                    // Hex digits, which include a "C"
+                    "A" | "B" | "C" | "D" | "E" | "F" if programmer => (Color::White, Color::Gray),
+                    "C" | "AC" | "CE" | "⌫" => (Color::Red, Color::Red), // Clear buttons
+                    "=" => (Color::Green, Color::Green),                 // Equals
                     "+" | "-" | "−" | "×" | "÷" | "^" | "%" => (Color::Cyan, Color::Cyan), // Operators
                     "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "." => {
                         (Color::White, Color::Gray)
@@ -246,37 +924,55 @@ fn draw_buttons(f: &mut Frame, app: &App, area: Rect, terminal_size: Rect) {
                     "Copy" => (Color::Blue, Color::Blue),                    // Copy
                     "Graph" => (Color::Yellow, Color::Yellow),               // Graph
                     // Scientific functions - use distinct colors
-                    "sin" | "cos" | "tan" | "√" | "log" | "ln" => {
-                        (Color::LightBlue, Color::LightBlue)
-                    } // Trig/log functions
+                    "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "atan2" | "√" | "log"
+                    | "ln" => (Color::LightBlue, Color::LightBlue), // Trig/log functions
                     "exp" | "abs" | "1/x" | "x²" => (Color::Magenta, Color::Magenta), // Advanced functions - same as parentheses
-                    _ => (Color::White, Color::Gray),                                 // Fallback
+                    "AND" | "OR" | "XOR" | "NOT" | "<<" | ">>" => (Color::Cyan, Color::Cyan), // Bitwise
+                    "gcd" | "lcm" | "prime?" | "factor" => (Color::LightCyan, Color::LightCyan), // Number theory
+                    "N" | "I/Y" | "PV" | "PMT" | "FV" => (Color::LightGreen, Color::LightGreen), // TVM values
+                    "CPT" => (Color::Yellow, Color::Yellow), // Compute
+                    "%chg" | "markup" | "margin" => (Color::LightYellow, Color::LightYellow), // Percentages
+                    _ => (Color::White, Color::Gray), // Fallback
                 };
                 (color, border, false)
             };
 
-            let text_style = Style::default().fg(text_color).add_modifier(if is_bold {
+            let mut text_style = Style::default().fg(text_color).add_modifier(if is_bold {
                 Modifier::BOLD
             } else {
                 Modifier::empty()
             });
+            if app
+                .button_flash
+                .is_some_and(|(row, col, _)| (row, col) == (app.scroll_offset + row_idx, col_idx))
+            {
+                text_style = text_style.add_modifier(Modifier::REVERSED);
+            }
 
-            let button = Paragraph::new(*label)
+            // Compact rows separate buttons with a single vertical rule instead of boxes
+            let borders = if !row_compact {
+                Borders::ALL
+            } else if col_idx + 1 < row.len() {
+                Borders::RIGHT
+            } else {
+                Borders::NONE
+            };
+            let block = Block::default()
+                .borders(borders)
+                .border_style(Style::default().fg(border_color))
+                .padding(Padding::top(if squeezed { rect.height / 2 } else { 0 }));
+            let width = block.inner(rect).width as usize;
+            let button = Paragraph::new(app.glyphs.fit(label, width))
                 .style(text_style)
                 .alignment(Alignment::Center)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .border_style(Style::default().fg(border_color)),
-                );
-            f.render_widget(button, row_chunks[col_idx]);
+                .block(block);
+            f.render_widget(button, rect);
         }
     }
 }
 
-fn draw_history(f: &mut Frame, app: &App, area: Rect) {
-    let history_items: Vec<ListItem> = app
-        .calculator_module
+fn history_items(app: &App) -> Vec<ListItem<'static>> {
+    app.calculator_module
         .history
         .iter()
         .rev() // Show most recent first
@@ -299,20 +995,58 @@ fn draw_history(f: &mut Frame, app: &App, area: Rect) {
                 format!("[{}] ", timestamp),
                 Style::default().fg(Color::Gray),
             )];
-            history_spans.extend(create_colored_expression(&entry.expression));
+            let expression = if app.config.normalize_history {
+                &entry.expression
+            } else {
+                entry.typed()
+            };
+            history_spans.extend(create_colored_expression(&app.glyphs.apply(expression)));
+            if let Some(context) = &entry.context {
+                history_spans.push(Span::styled(
+                    format!("  [{}]", context),
+                    Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::DIM),
+                ));
+            }
+            if app.is_slow(entry.duration) {
+                history_spans.push(Span::styled(
+                    format!(
+                        "  {} {}",
+                        app.glyphs.apply("🐢"),
+                        format_elapsed(entry.duration)
+                    ),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            if let Some(digits) = entry.reliable_digits {
+                history_spans.push(Span::styled(
+                    format!(" {}", app.glyphs.apply(&cancellation_badge(digits))),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
 
             ListItem::new(vec![
                 Line::from(history_spans),
                 Line::from(vec![
                     Span::raw("  "),
                     Span::styled("= ", Style::default().fg(Color::Gray)),
-                    Span::styled(&entry.result, Style::default().fg(Color::Green)),
+                    Span::styled(
+                        calculator::group_digits(
+                            &entry.result,
+                            app.calculator_module.digit_grouping,
+                        ),
+                        Style::default().fg(Color::Green),
+                    ),
                 ]),
             ])
             .style(style)
         })
-        .collect();
+        .collect()
+}
 
+fn draw_history(f: &mut Frame, app: &App, area: Rect) {
+    let history_items = history_items(app);
     if history_items.is_empty() {
         let empty_widget = Paragraph::new(vec![
             Line::from("No calculations yet"),
@@ -329,9 +1063,16 @@ fn draw_history(f: &mut Frame, app: &App, area: Rect) {
         );
         f.render_widget(empty_widget, area);
     } else {
+        // A half-width pane has room for the keys but not their descriptions
+        let title = "History (h: back to calc, ↑↓: navigate, r: recall)";
+        let title = if title.chars().count() + 2 > area.width as usize {
+            "History (h: back, ↑↓, r: recall)"
+        } else {
+            title
+        };
         let history_list = List::new(history_items).block(
             Block::default()
-                .title("History (h: back to calc, ↑↓: navigate, r: recall)")
+                .title(title)
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Cyan)),
         );
@@ -339,11 +1080,36 @@ fn draw_history(f: &mut Frame, app: &App, area: Rect) {
     }
 }
 
-fn draw_status(f: &mut Frame, app: &App, area: Rect) {
+/// Characters of the alternate expression shown in the status line.
+const ALTERNATE_PREVIEW: usize = 12;
+
+/// The first `max` chars of `text`, with an ellipsis when it is longer.
+fn preview(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(max - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Red for a malformed expression, a typing slip; magenta for a well formed one with no
+/// value, such as a division by zero.
+fn error_color(app: &App) -> Color {
+    match &app.calculator_module.error {
+        Some(error) if !error.is_syntax() => Color::Magenta,
+        _ => Color::Red,
+    }
+}
+
+/// The status message or error, with the macro badge and any suggested fix.
+fn status_spans(app: &App) -> Vec<Span<'static>> {
     let (status_text, status_style) = if let Some(ref error) = app.calculator_module.error_message {
         (
             error.clone(),
-            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            Style::default()
+                .fg(error_color(app))
+                .add_modifier(Modifier::BOLD),
         )
     } else {
         (
@@ -352,12 +1118,60 @@ fn draw_status(f: &mut Frame, app: &App, area: Rect) {
         )
     };
 
+    let mut spans = vec![Span::styled(status_text, status_style)];
+    if app.macros.is_recording() {
+        spans.insert(
+            0,
+            Span::styled(
+                "● REC ",
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+        );
+    }
+    if let Some(hint) = &app.calculator_module.error_hint {
+        spans.push(Span::styled(" | ", Style::default().fg(Color::Gray)));
+        spans.push(Span::styled(
+            hint.clone(),
+            Style::default().fg(Color::Yellow),
+        ));
+    } else if let Some(hint) = &app.typing_hint
+        && app.state == AppState::Typing
+        && app.calculator_module.error_message.is_none()
+    {
+        spans.push(Span::styled(" | ", Style::default().fg(Color::Gray)));
+        spans.push(Span::styled(hint.clone(), Style::default().fg(Color::Cyan)));
+    } else if let Some(alternate) = &app.calculator_module.alternate_expression
+        && app.state != AppState::Graph
+    {
+        spans.push(Span::styled(
+            format!(" | ^O: {}", preview(alternate, ALTERNATE_PREVIEW)),
+            Style::default().fg(Color::Gray),
+        ));
+    }
+    spans
+}
+
+/// `elapsed` in milliseconds, or seconds once there are more than a thousand of them.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    match elapsed.as_millis() {
+        ms @ 0..1000 => format!("{} ms", ms),
+        _ => format!("{:.1} s", elapsed.as_secs_f64()),
+    }
+}
+
+/// The flag on a result that kept only `digits` reliable digits through cancellation.
+fn cancellation_badge(digits: u32) -> String {
+    let plural = if digits == 1 { "" } else { "s" };
+    format!(" ⚠ cancellation, ~{} reliable digit{}", digits, plural)
+}
+
+fn draw_status(f: &mut Frame, app: &App, area: Rect) {
     let help_text = match app.state {
         AppState::Normal => {
             if app.show_history {
                 "h: Back to calculator | ↑↓: Navigate history | r: Recall | q: Quit"
             } else {
-                "`: Typing mode | m: Toggle mode | h: History | 2nd: Variables | Ctrl+g: Graph | ←→↑↓: Navigate | Enter/Space/Mouse: Press button | q: Quit"
+                "`: Typing mode | m: Toggle mode | d: DEG/RAD | h: History | 2nd: Variables | Ctrl+g: Graph | ←→↑↓: Navigate | Enter/Space/Mouse: Press button | q: Quit"
             }
         }
         AppState::Typing => match app.calculator_module.mode {
@@ -365,17 +1179,38 @@ fn draw_status(f: &mut Frame, app: &App, area: Rect) {
                 "Typing Mode: Basic (m: switch to scientific, h: history, Ctrl+g: graph, `: exit, type expressions with variables)"
             }
             crate::calculator::CalculatorMode::Scientific => {
-                "Typing Mode: Scientific (m: switch to basic, h: history, Ctrl+g: graph, `: exit, type expressions with variables)"
+                "Typing Mode: Scientific (m: switch to programmer, h: history, Ctrl+g: graph, `: exit, type expressions with variables)"
+            }
+            crate::calculator::CalculatorMode::Programmer => {
+                "Typing Mode: Programmer (m: switch to financial, h: history, Ctrl+g: graph, `: exit, integers only; 0x, 0b and 0o literals)"
+            }
+            crate::calculator::CalculatorMode::Financial => {
+                "Typing Mode: Financial (m: switch to basic, h: history, Ctrl+g: graph, `: exit, N I/Y PV PMT FV keys in button mode)"
             }
         },
-        AppState::Graph => "Graph Mode: ↑↓←→ pan | +/- zoom | r reset | c toggle coords | Esc exit",
+        AppState::Graph => {
+            "Graph Mode: ↑↓←→ pan | +/- zoom | r reset | c toggle coords | t tangent | Esc exit"
+        }
+        AppState::Convert => {
+            "Unit Converter: Tab/←→ list | ↑↓ choose | 0-9 value | s swap | Enter use | Esc exit"
+        }
+        AppState::Stats => {
+            "Statistics: 0-9 value | Enter/↑↓ row | Tab/←→ column | Del row | f fit | g plot | Esc exit"
+        }
+        AppState::Matrix => {
+            "Matrix: 0-9 value | Tab/arrows cell | r/R row | c/C column | Enter insert | Esc exit"
+        }
+        AppState::Dates => {
+            "Dates: 0-9 - date or days | Tab/↑↓ field | t today | Enter calculate | Esc exit"
+        }
     };
 
+    let mut spans = status_spans(app);
+    let prefix = Span::styled("Status: ", Style::default().fg(Color::Cyan));
+    spans.insert(usize::from(app.macros.is_recording()), prefix);
+
     let status = Paragraph::new(vec![
-        Line::from(vec![
-            Span::styled("Status: ", Style::default().fg(Color::Cyan)),
-            Span::styled(status_text, status_style),
-        ]),
+        Line::from(spans),
         Line::from(vec![
             Span::styled("Help: ", Style::default().fg(Color::Gray)),
             Span::styled(help_text, Style::default().fg(Color::Gray)),
@@ -390,23 +1225,64 @@ fn draw_status(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(status, area);
 }
 
-fn draw_graph(f: &mut Frame, app: &App, area: Rect, _terminal_size: Rect) {
-    // Generate graph points if needed
-    if app.graph_module.points.is_empty() {
-        // We'll generate points in the main loop, for now just show a placeholder
-    }
+fn screen_layout(area: Rect) -> Rc<[Rect]> {
+    Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Title
+            Constraint::Min(0),    // Main content
+            Constraint::Length(3), // Status
+        ])
+        .split(area)
+}
 
-    let chunks = Layout::default()
+fn graph_layout(area: Rect) -> Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3), // Expression display
             Constraint::Min(0),    // Graph area
             Constraint::Length(3), // Controls info
         ])
-        .split(area);
+        .split(area)
+}
+
+/// Where graph mode plots the curve for a terminal of this size.
+pub fn graph_plot_area(terminal_size: Rect, mode: LayoutMode) -> Rect {
+    let area = match mode {
+        LayoutMode::Mini => terminal_size,
+        _ => graph_layout(screen_layout(terminal_size)[1])[1],
+    };
+    Block::default().borders(Borders::ALL).inner(area)
+}
+
+fn draw_graph(f: &mut Frame, app: &App, area: Rect, _terminal_size: Rect) {
+    // Generate graph points if needed
+    if app.graph_module.points.is_empty() {
+        // We'll generate points in the main loop, for now just show a placeholder
+    }
 
-    // Draw expression
-    let expression_text = format!("f(x) = {}", app.graph_expression);
+    let chunks = graph_layout(area);
+
+    // Draw expression, or say what data is plotted
+    let data = app.graph_module.scatter.len();
+    // A parametric curve gives the point itself, in terms of t, a polar one names r
+    // already and an implicit one is plotted where it's 0
+    let curves = || graph::curves(&app.graph_expression);
+    let (name, equals) = if curves().all(|curve| graph::polar(curve).is_some()) {
+        ("", "")
+    } else if curves().all(graph::is_parametric) {
+        ("(x, y) = ", "")
+    } else if curves().all(graph::is_implicit) {
+        ("", " = 0")
+    } else {
+        ("f(x) = ", "")
+    };
+    let expression_text = match (app.graph_expression.as_str(), data) {
+        ("", _) => format!("{} data point{}", data, if data == 1 { "" } else { "s" }),
+        (expression, 0) => format!("{}{}{}", name, expression, equals),
+        (expression, _) => format!("{}{}{} over {} data points", name, expression, equals, data),
+    };
     let expression_para = Paragraph::new(expression_text)
         .style(
             Style::default()
@@ -425,7 +1301,14 @@ fn draw_graph(f: &mut Frame, app: &App, area: Rect, _terminal_size: Rect) {
     draw_graph_area(f, app, chunks[1]);
 
     // Draw controls info
-    let controls_text = "Controls: ↑↓←→ pan | +/- zoom | r reset | c toggle coords | Esc exit";
+    let controls_text = format!(
+        "{}: ↑↓←→ pan | +/- zoom | r reset | c coords | ? help | Esc exit",
+        if app.aspect_lock {
+            "Aspect 1:1"
+        } else {
+            "Controls"
+        }
+    );
     let controls_para = Paragraph::new(controls_text)
         .style(Style::default().fg(Color::Yellow))
         .alignment(Alignment::Center)
@@ -438,89 +1321,86 @@ fn draw_graph(f: &mut Frame, app: &App, area: Rect, _terminal_size: Rect) {
 }
 
 fn draw_graph_area(f: &mut Frame, app: &App, area: Rect) {
-    // Create a simple text-based graph
-    let mut graph_lines = Vec::new();
-
-    // Calculate graph dimensions
-    let width = area.width as usize;
-    let height = area.height as usize;
-
-    // Create a 2D grid to represent the graph
-    let mut grid = vec![vec![' '; width]; height];
-
-    // Draw axes
-    let x_axis_y = height / 2;
-    let y_axis_x = width / 2;
-
-    // Draw x-axis
-    for x in 0..width {
-        grid[x_axis_y][x] = '─';
-    }
-
-    // Draw y-axis
-    for row in grid.iter_mut().take(height) {
-        row[y_axis_x] = '│';
-    }
-
-    // Draw origin
-    if x_axis_y < height && y_axis_x < width {
-        grid[x_axis_y][y_axis_x] = '┼';
-    }
-
-    // Draw graph points
-    for point in &app.graph_module.points {
-        let x_ratio = (point.x - app.graph_x_min) / (app.graph_x_max - app.graph_x_min);
-        let y_ratio = (point.y - app.graph_y_min) / (app.graph_y_max - app.graph_y_min);
-
-        let graph_x = (x_ratio * (width - 1) as f64) as usize;
-        let graph_y = ((1.0 - y_ratio) * (height - 1) as f64) as usize;
-
-        if graph_x < width && graph_y < height {
-            grid[graph_y][graph_x] = '●';
-        }
+    // The grid fills the inside of the border
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    let width = inner.width as usize;
+    let height = inner.height as usize;
+    if width == 0 || height == 0 {
+        return;
     }
 
-    // Draw cursor position if enabled
-    if app.show_cursor_coords {
-        let x_ratio = (app.graph_cursor_x - app.graph_x_min) / (app.graph_x_max - app.graph_x_min);
-        let y_ratio = (app.graph_cursor_y - app.graph_y_min) / (app.graph_y_max - app.graph_y_min);
-
-        let cursor_x = (x_ratio * (width - 1) as f64) as usize;
-        let cursor_y = ((1.0 - y_ratio) * (height - 1) as f64) as usize;
-
-        if cursor_x < width && cursor_y < height {
-            grid[cursor_y][cursor_x] = '×';
-        }
-    }
+    // Draw into the app's grid buffer, which only grows when the area does
+    let mut grid = app.graph_grid.borrow_mut();
+    let cursor = app
+        .show_cursor_coords
+        .then_some((app.graph_cursor_x, app.graph_cursor_y));
+    graph::draw_grid(
+        &mut grid,
+        width,
+        height,
+        &app.graph_module,
+        graph::Charset::Unicode,
+        cursor,
+    );
 
-    // Convert grid to text lines
-    for row in grid {
-        let line: String = row.iter().collect();
-        graph_lines.push(line);
-    }
+    // Convert grid to text lines, with the undefined band and gridlines dimmed and the
+    // tick labels in gray
+    let band = Style::default().fg(Color::DarkGray);
+    let style_of = |ch: char| match ch {
+        '░' | '┊' | '┈' | '∙' => Some(band),
+        '0'..='9' | '.' | '-' => Some(Style::default().fg(Color::Gray)),
+        '◉' => Some(Style::default().fg(Color::Yellow)),
+        '▒' => Some(Style::default().fg(Color::Cyan)),
+        '┆' => Some(Style::default().fg(Color::Magenta)),
+        _ => None,
+    };
+    let graph_lines: Vec<Line> = grid
+        .chunks(width)
+        .map(|row| {
+            let mut spans = Vec::new();
+            for run in row.chunk_by(|a, b| style_of(*a) == style_of(*b)) {
+                let text: String = run.iter().collect();
+                spans.push(match style_of(run[0]) {
+                    Some(style) => Span::styled(text, style),
+                    None => Span::raw(text),
+                });
+            }
+            Line::from(spans)
+        })
+        .collect();
 
     // Create the graph widget
-    let graph_text = graph_lines.join("\n");
-    let graph_para = Paragraph::new(graph_text)
+    let mut block = Block::default()
+        .title("Graph")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Green));
+    if let Some(note) = graph::describe_undefined(&app.graph_module.domain) {
+        block = block.title(Line::styled(format!(" {} ", note), band).right_aligned());
+    }
+    let graph_para = Paragraph::new(graph_lines)
         .style(Style::default().fg(Color::Green))
-        .block(
-            Block::default()
-                .title("Graph")
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Green)),
-        );
+        .block(block);
     f.render_widget(graph_para, area);
 
+    if let Some(findings) = &app.graph_module.findings {
+        draw_findings(f, findings, inner);
+    }
+
     // Draw coordinate info
     if app.show_cursor_coords {
         let coord_text = format!(
-            "Cursor: ({:.2}, {:.2}) | Range: x[{:.1}, {:.1}] y[{:.1}, {:.1}]",
+            "Cursor: ({:.2}, {:.2}) | Range: x[{:.1}, {:.1}] y[{:.1}, {:.1}]{}",
             app.graph_cursor_x,
             app.graph_cursor_y,
             app.graph_x_min,
             app.graph_x_max,
             app.graph_y_min,
-            app.graph_y_max
+            app.graph_y_max,
+            if app.graph_module.is_defined_at(app.graph_cursor_x) {
+                ""
+            } else {
+                " | undefined"
+            }
         );
 
         // Draw coordinate info in a small area at the bottom
@@ -538,78 +1418,462 @@ fn draw_graph_area(f: &mut Frame, app: &App, area: Rect) {
                     .borders(Borders::ALL)
                     .border_style(Style::default().fg(Color::Cyan)),
             );
+        // Over the plot, none of which should show through
+        f.render_widget(Clear, coord_area);
         f.render_widget(coord_para, coord_area);
     }
 }
 
-fn draw_help(f: &mut Frame, _app: &App, area: Rect) {
-    let help_text = vec![
-        "Calculator Help",
-        "",
-        "Navigation:",
-        "  ↑↓←→     Navigate buttons",
-        "  Enter    Press selected button",
-        "  Space    Press selected button",
-        "  Mouse    Click button directly",
-        "",
-        "Modes:",
-        "  `        Toggle typing mode",
-        "  m        Switch Basic/Scientific modes",
-        "  h        Toggle calculation history",
-        "  2nd      Access secondary functions",
-        "  ?        Show this help (Esc to close)",
-        "",
-        "Operations:",
-        "  c        Clear current expression",
-        "  C        Clear all (expression and history)",
-        "  ⌫        Backspace",
-        "  r        Recall from history",
-        "",
-        "Graphing:",
-        "  Ctrl+g   Graph current expression (always available)",
-        "  Graph    Graph button (2nd function mode only)",
-        "  ↑↓←→     Pan graph view",
-        "  +/-      Zoom in/out",
-        "  r        Reset view to default range",
-        "  c        Toggle coordinate display",
-        "  Esc      Exit graph mode",
-        "",
-        "Variables (2nd function mode):",
-        "  x, y, z  Primary variables",
-        "  a, b, c  Secondary variables",
-        "  π        Pi constant (3.14159)",
-        "  e        Euler's number (2.71828)",
-        "",
-        "Scientific Functions (Scientific mode):",
-        "  sin, cos, tan  Trigonometric functions",
-        "  log, ln        Logarithmic functions",
-        "  √, exp         Square root, exponential",
-        "  abs, 1/x, x²   Absolute value, reciprocal, square",
-        "",
-        "Exit:",
-        "  q        Quit application",
-        "  Esc      Close help or quit",
+/// The points found on the curve, listed in the top right corner of the plot `area`,
+/// as many as fit.
+fn draw_findings(f: &mut Frame, findings: &graph::Findings, area: Rect) {
+    let mut lines = findings.lines();
+    let rows = usize::from(area.height.saturating_sub(2));
+    if lines.len() > rows {
+        let hidden = lines.len() - rows.saturating_sub(1);
+        lines.truncate(rows.saturating_sub(1));
+        lines.push(format!("… {} more", hidden));
+    }
+    let title = format!(" {} ", findings.feature.name());
+    let widest = lines
+        .iter()
+        .chain([&title])
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let width = (widest as u16 + 4).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let panel = Rect::new(area.right().saturating_sub(width), area.y, width, height);
+    f.render_widget(Clear, panel);
+    let list = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+        .style(Style::default().fg(Color::Yellow))
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .padding(Padding::horizontal(1))
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+    f.render_widget(list, panel);
+}
+
+/// The unit converter: the value and what it converts to, over the category and unit
+/// lists side by side. The focused list is outlined in yellow.
+fn draw_converter(f: &mut Frame, app: &App, area: Rect) {
+    let converter = &app.converter;
+    let block = Block::default()
+        .title(" Unit converter (Tab list, ↑↓ choose, s swap, Enter use) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let rows = Layout::vertical([Constraint::Length(2), Constraint::Min(0)]).split(inner);
+
+    let label_style = Style::default().fg(Color::Gray);
+    let result = converter
+        .result()
+        .map_or_else(|| "-".to_string(), calculator::format_result);
+    let readout = vec![
+        Line::from(vec![
+            Span::styled("Value: ", label_style),
+            Span::raw(format!(
+                "{} {}",
+                converter.input,
+                converter.from_unit().symbol
+            )),
+        ]),
+        Line::from(vec![
+            Span::styled("    = ", label_style),
+            Span::styled(
+                format!("{} {}", result, converter.to_unit().symbol),
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
     ];
+    f.render_widget(Paragraph::new(readout), rows[0]);
 
-    let help_items: Vec<ListItem> = help_text
+    let categories: Vec<String> = Category::ALL
         .iter()
-        .map(|line| {
-            let style = if line.is_empty() {
-                Style::default()
-            } else if line.starts_with("  ") {
-                Style::default().fg(Color::White)
-            } else if line.ends_with(':') {
+        .map(|category| category.label().to_string())
+        .collect();
+    let units: Vec<String> = converter
+        .units()
+        .iter()
+        .map(|unit| format!("{:<4}{}", unit.symbol, unit.name))
+        .collect();
+    let lists = [
+        (Field::Category, "Category", categories, converter.category),
+        (Field::From, "From", units.clone(), converter.from),
+        (Field::To, "To", units, converter.to),
+    ];
+    let columns = Layout::horizontal([Constraint::Ratio(1, 3); 3]).split(rows[1]);
+    for ((field, title, items, selected), &column) in lists.into_iter().zip(columns.iter()) {
+        let focused = converter.focus == field;
+        let (border, highlight) = if focused {
+            (Color::Yellow, choice_style(true))
+        } else {
+            (
+                Color::DarkGray,
                 Style::default()
                     .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD)
-            } else {
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD)
+                    .add_modifier(Modifier::BOLD),
+            )
+        };
+        let list = List::new(items)
+            .style(Style::default().fg(Color::White))
+            .highlight_style(highlight)
+            .highlight_symbol("> ")
+            .highlight_spacing(HighlightSpacing::Always)
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(border)),
+            );
+        // The state scrolls long lists to keep the selection in view
+        let mut state = ListState::default().with_selected(Some(selected));
+        f.render_stateful_widget(list, column, &mut state);
+    }
+}
+
+/// The statistics panel: the data table beside the statistics of what is entered.
+fn draw_stats(f: &mut Frame, app: &App, area: Rect) {
+    let table = &app.stats;
+    let block = Block::default()
+        .title(" Statistics (Enter next row, Tab column, g plot, ? help) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let panes =
+        Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).split(inner);
+    let label_style = Style::default().fg(Color::Gray);
+
+    let data_block = Block::default()
+        .title("Data")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+    let data_area = data_block.inner(panes[0]);
+    f.render_widget(data_block, panes[0]);
+    let width = usize::from(data_area.width.saturating_sub(5) / 2);
+    let mut lines = vec![Line::styled(
+        format!("{:>4} {:>w$}{:>w$}", "#", "x", "y", w = width),
+        label_style,
+    )];
+    // Scroll to keep the selected row in view; the row after the last takes new values
+    let visible = usize::from(data_area.height.saturating_sub(1)).max(1);
+    let first = (table.row + 1).saturating_sub(visible);
+    for row in (first..=table.rows.len()).take(visible) {
+        let mut spans = vec![Span::styled(format!("{:>4} ", row + 1), label_style)];
+        for column in [Column::X, Column::Y] {
+            let selected = row == table.row && column == table.column;
+            let text = match table.cell(row, column) {
+                _ if selected && !table.input.is_empty() => format!("{}_", table.input),
+                Some(value) => calculator::format_result(value),
+                None => String::new(),
             };
-            ListItem::new(*line).style(style)
+            spans.push(Span::styled(
+                format!("{:>w$}", text, w = width),
+                choice_style(selected),
+            ));
+        }
+        lines.push(Line::from(spans));
+    }
+    f.render_widget(Paragraph::new(lines), data_area);
+
+    let heading = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let text =
+        |value: Option<f64>| value.map_or_else(|| "-".to_string(), calculator::format_result);
+    let stat = |label: &str, value: Option<f64>| {
+        Line::from(vec![
+            Span::styled(format!("{:<10}", label), label_style),
+            Span::raw(text(value)),
+        ])
+    };
+    // Two statistics side by side, so every section fits on a short terminal
+    let stat_pair = |left: (&str, Option<f64>), right: (&str, Option<f64>)| {
+        Line::from(vec![
+            Span::styled(format!("{:<7}", left.0), label_style),
+            Span::raw(format!("{:<13}", text(left.1))),
+            Span::styled(format!("{:<7}", right.0), label_style),
+            Span::raw(text(right.1)),
+        ])
+    };
+    let name = table.column.label();
+    let mut lines = Vec::new();
+    match stats::summarize(&table.values(table.column)) {
+        Some(summary) => {
+            lines.push(Line::styled(format!("One variable ({})", name), heading));
+            lines.extend([
+                stat_pair(
+                    ("n", Some(summary.count as f64)),
+                    (&format!("Σ{}", name), Some(summary.sum)),
+                ),
+                stat_pair(
+                    ("mean", Some(summary.mean)),
+                    ("median", Some(summary.median)),
+                ),
+                stat_pair(
+                    (&format!("s{}", name), summary.sample_sd),
+                    (&format!("σ{}", name), Some(summary.population_sd)),
+                ),
+                stat_pair(("min", Some(summary.min)), ("max", Some(summary.max))),
+                stat_pair(("Q1", Some(summary.q1)), ("Q3", Some(summary.q3))),
+            ]);
+        }
+        None => lines.push(Line::styled(
+            format!("Type values into {} to see their statistics", name),
+            label_style,
+        )),
+    }
+    let pairs = table.pairs();
+    if let Some(paired) = stats::pair_stats(&pairs) {
+        lines.push(Line::raw(""));
+        lines.push(Line::styled("Two variable (x, y)", heading));
+        lines.extend([
+            stat_pair(
+                ("pairs", Some(paired.count as f64)),
+                ("r", paired.correlation),
+            ),
+            stat_pair(
+                ("mean x", Some(paired.mean_x)),
+                ("mean y", Some(paired.mean_y)),
+            ),
+            stat_pair(("Σxy", Some(paired.sum_xy)), ("cov", paired.covariance)),
+        ]);
+        let model = table.model;
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            format!("{} fit  {}", model.label(), model.equation()),
+            heading,
+        ));
+        match stats::fit(model, &pairs) {
+            Some(fit) => {
+                let labels: &[&str] = match model {
+                    Model::Linear => &["intercept", "slope"],
+                    _ => &["a", "b", "c"],
+                };
+                for (label, &value) in labels.iter().zip(&fit.coefficients) {
+                    lines.push(stat(label, Some(value)));
+                }
+                lines.push(stat("R²", fit.r_squared));
+            }
+            None => lines.push(Line::styled(model.requirement(), label_style)),
+        }
+    }
+    let stats_block = Block::default()
+        .title("Statistics")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::DarkGray));
+    f.render_widget(Paragraph::new(lines).block(stats_block), panes[1]);
+}
+
+/// The matrix editor: the grid being typed beside its size and determinant.
+fn draw_matrix_editor(f: &mut Frame, app: &App, area: Rect) {
+    let editor = &app.matrix_editor;
+    let (rows, cols) = editor.matrix.size();
+    let block = Block::default()
+        .title(" Matrix Editor (Tab next cell, Enter insert, ? help) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let panes =
+        Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)]).split(inner);
+    let label_style = Style::default().fg(Color::Gray);
+
+    let grid_block = Block::default()
+        .title(format!("{}×{}", rows, cols))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+    let grid_area = grid_block.inner(panes[0]);
+    f.render_widget(grid_block, panes[0]);
+    // Columns share the width, each at least wide enough for a short number
+    let width = usize::from(grid_area.width / cols as u16).max(6);
+    let lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let spans = (0..cols).map(|col| {
+                let selected = (row, col) == (editor.row, editor.col);
+                let text = match selected && !editor.input.is_empty() {
+                    true => format!("{}_", editor.input),
+                    false => calculator::format_result(editor.matrix.get(row, col)),
+                };
+                Span::styled(
+                    format!("{:>w$} ", text, w = width - 1),
+                    choice_style(selected),
+                )
+            });
+            Line::from(spans.collect::<Vec<_>>())
         })
         .collect();
+    f.render_widget(Paragraph::new(lines), grid_area);
+
+    let det = match editor.matrix.determinant() {
+        Ok(det) => calculator::format_result(det),
+        Err(_) => "- (not square)".to_string(),
+    };
+    let lines = vec![
+        Line::from(vec![Span::styled("det  ", label_style), Span::raw(det)]),
+        Line::raw(""),
+        Line::styled("r/R and c/C add or remove rows and columns", label_style),
+        Line::raw(""),
+        Line::styled(
+            "Enter inserts it at the cursor, to wrap in det(), inv() or transpose(), or multiply with *",
+            label_style,
+        ),
+    ];
+    let info_block = Block::default()
+        .title("Matrix")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::DarkGray));
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(info_block)
+            .wrap(Wrap { trim: true }),
+        panes[1],
+    );
+}
+
+/// The date calculator: two dates with their weekdays and the days between, then the
+/// start moved by a number of days.
+fn draw_dates(f: &mut Frame, app: &App, area: Rect) {
+    let panel = &app.dates;
+    let block = Block::default()
+        .title(" Date calculator (Tab move, t today, Enter calculate) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let label_style = Style::default().fg(Color::Gray);
+    let result_style = Style::default()
+        .fg(Color::Green)
+        .add_modifier(Modifier::BOLD);
+    let weekday = |date: Option<NaiveDate>| date.map_or("", dates::weekday_name);
+    let field = |label: &'static str, text: &str, focus: DateField, note: &'static str| {
+        let focused = panel.focus == focus;
+        let text = if focused {
+            format!("{}_", text)
+        } else {
+            text.to_string()
+        };
+        Line::from(vec![
+            Span::styled(format!("{:<13}", label), label_style),
+            Span::styled(format!("{:<11}", text), choice_style(focused)),
+            Span::styled(format!(" {}", note), label_style),
+        ])
+    };
+    let between = match panel.between() {
+        Some(days) => {
+            let (weeks, rest) = (days.abs() / 7, days.abs() % 7);
+            let plural = |n: i64| if n == 1 { "" } else { "s" };
+            format!(
+                "{} day{} ({} week{} {} day{})",
+                days,
+                plural(days.abs()),
+                weeks,
+                plural(weeks),
+                rest,
+                plural(rest)
+            )
+        }
+        None => "-".to_string(),
+    };
+    let shifted = panel.shifted().map_or_else(
+        || "-".to_string(),
+        |date| format!("{}  {}", date.format("%Y-%m-%d"), dates::weekday_name(date)),
+    );
+    let lines = vec![
+        field(
+            "Start",
+            &panel.start,
+            DateField::Start,
+            weekday(panel.start_date()),
+        ),
+        field("End", &panel.end, DateField::End, weekday(panel.end_date())),
+        Line::from(vec![
+            Span::styled(format!("{:<13}", "Between"), label_style),
+            Span::styled(between, result_style),
+        ]),
+        Line::raw(""),
+        field("Days", &panel.days, DateField::Days, "added to the start"),
+        Line::from(vec![
+            Span::styled(format!("{:<13}", "Start + days"), label_style),
+            Span::styled(shifted, result_style),
+        ]),
+        Line::raw(""),
+        Line::styled(
+            "Enter calculates the days between, or the start plus the days when they are selected",
+            label_style,
+        ),
+        Line::styled(
+            "In expressions: \"2024-06-15\" + 90, weekday(today()), days_between(a, b)",
+            label_style,
+        ),
+    ];
+    f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), inner);
+}
+
+fn draw_help(f: &mut Frame, app: &App, area: Rect) {
+    let state_name = match app.state {
+        AppState::Normal => "Button Navigation",
+        AppState::Typing => "Typing Mode",
+        AppState::Graph => "Graph Mode",
+        AppState::Convert => "Unit Converter",
+        AppState::Stats => "Statistics",
+        AppState::Matrix => "Matrix Editor",
+        AppState::Dates => "Date Calculator",
+    };
+    // Keys that run a command are listed as the config binds them
+    let keys = |binding: &keymap::Binding| match binding.command {
+        Some(command) => {
+            let keys = app.keymap.shortcuts(app.state, command);
+            if keys.is_empty() {
+                "-".to_string()
+            } else {
+                keys.join(" / ")
+            }
+        }
+        None => app.glyphs.apply(binding.keys).into_owned(),
+    };
+    let key_width = keymap::sections(app.state)
+        .iter()
+        .flat_map(|section| section.bindings)
+        .map(|binding| keys(binding).chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut help_items = vec![
+        ListItem::new(format!("{} Help", state_name)).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ),
+    ];
+    for section in keymap::sections(app.state) {
+        help_items.push(ListItem::new(""));
+        help_items.push(
+            ListItem::new(format!("{}:", section.title)).style(
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        );
+        for binding in section.bindings {
+            help_items.push(
+                ListItem::new(format!(
+                    "  {:<width$}  {}",
+                    keys(binding),
+                    app.glyphs.apply(binding.action),
+                    width = key_width
+                ))
+                .style(Style::default().fg(Color::White)),
+            );
+        }
+    }
 
     let help_list = List::new(help_items).block(
         Block::default()
@@ -619,3 +1883,408 @@ fn draw_help(f: &mut Frame, _app: &App, area: Rect) {
     );
     f.render_widget(help_list, area);
 }
+
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + area.width.saturating_sub(width) / 2,
+        area.y + area.height.saturating_sub(height) / 2,
+        width,
+        height,
+    )
+}
+
+fn choice_style(selected: bool) -> Style {
+    if selected {
+        Style::default()
+            .fg(Color::Black)
+            .bg(Color::LightCyan)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::White)
+    }
+}
+
+fn draw_popup(f: &mut Frame, popup: &Popup, current_result: &str, area: Rect) {
+    let (title, lines) = match popup {
+        Popup::Palette(palette) => {
+            let mut lines = vec![Line::from(vec![
+                Span::styled("> ", Style::default().fg(Color::Cyan)),
+                Span::raw(palette.filter.clone()),
+            ])];
+            for (idx, command) in palette.matches().iter().enumerate() {
+                lines.push(Line::styled(
+                    command.label(),
+                    choice_style(idx == palette.selected),
+                ));
+            }
+            ("Commands (↑↓ select, Enter run, Esc close)", lines)
+        }
+        Popup::ImportPrompt(input) => (
+            "Import share string (Enter import, Esc cancel)",
+            vec![
+                Line::styled("Paste a share string:", Style::default().fg(Color::Gray)),
+                Line::from(vec![
+                    Span::styled("> ", Style::default().fg(Color::Cyan)),
+                    Span::raw(input.clone()),
+                ]),
+            ],
+        ),
+        Popup::Convert(chooser) => {
+            let mut lines = vec![Line::from(vec![
+                Span::styled("Result: ", Style::default().fg(Color::Gray)),
+                Span::styled(
+                    current_result.to_string(),
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD),
+                ),
+            ])];
+            for (idx, conversion) in CONVERSIONS.iter().enumerate() {
+                lines.push(Line::styled(
+                    format!("{:<18} {}(x)", conversion.label, conversion.name),
+                    choice_style(idx == chooser.selected),
+                ));
+            }
+            ("Convert result (Enter apply, Esc close)", lines)
+        }
+        Popup::Constants(chooser) => {
+            let lines = CONSTANTS
+                .iter()
+                .enumerate()
+                .map(|(idx, constant)| {
+                    let value = calculator::format_result(constant.value);
+                    Line::styled(
+                        format!(
+                            "{:<9}{:<20}{} {}",
+                            constant.name, constant.label, value, constant.unit
+                        ),
+                        choice_style(idx == chooser.selected),
+                    )
+                })
+                .collect();
+            ("Constants (Enter insert, Esc close)", lines)
+        }
+        Popup::Precision {
+            expression,
+            readings,
+        } => {
+            let mut lines = vec![Line::from(vec![
+                Span::styled("Expression: ", Style::default().fg(Color::Gray)),
+                Span::raw(expression.clone()),
+            ])];
+            let reference = readings
+                .first()
+                .map_or("", |reading| reading.value.as_str());
+            let width = readings.iter().map(|r| r.method.len()).max().unwrap_or(0);
+            for reading in readings {
+                let mut spans = vec![Span::styled(
+                    format!("{:<width$}  ", reading.method),
+                    Style::default().fg(Color::Cyan),
+                )];
+                // Digits that differ from the f64 result are the float artifacts
+                let differing = precision::differing(reference, &reading.value);
+                spans.extend(reading.value.chars().zip(differing).map(|(c, differs)| {
+                    let color = if differs { Color::Yellow } else { Color::Green };
+                    Span::styled(c.to_string(), Style::default().fg(color))
+                }));
+                lines.push(Line::from(spans));
+            }
+            ("Precision check (Esc close)", lines)
+        }
+        Popup::Confirm { message, .. } => (
+            "Confirm",
+            vec![Line::styled(
+                message.clone(),
+                Style::default().fg(Color::Yellow),
+            )],
+        ),
+        Popup::SharePreview(payload) => {
+            let mut lines = vec![Line::styled(
+                "The following will be applied:",
+                Style::default().fg(Color::Gray),
+            )];
+            lines.extend(
+                payload
+                    .describe()
+                    .into_iter()
+                    .map(|line| Line::styled(line, Style::default().fg(Color::White))),
+            );
+            ("Import preview (Enter/y apply, Esc/n cancel)", lines)
+        }
+    };
+
+    let popup_area = centered_rect(60, lines.len() as u16 + 2, area);
+    f.render_widget(Clear, popup_area);
+    let para = Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(para, popup_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::CalculatorMode;
+    use crate::glyphs::GlyphSet;
+    use ratatui::{Terminal, backend::TestBackend};
+
+    fn render(app: &App, width: u16, height: u16) {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| draw(f, app, f.area())).unwrap();
+    }
+
+    #[test]
+    fn parens_are_colored_by_depth() {
+        let colors = |expression: &str| -> Vec<Option<Color>> {
+            create_colored_expression(expression)
+                .iter()
+                .map(|span| span.style.fg)
+                .collect()
+        };
+        let [d0, d1, d2, _] = PAREN_COLORS.map(Some);
+        let (white, cyan) = (Some(Color::White), Some(Color::Cyan));
+
+        // Pairs share a color, and operators keep theirs
+        assert_eq!(
+            colors("((1)+(2))"),
+            [d0, d1, white, d1, cyan, d1, white, d1, d0]
+        );
+        assert_eq!(colors("((((()))))")[4], d0);
+        assert_eq!(colors("((()))")[2], d2);
+
+        // A closer with nothing to close is an error; the rest still pair up
+        let spans = create_colored_expression("1)+(2)");
+        assert_eq!(spans[1].style.fg, Some(Color::Red));
+        assert!(spans[1].style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(spans[3].style.fg, d0);
+        assert_eq!(spans[5].style.fg, d0);
+
+        // So is an opener never closed, and a bracket closing the other kind
+        let red = Some(Color::Red);
+        assert_eq!(colors("(1+(2)"), [red, white, cyan, d0, white, d0]);
+        assert_eq!(colors("[1)]")[2], red);
+        assert_eq!(colors("\"(\"")[1], Some(Color::White));
+    }
+
+    #[test]
+    fn digit_separators_are_dimmed() {
+        let dimmed = |expression: &str| -> Vec<usize> {
+            create_colored_expression(expression)
+                .iter()
+                .enumerate()
+                .filter(|(_, span)| span.style.fg == Some(Color::DarkGray))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        assert_eq!(dimmed("1_000+k_b"), [1]);
+        assert_eq!(dimmed("1_234_567.5"), [1, 5]);
+        assert_eq!(dimmed("max(1,234)"), []);
+    }
+
+    #[test]
+    fn sparkline_scales_to_the_range() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[5.0]), "▄");
+        assert_eq!(sparkline(&[2.0, 2.0, 2.0]), "▄▄▄");
+        assert_eq!(sparkline(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), "▁▂▃▄▅▆▇");
+        assert_eq!(sparkline(&[-3.0, 0.0, 3.0]), "▁▄▇");
+        assert_eq!(sparkline(&[-10.0, -9.9, -10.0]), "▁▇▁");
+
+        // Text results are skipped and only the newest that fit are charted
+        let mut app = App::new();
+        for expression in ["1", "2", "3", "ratio(1, 2)"] {
+            app.calculator_module.set_expression(expression.to_string());
+            app.calculator_module.calculate();
+        }
+        assert_eq!(history_sparkline(&app, 80), "▁▄▇");
+        assert_eq!(history_sparkline(&app, 2), "▁▇");
+    }
+
+    #[test]
+    fn click_columns_follow_padding_scrolling_and_wide_glyphs() {
+        let area = Rect::new(10, 3, 8, 1);
+
+        // "1+2" right-aligned: 5 columns of padding, then one column per char
+        let (first, padding, columns) = layout_expression(&[1, 1, 1], 3, 3, area);
+        assert_eq!((first, padding), (0, 5));
+        let at = |column| columns.index_at(column, 3);
+        assert_eq!(
+            [at(11), at(15), at(16), at(17)],
+            [Some(0), Some(0), Some(1), Some(2)]
+        );
+        assert_eq!((at(9), at(18), columns.index_at(16, 4)), (None, None, None));
+
+        // 12 chars plus the end-of-line cursor cell, scrolled so the cursor shows
+        let (first, padding, columns) = layout_expression(&[1; 13], 12, 12, area);
+        assert_eq!((first, padding), (5, 0));
+        assert_eq!(columns.index_at(10, 3), Some(5));
+        assert_eq!(columns.index_at(17, 3), Some(12));
+
+        // The same line with the cursor near the start shows the start and clips the rest
+        let (first, _, columns) = layout_expression(&[1; 13], 2, 12, area);
+        assert_eq!(first, 0);
+        assert_eq!(columns.index_at(17, 3), Some(7));
+
+        // Wide glyphs take two columns, and both columns map to the same char
+        let (first, padding, columns) = layout_expression(&[2, 1, 2], 0, 3, area);
+        assert_eq!((first, padding), (0, 3));
+        let at = |column| columns.index_at(column, 3);
+        assert_eq!(
+            [at(13), at(14), at(15), at(16), at(17)],
+            [Some(0), Some(0), Some(1), Some(2), Some(2)]
+        );
+
+        // A wide glyph cut off at the right edge is left off the map
+        let narrow = Rect::new(10, 3, 7, 1);
+        let (first, _, columns) = layout_expression(&[2, 2, 2, 2, 2], 0, 5, narrow);
+        assert_eq!(first, 0);
+        assert_eq!(columns.index_at(15, 3), Some(2));
+        assert_eq!(columns.index_at(16, 3), Some(3));
+    }
+
+    #[test]
+    fn slow_calculations_show_how_long_they_took() {
+        assert_eq!(format_elapsed(Duration::from_millis(340)), "340 ms");
+        assert_eq!(format_elapsed(Duration::from_millis(2_450)), "2.5 s");
+
+        let mut app = App::new();
+        app.calculator_module.set_expression("e".to_string());
+        app.calculate();
+        assert!(!app.status_message.contains(" in "));
+        app.report_duration(Duration::from_millis(340));
+        assert_eq!(app.status_message, "= 2.7182818285 in 340 ms");
+
+        app.calculator_module.history[0].duration = Duration::from_millis(340);
+        app.calculator_module.set_expression("1+1".to_string());
+        app.calculate();
+        app.toggle_history();
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal.draw(|f| draw(f, &app, f.area())).unwrap();
+        let rows: Vec<String> = (0..30)
+            .map(|y| {
+                (0..80)
+                    .map(|x| terminal.backend().buffer()[(x, y)].symbol())
+                    .collect()
+            })
+            .collect();
+        // Only the slow entry is flagged
+        let flagged: Vec<&String> = rows.iter().filter(|row| row.contains("🐢")).collect();
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].contains("] e") && flagged[0].contains("340 ms"));
+    }
+
+    #[test]
+    fn cancelled_results_are_flagged_with_their_reliable_digits() {
+        let mut app = App::new();
+        app.calculator_module
+            .set_expression("(1+10^-13)-1".to_string());
+        app.calculate();
+        let rows = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+            terminal.draw(|f| draw(f, app, f.area())).unwrap();
+            (0..30)
+                .map(|y| {
+                    (0..80)
+                        .map(|x| terminal.backend().buffer()[(x, y)].symbol())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(rows(&app).iter().any(
+            |row| row.contains("Result:") && row.contains("⚠ cancellation, ~2 reliable digits")
+        ));
+
+        app.glyphs = GlyphSet::Ascii;
+        app.toggle_history();
+        let flagged: Vec<String> = rows(&app)
+            .into_iter()
+            .filter(|row| row.contains("! cancellation"))
+            .collect();
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].contains("10^-13"), "{}", flagged[0]);
+    }
+
+    #[test]
+    fn radix_literals_show_their_values_beside_the_result() {
+        let mut app = App::new();
+        app.calculator_module
+            .set_expression("0xFF + 0b1010".to_string());
+        app.calculator_module.update_result();
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal.draw(|f| draw(f, &app, f.area())).unwrap();
+        let result_row = (0..30)
+            .map(|y| {
+                (0..80)
+                    .map(|x| terminal.backend().buffer()[(x, y)].symbol())
+                    .collect::<String>()
+            })
+            .find(|row| row.contains("Result:"))
+            .unwrap();
+        assert!(
+            result_row.contains("265 (0xFF = 255, 0b1010 = 10)"),
+            "{}",
+            result_row
+        );
+    }
+
+    #[test]
+    fn draws_without_panicking_on_tiny_terminals() {
+        let mut calculator = App::new();
+        calculator.calculator_module.current_expression = "12345678901234567890+(3*4)".into();
+        calculator.mouse_position = Some((5, 10));
+
+        let mut scientific = App::new();
+        scientific.calculator_module.mode = CalculatorMode::Scientific;
+        scientific.second_function_mode = true;
+        scientific.button_position = Some((5, 3));
+
+        let mut programmer = App::new();
+        programmer.calculator_module.mode = CalculatorMode::Programmer;
+        programmer.calculator_module.current_expression = "shl(1, 62)".into();
+        programmer.calculator_module.update_result();
+
+        let mut graph = App::new();
+        graph.calculator_module.current_expression = "x^2".into();
+        graph.enter_graph_mode();
+
+        let mut history = App::new();
+        history.calculator_module.current_expression = "1+2".into();
+        history.calculator_module.calculate();
+        history.toggle_history();
+
+        let mut help = App::new();
+        help.toggle_help();
+
+        let mut palette = App::new();
+        palette.open_palette();
+
+        let mut converter = App::new();
+        converter.open_converter();
+        converter.converter.focus_next();
+
+        for app in [
+            &calculator,
+            &scientific,
+            &programmer,
+            &graph,
+            &history,
+            &help,
+            &palette,
+            &converter,
+        ] {
+            for width in 1..=40 {
+                for height in 1..=15 {
+                    render(app, width, height);
+                }
+            }
+            for (width, height) in [(50, 20), (59, 29), (60, 30), (120, 40)] {
+                render(app, width, height);
+            }
+        }
+    }
+}
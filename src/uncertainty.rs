@@ -0,0 +1,173 @@
+//! Values with an uncertainty, such as `9.81±0.02`: a value and its standard error.
+//! Arithmetic and functions of one number carry the error through to first order,
+//! independent errors adding in quadrature:
+//!
+//! `σ_f² = (∂f/∂a·σ_a)² + (∂f/∂b·σ_b)²`
+//!
+//! Each slope is estimated by a central difference, so every function the evaluator
+//! knows carries errors without one being written for it. Every uncertain value counts
+//! as independent of the others, even the same one used twice, so `(2±0.1)*(2±0.1)` has
+//! a smaller error than `(2±0.1)^2`.
+
+use crate::calculator::{EvalError, EvalResult};
+use crate::eval::{BinaryOp, Value};
+
+/// `value ± error`, of two numbers, the error 0 or more.
+pub(crate) fn with_error(value: Value, error: Value) -> EvalResult<Value> {
+    let (value, error) = (value.number()?, error.number()?);
+    if error < 0.0 || error.is_nan() {
+        return Err(EvalError::Domain {
+            func: "±",
+            arg: error,
+            reason: "The error after ± must be 0 or more",
+        });
+    }
+    Ok(Value::Uncertain(value, error))
+}
+
+/// `f` of `value ± error`: what `f` gives for the value, with the error scaled by the
+/// slope of `f` there.
+pub(crate) fn propagate(
+    value: f64,
+    error: f64,
+    mut f: impl FnMut(f64) -> EvalResult<f64>,
+) -> EvalResult<Value> {
+    let result = f(value)?;
+    if error == 0.0 {
+        return Ok(Value::Uncertain(result, 0.0));
+    }
+    let slope = slope(f, value, result, error);
+    Ok(Value::Uncertain(result, (slope * error).abs()))
+}
+
+/// `left op right` where either has an uncertainty, for the arithmetic operators; the
+/// other may be a plain number. `binary` works the operator out on numbers.
+pub(crate) fn combine(
+    op: BinaryOp,
+    left: Value,
+    right: Value,
+    binary: impl Fn(BinaryOp, f64, f64) -> EvalResult<f64>,
+) -> EvalResult<Value> {
+    if !matches!(
+        op,
+        BinaryOp::Add
+            | BinaryOp::Subtract
+            | BinaryOp::Multiply
+            | BinaryOp::Divide
+            | BinaryOp::FloorDivide
+            | BinaryOp::Remainder
+            | BinaryOp::Power
+    ) {
+        return Err(EvalError::UnexpectedUncertain);
+    }
+    let ((a, error_a), (b, error_b)) = (parts(left)?, parts(right)?);
+    let result = binary(op, a, b)?;
+    let mut error = 0.0_f64;
+    if error_a != 0.0 {
+        error = error.hypot(slope(|a| binary(op, a, b), a, result, error_a) * error_a);
+    }
+    if error_b != 0.0 {
+        error = error.hypot(slope(|b| binary(op, a, b), b, result, error_b) * error_b);
+    }
+    Ok(Value::Uncertain(result, error))
+}
+
+/// The value and error of a number, which has none, or an uncertain value.
+fn parts(value: Value) -> EvalResult<(f64, f64)> {
+    match value {
+        Value::Number(value) => Ok((value, 0.0)),
+        Value::Uncertain(value, error) => Ok((value, error)),
+        _ => Err(EvalError::UnexpectedUncertain),
+    }
+}
+
+/// The slope of `f` at `x`, where it gives `fx`, over a step small next to both `x`
+/// and its `error`: a central difference, or one side only where `f` isn't defined on
+/// the other, as `sqrt` is at 0.
+fn slope(mut f: impl FnMut(f64) -> EvalResult<f64>, x: f64, fx: f64, error: f64) -> f64 {
+    // A power of two, so `x` plus or minus it is exact and a straight line's slope
+    // comes out exactly
+    let step = (x.abs().max(error) * 1e-6).log2().round().exp2();
+    let mut at = |x: f64| f(x).ok().filter(|y| y.is_finite());
+    match (at(x + step), at(x - step)) {
+        (Some(up), Some(down)) => (up - down) / (2.0 * step),
+        (Some(up), None) => (up - fx) / step,
+        (None, Some(down)) => (fx - down) / step,
+        (None, None) => f64::NAN,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uncertain(value: Value) -> (f64, f64) {
+        match value {
+            Value::Uncertain(value, error) => (value, error),
+            other => panic!("not uncertain: {:?}", other),
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-8 * b.abs().max(1e-3)
+    }
+
+    fn arithmetic(op: BinaryOp, a: f64, b: f64) -> EvalResult<f64> {
+        Ok(match op {
+            BinaryOp::Add => a + b,
+            BinaryOp::Subtract => a - b,
+            BinaryOp::Multiply => a * b,
+            BinaryOp::Divide => a / b,
+            _ => a.powf(b),
+        })
+    }
+
+    #[test]
+    fn errors_add_in_quadrature() {
+        let a = Value::Uncertain(3.0, 0.3);
+        let b = Value::Uncertain(4.0, 0.4);
+        for (op, value, error) in [
+            (BinaryOp::Add, 7.0, 0.5),
+            (BinaryOp::Subtract, -1.0, 0.5),
+            // Relative errors of 10% each
+            (BinaryOp::Multiply, 12.0, 12.0 * 0.02_f64.sqrt()),
+            (BinaryOp::Divide, 0.75, 0.75 * 0.02_f64.sqrt()),
+        ] {
+            let (v, e) = uncertain(combine(op, a.clone(), b.clone(), arithmetic).unwrap());
+            assert!(
+                close(v, value) && close(e, error),
+                "{:?}: {} ± {}",
+                op,
+                v,
+                e
+            );
+        }
+
+        // A plain number has no error of its own
+        let (v, e) =
+            uncertain(combine(BinaryOp::Power, a.clone(), Value::Number(2.0), arithmetic).unwrap());
+        assert!(close(v, 9.0) && close(e, 1.8), "{} ± {}", v, e);
+        assert_eq!(
+            combine(BinaryOp::Less, a, b, arithmetic),
+            Err(EvalError::UnexpectedUncertain)
+        );
+    }
+
+    #[test]
+    fn functions_scale_the_error_by_their_slope() {
+        let (v, e) = uncertain(propagate(0.0, 0.1, |x| Ok(x.sin())).unwrap());
+        assert!(close(v, 0.0) && close(e, 0.1), "{} ± {}", v, e);
+        let (v, e) = uncertain(propagate(100.0, 2.0, |x| Ok(x.sqrt())).unwrap());
+        assert!(close(v, 10.0) && close(e, 0.1), "{} ± {}", v, e);
+        // Only the right of 0 has a square root
+        let (_, e) = uncertain(propagate(0.0, 1.0, |x| Ok(x.sqrt())).unwrap());
+        assert!(e > 100.0);
+
+        assert_eq!(
+            with_error(Value::Number(1.0), Value::Number(-0.1))
+                .unwrap_err()
+                .to_string(),
+            "The error after ± must be 0 or more"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Units of measure for `convert(value, "from", "to")` and the unit converter panel,
+//! grouped by what they measure, along with the panel's state.
+
+/// What a unit measures; only units of the same category convert into each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    Length,
+    Mass,
+    Temperature,
+    Data,
+}
+
+impl Category {
+    pub const ALL: &'static [Category] = &[
+        Category::Length,
+        Category::Mass,
+        Category::Temperature,
+        Category::Data,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::Length => "length",
+            Category::Mass => "mass",
+            Category::Temperature => "temperature",
+            Category::Data => "data size",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Unit {
+    /// What `convert` is given, e.g. `km`.
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub category: Category,
+    /// One of this unit is `scale / divisor` of its category's base unit (metres,
+    /// kilograms, degrees Celsius and bytes), after adding `offset`, which only
+    /// temperatures have. A fraction keeps Fahrenheit's 5/9 exact.
+    scale: f64,
+    divisor: f64,
+    offset: f64,
+}
+
+const fn unit(symbol: &'static str, name: &'static str, category: Category, scale: f64) -> Unit {
+    Unit {
+        symbol,
+        name,
+        category,
+        scale,
+        divisor: 1.0,
+        offset: 0.0,
+    }
+}
+
+/// Every unit, in the order the converter lists them.
+pub const UNITS: &[Unit] = &[
+    unit("mm", "millimetre", Category::Length, 0.001),
+    unit("cm", "centimetre", Category::Length, 0.01),
+    unit("m", "metre", Category::Length, 1.0),
+    unit("km", "kilometre", Category::Length, 1000.0),
+    unit("in", "inch", Category::Length, 0.0254),
+    unit("ft", "foot", Category::Length, 0.3048),
+    unit("yd", "yard", Category::Length, 0.9144),
+    unit("mi", "mile", Category::Length, 1609.344),
+    unit("nmi", "nautical mile", Category::Length, 1852.0),
+    unit("mg", "milligram", Category::Mass, 1e-6),
+    unit("g", "gram", Category::Mass, 0.001),
+    unit("kg", "kilogram", Category::Mass, 1.0),
+    unit("t", "tonne", Category::Mass, 1000.0),
+    unit("oz", "ounce", Category::Mass, 0.028349523125),
+    unit("lb", "pound", Category::Mass, 0.45359237),
+    unit("st", "stone", Category::Mass, 6.35029318),
+    unit("C", "celsius", Category::Temperature, 1.0),
+    Unit {
+        symbol: "F",
+        name: "fahrenheit",
+        category: Category::Temperature,
+        scale: 5.0,
+        divisor: 9.0,
+        offset: -32.0,
+    },
+    Unit {
+        symbol: "K",
+        name: "kelvin",
+        category: Category::Temperature,
+        scale: 1.0,
+        divisor: 1.0,
+        offset: -273.15,
+    },
+    unit("bit", "bit", Category::Data, 0.125),
+    unit("B", "byte", Category::Data, 1.0),
+    unit("KB", "kilobyte", Category::Data, 1e3),
+    unit("MB", "megabyte", Category::Data, 1e6),
+    unit("GB", "gigabyte", Category::Data, 1e9),
+    unit("TB", "terabyte", Category::Data, 1e12),
+    unit("KiB", "kibibyte", Category::Data, 1024.0),
+    unit("MiB", "mebibyte", Category::Data, 1048576.0),
+    unit("GiB", "gibibyte", Category::Data, 1073741824.0),
+    unit("TiB", "tebibyte", Category::Data, 1099511627776.0),
+];
+
+/// The unit `text` names, by symbol or name. Case only matters when it tells two
+/// symbols apart, so `KM` and `Celsius` work too.
+pub fn find_unit(text: &str) -> Option<&'static Unit> {
+    let text = text.trim();
+    UNITS.iter().find(|unit| unit.symbol == text).or_else(|| {
+        UNITS.iter().find(|unit| {
+            unit.symbol.eq_ignore_ascii_case(text) || unit.name.eq_ignore_ascii_case(text)
+        })
+    })
+}
+
+/// The units of `category`, in order.
+pub fn units_in(category: Category) -> impl Iterator<Item = &'static Unit> {
+    UNITS.iter().filter(move |unit| unit.category == category)
+}
+
+/// `value` in `from` expressed in `to`; both must measure the same thing.
+pub fn convert(value: f64, from: &Unit, to: &Unit) -> f64 {
+    let base = (value + from.offset) * from.scale / from.divisor;
+    base * to.divisor / to.scale - to.offset
+}
+
+/// What the converter panel's arrow keys move through.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Field {
+    #[default]
+    Category,
+    From,
+    To,
+}
+
+/// The unit converter panel: a category, a unit to convert from and one to convert to,
+/// and the value typed in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Converter {
+    pub category: usize,
+    /// Index among the units of the category.
+    pub from: usize,
+    pub to: usize,
+    pub focus: Field,
+    pub input: String,
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            category: 0,
+            from: 0,
+            to: 1,
+            focus: Field::default(),
+            input: "1".to_string(),
+        }
+    }
+}
+
+impl Converter {
+    /// Start converting `value`, such as the calculator's result, keeping the units.
+    pub fn set_value(&mut self, value: f64) {
+        if value.is_finite() {
+            self.input = value.to_string();
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        Category::ALL[self.category]
+    }
+
+    pub fn units(&self) -> Vec<&'static Unit> {
+        units_in(self.category()).collect()
+    }
+
+    pub fn from_unit(&self) -> &'static Unit {
+        self.units()[self.from]
+    }
+
+    pub fn to_unit(&self) -> &'static Unit {
+        self.units()[self.to]
+    }
+
+    /// The typed value converted, when it is a number.
+    pub fn result(&self) -> Option<f64> {
+        let value = self.input.parse::<f64>().ok()?;
+        Some(convert(value, self.from_unit(), self.to_unit()))
+    }
+
+    /// The conversion as an expression for the calculator.
+    pub fn expression(&self) -> String {
+        format!(
+            "convert({}, \"{}\", \"{}\")",
+            self.input,
+            self.from_unit().symbol,
+            self.to_unit().symbol
+        )
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus = match self.focus {
+            Field::Category => Field::From,
+            Field::From => Field::To,
+            Field::To => Field::Category,
+        };
+    }
+
+    pub fn focus_prev(&mut self) {
+        self.focus = match self.focus {
+            Field::Category => Field::To,
+            Field::From => Field::Category,
+            Field::To => Field::From,
+        };
+    }
+
+    /// Move the focused list's selection by `step`, wrapping around. A new category
+    /// starts over from its first two units.
+    pub fn select(&mut self, step: isize) {
+        let step_in =
+            |index: usize, len: usize| (index as isize + step).rem_euclid(len as isize) as usize;
+        match self.focus {
+            Field::Category => {
+                self.category = step_in(self.category, Category::ALL.len());
+                self.from = 0;
+                self.to = 1;
+            }
+            Field::From => self.from = step_in(self.from, self.units().len()),
+            Field::To => self.to = step_in(self.to, self.units().len()),
+        }
+    }
+
+    pub fn swap(&mut self) {
+        (self.from, self.to) = (self.to, self.from);
+    }
+
+    /// Type `c` into the value, ignoring anything that can't be part of a number.
+    pub fn push(&mut self, c: char) {
+        let fits = c.is_ascii_digit()
+            || (c == '.' && !self.input.contains('.'))
+            || (c == '-' && self.input.is_empty());
+        if fits {
+            self.input.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(text: &str) -> &'static Unit {
+        find_unit(text).unwrap()
+    }
+
+    #[test]
+    fn units_convert_within_their_category() {
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-9 * b.abs().max(1.0);
+        assert!(close(convert(5.0, unit("km"), unit("mi")), 3.106855961));
+        assert!(close(convert(1.0, unit("ft"), unit("in")), 12.0));
+        assert!(close(convert(1.0, unit("lb"), unit("oz")), 16.0));
+        assert!(close(convert(100.0, unit("C"), unit("F")), 212.0));
+        assert!(close(convert(-40.0, unit("F"), unit("C")), -40.0));
+        assert!(close(convert(0.0, unit("K"), unit("C")), -273.15));
+        assert!(close(convert(1.0, unit("GiB"), unit("MB")), 1073.741824));
+        assert!(close(convert(1.0, unit("B"), unit("bit")), 8.0));
+
+        // Symbols are matched exactly first, then in any case or by name
+        assert_eq!(unit("Mb"), unit("MB"));
+        assert_eq!(unit("KM").symbol, "km");
+        assert_eq!(unit("Fahrenheit").symbol, "F");
+        assert_eq!(find_unit("furlong"), None);
+        for category in Category::ALL {
+            assert!(units_in(*category).count() >= 3, "{:?}", category);
+        }
+    }
+
+    #[test]
+    fn converter_moves_through_categories_and_units() {
+        let mut converter = Converter::default();
+        converter.set_value(5.0);
+        assert_eq!(converter.expression(), "convert(5, \"mm\", \"cm\")");
+        converter.focus_next();
+        converter.select(3);
+        converter.focus_next();
+        converter.select(-2);
+        assert_eq!(converter.from_unit().symbol, "km");
+        assert_eq!(converter.to_unit().symbol, "nmi");
+        converter.swap();
+        assert_eq!(converter.expression(), "convert(5, \"nmi\", \"km\")");
+        assert_eq!(converter.result(), Some(9.26));
+
+        // A new category starts from its first units; the value stays
+        converter.focus = Field::Category;
+        converter.select(-2);
+        assert_eq!(converter.category(), Category::Temperature);
+        assert_eq!(converter.to_unit().symbol, "F");
+        converter.backspace();
+        for c in "-4x0.5.".chars() {
+            converter.push(c);
+        }
+        assert_eq!(converter.input, "-40.5");
+        converter.set_value(f64::NAN);
+        assert_eq!(converter.input, "-40.5");
+    }
+}
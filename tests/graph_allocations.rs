@@ -0,0 +1,88 @@
+//! Allocation counts for redrawing and re-sampling the graph, measured with a counting
+//! global allocator. Counts are per thread so parallel tests don't disturb each other.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use ratatui::{Terminal, backend::TestBackend};
+use tcalc::{App, ui};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Allocations made while running `f` on this thread.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn pan_pan_zoom_reuses_buffers() {
+    let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+    let mut app = App::new();
+    app.calculator_module.set_expression("x^2".to_string());
+    app.enter_graph_mode();
+    terminal.draw(|f| ui::draw(f, &app, f.area())).unwrap();
+
+    let points = app.graph_module.points.as_ptr();
+    let grid = app.graph_grid.borrow().as_ptr();
+    let first_frame = allocations(|| {
+        app.pan_graph(1.0, 0.0);
+        app.pan_graph(1.0, 0.0);
+        app.zoom_graph(1.2);
+        terminal.draw(|f| ui::draw(f, &app, f.area())).unwrap();
+    });
+
+    // The point and grid buffers were refilled in place rather than reallocated
+    assert_eq!(app.graph_module.points.as_ptr(), points);
+    assert_eq!(app.graph_grid.borrow().as_ptr(), grid);
+
+    // Re-sampling an unchanged view is skipped outright
+    let expression = app.graph_expression.clone();
+    let resample = allocations(|| {
+        app.graph_module
+            .generate_points(&expression, 100, 50)
+            .unwrap()
+    });
+    assert_eq!(resample, 0);
+
+    // A moved view is resampled from one parse, not one per sample
+    let expression = "exp(x) - sin(x)^2";
+    app.graph_module.x_min += 0.5;
+    let moved = allocations(|| {
+        app.graph_module
+            .generate_points(expression, 100, 50)
+            .unwrap()
+    });
+    assert!(moved < 50, "{} allocations for 100 samples", moved);
+
+    // Redrawing the same view only pays for the frame's text, not for the samples. Now
+    // that sampling allocates so little, that is most of what a pan costs too.
+    let redraw = allocations(|| {
+        terminal.draw(|f| ui::draw(f, &app, f.area())).unwrap();
+    });
+    assert!(
+        redraw < first_frame,
+        "redraw {} vs pan-pan-zoom {}",
+        redraw,
+        first_frame
+    );
+}
@@ -0,0 +1,402 @@
+//! Golden-frame snapshot tests for the UI.
+//!
+//! Each test renders an `App` into a `TestBackend` and compares the frame text against
+//! `tests/snapshots/<name>.txt`. After an intentional UI change, regenerate the golden
+//! files with `TCALC_BLESS=1 cargo test --test ui_snapshots` and review the diff.
+
+use std::fs;
+use std::path::PathBuf;
+
+use ratatui::{Terminal, backend::TestBackend, layout::Rect};
+use tcalc::calculator::CalculatorMode;
+use tcalc::finance::TvmField;
+use tcalc::glyphs::GlyphSet;
+use tcalc::graph::Feature;
+use tcalc::{App, stats, ui};
+
+const WIDTH: u16 = 80;
+const HEIGHT: u16 = 30;
+
+fn render(app: &App, width: u16, height: u16) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|f| ui::draw(f, app, f.area())).unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let mut text = String::new();
+    for y in 0..height {
+        let mut line = String::new();
+        for x in 0..width {
+            line.push_str(buffer[(x, y)].symbol());
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    mask_clock(&text)
+}
+
+/// Replace wall-clock times (`HH:MM:SS`) so snapshots don't depend on when they run.
+fn mask_clock(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let window = &chars[i..(i + 8).min(chars.len())];
+        let is_time = window.len() == 8
+            && window.iter().enumerate().all(|(j, c)| {
+                if j == 2 || j == 5 {
+                    *c == ':'
+                } else {
+                    c.is_ascii_digit()
+                }
+            });
+        if is_time {
+            out.push_str("HH:MM:SS");
+            i += 8;
+        } else {
+            out.push(chars[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.txt", name));
+
+    if std::env::var_os("TCALC_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing snapshot {}; run with TCALC_BLESS=1 to create it",
+            path.display()
+        )
+    });
+    if expected != actual {
+        panic!(
+            "snapshot {} differs (run with TCALC_BLESS=1 to update)\n--- expected\n{}\n--- actual\n{}",
+            name, expected, actual
+        );
+    }
+}
+
+fn type_expression(app: &mut App, expression: &str) {
+    app.calculator_module.current_expression = expression.to_string();
+    app.calculator_module.update_result();
+}
+
+#[test]
+fn fresh() {
+    let app = App::new();
+    assert_snapshot("fresh", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn long_expression() {
+    let mut app = App::new();
+    type_expression(
+        &mut app,
+        "((12345.678 + 98765.4321) * (3.14159 - 2.71828)) / (42 ^ 2) + 7 % 3 - 0.000001",
+    );
+    assert_snapshot("long_expression", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn error() {
+    let mut app = App::new();
+    type_expression(&mut app, "1/(2-2)");
+    app.calculator_module.calculate();
+    assert_snapshot("error", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn syntax_error_caret() {
+    let mut app = App::new();
+    type_expression(&mut app, "1+*2-3");
+    app.calculator_module.calculate();
+    assert_snapshot("syntax_error", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn unbalanced_brackets() {
+    let mut app = App::new();
+    type_expression(&mut app, "2*(3+(4");
+    assert_snapshot("unbalanced_brackets", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn history_with_three_entries() {
+    let mut app = App::new();
+    for expression in ["1+2", "(3+4)*2", "2^10"] {
+        type_expression(&mut app, expression);
+        app.calculator_module.calculate();
+    }
+    app.toggle_history();
+    assert_snapshot("history", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn wide_terminals_show_history_beside_the_keypad() {
+    let mut app = App::new();
+    for expression in ["1+2", "(3+4)*2", "2^10"] {
+        type_expression(&mut app, expression);
+        app.calculator_module.calculate();
+    }
+    app.toggle_history();
+    assert_snapshot("history_split_100", &render(&app, 100, HEIGHT));
+    assert_snapshot("history_split_120", &render(&app, 120, HEIGHT));
+
+    // Clicks go to the button drawn under them, not where the full-width keypad would be
+    let rects = app.button_rects.borrow().clone();
+    let (rect, row, col) = rects[5];
+    assert_eq!(
+        app.mouse_to_button_coords(rect.x + 1, rect.y + 1, Rect::new(0, 0, 120, HEIGHT)),
+        Some((row, col))
+    );
+    let beside = rects.iter().map(|(rect, ..)| rect.right()).max().unwrap() + 2;
+    assert_eq!(
+        app.mouse_to_button_coords(beside, rect.y + 1, Rect::new(0, 0, 120, HEIGHT)),
+        None
+    );
+    assert_eq!(
+        app.mouse_to_button_coords(rect.x + 1, rect.y + 1, Rect::new(0, 0, WIDTH, HEIGHT)),
+        None
+    );
+}
+
+#[test]
+fn long_history_entries_squeeze_the_keypad() {
+    let mut app = App::new();
+    type_expression(
+        &mut app,
+        "sqrt(3^2+4^2)*(1+2+3+4+5+6+7+8+9+10)/(11+12+13+14+15)-16*17+18^2",
+    );
+    app.calculator_module.calculate();
+    app.second_function_mode = true;
+    app.toggle_history();
+    // Buttons too narrow for boxes lose their borders and long labels, such as Gr for Graph
+    assert_snapshot("history_split_squeezed", &render(&app, 100, HEIGHT));
+}
+
+#[test]
+fn scientific_second_mode() {
+    let mut app = App::new();
+    app.calculator_module.mode = CalculatorMode::Scientific;
+    app.toggle_second_function();
+    app.button_position = Some((1, 2));
+    assert_snapshot("scientific_2nd", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn programmer_mode_reads_results_out_in_each_base() {
+    let mut app = App::new();
+    app.calculator_module.mode = CalculatorMode::Programmer;
+    app.calculator_module
+        .set_expression("0xFF + 0b1010".to_string());
+    app.calculator_module.update_result();
+    assert_snapshot("programmer", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn unit_converter() {
+    let mut app = App::new();
+    type_expression(&mut app, "5");
+    app.open_converter();
+    app.converter.focus_next();
+    app.converter.select(3);
+    app.converter.focus_next();
+    app.converter.select(6);
+    assert_snapshot("converter", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn statistics_table() {
+    let mut app = App::new();
+    app.open_stats();
+    for (x, y) in [(1.0, 2.0), (2.0, 4.5), (4.0, 7.0)] {
+        app.stats.rows.push([Some(x), Some(y)]);
+    }
+    app.stats.rows.push([Some(5.0), None]);
+    app.stats.row = 3;
+    app.stats.column = stats::Column::Y;
+    app.stats.input = "9.".to_string();
+    assert_snapshot("statistics", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn matrix_editor() {
+    let mut app = App::new();
+    app.open_matrix_editor();
+    app.matrix_editor.resize(1, 1);
+    for c in "2\t-1\t0\t-1\t2\t-1\t0\t-1\t2.5".chars() {
+        match c {
+            '\t' => app.matrix_editor.next_cell(),
+            c => app.matrix_editor.push(c),
+        }
+    }
+    assert_snapshot("matrix_editor", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn financial_mode() {
+    let mut app = App::new();
+    app.calculator_module.mode = CalculatorMode::Financial;
+    app.tvm.set(TvmField::N, 360.0);
+    app.tvm.set(TvmField::Rate, 0.5);
+    app.tvm.set(TvmField::Pv, 200_000.0);
+    app.tvm.compute(TvmField::Pmt);
+    assert_snapshot("financial_mode", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn date_calculator() {
+    let mut app = App::new();
+    app.dates.start = "2024-01-01".to_string();
+    app.dates.end = "2024-06-15".to_string();
+    app.dates.days = "90".to_string();
+    app.open_dates();
+    app.dates.focus_next();
+    assert_snapshot("date_calculator", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn ascii_glyphs_keep_the_keypad_aligned() {
+    let mut app = App::new();
+    app.calculator_module.mode = CalculatorMode::Scientific;
+    app.toggle_second_function();
+    let unicode = render(&app, WIDTH, HEIGHT);
+    app.glyphs = GlyphSet::Ascii;
+    let ascii = render(&app, WIDTH, HEIGHT);
+    assert_snapshot("scientific_2nd_ascii", &ascii);
+
+    // Longer ASCII labels stay inside their buttons: every border is where it was
+    let borders = |line: &str| -> Vec<(usize, char)> {
+        line.chars()
+            .enumerate()
+            .filter(|(_, c)| "│┌┐└┘".contains(*c))
+            .collect()
+    };
+    for (unicode, ascii) in unicode.lines().zip(ascii.lines()) {
+        assert_eq!(borders(unicode), borders(ascii), "{}", ascii);
+    }
+    assert!(ascii.contains("sqrt") && ascii.contains("<=") && !ascii.contains('⌫'));
+}
+
+#[test]
+fn graph_of_sin() {
+    let mut app = App::new();
+    type_expression(&mut app, "sin(x)");
+    app.enter_graph_mode();
+    assert_snapshot("graph_sin", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_tangent() {
+    let mut app = App::new();
+    type_expression(&mut app, "x^2/4 - 2");
+    app.enter_graph_mode();
+    app.graph_cursor_x = 2.0;
+    app.toggle_tangent();
+    assert_snapshot("graph_tangent", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_gridlines() {
+    let mut app = App::new();
+    type_expression(&mut app, "x^3/20");
+    app.enter_graph_mode();
+    app.show_cursor_coords = false;
+    app.toggle_gridlines();
+    assert_snapshot("graph_gridlines", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_roots() {
+    let mut app = App::new();
+    type_expression(&mut app, "x^3/20 - x");
+    app.enter_graph_mode();
+    app.toggle_findings(Feature::Roots);
+    assert_snapshot("graph_roots", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_parametric() {
+    let mut app = App::new();
+    type_expression(&mut app, "[6cos(t), 6sin(t)]");
+    app.enter_graph_mode();
+    app.show_cursor_coords = false;
+    assert_snapshot("graph_parametric", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_polar() {
+    let mut app = App::new();
+    type_expression(&mut app, "r = 2 + 6cos(3theta)");
+    app.enter_graph_mode();
+    app.show_cursor_coords = false;
+    assert_snapshot("graph_polar", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_implicit() {
+    let mut app = App::new();
+    type_expression(&mut app, "x^2/64 + y^2/25 - 1; x*y - 4");
+    app.enter_graph_mode();
+    app.show_cursor_coords = false;
+    assert_snapshot("graph_implicit", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_integral() {
+    let mut app = App::new();
+    type_expression(&mut app, "x^2/10 - 3");
+    app.enter_graph_mode();
+    for x in [-2.0, 6.0] {
+        app.graph_cursor_x = x;
+        app.drop_bound();
+    }
+    assert_snapshot("graph_integral", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn graph_help() {
+    let mut app = App::new();
+    type_expression(&mut app, "sin(x)");
+    app.enter_graph_mode();
+    app.toggle_help();
+    assert_snapshot("graph_help", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn statement_results() {
+    let mut app = App::new();
+    type_expression(&mut app, "a=3; b=4; a*b; a+b; ratio(a, b); b^a");
+    app.calculate();
+    assert_snapshot("statements", &render(&app, WIDTH, HEIGHT));
+}
+
+#[test]
+fn mini_layout() {
+    let mut app = App::new();
+    app.set_terminal_size(Rect::new(0, 0, 40, 10));
+    type_expression(&mut app, "2^10 - 24");
+    app.calculate();
+    assert_snapshot("mini_40x10", &render(&app, 40, 10));
+}
+
+#[test]
+fn mini_layout_history() {
+    let mut app = App::new();
+    app.set_terminal_size(Rect::new(0, 0, 30, 8));
+    for expression in ["1+2", "3*4"] {
+        type_expression(&mut app, expression);
+        app.calculate();
+    }
+    app.toggle_history();
+    assert_snapshot("mini_30x8_history", &render(&app, 30, 8));
+}